@@ -95,9 +95,57 @@ fn build_os_disk_dir(target_arch: &str) -> PathBuf {
     fs::write(disk.join("var/hello.txt"), "Hello, axiom-ebpf!\n")
         .expect("should be able to write hello.txt");
 
+    write_bpf_keyring(&disk.join("etc/bpf"));
+
     disk
 }
 
+/// Provision the kernel's trusted BPF keyring on the disk image.
+///
+/// If `AXIOM_BPF_TRUSTED_KEYS` names a directory, every `*.pub` file in it
+/// (raw Ed25519 public keys as written by `rk key generate`) is concatenated
+/// into `/etc/bpf/trusted_keys`. The kernel only loads programs signed by
+/// one of them.
+///
+/// Unsigned programs are refused unless `AXIOM_BPF_ALLOW_UNSIGNED=1` marks
+/// the image to accept them, which a cloud profile kernel honors for
+/// development.
+fn write_bpf_keyring(bpf_dir: &Path) {
+    println!("cargo:rerun-if-env-changed=AXIOM_BPF_TRUSTED_KEYS");
+    println!("cargo:rerun-if-env-changed=AXIOM_BPF_ALLOW_UNSIGNED");
+
+    if std::env::var_os("AXIOM_BPF_ALLOW_UNSIGNED").is_some_and(|value| value == "1") {
+        fs::write(bpf_dir.join("allow_unsigned"), "")
+            .expect("should be able to write allow_unsigned");
+    }
+
+    let Some(keys_dir) = std::env::var_os("AXIOM_BPF_TRUSTED_KEYS") else {
+        return;
+    };
+    println!("cargo:rerun-if-changed={}", Path::new(&keys_dir).display());
+
+    let mut key_files = fs::read_dir(&keys_dir)
+        .expect("AXIOM_BPF_TRUSTED_KEYS should be a readable directory")
+        .map(|entry| entry.expect("should be able to read key directory").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pub"))
+        .collect::<Vec<_>>();
+    key_files.sort();
+
+    let mut keyring = Vec::new();
+    for path in key_files {
+        let key = fs::read(&path).expect("should be able to read public key");
+        assert_eq!(
+            32,
+            key.len(),
+            "{} should be a raw 32-byte public key",
+            path.display()
+        );
+        keyring.extend_from_slice(&key);
+    }
+
+    fs::write(bpf_dir.join("trusted_keys"), keyring).expect("should be able to write trusted_keys");
+}
+
 fn build_dir(current_path: &Path, current_dir: &Dir<'_>, target_arch: &str) {
     for file in current_dir.files {
         let file_path = current_path.join(file.name);
@@ -66,12 +66,17 @@ pub const BPF_LINK_CREATE: u32 = 28;
 pub const BPF_LINK_UPDATE: u32 = 29;
 pub const BPF_LINK_GET_FD_BY_ID: u32 = 30;
 pub const BPF_LINK_GET_NEXT_ID: u32 = 31;
-pub const BPF_ENABLE_STATS: u32 = 32;
+pub const BPF_ENABLE_STATS: u32 = 32; // flags: 1 to collect runtime stats, 0 to stop
 pub const BPF_ITER_CREATE: u32 = 33;
 pub const BPF_LINK_DETACH: u32 = 34;
 pub const BPF_PROG_BIND_MAP: u32 = 35;
 pub const BPF_PROG_LOAD_ELF: u32 = 36; // Custom command for loading ELF files
 pub const BPF_RINGBUF_POLL: u32 = 37; // Custom command for polling ringbuf events
+pub const BPF_PROG_LOAD_SIGNED: u32 = 38; // Custom command for loading signed (.rbpf) programs
+pub const BPF_PROG_UNLOAD: u32 = 39; // Custom command for unloading a program by fd
+pub const BPF_MAP_DELETE: u32 = 40; // Custom command for deleting a map by fd
+pub const BPF_OBJ_UNPIN: u32 = 41; // Custom command for removing a pin from the bpffs
+pub const BPF_TIMESERIES_QUERY: u32 = 42; // Custom command for time-series map aggregates
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -104,11 +109,155 @@ pub struct BpfAttr {
     pub line_info: u64,
     pub line_info_cnt: u32,
     pub attach_btf_id: u32,
-    pub attach_prog_fd: u32, // Also used as map_fd for MAP_LOOKUP/UPDATE
+    pub attach_prog_fd: u32, // Program fd for ATTACH/DETACH/TEST_RUN/PROG_UNLOAD
 
     // Map element operations (MAP_LOOKUP_ELEM, MAP_UPDATE_ELEM, MAP_DELETE_ELEM)
-    pub map_fd: u32,
-    pub key: u64,   // pointer to key
-    pub value: u64, // pointer to value (or next_key for GET_NEXT_KEY)
-    pub flags: u64, // update flags
+    pub map_fd: u32, // Map fd returned by MAP_CREATE
+    pub key: u64,    // pointer to key
+    pub value: u64,  // pointer to value (or next_key for GET_NEXT_KEY)
+    pub flags: u64,  // update flags
+
+    // Program test runs (PROG_TEST_RUN)
+    pub data_size_in: u32,
+    pub data_size_out: u32, // in: capacity of data_out, out: size of the output data
+    pub data_in: u64,       // pointer to input data
+    pub data_out: u64,      // pointer to output buffer (may be 0)
+    pub repeat: u32,        // number of runs, 0 means 1, at most 1000
+    pub duration: u32,      // out: average run time in nanoseconds
+    pub retval: u64,        // out: R0 of the last run
+
+    // Object info and pinning (OBJ_GET_INFO_BY_FD, OBJ_PIN, OBJ_GET, OBJ_UNPIN)
+    pub bpf_fd: u32,   // object fd for OBJ_GET_INFO_BY_FD and OBJ_PIN
+    pub info_len: u32, // in: size of the info buffer, out: bytes written
+    pub info: u64,     // pointer to the info buffer
+    pub pathname: u64, // pointer to a NUL-terminated path below /sys/fs/bpf
+
+    // Enumeration (PROG/MAP_GET_NEXT_ID, PROG/MAP_GET_FD_BY_ID, PROG_QUERY)
+    pub start_id: u32, // ID to continue after; the object ID for *_GET_FD_BY_ID
+    pub next_id: u32,  // out: next ID after start_id
+    pub prog_ids: u64, // pointer to a u32 array receiving the attached program IDs
+    pub prog_cnt: u32, // in: capacity of prog_ids, out: number of attached programs
+
+    // Map creation extras (MAP_CREATE)
+    pub map_extra: u64, // ring buffers: unconsumed bytes that wake a poll()ing consumer
+
+    // Batched map operations (MAP_*_BATCH), on the map in map_fd
+    pub in_batch: u64,   // pointer to the cursor to continue from, 0 to start over
+    pub out_batch: u64,  // pointer receiving the cursor for the next call (key_size bytes)
+    pub keys: u64,       // pointer to an array of count keys
+    pub values: u64,     // pointer to an array of count values
+    pub elem_flags: u64, // update flags applied to every element (UPDATE_BATCH)
+    pub count: u32,      // in: number of elements, out: number processed
+
+    // Global data (PROG_LOAD_ELF)
+    pub rodata_size: u32, // size of rodata, must match the object's .rodata section
+    pub rodata: u64,      // pointer to the initial .rodata contents, 0 to keep the object's
+
+    // Type information (BTF_LOAD); BTF_GET_FD_BY_ID takes the ID in start_id
+    pub btf: u64,      // pointer to a raw .BTF blob
+    pub btf_size: u32, // size of the blob
+
+    // Time-series aggregates (TIMESERIES_QUERY) of the map in map_fd, written
+    // to info as a BpfTsAggregate. A nonzero count selects the last count
+    // entries, otherwise the entries stamped within the window; count is
+    // set to the number of entries aggregated.
+    pub window_start_ns: u64, // start of the window (inclusive)
+    pub window_end_ns: u64,   // end of the window (inclusive)
+
+    // Hook scheduling (PROG_ATTACH), applies to every hook of the program
+    pub exec_priority: u32, // BPF_EXEC_PRIO_*, 0 keeps the current priority
+    pub deadline_ns: u64,   // time after the hook fired a run must finish by, 0 for none
+}
+
+// Hook execution priorities for BpfAttr::exec_priority. Programs with a
+// deadline or at least high priority run inline in the hook, the others are
+// deferred to a kernel task.
+pub const BPF_EXEC_PRIO_DEFAULT: u32 = 0;
+pub const BPF_EXEC_PRIO_LOW: u32 = 1;
+pub const BPF_EXEC_PRIO_NORMAL: u32 = 2;
+pub const BPF_EXEC_PRIO_HIGH: u32 = 3;
+pub const BPF_EXEC_PRIO_CRITICAL: u32 = 4;
+
+// Runtime error kinds counted in BpfProgInfo::err_cnt
+pub const BPF_ERR_DIVISION_BY_ZERO: usize = 0;
+pub const BPF_ERR_OUT_OF_BOUNDS: usize = 1;
+pub const BPF_ERR_STACK_OVERFLOW: usize = 2;
+pub const BPF_ERR_INVALID_HELPER: usize = 3;
+pub const BPF_ERR_TIMEOUT: usize = 4;
+pub const BPF_ERR_INVALID_INSTRUCTION: usize = 5;
+pub const BPF_ERR_NOT_LOADED: usize = 6;
+pub const BPF_ERR_OUT_OF_MEMORY: usize = 7;
+pub const BPF_ERR_KINDS: usize = 8;
+
+/// Program information returned by OBJ_GET_INFO_BY_FD.
+///
+/// Runtime statistics are only collected while enabled with BPF_ENABLE_STATS.
+/// To receive the IDs of the maps used by the program, set `map_ids` and
+/// `nr_map_ids` before the call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BpfProgInfo {
+    pub id: u32,
+    pub jited: u32, // 1 if the program runs from a JIT image
+    pub run_cnt: u64,
+    pub run_time_ns: u64,     // total over all runs
+    pub max_run_time_ns: u64, // worst case of a single run
+    pub last_retval: u64,
+    pub err_cnt: [u64; BPF_ERR_KINDS], // indexed by BPF_ERR_*
+    pub prog_type: u32,
+    pub insn_cnt: u32,
+    pub nr_map_ids: u32,    // in: capacity of map_ids, out: number of maps used
+    pub signed: u32,        // 1 if loaded from a verified signed program
+    pub attach_types: u64,  // bit n set while attached to attach type n
+    pub map_ids: u64,       // pointer to a u32 array receiving the map IDs
+    pub load_time_ns: u64,  // kernel time at load, as bpf_ktime_get_ns
+    pub signer_id: [u8; 8], // key ID of the signer when signed
+    pub btf_id: u32,        // BTF of the object the program came from, 0 if none
+    pub nr_line_info: u32,  // number of source line records kept for the program
+    // Hook runs that finished after their deadline, counted even without stats
+    pub deadline_misses: u64,
+}
+
+/// Map information returned by OBJ_GET_INFO_BY_FD.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BpfMapInfo {
+    pub id: u32,
+    pub map_type: u32,
+    pub key_size: u32,
+    pub value_size: u32,
+    pub max_entries: u32,
+    pub btf_id: u32,            // BTF describing key and value, 0 if none
+    pub btf_key_type_id: u32,   // 0 if unknown
+    pub btf_value_type_id: u32, // 0 if unknown
+}
+
+/// BTF information returned by OBJ_GET_INFO_BY_FD.
+///
+/// To receive the raw type data, set `btf` and `btf_size` before the call;
+/// `btf_size` is always set to the full size of the data.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BpfBtfInfo {
+    pub btf: u64,      // pointer to a buffer receiving the raw .BTF blob
+    pub btf_size: u32, // in: capacity of btf, out: size of the blob
+    pub id: u32,
+}
+
+/// Aggregates of a time-series map returned by TIMESERIES_QUERY.
+///
+/// Values are read as signed integers; the mean and standard deviation are
+/// truncated. This is the layout the bpf_timeseries_query helpers write for
+/// programs.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BpfTsAggregate {
+    pub count: u64, // number of entries aggregated
+    pub oldest_ns: u64,
+    pub newest_ns: u64,
+    pub min: i64,
+    pub max: i64,
+    pub mean: i64,
+    pub stddev: i64, // population standard deviation
+    pub slope: i64,  // change from the oldest to the newest value, per second
 }
@@ -5,7 +5,9 @@ mod errno;
 mod fcntl;
 mod limits;
 mod mman;
+mod poll;
 pub mod process;
+mod sched;
 pub mod syscall;
 mod time;
 mod uio;
@@ -15,7 +17,9 @@ pub use errno::*;
 pub use fcntl::*;
 pub use limits::*;
 pub use mman::*;
+pub use poll::*;
 pub use process::*;
+pub use sched::*;
 pub use syscall::*;
 pub use time::*;
 pub use uio::*;
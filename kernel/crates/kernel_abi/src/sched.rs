@@ -0,0 +1,45 @@
+// Scheduling policies
+/// Time-shared, below every real-time task.
+pub const SCHED_OTHER: i32 = 0;
+/// Real-time, runs until it blocks, yields or a higher priority preempts it.
+pub const SCHED_FIFO: i32 = 1;
+/// Real-time, like [`SCHED_FIFO`] but time-sliced among equal priorities.
+pub const SCHED_RR: i32 = 2;
+/// Earliest deadline first with a runtime budget per period, above every
+/// other policy. Only set through `sched_setattr`.
+pub const SCHED_DEADLINE: i32 = 6;
+
+/// Lowest priority of the real-time policies.
+pub const SCHED_PRIORITY_MIN: i32 = 1;
+/// Highest priority of the real-time policies.
+pub const SCHED_PRIORITY_MAX: i32 = 99;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct sched_param {
+    /// 0 for [`SCHED_OTHER`], otherwise between [`SCHED_PRIORITY_MIN`] and
+    /// [`SCHED_PRIORITY_MAX`].
+    pub sched_priority: i32,
+}
+
+/// The scheduling attributes of `sched_setattr` and `sched_getattr`.
+///
+/// [`SCHED_DEADLINE`] tasks get `sched_runtime` nanoseconds of CPU time in
+/// every `sched_period`, and each period's runtime by `sched_deadline` after
+/// the period starts. A zero period equals the deadline.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct sched_attr {
+    /// The size of the structure, for future extensions.
+    pub size: u32,
+    pub sched_policy: u32,
+    /// Reserved, must be 0.
+    pub sched_flags: u64,
+    /// Ignored, time-shared tasks have no nice values.
+    pub sched_nice: i32,
+    /// As in [`sched_param`], 0 for [`SCHED_DEADLINE`].
+    pub sched_priority: u32,
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
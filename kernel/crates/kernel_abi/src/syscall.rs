@@ -70,4 +70,13 @@ n! {
     SYS_FORK = 57,
     SYS_EXECVE = 58,
     SYS_WAITPID = 59,
+    SYS_SCHED_SETSCHEDULER = 60,
+    SYS_SCHED_GETSCHEDULER = 61,
+    SYS_SCHED_SETPARAM = 62,
+    SYS_SCHED_GETPARAM = 63,
+    SYS_SCHED_YIELD = 64,
+    SYS_SCHED_SETATTR = 65,
+    SYS_SCHED_GETATTR = 66,
+    SYS_SCHED_SETAFFINITY = 67,
+    SYS_SCHED_GETAFFINITY = 68,
 }
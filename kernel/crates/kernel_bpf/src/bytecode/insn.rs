@@ -44,6 +44,24 @@ impl BpfInsn {
     /// Size of a BPF instruction in bytes
     pub const SIZE: usize = 8;
 
+    /// `src_reg` value marking a wide load whose immediate names a map.
+    pub const PSEUDO_MAP_FD: u8 = 1;
+
+    /// `src_reg` value marking a call of a BPF function rather than a helper.
+    pub const PSEUDO_CALL: u8 = 1;
+
+    /// `src_reg` value marking a wide load of an address in the value of a
+    /// map, as globals in `.data`, `.rodata` and `.bss` are loaded.
+    ///
+    /// The immediate names the map and the next slot's immediate holds the
+    /// offset into the value. The kernel replaces both with the address
+    /// when the program is loaded.
+    pub const PSEUDO_MAP_VALUE: u8 = 2;
+
+    /// `src_reg` value marking a wide load of the address of a BPF
+    /// function, as passed to `bpf_loop`.
+    pub const PSEUDO_FUNC: u8 = 4;
+
     /// Create a new instruction.
     #[inline]
     pub const fn new(opcode: u8, dst: u8, src: u8, offset: i16, imm: i32) -> Self {
@@ -101,6 +119,26 @@ impl BpfInsn {
         self.opcode == 0x18
     }
 
+    /// Check if this is a wide load of a map reference.
+    #[inline]
+    pub const fn is_map_load(&self) -> bool {
+        self.is_wide() && self.src_reg() == Self::PSEUDO_MAP_FD
+    }
+
+    /// Check if this is a wide load of an address in a map value.
+    #[inline]
+    pub const fn is_map_value_load(&self) -> bool {
+        self.is_wide() && self.src_reg() == Self::PSEUDO_MAP_VALUE
+    }
+
+    /// Check if this is a wide load of a BPF function's address.
+    ///
+    /// As in a call, the immediate is relative to the next slot.
+    #[inline]
+    pub const fn is_func_load(&self) -> bool {
+        self.is_wide() && self.src_reg() == Self::PSEUDO_FUNC
+    }
+
     /// Check if this is an ALU instruction.
     #[inline]
     pub const fn is_alu(&self) -> bool {
@@ -142,6 +180,15 @@ impl BpfInsn {
         self.opcode == 0x85
     }
 
+    /// Check if this is a call of a BPF function in the same program.
+    ///
+    /// The immediate is the offset of the function from the next
+    /// instruction, like a jump offset.
+    #[inline]
+    pub const fn is_pseudo_call(&self) -> bool {
+        self.is_call() && self.src_reg() == Self::PSEUDO_CALL
+    }
+
     /// Get the ALU operation if this is an ALU instruction.
     #[inline]
     pub const fn alu_op(&self) -> Option<AluOp> {
@@ -230,6 +277,13 @@ impl BpfInsn {
         Self::new(0x85, 0, 0, 0, helper_id)
     }
 
+    /// Create a call of the BPF function `offset` instructions after the
+    /// next one.
+    #[inline]
+    pub const fn call_local(offset: i32) -> Self {
+        Self::new(0x85, 0, Self::PSEUDO_CALL, 0, offset)
+    }
+
     /// Create a conditional jump (jeq imm).
     #[inline]
     pub const fn jeq_imm(dst: u8, imm: i32, offset: i16) -> Self {
@@ -334,6 +388,10 @@ impl fmt::Display for BpfInsn {
             return write!(f, "exit");
         }
 
+        if self.is_pseudo_call() {
+            return write!(f, "call pc{:+}", self.imm);
+        }
+
         if self.is_call() {
             return write!(f, "call {}", self.imm);
         }
@@ -421,6 +479,41 @@ impl WideInsn {
             next: BpfInsn::new(0x00, 0, 0, 0, (imm64 >> 32) as i32),
         }
     }
+
+    /// Create a wide instruction loading a reference to map `fd`.
+    ///
+    /// The kernel resolves `fd` to the map's ID when the program is loaded.
+    #[inline]
+    pub const fn ld_map_fd(dst: u8, fd: i32) -> Self {
+        Self {
+            insn: BpfInsn::new(0x18, dst, BpfInsn::PSEUDO_MAP_FD, 0, fd),
+            next: BpfInsn::new(0x00, 0, 0, 0, 0),
+        }
+    }
+
+    /// Create a wide instruction loading the address `offset` bytes into
+    /// the value of map `fd`.
+    ///
+    /// The map must be a single-entry array; the kernel resolves the
+    /// reference to the address when the program is loaded.
+    #[inline]
+    pub const fn ld_map_value(dst: u8, fd: i32, offset: i32) -> Self {
+        Self {
+            insn: BpfInsn::new(0x18, dst, BpfInsn::PSEUDO_MAP_VALUE, 0, fd),
+            next: BpfInsn::new(0x00, 0, 0, 0, offset),
+        }
+    }
+
+    /// Create a wide instruction loading the address of a BPF function.
+    ///
+    /// As in a call, `offset` counts from the slot after the first one.
+    #[inline]
+    pub const fn ld_func(dst: u8, offset: i32) -> Self {
+        Self {
+            insn: BpfInsn::new(0x18, dst, BpfInsn::PSEUDO_FUNC, 0, offset),
+            next: BpfInsn::new(0x00, 0, 0, 0, 0),
+        }
+    }
 }
 
 /// Parsed instruction with extracted fields.
@@ -516,6 +609,16 @@ mod tests {
         assert!(insn.is_call());
         assert!(!insn.is_exit());
         assert_eq!(format!("{}", insn), "call 42");
+        assert!(!insn.is_pseudo_call());
+    }
+
+    #[test]
+    fn local_call_instruction() {
+        let insn = BpfInsn::call_local(-3);
+        assert!(insn.is_call());
+        assert!(insn.is_pseudo_call());
+        assert_eq!(insn.imm, -3);
+        assert_eq!(format!("{}", insn), "call pc-3");
     }
 
     #[test]
@@ -531,5 +634,23 @@ mod tests {
         let wide = WideInsn::ld_dw_imm(0, 0x123456789abcdef0);
         assert!(wide.insn.is_wide());
         assert_eq!(wide.imm64(), 0x123456789abcdef0);
+        assert!(!wide.insn.is_map_load());
+    }
+
+    #[test]
+    fn map_load_instruction() {
+        let wide = WideInsn::ld_map_fd(1, 7);
+        assert!(wide.insn.is_map_load());
+        assert_eq!(wide.insn.dst_reg(), 1);
+        assert_eq!(wide.imm64(), 7);
+
+        let func = WideInsn::ld_func(2, 5);
+        assert!(func.insn.is_func_load());
+        assert!(!func.insn.is_map_load());
+
+        let value = WideInsn::ld_map_value(3, 7, 16);
+        assert!(value.insn.is_map_value_load());
+        assert!(!value.insn.is_map_load());
+        assert_eq!(value.next.imm, 16);
     }
 }
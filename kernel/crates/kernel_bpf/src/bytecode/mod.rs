@@ -21,8 +21,10 @@ pub mod insn;
 pub mod opcode;
 pub mod program;
 pub mod registers;
+pub mod subprog;
 
 pub use insn::{BpfInsn, WideInsn};
 pub use opcode::{AluOp, JmpOp, MemSize, OpcodeClass};
 pub use program::{BpfProgType, BpfProgram, ProgramError};
 pub use registers::{Register, RegisterFile};
+pub use subprog::Subprog;
@@ -17,6 +17,7 @@ use core::fmt;
 use core::marker::PhantomData;
 
 use super::insn::BpfInsn;
+use super::subprog::{self, Subprog};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
 /// BPF program types.
@@ -157,6 +158,8 @@ pub struct BpfProgram<P: PhysicalProfile = ActiveProfile> {
     insns: Vec<BpfInsn>,
     /// Computed stack size required
     stack_size: usize,
+    /// Functions of the program, the main function first
+    subprogs: Vec<Subprog>,
     /// Program name for debugging
     name: Option<&'static str>,
     /// Marker for profile type
@@ -169,6 +172,7 @@ impl<P: PhysicalProfile> Clone for BpfProgram<P> {
             prog_type: self.prog_type,
             insns: self.insns.clone(),
             stack_size: self.stack_size,
+            subprogs: self.subprogs.clone(),
             name: self.name,
             _profile: PhantomData,
         }
@@ -222,6 +226,7 @@ impl<P: PhysicalProfile> BpfProgram<P> {
 
         Ok(Self {
             prog_type,
+            subprogs: subprog::split(&insns),
             insns,
             stack_size,
             name: None,
@@ -235,6 +240,15 @@ impl<P: PhysicalProfile> BpfProgram<P> {
         self
     }
 
+    /// Widen the functions' frames to the depths the verifier found, one
+    /// per function in order.
+    pub(crate) fn with_stack_depths(mut self, depths: &[usize]) -> Self {
+        for (subprog, &depth) in self.subprogs.iter_mut().zip(depths) {
+            subprog.stack_depth = subprog.stack_depth.max(depth.next_multiple_of(8));
+        }
+        self
+    }
+
     /// Get the program type.
     #[inline]
     pub fn prog_type(&self) -> BpfProgType {
@@ -259,6 +273,21 @@ impl<P: PhysicalProfile> BpfProgram<P> {
         self.stack_size
     }
 
+    /// Get the functions of the program, the main function first.
+    #[inline]
+    pub fn subprogs(&self) -> &[Subprog] {
+        &self.subprogs
+    }
+
+    /// Get the function starting at instruction `start`.
+    pub fn subprog_at(&self, start: usize) -> Option<&Subprog> {
+        let idx = self
+            .subprogs
+            .binary_search_by_key(&start, |subprog| subprog.start)
+            .ok()?;
+        Some(&self.subprogs[idx])
+    }
+
     /// Get the program name.
     #[inline]
     pub fn name(&self) -> Option<&'static str> {
@@ -454,6 +483,21 @@ mod tests {
         assert_eq!(program.name(), Some("test"));
     }
 
+    #[test]
+    fn program_functions() {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::call_local(1))
+            .exit()
+            .insn(BpfInsn::mov64_imm(0, 1))
+            .exit()
+            .build()
+            .expect("valid program");
+
+        assert_eq!(program.subprogs().len(), 2);
+        assert_eq!(program.subprog_at(2).map(|s| s.end), Some(4));
+        assert_eq!(program.subprog_at(1), None);
+    }
+
     #[test]
     fn empty_program_rejected() {
         let result = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter).build();
@@ -0,0 +1,183 @@
+//! BPF Functions
+//!
+//! Clang compiles static functions to BPF functions, called with
+//! `call pc+off` (see [`BpfInsn::is_pseudo_call`]). All functions of a
+//! program share one instruction stream: the main function comes first
+//! and every call target starts another function, which runs up to the
+//! start of the next one. Functions whose address is loaded (see
+//! [`BpfInsn::is_func_load`]) start one as well; `bpf_loop` calls them
+//! back.
+//!
+//! Each call gets a frame of its own. R1-R5 carry the arguments and R0
+//! the return value, R6-R9 are preserved across the call and R10 points to
+//! the top of the callee's stack, which sits right below the caller's.
+//! The frames run on the kernel stack, so as on Linux each may use at most
+//! [`MAX_FRAME_SIZE`] bytes once a program has more than one function,
+//! and the stacks of the deepest call chain must fit in the profile's
+//! `MAX_STACK_SIZE` together.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::insn::BpfInsn;
+use super::opcode::OpcodeClass;
+
+/// Maximum number of frames of a call chain, the main function's included.
+pub const MAX_CALL_FRAMES: usize = 8;
+
+/// Maximum stack of one frame in a program with BPF functions.
+pub const MAX_FRAME_SIZE: usize = 512;
+
+/// A function of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subprog {
+    /// Index of the first instruction
+    pub start: usize,
+    /// Index one past the last instruction
+    pub end: usize,
+    /// Bytes of stack the function's frame needs, a multiple of 8. Split
+    /// off a program, this counts the accesses relative to R10; the
+    /// verifier adds those through other stack pointers.
+    pub stack_depth: usize,
+}
+
+impl Subprog {
+    /// Check if instruction `idx` belongs to this function.
+    #[inline]
+    pub fn contains(&self, idx: usize) -> bool {
+        (self.start..self.end).contains(&idx)
+    }
+}
+
+/// Target of the call at `idx`, if `insn` calls a BPF function.
+///
+/// The target may be out of range; callers check it against the program.
+pub fn call_target(idx: usize, insn: &BpfInsn) -> Option<usize> {
+    if !insn.is_pseudo_call() {
+        return None;
+    }
+    usize::try_from(idx as i64 + 1 + insn.imm as i64).ok()
+}
+
+/// Function whose address the wide load at `idx` loads, if `insn` loads
+/// one.
+///
+/// The target may be out of range; callers check it against the program.
+pub fn func_target(idx: usize, insn: &BpfInsn) -> Option<usize> {
+    if !insn.is_func_load() {
+        return None;
+    }
+    usize::try_from(idx as i64 + 1 + insn.imm as i64).ok()
+}
+
+/// Split a program into its functions, in instruction order.
+///
+/// Calls and function loads whose target is out of range are left for
+/// the verifier to reject and don't start a function.
+pub fn split(insns: &[BpfInsn]) -> Vec<Subprog> {
+    let mut starts: Vec<usize> = insns
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, insn)| call_target(idx, insn).or_else(|| func_target(idx, insn)))
+        .filter(|&target| target < insns.len())
+        .collect();
+    starts.push(0);
+    starts.sort_unstable();
+    starts.dedup();
+
+    let ends = starts.iter().skip(1).copied().chain([insns.len()]);
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| Subprog {
+            start,
+            end,
+            stack_depth: stack_depth(&insns[start..end]).next_multiple_of(8),
+        })
+        .collect()
+}
+
+/// Deepest stack byte accessed through R10 by `insns`.
+pub fn stack_depth(insns: &[BpfInsn]) -> usize {
+    insns
+        .iter()
+        .filter_map(|insn| {
+            let base = match insn.class()? {
+                OpcodeClass::Ldx => insn.src_reg(),
+                OpcodeClass::Stx | OpcodeClass::St => insn.dst_reg(),
+                _ => return None,
+            };
+            (base == 10 && insn.offset < 0).then(|| -(insn.offset as isize) as usize)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::insn::WideInsn;
+
+    #[test]
+    fn single_function() {
+        let insns = [
+            BpfInsn::new(0x7a, 10, 0, -12, 0), // *(u64 *)(r10 - 12) = 0
+            BpfInsn::mov64_imm(0, 0),
+            BpfInsn::exit(),
+        ];
+        assert_eq!(
+            split(&insns),
+            [Subprog {
+                start: 0,
+                end: 3,
+                stack_depth: 16
+            }]
+        );
+    }
+
+    #[test]
+    fn call_targets_start_functions() {
+        let insns = [
+            BpfInsn::call_local(2),           // 0: call 3
+            BpfInsn::call_local(3),           // 1: call 5
+            BpfInsn::exit(),                  // 2
+            BpfInsn::new(0x7a, 10, 0, -8, 0), // 3
+            BpfInsn::exit(),                  // 4
+            BpfInsn::call_local(-3),          // 5: call 3
+            BpfInsn::exit(),                  // 6
+            BpfInsn::call_local(100),         // 7: out of range
+        ];
+        let subprogs = split(&insns);
+        let bounds: Vec<_> = subprogs.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(bounds, [(0, 3), (3, 5), (5, 8)]);
+        assert_eq!(subprogs[1].stack_depth, 8);
+        assert_eq!(subprogs[2].stack_depth, 0);
+        assert!(subprogs[2].contains(7));
+        assert!(!subprogs[2].contains(8));
+    }
+
+    #[test]
+    fn loaded_functions_start_functions() {
+        let func = WideInsn::ld_func(2, 3);
+        let insns = [
+            func.insn,                // 0: r2 = &func 4
+            func.next,                // 1
+            BpfInsn::mov64_imm(0, 0), // 2
+            BpfInsn::exit(),          // 3
+            BpfInsn::mov64_imm(0, 1), // 4
+            BpfInsn::exit(),          // 5
+        ];
+        let bounds: Vec<_> = split(&insns).iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(bounds, [(0, 4), (4, 6)]);
+        assert_eq!(func_target(0, &insns[0]), Some(4));
+        assert_eq!(func_target(4, &insns[4]), None);
+    }
+
+    #[test]
+    fn helper_calls_are_not_targets() {
+        assert_eq!(call_target(0, &BpfInsn::call(1)), None);
+        assert_eq!(call_target(4, &BpfInsn::call_local(-5)), Some(0));
+        assert_eq!(call_target(4, &BpfInsn::call_local(-6)), None);
+    }
+}
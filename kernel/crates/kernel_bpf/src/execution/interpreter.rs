@@ -10,41 +10,30 @@
 //! The interpreter enforces profile-specific limits:
 //! - Instruction count bounded by `P::MAX_INSN_COUNT`
 //! - Stack size bounded by `P::MAX_STACK_SIZE`
+//! - Tail call chains bounded by `P::MAX_TAIL_CALLS`
+//! - BPF function calls bounded by [`MAX_CALL_FRAMES`] frames, which
+//!   share the stack
+//! - `bpf_loop` iterations bounded by `P::MAX_LOOP_ITERATIONS`; each
+//!   iteration calls the callback like a BPF function
 
 extern crate alloc;
 
+use alloc::sync::Arc;
 use alloc::vec;
+use alloc::vec::Vec;
 use core::marker::PhantomData;
 
-use super::{BpfContext, BpfError, BpfExecutor, BpfResult};
+use super::{
+    BpfContext, BpfError, BpfExecutor, BpfResult, TAIL_CALL_FAILED, TailCallTarget, TailCalls,
+};
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::opcode::{AluOp, JmpOp, MemSize, OpcodeClass, SourceType};
 use crate::bytecode::program::BpfProgram;
 use crate::bytecode::registers::{Register, RegisterFile};
+use crate::bytecode::subprog::{self, MAX_CALL_FRAMES};
+use crate::helpers::{self, HelperId};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
-// SAFETY: These functions are defined in the kernel and linked into the final binary.
-// They follow the C calling convention which matches the interpreter's expectations.
-// In host-based tests, these symbols are provided by the `helpers_stub` module in `mod.rs`.
-unsafe extern "C" {
-    fn bpf_ktime_get_ns() -> u64;
-    fn bpf_get_interrupt_latency_ns(ctx: *const BpfContext) -> u64;
-    fn bpf_get_boot_time_ms(ctx: *const BpfContext) -> u64;
-    fn bpf_get_kernel_heap_kb(ctx: *const BpfContext) -> u64;
-    fn bpf_get_kernel_image_mb(ctx: *const BpfContext) -> u64;
-    fn bpf_trace_printk(fmt: *const u8, size: u32) -> i32;
-    fn bpf_map_lookup_elem(map_id: u32, key: *const u8) -> *mut u8;
-    fn bpf_map_update_elem(map_id: u32, key: *const u8, value: *const u8, flags: u64) -> i32;
-    fn bpf_map_delete_elem(map_id: u32, key: *const u8) -> i32;
-    fn bpf_ringbuf_output(map_id: u32, data: *const u8, size: u64, flags: u64) -> i64;
-    fn bpf_timeseries_push(map_id: u32, key: *const u8, value: *const u8) -> i64;
-    // Robotics helpers
-    fn bpf_gpio_read(pin: u32) -> i64;
-    fn bpf_gpio_write(pin: u32, value: u32) -> i64;
-    fn bpf_motor_emergency_stop(reason: u32) -> i64;
-    fn bpf_pwm_write(pwm_id: u32, channel: u32, duty: u32) -> i64;
-}
-
 /// BPF bytecode interpreter.
 ///
 /// The interpreter executes BPF programs instruction by instruction.
@@ -61,6 +50,231 @@ impl<P: PhysicalProfile> Interpreter<P> {
         }
     }
 
+    /// Execute a program whose tail calls are resolved through
+    /// `tail_calls`.
+    ///
+    /// The chain shares the stack and the instruction budget of the run.
+    pub fn execute_with_tail_calls(
+        &self,
+        program: &BpfProgram<P>,
+        ctx: &BpfContext,
+        tail_calls: &mut TailCalls<'_, P>,
+    ) -> BpfResult {
+        let mut current = program;
+        let mut insns = current.instructions();
+
+        if insns.is_empty() {
+            return Err(BpfError::NotLoaded);
+        }
+
+        // Program the chain continued with, once there was a tail call
+        let mut target: Option<Arc<dyn TailCallTarget<P>>> = None;
+
+        // Initialize register file
+        let mut regs = RegisterFile::new();
+
+        // Allocate stack
+        let mut stack = vec![0u8; P::MAX_STACK_SIZE];
+
+        // R1 = context pointer
+        regs.set(Register::R1, ctx as *const _ as u64);
+
+        // R10 = frame pointer (top of stack)
+        let fp = stack.as_ptr() as u64 + P::MAX_STACK_SIZE as u64;
+        // SAFETY: We are initializing the frame pointer R10 with a valid stack address.
+        // This is safe because we just allocated the stack.
+        unsafe {
+            regs.set_unchecked(Register::R10, fp);
+        }
+
+        // Callers of the running function. The running function's frame
+        // ends at `top` and takes `depth` bytes below it.
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut top = stack.len();
+        let mut depth = current.subprogs()[0].stack_depth;
+
+        // Execute
+        let mut pc = 0usize;
+        let mut insn_count = 0usize;
+        let insn_limit = P::MAX_INSN_COUNT;
+
+        loop {
+            // Check bounds
+            if pc >= insns.len() {
+                return Err(BpfError::OutOfBounds);
+            }
+
+            // Check instruction limit
+            insn_count += 1;
+            if insn_count > insn_limit {
+                return Err(BpfError::Timeout);
+            }
+
+            let insn = &insns[pc];
+
+            // A BPF function call gets a frame below the caller's, and so
+            // does the callback of a bpf_loop
+            let entry = if let Some(callee) = subprog::call_target(pc, insn) {
+                Some((callee, None))
+            } else if insn.is_call() && insn.imm == HelperId::Loop as i32 {
+                match Self::start_loop(&regs) {
+                    Ok(looping) => Some((looping.callback, Some(looping))),
+                    Err(result) => {
+                        regs.set(Register::R0, result);
+                        pc += 1;
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some((callee, looping)) = entry {
+                let callee_depth = current
+                    .subprog_at(callee)
+                    .ok_or(BpfError::InvalidInstruction)?
+                    .stack_depth;
+                if frames.len() + 2 > MAX_CALL_FRAMES || top < depth + callee_depth {
+                    return Err(BpfError::StackOverflow);
+                }
+
+                if let Some(looping) = &looping {
+                    regs.set(Register::R1, 0);
+                    regs.set(Register::R2, looping.ctx);
+                }
+                frames.push(Frame {
+                    return_pc: pc + 1,
+                    callee_saved: [Register::R6, Register::R7, Register::R8, Register::R9]
+                        .map(|reg| regs.get(reg)),
+                    depth,
+                    looping,
+                });
+                top -= depth;
+                depth = callee_depth;
+                // SAFETY: The new frame pointer stays within the stack.
+                unsafe {
+                    regs.set_unchecked(Register::R10, stack.as_ptr() as u64 + top as u64);
+                }
+                pc = callee;
+                continue;
+            }
+
+            // A tail call starts over with the target's first instruction.
+            // Functions can't tail-call (the verifier rejects it), so there
+            // the call just fails.
+            if insn.is_call() && !insn.is_pseudo_call() && insn.imm == HelperId::TailCall as i32 {
+                let (map_id, index) = (regs.get(Register::R2), regs.get(Register::R3));
+                let next = if frames.is_empty() {
+                    tail_calls.next(map_id, index)
+                } else {
+                    None
+                };
+                match next {
+                    Some(next) => {
+                        current = target.insert(next).program();
+                        insns = current.instructions();
+                        depth = current.subprogs()[0].stack_depth;
+                        regs.set(Register::R1, ctx as *const _ as u64);
+                        pc = 0;
+                    }
+                    None => {
+                        regs.set(Register::R0, TAIL_CALL_FAILED);
+                        pc += 1;
+                    }
+                }
+                continue;
+            }
+
+            // Handle wide instruction
+            if insn.is_wide() {
+                if pc + 1 >= insns.len() {
+                    return Err(BpfError::InvalidInstruction);
+                }
+                let next_insn = &insns[pc + 1];
+                let imm64 = match subprog::func_target(pc, insn) {
+                    // Function addresses are instruction indices
+                    Some(start) => start as u64,
+                    None => (insn.imm as u32 as u64) | ((next_insn.imm as u32 as u64) << 32),
+                };
+
+                let dst = Register::from_raw(insn.dst_reg()).ok_or(BpfError::InvalidInstruction)?;
+                regs.set(dst, imm64);
+
+                pc += 2;
+                continue;
+            }
+
+            // Execute instruction
+            match self.execute_insn(insn, &mut regs, &mut stack[..top], ctx)? {
+                InsnResult::Continue => {
+                    pc += 1;
+                }
+                InsnResult::Jump(offset) => {
+                    pc = ((pc as i64) + 1 + (offset as i64)) as usize;
+                }
+                InsnResult::Exit => {
+                    let Some(mut frame) = frames.pop() else {
+                        return Ok(regs.return_value());
+                    };
+                    // A callback runs again in the same frame until it
+                    // returns non-zero or the count is reached. The helper
+                    // returns the number of iterations run.
+                    if let Some(looping) = &mut frame.looping {
+                        looping.index += 1;
+                        if regs.get(Register::R0) == 0 && looping.index < looping.count {
+                            regs.set(Register::R1, looping.index);
+                            regs.set(Register::R2, looping.ctx);
+                            pc = looping.callback;
+                            frames.push(frame);
+                            continue;
+                        }
+                        regs.set(Register::R0, looping.index);
+                    }
+                    // Back to the caller, with R0 as the function's result
+                    for (reg, value) in [Register::R6, Register::R7, Register::R8, Register::R9]
+                        .into_iter()
+                        .zip(frame.callee_saved)
+                    {
+                        regs.set(reg, value);
+                    }
+                    top += frame.depth;
+                    depth = frame.depth;
+                    // SAFETY: The caller's frame pointer is within the stack.
+                    unsafe {
+                        regs.set_unchecked(Register::R10, stack.as_ptr() as u64 + top as u64);
+                    }
+                    pc = frame.return_pc;
+                }
+                InsnResult::WideLoad => {
+                    // Handled above, shouldn't reach here
+                    return Err(BpfError::InvalidInstruction);
+                }
+            }
+        }
+    }
+
+    /// Check the arguments of a `bpf_loop` call.
+    ///
+    /// Returns the state of the loop, or the helper's result if no
+    /// iteration runs.
+    fn start_loop(regs: &RegisterFile) -> Result<Loop, u64> {
+        let count = regs.get(Register::R1);
+        if regs.get(Register::R4) != 0 {
+            return Err(helpers::LOOP_FAILED);
+        }
+        if count > P::MAX_LOOP_ITERATIONS as u64 {
+            return Err(helpers::LOOP_TOO_LONG);
+        }
+        if count == 0 {
+            return Err(0);
+        }
+        Ok(Loop {
+            callback: regs.get(Register::R2) as usize,
+            ctx: regs.get(Register::R3),
+            index: 0,
+            count,
+        })
+    }
+
     /// Execute a single instruction.
     fn execute_insn(
         &self,
@@ -123,52 +337,80 @@ impl<P: PhysicalProfile> Interpreter<P> {
 
         let alu_op = AluOp::from_opcode(insn.opcode).ok_or(BpfError::InvalidInstruction)?;
 
-        let result = match alu_op {
-            AluOp::Add => dst_val.wrapping_add(src_val),
-            AluOp::Sub => dst_val.wrapping_sub(src_val),
-            AluOp::Mul => dst_val.wrapping_mul(src_val),
-            AluOp::Div => {
-                if src_val == 0 {
-                    return Err(BpfError::DivisionByZero);
-                }
-                dst_val / src_val
-            }
-            AluOp::Or => dst_val | src_val,
-            AluOp::And => dst_val & src_val,
-            AluOp::Lsh => dst_val << (src_val & 0x3f),
-            AluOp::Rsh => dst_val >> (src_val & 0x3f),
-            AluOp::Neg => (-(dst_val as i64)) as u64,
-            AluOp::Mod => {
-                if src_val == 0 {
-                    return Err(BpfError::DivisionByZero);
+        let result = if is_64bit {
+            match alu_op {
+                AluOp::Add => dst_val.wrapping_add(src_val),
+                AluOp::Sub => dst_val.wrapping_sub(src_val),
+                AluOp::Mul => dst_val.wrapping_mul(src_val),
+                AluOp::Div => {
+                    if src_val == 0 {
+                        return Err(BpfError::DivisionByZero);
+                    }
+                    dst_val / src_val
                 }
-                dst_val % src_val
-            }
-            AluOp::Xor => dst_val ^ src_val,
-            AluOp::Mov => src_val,
-            AluOp::Arsh => ((dst_val as i64) >> (src_val & 0x3f)) as u64,
-            AluOp::End => {
-                // Byte swap
-                match insn.imm {
-                    16 => (dst_val as u16).swap_bytes() as u64,
-                    32 => (dst_val as u32).swap_bytes() as u64,
-                    64 => dst_val.swap_bytes(),
-                    _ => return Err(BpfError::InvalidInstruction),
+                AluOp::Or => dst_val | src_val,
+                AluOp::And => dst_val & src_val,
+                AluOp::Lsh => dst_val << (src_val & 0x3f),
+                AluOp::Rsh => dst_val >> (src_val & 0x3f),
+                AluOp::Neg => (dst_val as i64).wrapping_neg() as u64,
+                AluOp::Mod => {
+                    if src_val == 0 {
+                        return Err(BpfError::DivisionByZero);
+                    }
+                    dst_val % src_val
                 }
+                AluOp::Xor => dst_val ^ src_val,
+                AluOp::Mov => src_val,
+                AluOp::Arsh => ((dst_val as i64) >> (src_val & 0x3f)) as u64,
+                AluOp::End => Self::byte_swap(insn, dst_val)?,
             }
-        };
-
-        // Truncate to 32 bits for 32-bit ALU
-        let result = if is_64bit {
-            result
         } else {
-            (result as u32) as u64
+            // 32-bit ALU operates on the low halves and zero-extends the result
+            let dst_val = dst_val as u32;
+            let src_val = src_val as u32;
+            let result = match alu_op {
+                AluOp::Add => dst_val.wrapping_add(src_val),
+                AluOp::Sub => dst_val.wrapping_sub(src_val),
+                AluOp::Mul => dst_val.wrapping_mul(src_val),
+                AluOp::Div => {
+                    if src_val == 0 {
+                        return Err(BpfError::DivisionByZero);
+                    }
+                    dst_val / src_val
+                }
+                AluOp::Or => dst_val | src_val,
+                AluOp::And => dst_val & src_val,
+                AluOp::Lsh => dst_val << (src_val & 0x1f),
+                AluOp::Rsh => dst_val >> (src_val & 0x1f),
+                AluOp::Neg => (dst_val as i32).wrapping_neg() as u32,
+                AluOp::Mod => {
+                    if src_val == 0 {
+                        return Err(BpfError::DivisionByZero);
+                    }
+                    dst_val % src_val
+                }
+                AluOp::Xor => dst_val ^ src_val,
+                AluOp::Mov => src_val,
+                AluOp::Arsh => ((dst_val as i32) >> (src_val & 0x1f)) as u32,
+                AluOp::End => Self::byte_swap(insn, regs.get(dst))? as u32,
+            };
+            result as u64
         };
 
         regs.set(dst, result);
         Ok(())
     }
 
+    /// Byte swap for `BPF_END`.
+    fn byte_swap(insn: &BpfInsn, value: u64) -> Result<u64, BpfError> {
+        match insn.imm {
+            16 => Ok((value as u16).swap_bytes() as u64),
+            32 => Ok((value as u32).swap_bytes() as u64),
+            64 => Ok(value.swap_bytes()),
+            _ => Err(BpfError::InvalidInstruction),
+        }
+    }
+
     /// Execute a jump instruction.
     fn execute_jmp(
         &self,
@@ -204,11 +446,16 @@ impl<P: PhysicalProfile> Interpreter<P> {
             insn.imm as i64 as u64
         };
 
-        // Truncate to 32 bits for 32-bit jumps
-        let (dst_val, src_val) = if is_64bit {
-            (dst_val, src_val)
+        // 32-bit jumps compare the low halves, sign-extending for signed ops
+        let (dst_val, src_val, dst_signed, src_signed) = if is_64bit {
+            (dst_val, src_val, dst_val as i64, src_val as i64)
         } else {
-            ((dst_val as u32) as u64, (src_val as u32) as u64)
+            (
+                (dst_val as u32) as u64,
+                (src_val as u32) as u64,
+                (dst_val as i32) as i64,
+                (src_val as i32) as i64,
+            )
         };
 
         let condition = match jmp_op {
@@ -219,10 +466,10 @@ impl<P: PhysicalProfile> Interpreter<P> {
             JmpOp::Jle => dst_val <= src_val,
             JmpOp::Jset => (dst_val & src_val) != 0,
             JmpOp::Jne => dst_val != src_val,
-            JmpOp::Jsgt => (dst_val as i64) > (src_val as i64),
-            JmpOp::Jsge => (dst_val as i64) >= (src_val as i64),
-            JmpOp::Jslt => (dst_val as i64) < (src_val as i64),
-            JmpOp::Jsle => (dst_val as i64) <= (src_val as i64),
+            JmpOp::Jsgt => dst_signed > src_signed,
+            JmpOp::Jsge => dst_signed >= src_signed,
+            JmpOp::Jslt => dst_signed < src_signed,
+            JmpOp::Jsle => dst_signed <= src_signed,
             _ => return Err(BpfError::InvalidInstruction),
         };
 
@@ -252,7 +499,7 @@ impl<P: PhysicalProfile> Interpreter<P> {
         ];
 
         // Execute helper
-        let result = self.call_helper(helper_id, args, ctx)?;
+        let result = call_helper(helper_id, args, ctx)?;
 
         // Store result in R0
         regs.set(Register::R0, result);
@@ -260,81 +507,6 @@ impl<P: PhysicalProfile> Interpreter<P> {
         Ok(InsnResult::Continue)
     }
 
-    /// Call a helper function.
-    fn call_helper(
-        &self,
-        helper_id: i32,
-        args: [u64; 5],
-        ctx: &BpfContext,
-    ) -> Result<u64, BpfError> {
-        // SAFETY: Calling BPF helpers is inherently unsafe as they are extern "C" functions.
-        // We rely on the BPF verifier (in a full implementation) to ensure arguments are valid.
-        // In this interpreter, we assume arguments are reasonably well-formed or the helper handles invalid inputs.
-        unsafe {
-            match helper_id {
-                // bpf_ktime_get_ns
-                1 => Ok(bpf_ktime_get_ns()),
-
-                // bpf_get_interrupt_latency_ns
-                13 => Ok(bpf_get_interrupt_latency_ns(ctx as *const BpfContext)),
-
-                // bpf_get_boot_time_ms
-                15 => Ok(bpf_get_boot_time_ms(ctx as *const BpfContext)),
-
-                // bpf_get_kernel_heap_kb
-                16 => Ok(bpf_get_kernel_heap_kb(ctx as *const BpfContext)),
-
-                // bpf_get_kernel_image_mb
-                17 => Ok(bpf_get_kernel_image_mb(ctx as *const BpfContext)),
-
-                // bpf_trace_printk
-                2 => Ok(bpf_trace_printk(args[0] as *const u8, args[1] as u32) as u64),
-
-                // bpf_map_lookup_elem
-                5 => Ok(bpf_map_lookup_elem(args[0] as u32, args[1] as *const u8) as u64),
-
-                // bpf_map_update_elem
-                6 => Ok(bpf_map_update_elem(
-                    args[0] as u32,
-                    args[1] as *const u8,
-                    args[2] as *const u8,
-                    args[3],
-                ) as u64),
-
-                // bpf_map_delete_elem
-                7 => Ok(bpf_map_delete_elem(args[0] as u32, args[1] as *const u8) as u64),
-
-                // bpf_ringbuf_output
-                8 => Ok(
-                    bpf_ringbuf_output(args[0] as u32, args[1] as *const u8, args[2], args[3])
-                        as u64,
-                ),
-
-                // bpf_timeseries_push
-                9 => Ok(bpf_timeseries_push(
-                    args[0] as u32,
-                    args[1] as *const u8,
-                    args[2] as *const u8,
-                ) as u64),
-
-                // Robotics Helpers
-                // bpf_gpio_set (1003) -> bpf_gpio_write
-                1003 => Ok(bpf_gpio_write(args[0] as u32, args[1] as u32) as u64),
-
-                // bpf_gpio_get (1004) -> bpf_gpio_read
-                1004 => Ok(bpf_gpio_read(args[0] as u32) as u64),
-
-                // bpf_motor_emergency_stop (1000)
-                1000 => Ok(bpf_motor_emergency_stop(args[0] as u32) as u64),
-                // bpf_pwm_write (1005)
-                1005 => Ok(bpf_pwm_write(args[0] as u32, args[1] as u32, args[2] as u32) as u64),
-
-                // Unknown helper
-                _ => Err(BpfError::InvalidHelper(helper_id)),
-            }
-        }
-    }
-
     /// Execute a load instruction.
     fn execute_load(
         &self,
@@ -541,81 +713,50 @@ impl<P: PhysicalProfile> Default for Interpreter<P> {
 
 impl<P: PhysicalProfile> BpfExecutor<P> for Interpreter<P> {
     fn execute(&self, program: &BpfProgram<P>, ctx: &BpfContext) -> BpfResult {
-        let insns = program.instructions();
-
-        if insns.is_empty() {
-            return Err(BpfError::NotLoaded);
-        }
-
-        // Initialize register file
-        let mut regs = RegisterFile::new();
-
-        // Allocate stack
-        let mut stack = vec![0u8; P::MAX_STACK_SIZE];
-
-        // R1 = context pointer
-        regs.set(Register::R1, ctx as *const _ as u64);
-
-        // R10 = frame pointer (top of stack)
-        let fp = stack.as_ptr() as u64 + P::MAX_STACK_SIZE as u64;
-        // SAFETY: We are initializing the frame pointer R10 with a valid stack address.
-        // This is safe because we just allocated the stack.
-        unsafe {
-            regs.set_unchecked(Register::R10, fp);
-        }
-
-        // Execute
-        let mut pc = 0usize;
-        let mut insn_count = 0usize;
-        let insn_limit = P::MAX_INSN_COUNT;
-
-        loop {
-            // Check bounds
-            if pc >= insns.len() {
-                return Err(BpfError::OutOfBounds);
-            }
-
-            // Check instruction limit
-            insn_count += 1;
-            if insn_count > insn_limit {
-                return Err(BpfError::Timeout);
-            }
-
-            let insn = &insns[pc];
-
-            // Handle wide instruction
-            if insn.is_wide() {
-                if pc + 1 >= insns.len() {
-                    return Err(BpfError::InvalidInstruction);
-                }
-                let next_insn = &insns[pc + 1];
-                let imm64 = (insn.imm as u32 as u64) | ((next_insn.imm as u32 as u64) << 32);
+        self.execute_with_tail_calls(program, ctx, &mut TailCalls::none())
+    }
+}
 
-                let dst = Register::from_raw(insn.dst_reg()).ok_or(BpfError::InvalidInstruction)?;
-                regs.set(dst, imm64);
+/// Call a helper function through the helper registry.
+///
+/// Shared by the interpreter and the x86_64 JIT, which calls back into it
+/// through a trampoline.
+pub(crate) fn call_helper(
+    helper_id: i32,
+    args: [u64; 5],
+    ctx: &BpfContext,
+) -> Result<u64, BpfError> {
+    let def = helpers::lookup(helper_id)
+        .filter(|def| def.is_available())
+        .ok_or(BpfError::InvalidHelper(helper_id))?;
+
+    // SAFETY: Calling BPF helpers is inherently unsafe as they are extern "C" functions.
+    // We rely on the BPF verifier to ensure arguments match the helper signature.
+    unsafe { def.call(args, ctx) }.ok_or(BpfError::InvalidHelper(helper_id))
+}
 
-                pc += 2;
-                continue;
-            }
+/// Caller state saved while a BPF function runs.
+struct Frame {
+    /// Instruction after the call
+    return_pc: usize,
+    /// Caller's R6-R9
+    callee_saved: [u64; 4],
+    /// Stack bytes of the caller's frame
+    depth: usize,
+    /// The `bpf_loop` running the function as its callback, if any
+    looping: Option<Loop>,
+}
 
-            // Execute instruction
-            match self.execute_insn(insn, &mut regs, &mut stack, ctx)? {
-                InsnResult::Continue => {
-                    pc += 1;
-                }
-                InsnResult::Jump(offset) => {
-                    pc = ((pc as i64) + 1 + (offset as i64)) as usize;
-                }
-                InsnResult::Exit => {
-                    return Ok(regs.return_value());
-                }
-                InsnResult::WideLoad => {
-                    // Handled above, shouldn't reach here
-                    return Err(BpfError::InvalidInstruction);
-                }
-            }
-        }
-    }
+/// State of a running `bpf_loop`.
+struct Loop {
+    /// First instruction of the callback
+    callback: usize,
+    /// Context argument passed to each iteration
+    ctx: u64,
+    /// Iterations run so far
+    index: u64,
+    /// Iterations asked for
+    count: u64,
 }
 
 /// Result of executing a single instruction.
@@ -633,6 +774,7 @@ enum InsnResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bytecode::insn::WideInsn;
     use crate::bytecode::program::{BpfProgType, ProgramBuilder};
     use crate::execution::helpers_stub;
 
@@ -740,7 +882,7 @@ mod tests {
         let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
             .insn(BpfInsn::mov64_imm(1, 0)) // r1 = map_id (0)
             .insn(BpfInsn::mov64_imm(2, 0)) // r2 = key_ptr (dummy)
-            .insn(BpfInsn::call(5)) // r0 = bpf_map_lookup_elem(r1, r2)
+            .insn(BpfInsn::call(3)) // r0 = bpf_map_lookup_elem(r1, r2)
             .exit()
             .build()
             .expect("valid program");
@@ -768,7 +910,7 @@ mod tests {
             .insn(BpfInsn::mov64_imm(2, 0)) // r2 = key_ptr (dummy)
             .insn(BpfInsn::mov64_imm(3, 0)) // r3 = value_ptr (dummy)
             .insn(BpfInsn::mov64_imm(4, 0)) // r4 = flags (0)
-            .insn(BpfInsn::call(6)) // r0 = bpf_map_update_elem(r1, r2, r3, r4)
+            .insn(BpfInsn::call(4)) // r0 = bpf_map_update_elem(r1, r2, r3, r4)
             .exit()
             .build()
             .expect("valid program");
@@ -790,7 +932,7 @@ mod tests {
         let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
             .insn(BpfInsn::mov64_imm(1, 0)) // r1 = map_id (0)
             .insn(BpfInsn::mov64_imm(2, 0)) // r2 = key_ptr (dummy)
-            .insn(BpfInsn::call(7)) // r0 = bpf_map_delete_elem(r1, r2)
+            .insn(BpfInsn::call(5)) // r0 = bpf_map_delete_elem(r1, r2)
             .exit()
             .build()
             .expect("valid program");
@@ -803,6 +945,33 @@ mod tests {
         assert_eq!(result, Ok(0));
     }
 
+    #[test]
+    fn execute_verified_timeseries_query() {
+        // Helper 1008 = bpf_timeseries_query(map_id, n, out, size), with
+        // `out` a stack buffer addressed off a copy of r10
+        let map = WideInsn::ld_map_fd(1, 0);
+        let insns = [
+            map.insn, // r1 = map 0
+            map.next,
+            BpfInsn::mov64_imm(2, 5),       // r2 = n
+            BpfInsn::mov64_reg(3, 10),      // r3 = fp
+            BpfInsn::add64_imm(3, -64),     // r3 = fp - 64
+            BpfInsn::mov64_imm(4, 64),      // r4 = size
+            BpfInsn::mov64_reg(6, 3),       // r6 = out
+            BpfInsn::call(1008),            // bpf_timeseries_query(r1, r2, r3, r4)
+            BpfInsn::new(0x79, 0, 6, 0, 0), // r0 = *(u64 *)(r6 + 0)
+            BpfInsn::exit(),
+        ];
+        let program =
+            crate::verifier::verify_program(BpfProgType::SocketFilter, &insns).expect("verifies");
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+
+        // The count the helper wrote into the buffer
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(5));
+    }
+
     #[test]
     fn execute_gpio_helper() {
         // Test that calling bpf_gpio_write helper works
@@ -839,4 +1008,199 @@ mod tests {
         let result = interpreter.execute(&program, &ctx);
         assert_eq!(result, Ok(12345));
     }
+
+    struct Target(BpfProgram<ActiveProfile>);
+
+    impl TailCallTarget<ActiveProfile> for Target {
+        fn id(&self) -> u32 {
+            1
+        }
+
+        fn program(&self) -> &BpfProgram<ActiveProfile> {
+            &self.0
+        }
+    }
+
+    /// A program that tail-calls slot `index` and returns `fallback` if
+    /// that fails.
+    fn tail_caller(index: i32, fallback: i32) -> BpfProgram<ActiveProfile> {
+        ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(2, 1)) // r2 = map ID
+            .insn(BpfInsn::mov64_imm(3, index)) // r3 = index
+            .insn(BpfInsn::call(HelperId::TailCall as i32))
+            .insn(BpfInsn::mov64_imm(0, fallback))
+            .exit()
+            .build()
+            .expect("valid program")
+    }
+
+    #[test]
+    fn tail_call_continues_with_target() {
+        let target: Arc<dyn TailCallTarget<ActiveProfile>> = Arc::new(Target(
+            ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+                .insn(BpfInsn::mov64_reg(0, 1)) // r0 = ctx
+                .exit()
+                .build()
+                .expect("valid program"),
+        ));
+        let resolve = |map_id: u32, index: u32| (map_id == 1 && index == 0).then(|| target.clone());
+        let mut tail_calls = TailCalls::new(&resolve);
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+        let result =
+            interpreter.execute_with_tail_calls(&tail_caller(0, 99), &ctx, &mut tail_calls);
+
+        // The target got the context and its result is the program's
+        assert_eq!(result, Ok(&ctx as *const _ as u64));
+        assert_eq!(tail_calls.count(), 1);
+    }
+
+    #[test]
+    fn failed_tail_call_continues_after_the_call() {
+        let resolve = |_: u32, _: u32| None;
+        let mut tail_calls = TailCalls::new(&resolve);
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::call(HelperId::TailCall as i32))
+            .exit()
+            .build()
+            .expect("valid program");
+
+        let result = interpreter.execute_with_tail_calls(&program, &ctx, &mut tail_calls);
+        assert_eq!(result, Ok(TAIL_CALL_FAILED));
+        assert_eq!(tail_calls.count(), 0);
+
+        // Without a resolver, every tail call fails
+        assert_eq!(interpreter.execute(&tail_caller(0, 7), &ctx), Ok(7));
+    }
+
+    #[test]
+    fn tail_call_chain_is_bounded() {
+        // Slot 0 holds a program that tail-calls slot 0
+        let target: Arc<dyn TailCallTarget<ActiveProfile>> = Arc::new(Target(tail_caller(0, 7)));
+        let resolve = |_: u32, _: u32| Some(target.clone());
+        let mut tail_calls = TailCalls::new(&resolve);
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+        let result = interpreter.execute_with_tail_calls(&tail_caller(0, 1), &ctx, &mut tail_calls);
+
+        assert_eq!(result, Ok(7));
+        assert_eq!(tail_calls.count(), ActiveProfile::MAX_TAIL_CALLS);
+    }
+
+    #[test]
+    fn function_call_gets_its_own_frame() {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(6, 100)) // 0: r6 = 100
+            .insn(BpfInsn::new(0x7a, 10, 0, -8, 5)) // 1: *(u64 *)(r10 - 8) = 5
+            .insn(BpfInsn::mov64_imm(1, 20)) // 2: r1 = 20
+            .insn(BpfInsn::call_local(4)) // 3: call 8
+            .insn(BpfInsn::add64_reg(0, 6)) // 4: r0 += r6
+            .insn(BpfInsn::new(0x79, 1, 10, -8, 0)) // 5: r1 = *(u64 *)(r10 - 8)
+            .insn(BpfInsn::add64_reg(0, 1)) // 6: r0 += r1
+            .exit() // 7
+            .insn(BpfInsn::mov64_imm(6, 1)) // 8: r6 = 1 (caller's is restored)
+            .insn(BpfInsn::new(0x7a, 10, 0, -8, 7)) // 9: *(u64 *)(r10 - 8) = 7
+            .insn(BpfInsn::new(0x79, 0, 10, -8, 0)) // 10: r0 = *(u64 *)(r10 - 8)
+            .insn(BpfInsn::add64_reg(0, 1)) // 11: r0 += r1
+            .exit() // 12
+            .build()
+            .expect("valid program");
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+
+        // (7 + 20) from the function, plus r6 and the caller's stack slot
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(27 + 100 + 5));
+    }
+
+    #[test]
+    fn function_calls_are_bounded() {
+        // Each function calls the next one, one more than allowed
+        let mut builder = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter);
+        for _ in 0..MAX_CALL_FRAMES {
+            builder = builder.insn(BpfInsn::call_local(1)).exit();
+        }
+        let program = builder
+            .insn(BpfInsn::mov64_imm(0, 1))
+            .exit()
+            .build()
+            .expect("valid program");
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+        assert_eq!(
+            interpreter.execute(&program, &ctx),
+            Err(BpfError::StackOverflow)
+        );
+
+        let program = BpfProgram::<ActiveProfile>::new(
+            BpfProgType::SocketFilter,
+            program.instructions()[2..].to_vec(),
+            0,
+        )
+        .expect("valid program");
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(1));
+    }
+
+    /// Sums the iteration numbers into a stack slot through bpf_loop's
+    /// context, returning the sum plus 100 per iteration.
+    fn summing_loop(count: i32, callback_result: BpfInsn) -> BpfProgram<ActiveProfile> {
+        let func = WideInsn::ld_func(2, 10);
+        ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::new(0x7a, 10, 0, -8, 0)) // 0: *(u64 *)(r10 - 8) = 0
+            .insn(BpfInsn::mov64_imm(1, count)) // 1: r1 = count
+            .insn(func.insn) // 2: r2 = &func 13
+            .insn(func.next) // 3
+            .insn(BpfInsn::mov64_reg(3, 10)) // 4: r3 = r10
+            .insn(BpfInsn::add64_imm(3, -8)) // 5: r3 -= 8
+            .insn(BpfInsn::mov64_imm(4, 0)) // 6: r4 = 0
+            .insn(BpfInsn::call(HelperId::Loop as i32)) // 7: bpf_loop
+            .insn(BpfInsn::mov64_reg(6, 0)) // 8: r6 = r0
+            .insn(BpfInsn::new(0x79, 0, 10, -8, 0)) // 9: r0 = *(u64 *)(r10 - 8)
+            .insn(BpfInsn::new(0x27, 6, 0, 0, 100)) // 10: r6 *= 100
+            .insn(BpfInsn::add64_reg(0, 6)) // 11: r0 += r6
+            .exit() // 12
+            .insn(BpfInsn::new(0x79, 3, 2, 0, 0)) // 13: r3 = *(u64 *)(r2 + 0)
+            .insn(BpfInsn::add64_reg(3, 1)) // 14: r3 += r1
+            .insn(BpfInsn::new(0x7b, 2, 3, 0, 0)) // 15: *(u64 *)(r2 + 0) = r3
+            .insn(callback_result) // 16
+            .exit() // 17
+            .build()
+            .expect("valid program")
+    }
+
+    #[test]
+    fn loop_calls_back_once_per_iteration() {
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+
+        // 0 + 1 + 2 + 3 + 4 over five iterations
+        let program = summing_loop(5, BpfInsn::mov64_imm(0, 0));
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(10 + 500));
+
+        // The callback stops the loop by returning non-zero
+        let program = summing_loop(5, BpfInsn::mov64_reg(0, 1));
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(1 + 200));
+
+        let program = summing_loop(0, BpfInsn::mov64_imm(0, 0));
+        assert_eq!(interpreter.execute(&program, &ctx), Ok(0));
+    }
+
+    #[test]
+    fn loop_count_is_bounded_by_profile() {
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        let ctx = BpfContext::empty();
+
+        let too_long = ActiveProfile::MAX_LOOP_ITERATIONS as i32 + 1;
+        let program = summing_loop(too_long, BpfInsn::mov64_imm(0, 0));
+        assert_eq!(
+            interpreter.execute(&program, &ctx),
+            Ok(helpers::LOOP_TOO_LONG.wrapping_mul(100))
+        );
+    }
 }
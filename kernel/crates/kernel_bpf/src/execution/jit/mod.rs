@@ -21,7 +21,18 @@
 //! | R9     | R15    | Callee-saved               |
 //! | R10    | RBP    | Frame pointer (read-only)  |
 //!
-//! Note: R9 is used as a temporary for some operations.
+//! R9-R11 are scratch registers, and R12 holds the [`JitRuntime`] pointer
+//! for the whole invocation.
+//!
+//! # Calling Convention
+//!
+//! A compiled image is called as
+//! `extern "C" fn(ctx: *const BpfContext, rt: *mut JitRuntime) -> u64`.
+//! Helper calls go through [`jit_call_helper`], which dispatches to the same
+//! helper implementations as the interpreter. Runtime errors (division by
+//! zero, failing helpers, running off the end of the program) are reported
+//! through the runtime's status word, so [`JitImage::call`] returns the same
+//! [`BpfResult`] as the interpreter would.
 //!
 //! # Stack Layout
 //!
@@ -32,17 +43,30 @@
 //! ├─────────────────────┤
 //! │ Saved RBP           │
 //! ├─────────────────────┤
-//! │ Saved RBX           │
-//! ├─────────────────────┤
-//! │ Saved R13-R15       │
-//! ├─────────────────────┤
-//! │ BPF stack space     │
-//! │ (profile max)       │
+//! │ Saved RBX, R12-R15  │
 //! ├─────────────────────┤  ← BPF R10 (frame pointer)
+//! │ BPF stack space     │
+//! │ (program depth)     │
+//! ├─────────────────────┤
 //! │                     │
 //! Low Address
 //! ```
 //!
+//! BPF functions are called with a native `call`. Each saves RBP and
+//! RBX, R13-R15 (BPF R6-R9) and reserves its own depth below them, so its
+//! frame lies below the caller's. Programs whose main frame needs more
+//! than [`MAX_JIT_STACK_SIZE`] are not compiled. The runtime counts the
+//! frames and keeps
+//! the main frame's RBP for the abort path, which can be taken from any
+//! function.
+//!
+//! # Executable Memory
+//!
+//! Images are written to memory from `bpf_jit_alloc_exec` (writable, not
+//! executable), then flipped to read-only + executable with
+//! `bpf_jit_protect_exec` before they are ever run (W^X). Both are provided
+//! by the kernel.
+//!
 //! # Profile Erasure
 //!
 //! This entire module is gated behind:
@@ -53,14 +77,24 @@
 extern crate alloc;
 
 use alloc::vec::Vec;
-use core::marker::PhantomData;
+use core::mem::offset_of;
 
 use crate::bytecode::insn::BpfInsn;
-use crate::bytecode::opcode::{AluOp, JmpOp, MemSize, OpcodeClass, SourceType};
+use crate::bytecode::opcode::{AluOp, JmpOp, MemMode, MemSize, OpcodeClass, SourceType};
 use crate::bytecode::program::BpfProgram;
-use crate::execution::{BpfContext, BpfExecutor, BpfResult};
+use crate::bytecode::subprog::{self, MAX_CALL_FRAMES, Subprog};
+use crate::execution::interpreter::call_helper;
+use crate::execution::{BpfContext, BpfError, BpfExecutor, BpfResult, MAX_JIT_STACK_SIZE};
+use crate::helpers::HelperId;
 use crate::profile::CloudProfile;
 
+// External kernel functions provided by the main kernel crate
+unsafe extern "C" {
+    fn bpf_jit_alloc_exec(size: usize) -> *mut u8;
+    fn bpf_jit_free_exec(ptr: *mut u8, size: usize);
+    fn bpf_jit_protect_exec(ptr: *mut u8, size: usize) -> bool;
+}
+
 // x86_64 register encodings (REX.W mode, 64-bit)
 const RAX: u8 = 0;
 const RCX: u8 = 1;
@@ -72,7 +106,9 @@ const RSI: u8 = 6;
 const RDI: u8 = 7;
 const R8: u8 = 8;
 const R9: u8 = 9;
-// R10-R11 are caller-saved, avoid for BPF registers
+const R10: u8 = 10;
+const R11: u8 = 11;
+const R12: u8 = 12;
 const R13: u8 = 13;
 const R14: u8 = 14;
 const R15: u8 = 15;
@@ -94,7 +130,99 @@ const BPF_TO_X64: [u8; 11] = [
 ];
 
 /// Temporary register for complex operations.
-const TMP_REG: u8 = R9;
+const TMP_REG: u8 = R11;
+
+/// Register holding the `JitRuntime` pointer.
+const RT_REG: u8 = R12;
+
+/// Jump target used for jumps that leave the program.
+const OUT_OF_PROGRAM: usize = usize::MAX;
+
+/// Program ran to completion.
+const STATUS_OK: u64 = 0;
+/// Division or modulo by zero.
+const STATUS_DIV_ZERO: u64 = 1;
+/// Control flow left the program without `exit`.
+const STATUS_OUT_OF_BOUNDS: u64 = 2;
+/// A helper failed; the error is in `JitRuntime::error`.
+const STATUS_HELPER_FAILED: u64 = 3;
+/// BPF function calls nested too deep.
+const STATUS_STACK_OVERFLOW: u64 = 4;
+
+/// Per-invocation state shared between a JIT image and the helper trampoline.
+#[repr(C)]
+struct JitRuntime {
+    /// Context the program was called with
+    ctx: *const BpfContext,
+    /// Helper ID for the pending call, written by the image
+    helper_id: i64,
+    /// One of the `STATUS_*` codes
+    status: u64,
+    /// Error reported by a failing helper
+    error: BpfError,
+    /// RBP of the main function's frame
+    frame: u64,
+    /// BPF functions currently running
+    calls: u64,
+}
+
+/// Trampoline from JIT-compiled code into the BPF helpers.
+///
+/// BPF R1-R5 are already in the System V argument registers, and the image
+/// passes the runtime as the sixth argument.
+unsafe extern "C" fn jit_call_helper(
+    r1: u64,
+    r2: u64,
+    r3: u64,
+    r4: u64,
+    r5: u64,
+    rt: *mut JitRuntime,
+) -> u64 {
+    // SAFETY: `rt` is the runtime passed to the image by `JitImage::call`,
+    // which outlives the call.
+    let rt = unsafe { &mut *rt };
+    // SAFETY: `ctx` is the reference passed to `JitImage::call`.
+    let ctx = unsafe { &*rt.ctx };
+
+    match call_helper(rt.helper_id as i32, [r1, r2, r3, r4, r5], ctx) {
+        Ok(value) => value,
+        Err(e) => {
+            rt.error = e;
+            rt.status = STATUS_HELPER_FAILED;
+            0
+        }
+    }
+}
+
+/// Two-operand ALU opcodes (`op r/m, reg`).
+mod op {
+    pub const ADD: u8 = 0x01;
+    pub const OR: u8 = 0x09;
+    pub const AND: u8 = 0x21;
+    pub const SUB: u8 = 0x29;
+    pub const XOR: u8 = 0x31;
+    pub const CMP: u8 = 0x39;
+    pub const TEST: u8 = 0x85;
+    pub const MOV: u8 = 0x89;
+}
+
+/// ModR/M opcode extensions for the immediate, shift and unary groups.
+mod ext {
+    // Group 1 (81 /ext id)
+    pub const ADD: u8 = 0;
+    pub const OR: u8 = 1;
+    pub const AND: u8 = 4;
+    pub const SUB: u8 = 5;
+    pub const XOR: u8 = 6;
+    pub const CMP: u8 = 7;
+    // Group 2 (C1 /ext ib, D3 /ext)
+    pub const SHL: u8 = 4;
+    pub const SHR: u8 = 5;
+    pub const SAR: u8 = 7;
+    // Group 3 (F7 /ext)
+    pub const NEG: u8 = 3;
+    pub const DIV: u8 = 6;
+}
 
 /// x86_64 code emitter.
 struct X64Emitter {
@@ -102,8 +230,14 @@ struct X64Emitter {
     code: Vec<u8>,
     /// Jump targets that need patching (code_offset, target_insn_idx)
     jump_patches: Vec<(usize, usize)>,
+    /// Jumps to the abort path that need patching
+    abort_patches: Vec<usize>,
     /// BPF instruction offsets in generated code
     insn_offsets: Vec<usize>,
+    /// Calls of BPF functions that need patching (code_offset, target_insn_idx)
+    call_patches: Vec<(usize, usize)>,
+    /// Entry points of BPF functions (first_insn_idx, code_offset)
+    subprog_entries: Vec<(usize, usize)>,
 }
 
 impl X64Emitter {
@@ -111,7 +245,10 @@ impl X64Emitter {
         Self {
             code: Vec::with_capacity(capacity),
             jump_patches: Vec::new(),
+            abort_patches: Vec::new(),
             insn_offsets: Vec::new(),
+            call_patches: Vec::new(),
+            subprog_entries: Vec::new(),
         }
     }
 
@@ -141,6 +278,22 @@ impl X64Emitter {
         self.jump_patches.push((self.offset() - 4, target_insn));
     }
 
+    /// Record a call of the BPF function starting at `target_insn`.
+    fn record_call(&mut self, target_insn: usize) {
+        self.call_patches.push((self.offset() - 4, target_insn));
+    }
+
+    /// Record a jump to the abort path.
+    fn record_abort(&mut self) {
+        self.abort_patches.push(self.offset() - 4);
+    }
+
+    /// Point the rel32 displacement at `at` to `target`.
+    fn patch_rel32(&mut self, at: usize, target: usize) {
+        let rel_offset = (target as i32) - (at as i32) - 4;
+        self.code[at..at + 4].copy_from_slice(&rel_offset.to_le_bytes());
+    }
+
     /// Check if register needs REX prefix (R8-R15).
     fn needs_rex(reg: u8) -> bool {
         reg >= 8
@@ -164,6 +317,13 @@ impl X64Emitter {
         rex
     }
 
+    /// Emit a REX prefix if the operand size or registers need one.
+    fn emit_rex(&mut self, w: bool, r: u8, b: u8) {
+        if w || Self::needs_rex(r) || Self::needs_rex(b) {
+            self.emit_byte(Self::rex(w, r, 0, b));
+        }
+    }
+
     /// Build ModR/M byte.
     fn modrm(md: u8, reg: u8, rm: u8) -> u8 {
         ((md & 0x3) << 6) | ((reg & 0x7) << 3) | (rm & 0x7)
@@ -171,225 +331,113 @@ impl X64Emitter {
 
     // ============================================================
     // x86_64 Instruction Encoding
+    //
+    // `w` selects the operand size: 64-bit (REX.W) or 32-bit. Writes to
+    // a 32-bit register zero-extend into the full 64-bit register, which
+    // is exactly the BPF ALU32 semantics.
     // ============================================================
 
     /// MOV reg, reg (64-bit)
     fn emit_mov_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 89 /r (MOV r/m64, r64)
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x89);
-        self.emit_byte(Self::modrm(0b11, src, dst));
-    }
-
-    /// MOV reg, imm64
-    fn emit_mov_imm64(&mut self, dst: u8, imm: i64) {
-        // REX.W + B8+rd io (MOV r64, imm64)
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xB8 + (dst & 0x7));
-        self.emit_bytes(&imm.to_le_bytes());
-    }
-
-    /// MOV reg, imm32 (sign-extended to 64-bit)
-    fn emit_mov_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + C7 /0 id (MOV r/m64, imm32)
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xC7);
-        self.emit_byte(Self::modrm(0b11, 0, dst));
-        self.emit_bytes(&imm.to_le_bytes());
-    }
-
-    /// XOR reg, reg (for zeroing)
-    fn emit_xor_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 31 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x31);
-        self.emit_byte(Self::modrm(0b11, src, dst));
+        self.emit_alu_reg(op::MOV, dst, src, true);
     }
 
-    /// ADD reg, reg
-    fn emit_add_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 01 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x01);
+    /// `<op> dst, src` for the two-operand ALU group
+    fn emit_alu_reg(&mut self, opcode: u8, dst: u8, src: u8, w: bool) {
+        // [REX] op /r
+        self.emit_rex(w, src, dst);
+        self.emit_byte(opcode);
         self.emit_byte(Self::modrm(0b11, src, dst));
     }
 
-    /// ADD reg, imm32
-    fn emit_add_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /0 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
+    /// `<op> dst, imm32` (immediate sign-extended in 64-bit mode)
+    fn emit_alu_imm32(&mut self, ext: u8, dst: u8, imm: i32, w: bool) {
+        // [REX] 81 /ext id
+        self.emit_rex(w, 0, dst);
         self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 0, dst));
+        self.emit_byte(Self::modrm(0b11, ext, dst));
         self.emit_bytes(&imm.to_le_bytes());
     }
 
-    /// SUB reg, reg
-    fn emit_sub_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 29 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x29);
-        self.emit_byte(Self::modrm(0b11, src, dst));
+    /// MOV reg, imm64
+    fn emit_mov_imm64(&mut self, dst: u8, imm: i64) {
+        // REX.W + B8+rd io (MOV r64, imm64)
+        self.emit_byte(Self::rex(true, 0, 0, dst));
+        self.emit_byte(0xB8 + (dst & 0x7));
+        self.emit_bytes(&imm.to_le_bytes());
     }
 
-    /// SUB reg, imm32
-    fn emit_sub_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /5 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 5, dst));
+    /// MOV reg, imm32 (sign-extended in 64-bit mode, zero-extended otherwise)
+    fn emit_mov_imm32(&mut self, dst: u8, imm: i32, w: bool) {
+        if w {
+            // REX.W + C7 /0 id (MOV r/m64, imm32)
+            self.emit_byte(Self::rex(true, 0, 0, dst));
+            self.emit_byte(0xC7);
+            self.emit_byte(Self::modrm(0b11, 0, dst));
+        } else {
+            // [REX] B8+rd id (MOV r32, imm32)
+            self.emit_rex(false, 0, dst);
+            self.emit_byte(0xB8 + (dst & 0x7));
+        }
         self.emit_bytes(&imm.to_le_bytes());
     }
 
-    /// IMUL reg, reg (signed multiply)
-    fn emit_imul_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 0F AF /r
-        self.emit_byte(Self::rex(true, dst, 0, src));
+    /// IMUL reg, reg (low half is the same for signed and unsigned)
+    fn emit_imul_reg(&mut self, dst: u8, src: u8, w: bool) {
+        // [REX] 0F AF /r
+        self.emit_rex(w, dst, src);
         self.emit_bytes(&[0x0F, 0xAF]);
         self.emit_byte(Self::modrm(0b11, dst, src));
     }
 
-    /// DIV reg (unsigned: RDX:RAX / reg -> RAX quotient, RDX remainder)
-    fn emit_div_reg(&mut self, src: u8) {
-        // REX.W + F7 /6
-        self.emit_byte(Self::rex(true, 0, 0, src));
+    /// Unary group 3 (NEG, DIV)
+    fn emit_unary(&mut self, ext: u8, reg: u8, w: bool) {
+        // [REX] F7 /ext
+        self.emit_rex(w, 0, reg);
         self.emit_byte(0xF7);
-        self.emit_byte(Self::modrm(0b11, 6, src));
-    }
-
-    /// IDIV reg (signed)
-    #[allow(dead_code)]
-    fn emit_idiv_reg(&mut self, src: u8) {
-        // REX.W + F7 /7
-        self.emit_byte(Self::rex(true, 0, 0, src));
-        self.emit_byte(0xF7);
-        self.emit_byte(Self::modrm(0b11, 7, src));
-    }
-
-    /// AND reg, reg
-    fn emit_and_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 21 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x21);
-        self.emit_byte(Self::modrm(0b11, src, dst));
-    }
-
-    /// AND reg, imm32
-    fn emit_and_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /4 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 4, dst));
-        self.emit_bytes(&imm.to_le_bytes());
-    }
-
-    /// OR reg, reg
-    fn emit_or_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 09 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x09);
-        self.emit_byte(Self::modrm(0b11, src, dst));
-    }
-
-    /// OR reg, imm32
-    fn emit_or_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /1 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 1, dst));
-        self.emit_bytes(&imm.to_le_bytes());
-    }
-
-    /// XOR reg, imm32
-    fn emit_xor_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /6 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 6, dst));
-        self.emit_bytes(&imm.to_le_bytes());
+        self.emit_byte(Self::modrm(0b11, ext, reg));
     }
 
-    /// SHL reg, CL (shift left by CL)
-    fn emit_shl_cl(&mut self, dst: u8) {
-        // REX.W + D3 /4
-        self.emit_byte(Self::rex(true, 0, 0, dst));
+    /// Shift group 2 by CL
+    fn emit_shift_cl(&mut self, ext: u8, dst: u8, w: bool) {
+        // [REX] D3 /ext
+        self.emit_rex(w, 0, dst);
         self.emit_byte(0xD3);
-        self.emit_byte(Self::modrm(0b11, 4, dst));
+        self.emit_byte(Self::modrm(0b11, ext, dst));
     }
 
-    /// SHL reg, imm8
-    fn emit_shl_imm(&mut self, dst: u8, imm: u8) {
-        // REX.W + C1 /4 ib
-        self.emit_byte(Self::rex(true, 0, 0, dst));
+    /// Shift group 2 by imm8
+    fn emit_shift_imm(&mut self, ext: u8, dst: u8, imm: u8, w: bool) {
+        // [REX] C1 /ext ib
+        self.emit_rex(w, 0, dst);
         self.emit_byte(0xC1);
-        self.emit_byte(Self::modrm(0b11, 4, dst));
-        self.emit_byte(imm & 0x3F);
+        self.emit_byte(Self::modrm(0b11, ext, dst));
+        self.emit_byte(imm & if w { 0x3F } else { 0x1F });
     }
 
-    /// SHR reg, CL (shift right logical)
-    fn emit_shr_cl(&mut self, dst: u8) {
-        // REX.W + D3 /5
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xD3);
-        self.emit_byte(Self::modrm(0b11, 5, dst));
+    /// BSWAP reg
+    fn emit_bswap(&mut self, reg: u8, w: bool) {
+        // [REX] 0F C8+rd
+        self.emit_rex(w, 0, reg);
+        self.emit_bytes(&[0x0F, 0xC8 + (reg & 0x7)]);
     }
 
-    /// SHR reg, imm8
-    fn emit_shr_imm(&mut self, dst: u8, imm: u8) {
-        // REX.W + C1 /5 ib
-        self.emit_byte(Self::rex(true, 0, 0, dst));
+    /// ROL reg16, 8 (swap the two low bytes)
+    fn emit_rol16_8(&mut self, reg: u8) {
+        // 66 [REX] C1 /0 08
+        self.emit_byte(0x66);
+        self.emit_rex(false, 0, reg);
         self.emit_byte(0xC1);
-        self.emit_byte(Self::modrm(0b11, 5, dst));
-        self.emit_byte(imm & 0x3F);
-    }
-
-    /// SAR reg, CL (shift right arithmetic)
-    fn emit_sar_cl(&mut self, dst: u8) {
-        // REX.W + D3 /7
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xD3);
-        self.emit_byte(Self::modrm(0b11, 7, dst));
+        self.emit_byte(Self::modrm(0b11, 0, reg));
+        self.emit_byte(8);
     }
 
-    /// SAR reg, imm8
-    fn emit_sar_imm(&mut self, dst: u8, imm: u8) {
-        // REX.W + C1 /7 ib
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xC1);
-        self.emit_byte(Self::modrm(0b11, 7, dst));
-        self.emit_byte(imm & 0x3F);
-    }
-
-    /// NEG reg (two's complement)
-    fn emit_neg(&mut self, dst: u8) {
-        // REX.W + F7 /3
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0xF7);
-        self.emit_byte(Self::modrm(0b11, 3, dst));
-    }
-
-    /// CMP reg, reg
-    fn emit_cmp_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 39 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x39);
-        self.emit_byte(Self::modrm(0b11, src, dst));
-    }
-
-    /// CMP reg, imm32
-    fn emit_cmp_imm32(&mut self, dst: u8, imm: i32) {
-        // REX.W + 81 /7 id
-        self.emit_byte(Self::rex(true, 0, 0, dst));
-        self.emit_byte(0x81);
-        self.emit_byte(Self::modrm(0b11, 7, dst));
-        self.emit_bytes(&imm.to_le_bytes());
-    }
-
-    /// TEST reg, reg
-    fn emit_test_reg(&mut self, dst: u8, src: u8) {
-        // REX.W + 85 /r
-        self.emit_byte(Self::rex(true, src, 0, dst));
-        self.emit_byte(0x85);
-        self.emit_byte(Self::modrm(0b11, src, dst));
+    /// MOVZX reg32, reg16
+    fn emit_movzx16(&mut self, dst: u8, src: u8) {
+        // [REX] 0F B7 /r
+        self.emit_rex(false, dst, src);
+        self.emit_bytes(&[0x0F, 0xB7]);
+        self.emit_byte(Self::modrm(0b11, dst, src));
     }
 
     /// JMP rel32 (unconditional)
@@ -407,13 +455,20 @@ impl X64Emitter {
     }
 
     /// CALL rel32
-    #[allow(dead_code)]
     fn emit_call_rel32(&mut self, offset: i32) {
         // E8 cd
         self.emit_byte(0xE8);
         self.emit_bytes(&offset.to_le_bytes());
     }
 
+    /// CALL reg (indirect)
+    fn emit_call_reg(&mut self, reg: u8) {
+        // [REX.B] FF /2
+        self.emit_rex(false, 0, reg);
+        self.emit_byte(0xFF);
+        self.emit_byte(Self::modrm(0b11, 2, reg));
+    }
+
     /// RET
     fn emit_ret(&mut self) {
         self.emit_byte(0xC3);
@@ -465,6 +520,15 @@ impl X64Emitter {
         self.emit_modrm_disp(src, base, disp);
     }
 
+    /// MOV qword [base + disp32], imm32 (sign-extended)
+    fn emit_store_imm32(&mut self, base: u8, disp: i32, imm: i32) {
+        // REX.W + C7 /0 id
+        self.emit_byte(Self::rex(true, 0, 0, base));
+        self.emit_byte(0xC7);
+        self.emit_modrm_disp(0, base, disp);
+        self.emit_bytes(&imm.to_le_bytes());
+    }
+
     /// MOV reg, [base + disp32]
     fn emit_load(&mut self, dst: u8, base: u8, disp: i32, size: MemSize) {
         match size {
@@ -501,7 +565,7 @@ impl X64Emitter {
 
         if base_enc == RSP {
             // Need SIB byte for RSP/R12 as base
-            if disp == 0 && base_enc != RBP {
+            if disp == 0 {
                 self.emit_byte(Self::modrm(0b00, reg_enc, 0b100));
                 self.emit_byte(0x24); // SIB: no index, RSP base
             } else if (-128..=127).contains(&disp) {
@@ -523,18 +587,6 @@ impl X64Emitter {
             self.emit_bytes(&disp.to_le_bytes());
         }
     }
-
-    /// CQO - sign extend RAX to RDX:RAX
-    #[allow(dead_code)]
-    fn emit_cqo(&mut self) {
-        // REX.W + 99
-        self.emit_bytes(&[0x48, 0x99]);
-    }
-
-    /// XOR RDX, RDX - zero RDX for unsigned division
-    fn emit_xor_rdx_rdx(&mut self) {
-        self.emit_xor_reg(RDX, RDX);
-    }
 }
 
 /// x86_64 condition codes.
@@ -557,25 +609,109 @@ mod cc {
 }
 
 /// JIT-compiled BPF program.
-#[allow(dead_code)]
 pub struct JitProgram {
-    /// Executable code (would be mmap'd with PROT_EXEC in real impl)
+    /// Machine code, not yet executable
     code: Vec<u8>,
     /// Entry point offset
     entry: usize,
 }
 
+/// JIT image installed in executable memory.
+///
+/// Compiling and installing a program is expensive, so callers that run the
+/// same program repeatedly (e.g. hooks) should install it once and keep the
+/// image around. The executable memory is released when the image is dropped.
+pub struct JitImage {
+    ptr: *mut u8,
+    size: usize,
+    entry: usize,
+}
+
+// SAFETY: The image is read-only after `install`, so sharing the entry
+// point between threads is sound.
+unsafe impl Send for JitImage {}
+unsafe impl Sync for JitImage {}
+
+impl JitImage {
+    /// Copy compiled code into executable memory and seal it (W^X).
+    pub fn install(jit_prog: &JitProgram) -> Result<Self, JitError> {
+        let size = jit_prog.code.len();
+
+        // SAFETY: Calling external kernel function to allocate writable memory
+        let ptr = unsafe { bpf_jit_alloc_exec(size) };
+        if ptr.is_null() {
+            return Err(JitError::AllocationFailed);
+        }
+
+        // SAFETY: ptr is valid for size bytes as returned by alloc, and
+        // still writable.
+        unsafe {
+            core::ptr::copy_nonoverlapping(jit_prog.code.as_ptr(), ptr, size);
+        }
+
+        // SAFETY: ptr/size describe the allocation above.
+        if !unsafe { bpf_jit_protect_exec(ptr, size) } {
+            // SAFETY: ptr/size came from bpf_jit_alloc_exec.
+            unsafe { bpf_jit_free_exec(ptr, size) };
+            return Err(JitError::AllocationFailed);
+        }
+
+        Ok(Self {
+            ptr,
+            size,
+            entry: jit_prog.entry,
+        })
+    }
+
+    /// Size of the installed code in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Run the installed program.
+    pub fn call(&self, ctx: &BpfContext) -> BpfResult {
+        let mut rt = JitRuntime {
+            ctx,
+            helper_id: 0,
+            status: STATUS_OK,
+            error: BpfError::InvalidInstruction,
+            frame: 0,
+            calls: 0,
+        };
+
+        // SAFETY: ptr + entry points to the prologue of code emitted by the
+        // compiler, which follows the System V ABI.
+        let func: unsafe extern "C" fn(*const BpfContext, *mut JitRuntime) -> u64 =
+            unsafe { core::mem::transmute(self.ptr.add(self.entry)) };
+
+        let result = unsafe { func(ctx, &mut rt) };
+
+        match rt.status {
+            STATUS_OK => Ok(result),
+            STATUS_DIV_ZERO => Err(BpfError::DivisionByZero),
+            STATUS_OUT_OF_BOUNDS => Err(BpfError::OutOfBounds),
+            STATUS_STACK_OVERFLOW => Err(BpfError::StackOverflow),
+            _ => Err(rt.error),
+        }
+    }
+}
+
+impl Drop for JitImage {
+    fn drop(&mut self) {
+        // SAFETY: ptr/size came from bpf_jit_alloc_exec in `install`.
+        unsafe { bpf_jit_free_exec(self.ptr, self.size) };
+    }
+}
+
 /// JIT compiler and executor.
 pub struct JitExecutor {
-    _private: PhantomData<()>,
+    _private: (),
 }
 
 impl JitExecutor {
     /// Create a new JIT executor.
     pub fn new() -> Self {
-        Self {
-            _private: PhantomData,
-        }
+        Self { _private: () }
     }
 
     /// Compile a BPF program to native code.
@@ -585,8 +721,17 @@ impl JitExecutor {
             return Err(JitError::CodegenFailed);
         }
 
-        let mut compiler = Arm64JitCompiler::new();
-        compiler.compile_program(insns)
+        let stack_size = program.stack_size().max(program.subprogs()[0].stack_depth);
+        if stack_size > MAX_JIT_STACK_SIZE {
+            return Err(JitError::StackTooLarge);
+        }
+        let mut compiler = X64JitCompiler::new(stack_size);
+        compiler.compile_program(insns, program.subprogs())
+    }
+
+    /// Compile a program and install it in executable memory.
+    pub fn load(&self, program: &BpfProgram<CloudProfile>) -> Result<JitImage, JitError> {
+        JitImage::install(&self.compile(program)?)
     }
 }
 
@@ -598,17 +743,8 @@ impl Default for JitExecutor {
 
 impl BpfExecutor<CloudProfile> for JitExecutor {
     fn execute(&self, program: &BpfProgram<CloudProfile>, ctx: &BpfContext) -> BpfResult {
-        // Try to compile, fall back to interpreter on failure
-        match self.compile(program) {
-            Ok(_jit_prog) => {
-                // In a real implementation, we would:
-                // 1. mmap the code with PROT_EXEC
-                // 2. Cast to function pointer
-                // 3. Call with context
-                // For safety reasons in this demo, fall back to interpreter
-                let interp = crate::execution::Interpreter::<CloudProfile>::new();
-                interp.execute(program, ctx)
-            }
+        match self.load(program) {
+            Ok(image) => image.call(ctx),
             Err(_) => {
                 // Fall back to interpreter
                 let interp = crate::execution::Interpreter::<CloudProfile>::new();
@@ -619,22 +755,36 @@ impl BpfExecutor<CloudProfile> for JitExecutor {
 }
 
 /// x86_64 JIT compiler.
-pub struct Arm64JitCompiler {
+pub struct X64JitCompiler {
     emitter: X64Emitter,
-    stack_size: usize,
+    /// Bytes reserved below RSP for the main frame (BPF stack + alignment)
+    frame_size: usize,
 }
 
-impl Arm64JitCompiler {
-    /// Create a new compiler.
-    pub fn new() -> Self {
+impl X64JitCompiler {
+    /// Create a new compiler for a program whose main function uses
+    /// `stack_size` bytes of stack.
+    ///
+    /// Programs that were not run through the verifier (e.g. built with
+    /// `ProgramBuilder`) report a stack size of zero, so callers should
+    /// cover the function's R10-relative accesses as well.
+    pub fn new(stack_size: usize) -> Self {
+        // Six pushes leave RSP 8 bytes off 16-byte alignment; the extra 8
+        // bytes restore it for helper calls.
+        let frame_size = stack_size.next_multiple_of(16) + 8;
         Self {
             emitter: X64Emitter::new(4096),
-            stack_size: 512, // Default BPF stack size
+            frame_size,
         }
     }
 
-    /// Compile a BPF program.
-    pub fn compile_program(&mut self, insns: &[BpfInsn]) -> Result<JitProgram, JitError> {
+    /// Compile a BPF program made of the functions `subprogs`, each
+    /// reserving its `stack_depth` bytes of stack.
+    pub fn compile_program(
+        &mut self,
+        insns: &[BpfInsn],
+        subprogs: &[Subprog],
+    ) -> Result<JitProgram, JitError> {
         // Reserve space for instruction offsets
         self.emitter.insn_offsets.reserve(insns.len());
 
@@ -642,31 +792,64 @@ impl Arm64JitCompiler {
         let entry = self.emitter.offset();
         self.emit_prologue();
 
-        // Compile each instruction
-        let mut i = 0;
-        while i < insns.len() {
-            self.emitter.mark_insn();
-            let insn = &insns[i];
+        for sub in subprogs {
+            if sub.start > 0 {
+                self.emitter
+                    .subprog_entries
+                    .push((sub.start, self.emitter.offset()));
+                self.emit_subprog_prologue(sub.stack_depth);
+            }
+
+            // Compile each instruction
+            let mut i = sub.start;
+            while i < sub.end {
+                self.emitter.mark_insn();
+                let insn = &insns[i];
 
-            // Handle wide instructions (64-bit immediate)
-            if insn.is_wide() {
-                if i + 1 >= insns.len() {
-                    return Err(JitError::CodegenFailed);
+                // Handle wide instructions (64-bit immediate)
+                if insn.is_wide() {
+                    if i + 1 >= sub.end {
+                        return Err(JitError::CodegenFailed);
+                    }
+                    // The second slot is not a valid jump target, but keep the
+                    // offsets indexed by BPF instruction.
+                    self.emitter.mark_insn();
+                    let next = &insns[i + 1];
+                    let imm64 = (insn.imm as u32 as u64) | ((next.imm as u32 as u64) << 32);
+                    let dst = BPF_TO_X64[insn.dst_reg() as usize];
+                    self.emitter.emit_mov_imm64(dst, imm64 as i64);
+                    i += 2;
+                    continue;
                 }
-                let next = &insns[i + 1];
-                let imm64 = (insn.imm as u32 as u64) | ((next.imm as u32 as u64) << 32);
-                let dst = BPF_TO_X64[insn.dst_reg() as usize];
-                self.emitter.emit_mov_imm64(dst, imm64 as i64);
-                i += 2;
-                continue;
+
+                self.compile_insn(insn, i, sub, insns.len())?;
+                i += 1;
             }
 
-            self.compile_insn(insn)?;
-            i += 1;
+            // Don't run into the next function's prologue
+            if sub.end < insns.len() {
+                self.emitter.emit_jmp_rel32(0);
+                self.emitter.record_jump(OUT_OF_PROGRAM);
+            }
         }
 
+        // Running off the end of the program (or jumping out of it)
+        let out_of_bounds = self.emitter.offset();
+        self.emit_set_status(STATUS_OUT_OF_BOUNDS);
+
+        // Abort path: status is already set, return 0 from the main frame
+        let abort = self.emitter.offset();
+        self.emitter.emit_load(
+            RBP,
+            RT_REG,
+            offset_of!(JitRuntime, frame) as i32,
+            MemSize::DWord,
+        );
+        self.emitter.emit_alu_reg(op::XOR, RAX, RAX, false);
+        self.emit_epilogue();
+
         // Patch jumps
-        self.patch_jumps();
+        self.patch_jumps(out_of_bounds, abort);
 
         Ok(JitProgram {
             code: core::mem::take(&mut self.emitter.code),
@@ -679,27 +862,66 @@ impl Arm64JitCompiler {
         // Save callee-saved registers
         self.emitter.emit_push(RBP);
         self.emitter.emit_push(RBX);
+        self.emitter.emit_push(R12);
         self.emitter.emit_push(R13);
         self.emitter.emit_push(R14);
         self.emitter.emit_push(R15);
 
-        // Setup BPF frame pointer (RBP points to BPF stack)
-        // SUB RSP, stack_size
-        self.emitter.emit_sub_imm32(RSP, self.stack_size as i32);
+        // Keep the runtime pointer out of the BPF registers
+        self.emitter.emit_mov_reg(RT_REG, RSI);
+        self.emitter.emit_alu_reg(op::XOR, RSI, RSI, false);
+        self.emitter.emit_alu_reg(op::XOR, RAX, RAX, false);
 
-        // MOV RBP, RSP (R10 = frame pointer)
+        // MOV RBP, RSP (R10 = frame pointer, top of the BPF stack)
         self.emitter.emit_mov_reg(RBP, RSP);
+        self.emitter.emit_store(
+            RT_REG,
+            offset_of!(JitRuntime, frame) as i32,
+            RBP,
+            MemSize::DWord,
+        );
+
+        // SUB RSP, frame_size
+        self.emitter
+            .emit_alu_imm32(ext::SUB, RSP, self.frame_size as i32, true);
+    }
+
+    /// Emit the prologue of a BPF function using `stack_size` bytes of stack.
+    fn emit_subprog_prologue(&mut self, stack_size: usize) {
+        // The caller's R6-R9 and R10. With the return address that's six
+        // slots, so RSP stays 16-byte aligned.
+        self.emitter.emit_push(RBP);
+        self.emitter.emit_push(RBX);
+        self.emitter.emit_push(R13);
+        self.emitter.emit_push(R14);
+        self.emitter.emit_push(R15);
+
+        self.emitter.emit_mov_reg(RBP, RSP);
+        self.emitter
+            .emit_alu_imm32(ext::SUB, RSP, stack_size.next_multiple_of(16) as i32, true);
+    }
+
+    /// Emit the epilogue of a BPF function.
+    fn emit_subprog_epilogue(&mut self) {
+        self.emitter.emit_mov_reg(RSP, RBP);
+        self.emitter.emit_pop(R15);
+        self.emitter.emit_pop(R14);
+        self.emitter.emit_pop(R13);
+        self.emitter.emit_pop(RBX);
+        self.emitter.emit_pop(RBP);
+        self.emitter.emit_ret();
     }
 
     /// Emit function epilogue.
     fn emit_epilogue(&mut self) {
         // Restore stack
-        self.emitter.emit_add_imm32(RSP, self.stack_size as i32);
+        self.emitter.emit_mov_reg(RSP, RBP);
 
         // Restore callee-saved registers
         self.emitter.emit_pop(R15);
         self.emitter.emit_pop(R14);
         self.emitter.emit_pop(R13);
+        self.emitter.emit_pop(R12);
         self.emitter.emit_pop(RBX);
         self.emitter.emit_pop(RBP);
 
@@ -707,11 +929,23 @@ impl Arm64JitCompiler {
         self.emitter.emit_ret();
     }
 
-    /// Compile a single BPF instruction.
-    fn compile_insn(&mut self, insn: &BpfInsn) -> Result<(), JitError> {
+    /// Store a `STATUS_*` code in the runtime.
+    fn emit_set_status(&mut self, status: u64) {
+        self.emitter
+            .emit_store_imm32(RT_REG, offset_of!(JitRuntime, status) as i32, status as i32);
+    }
+
+    /// Compile a single BPF instruction of function `sub`.
+    fn compile_insn(
+        &mut self,
+        insn: &BpfInsn,
+        idx: usize,
+        sub: &Subprog,
+        len: usize,
+    ) -> Result<(), JitError> {
         // Exit instruction
         if insn.is_exit() {
-            self.emit_epilogue();
+            self.emit_exit(sub);
             return Ok(());
         }
 
@@ -719,11 +953,17 @@ impl Arm64JitCompiler {
             return Err(JitError::UnsupportedInstruction);
         };
 
+        if insn.dst_reg() as usize >= BPF_TO_X64.len()
+            || insn.src_reg() as usize >= BPF_TO_X64.len()
+        {
+            return Err(JitError::UnsupportedInstruction);
+        }
+
         match class {
             OpcodeClass::Alu64 => self.compile_alu(insn, true)?,
             OpcodeClass::Alu32 => self.compile_alu(insn, false)?,
-            OpcodeClass::Jmp => self.compile_jmp(insn, true)?,
-            OpcodeClass::Jmp32 => self.compile_jmp(insn, false)?,
+            OpcodeClass::Jmp => self.compile_jmp(insn, idx, sub, len, true)?,
+            OpcodeClass::Jmp32 => self.compile_jmp(insn, idx, sub, len, false)?,
             OpcodeClass::Ldx => self.compile_load(insn)?,
             OpcodeClass::Stx | OpcodeClass::St => self.compile_store(insn)?,
             OpcodeClass::Ld => {
@@ -737,208 +977,215 @@ impl Arm64JitCompiler {
 
     /// Compile ALU instruction.
     fn compile_alu(&mut self, insn: &BpfInsn, is_64bit: bool) -> Result<(), JitError> {
+        let w = is_64bit;
         let dst = BPF_TO_X64[insn.dst_reg() as usize];
-        let is_reg = matches!(SourceType::from_opcode(insn.opcode), SourceType::Reg);
+        let src = if matches!(SourceType::from_opcode(insn.opcode), SourceType::Reg) {
+            Some(BPF_TO_X64[insn.src_reg() as usize])
+        } else {
+            None
+        };
 
-        let Some(op) = AluOp::from_opcode(insn.opcode) else {
+        let Some(alu_op) = AluOp::from_opcode(insn.opcode) else {
             return Err(JitError::UnsupportedInstruction);
         };
 
-        match op {
-            AluOp::Add => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_add_reg(dst, src);
-                } else {
-                    self.emitter.emit_add_imm32(dst, insn.imm);
-                }
-            }
-            AluOp::Sub => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_sub_reg(dst, src);
-                } else {
-                    self.emitter.emit_sub_imm32(dst, insn.imm);
-                }
-            }
+        match alu_op {
+            AluOp::Add => self.emit_binary(op::ADD, ext::ADD, dst, src, insn.imm, w),
+            AluOp::Sub => self.emit_binary(op::SUB, ext::SUB, dst, src, insn.imm, w),
+            AluOp::Or => self.emit_binary(op::OR, ext::OR, dst, src, insn.imm, w),
+            AluOp::And => self.emit_binary(op::AND, ext::AND, dst, src, insn.imm, w),
+            AluOp::Xor => self.emit_binary(op::XOR, ext::XOR, dst, src, insn.imm, w),
+            AluOp::Mov => match src {
+                Some(src) => self.emitter.emit_alu_reg(op::MOV, dst, src, w),
+                None => self.emitter.emit_mov_imm32(dst, insn.imm, w),
+            },
             AluOp::Mul => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_imul_reg(dst, src);
-                } else {
-                    // IMUL with immediate requires different encoding
-                    // Load imm to TMP, then multiply
-                    self.emitter.emit_mov_imm32(TMP_REG, insn.imm);
-                    self.emitter.emit_imul_reg(dst, TMP_REG);
-                }
-            }
-            AluOp::Div => {
-                // Division requires RAX:RDX setup
-                // Save RAX if dst != RAX
-                if dst != RAX {
-                    self.emitter.emit_mov_reg(TMP_REG, RAX);
-                    self.emitter.emit_mov_reg(RAX, dst);
-                }
-                self.emitter.emit_xor_rdx_rdx(); // Zero RDX for unsigned div
-
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_div_reg(src);
-                } else {
-                    self.emitter.emit_mov_imm32(R9, insn.imm);
-                    self.emitter.emit_div_reg(R9);
-                }
-
-                if dst != RAX {
-                    self.emitter.emit_mov_reg(dst, RAX);
-                    self.emitter.emit_mov_reg(RAX, TMP_REG);
-                }
-            }
-            AluOp::Mod => {
-                // Similar to div, but result is in RDX
-                if dst != RAX {
-                    self.emitter.emit_mov_reg(TMP_REG, RAX);
-                    self.emitter.emit_mov_reg(RAX, dst);
-                }
-                self.emitter.emit_xor_rdx_rdx();
-
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_div_reg(src);
-                } else {
-                    self.emitter.emit_mov_imm32(R9, insn.imm);
-                    self.emitter.emit_div_reg(R9);
-                }
-
-                // Remainder is in RDX
-                self.emitter.emit_mov_reg(dst, RDX);
-                if dst != RAX {
-                    self.emitter.emit_mov_reg(RAX, TMP_REG);
-                }
-            }
-            AluOp::Or => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_or_reg(dst, src);
-                } else {
-                    self.emitter.emit_or_imm32(dst, insn.imm);
-                }
-            }
-            AluOp::And => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_and_reg(dst, src);
-                } else {
-                    self.emitter.emit_and_imm32(dst, insn.imm);
-                }
-            }
-            AluOp::Xor => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_xor_reg(dst, src);
-                } else {
-                    self.emitter.emit_xor_imm32(dst, insn.imm);
-                }
-            }
-            AluOp::Lsh => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    // Shift amount must be in CL
-                    if src != RCX {
-                        self.emitter.emit_mov_reg(RCX, src);
+                let src = match src {
+                    Some(src) => src,
+                    None => {
+                        self.emitter.emit_mov_imm32(TMP_REG, insn.imm, w);
+                        TMP_REG
                     }
-                    self.emitter.emit_shl_cl(dst);
-                } else {
-                    self.emitter.emit_shl_imm(dst, insn.imm as u8);
-                }
+                };
+                self.emitter.emit_imul_reg(dst, src, w);
             }
-            AluOp::Rsh => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    if src != RCX {
-                        self.emitter.emit_mov_reg(RCX, src);
-                    }
-                    self.emitter.emit_shr_cl(dst);
-                } else {
-                    self.emitter.emit_shr_imm(dst, insn.imm as u8);
+            AluOp::Div => self.emit_div_mod(dst, src, insn.imm, w, false),
+            AluOp::Mod => self.emit_div_mod(dst, src, insn.imm, w, true),
+            AluOp::Lsh => self.emit_shift(ext::SHL, dst, src, insn.imm, w),
+            AluOp::Rsh => self.emit_shift(ext::SHR, dst, src, insn.imm, w),
+            AluOp::Arsh => self.emit_shift(ext::SAR, dst, src, insn.imm, w),
+            AluOp::Neg => self.emitter.emit_unary(ext::NEG, dst, w),
+            AluOp::End => match insn.imm {
+                16 => {
+                    self.emitter.emit_rol16_8(dst);
+                    self.emitter.emit_movzx16(dst, dst);
                 }
-            }
-            AluOp::Arsh => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    if src != RCX {
-                        self.emitter.emit_mov_reg(RCX, src);
+                32 => self.emitter.emit_bswap(dst, false),
+                64 => {
+                    self.emitter.emit_bswap(dst, true);
+                    if !w {
+                        self.emitter.emit_alu_reg(op::MOV, dst, dst, false);
                     }
-                    self.emitter.emit_sar_cl(dst);
-                } else {
-                    self.emitter.emit_sar_imm(dst, insn.imm as u8);
-                }
-            }
-            AluOp::Neg => {
-                self.emitter.emit_neg(dst);
-            }
-            AluOp::Mov => {
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_mov_reg(dst, src);
-                } else if insn.imm == 0 {
-                    self.emitter.emit_xor_reg(dst, dst);
-                } else {
-                    self.emitter.emit_mov_imm32(dst, insn.imm);
                 }
+                _ => return Err(JitError::UnsupportedInstruction),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Emit a two-operand ALU operation with a register or immediate source.
+    fn emit_binary(&mut self, opcode: u8, ext: u8, dst: u8, src: Option<u8>, imm: i32, w: bool) {
+        match src {
+            Some(src) => self.emitter.emit_alu_reg(opcode, dst, src, w),
+            None => self.emitter.emit_alu_imm32(ext, dst, imm, w),
+        }
+    }
+
+    /// Emit a shift. Variable shift counts must be in CL, which is BPF R4.
+    fn emit_shift(&mut self, ext: u8, dst: u8, src: Option<u8>, imm: i32, w: bool) {
+        match src {
+            None => self.emitter.emit_shift_imm(ext, dst, imm as u8, w),
+            Some(RCX) => self.emitter.emit_shift_cl(ext, dst, w),
+            Some(src) if dst == RCX => {
+                self.emitter.emit_mov_reg(TMP_REG, RCX);
+                self.emitter.emit_mov_reg(RCX, src);
+                self.emitter.emit_shift_cl(ext, TMP_REG, w);
+                self.emitter.emit_mov_reg(RCX, TMP_REG);
             }
-            AluOp::End => {
-                // Byte swap - not commonly used, skip for now
-                return Err(JitError::UnsupportedInstruction);
+            Some(src) => {
+                self.emitter.emit_mov_reg(TMP_REG, RCX);
+                self.emitter.emit_mov_reg(RCX, src);
+                self.emitter.emit_shift_cl(ext, dst, w);
+                self.emitter.emit_mov_reg(RCX, TMP_REG);
             }
         }
 
-        // For 32-bit ALU, zero-extend result
-        if !is_64bit {
-            // MOV eax, eax zero-extends to 64-bit
-            // This is implicit in x86_64 for 32-bit operations
+        // A zero shift count leaves the destination untouched, so make the
+        // 32-bit zero-extension explicit.
+        if !w {
+            self.emitter.emit_alu_reg(op::MOV, dst, dst, false);
         }
+    }
 
-        Ok(())
+    /// Emit unsigned division or modulo.
+    ///
+    /// DIV uses RDX:RAX (BPF R3 and R0), so both are saved in scratch
+    /// registers around it. A zero divisor aborts the program.
+    fn emit_div_mod(&mut self, dst: u8, src: Option<u8>, imm: i32, w: bool, is_mod: bool) {
+        // Divisor -> R11
+        match src {
+            Some(src) => self.emitter.emit_mov_reg(TMP_REG, src),
+            None => self.emitter.emit_mov_imm32(TMP_REG, imm, w),
+        }
+
+        // if divisor == 0: abort with STATUS_DIV_ZERO
+        self.emitter.emit_alu_reg(op::TEST, TMP_REG, TMP_REG, w);
+        self.emitter.emit_jcc_rel32(cc::JNE, 0);
+        let nonzero_patch = self.emitter.offset() - 4;
+        self.emit_set_status(STATUS_DIV_ZERO);
+        self.emitter.emit_jmp_rel32(0);
+        self.emitter.record_abort();
+        let nonzero = self.emitter.offset();
+        self.emitter.patch_rel32(nonzero_patch, nonzero);
+
+        self.emitter.emit_mov_reg(R10, RAX);
+        self.emitter.emit_mov_reg(R9, RDX);
+        self.emitter.emit_mov_reg(RAX, dst);
+        self.emitter.emit_alu_reg(op::XOR, RDX, RDX, false);
+        self.emitter.emit_unary(ext::DIV, TMP_REG, w);
+        self.emitter
+            .emit_mov_reg(TMP_REG, if is_mod { RDX } else { RAX });
+        self.emitter.emit_mov_reg(RAX, R10);
+        self.emitter.emit_mov_reg(RDX, R9);
+        self.emitter.emit_mov_reg(dst, TMP_REG);
+    }
+
+    /// Return from function `sub`.
+    fn emit_exit(&mut self, sub: &Subprog) {
+        if sub.start == 0 {
+            self.emit_epilogue();
+        } else {
+            self.emit_subprog_epilogue();
+        }
     }
 
-    /// Compile jump instruction.
-    fn compile_jmp(&mut self, insn: &BpfInsn, _is_64bit: bool) -> Result<(), JitError> {
-        let Some(op) = JmpOp::from_opcode(insn.opcode) else {
+    /// Compile jump instruction of function `sub`.
+    fn compile_jmp(
+        &mut self,
+        insn: &BpfInsn,
+        idx: usize,
+        sub: &Subprog,
+        len: usize,
+        is_64bit: bool,
+    ) -> Result<(), JitError> {
+        let w = is_64bit;
+        let Some(jmp_op) = JmpOp::from_opcode(insn.opcode) else {
             return Err(JitError::UnsupportedInstruction);
         };
 
-        match op {
+        // Jumps can't leave the function
+        let target = idx as isize + 1 + insn.offset as isize;
+        let target = if target >= 0 && sub.contains(target as usize) {
+            target as usize
+        } else {
+            OUT_OF_PROGRAM
+        };
+
+        match jmp_op {
             JmpOp::Ja => {
                 // Unconditional jump
                 self.emitter.emit_jmp_rel32(0); // Placeholder
-                let target = (self.emitter.insn_offsets.len() as i32) + insn.offset as i32;
-                self.emitter.record_jump(target as usize);
+                self.emitter.record_jump(target);
+            }
+            JmpOp::Call if insn.is_pseudo_call() => {
+                // The main function can't be called
+                let callee = subprog::call_target(idx, insn).filter(|&t| t > 0 && t < len);
+                let Some(callee) = callee else {
+                    return Err(JitError::UnsupportedInstruction);
+                };
+                self.emit_local_call(callee);
             }
             JmpOp::Call => {
-                // Helper call - for now, emit a call to a stub
-                // In real impl, would resolve helper and call it
-                // For safety, just return 0
-                self.emitter.emit_xor_reg(RAX, RAX);
+                // Only helper calls are supported. Tail calls would have to
+                // replace the frame and bpf_loop calls back into the
+                // program, so those programs are interpreted.
+                if insn.src_reg() != 0
+                    || insn.imm == HelperId::TailCall as i32
+                    || insn.imm == HelperId::Loop as i32
+                {
+                    return Err(JitError::UnsupportedInstruction);
+                }
+                self.emit_helper_call(insn.imm);
             }
             JmpOp::Exit => {
-                self.emit_epilogue();
+                self.emit_exit(sub);
             }
             _ => {
                 // Conditional jump
                 let dst = BPF_TO_X64[insn.dst_reg() as usize];
                 let is_reg = matches!(SourceType::from_opcode(insn.opcode), SourceType::Reg);
 
-                if is_reg {
-                    let src = BPF_TO_X64[insn.src_reg() as usize];
-                    self.emitter.emit_cmp_reg(dst, src);
-                } else {
-                    self.emitter.emit_cmp_imm32(dst, insn.imm);
+                match (jmp_op, is_reg) {
+                    (JmpOp::Jset, true) => {
+                        // JSET: jump if (dst & src) != 0
+                        let src = BPF_TO_X64[insn.src_reg() as usize];
+                        self.emitter.emit_alu_reg(op::TEST, dst, src, w);
+                    }
+                    (JmpOp::Jset, false) => {
+                        self.emitter.emit_mov_imm32(TMP_REG, insn.imm, w);
+                        self.emitter.emit_alu_reg(op::TEST, dst, TMP_REG, w);
+                    }
+                    (_, true) => {
+                        let src = BPF_TO_X64[insn.src_reg() as usize];
+                        self.emitter.emit_alu_reg(op::CMP, dst, src, w);
+                    }
+                    (_, false) => self.emitter.emit_alu_imm32(ext::CMP, dst, insn.imm, w),
                 }
 
-                let cc = match op {
+                let cc = match jmp_op {
                     JmpOp::Jeq => cc::JE,
-                    JmpOp::Jne => cc::JNE,
+                    JmpOp::Jne | JmpOp::Jset => cc::JNE,
                     JmpOp::Jgt => cc::JA,
                     JmpOp::Jge => cc::JAE,
                     JmpOp::Jlt => cc::JB,
@@ -947,31 +1194,76 @@ impl Arm64JitCompiler {
                     JmpOp::Jsge => cc::JGE,
                     JmpOp::Jslt => cc::JL,
                     JmpOp::Jsle => cc::JLE,
-                    JmpOp::Jset => {
-                        // JSET: jump if (dst & src) != 0
-                        if is_reg {
-                            let src = BPF_TO_X64[insn.src_reg() as usize];
-                            self.emitter.emit_test_reg(dst, src);
-                        } else {
-                            self.emitter.emit_mov_imm32(TMP_REG, insn.imm);
-                            self.emitter.emit_test_reg(dst, TMP_REG);
-                        }
-                        cc::JNE
-                    }
                     _ => return Err(JitError::UnsupportedInstruction),
                 };
 
                 self.emitter.emit_jcc_rel32(cc, 0); // Placeholder
-                let target = (self.emitter.insn_offsets.len() as i32) + insn.offset as i32;
-                self.emitter.record_jump(target as usize);
+                self.emitter.record_jump(target);
             }
         }
 
         Ok(())
     }
 
+    /// Call a helper through `jit_call_helper`, aborting if it fails.
+    fn emit_helper_call(&mut self, helper_id: i32) {
+        self.emitter
+            .emit_store_imm32(RT_REG, offset_of!(JitRuntime, helper_id) as i32, helper_id);
+        self.emitter.emit_mov_reg(R9, RT_REG);
+        self.emitter
+            .emit_mov_imm64(RAX, jit_call_helper as *const () as i64);
+        self.emitter.emit_call_reg(RAX);
+
+        // if status != 0: abort
+        self.emitter.emit_load(
+            TMP_REG,
+            RT_REG,
+            offset_of!(JitRuntime, status) as i32,
+            MemSize::DWord,
+        );
+        self.emitter.emit_alu_reg(op::TEST, TMP_REG, TMP_REG, true);
+        self.emitter.emit_jcc_rel32(cc::JNE, 0);
+        self.emitter.record_abort();
+    }
+
+    /// Call the BPF function starting at `target`, aborting if the chain
+    /// gets too long.
+    fn emit_local_call(&mut self, target: usize) {
+        let calls = offset_of!(JitRuntime, calls) as i32;
+
+        // if ++calls >= MAX_CALL_FRAMES: abort with STATUS_STACK_OVERFLOW
+        self.emitter
+            .emit_load(TMP_REG, RT_REG, calls, MemSize::DWord);
+        self.emitter.emit_alu_imm32(ext::ADD, TMP_REG, 1, true);
+        self.emitter
+            .emit_alu_imm32(ext::CMP, TMP_REG, MAX_CALL_FRAMES as i32, true);
+        self.emitter.emit_jcc_rel32(cc::JB, 0);
+        let ok_patch = self.emitter.offset() - 4;
+        self.emit_set_status(STATUS_STACK_OVERFLOW);
+        self.emitter.emit_jmp_rel32(0);
+        self.emitter.record_abort();
+        let ok = self.emitter.offset();
+        self.emitter.patch_rel32(ok_patch, ok);
+        self.emitter
+            .emit_store(RT_REG, calls, TMP_REG, MemSize::DWord);
+
+        self.emitter.emit_call_rel32(0);
+        self.emitter.record_call(target);
+
+        // --calls
+        self.emitter
+            .emit_load(TMP_REG, RT_REG, calls, MemSize::DWord);
+        self.emitter.emit_alu_imm32(ext::SUB, TMP_REG, 1, true);
+        self.emitter
+            .emit_store(RT_REG, calls, TMP_REG, MemSize::DWord);
+    }
+
     /// Compile load instruction.
     fn compile_load(&mut self, insn: &BpfInsn) -> Result<(), JitError> {
+        if MemMode::from_opcode(insn.opcode) != Some(MemMode::Mem) {
+            return Err(JitError::UnsupportedInstruction);
+        }
+
         let dst = BPF_TO_X64[insn.dst_reg() as usize];
         let src = BPF_TO_X64[insn.src_reg() as usize];
         let Some(size) = MemSize::from_opcode(insn.opcode) else {
@@ -984,6 +1276,10 @@ impl Arm64JitCompiler {
 
     /// Compile store instruction.
     fn compile_store(&mut self, insn: &BpfInsn) -> Result<(), JitError> {
+        if MemMode::from_opcode(insn.opcode) != Some(MemMode::Mem) {
+            return Err(JitError::UnsupportedInstruction);
+        }
+
         let dst = BPF_TO_X64[insn.dst_reg() as usize];
         let Some(size) = MemSize::from_opcode(insn.opcode) else {
             return Err(JitError::UnsupportedInstruction);
@@ -995,7 +1291,7 @@ impl Arm64JitCompiler {
 
         if matches!(class, OpcodeClass::St) {
             // Store immediate
-            self.emitter.emit_mov_imm32(TMP_REG, insn.imm);
+            self.emitter.emit_mov_imm32(TMP_REG, insn.imm, true);
             self.emitter
                 .emit_store(dst, insn.offset as i32, TMP_REG, size);
         } else {
@@ -1008,28 +1304,33 @@ impl Arm64JitCompiler {
     }
 
     /// Patch jump targets.
-    fn patch_jumps(&mut self) {
-        for (patch_offset, target_insn) in &self.emitter.jump_patches {
-            let target_offset = if *target_insn < self.emitter.insn_offsets.len() {
-                self.emitter.insn_offsets[*target_insn]
+    fn patch_jumps(&mut self, out_of_bounds: usize, abort: usize) {
+        let jump_patches = core::mem::take(&mut self.emitter.jump_patches);
+        for (patch_offset, target_insn) in jump_patches {
+            let target_offset = if target_insn == OUT_OF_PROGRAM {
+                out_of_bounds
             } else {
-                // Jump past end - point to epilogue
-                self.emitter.code.len()
+                self.emitter.insn_offsets[target_insn]
             };
+            self.emitter.patch_rel32(patch_offset, target_offset);
+        }
 
-            // Calculate relative offset (from instruction after the jump)
-            let rel_offset = (target_offset as i32) - (*patch_offset as i32) - 4;
-
-            // Patch the offset
-            let bytes = rel_offset.to_le_bytes();
-            self.emitter.code[*patch_offset..*patch_offset + 4].copy_from_slice(&bytes);
+        let abort_patches = core::mem::take(&mut self.emitter.abort_patches);
+        for patch_offset in abort_patches {
+            self.emitter.patch_rel32(patch_offset, abort);
         }
-    }
-}
 
-impl Default for Arm64JitCompiler {
-    fn default() -> Self {
-        Self::new()
+        // Call targets always start a function
+        let call_patches = core::mem::take(&mut self.emitter.call_patches);
+        for (patch_offset, target_insn) in call_patches {
+            let entry = self
+                .emitter
+                .subprog_entries
+                .binary_search_by_key(&target_insn, |&(start, _)| start)
+                .map(|idx| self.emitter.subprog_entries[idx].1)
+                .unwrap_or(abort);
+            self.emitter.patch_rel32(patch_offset, entry);
+        }
     }
 }
 
@@ -1044,6 +1345,8 @@ pub enum JitError {
     CodegenFailed,
     /// Unsupported instruction
     UnsupportedInstruction,
+    /// The program needs more stack than a JIT frame may take
+    StackTooLarge,
 }
 
 impl core::fmt::Display for JitError {
@@ -1053,6 +1356,7 @@ impl core::fmt::Display for JitError {
             Self::AllocationFailed => write!(f, "failed to allocate executable memory"),
             Self::CodegenFailed => write!(f, "code generation failed"),
             Self::UnsupportedInstruction => write!(f, "unsupported instruction"),
+            Self::StackTooLarge => write!(f, "stack too large for a JIT frame"),
         }
     }
 }
@@ -1061,6 +1365,13 @@ impl core::fmt::Display for JitError {
 mod tests {
     use super::*;
 
+    // Dummy implementation to satisfy linker. Allocation and freeing are
+    // stubbed out by the ARM64 JIT tests.
+    #[unsafe(no_mangle)]
+    unsafe extern "C" fn bpf_jit_protect_exec(_ptr: *mut u8, _size: usize) -> bool {
+        false
+    }
+
     #[test]
     fn jit_compile_simple() {
         use crate::bytecode::insn::BpfInsn;
@@ -1118,6 +1429,160 @@ mod tests {
         assert_eq!(result, Ok(42));
     }
 
+    #[test]
+    fn jit_leaves_large_stacks_to_interpreter() {
+        use crate::bytecode::insn::BpfInsn;
+        use crate::bytecode::program::{BpfProgType, ProgramBuilder};
+
+        let offset = -(MAX_JIT_STACK_SIZE as i16) - 8;
+        let program = ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::new(0x7a, 10, 0, offset, 7)) // *(u64 *)(r10 - 8K - 8) = 7
+            .insn(BpfInsn::new(0x79, 0, 10, offset, 0)) // r0 = *(u64 *)(r10 - 8K - 8)
+            .insn(BpfInsn::exit())
+            .build()
+            .expect("valid program");
+
+        let jit = JitExecutor::new();
+        assert_eq!(jit.compile(&program).err(), Some(JitError::StackTooLarge));
+        assert_eq!(jit.execute(&program, &BpfContext::empty()), Ok(7));
+    }
+
+    #[test]
+    fn jit_rejects_tail_calls() {
+        use crate::bytecode::insn::BpfInsn;
+        use crate::bytecode::program::{BpfProgType, ProgramBuilder};
+
+        let program = ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::call(HelperId::TailCall as i32))
+            .insn(BpfInsn::mov64_imm(0, 1))
+            .insn(BpfInsn::exit())
+            .build()
+            .expect("valid program");
+
+        let jit = JitExecutor::new();
+        assert_eq!(
+            jit.compile(&program).err(),
+            Some(JitError::UnsupportedInstruction)
+        );
+
+        // The interpreter runs it, with the tail call failing
+        let ctx = BpfContext::empty();
+        assert_eq!(jit.execute(&program, &ctx), Ok(1));
+    }
+
+    #[test]
+    fn jit_leaves_bpf_loop_to_interpreter() {
+        use crate::bytecode::insn::{BpfInsn, WideInsn};
+        use crate::bytecode::program::{BpfProgType, ProgramBuilder};
+
+        let func = WideInsn::ld_func(2, 5);
+        let program = ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(1, 3)) // 0: r1 = 3
+            .insn(func.insn) // 1: r2 = &func 7
+            .insn(func.next) // 2
+            .insn(BpfInsn::mov64_imm(3, 0)) // 3
+            .insn(BpfInsn::mov64_imm(4, 0)) // 4
+            .insn(BpfInsn::call(HelperId::Loop as i32)) // 5
+            .insn(BpfInsn::exit()) // 6
+            .insn(BpfInsn::mov64_imm(0, 0)) // 7
+            .insn(BpfInsn::exit()) // 8
+            .build()
+            .expect("valid program");
+
+        let jit = JitExecutor::new();
+        assert_eq!(
+            jit.compile(&program).err(),
+            Some(JitError::UnsupportedInstruction)
+        );
+
+        // The interpreter runs all three iterations
+        let ctx = BpfContext::empty();
+        assert_eq!(jit.execute(&program, &ctx), Ok(3));
+    }
+
+    #[test]
+    fn jit_frame_covers_stack_accesses() {
+        let insns = [
+            BpfInsn::new(0x7b, 10, 1, -24, 0), // *(u64 *)(r10 - 24) = r1
+            BpfInsn::new(0x79, 0, 10, -8, 0),  // r0 = *(u64 *)(r10 - 8)
+            BpfInsn::exit(),
+        ];
+        assert_eq!(subprog::stack_depth(&insns), 24);
+
+        // 24 bytes round up to 32, plus 8 for call alignment
+        let compiler = X64JitCompiler::new(subprog::stack_depth(&insns));
+        assert_eq!(compiler.frame_size, 40);
+    }
+
+    #[test]
+    fn jit_compiles_function_calls() {
+        let insns = [
+            BpfInsn::mov64_imm(6, 1),         // 0: r6 = 1
+            BpfInsn::call_local(2),           // 1: call 4
+            BpfInsn::add64_reg(0, 6),         // 2: r0 += r6
+            BpfInsn::exit(),                  // 3
+            BpfInsn::new(0x7a, 10, 0, -8, 2), // 4: *(u64 *)(r10 - 8) = 2
+            BpfInsn::new(0x79, 0, 10, -8, 0), // 5: r0 = *(u64 *)(r10 - 8)
+            BpfInsn::exit(),                  // 6
+        ];
+
+        let mut compiler = X64JitCompiler::new(0);
+        let program = compiler
+            .compile_program(&insns, &subprog::split(&insns))
+            .expect("compiles");
+        let entries = &compiler.emitter.subprog_entries;
+        assert_eq!(entries.len(), 1);
+        let (start, entry) = entries[0];
+        assert_eq!(start, 4);
+
+        // The function starts with its prologue: PUSH RBP; PUSH RBX
+        assert_eq!(&program.code[entry..entry + 2], &[0x55, 0x53]);
+
+        // Some CALL rel32 lands on it
+        let calls_entry = (0..program.code.len() - 5).any(|at| {
+            let rel = i32::from_le_bytes(program.code[at + 1..at + 5].try_into().unwrap());
+            program.code[at] == 0xE8 && (at as i64 + 5 + rel as i64) as usize == entry
+        });
+        assert!(calls_entry);
+
+        // Calls to nowhere or to the main function are rejected
+        for offset in [5, -1] {
+            let insns = [BpfInsn::call_local(offset), BpfInsn::exit()];
+            let mut compiler = X64JitCompiler::new(0);
+            assert_eq!(
+                compiler
+                    .compile_program(&insns, &subprog::split(&insns))
+                    .err(),
+                Some(JitError::UnsupportedInstruction)
+            );
+        }
+    }
+
+    #[test]
+    fn jit_reserves_verified_frames() {
+        use crate::bytecode::program::{BpfProgType, ProgramBuilder};
+
+        let mut builder = ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter);
+        for insn in [
+            BpfInsn::call_local(1),   // 0: call 2
+            BpfInsn::exit(),          // 1
+            BpfInsn::mov64_imm(0, 0), // 2
+            BpfInsn::exit(),          // 3
+        ] {
+            builder = builder.insn(insn);
+        }
+        // The verifier found the function using 64 bytes through a copy of R10
+        let program = builder
+            .build()
+            .expect("valid program")
+            .with_stack_depths(&[0, 64]);
+
+        // SUB RSP, 64 in the function's prologue
+        let jit = JitExecutor::new().compile(&program).expect("compiles");
+        let sub_rsp = [0x48, 0x81, 0xEC, 64, 0, 0, 0];
+        assert!(jit.code.windows(sub_rsp.len()).any(|code| code == sub_rsp));
+    }
+
     #[test]
     fn emitter_mov_reg() {
         let mut emitter = X64Emitter::new(64);
@@ -1134,9 +1599,35 @@ mod tests {
         let mut emitter = X64Emitter::new(64);
 
         // ADD RAX, RCX
-        emitter.emit_add_reg(RAX, RCX);
+        emitter.emit_alu_reg(op::ADD, RAX, RCX, true);
 
         // Should be: REX.W + 01 /r
         assert_eq!(&emitter.code[..3], &[0x48, 0x01, 0xC8]);
     }
+
+    #[test]
+    fn emitter_32bit_ops_drop_rex_w() {
+        let mut emitter = X64Emitter::new(64);
+
+        // ADD EAX, ECX
+        emitter.emit_alu_reg(op::ADD, RAX, RCX, false);
+        assert_eq!(&emitter.code[..], &[0x01, 0xC8]);
+
+        // MOV R9D, 7
+        emitter.code.clear();
+        emitter.emit_mov_imm32(R9, 7, false);
+        assert_eq!(&emitter.code[..], &[0x41, 0xB9, 7, 0, 0, 0]);
+    }
+
+    #[test]
+    fn emitter_r12_base_uses_sib() {
+        let mut emitter = X64Emitter::new(64);
+
+        // MOV qword [R12 + 16], 3
+        emitter.emit_store_imm32(R12, 16, 3);
+        assert_eq!(
+            &emitter.code[..],
+            &[0x49, 0xC7, 0x44, 0x24, 0x10, 3, 0, 0, 0]
+        );
+    }
 }
@@ -22,6 +22,17 @@
 //! | R9     | X22    | Callee-saved               |
 //! | R10    | X25    | Frame pointer (read-only)  |
 //!
+//! X26 keeps the context for helper calls, and X23 the [`TailCalls`] state
+//! of the run.
+//!
+//! # Calling Convention
+//!
+//! An image is called as
+//! `extern "C" fn(ctx: *const BpfContext, tail_calls: *mut TailCalls<P>) -> u64`.
+//! A tail call tears down the frame and branches to the target's entry
+//! with the same arguments, so the target returns straight to the caller
+//! of the chain.
+//!
 //! # Stack Layout
 //!
 //! ```text
@@ -31,14 +42,21 @@
 //! ├─────────────────────┤
 //! │ Saved FP (X29)      │
 //! ├─────────────────────┤
-//! │ Saved X19-X25       │
+//! │ Saved X19-X26       │
 //! ├─────────────────────┤
 //! │ BPF stack space     │
-//! │ (profile max)       │
+//! │ (program depth)     │
 //! ├─────────────────────┤  ← BPF R10 (frame pointer)
 //! │                     │
 //! Low Address
 //! ```
+//!
+//! BPF functions are entered with `bl`. Each saves X29/X30, X19-X22
+//! (BPF R6-R9) and X25/X26 and reserves its own depth below them, so its
+//! frame lies below the caller's. The image is only compiled if the call
+//! graph is bounded and the main frame takes at most
+//! [`MAX_JIT_STACK_SIZE`] bytes, as nothing limits the native stack at run
+//! time.
 
 extern crate alloc;
 
@@ -48,8 +66,13 @@ use core::marker::PhantomData;
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::opcode::{AluOp, JmpOp, MemSize, OpcodeClass, SourceType};
 use crate::bytecode::program::BpfProgram;
-use crate::execution::{BpfContext, BpfExecutor, BpfResult};
+use crate::bytecode::subprog::{self, Subprog};
+use crate::execution::{
+    BpfContext, BpfExecutor, BpfResult, MAX_JIT_STACK_SIZE, TAIL_CALL_FAILED, TailCalls,
+};
+use crate::helpers::{self, HelperId};
 use crate::profile::{ActiveProfile, PhysicalProfile};
+use crate::verifier::subprog::check_subprogs;
 
 // External kernel functions provided by the main kernel crate
 #[cfg(not(test))]
@@ -65,14 +88,18 @@ const X1: u8 = 1;
 const X2: u8 = 2;
 const X3: u8 = 3;
 const X4: u8 = 4;
-// X5-X6 are not used in our mapping
+const X5: u8 = 5; // Helper context argument
+// X6 is not used in our mapping
 const X7: u8 = 7;
 const X9: u8 = 9; // Scratch register for helper addresses
 const X19: u8 = 19;
 const X20: u8 = 20;
 const X21: u8 = 21;
 const X22: u8 = 22;
+const X23: u8 = 23; // Tail-call state of the run
+const X24: u8 = 24;
 const X25: u8 = 25;
+const X26: u8 = 26; // Saved context pointer for helper calls
 const X29: u8 = 29; // Frame pointer
 const X30: u8 = 30; // Link register
 const SP: u8 = 31; // Stack pointer (when used as base)
@@ -100,8 +127,10 @@ struct Arm64Emitter {
     jump_patches: Vec<(usize, usize)>,
     /// Instruction offsets (BPF insn index -> code offset)
     insn_offsets: Vec<usize>,
-    /// Stack size used in prologue (for matching epilogue)
-    stack_size: usize,
+    /// Calls of BPF functions that need patching (offset -> target instruction index)
+    call_patches: Vec<(usize, usize)>,
+    /// Entry points of BPF functions (first instruction index, code offset)
+    subprog_entries: Vec<(usize, usize)>,
 }
 
 impl Arm64Emitter {
@@ -110,7 +139,8 @@ impl Arm64Emitter {
             code: Vec::with_capacity(capacity),
             jump_patches: Vec::new(),
             insn_offsets: Vec::new(),
-            stack_size: 0,
+            call_patches: Vec::new(),
+            subprog_entries: Vec::new(),
         }
     }
 
@@ -134,6 +164,11 @@ impl Arm64Emitter {
         self.jump_patches.push((self.offset() - 4, target_insn));
     }
 
+    /// Record a call of the BPF function starting at `target_insn`.
+    fn record_call(&mut self, target_insn: usize) {
+        self.call_patches.push((self.offset() - 4, target_insn));
+    }
+
     // ============================================================
     // ARM64 Instruction Encoding
     // ============================================================
@@ -474,12 +509,35 @@ impl Arm64Emitter {
         self.emit(insn);
     }
 
+    /// Branch with link: BL offset
+    fn emit_bl(&mut self, offset: i32) {
+        // BL: imm26 offset
+        let imm26 = ((offset >> 2) as u32) & 0x3FFFFFF;
+        let insn = 0x94000000 | imm26;
+        self.emit(insn);
+    }
+
     /// Branch to link register: RET
     fn emit_ret(&mut self) {
         // RET (X30)
         self.emit(0xD65F03C0);
     }
 
+    /// Branch to register: BR Xn
+    fn emit_br(&mut self, rn: u8) {
+        let insn = 0xD61F0000 | ((rn as u32) << 5);
+        self.emit(insn);
+    }
+
+    /// Compare and branch if zero: CBZ Xt, offset
+    ///
+    /// Overwrites the placeholder at code offset `at`.
+    fn patch_cbz(&mut self, at: usize, rt: u8, offset: i32) {
+        let imm19 = ((offset >> 2) as u32) & 0x7FFFF;
+        let insn = 0xB4000000 | (imm19 << 5) | ((rt as u32) & 0x1f);
+        self.code[at..at + 4].copy_from_slice(&insn.to_le_bytes());
+    }
+
     /// Branch with link to register: BLR Xn
     fn emit_blr(&mut self, rn: u8) {
         // BLR Xn
@@ -560,13 +618,14 @@ impl Arm64Emitter {
 }
 
 /// ARM64 JIT-compiled BPF program.
-pub struct Arm64JitProgram {
+pub struct Arm64JitProgram<P: PhysicalProfile = ActiveProfile> {
     /// Executable code
-    #[allow(dead_code)]
     code: Vec<u8>,
     /// Entry point function
     #[allow(dead_code)]
     entry: usize,
+    /// Profile the tail-call runtime was compiled for
+    _profile: PhantomData<fn() -> P>,
 }
 
 /// ARM64 JIT compiler error.
@@ -578,6 +637,8 @@ pub enum Arm64JitError {
     CodeTooLarge,
     /// Memory allocation failed
     AllocationFailed,
+    /// The program needs more stack than a JIT frame may take
+    StackTooLarge,
 }
 
 /// ARM64 JIT compiler.
@@ -594,35 +655,49 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
     }
 
     /// Compile a BPF program to ARM64 machine code.
-    pub fn compile(&self, program: &BpfProgram<P>) -> Result<Arm64JitProgram, Arm64JitError> {
+    pub fn compile(&self, program: &BpfProgram<P>) -> Result<Arm64JitProgram<P>, Arm64JitError> {
         let insns = program.instructions();
 
         // Estimate code size (roughly 4 ARM64 instructions per BPF instruction)
         let estimated_size = insns.len() * 16 + 256; // Extra for prologue/epilogue
         let mut emitter = Arm64Emitter::new(estimated_size);
 
+        // Functions must keep to themselves and their calls must be bounded
+        check_subprogs::<P>(insns).map_err(|_| Arm64JitError::UnsupportedInstruction)?;
+
         // Emit prologue
-        self.emit_prologue(&mut emitter, P::MAX_STACK_SIZE);
-
-        // Compile each BPF instruction
-        let mut idx = 0;
-        while idx < insns.len() {
-            let insn = &insns[idx];
-            emitter.mark_insn();
-
-            // Check for wide instruction (LD_IMM64)
-            if insn.is_wide() {
-                if idx + 1 >= insns.len() {
-                    return Err(Arm64JitError::UnsupportedInstruction);
-                }
-                let next_insn = &insns[idx + 1];
-                self.compile_ld_wide(&mut emitter, insn, next_insn)?;
-                // Mark the second instruction slot (for jump target purposes)
+        let stack_size = program.stack_size().max(program.subprogs()[0].stack_depth);
+        if stack_size > MAX_JIT_STACK_SIZE {
+            return Err(Arm64JitError::StackTooLarge);
+        }
+        self.emit_prologue(&mut emitter, stack_size);
+
+        for sub in program.subprogs() {
+            if sub.start > 0 {
+                emitter.subprog_entries.push((sub.start, emitter.offset()));
+                self.emit_subprog_prologue(&mut emitter, sub.stack_depth);
+            }
+
+            // Compile each BPF instruction
+            let mut idx = sub.start;
+            while idx < sub.end {
+                let insn = &insns[idx];
                 emitter.mark_insn();
-                idx += 2;
-            } else {
-                self.compile_insn(&mut emitter, insn, idx)?;
-                idx += 1;
+
+                // Check for wide instruction (LD_IMM64)
+                if insn.is_wide() {
+                    if idx + 1 >= sub.end {
+                        return Err(Arm64JitError::UnsupportedInstruction);
+                    }
+                    let next_insn = &insns[idx + 1];
+                    self.compile_ld_wide(&mut emitter, insn, next_insn)?;
+                    // Mark the second instruction slot (for jump target purposes)
+                    emitter.mark_insn();
+                    idx += 2;
+                } else {
+                    self.compile_insn(&mut emitter, insn, idx, sub)?;
+                    idx += 1;
+                }
             }
         }
 
@@ -632,61 +707,111 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
         Ok(Arm64JitProgram {
             code: emitter.code,
             entry: 0,
+            _profile: PhantomData,
         })
     }
 
     /// Emit function prologue.
     fn emit_prologue(&self, emitter: &mut Arm64Emitter, stack_size: usize) {
-        // Store stack size for epilogue
-        emitter.stack_size = stack_size;
-
         // Save frame pointer and link register
         emitter.emit_stp(X29, X30, SP, -16);
 
-        // Set up frame pointer
-        emitter.emit_mov_reg(X29, SP);
+        // Set up frame pointer (MOV X29, SP)
+        emitter.emit_add_imm(X29, SP, 0);
 
-        // Save callee-saved registers (X19-X25)
+        // Save callee-saved registers (X19-X26)
         emitter.emit_stp(X19, X20, SP, -32);
         emitter.emit_stp(X21, X22, SP, -48);
-        emitter.emit_stp(X25, X25, SP, -56); // X25 is BPF R10
+        emitter.emit_stp(X25, X26, SP, -64); // X25 is BPF R10, X26 holds ctx
+        emitter.emit_stp(X23, X24, SP, -80); // X23 holds the tail-call state
 
-        // Allocate BPF stack space
-        let stack_alloc = ((stack_size + 15) & !15) as u16; // 16-byte aligned
-        emitter.emit_sub_imm(SP, SP, stack_alloc + 64);
+        // Step below the saved registers
+        emitter.emit_sub_imm(SP, SP, 80);
 
         // Set up BPF frame pointer (R10 -> X25)
         // Points to the top of BPF stack
-        emitter.emit_mov_reg(X25, SP);
-        emitter.emit_add_imm(X25, X25, stack_alloc);
+        emitter.emit_add_imm(X25, SP, 0);
+
+        // Allocate BPF stack space, 16-byte aligned. SUB takes a 12-bit
+        // immediate, so large frames take several steps.
+        let mut remaining = stack_size.next_multiple_of(16);
+        while remaining > 0 {
+            let step = remaining.min(0xFF0);
+            emitter.emit_sub_imm(SP, SP, step as u16);
+            remaining -= step;
+        }
+
+        // Keep the context for helpers, which take it as their sixth argument
+        emitter.emit_mov_reg(X26, X0);
+
+        // Keep the tail-call state for `bpf_tail_call`
+        emitter.emit_mov_reg(X23, X1);
     }
 
     /// Emit function epilogue.
     fn emit_epilogue(&self, emitter: &mut Arm64Emitter) {
-        let stack_size = emitter.stack_size;
-        let stack_alloc = ((stack_size + 15) & !15) as u16;
+        self.emit_teardown(emitter);
 
-        // Deallocate stack
-        emitter.emit_add_imm(SP, SP, stack_alloc + 64);
+        // Return
+        emitter.emit_ret();
+    }
+
+    /// Emit the prologue of a BPF function using `stack_size` bytes of stack.
+    fn emit_subprog_prologue(&self, emitter: &mut Arm64Emitter, stack_size: usize) {
+        // Save the caller's FP/LR, R6-R9 and R10 (X26 rides along)
+        emitter.emit_sub_imm(SP, SP, 64);
+        emitter.emit_stp(X29, X30, SP, 48);
+        emitter.emit_stp(X19, X20, SP, 32);
+        emitter.emit_stp(X21, X22, SP, 16);
+        emitter.emit_stp(X25, X26, SP, 0);
+        emitter.emit_add_imm(X29, SP, 64);
+
+        // R10 points to the top of this function's stack
+        emitter.emit_add_imm(X25, SP, 0);
+
+        // SUB takes a 12-bit immediate, so large frames take several steps
+        let mut remaining = stack_size.next_multiple_of(16);
+        while remaining > 0 {
+            let step = remaining.min(0xFF0);
+            emitter.emit_sub_imm(SP, SP, step as u16);
+            remaining -= step;
+        }
+    }
+
+    /// Emit the epilogue of a BPF function.
+    fn emit_subprog_epilogue(&self, emitter: &mut Arm64Emitter) {
+        emitter.emit_sub_imm(SP, X29, 64);
+        emitter.emit_ldp(X25, X26, SP, 0);
+        emitter.emit_ldp(X21, X22, SP, 16);
+        emitter.emit_ldp(X19, X20, SP, 32);
+        emitter.emit_ldp(X29, X30, SP, 48);
+        emitter.emit_add_imm(SP, SP, 64);
+        emitter.emit_ret();
+    }
+
+    /// Free the frame and restore the caller's registers, leaving X0-X18
+    /// untouched.
+    fn emit_teardown(&self, emitter: &mut Arm64Emitter) {
+        // Deallocate stack: X29 still holds SP at entry
+        emitter.emit_add_imm(SP, X29, 0);
 
         // Restore callee-saved registers
-        emitter.emit_ldp(X25, X25, SP, -56);
+        emitter.emit_ldp(X23, X24, SP, -80);
+        emitter.emit_ldp(X25, X26, SP, -64);
         emitter.emit_ldp(X21, X22, SP, -48);
         emitter.emit_ldp(X19, X20, SP, -32);
 
         // Restore frame pointer and link register
         emitter.emit_ldp(X29, X30, SP, -16);
-
-        // Return
-        emitter.emit_ret();
     }
 
-    /// Compile a single BPF instruction.
+    /// Compile a single BPF instruction of function `sub`.
     fn compile_insn(
         &self,
         emitter: &mut Arm64Emitter,
         insn: &BpfInsn,
         idx: usize,
+        sub: &Subprog,
     ) -> Result<(), Arm64JitError> {
         let class = insn.class().ok_or(Arm64JitError::UnsupportedInstruction)?;
 
@@ -698,7 +823,7 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
                 self.compile_alu(emitter, insn, false)?;
             }
             OpcodeClass::Jmp | OpcodeClass::Jmp32 => {
-                self.compile_jmp(emitter, insn, idx)?;
+                self.compile_jmp(emitter, insn, idx, sub)?;
             }
             OpcodeClass::Ldx => {
                 self.compile_ldx(emitter, insn)?;
@@ -883,15 +1008,21 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
         Ok(())
     }
 
-    /// Compile jump instruction.
+    /// Compile jump instruction of function `sub`.
     fn compile_jmp(
         &self,
         emitter: &mut Arm64Emitter,
         insn: &BpfInsn,
-        _idx: usize,
+        idx: usize,
+        sub: &Subprog,
     ) -> Result<(), Arm64JitError> {
         // Check for EXIT
         if insn.is_exit() {
+            if sub.start > 0 {
+                // R0 stays in X7 for the calling function
+                self.emit_subprog_epilogue(emitter);
+                return Ok(());
+            }
             // Move return value from BPF R0 (X7) to ARM64 return register (X0)
             emitter.emit_mov_reg(X0, X7);
             self.emit_epilogue(emitter);
@@ -900,6 +1031,13 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
 
         let jmp_op = insn.jmp_op().ok_or(Arm64JitError::UnsupportedInstruction)?;
 
+        // Call of a BPF function, checked by `check_subprogs`
+        if let Some(target) = subprog::call_target(idx, insn) {
+            emitter.emit_bl(0); // Placeholder, will patch
+            emitter.record_call(target);
+            return Ok(());
+        }
+
         // Handle CALL instruction
         if jmp_op.is_call() {
             return self.compile_call(emitter, insn);
@@ -911,7 +1049,7 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
         if jmp_op.is_unconditional() {
             // JA: unconditional jump
             emitter.emit_b(target as i32 * 4); // Placeholder, will patch
-            emitter.record_jump((insn.offset as usize).wrapping_add(_idx).wrapping_add(1));
+            emitter.record_jump((insn.offset as usize).wrapping_add(idx).wrapping_add(1));
         } else {
             // Conditional jump
             let dst = BPF_TO_ARM64[insn.dst_reg() as usize];
@@ -938,7 +1076,7 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
                 }
                 // JSET jumps if (dst & src) != 0, i.e., NE condition
                 emitter.emit_b_cond(1, target as i32 * 4); // NE = 1
-                emitter.record_jump((insn.offset as usize).wrapping_add(_idx).wrapping_add(1));
+                emitter.record_jump((insn.offset as usize).wrapping_add(idx).wrapping_add(1));
                 return Ok(());
             }
 
@@ -977,7 +1115,7 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
             };
 
             emitter.emit_b_cond(cond, target as i32 * 4);
-            emitter.record_jump((insn.offset as usize).wrapping_add(_idx).wrapping_add(1));
+            emitter.record_jump((insn.offset as usize).wrapping_add(idx).wrapping_add(1));
         }
 
         Ok(())
@@ -989,19 +1127,28 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
         emitter: &mut Arm64Emitter,
         insn: &BpfInsn,
     ) -> Result<(), Arm64JitError> {
-        let helper_id = insn.imm;
+        if insn.imm == HelperId::TailCall as i32 && HelperId::TailCall.is_available() {
+            self.compile_tail_call(emitter);
+            return Ok(());
+        }
 
-        // Get the helper function address based on helper_id
-        // BPF R1-R5 are already in ARM64 X0-X4 due to our register mapping
-        // so arguments are already in the right place for ARM64 calling convention
+        // bpf_loop calls back into the program; the interpreter runs it
+        if insn.imm == HelperId::Loop as i32 {
+            return Err(Arm64JitError::UnsupportedInstruction);
+        }
+
+        let helper = helpers::lookup(insn.imm)
+            .filter(|def| def.is_available())
+            .and_then(|def| def.func)
+            .ok_or(Arm64JitError::UnsupportedInstruction)?;
 
-        // Get helper address and load it into X9
-        let helper_addr = self.get_helper_address(helper_id)?;
-        emitter.emit_mov64_imm(X9, helper_addr as i64);
+        // BPF R1-R5 are already in ARM64 X0-X4 due to our register mapping
+        // so arguments are already in the right place for ARM64 calling convention.
+        // The context pointer goes in X5.
+        emitter.emit_mov_reg(X5, X26);
 
-        // Save caller-saved BPF registers before call
-        // BPF R0 (X7) may be clobbered by the helper
-        // X5, X6 are not in our mapping and can be clobbered
+        // Load the helper address into X9
+        emitter.emit_mov64_imm(X9, helper as usize as i64);
 
         // Call the helper function
         emitter.emit_blr(X9);
@@ -1012,46 +1159,31 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
         Ok(())
     }
 
-    /// Get the address of a BPF helper function.
-    fn get_helper_address(&self, helper_id: i32) -> Result<u64, Arm64JitError> {
-        // These are the same helper IDs used in the interpreter
-        // SAFETY: These functions are defined in the kernel and linked into the final binary.
-        // They follow the C calling convention which matches the JIT's expectations.
-        unsafe extern "C" {
-            fn bpf_ktime_get_ns() -> u64;
-            fn bpf_trace_printk(fmt: *const u8, size: u32) -> i32;
-            fn bpf_map_lookup_elem(map_id: u32, key: *const u8) -> *mut u8;
-            fn bpf_map_update_elem(
-                map_id: u32,
-                key: *const u8,
-                value: *const u8,
-                flags: u64,
-            ) -> i32;
-            fn bpf_map_delete_elem(map_id: u32, key: *const u8) -> i32;
-            fn bpf_ringbuf_output(map_id: u32, data: *const u8, size: u64, flags: u64) -> i64;
-            fn bpf_timeseries_push(map_id: u32, key: *const u8, value: *const u8) -> i64;
-            // Robotics Helpers
-            fn bpf_gpio_read(pin: u32) -> i64;
-            fn bpf_gpio_write(pin: u32, value: u32) -> i64;
-            fn bpf_motor_emergency_stop(reason: u32) -> i64;
-            fn bpf_pwm_write(pwm_id: u32, channel: u32, duty: u32) -> i64;
-        }
+    /// Compile a `bpf_tail_call`.
+    ///
+    /// [`jit_tail_call`] resolves the target's entry point. If there is one,
+    /// the frame is torn down and the target entered with the context and
+    /// the tail-call state, as if it had been called instead of us.
+    /// Otherwise R0 is set to [`TAIL_CALL_FAILED`] and execution continues.
+    fn compile_tail_call(&self, emitter: &mut Arm64Emitter) {
+        // The map ID (R2) and index (R3) are already in X1 and X2
+        emitter.emit_mov_reg(X0, X23);
+        emitter.emit_mov64_imm(X9, jit_tail_call::<P> as *const () as i64);
+        emitter.emit_blr(X9);
 
-        match helper_id {
-            1 => Ok(bpf_ktime_get_ns as *const () as u64),
-            2 => Ok(bpf_trace_printk as *const () as u64),
-            3 => Ok(bpf_map_lookup_elem as *const () as u64),
-            4 => Ok(bpf_map_update_elem as *const () as u64),
-            5 => Ok(bpf_map_delete_elem as *const () as u64),
-            6 => Ok(bpf_ringbuf_output as *const () as u64),
-            1001 => Ok(bpf_timeseries_push as *const () as u64),
-            // Robotics Helpers
-            1000 => Ok(bpf_motor_emergency_stop as *const () as u64),
-            1003 => Ok(bpf_gpio_write as *const () as u64),
-            1004 => Ok(bpf_gpio_read as *const () as u64),
-            1005 => Ok(bpf_pwm_write as *const () as u64),
-            _ => Err(Arm64JitError::UnsupportedInstruction),
-        }
+        // CBZ X0, failed
+        let branch = emitter.offset();
+        emitter.emit(0);
+
+        emitter.emit_mov_reg(X9, X0);
+        emitter.emit_mov_reg(X0, X26);
+        emitter.emit_mov_reg(X1, X23);
+        self.emit_teardown(emitter);
+        emitter.emit_br(X9);
+
+        let failed = emitter.offset();
+        emitter.patch_cbz(branch, X0, (failed - branch) as i32);
+        emitter.emit_mov64_imm(X7, TAIL_CALL_FAILED as i64);
     }
 
     /// Compile load instruction (LDX).
@@ -1152,6 +1284,19 @@ impl<P: PhysicalProfile> Arm64JitCompiler<P> {
             emitter.code[*code_offset..*code_offset + 4].copy_from_slice(&patched.to_le_bytes());
         }
 
+        for (code_offset, target_insn) in &emitter.call_patches {
+            let (_, target_offset) = emitter
+                .subprog_entries
+                .iter()
+                .find(|(start, _)| start == target_insn)
+                .ok_or(Arm64JitError::UnsupportedInstruction)?;
+            let branch_offset = (*target_offset as i32) - (*code_offset as i32);
+            let imm26 = ((branch_offset >> 2) as u32) & 0x3FFFFFF;
+            let patched = 0x94000000 | imm26;
+
+            emitter.code[*code_offset..*code_offset + 4].copy_from_slice(&patched.to_le_bytes());
+        }
+
         Ok(())
     }
 }
@@ -1176,9 +1321,14 @@ impl<P: PhysicalProfile> Arm64JitExecutor<P> {
     }
 
     /// Compile a program.
-    pub fn compile(&self, program: &BpfProgram<P>) -> Result<Arm64JitProgram, Arm64JitError> {
+    pub fn compile(&self, program: &BpfProgram<P>) -> Result<Arm64JitProgram<P>, Arm64JitError> {
         self.compiler.compile(program)
     }
+
+    /// Compile a program and install it in executable memory.
+    pub fn load(&self, program: &BpfProgram<P>) -> Result<Arm64JitImage<P>, Arm64JitError> {
+        Arm64JitImage::install(&self.compile(program)?)
+    }
 }
 
 impl<P: PhysicalProfile> Default for Arm64JitExecutor<P> {
@@ -1189,45 +1339,11 @@ impl<P: PhysicalProfile> Default for Arm64JitExecutor<P> {
 
 impl<P: PhysicalProfile> BpfExecutor<P> for Arm64JitExecutor<P> {
     fn execute(&self, program: &BpfProgram<P>, ctx: &BpfContext) -> BpfResult {
-        // Try to compile
-        match self.compile(program) {
-            Ok(jit_prog) => {
-                // 1. Allocate executable memory
-                let size = jit_prog.code.len();
-
-                // SAFETY: Calling external kernel function to allocate RX memory
-                let ptr = unsafe { bpf_jit_alloc_exec(size) };
-
-                if ptr.is_null() {
-                    // Fallback to interpreter if allocation fails
-                    let interp = crate::execution::Interpreter::<P>::new();
-                    return interp.execute(program, ctx);
-                }
-
-                // 2. Copy code
-                // SAFETY: ptr is valid for size bytes as returned by alloc.
-                // We are writing to it before it is effectively executable (or relying on RWX mapping).
-                unsafe {
-                    core::ptr::copy_nonoverlapping(jit_prog.code.as_ptr(), ptr, size);
-                }
-
-                // 3. Sync cache
-                // SAFETY: Required to ensure instruction fetch sees the new code
-                unsafe { aarch64_jit_sync_cache(ptr as usize, size) };
-
-                // 4. Cast to function pointer and execute
-                // BPF JIT function signature: fn(ctx: *const BpfContext) -> u64
-                // The JIT ensures R1 (ctx) is in X0, and R0 (ret) is moved to X0 before return.
-                let func: unsafe extern "C" fn(*const BpfContext) -> u64 =
-                    unsafe { core::mem::transmute(ptr) };
-
-                let result = unsafe { func(ctx) };
-
-                // 5. Free memory
-                unsafe { bpf_jit_free_exec(ptr, size) };
-
-                Ok(result)
-            }
+        match self
+            .compile(program)
+            .and_then(|jit_prog| Arm64JitImage::install(&jit_prog))
+        {
+            Ok(image) => image.call(ctx),
             Err(_) => {
                 // Fall back to interpreter
                 let interp = crate::execution::Interpreter::<P>::new();
@@ -1237,6 +1353,104 @@ impl<P: PhysicalProfile> BpfExecutor<P> for Arm64JitExecutor<P> {
     }
 }
 
+/// ARM64 JIT image installed in executable memory.
+///
+/// Compiling and installing a program is expensive, so callers that run the
+/// same program repeatedly (e.g. hooks) should install it once and keep the
+/// image around. The executable memory is released when the image is dropped.
+pub struct Arm64JitImage<P: PhysicalProfile = ActiveProfile> {
+    ptr: *mut u8,
+    size: usize,
+    _profile: PhantomData<fn() -> P>,
+}
+
+// SAFETY: The image is never written after `install`, so sharing the
+// entry point between threads is sound.
+unsafe impl<P: PhysicalProfile> Send for Arm64JitImage<P> {}
+unsafe impl<P: PhysicalProfile> Sync for Arm64JitImage<P> {}
+
+impl<P: PhysicalProfile> Arm64JitImage<P> {
+    /// Copy compiled code into freshly allocated executable memory.
+    pub fn install(jit_prog: &Arm64JitProgram<P>) -> Result<Self, Arm64JitError> {
+        let size = jit_prog.code.len();
+
+        // SAFETY: Calling external kernel function to allocate RX memory
+        let ptr = unsafe { bpf_jit_alloc_exec(size) };
+        if ptr.is_null() {
+            return Err(Arm64JitError::AllocationFailed);
+        }
+
+        // SAFETY: ptr is valid for size bytes as returned by alloc.
+        // We are writing to it before it is effectively executable (or relying on RWX mapping).
+        unsafe {
+            core::ptr::copy_nonoverlapping(jit_prog.code.as_ptr(), ptr, size);
+        }
+
+        // SAFETY: Required to ensure instruction fetch sees the new code
+        unsafe { aarch64_jit_sync_cache(ptr as usize, size) };
+
+        Ok(Self {
+            ptr,
+            size,
+            _profile: PhantomData,
+        })
+    }
+
+    /// Size of the installed code in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Entry point of the installed code, for tail calls into it.
+    pub fn entry(&self) -> *const u8 {
+        self.ptr
+    }
+
+    /// Run the installed program, in which every tail call fails.
+    pub fn call(&self, ctx: &BpfContext) -> BpfResult {
+        self.call_with_tail_calls(ctx, &mut TailCalls::none())
+    }
+
+    /// Run the installed program with tail calls resolved through
+    /// `tail_calls`.
+    pub fn call_with_tail_calls(
+        &self,
+        ctx: &BpfContext,
+        tail_calls: &mut TailCalls<'_, P>,
+    ) -> BpfResult {
+        // The JIT ensures R1 (ctx) is in X0, and R0 (ret) is moved to X0 before return.
+        // SAFETY: ptr points to verified code emitted by the compiler, whose
+        // tail-call runtime expects the state of this profile.
+        let func: unsafe extern "C" fn(*const BpfContext, *mut TailCalls<'_, P>) -> u64 =
+            unsafe { core::mem::transmute(self.ptr) };
+
+        Ok(unsafe { func(ctx, tail_calls) })
+    }
+}
+
+/// Tail-call runtime for JIT code.
+///
+/// Returns the entry point of the target, or null if the call fails.
+unsafe extern "C" fn jit_tail_call<P: PhysicalProfile>(
+    tail_calls: *mut TailCalls<'_, P>,
+    map_id: u64,
+    index: u64,
+) -> *const u8 {
+    // SAFETY: the image was entered through `call_with_tail_calls`, whose
+    // state outlives the run.
+    let tail_calls = unsafe { &mut *tail_calls };
+    tail_calls
+        .next_jit(map_id, index)
+        .unwrap_or(core::ptr::null())
+}
+
+impl<P: PhysicalProfile> Drop for Arm64JitImage<P> {
+    fn drop(&mut self) {
+        // SAFETY: ptr/size came from bpf_jit_alloc_exec in `install`.
+        unsafe { bpf_jit_free_exec(self.ptr, self.size) };
+    }
+}
+
 // Dummy implementations for tests to satisfy linker
 #[cfg(test)]
 #[unsafe(no_mangle)]
@@ -1254,9 +1468,12 @@ unsafe extern "C" fn aarch64_jit_sync_cache(_start: usize, _len: usize) {}
 
 #[cfg(test)]
 mod tests {
+    use alloc::sync::Arc;
+
     use super::*;
     use crate::bytecode::insn::BpfInsn;
     use crate::bytecode::program::{BpfProgType, ProgramBuilder};
+    use crate::execution::TailCallTarget;
 
     #[test]
     fn test_register_mapping() {
@@ -1385,6 +1602,155 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_compile_rejects_unimplemented_helper() {
+        for helper_id in [9999, HelperId::GetPrandomU32 as i32, HelperId::Loop as i32] {
+            let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+                .insn(BpfInsn::call(helper_id))
+                .exit()
+                .build()
+                .expect("valid program");
+
+            let compiler = Arm64JitCompiler::<ActiveProfile>::new();
+            assert_eq!(
+                compiler.compile(&program).err(),
+                Some(Arm64JitError::UnsupportedInstruction)
+            );
+        }
+    }
+
+    #[test]
+    fn test_compile_tail_call() {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::call(HelperId::TailCall as i32))
+            .insn(BpfInsn::mov64_imm(0, 1))
+            .exit()
+            .build()
+            .expect("valid program");
+
+        let compiler = Arm64JitCompiler::<ActiveProfile>::new();
+        let jit_prog = compiler.compile(&program).expect("tail calls compile");
+        let (words, rest) = jit_prog.code.as_chunks::<4>();
+        assert!(rest.is_empty());
+        let words: Vec<u32> = words.iter().copied().map(u32::from_le_bytes).collect();
+
+        // BR X9 to the target, and a CBZ X0 that skips it when the call fails
+        let br = words.iter().position(|&w| w == 0xD61F0120).expect("BR X9");
+        let cbz = words
+            .iter()
+            .position(|&w| w & 0xFF00001F == 0xB4000000)
+            .expect("CBZ X0");
+        let skip = ((words[cbz] >> 5) & 0x7FFFF) as usize;
+        assert_eq!(cbz + skip, br + 1);
+    }
+
+    #[test]
+    fn test_compile_function_calls() {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(1, 1)) // 0
+            .insn(BpfInsn::call_local(1)) // 1: call 3
+            .exit() // 2
+            .insn(BpfInsn::mov64_reg(0, 1)) // 3
+            .exit() // 4
+            .build()
+            .expect("valid program");
+
+        let compiler = Arm64JitCompiler::<ActiveProfile>::new();
+        let jit_prog = compiler.compile(&program).expect("function calls compile");
+        let words: Vec<u32> = jit_prog
+            .code
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|&word| u32::from_le_bytes(word))
+            .collect();
+
+        // BL lands on the function's prologue, which opens with SUB SP, SP, #64
+        let bl = words
+            .iter()
+            .position(|&w| w & 0xFC000000 == 0x94000000)
+            .expect("BL");
+        let target = bl + (words[bl] & 0x3FFFFFF) as usize;
+        assert_eq!(words[target], 0xD10103FF);
+        // The function returns with RET
+        assert!(words[target..].contains(&0xD65F03C0));
+
+        let recursive = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::call_local(1)) // 0: call 2
+            .exit() // 1
+            .insn(BpfInsn::call_local(-1)) // 2: call 2
+            .exit() // 3
+            .build()
+            .expect("valid program");
+        assert_eq!(
+            compiler.compile(&recursive).err(),
+            Some(Arm64JitError::UnsupportedInstruction)
+        );
+    }
+
+    #[test]
+    fn test_jit_tail_call_runtime() {
+        struct Target {
+            program: BpfProgram<ActiveProfile>,
+            entry: Option<*const u8>,
+        }
+
+        // SAFETY: the entry point is never dereferenced.
+        unsafe impl Send for Target {}
+        unsafe impl Sync for Target {}
+
+        impl TailCallTarget<ActiveProfile> for Target {
+            fn id(&self) -> u32 {
+                1
+            }
+
+            fn program(&self) -> &BpfProgram<ActiveProfile> {
+                &self.program
+            }
+
+            fn jit_entry(&self) -> Option<*const u8> {
+                self.entry
+            }
+        }
+
+        let program = || {
+            ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+                .insn(BpfInsn::mov64_imm(0, 0))
+                .exit()
+                .build()
+                .expect("valid program")
+        };
+        let jited: Arc<dyn TailCallTarget<ActiveProfile>> = Arc::new(Target {
+            program: program(),
+            entry: Some(0x1000 as *const u8),
+        });
+        let interpreted: Arc<dyn TailCallTarget<ActiveProfile>> = Arc::new(Target {
+            program: program(),
+            entry: None,
+        });
+        let resolve = |_map_id: u32, index: u32| match index {
+            0 => Some(jited.clone()),
+            1 => Some(interpreted.clone()),
+            _ => None,
+        };
+        let mut tail_calls = TailCalls::new(&resolve);
+
+        let call = |tail_calls: &mut TailCalls<'_, ActiveProfile>, index| unsafe {
+            jit_tail_call(tail_calls, 5, index)
+        };
+        assert_eq!(call(&mut tail_calls, 0), 0x1000 as *const u8);
+        // No image to branch to
+        assert!(call(&mut tail_calls, 1).is_null());
+        // Empty slot
+        assert!(call(&mut tail_calls, 2).is_null());
+        assert_eq!(tail_calls.count(), 1);
+
+        while tail_calls.count() < ActiveProfile::MAX_TAIL_CALLS {
+            assert!(!call(&mut tail_calls, 0).is_null());
+        }
+        assert!(call(&mut tail_calls, 0).is_null());
+    }
+
     #[test]
     fn test_compile_jset() {
         // Test JSET instruction compilation
@@ -1448,13 +1814,29 @@ mod tests {
     }
 
     #[test]
-    fn test_stack_size_from_profile() {
-        // Verify that the emitter records the correct stack size
-        let mut emitter = Arm64Emitter::new(256);
-        let compiler = Arm64JitCompiler::<ActiveProfile>::new();
+    fn test_stack_size_from_program() {
+        use crate::profile::CloudProfile;
+
+        // *(u64 *)(r10 + offset) = 0, on the profile with room for more
+        // stack than a JIT frame
+        let program = |offset: i16| {
+            ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter)
+                .insn(BpfInsn::new(0x7a, 10, 0, offset, 0))
+                .insn(BpfInsn::mov64_imm(0, 0))
+                .exit()
+                .build()
+                .expect("valid program")
+        };
+        let compiler = Arm64JitCompiler::<CloudProfile>::new();
 
-        compiler.emit_prologue(&mut emitter, 8192);
-        assert_eq!(emitter.stack_size, 8192);
+        // Frames past the 12-bit SUB immediate take several steps
+        assert!(compiler.compile(&program(-4096)).is_ok());
+        assert_eq!(
+            compiler
+                .compile(&program(-(MAX_JIT_STACK_SIZE as i16) - 8))
+                .err(),
+            Some(Arm64JitError::StackTooLarge)
+        );
     }
 
     #[test]
@@ -1487,4 +1869,24 @@ mod tests {
             assert!(result.is_ok(), "Failed to compile {}", name);
         }
     }
+
+    #[test]
+    fn test_load_reports_allocation_failure() {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(0, 7))
+            .exit()
+            .build()
+            .expect("valid program");
+
+        // The test allocator never hands out executable memory.
+        let executor = Arm64JitExecutor::<ActiveProfile>::new();
+        assert!(matches!(
+            executor.load(&program),
+            Err(Arm64JitError::AllocationFailed)
+        ));
+
+        // Execution falls back to the interpreter.
+        let ctx = BpfContext::empty();
+        assert_eq!(executor.execute(&program, &ctx), Ok(7));
+    }
 }
@@ -17,6 +17,7 @@
 extern crate alloc;
 
 mod interpreter;
+mod tail_call;
 
 #[cfg(test)]
 #[allow(clippy::missing_safety_doc, improper_ctypes_definitions)]
@@ -32,6 +33,12 @@ pub mod helpers_stub {
         0
     }
 
+    /// Host tests run on a single CPU.
+    #[unsafe(no_mangle)]
+    pub extern "C" fn bpf_get_smp_processor_id() -> u32 {
+        0
+    }
+
     #[unsafe(no_mangle)]
     pub extern "C" fn bpf_get_interrupt_latency_ns(ctx: *const BpfContext) -> u64 {
         if ctx.is_null() {
@@ -124,6 +131,26 @@ pub mod helpers_stub {
         0
     }
 
+    /// Reports `n` entries, written as the aggregate's leading count.
+    #[unsafe(no_mangle)]
+    pub extern "C" fn bpf_timeseries_query(_map_id: u32, n: u64, out: *mut u8, size: u32) -> i64 {
+        let count = n.to_ne_bytes();
+        let len = count.len().min(size as usize);
+        unsafe { core::ptr::copy_nonoverlapping(count.as_ptr(), out, len) };
+        n as i64
+    }
+
+    #[unsafe(no_mangle)]
+    pub extern "C" fn bpf_timeseries_query_window(
+        _map_id: u32,
+        _start_ns: u64,
+        _end_ns: u64,
+        _out: *mut u8,
+        _size: u32,
+    ) -> i64 {
+        0
+    }
+
     #[unsafe(no_mangle)]
     pub extern "C" fn bpf_motor_emergency_stop(_reason: u32) -> i64 {
         0
@@ -148,11 +175,18 @@ pub mod jit_aarch64;
 
 pub use interpreter::Interpreter;
 #[cfg(any(target_arch = "aarch64", test))]
-pub use jit_aarch64::{Arm64JitCompiler, Arm64JitExecutor};
+pub use jit_aarch64::{Arm64JitCompiler, Arm64JitExecutor, Arm64JitImage};
+pub use tail_call::{TAIL_CALL_FAILED, TailCallResolver, TailCallTarget, TailCalls};
 
 use crate::bytecode::program::BpfProgram;
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
+/// Most stack the JITs give the main frame of a program.
+///
+/// Compiled programs run on the kernel stack, so those needing more are
+/// left to the interpreter, which keeps its stack on the heap.
+pub const MAX_JIT_STACK_SIZE: usize = 8 * 1024;
+
 /// Execution context passed to BPF programs.
 ///
 /// This contains pointers to the program's input data and metadata.
@@ -191,6 +225,32 @@ pub struct SyscallTraceContext {
     pub arg6: u64,
 }
 
+/// Context for the deadline overrun tracepoint, fired when a
+/// `SCHED_DEADLINE` task runs late.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SchedDeadlineTraceContext {
+    pub timestamp: u64,
+    pub pid: u64,
+    pub tid: u64,
+    /// [`DEADLINE_BUDGET_EXHAUSTED`] or [`DEADLINE_MISSED`].
+    pub kind: u32,
+    pub _pad: u32,
+    /// The overruns of the task so far, this one included.
+    pub overruns: u64,
+    /// The absolute deadline of the period that overran.
+    pub deadline: u64,
+    pub runtime_ns: u64,
+    pub deadline_ns: u64,
+    pub period_ns: u64,
+}
+
+/// The task used up its runtime before the deadline, and is throttled until
+/// the period's deadline.
+pub const DEADLINE_BUDGET_EXHAUSTED: u32 = 1;
+/// The deadline passed before the task used up its runtime.
+pub const DEADLINE_MISSED: u32 = 2;
+
 impl BpfContext {
     /// Create an empty context.
     pub const fn empty() -> Self {
@@ -0,0 +1,109 @@
+//! Tail Calls
+//!
+//! `bpf_tail_call(ctx, prog_array, index)` replaces the running program
+//! with the program in slot `index` of a
+//! [program array](crate::maps::ProgArrayMap). The target starts at its
+//! first instruction with the same context and stack, and whatever it
+//! returns is the result of the whole run. If the call fails (empty slot,
+//! index out of range, chain too long) the helper returns
+//! [`TAIL_CALL_FAILED`] and the caller carries on after the call.
+//!
+//! Program arrays are named by map ID, which only the kernel can resolve,
+//! so each run is handed a [`TailCalls`] with a resolver. It also counts
+//! the calls of the run, which are bounded by `P::MAX_TAIL_CALLS`.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::bytecode::program::BpfProgram;
+use crate::profile::{ActiveProfile, PhysicalProfile};
+
+/// Value of R0 after a tail call that failed.
+pub const TAIL_CALL_FAILED: u64 = -1i64 as u64;
+
+/// A program that can be stored in a program array and tail-called.
+pub trait TailCallTarget<P: PhysicalProfile = ActiveProfile>: Send + Sync {
+    /// Program ID, reported when userspace looks up a slot.
+    fn id(&self) -> u32;
+
+    /// The verified program, as run by the interpreter.
+    fn program(&self) -> &BpfProgram<P>;
+
+    /// Entry point of the program's AArch64 JIT image, if it has one.
+    ///
+    /// JIT code can only tail-call programs that have an image.
+    fn jit_entry(&self) -> Option<*const u8> {
+        None
+    }
+}
+
+/// Resolves slot `index` of the program array with ID `map_id`.
+pub type TailCallResolver<'a, P = ActiveProfile> =
+    dyn Fn(u32, u32) -> Option<Arc<dyn TailCallTarget<P>>> + 'a;
+
+/// Tail-call state of one program run.
+pub struct TailCalls<'a, P: PhysicalProfile = ActiveProfile> {
+    resolve: Option<&'a TailCallResolver<'a, P>>,
+    count: usize,
+    /// Targets a JIT chain jumped to. The caller's code keeps running
+    /// until it reaches the jump, so it is only let go by the next call.
+    #[cfg(any(target_arch = "aarch64", test))]
+    running: [Option<Arc<dyn TailCallTarget<P>>>; 2],
+}
+
+impl<'a, P: PhysicalProfile> TailCalls<'a, P> {
+    /// State for a run whose program arrays are resolved by `resolve`.
+    pub fn new(resolve: &'a TailCallResolver<'a, P>) -> Self {
+        Self {
+            resolve: Some(resolve),
+            count: 0,
+            #[cfg(any(target_arch = "aarch64", test))]
+            running: [None, None],
+        }
+    }
+
+    /// State for a run without program arrays, in which every tail call
+    /// fails.
+    pub fn none() -> Self {
+        Self {
+            resolve: None,
+            count: 0,
+            #[cfg(any(target_arch = "aarch64", test))]
+            running: [None, None],
+        }
+    }
+
+    /// Number of tail calls taken so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Look up the target of a tail call, if the chain may grow.
+    fn resolve(&self, map_id: u64, index: u64) -> Option<Arc<dyn TailCallTarget<P>>> {
+        if self.count >= P::MAX_TAIL_CALLS {
+            return None;
+        }
+        let resolve = self.resolve?;
+        resolve(map_id as u32, u32::try_from(index).ok()?)
+    }
+
+    /// Take a tail call from the interpreter.
+    pub(crate) fn next(&mut self, map_id: u64, index: u64) -> Option<Arc<dyn TailCallTarget<P>>> {
+        let target = self.resolve(map_id, index)?;
+        self.count += 1;
+        Some(target)
+    }
+
+    /// Take a tail call from JIT code, returning the target's entry point.
+    #[cfg(any(target_arch = "aarch64", test))]
+    pub(crate) fn next_jit(&mut self, map_id: u64, index: u64) -> Option<*const u8> {
+        let target = self.resolve(map_id, index)?;
+        let entry = target.jit_entry()?;
+        self.count += 1;
+
+        let [caller, current] = &mut self.running;
+        *caller = current.replace(target);
+        Some(entry)
+    }
+}
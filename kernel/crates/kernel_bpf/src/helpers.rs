@@ -0,0 +1,671 @@
+//! BPF Helper Registry
+//!
+//! The single table of helper functions callable from BPF programs. Each
+//! entry ties together the helper ID, its name, its signature, its profile
+//! availability and the function that implements it. Every consumer reads
+//! from here:
+//!
+//! - the verifiers check calls against the signature and availability
+//! - the interpreter and both JITs call through the function pointer,
+//!   except for `bpf_tail_call`, which replaces the running program (see
+//!   [`TailCalls`](crate::execution::TailCalls)), and `bpf_loop`, which
+//!   calls back into it and is only run by the interpreter
+//! - the ELF relocator resolves helper symbols by name
+//!
+//! The [`HelperId`] enum and the definitions are generated from the same
+//! list by the `helpers!` macro, so an ID cannot be paired with the wrong
+//! helper.
+//!
+//! # Calling Convention
+//!
+//! All implementations share the [`HelperFn`] ABI: the five argument
+//! registers R1-R5 as raw `u64` values, followed by the program context.
+//! The adapters below narrow the arguments to each helper's C signature.
+//! On both x86_64 and AArch64 the six parameters are passed in registers,
+//! so JIT code can call an entry directly.
+
+use crate::execution::BpfContext;
+
+/// Uniform helper ABI: `(r1, r2, r3, r4, r5, ctx) -> r0`.
+pub type HelperFn = unsafe extern "C" fn(u64, u64, u64, u64, u64, *const BpfContext) -> u64;
+
+/// Argument type for helper functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    /// Any scalar value (integer)
+    Scalar,
+    /// Map loaded by a `BPF_PSEUDO_MAP_FD` wide load, which the program
+    /// holds; the helper gets its ID
+    PtrToMap,
+    /// Pointer to map key (read-only)
+    PtrToMapKey,
+    /// Pointer to map value
+    PtrToMapValue,
+    /// Pointer to stack memory
+    PtrToStack,
+    /// Pointer to memory (generic, with size)
+    PtrToMem,
+    /// Pointer to memory or null
+    PtrToMemOrNull,
+    /// Size of memory buffer (paired with PtrToMem)
+    MemSize,
+    /// Pointer to context
+    PtrToCtx,
+    /// Any pointer type
+    AnyPtr,
+    /// Constant value (flags, etc.)
+    Const,
+    /// Ring buffer pointer
+    PtrToRingbuf,
+    /// Reserved ring buffer entry
+    PtrToRingbufSample,
+    /// Address of a BPF function of the program
+    PtrToFunc,
+}
+
+/// Return type for helper functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnType {
+    /// Returns an integer/scalar
+    Integer,
+    /// Returns pointer to map value (may be NULL)
+    PtrToMapValueOrNull,
+    /// Returns pointer to allocated memory (may be NULL)
+    PtrToAllocMemOrNull,
+    /// Returns void (always 0)
+    Void,
+}
+
+/// Registry entry for one helper.
+#[derive(Debug)]
+pub struct HelperDef {
+    /// Helper ID, as encoded in the `imm` field of a call instruction
+    pub id: HelperId,
+    /// Symbol name, used for ELF relocation and diagnostics
+    pub name: &'static str,
+    /// Argument types (up to 5 arguments, R1-R5)
+    pub args: &'static [ArgType],
+    /// Return type
+    pub ret: ReturnType,
+    /// Whether the helper is available in the embedded profile.
+    /// Every helper is available in the cloud profile.
+    pub embedded: bool,
+    /// Implementation, or `None` if this kernel does not provide one
+    pub func: Option<HelperFn>,
+}
+
+impl HelperDef {
+    /// Check if the helper is available in the current profile.
+    pub const fn is_available(&self) -> bool {
+        #[cfg(all(feature = "embedded-profile", not(feature = "cloud-profile")))]
+        {
+            self.embedded
+        }
+        #[cfg(feature = "cloud-profile")]
+        {
+            true
+        }
+    }
+
+    /// Call the helper.
+    ///
+    /// Returns `None` if the helper has no implementation.
+    ///
+    /// # Safety
+    ///
+    /// The arguments must satisfy the helper's signature; the verifier
+    /// establishes this for verified programs.
+    pub unsafe fn call(&self, args: [u64; 5], ctx: &BpfContext) -> Option<u64> {
+        let func = self.func?;
+        // SAFETY: guaranteed by the caller.
+        Some(unsafe { func(args[0], args[1], args[2], args[3], args[4], ctx) })
+    }
+}
+
+/// Look up a helper by the ID used in call instructions.
+pub fn lookup(id: i32) -> Option<&'static HelperDef> {
+    HelperId::from_raw(id).map(HelperId::def)
+}
+
+/// Look up a helper by symbol name.
+pub fn lookup_by_name(name: &str) -> Option<&'static HelperDef> {
+    HelperId::ALL
+        .iter()
+        .map(|id| id.def())
+        .find(|def| def.name == name)
+}
+
+/// Declare the helper table.
+///
+/// Generates [`HelperId`] with one variant per row, together with the
+/// matching [`HelperDef`] for each variant.
+macro_rules! helpers {
+    ($(
+        $(#[doc = $doc:literal])*
+        $variant:ident = $id:literal {
+            name: $name:literal,
+            args: [$($arg:ident),* $(,)?],
+            ret: $ret:ident,
+            embedded: $embedded:literal,
+            func: $func:expr $(,)?
+        }
+    )*) => {
+        /// Helper function identifier.
+        ///
+        /// These IDs match the standard BPF helper IDs where applicable,
+        /// with rkBPF extensions starting at 1000.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(i32)]
+        pub enum HelperId {
+            $($(#[doc = $doc])* $variant = $id,)*
+        }
+
+        impl HelperId {
+            /// Every helper, in ID order.
+            pub const ALL: &'static [HelperId] = &[$(Self::$variant),*];
+
+            /// Try to convert from raw helper ID.
+            pub const fn from_raw(id: i32) -> Option<Self> {
+                match id {
+                    $($id => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Registry entry for this helper.
+            pub const fn def(self) -> &'static HelperDef {
+                match self {
+                    $(Self::$variant => &HelperDef {
+                        id: Self::$variant,
+                        name: $name,
+                        args: &[$(ArgType::$arg),*],
+                        ret: ReturnType::$ret,
+                        embedded: $embedded,
+                        func: $func,
+                    },)*
+                }
+            }
+        }
+    };
+}
+
+helpers! {
+    // ===== Core Helpers (1-12) =====
+    /// Get current time in nanoseconds
+    KtimeGetNs = 1 {
+        name: "bpf_ktime_get_ns",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::ktime_get_ns),
+    }
+    /// Print debug message (debug builds only)
+    TracePrintk = 2 {
+        name: "bpf_trace_printk",
+        args: [PtrToMem, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::trace_printk),
+    }
+    /// Look up element in map
+    MapLookupElem = 3 {
+        name: "bpf_map_lookup_elem",
+        args: [PtrToMap, PtrToMapKey],
+        ret: PtrToMapValueOrNull,
+        embedded: true,
+        func: Some(abi::map_lookup_elem),
+    }
+    /// Update element in map
+    MapUpdateElem = 4 {
+        name: "bpf_map_update_elem",
+        args: [PtrToMap, PtrToMapKey, PtrToMapValue, Const],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::map_update_elem),
+    }
+    /// Delete element from map
+    MapDeleteElem = 5 {
+        name: "bpf_map_delete_elem",
+        args: [PtrToMap, PtrToMapKey],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::map_delete_elem),
+    }
+    /// Output to ring buffer (reserve + submit)
+    RingbufOutput = 6 {
+        name: "bpf_ringbuf_output",
+        args: [PtrToMap, PtrToMem, MemSize, Const],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::ringbuf_output),
+    }
+    /// Get pseudo-random u32
+    GetPrandomU32 = 7 {
+        name: "bpf_get_prandom_u32",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Get current CPU ID
+    GetSmpProcessorId = 8 {
+        name: "bpf_get_smp_processor_id",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::get_smp_processor_id),
+    }
+    /// Continue with a program from a program array
+    TailCall = 12 {
+        name: "bpf_tail_call",
+        args: [PtrToCtx, PtrToMap, Scalar],
+        ret: Integer,
+        embedded: true,
+        func: Some(tail_call_fallback),
+    }
+
+    // ===== Context Helpers (13-19) =====
+    /// Get the interrupt latency recorded in the context
+    GetInterruptLatencyNs = 13 {
+        name: "bpf_get_interrupt_latency_ns",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::get_interrupt_latency_ns),
+    }
+    /// Get the boot time recorded in the context
+    GetBootTimeMs = 15 {
+        name: "bpf_get_boot_time_ms",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::get_boot_time_ms),
+    }
+    /// Get the kernel heap size recorded in the context
+    GetKernelHeapKb = 16 {
+        name: "bpf_get_kernel_heap_kb",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::get_kernel_heap_kb),
+    }
+    /// Get the kernel image size recorded in the context
+    GetKernelImageMb = 17 {
+        name: "bpf_get_kernel_image_mb",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::get_kernel_image_mb),
+    }
+
+    // ===== Memory Helpers (20-30) =====
+    /// Read from arbitrary memory (with safety checks)
+    ProbeRead = 20 {
+        name: "bpf_probe_read",
+        args: [PtrToStack, MemSize, AnyPtr],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+
+    // ===== Process Helpers (30-40) =====
+    /// Get current PID and TGID
+    GetCurrentPidTgid = 30 {
+        name: "bpf_get_current_pid_tgid",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Get current UID and GID
+    GetCurrentUidGid = 31 {
+        name: "bpf_get_current_uid_gid",
+        args: [],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Get current process command name
+    GetCurrentComm = 32 {
+        name: "bpf_get_current_comm",
+        args: [PtrToStack, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+
+    // ===== Ring Buffer Helpers (Advanced) (40-50) =====
+    /// Reserve space in ring buffer
+    RingbufReserve = 40 {
+        name: "bpf_ringbuf_reserve",
+        args: [PtrToRingbuf, Scalar, Const],
+        ret: PtrToAllocMemOrNull,
+        // Dynamic allocation
+        embedded: false,
+        func: None,
+    }
+    /// Submit reserved ring buffer entry
+    RingbufSubmit = 41 {
+        name: "bpf_ringbuf_submit",
+        args: [PtrToRingbufSample, Const],
+        ret: Void,
+        embedded: true,
+        func: None,
+    }
+    /// Discard reserved ring buffer entry
+    RingbufDiscard = 42 {
+        name: "bpf_ringbuf_discard",
+        args: [PtrToRingbufSample, Const],
+        ret: Void,
+        embedded: true,
+        func: None,
+    }
+
+    // ===== Loop Helpers (181) =====
+    /// Call a BPF function once per iteration, up to a bounded count
+    Loop = 181 {
+        name: "bpf_loop",
+        args: [Scalar, PtrToFunc, PtrToMemOrNull, Const],
+        ret: Integer,
+        embedded: true,
+        func: Some(loop_fallback),
+    }
+
+    // ===== rkBPF Robotics Helpers (1000+) =====
+    /// Emergency stop all motors
+    MotorEmergencyStop = 1000 {
+        name: "bpf_motor_emergency_stop",
+        args: [Scalar],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::motor_emergency_stop),
+    }
+    /// Push value to time-series map
+    TimeseriesPush = 1001 {
+        name: "bpf_timeseries_push",
+        args: [PtrToMap, PtrToMapKey, PtrToMapValue],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::timeseries_push),
+    }
+    /// Get last timestamp from sensor
+    SensorLastTimestamp = 1002 {
+        name: "bpf_sensor_last_timestamp",
+        args: [Scalar],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Set GPIO pin state
+    GpioSet = 1003 {
+        name: "bpf_gpio_set",
+        args: [Scalar, Scalar],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::gpio_set),
+    }
+    /// Read GPIO pin state
+    GpioGet = 1004 {
+        name: "bpf_gpio_get",
+        args: [Scalar],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::gpio_get),
+    }
+    /// Write to PWM channel
+    PwmWrite = 1005 {
+        name: "bpf_pwm_write",
+        args: [Scalar, Scalar, Scalar],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::pwm_write),
+    }
+    /// Read IIO sensor value
+    IioRead = 1006 {
+        name: "bpf_iio_read",
+        args: [Scalar, PtrToStack, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Send CAN message
+    CanSend = 1007 {
+        name: "bpf_can_send",
+        args: [Scalar, PtrToMem, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: None,
+    }
+    /// Aggregate the last N entries of a time-series map
+    TimeseriesQuery = 1008 {
+        name: "bpf_timeseries_query",
+        args: [PtrToMap, Scalar, PtrToStack, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::timeseries_query),
+    }
+    /// Aggregate the entries of a time-series map within a time window
+    TimeseriesQueryWindow = 1009 {
+        name: "bpf_timeseries_query_window",
+        args: [PtrToMap, Scalar, Scalar, PtrToStack, MemSize],
+        ret: Integer,
+        embedded: true,
+        func: Some(abi::timeseries_query_window),
+    }
+}
+
+impl HelperId {
+    /// Look up a helper ID by symbol name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        lookup_by_name(name).map(|def| def.id)
+    }
+
+    /// Get the helper name for error messages.
+    pub const fn name(&self) -> &'static str {
+        self.def().name
+    }
+
+    /// Check if this helper is available in embedded profile.
+    #[cfg(feature = "embedded-profile")]
+    pub const fn available_in_embedded(&self) -> bool {
+        self.def().embedded
+    }
+
+    /// Check if this helper is available in cloud profile.
+    #[cfg(feature = "cloud-profile")]
+    pub const fn available_in_cloud(&self) -> bool {
+        // All helpers available in cloud profile
+        true
+    }
+
+    /// Check if helper is available in the current profile.
+    pub const fn is_available(&self) -> bool {
+        self.def().is_available()
+    }
+}
+
+/// Fallback for `bpf_tail_call`.
+///
+/// A tail call replaces the running program, so executors that support it
+/// handle the call themselves. Anywhere else it fails like a call to an
+/// empty slot.
+unsafe extern "C" fn tail_call_fallback(
+    _ctx: u64,
+    _map_id: u64,
+    _index: u64,
+    _: u64,
+    _: u64,
+    _: *const BpfContext,
+) -> u64 {
+    crate::execution::TAIL_CALL_FAILED
+}
+
+/// Value of R0 after a `bpf_loop` that could not run (`-EINVAL`).
+pub(crate) const LOOP_FAILED: u64 = -22i64 as u64;
+
+/// Value of R0 after a `bpf_loop` asked for more iterations than the
+/// profile's `MAX_LOOP_ITERATIONS` (`-E2BIG`).
+pub(crate) const LOOP_TOO_LONG: u64 = -7i64 as u64;
+
+/// Fallback for `bpf_loop`.
+///
+/// The callback is a function of the running program, so only executors
+/// that can call back into it handle the helper. Anywhere else it fails
+/// without running an iteration.
+unsafe extern "C" fn loop_fallback(
+    _nr_loops: u64,
+    _callback: u64,
+    _ctx: u64,
+    _flags: u64,
+    _: u64,
+    _: *const BpfContext,
+) -> u64 {
+    LOOP_FAILED
+}
+
+/// Adapters from the [`HelperFn`] ABI to the kernel's helper functions.
+///
+/// Arguments are narrowed from raw register values to the C parameter
+/// types; the verifier has already checked them against the signature.
+mod abi {
+    use crate::execution::BpfContext;
+
+    // SAFETY: These functions are defined in the kernel and linked into the final binary.
+    // They follow the C calling convention.
+    // In host-based tests, these symbols are provided by `execution::helpers_stub`.
+    unsafe extern "C" {
+        fn bpf_ktime_get_ns() -> u64;
+        fn bpf_get_smp_processor_id() -> u32;
+        fn bpf_get_interrupt_latency_ns(ctx: *const BpfContext) -> u64;
+        fn bpf_get_boot_time_ms(ctx: *const BpfContext) -> u64;
+        fn bpf_get_kernel_heap_kb(ctx: *const BpfContext) -> u64;
+        fn bpf_get_kernel_image_mb(ctx: *const BpfContext) -> u64;
+        fn bpf_trace_printk(fmt: *const u8, size: u32) -> i32;
+        fn bpf_map_lookup_elem(map_id: u32, key: *const u8) -> *mut u8;
+        fn bpf_map_update_elem(map_id: u32, key: *const u8, value: *const u8, flags: u64) -> i32;
+        fn bpf_map_delete_elem(map_id: u32, key: *const u8) -> i32;
+        fn bpf_ringbuf_output(map_id: u32, data: *const u8, size: u64, flags: u64) -> i64;
+        fn bpf_timeseries_push(map_id: u32, key: *const u8, value: *const u8) -> i64;
+        fn bpf_timeseries_query(map_id: u32, n: u64, out: *mut u8, size: u32) -> i64;
+        fn bpf_timeseries_query_window(
+            map_id: u32,
+            start_ns: u64,
+            end_ns: u64,
+            out: *mut u8,
+            size: u32,
+        ) -> i64;
+        // Robotics helpers
+        fn bpf_gpio_read(pin: u32) -> i64;
+        fn bpf_gpio_write(pin: u32, value: u32) -> i64;
+        fn bpf_motor_emergency_stop(reason: u32) -> i64;
+        fn bpf_pwm_write(pwm_id: u32, channel: u32, duty: u32) -> i64;
+    }
+
+    /// Define an adapter with the uniform helper signature.
+    macro_rules! adapter {
+        ($name:ident($r1:pat, $r2:pat, $r3:pat, $r4:pat, $r5:pat, $ctx:pat) => $body:expr) => {
+            pub(super) unsafe extern "C" fn $name(
+                $r1: u64,
+                $r2: u64,
+                $r3: u64,
+                $r4: u64,
+                $r5: u64,
+                $ctx: *const BpfContext,
+            ) -> u64 {
+                // SAFETY: the verifier checked the arguments against the
+                // helper signature.
+                unsafe { $body }
+            }
+        };
+    }
+
+    adapter!(ktime_get_ns(_, _, _, _, _, _) => bpf_ktime_get_ns());
+    adapter!(get_smp_processor_id(_, _, _, _, _, _) => bpf_get_smp_processor_id() as u64);
+    adapter!(get_interrupt_latency_ns(_, _, _, _, _, ctx) => bpf_get_interrupt_latency_ns(ctx));
+    adapter!(get_boot_time_ms(_, _, _, _, _, ctx) => bpf_get_boot_time_ms(ctx));
+    adapter!(get_kernel_heap_kb(_, _, _, _, _, ctx) => bpf_get_kernel_heap_kb(ctx));
+    adapter!(get_kernel_image_mb(_, _, _, _, _, ctx) => bpf_get_kernel_image_mb(ctx));
+    adapter!(trace_printk(fmt, size, _, _, _, _) => {
+        bpf_trace_printk(fmt as *const u8, size as u32) as u64
+    });
+    adapter!(map_lookup_elem(map_id, key, _, _, _, _) => {
+        bpf_map_lookup_elem(map_id as u32, key as *const u8) as u64
+    });
+    adapter!(map_update_elem(map_id, key, value, flags, _, _) => {
+        bpf_map_update_elem(map_id as u32, key as *const u8, value as *const u8, flags) as u64
+    });
+    adapter!(map_delete_elem(map_id, key, _, _, _, _) => {
+        bpf_map_delete_elem(map_id as u32, key as *const u8) as u64
+    });
+    adapter!(ringbuf_output(map_id, data, size, flags, _, _) => {
+        bpf_ringbuf_output(map_id as u32, data as *const u8, size, flags) as u64
+    });
+    adapter!(timeseries_push(map_id, key, value, _, _, _) => {
+        bpf_timeseries_push(map_id as u32, key as *const u8, value as *const u8) as u64
+    });
+    adapter!(timeseries_query(map_id, n, out, size, _, _) => {
+        bpf_timeseries_query(map_id as u32, n, out as *mut u8, size as u32) as u64
+    });
+    adapter!(timeseries_query_window(map_id, start_ns, end_ns, out, size, _) => {
+        bpf_timeseries_query_window(map_id as u32, start_ns, end_ns, out as *mut u8, size as u32)
+            as u64
+    });
+    adapter!(motor_emergency_stop(reason, _, _, _, _, _) => {
+        bpf_motor_emergency_stop(reason as u32) as u64
+    });
+    adapter!(gpio_set(pin, value, _, _, _, _) => bpf_gpio_write(pin as u32, value as u32) as u64);
+    adapter!(gpio_get(pin, _, _, _, _, _) => bpf_gpio_read(pin as u32) as u64);
+    adapter!(pwm_write(pwm_id, channel, duty, _, _, _) => {
+        bpf_pwm_write(pwm_id as u32, channel as u32, duty as u32) as u64
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_consistent() {
+        let mut prev = i32::MIN;
+        for &id in HelperId::ALL {
+            let def = id.def();
+            assert_eq!(def.id, id);
+            assert_eq!(HelperId::from_raw(id as i32), Some(id));
+            assert_eq!(lookup_by_name(def.name).map(|d| d.id), Some(id));
+            assert!(def.args.len() <= 5);
+            // Sorted and unique
+            assert!(id as i32 > prev);
+            prev = id as i32;
+        }
+    }
+
+    #[test]
+    fn lookup_by_id_and_name() {
+        assert_eq!(lookup(3).map(|d| d.name), Some("bpf_map_lookup_elem"));
+        assert_eq!(
+            HelperId::from_name("bpf_ringbuf_output"),
+            Some(HelperId::RingbufOutput)
+        );
+        assert_eq!(
+            HelperId::from_name("bpf_get_interrupt_latency_ns"),
+            Some(HelperId::GetInterruptLatencyNs)
+        );
+        assert!(lookup(9999).is_none());
+        assert!(lookup_by_name("unknown_helper").is_none());
+    }
+
+    #[test]
+    fn call_passes_context() {
+        let mut ctx = BpfContext::empty();
+        ctx.boot_time_ms = 1234;
+
+        let def = HelperId::GetBootTimeMs.def();
+        assert_eq!(unsafe { def.call([0; 5], &ctx) }, Some(1234));
+
+        // Declared but not provided by this kernel
+        assert_eq!(
+            unsafe { HelperId::GetPrandomU32.def().call([0; 5], &ctx) },
+            None
+        );
+    }
+}
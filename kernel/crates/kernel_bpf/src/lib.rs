@@ -38,6 +38,7 @@
 //! - [`bytecode`] - BPF instruction set, registers, and program representation
 //! - [`verifier`] - Static safety verification with profile constraints
 //! - [`execution`] - Program execution engines (interpreter, JIT)
+//! - [`helpers`] - Registry of helper functions callable from programs
 //! - [`maps`] - BPF map implementations for data storage
 //! - [`scheduler`] - Profile-aware program scheduling
 //!
@@ -102,6 +103,7 @@ compile_error!(
 pub mod attach;
 pub mod bytecode;
 pub mod execution;
+pub mod helpers;
 pub mod loader;
 pub mod maps;
 pub mod profile;
@@ -0,0 +1,786 @@
+//! BTF Type Information
+//!
+//! Parses the `.BTF` section (the types of a program's maps and globals)
+//! and the line info of `.BTF.ext` (which source line each instruction was
+//! compiled from).
+//!
+//! Only what the loader needs is kept: type sizes and layouts, to check
+//! BTF-defined maps against their declared key and value types, and line
+//! info, so verifier errors can point to the source. The raw blob is kept
+//! as well, for handing to userspace tools that decode values by type.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use super::error::{LoadError, LoadResult};
+
+/// Magic number at the start of `.BTF` and `.BTF.ext`.
+const BTF_MAGIC: u16 = 0xeb9f;
+/// Size of the `.BTF` header.
+const BTF_HEADER_SIZE: usize = 24;
+/// Size of the `.BTF.ext` header up to the line info.
+const BTF_EXT_HEADER_SIZE: usize = 24;
+/// Size of a line info record.
+const LINE_INFO_SIZE: usize = 16;
+
+/// Typedefs and modifiers followed before giving up, guarding against
+/// cycles in malformed input.
+const MAX_RESOLVE_DEPTH: usize = 32;
+
+// BTF kinds
+const BTF_KIND_INT: u32 = 1;
+const BTF_KIND_PTR: u32 = 2;
+const BTF_KIND_ARRAY: u32 = 3;
+const BTF_KIND_STRUCT: u32 = 4;
+const BTF_KIND_UNION: u32 = 5;
+const BTF_KIND_ENUM: u32 = 6;
+const BTF_KIND_FWD: u32 = 7;
+const BTF_KIND_TYPEDEF: u32 = 8;
+const BTF_KIND_VOLATILE: u32 = 9;
+const BTF_KIND_CONST: u32 = 10;
+const BTF_KIND_RESTRICT: u32 = 11;
+const BTF_KIND_FUNC: u32 = 12;
+const BTF_KIND_FUNC_PROTO: u32 = 13;
+const BTF_KIND_VAR: u32 = 14;
+const BTF_KIND_DATASEC: u32 = 15;
+const BTF_KIND_FLOAT: u32 = 16;
+const BTF_KIND_DECL_TAG: u32 = 17;
+const BTF_KIND_TYPE_TAG: u32 = 18;
+const BTF_KIND_ENUM64: u32 = 19;
+
+/// A BTF type.
+///
+/// Names are offsets into the string section, see [`Btf::name`]; type
+/// references are type IDs, with 0 meaning `void`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BtfType {
+    /// Integer of `size` bytes
+    Int {
+        /// Name offset
+        name: u32,
+        /// Size in bytes
+        size: u32,
+        /// `BTF_INT_SIGNED`, `BTF_INT_CHAR` or `BTF_INT_BOOL` bits
+        encoding: u8,
+    },
+    /// Pointer
+    Ptr {
+        /// Pointee
+        target: u32,
+    },
+    /// Array of `len` elements
+    Array {
+        /// Element type
+        elem: u32,
+        /// Number of elements
+        len: u32,
+    },
+    /// Struct or union
+    Struct {
+        /// Name offset, 0 if anonymous
+        name: u32,
+        /// Size in bytes
+        size: u32,
+        /// Whether this is a union
+        union: bool,
+        /// Members in declaration order
+        members: Vec<BtfMember>,
+    },
+    /// Enum (32 or 64-bit)
+    Enum {
+        /// Name offset
+        name: u32,
+        /// Size in bytes
+        size: u32,
+    },
+    /// Forward declaration
+    Fwd {
+        /// Name offset
+        name: u32,
+    },
+    /// Typedef
+    Typedef {
+        /// Name offset
+        name: u32,
+        /// Aliased type
+        target: u32,
+    },
+    /// `volatile`, `const`, `restrict` or a type tag
+    Modifier {
+        /// Modified type
+        target: u32,
+    },
+    /// Function
+    Func {
+        /// Name offset
+        name: u32,
+        /// Function prototype
+        proto: u32,
+    },
+    /// Function prototype
+    FuncProto {
+        /// Return type
+        ret: u32,
+    },
+    /// Global variable
+    Var {
+        /// Name offset
+        name: u32,
+        /// Variable type
+        target: u32,
+    },
+    /// Section of variables, e.g. `.maps` or `.rodata`
+    DataSec {
+        /// Name offset
+        name: u32,
+        /// Size in bytes
+        size: u32,
+        /// Variables in the section
+        vars: Vec<BtfVarSecInfo>,
+    },
+    /// Floating point number
+    Float {
+        /// Name offset
+        name: u32,
+        /// Size in bytes
+        size: u32,
+    },
+    /// Declaration tag
+    DeclTag {
+        /// Tagged type
+        target: u32,
+    },
+}
+
+/// A member of a struct or union.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BtfMember {
+    /// Name offset
+    pub name: u32,
+    /// Member type
+    pub ty: u32,
+    /// Offset from the start of the struct in bits
+    pub bit_offset: u32,
+    /// Size of a bitfield in bits, 0 for other members
+    pub bitfield_size: u32,
+}
+
+/// A variable in a data section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BtfVarSecInfo {
+    /// The variable (a [`BtfType::Var`])
+    pub ty: u32,
+    /// Offset in the section
+    pub offset: u32,
+    /// Size in bytes
+    pub size: u32,
+}
+
+/// Parsed `.BTF` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Btf {
+    /// The section as loaded
+    data: Vec<u8>,
+    /// Types, the first having ID 1
+    types: Vec<BtfType>,
+    /// String section within `data`
+    strings: Range<usize>,
+}
+
+impl Btf {
+    /// Parse a `.BTF` section.
+    pub fn parse(data: &[u8]) -> LoadResult<Self> {
+        if data.len() < BTF_HEADER_SIZE || read_u16(data, 0) != Some(BTF_MAGIC) || data[2] != 1 {
+            return Err(LoadError::BtfError);
+        }
+        let field = |idx: usize| read_u32(data, 4 + idx * 4).ok_or(LoadError::BtfError);
+        let hdr_len = field(0)? as usize;
+        let section = |off: u32, len: u32| {
+            let start = hdr_len
+                .checked_add(off as usize)
+                .ok_or(LoadError::BtfError)?;
+            let end = start.checked_add(len as usize).ok_or(LoadError::BtfError)?;
+            if hdr_len < BTF_HEADER_SIZE || end > data.len() {
+                return Err(LoadError::BtfError);
+            }
+            Ok(start..end)
+        };
+        let type_section = section(field(1)?, field(2)?)?;
+        let strings = section(field(3)?, field(4)?)?;
+
+        let types = parse_types(&data[type_section])?;
+        let btf = Self {
+            data: data.to_vec(),
+            types,
+            strings,
+        };
+        btf.validate()?;
+        Ok(btf)
+    }
+
+    /// Check that type references and names are in range.
+    fn validate(&self) -> LoadResult<()> {
+        let type_ok = |id: u32| id as usize <= self.types.len();
+        let name_ok = |off: u32| self.name(off).is_some();
+        let valid = self.types.iter().all(|ty| match ty {
+            BtfType::Int { name, .. }
+            | BtfType::Enum { name, .. }
+            | BtfType::Fwd { name }
+            | BtfType::Float { name, .. } => name_ok(*name),
+            BtfType::Ptr { target }
+            | BtfType::Modifier { target }
+            | BtfType::DeclTag { target } => type_ok(*target),
+            BtfType::FuncProto { ret } => type_ok(*ret),
+            BtfType::Array { elem, .. } => type_ok(*elem),
+            BtfType::Typedef { name, target } | BtfType::Var { name, target } => {
+                name_ok(*name) && type_ok(*target)
+            }
+            BtfType::Func { name, proto } => name_ok(*name) && type_ok(*proto),
+            BtfType::Struct { name, members, .. } => {
+                name_ok(*name) && members.iter().all(|m| name_ok(m.name) && type_ok(m.ty))
+            }
+            BtfType::DataSec { name, vars, .. } => {
+                name_ok(*name) && vars.iter().all(|v| type_ok(v.ty))
+            }
+        });
+        if valid {
+            Ok(())
+        } else {
+            Err(LoadError::BtfError)
+        }
+    }
+
+    /// The section as loaded, for handing to userspace.
+    pub fn raw(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Number of types, not counting `void`.
+    pub fn type_count(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Get a type by ID.
+    pub fn type_by_id(&self, id: u32) -> Option<&BtfType> {
+        self.types.get((id as usize).checked_sub(1)?)
+    }
+
+    /// Get the string at `offset` in the string section.
+    pub fn name(&self, offset: u32) -> Option<&str> {
+        let strings = &self.data[self.strings.clone()];
+        let rest = strings.get(offset as usize..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        core::str::from_utf8(&rest[..end]).ok()
+    }
+
+    /// Get the name of a type, empty for anonymous types.
+    pub fn type_name(&self, id: u32) -> Option<&str> {
+        let name = match self.type_by_id(id)? {
+            BtfType::Int { name, .. }
+            | BtfType::Struct { name, .. }
+            | BtfType::Enum { name, .. }
+            | BtfType::Fwd { name }
+            | BtfType::Typedef { name, .. }
+            | BtfType::Func { name, .. }
+            | BtfType::Var { name, .. }
+            | BtfType::DataSec { name, .. }
+            | BtfType::Float { name, .. } => *name,
+            _ => 0,
+        };
+        self.name(name)
+    }
+
+    /// Follow typedefs and modifiers to the underlying type.
+    pub fn resolve(&self, mut id: u32) -> Option<(u32, &BtfType)> {
+        for _ in 0..MAX_RESOLVE_DEPTH {
+            match self.type_by_id(id)? {
+                BtfType::Typedef { target, .. } | BtfType::Modifier { target } => id = *target,
+                ty => return Some((id, ty)),
+            }
+        }
+        None
+    }
+
+    /// Size of a type in bytes; `None` for `void`, functions and
+    /// incomplete types.
+    pub fn size_of(&self, id: u32) -> Option<u32> {
+        self.size_of_at(id, 0)
+    }
+
+    fn size_of_at(&self, id: u32, depth: usize) -> Option<u32> {
+        if depth >= MAX_RESOLVE_DEPTH {
+            return None;
+        }
+        match self.resolve(id)?.1 {
+            BtfType::Int { size, .. }
+            | BtfType::Struct { size, .. }
+            | BtfType::Enum { size, .. }
+            | BtfType::DataSec { size, .. }
+            | BtfType::Float { size, .. } => Some(*size),
+            BtfType::Ptr { .. } => Some(8),
+            BtfType::Array { elem, len } => self.size_of_at(*elem, depth + 1)?.checked_mul(*len),
+            BtfType::Var { target, .. } => self.size_of_at(*target, depth + 1),
+            _ => None,
+        }
+    }
+
+    /// Find a type of the given name for which `pred` holds.
+    pub fn find(&self, name: &str, pred: impl Fn(&BtfType) -> bool) -> Option<u32> {
+        (1..=self.types.len() as u32)
+            .find(|&id| pred(self.type_by_id(id).unwrap()) && self.type_name(id) == Some(name))
+    }
+
+    /// Find the data section named `name`, e.g. `.maps`.
+    pub fn datasec(&self, name: &str) -> Option<(u32, &[BtfVarSecInfo])> {
+        let id = self.find(name, |ty| matches!(ty, BtfType::DataSec { .. }))?;
+        match self.type_by_id(id)? {
+            BtfType::DataSec { vars, .. } => Some((id, vars)),
+            _ => None,
+        }
+    }
+
+    /// Members of the struct or union `id`, after typedefs and modifiers.
+    pub fn members(&self, id: u32) -> Option<&[BtfMember]> {
+        match self.resolve(id)?.1 {
+            BtfType::Struct { members, .. } => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the type section.
+fn parse_types(data: &[u8]) -> LoadResult<Vec<BtfType>> {
+    let mut types = Vec::new();
+    let mut pos = 0;
+    let word = |pos: usize| read_u32(data, pos).ok_or(LoadError::BtfError);
+
+    while pos < data.len() {
+        let name = word(pos)?;
+        let info = word(pos + 4)?;
+        let size_type = word(pos + 8)?;
+        pos += 12;
+
+        let vlen = (info & 0xffff) as usize;
+        let kind = (info >> 24) & 0x1f;
+        let kind_flag = info >> 31 != 0;
+
+        let ty = match kind {
+            BTF_KIND_INT => {
+                let encoding = (word(pos)? >> 24) as u8 & 0x0f;
+                pos += 4;
+                BtfType::Int {
+                    name,
+                    size: size_type,
+                    encoding,
+                }
+            }
+            BTF_KIND_PTR => BtfType::Ptr { target: size_type },
+            BTF_KIND_ARRAY => {
+                let ty = BtfType::Array {
+                    elem: word(pos)?,
+                    len: word(pos + 8)?,
+                };
+                pos += 12;
+                ty
+            }
+            BTF_KIND_STRUCT | BTF_KIND_UNION => {
+                let mut members = Vec::with_capacity(vlen);
+                for _ in 0..vlen {
+                    let offset = word(pos + 8)?;
+                    let (bit_offset, bitfield_size) = if kind_flag {
+                        (offset & 0x00ff_ffff, offset >> 24)
+                    } else {
+                        (offset, 0)
+                    };
+                    members.push(BtfMember {
+                        name: word(pos)?,
+                        ty: word(pos + 4)?,
+                        bit_offset,
+                        bitfield_size,
+                    });
+                    pos += 12;
+                }
+                BtfType::Struct {
+                    name,
+                    size: size_type,
+                    union: kind == BTF_KIND_UNION,
+                    members,
+                }
+            }
+            BTF_KIND_ENUM | BTF_KIND_ENUM64 => {
+                pos += vlen * if kind == BTF_KIND_ENUM { 8 } else { 12 };
+                BtfType::Enum {
+                    name,
+                    size: size_type,
+                }
+            }
+            BTF_KIND_FWD => BtfType::Fwd { name },
+            BTF_KIND_TYPEDEF => BtfType::Typedef {
+                name,
+                target: size_type,
+            },
+            BTF_KIND_VOLATILE | BTF_KIND_CONST | BTF_KIND_RESTRICT | BTF_KIND_TYPE_TAG => {
+                BtfType::Modifier { target: size_type }
+            }
+            BTF_KIND_FUNC => BtfType::Func {
+                name,
+                proto: size_type,
+            },
+            BTF_KIND_FUNC_PROTO => {
+                pos += vlen * 8;
+                BtfType::FuncProto { ret: size_type }
+            }
+            BTF_KIND_VAR => {
+                pos += 4;
+                BtfType::Var {
+                    name,
+                    target: size_type,
+                }
+            }
+            BTF_KIND_DATASEC => {
+                let mut vars = Vec::with_capacity(vlen);
+                for _ in 0..vlen {
+                    vars.push(BtfVarSecInfo {
+                        ty: word(pos)?,
+                        offset: word(pos + 4)?,
+                        size: word(pos + 8)?,
+                    });
+                    pos += 12;
+                }
+                BtfType::DataSec {
+                    name,
+                    size: size_type,
+                    vars,
+                }
+            }
+            BTF_KIND_FLOAT => BtfType::Float {
+                name,
+                size: size_type,
+            },
+            BTF_KIND_DECL_TAG => {
+                pos += 4;
+                BtfType::DeclTag { target: size_type }
+            }
+            _ => return Err(LoadError::BtfError),
+        };
+        types.push(ty);
+    }
+
+    if pos != data.len() {
+        return Err(LoadError::BtfError);
+    }
+    Ok(types)
+}
+
+/// The source line an instruction was compiled from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineInfo {
+    /// Index of the first instruction of the line
+    pub insn_idx: usize,
+    /// Source file
+    pub file: String,
+    /// Text of the source line
+    pub source: String,
+    /// Line number, from 1
+    pub line: u32,
+    /// Column, from 1; 0 if unknown
+    pub col: u32,
+}
+
+impl fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.col != 0 {
+            write!(f, ":{}", self.col)?;
+        }
+        if !self.source.is_empty() {
+            write!(f, ": {}", self.source.trim())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the line info of a `.BTF.ext` section.
+///
+/// Returns, for each program section, its line info sorted by instruction
+/// index within the section.
+pub fn parse_line_info(data: &[u8], btf: &Btf) -> LoadResult<Vec<(String, Vec<LineInfo>)>> {
+    if data.len() < 8 || read_u16(data, 0) != Some(BTF_MAGIC) || data[2] != 1 {
+        return Err(LoadError::BtfError);
+    }
+    let hdr_len = read_u32(data, 4).ok_or(LoadError::BtfError)? as usize;
+    if hdr_len < BTF_EXT_HEADER_SIZE {
+        // No line info in this object
+        return Ok(Vec::new());
+    }
+    let field = |pos: usize| read_u32(data, pos).ok_or(LoadError::BtfError);
+    let start = hdr_len
+        .checked_add(field(16)? as usize)
+        .ok_or(LoadError::BtfError)?;
+    let end = start
+        .checked_add(field(20)? as usize)
+        .ok_or(LoadError::BtfError)?;
+    let lines = data.get(start..end).ok_or(LoadError::BtfError)?;
+    if lines.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let word = |pos: usize| read_u32(lines, pos).ok_or(LoadError::BtfError);
+    let name = |off: u32| btf.name(off).map(String::from).ok_or(LoadError::BtfError);
+    let rec_size = word(0)? as usize;
+    if rec_size < LINE_INFO_SIZE {
+        return Err(LoadError::BtfError);
+    }
+
+    let mut sections = Vec::new();
+    let mut pos = 4;
+    while pos < lines.len() {
+        let section = name(word(pos)?)?;
+        let count = word(pos + 4)? as usize;
+        pos += 8;
+
+        let mut infos = Vec::with_capacity(count.min(lines.len() / rec_size));
+        for _ in 0..count {
+            let line_col = word(pos + 12)?;
+            infos.push(LineInfo {
+                insn_idx: word(pos)? as usize / 8,
+                file: name(word(pos + 4)?)?,
+                source: name(word(pos + 8)?)?,
+                line: line_col >> 10,
+                col: line_col & 0x3ff,
+            });
+            pos += rec_size;
+        }
+        infos.sort_by_key(|info| info.insn_idx);
+        sections.push((section, infos));
+    }
+
+    Ok(sections)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_ne_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        data.get(pos..pos.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+/// A line info record `(insn_off, file, source, line)` for
+/// [`BtfBuilder::line_info`].
+#[cfg(test)]
+pub(super) type LineRecord<'a> = (u32, &'a str, &'a str, u32);
+
+/// Builds `.BTF` and `.BTF.ext` sections for tests.
+#[cfg(test)]
+pub(super) struct BtfBuilder {
+    types: Vec<u8>,
+    count: u32,
+    strings: Vec<u8>,
+}
+
+#[cfg(test)]
+impl BtfBuilder {
+    pub fn new() -> Self {
+        Self {
+            types: Vec::new(),
+            count: 0,
+            strings: alloc::vec![0],
+        }
+    }
+
+    /// Add a string, returning its offset.
+    pub fn string(&mut self, s: &str) -> u32 {
+        let off = self.strings.len() as u32;
+        self.strings.extend_from_slice(s.as_bytes());
+        self.strings.push(0);
+        off
+    }
+
+    /// Add a type from its header and trailing words, returning its ID.
+    pub fn add(&mut self, name: &str, kind: u32, vlen: u32, size_type: u32, rest: &[u32]) -> u32 {
+        let name = if name.is_empty() {
+            0
+        } else {
+            self.string(name)
+        };
+        for word in [name, (kind << 24) | vlen, size_type].iter().chain(rest) {
+            self.types.extend_from_slice(&word.to_ne_bytes());
+        }
+        self.count += 1;
+        self.count
+    }
+
+    pub fn int(&mut self, name: &str, size: u32) -> u32 {
+        self.add(name, BTF_KIND_INT, 0, size, &[size * 8])
+    }
+
+    pub fn ptr(&mut self, target: u32) -> u32 {
+        self.add("", BTF_KIND_PTR, 0, target, &[])
+    }
+
+    pub fn array(&mut self, elem: u32, len: u32) -> u32 {
+        self.add("", BTF_KIND_ARRAY, 0, 0, &[elem, elem, len])
+    }
+
+    pub fn typedef(&mut self, name: &str, target: u32) -> u32 {
+        self.add(name, BTF_KIND_TYPEDEF, 0, target, &[])
+    }
+
+    /// Struct with `(name, type, byte offset)` members.
+    pub fn structure(&mut self, name: &str, size: u32, members: &[(&str, u32, u32)]) -> u32 {
+        let mut rest = Vec::new();
+        for &(member, ty, offset) in members {
+            rest.extend([self.string(member), ty, offset * 8]);
+        }
+        self.add(name, BTF_KIND_STRUCT, members.len() as u32, size, &rest)
+    }
+
+    pub fn var(&mut self, name: &str, ty: u32) -> u32 {
+        self.add(name, BTF_KIND_VAR, 0, ty, &[1])
+    }
+
+    /// Data section with `(var, offset, size)` entries.
+    pub fn datasec(&mut self, name: &str, size: u32, vars: &[(u32, u32, u32)]) -> u32 {
+        let rest: Vec<u32> = vars.iter().flat_map(|&(v, o, s)| [v, o, s]).collect();
+        self.add(name, BTF_KIND_DATASEC, vars.len() as u32, size, &rest)
+    }
+
+    /// `__uint(name, value)`, as used in BTF-defined maps.
+    pub fn uint_field(&mut self, int: u32, value: u32) -> u32 {
+        let array = self.array(int, value);
+        self.ptr(array)
+    }
+
+    /// The `.BTF` section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&BTF_MAGIC.to_ne_bytes());
+        data.extend_from_slice(&[1, 0]);
+        let header = [
+            BTF_HEADER_SIZE as u32,
+            0,
+            self.types.len() as u32,
+            self.types.len() as u32,
+            self.strings.len() as u32,
+        ];
+        for word in header {
+            data.extend_from_slice(&word.to_ne_bytes());
+        }
+        data.extend_from_slice(&self.types);
+        data.extend_from_slice(&self.strings);
+        data
+    }
+
+    /// A `.BTF.ext` section with line info `(section, [record])`; strings
+    /// are added to this builder.
+    pub fn line_info(&mut self, sections: &[(&str, &[LineRecord<'_>])]) -> Vec<u8> {
+        let mut lines = (LINE_INFO_SIZE as u32).to_ne_bytes().to_vec();
+        for &(section, infos) in sections {
+            let name = self.string(section);
+            lines.extend_from_slice(&name.to_ne_bytes());
+            lines.extend_from_slice(&(infos.len() as u32).to_ne_bytes());
+            for &(insn_off, file, source, line) in infos {
+                let record = [insn_off, self.string(file), self.string(source), line << 10];
+                for word in record {
+                    lines.extend_from_slice(&word.to_ne_bytes());
+                }
+            }
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&BTF_MAGIC.to_ne_bytes());
+        data.extend_from_slice(&[1, 0]);
+        let header = [BTF_EXT_HEADER_SIZE as u32, 0, 0, 0, lines.len() as u32];
+        for word in header {
+            data.extend_from_slice(&word.to_ne_bytes());
+        }
+        data.extend_from_slice(&lines);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_types_and_sizes() {
+        let mut b = BtfBuilder::new();
+        let u32_id = b.int("unsigned int", 4);
+        let u64_id = b.int("unsigned long long", 8);
+        let alias = b.typedef("u64", u64_id);
+        let array = b.array(u32_id, 3);
+        let event = b.structure(
+            "event",
+            24,
+            &[("ts", alias, 0), ("vals", array, 8), ("id", u32_id, 20)],
+        );
+
+        let btf = Btf::parse(&b.build()).unwrap();
+        assert_eq!(btf.type_count(), 5);
+        assert_eq!(btf.type_name(event), Some("event"));
+        assert_eq!(btf.resolve(alias).map(|(id, _)| id), Some(u64_id));
+        assert_eq!(btf.size_of(alias), Some(8));
+        assert_eq!(btf.size_of(array), Some(12));
+        assert_eq!(btf.size_of(event), Some(24));
+        assert_eq!(btf.size_of(0), None);
+
+        let members = btf.members(event).unwrap();
+        assert_eq!(members.len(), 3);
+        assert_eq!(btf.name(members[1].name), Some("vals"));
+        assert_eq!(members[2].bit_offset, 160);
+        assert_eq!(btf.raw(), &b.build()[..]);
+    }
+
+    #[test]
+    fn reject_malformed() {
+        let mut b = BtfBuilder::new();
+        b.ptr(7); // no type 7
+        assert_eq!(Btf::parse(&b.build()), Err(LoadError::BtfError));
+
+        let mut data = BtfBuilder::new().build();
+        data[0] ^= 0xff;
+        assert_eq!(Btf::parse(&data), Err(LoadError::BtfError));
+        assert_eq!(Btf::parse(&data[..8]), Err(LoadError::BtfError));
+    }
+
+    #[test]
+    fn typedef_cycle_has_no_size() {
+        let mut b = BtfBuilder::new();
+        b.typedef("a", 2);
+        b.typedef("b", 1);
+        let btf = Btf::parse(&b.build()).unwrap();
+        assert_eq!(btf.resolve(1), None);
+        assert_eq!(btf.size_of(1), None);
+    }
+
+    #[test]
+    fn parse_line_info_sections() {
+        let mut b = BtfBuilder::new();
+        b.int("int", 4);
+        let ext = b.line_info(&[(
+            "socket",
+            &[
+                (16, "prog.c", "  return *p;", 12),
+                (0, "prog.c", "int prog(void) {", 10),
+            ],
+        )]);
+        let btf = Btf::parse(&b.build()).unwrap();
+
+        let sections = parse_line_info(&ext, &btf).unwrap();
+        assert_eq!(sections.len(), 1);
+        let (section, infos) = &sections[0];
+        assert_eq!(section, "socket");
+        assert_eq!(infos[0].insn_idx, 0);
+        assert_eq!(infos[1].insn_idx, 2);
+        assert_eq!(infos[1].line, 12);
+        assert_eq!(alloc::format!("{}", infos[1]), "prog.c:12: return *p;");
+    }
+}
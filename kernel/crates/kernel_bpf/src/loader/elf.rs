@@ -201,6 +201,16 @@ impl<'a> ElfParser<'a> {
         // Find string tables
         parser.find_string_tables()?;
 
+        // BTF sections are only told apart from data by name
+        for idx in 0..parser.sections.len() {
+            let section_type = match parser.section_name(&parser.sections[idx]).as_deref() {
+                Ok(".BTF") => SectionType::Btf,
+                Ok(".BTF.ext") => SectionType::BtfExt,
+                _ => continue,
+            };
+            parser.sections[idx].section_type = section_type;
+        }
+
         Ok(parser)
     }
 
@@ -1,5 +1,8 @@
 //! Loader Error Types
 
+extern crate alloc;
+
+use alloc::string::String;
 use core::fmt;
 
 /// Errors that can occur during BPF object loading.
@@ -47,6 +50,8 @@ pub enum LoadError {
     InvalidLicense,
     /// BTF parsing error
     BtfError,
+    /// A BTF-defined map's key or value size doesn't match its type
+    BtfMapMismatch(String),
 }
 
 impl fmt::Display for LoadError {
@@ -73,6 +78,13 @@ impl fmt::Display for LoadError {
             Self::LicenseNotFound => write!(f, "license not found"),
             Self::InvalidLicense => write!(f, "invalid license string"),
             Self::BtfError => write!(f, "BTF parsing error"),
+            Self::BtfMapMismatch(map) => {
+                write!(
+                    f,
+                    "map {}: key or value size doesn't match its BTF type",
+                    map
+                )
+            }
         }
     }
 }
@@ -22,7 +22,12 @@
 //! - ELF64 parsing for BPF objects
 //! - Multiple programs per object file
 //! - Map definitions and relocations
-//! - BTF parsing (optional, cloud profile only)
+//! - Global data: `.data`, `.rodata` and `.bss` become single-entry array
+//!   maps, and references to globals are relocated into their values
+//! - BPF-to-BPF calls, with the called functions of `.text` appended to
+//!   each program that uses them
+//! - BTF type info: BTF-defined maps, checked against their key and value
+//!   types, and line info for pointing verifier errors at the source
 //! - License extraction
 //!
 //! # Usage
@@ -42,22 +47,26 @@
 
 extern crate alloc;
 
+mod btf;
 mod elf;
 mod error;
 mod object;
 mod reloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
 
+pub use btf::{Btf, BtfMember, BtfType, BtfVarSecInfo, LineInfo};
 pub use elf::{ElfParser, SectionType};
 pub use error::{LoadError, LoadResult};
 pub use object::{BpfObject, LoadedMap, LoadedProgram};
+use reloc::DataSection;
 pub use reloc::Relocator;
 
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::program::BpfProgType;
-use crate::maps::MapDef;
+use crate::maps::{MapDef, MapType};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
 /// BPF program loader.
@@ -116,19 +125,68 @@ impl<P: PhysicalProfile> BpfLoader<P> {
         // Extract license
         let license = parser.find_license()?;
 
-        // Extract maps
-        let maps = self.load_maps(&mut parser)?;
+        // Extract type and line info
+        let btf = Self::load_btf(&parser)?;
+        let line_info = Self::load_line_info(&parser, btf.as_ref())?;
+
+        // Extract maps, then the maps holding global data
+        let mut maps = self.load_maps(&mut parser, btf.as_ref())?;
+        let data_sections = self.load_global_data(&parser, &mut maps, btf.as_ref())?;
 
         // Extract programs
-        let programs = self.load_programs(&mut parser, &maps)?;
+        let programs = self.load_programs(&mut parser, &maps, &data_sections, &line_info)?;
 
-        Ok(BpfObject::new(programs, maps, license))
+        let obj = BpfObject::new(programs, maps, license);
+        Ok(match btf {
+            Some(btf) => obj.with_btf(btf),
+            None => obj,
+        })
+    }
+
+    /// Parse the `.BTF` section, if there is one.
+    fn load_btf(parser: &ElfParser) -> LoadResult<Option<Btf>> {
+        let sections = parser.sections()?;
+        match sections.iter().find(|s| s.section_type == SectionType::Btf) {
+            Some(section) => Btf::parse(parser.section_data(section)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the line info of `.BTF.ext`, per program section.
+    fn load_line_info(
+        parser: &ElfParser,
+        btf: Option<&Btf>,
+    ) -> LoadResult<Vec<(String, Vec<LineInfo>)>> {
+        let sections = parser.sections()?;
+        let ext = sections
+            .iter()
+            .find(|s| s.section_type == SectionType::BtfExt);
+        match (ext, btf) {
+            (Some(section), Some(btf)) => btf::parse_line_info(parser.section_data(section)?, btf),
+            _ => Ok(Vec::new()),
+        }
     }
 
     /// Load map definitions from the ELF file.
-    fn load_maps(&self, parser: &mut ElfParser) -> LoadResult<Vec<LoadedMap>> {
+    ///
+    /// With BTF, `.maps` holds a variable for each map, and the map is
+    /// described by the variable's type; otherwise it is an array of
+    /// fixed-size definitions.
+    fn load_maps(&self, parser: &mut ElfParser, btf: Option<&Btf>) -> LoadResult<Vec<LoadedMap>> {
         let mut maps = Vec::new();
 
+        if let Some(btf) = btf
+            && let Some((_, vars)) = btf.datasec(".maps")
+        {
+            for var in vars {
+                if maps.len() >= self.max_maps {
+                    return Err(LoadError::TooManyMaps);
+                }
+                maps.push(Self::load_btf_map(btf, var)?);
+            }
+            return Ok(maps);
+        }
+
         // Look for "maps" section
         if let Some(section) = parser.find_section(".maps")? {
             let data = parser.section_data(&section)?;
@@ -143,28 +201,167 @@ impl<P: PhysicalProfile> BpfLoader<P> {
                 let map_def = MapDef::from_bytes(&data[offset..offset + MAP_DEF_SIZE])?;
                 let name = parser.section_name_at(section.name_offset + offset as u32)?;
 
-                maps.push(LoadedMap { name, def: map_def });
+                maps.push(LoadedMap::new(name, map_def));
                 offset += MAP_DEF_SIZE;
             }
         }
 
-        // Also check for BTF-defined maps
-        // TODO: BTF parsing for cloud profile
-
         Ok(maps)
     }
 
+    /// Read the definition of a BTF-defined map from its variable.
+    ///
+    /// Attributes are encoded in the types of the members: with
+    /// `__uint(max_entries, 16)` the member points to an array of 16
+    /// elements, with `__type(key, u32)` it points to the key type. Sizes
+    /// given explicitly must match the types.
+    fn load_btf_map(btf: &Btf, var: &BtfVarSecInfo) -> LoadResult<LoadedMap> {
+        let (name, ty) = match btf.type_by_id(var.ty) {
+            Some(BtfType::Var { name, target }) => (btf.name(*name), *target),
+            _ => (None, 0),
+        };
+        let name = String::from(name.ok_or(LoadError::BtfError)?);
+        let members = btf.members(ty).ok_or(LoadError::BtfError)?;
+
+        let (mut map_type, mut max_entries, mut flags) = (0, 0, 0);
+        let (mut key_size, mut value_size) = (None, None);
+        let (mut key_type, mut value_type) = (0, 0);
+        for member in members {
+            let Some((_, &BtfType::Ptr { target })) = btf.resolve(member.ty) else {
+                continue;
+            };
+            let uint = || match btf.resolve(target) {
+                Some((_, &BtfType::Array { len, .. })) => Ok(len),
+                _ => Err(LoadError::BtfError),
+            };
+            match btf.name(member.name) {
+                Some("type") => map_type = uint()?,
+                Some("max_entries") => max_entries = uint()?,
+                Some("map_flags") => flags = uint()?,
+                Some("key_size") => key_size = Some(uint()?),
+                Some("value_size") => value_size = Some(uint()?),
+                Some("key") => key_type = target,
+                Some("value") => value_type = target,
+                _ => {}
+            }
+        }
+
+        let size = |explicit: Option<u32>, ty: u32| match (explicit, ty) {
+            (explicit, 0) => Ok(explicit.unwrap_or(0)),
+            (explicit, ty) => btf
+                .size_of(ty)
+                .filter(|&size| explicit.is_none_or(|explicit| explicit == size))
+                .ok_or_else(|| LoadError::BtfMapMismatch(name.clone())),
+        };
+        let mut def = MapDef::new(
+            map_type_from_raw(map_type)?,
+            size(key_size, key_type)?,
+            size(value_size, value_type)?,
+            max_entries,
+        );
+        def.flags = flags;
+
+        Ok(LoadedMap::new(name, def).with_btf_types(key_type, value_type))
+    }
+
+    /// Create a map for each kind of global data section.
+    ///
+    /// All sections of a kind (e.g. `.rodata` and the `.rodata.str1.1` of
+    /// string literals) are laid out one after the other, each at its
+    /// alignment, in the value of one map. Kinds without data get no map.
+    ///
+    /// If the section named after the kind comes first, the map's value
+    /// has the type of its `DATASEC`.
+    fn load_global_data(
+        &self,
+        parser: &ElfParser,
+        maps: &mut Vec<LoadedMap>,
+        btf: Option<&Btf>,
+    ) -> LoadResult<Vec<DataSection>> {
+        let mut placed = Vec::new();
+
+        for kind in GLOBAL_DATA_SECTIONS {
+            let map = maps.len();
+            let mut data = Vec::new();
+            let mut typed = false;
+
+            for section in parser.sections()? {
+                let Ok(name) = parser.section_name(section) else {
+                    continue;
+                };
+                let of_kind = name
+                    .strip_prefix(kind)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'));
+                if !of_kind || !matches!(section.section_type, SectionType::Data | SectionType::Bss)
+                {
+                    continue;
+                }
+
+                let align = (section.addralign as usize).max(1);
+                data.resize(data.len().next_multiple_of(align), 0);
+                typed |= data.is_empty() && name == kind;
+                placed.push(DataSection {
+                    section: section.index,
+                    map,
+                    base: data.len(),
+                });
+
+                // The value size is a u32; `.bss` takes no room in the file
+                let end = u32::try_from(data.len() as u64 + section.size)
+                    .map_err(|_| LoadError::InvalidMapData)?;
+                if section.section_type == SectionType::Bss {
+                    data.resize(end as usize, 0);
+                } else {
+                    data.extend_from_slice(parser.section_data(section)?);
+                }
+            }
+
+            if data.is_empty() {
+                placed.retain(|p| p.map != map);
+                continue;
+            }
+            if maps.len() >= self.max_maps {
+                return Err(LoadError::TooManyMaps);
+            }
+            let mut global = LoadedMap::global_data(kind.into(), data);
+            if typed && let Some((datasec, _)) = btf.and_then(|btf| btf.datasec(kind)) {
+                global = global.with_btf_types(0, datasec);
+            }
+            maps.push(global);
+        }
+
+        Ok(placed)
+    }
+
     /// Load programs from the ELF file.
     fn load_programs(
         &self,
         parser: &mut ElfParser,
         maps: &[LoadedMap],
+        data_sections: &[DataSection],
+        line_info: &[(String, Vec<LineInfo>)],
     ) -> LoadResult<Vec<LoadedProgram<P>>> {
         let mut programs = Vec::new();
 
+        // `.text` holds the functions the programs call. It is only a
+        // program of its own in objects that have no other.
+        let sections = parser.sections()?;
+        let is_program = |section: &elf::SectionHeader| {
+            section.section_type == SectionType::Program
+                && parser
+                    .section_name(section)
+                    .is_ok_and(|name| name != ".text")
+        };
+        let text_only = !sections.iter().any(is_program);
+
         // Iterate through sections looking for program sections
-        for section in parser.sections()? {
-            if section.section_type != SectionType::Program {
+        for section in sections {
+            let wanted = if text_only {
+                section.section_type == SectionType::Program
+            } else {
+                is_program(section)
+            };
+            if !wanted {
                 continue;
             }
 
@@ -177,13 +374,14 @@ impl<P: PhysicalProfile> BpfLoader<P> {
             let data = parser.section_data(section)?;
 
             // Parse instructions
-            let insns = Self::parse_instructions(data)?;
+            let insns = parse_instructions(data)?;
 
             // Apply relocations
-            let mut relocator = Relocator::new(maps);
+            let mut relocator = Relocator::new(maps).with_data_sections(data_sections);
             let insns = relocator.relocate(&name, insns, parser)?;
+            let lines = program_line_info(parser, relocator.placement(), line_info)?;
 
-            programs.push(LoadedProgram::new(name, prog_type, insns));
+            programs.push(LoadedProgram::new(name, prog_type, insns).with_line_info(lines));
         }
 
         Ok(programs)
@@ -222,22 +420,53 @@ impl<P: PhysicalProfile> BpfLoader<P> {
             BpfProgType::SocketFilter
         }
     }
+}
 
-    /// Parse instructions from raw bytes.
-    fn parse_instructions(data: &[u8]) -> LoadResult<Vec<BpfInsn>> {
-        if !data.len().is_multiple_of(INSN_SIZE) {
-            return Err(LoadError::InvalidInstructionData);
-        }
+/// Line info of a program, from that of the sections its code came from.
+fn program_line_info(
+    parser: &ElfParser,
+    placement: &[(usize, usize, usize)],
+    line_info: &[(String, Vec<LineInfo>)],
+) -> LoadResult<Vec<LineInfo>> {
+    let mut lines = Vec::new();
+    if line_info.is_empty() {
+        return Ok(lines);
+    }
 
-        let mut insns = Vec::with_capacity(data.len() / INSN_SIZE);
+    let sections = parser.sections()?;
+    for &(section, base, len) in placement {
+        let name = parser.section_name(&sections[section])?;
+        let Some((_, infos)) = line_info.iter().find(|(s, _)| *s == name) else {
+            continue;
+        };
+        lines.extend(
+            infos
+                .iter()
+                .filter(|info| info.insn_idx < len)
+                .map(|info| LineInfo {
+                    insn_idx: base + info.insn_idx,
+                    ..info.clone()
+                }),
+        );
+    }
+    Ok(lines)
+}
 
-        for chunk in data.chunks_exact(INSN_SIZE) {
-            let insn = BpfInsn::from_bytes_load(chunk)?;
-            insns.push(insn);
-        }
+/// Parse instructions from raw bytes.
+fn parse_instructions(data: &[u8]) -> LoadResult<Vec<BpfInsn>> {
+    if !data.len().is_multiple_of(INSN_SIZE) {
+        return Err(LoadError::InvalidInstructionData);
+    }
+
+    let (chunks, _) = data.as_chunks::<INSN_SIZE>();
+    let mut insns = Vec::with_capacity(chunks.len());
 
-        Ok(insns)
+    for chunk in chunks {
+        let insn = BpfInsn::from_bytes_load(chunk)?;
+        insns.push(insn);
     }
+
+    Ok(insns)
 }
 
 impl<P: PhysicalProfile> Default for BpfLoader<P> {
@@ -246,6 +475,9 @@ impl<P: PhysicalProfile> Default for BpfLoader<P> {
     }
 }
 
+/// Global data sections, each of which becomes a map of that name.
+const GLOBAL_DATA_SECTIONS: [&str; 3] = [".data", ".rodata", ".bss"];
+
 /// Size of a map definition in bytes.
 const MAP_DEF_SIZE: usize = 20; // type + key_size + value_size + max_entries + flags
 
@@ -262,29 +494,8 @@ impl MapDef {
         let max_entries = u32::from_ne_bytes(data[12..16].try_into().unwrap());
         let flags = u32::from_ne_bytes(data[16..20].try_into().unwrap());
 
-        use crate::maps::MapType;
-        let map_type = match map_type_raw {
-            0 => MapType::Unspec,
-            1 => MapType::Hash,
-            2 => MapType::Array,
-            3 => MapType::ProgArray,
-            4 => MapType::PerfEventArray,
-            5 => MapType::PerCpuHash,
-            6 => MapType::PerCpuArray,
-            7 => MapType::StackTrace,
-            8 => MapType::CgroupArray,
-            27 => MapType::RingBuf,
-            #[cfg(feature = "cloud-profile")]
-            9 => MapType::LruHash,
-            #[cfg(feature = "cloud-profile")]
-            10 => MapType::LruPerCpuHash,
-            #[cfg(feature = "cloud-profile")]
-            11 => MapType::LpmTrie,
-            _ => return Err(LoadError::UnsupportedMapType(map_type_raw)),
-        };
-
         Ok(Self {
-            map_type,
+            map_type: map_type_from_raw(map_type_raw)?,
             key_size,
             value_size,
             max_entries,
@@ -293,6 +504,28 @@ impl MapDef {
     }
 }
 
+/// Convert a `BPF_MAP_TYPE_*` to a map type supported by this profile.
+fn map_type_from_raw(map_type_raw: u32) -> LoadResult<MapType> {
+    Ok(match map_type_raw {
+        0 => MapType::Unspec,
+        1 => MapType::Hash,
+        2 => MapType::Array,
+        3 => MapType::ProgArray,
+        4 => MapType::PerfEventArray,
+        5 => MapType::PerCpuHash,
+        6 => MapType::PerCpuArray,
+        7 => MapType::StackTrace,
+        8 => MapType::CgroupArray,
+        9 => MapType::LruHash,
+        27 => MapType::RingBuf,
+        #[cfg(feature = "cloud-profile")]
+        10 => MapType::LruPerCpuHash,
+        #[cfg(feature = "cloud-profile")]
+        11 => MapType::LpmTrie,
+        _ => return Err(LoadError::UnsupportedMapType(map_type_raw)),
+    })
+}
+
 /// Size of a BPF instruction in bytes.
 const INSN_SIZE: usize = 8;
 
@@ -315,7 +548,9 @@ impl BpfInsn {
 
 #[cfg(test)]
 mod tests {
+    use super::btf::BtfBuilder;
     use super::*;
+    use crate::bytecode::insn::WideInsn;
 
     #[test]
     fn loader_creation() {
@@ -344,6 +579,293 @@ mod tests {
         );
     }
 
+    fn code(insns: &[BpfInsn]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for insn in insns {
+            data.extend_from_slice(&[insn.opcode, insn.regs]);
+            data.extend_from_slice(&insn.offset.to_ne_bytes());
+            data.extend_from_slice(&insn.imm.to_ne_bytes());
+        }
+        data
+    }
+
+    /// Symbol table entry `(name, info, section, value)`.
+    fn symbols(entries: &[(u32, u8, u16, u64)]) -> Vec<u8> {
+        // Starting with the null symbol
+        let mut symtab = alloc::vec![0u8; 24];
+        for &(name, info, shndx, value) in entries {
+            let mut sym = [0u8; 24];
+            sym[0..4].copy_from_slice(&name.to_le_bytes());
+            sym[4] = info;
+            sym[6..8].copy_from_slice(&shndx.to_le_bytes());
+            sym[8..16].copy_from_slice(&value.to_le_bytes());
+            symtab.extend_from_slice(&sym);
+        }
+        symtab
+    }
+
+    /// Relocation entries `(offset, symbol, type)`.
+    fn relocations(entries: &[(u64, u64, u64)]) -> Vec<u8> {
+        let mut rel = Vec::new();
+        for &(offset, sym, rel_type) in entries {
+            rel.extend_from_slice(&offset.to_le_bytes());
+            rel.extend_from_slice(&((sym << 32) | rel_type).to_le_bytes());
+        }
+        rel
+    }
+
+    /// A section as (name, type, flags, link, info, alignment, contents).
+    type Section<'a> = (&'a str, u32, u64, u32, u32, u64, &'a [u8]);
+
+    /// ELF object with `.shstrtab` as section 1, followed by `sections`.
+    fn object(sections: &[Section<'_>]) -> Vec<u8> {
+        let mut shstrtab = b"\0.shstrtab\0".to_vec();
+        let mut names = Vec::new();
+        for (name, ..) in sections {
+            names.push(shstrtab.len() as u32);
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+        }
+
+        let mut data = alloc::vec![0u8; 64];
+        data[0..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2; // ELFCLASS64
+        data[5] = 1; // Little endian
+        data[6] = 1;
+        data[18..20].copy_from_slice(&247u16.to_le_bytes()); // EM_BPF
+
+        let all = core::iter::once((1, 3, 0, 0, 0, 1, &shstrtab[..])).chain(
+            sections.iter().zip(&names).map(
+                |(&(_, ty, flags, link, info, align, contents), &name)| {
+                    (name, ty, flags, link, info, align, contents)
+                },
+            ),
+        );
+        let mut headers = alloc::vec![0u8; 64];
+        for (name, sh_type, flags, link, info, align, contents) in all {
+            let mut header = [0u8; 64];
+            header[0..4].copy_from_slice(&name.to_le_bytes());
+            header[4..8].copy_from_slice(&sh_type.to_le_bytes());
+            header[8..16].copy_from_slice(&flags.to_le_bytes());
+            header[24..32].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+            header[40..44].copy_from_slice(&link.to_le_bytes());
+            header[44..48].copy_from_slice(&info.to_le_bytes());
+            header[48..56].copy_from_slice(&align.to_le_bytes());
+            headers.extend_from_slice(&header);
+            data.extend_from_slice(contents);
+        }
+
+        let shoff = data.len() as u64;
+        data[40..48].copy_from_slice(&shoff.to_le_bytes());
+        data[60..62].copy_from_slice(&(sections.len() as u16 + 2).to_le_bytes());
+        data[62..64].copy_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&headers);
+        data
+    }
+
+    /// Object whose `socket` program calls `add_one` in `.text`.
+    fn object_with_call() -> Vec<u8> {
+        let strtab = b"\0add_one\0";
+        // add_one: STT_FUNC in section 5 at instruction 2
+        let symtab = symbols(&[(1, 0x02, 5, 16)]);
+        let socket = code(&[
+            BpfInsn::mov64_imm(1, 41),
+            BpfInsn::call_local(-1), // Clang's encoding of a call to the symbol
+            BpfInsn::exit(),
+        ]);
+        let text = code(&[
+            BpfInsn::mov64_imm(0, 0),
+            BpfInsn::exit(),
+            BpfInsn::mov64_reg(0, 1), // add_one
+            BpfInsn::add64_imm(0, 1),
+            BpfInsn::exit(),
+        ]);
+        // R_BPF_64_32 of the call against add_one
+        let rel = relocations(&[(8, 1, 10)]);
+
+        object(&[
+            (".strtab", 3, 0, 0, 0, 1, strtab),
+            (".symtab", 2, 0, 2, 0, 8, &symtab),
+            ("socket", 1, 0x6, 0, 0, 8, &socket),
+            (".text", 1, 0x6, 0, 0, 8, &text),
+            (".relsocket", 9, 0, 3, 4, 8, &rel),
+        ])
+    }
+
+    #[test]
+    fn calls_pull_in_text() {
+        let data = object_with_call();
+        let obj = BpfLoader::<ActiveProfile>::new().load(&data).unwrap();
+
+        // `.text` is not a program of its own
+        assert_eq!(obj.program_names().collect::<Vec<_>>(), ["socket"]);
+
+        let insns = obj.programs()[0].insns();
+        assert_eq!(insns.len(), 8);
+        // The call lands on add_one, after the program's three instructions
+        assert_eq!(insns[1].imm, 3);
+        assert!(insns[1].is_pseudo_call());
+        assert_eq!(insns[5], BpfInsn::mov64_reg(0, 1));
+    }
+
+    /// Object whose `socket` program reads a string literal and a `.bss`
+    /// counter.
+    fn object_with_globals() -> Vec<u8> {
+        let strtab = b"\0counter\0";
+        let symtab = symbols(&[
+            (1, 0x01, 7, 4), // counter: STT_OBJECT 4 bytes into .bss
+            (0, 0x03, 6, 0), // STT_SECTION of .rodata.str1.1
+        ]);
+        let literal = WideInsn::ld_dw_imm(1, 2); // 2 bytes into the section
+        let counter = WideInsn::ld_dw_imm(2, 0);
+        let socket = code(&[
+            literal.insn,
+            literal.next,
+            counter.insn,
+            counter.next,
+            BpfInsn::new(0x61, 0, 2, 0, 0), // r0 = *(u32 *)(r2 + 0)
+            BpfInsn::exit(),
+        ]);
+        let rel = relocations(&[(0, 2, 1), (16, 1, 1)]);
+
+        object(&[
+            (".strtab", 3, 0, 0, 0, 1, strtab),
+            (".symtab", 2, 0, 2, 0, 8, &symtab),
+            ("socket", 1, 0x6, 0, 0, 8, &socket),
+            (".rodata", 1, 0x2, 0, 0, 4, &[1, 2, 3]),
+            (".rodata.str1.1", 1, 0x32, 0, 0, 1, b"hi!\0"),
+            (".bss", 8, 0x3, 0, 0, 8, &[0; 8]),
+            (".relsocket", 9, 0, 3, 4, 8, &rel),
+        ])
+    }
+
+    #[test]
+    fn globals_live_in_data_maps() {
+        let data = object_with_globals();
+        let mut obj = BpfLoader::<ActiveProfile>::new().load(&data).unwrap();
+
+        // No `.data`, so `.rodata` and `.bss` are the first maps
+        assert_eq!(obj.map_names().collect::<Vec<_>>(), [".rodata", ".bss"]);
+        let rodata = obj.map(".rodata").unwrap();
+        assert!(rodata.is_frozen());
+        assert_eq!(rodata.data(), b"\x01\x02\x03hi!\0");
+        assert_eq!(rodata.def().max_entries, 1);
+        let bss = obj.map(".bss").unwrap();
+        assert!(!bss.is_frozen());
+        assert_eq!(bss.data(), [0; 8]);
+
+        // The literal is 2 bytes into the section placed at offset 3
+        let insns = obj.programs()[0].insns();
+        assert!(insns[0].is_map_value_load());
+        assert_eq!((insns[0].imm, insns[1].imm), (0, 5));
+        assert!(insns[2].is_map_value_load());
+        assert_eq!((insns[2].imm, insns[3].imm), (1, 4));
+
+        obj.map_mut(".rodata").unwrap().data_mut()[0] = 7;
+        assert_eq!(obj.map(".rodata").unwrap().data()[0], 7);
+    }
+
+    /// Object whose `socket` program uses the BTF-defined array map
+    /// `events`, optionally declaring a value size as well.
+    fn object_with_btf_map(value_size: Option<u32>) -> Vec<u8> {
+        let mut btf = BtfBuilder::new();
+        let int = btf.int("unsigned int", 4);
+        let u64_id = btf.int("unsigned long long", 8);
+        let event = btf.structure("event", 16, &[("ts", u64_id, 0), ("id", int, 8)]);
+        let map_type = btf.uint_field(int, 2); // BPF_MAP_TYPE_ARRAY
+        let max_entries = btf.uint_field(int, 8);
+        let key = btf.ptr(int);
+        let value = btf.ptr(event);
+        let mut members = alloc::vec![
+            ("type", map_type, 0),
+            ("max_entries", max_entries, 8),
+            ("key", key, 16),
+            ("value", value, 24),
+        ];
+        if let Some(size) = value_size {
+            members.push(("value_size", btf.uint_field(int, size), 32));
+        }
+        let map = btf.structure("", members.len() as u32 * 8, &members);
+        let var = btf.var("events", map);
+        btf.datasec(".maps", 32, &[(var, 0, 32)]);
+        let ext = btf.line_info(&[(
+            "socket",
+            &[
+                (0, "prog.c", "void *map = &events;", 5),
+                (16, "prog.c", "return 0;", 6),
+            ],
+        )]);
+
+        let strtab = b"\0events\0";
+        let symtab = symbols(&[(1, 0x11, 5, 0)]); // events: global object in .maps
+        let map_ref = WideInsn::ld_map_fd(1, 0);
+        let socket = code(&[
+            map_ref.insn,
+            map_ref.next,
+            BpfInsn::mov64_imm(0, 0),
+            BpfInsn::exit(),
+        ]);
+        let rel = relocations(&[(0, 1, 1)]);
+
+        object(&[
+            (".strtab", 3, 0, 0, 0, 1, strtab),
+            (".symtab", 2, 0, 2, 0, 8, &symtab),
+            ("socket", 1, 0x6, 0, 0, 8, &socket),
+            (".maps", 1, 0x3, 0, 0, 8, &[0; 32]),
+            (".relsocket", 9, 0, 3, 4, 8, &rel),
+            (".BTF", 1, 0, 0, 0, 4, &btf.build()),
+            (".BTF.ext", 1, 0, 0, 0, 4, &ext),
+        ])
+    }
+
+    #[test]
+    fn btf_defined_maps() {
+        let data = object_with_btf_map(None);
+        let obj = BpfLoader::<ActiveProfile>::new().load(&data).unwrap();
+
+        let map = obj.map("events").unwrap();
+        assert_eq!(map.def().map_type, MapType::Array);
+        assert_eq!(map.def().key_size, 4);
+        assert_eq!(map.def().value_size, 16);
+        assert_eq!(map.def().max_entries, 8);
+
+        let btf = obj.btf().unwrap();
+        let (key, value) = map.btf_type_ids();
+        assert_eq!(btf.type_name(key), Some("unsigned int"));
+        assert_eq!(btf.type_name(value), Some("event"));
+
+        let prog = &obj.programs()[0];
+        assert!(prog.insns()[0].is_map_load());
+        assert_eq!(prog.insns()[0].imm, 0);
+    }
+
+    #[test]
+    fn btf_map_sizes_must_match_types() {
+        assert!(
+            BpfLoader::<ActiveProfile>::new()
+                .load(&object_with_btf_map(Some(16)))
+                .is_ok()
+        );
+        assert!(matches!(
+            BpfLoader::<ActiveProfile>::new().load(&object_with_btf_map(Some(12))),
+            Err(LoadError::BtfMapMismatch(name)) if name == "events"
+        ));
+    }
+
+    #[test]
+    fn line_info_points_at_source() {
+        let data = object_with_btf_map(None);
+        let obj = BpfLoader::<ActiveProfile>::new().load(&data).unwrap();
+        let prog = &obj.programs()[0];
+
+        assert_eq!(prog.line_info().len(), 2);
+        assert_eq!(prog.source_line(1).unwrap().line, 5);
+        let line = prog.source_line(3).unwrap();
+        assert_eq!((line.file.as_str(), line.line), ("prog.c", 6));
+        assert_eq!(alloc::format!("{}", line), "prog.c:6: return 0;");
+    }
+
     #[test]
     fn insn_from_bytes() {
         // mov64 r0, 42 instruction
@@ -8,9 +8,10 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
 
+use super::btf::{Btf, LineInfo};
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::program::BpfProgType;
-use crate::maps::MapDef;
+use crate::maps::{MapDef, MapType};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
 /// A loaded BPF program.
@@ -22,6 +23,8 @@ pub struct LoadedProgram<P: PhysicalProfile = ActiveProfile> {
     prog_type: BpfProgType,
     /// Program instructions
     insns: Vec<BpfInsn>,
+    /// Source lines from `.BTF.ext`, sorted by instruction
+    line_info: Vec<LineInfo>,
     /// Profile marker
     _profile: PhantomData<P>,
 }
@@ -33,10 +36,17 @@ impl<P: PhysicalProfile> LoadedProgram<P> {
             name,
             prog_type,
             insns,
+            line_info: Vec::new(),
             _profile: PhantomData,
         }
     }
 
+    /// Attach line info, sorted by instruction.
+    pub fn with_line_info(mut self, line_info: Vec<LineInfo>) -> Self {
+        self.line_info = line_info;
+        self
+    }
+
     /// Get the program name.
     pub fn name(&self) -> &str {
         &self.name
@@ -61,18 +71,79 @@ impl<P: PhysicalProfile> LoadedProgram<P> {
     pub fn into_insns(self) -> Vec<BpfInsn> {
         self.insns
     }
+
+    /// Get the line info, empty if the object has no `.BTF.ext`.
+    pub fn line_info(&self) -> &[LineInfo] {
+        &self.line_info
+    }
+
+    /// Get the source line instruction `insn_idx` was compiled from.
+    pub fn source_line(&self, insn_idx: usize) -> Option<&LineInfo> {
+        let next = self
+            .line_info
+            .partition_point(|info| info.insn_idx <= insn_idx);
+        self.line_info[..next].last()
+    }
 }
 
 /// A loaded map definition.
+///
+/// Besides the maps an object declares, the global data sections `.data`,
+/// `.rodata` and `.bss` each become a single-entry array map named after
+/// the section, whose value holds the section's contents.
 #[derive(Debug, Clone)]
 pub struct LoadedMap {
     /// Map name
     pub name: String,
     /// Map definition
     pub def: MapDef,
+    /// Initial value of a global data map; empty for declared maps
+    pub data: Vec<u8>,
+    /// Whether the map is frozen once created (`.rodata`)
+    pub frozen: bool,
+    /// BTF type of the keys, 0 if unknown
+    pub btf_key_type_id: u32,
+    /// BTF type of the values, 0 if unknown
+    pub btf_value_type_id: u32,
 }
 
 impl LoadedMap {
+    /// Create a declared map, which starts out empty.
+    pub fn new(name: String, def: MapDef) -> Self {
+        Self {
+            name,
+            def,
+            data: Vec::new(),
+            frozen: false,
+            btf_key_type_id: 0,
+            btf_value_type_id: 0,
+        }
+    }
+
+    /// Create the map of a global data section holding `data`.
+    ///
+    /// `.rodata` maps are frozen: after creation, neither userspace nor the
+    /// kernel's map commands may change them.
+    pub fn global_data(name: String, data: Vec<u8>) -> Self {
+        let def = MapDef::new(MapType::Array, 4, data.len() as u32, 1);
+        let frozen = name == ".rodata";
+        Self {
+            name,
+            def,
+            data,
+            frozen,
+            btf_key_type_id: 0,
+            btf_value_type_id: 0,
+        }
+    }
+
+    /// Set the BTF types of keys and values.
+    pub fn with_btf_types(mut self, key_type_id: u32, value_type_id: u32) -> Self {
+        self.btf_key_type_id = key_type_id;
+        self.btf_value_type_id = value_type_id;
+        self
+    }
+
     /// Get the map name.
     pub fn name(&self) -> &str {
         &self.name
@@ -82,6 +153,36 @@ impl LoadedMap {
     pub fn def(&self) -> &MapDef {
         &self.def
     }
+
+    /// Get the initial value of a global data map.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the initial value of a global data map for changing.
+    ///
+    /// This is how configuration constants in `.rodata` are set before the
+    /// program is loaded. The size of the value is fixed.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Check if this is the map of a global data section.
+    pub fn is_global_data(&self) -> bool {
+        !self.data.is_empty()
+    }
+
+    /// Check if the map is frozen once created.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Get the BTF types of keys and values, 0 if unknown.
+    ///
+    /// The value type of a global data map is its section's `DATASEC`.
+    pub fn btf_type_ids(&self) -> (u32, u32) {
+        (self.btf_key_type_id, self.btf_value_type_id)
+    }
 }
 
 /// A loaded BPF object file.
@@ -93,6 +194,8 @@ pub struct BpfObject<P: PhysicalProfile = ActiveProfile> {
     maps: Vec<LoadedMap>,
     /// License string
     license: Option<String>,
+    /// Type information from `.BTF`
+    btf: Option<Btf>,
 }
 
 impl<P: PhysicalProfile> BpfObject<P> {
@@ -106,9 +209,16 @@ impl<P: PhysicalProfile> BpfObject<P> {
             programs,
             maps,
             license,
+            btf: None,
         }
     }
 
+    /// Attach the object's type information.
+    pub fn with_btf(mut self, btf: Btf) -> Self {
+        self.btf = Some(btf);
+        self
+    }
+
     /// Get all programs.
     pub fn programs(&self) -> &[LoadedProgram<P>] {
         &self.programs
@@ -129,11 +239,21 @@ impl<P: PhysicalProfile> BpfObject<P> {
         self.maps.iter().find(|m| m.name == name)
     }
 
+    /// Get a map by name, e.g. `.rodata` to set its initial value.
+    pub fn map_mut(&mut self, name: &str) -> Option<&mut LoadedMap> {
+        self.maps.iter_mut().find(|m| m.name == name)
+    }
+
     /// Get the license string.
     pub fn license(&self) -> Option<&str> {
         self.license.as_deref()
     }
 
+    /// Get the type information, if the object has a `.BTF` section.
+    pub fn btf(&self) -> Option<&Btf> {
+        self.btf.as_ref()
+    }
+
     /// Get number of programs.
     pub fn program_count(&self) -> usize {
         self.programs.len()
@@ -193,10 +313,10 @@ mod tests {
             vec![],
         )];
 
-        let maps = vec![LoadedMap {
-            name: "map1".into(),
-            def: MapDef::new(crate::maps::MapType::Array, 4, 8, 100),
-        }];
+        let maps = vec![LoadedMap::new(
+            "map1".into(),
+            MapDef::new(MapType::Array, 4, 8, 100),
+        )];
 
         let obj = BpfObject::new(programs, maps, Some("GPL".into()));
 
@@ -207,5 +327,24 @@ mod tests {
         assert!(obj.program("prog1").is_some());
         assert!(obj.program("nonexistent").is_none());
         assert!(obj.map("map1").is_some());
+        assert!(!obj.map("map1").unwrap().is_global_data());
+    }
+
+    #[test]
+    fn global_data_maps() {
+        let rodata = LoadedMap::global_data(".rodata".into(), vec![1, 2, 3, 4]);
+        assert!(rodata.is_global_data());
+        assert!(rodata.is_frozen());
+        assert_eq!(rodata.def().map_type, MapType::Array);
+        assert_eq!(rodata.def().value_size, 4);
+        assert_eq!(rodata.def().max_entries, 1);
+
+        let bss = LoadedMap::global_data(".bss".into(), vec![0; 8]);
+        assert!(!bss.is_frozen());
+
+        // Constants are set before load, as with a libbpf skeleton
+        let mut obj = BpfObject::<ActiveProfile>::new(vec![], vec![rodata, bss], None);
+        obj.map_mut(".rodata").unwrap().data_mut()[0] = 9;
+        assert_eq!(obj.map(".rodata").unwrap().data(), [9, 2, 3, 4]);
     }
 }
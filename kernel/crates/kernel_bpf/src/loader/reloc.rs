@@ -1,15 +1,22 @@
 //! BPF Relocation Handler
 //!
 //! Handles relocations for map references and other symbols in BPF programs.
+//!
+//! Calls of BPF functions in another section (usually `.text`) pull that
+//! section's code into the program: it is appended once, its own
+//! relocations applied, and the calls pointed at it. This keeps every
+//! function of a program in one instruction stream.
 
 extern crate alloc;
 
+use alloc::vec;
 use alloc::vec::Vec;
 
-use super::elf::ElfParser;
+use super::elf::{ElfParser, SectionType, Symbol};
 use super::error::{LoadError, LoadResult};
 use super::object::LoadedMap;
 use crate::bytecode::insn::BpfInsn;
+use crate::helpers::HelperId;
 
 // BPF relocation types
 const R_BPF_64_64: u32 = 1;
@@ -17,16 +24,47 @@ const R_BPF_64_ABS64: u32 = 2;
 const R_BPF_64_ABS32: u32 = 3;
 const R_BPF_64_32: u32 = 10;
 
+/// Place of a global data section in the value of its map.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct DataSection {
+    /// Section index
+    pub section: usize,
+    /// Index of the map in the object's maps
+    pub map: usize,
+    /// Offset of the section's data in the map's value
+    pub base: usize,
+}
+
 /// BPF instruction relocation handler.
 pub struct Relocator<'a> {
     /// Map definitions for resolving map references
     maps: &'a [LoadedMap],
+    /// Global data sections for resolving references to globals
+    data_sections: &'a [DataSection],
+    /// Sections whose code the last relocated program holds
+    placed: Vec<(usize, usize, usize)>,
 }
 
 impl<'a> Relocator<'a> {
     /// Create a new relocator.
     pub fn new(maps: &'a [LoadedMap]) -> Self {
-        Self { maps }
+        Self {
+            maps,
+            data_sections: &[],
+            placed: Vec::new(),
+        }
+    }
+
+    /// Resolve references to globals in `data_sections`.
+    pub(super) fn with_data_sections(mut self, data_sections: &'a [DataSection]) -> Self {
+        self.data_sections = data_sections;
+        self
+    }
+
+    /// Sections whose code is in the last relocated program, as (section
+    /// index, first instruction, instruction count).
+    pub(super) fn placement(&self) -> &[(usize, usize, usize)] {
+        &self.placed
     }
 
     /// Apply relocations to instructions.
@@ -48,49 +86,73 @@ impl<'a> Relocator<'a> {
             })
             .ok_or(LoadError::InvalidRelocation)?;
 
-        // Get relocations for this section
-        let relocs = parser.relocations(section_idx)?;
-        if relocs.is_empty() {
-            return Ok(insns);
-        }
+        // Sections whose code is in `insns` (section index, first
+        // instruction, instruction count)
+        let mut placed = vec![(section_idx, 0, insns.len())];
+        let mut symbols = Vec::new();
 
-        // Get symbol table
-        let symbols = parser.symbols()?;
+        let mut next = 0;
+        while let Some(&(section_idx, base, len)) = placed.get(next) {
+            next += 1;
 
-        // Apply each relocation
-        for reloc in relocs {
-            let insn_idx = (reloc.offset / 8) as usize;
-            if insn_idx >= insns.len() {
-                return Err(LoadError::InvalidRelocation);
+            // Get relocations for this section
+            let relocs = parser.relocations(section_idx)?;
+            if relocs.is_empty() {
+                continue;
             }
 
-            // Get symbol
-            if reloc.sym_idx as usize >= symbols.len() {
-                return Err(LoadError::UndefinedSymbol);
+            // Get symbol table
+            if symbols.is_empty() {
+                symbols = parser.symbols()?;
             }
-            let sym = &symbols[reloc.sym_idx as usize];
-            let sym_name = parser.symbol_name(sym)?;
-
-            // Apply relocation based on type
-            match reloc.rel_type {
-                R_BPF_64_64 => {
-                    // Map reference - 64-bit load immediate
-                    self.relocate_map_ref(&mut insns, insn_idx, &sym_name)?;
-                }
-                R_BPF_64_32 => {
-                    // Helper function call
-                    self.relocate_call(&mut insns, insn_idx, &sym_name)?;
+
+            // Apply each relocation
+            for reloc in relocs {
+                let insn_idx = (reloc.offset / 8) as usize;
+                if insn_idx >= len {
+                    return Err(LoadError::InvalidRelocation);
                 }
-                R_BPF_64_ABS64 | R_BPF_64_ABS32 => {
-                    // Absolute references - typically for data
-                    // These are handled differently based on context
+                let insn_idx = base + insn_idx;
+
+                // Get symbol
+                if reloc.sym_idx as usize >= symbols.len() {
+                    return Err(LoadError::UndefinedSymbol);
                 }
-                _ => {
-                    // Unknown relocation type - ignore for now
+                let sym = &symbols[reloc.sym_idx as usize];
+                let sym_name = parser.symbol_name(sym)?;
+
+                // Apply relocation based on type
+                let data_section = self
+                    .data_sections
+                    .iter()
+                    .find(|data| data.section == sym.shndx as usize);
+                match reloc.rel_type {
+                    R_BPF_64_64 => match data_section {
+                        // Global variable or constant
+                        Some(data) => Self::relocate_data_ref(&mut insns, insn_idx, sym, data)?,
+                        // Map reference - 64-bit load immediate
+                        None => self.relocate_map_ref(&mut insns, insn_idx, &sym_name)?,
+                    },
+                    R_BPF_64_32 if insns[insn_idx].is_pseudo_call() => {
+                        // BPF function call
+                        Self::relocate_local_call(&mut insns, insn_idx, sym, &mut placed, parser)?;
+                    }
+                    R_BPF_64_32 => {
+                        // Helper function call
+                        self.relocate_call(&mut insns, insn_idx, &sym_name)?;
+                    }
+                    R_BPF_64_ABS64 | R_BPF_64_ABS32 => {
+                        // Absolute references - typically for data
+                        // These are handled differently based on context
+                    }
+                    _ => {
+                        // Unknown relocation type - ignore for now
+                    }
                 }
             }
         }
 
+        self.placed = placed;
         Ok(insns)
     }
 
@@ -115,7 +177,7 @@ impl<'a> Relocator<'a> {
         // src_reg = BPF_PSEUDO_MAP_FD (1) indicates map reference
         // imm contains the map index
         // regs format: dst (low 4 bits) | src (high 4 bits)
-        insn.regs = (insn.regs & 0x0f) | (1 << 4); // Set src to BPF_PSEUDO_MAP_FD
+        insn.regs = (insn.regs & 0x0f) | (BpfInsn::PSEUDO_MAP_FD << 4);
         insn.imm = map_idx as i32;
 
         // If this is a wide instruction, update the second half too
@@ -126,6 +188,30 @@ impl<'a> Relocator<'a> {
         Ok(())
     }
 
+    /// Relocate a reference to a global in a data section.
+    fn relocate_data_ref(
+        insns: &mut [BpfInsn],
+        insn_idx: usize,
+        sym: &Symbol,
+        data: &DataSection,
+    ) -> LoadResult<()> {
+        if !insns[insn_idx].is_wide() || insn_idx + 1 >= insns.len() {
+            return Err(LoadError::InvalidRelocation);
+        }
+
+        // References through the section symbol keep the variable's offset
+        // in the immediate
+        let offset = data.base as i64 + sym.value as i64 + insns[insn_idx].imm as i64;
+        let offset = i32::try_from(offset).map_err(|_| LoadError::InvalidRelocation)?;
+
+        let insn = &mut insns[insn_idx];
+        insn.regs = (insn.regs & 0x0f) | (BpfInsn::PSEUDO_MAP_VALUE << 4);
+        insn.imm = data.map as i32;
+        insns[insn_idx + 1].imm = offset;
+
+        Ok(())
+    }
+
     /// Relocate a function call.
     fn relocate_call(
         &self,
@@ -134,81 +220,47 @@ impl<'a> Relocator<'a> {
         sym_name: &str,
     ) -> LoadResult<()> {
         // Check if this is a helper function call
-        if let Some(helper_id) = Self::helper_name_to_id(sym_name) {
-            insns[insn_idx].imm = helper_id;
+        if let Some(helper_id) = HelperId::from_name(sym_name) {
+            insns[insn_idx].imm = helper_id as i32;
         }
-        // Otherwise, it's a BPF-to-BPF call which needs different handling
-        // (not implemented in streaming verifier)
 
         Ok(())
     }
 
-    /// Convert helper function name to ID.
-    fn helper_name_to_id(name: &str) -> Option<i32> {
-        // Common BPF helper functions
-        match name {
-            "bpf_map_lookup_elem" => Some(1),
-            "bpf_map_update_elem" => Some(2),
-            "bpf_map_delete_elem" => Some(3),
-            "bpf_probe_read" => Some(4),
-            "bpf_ktime_get_ns" => Some(5),
-            "bpf_trace_printk" => Some(6),
-            "bpf_get_prandom_u32" => Some(7),
-            "bpf_get_smp_processor_id" => Some(8),
-            "bpf_skb_store_bytes" => Some(9),
-            "bpf_l3_csum_replace" => Some(10),
-            "bpf_l4_csum_replace" => Some(11),
-            "bpf_tail_call" => Some(12),
-            "bpf_clone_redirect" => Some(13),
-            "bpf_get_current_pid_tgid" => Some(14),
-            "bpf_get_current_uid_gid" => Some(15),
-            "bpf_get_current_comm" => Some(16),
-            "bpf_get_cgroup_classid" => Some(17),
-            "bpf_skb_vlan_push" => Some(18),
-            "bpf_skb_vlan_pop" => Some(19),
-            "bpf_skb_get_tunnel_key" => Some(20),
-            "bpf_skb_set_tunnel_key" => Some(21),
-            "bpf_perf_event_read" => Some(22),
-            "bpf_redirect" => Some(23),
-            "bpf_get_route_realm" => Some(24),
-            "bpf_perf_event_output" => Some(25),
-            "bpf_skb_load_bytes" => Some(26),
-            "bpf_get_stackid" => Some(27),
-            "bpf_csum_diff" => Some(28),
-            "bpf_skb_get_tunnel_opt" => Some(29),
-            "bpf_skb_set_tunnel_opt" => Some(30),
-            "bpf_skb_change_proto" => Some(31),
-            "bpf_skb_change_type" => Some(32),
-            "bpf_skb_under_cgroup" => Some(33),
-            "bpf_get_hash_recalc" => Some(34),
-            "bpf_get_current_task" => Some(35),
-            "bpf_probe_write_user" => Some(36),
-            "bpf_current_task_under_cgroup" => Some(37),
-            "bpf_skb_change_tail" => Some(38),
-            "bpf_skb_pull_data" => Some(39),
-            "bpf_csum_update" => Some(40),
-            "bpf_set_hash_invalid" => Some(41),
-            "bpf_get_numa_node_id" => Some(42),
-            "bpf_skb_change_head" => Some(43),
-            "bpf_xdp_adjust_head" => Some(44),
-            "bpf_probe_read_str" => Some(45),
-            "bpf_get_socket_cookie" => Some(46),
-            "bpf_get_socket_uid" => Some(47),
-            "bpf_set_hash" => Some(48),
-            "bpf_setsockopt" => Some(49),
-            "bpf_skb_adjust_room" => Some(50),
-            // Ring buffer helpers
-            "bpf_ringbuf_output" => Some(130),
-            "bpf_ringbuf_reserve" => Some(131),
-            "bpf_ringbuf_submit" => Some(132),
-            "bpf_ringbuf_discard" => Some(133),
-            "bpf_ringbuf_query" => Some(134),
-            // rkBPF robotics-specific helpers
-            "bpf_motor_emergency_stop" => Some(200),
-            "bpf_timeseries_push" => Some(201),
-            "bpf_sensor_last_timestamp" => Some(202),
-            _ => None,
-        }
+    /// Relocate a call of a BPF function, appending the code of the
+    /// function's section if the program doesn't have it yet.
+    fn relocate_local_call(
+        insns: &mut Vec<BpfInsn>,
+        insn_idx: usize,
+        sym: &Symbol,
+        placed: &mut Vec<(usize, usize, usize)>,
+        parser: &ElfParser,
+    ) -> LoadResult<()> {
+        let section_idx = sym.shndx as usize;
+        let base = match placed.iter().find(|(idx, ..)| *idx == section_idx) {
+            Some(&(_, base, _)) => base,
+            None => {
+                let section = parser
+                    .sections()?
+                    .get(section_idx)
+                    .filter(|s| s.section_type == SectionType::Program)
+                    .ok_or(LoadError::InvalidRelocation)?;
+                let code = super::parse_instructions(parser.section_data(section)?)?;
+
+                let base = insns.len();
+                placed.push((section_idx, base, code.len()));
+                insns.extend(code);
+                base
+            }
+        };
+
+        // Clang encodes the target as the symbol's instruction, adjusted
+        // by the immediate; the call wants it relative to the next one
+        let target = base as i64 + (sym.value / 8) as i64 + insns[insn_idx].imm as i64 + 1;
+        insns[insn_idx].imm = i32::try_from(target - (insn_idx as i64 + 1))
+            .map_err(|_| LoadError::InvalidRelocation)?;
+
+        Ok(())
     }
 }
 
@@ -218,16 +270,22 @@ mod tests {
 
     #[test]
     fn helper_name_mapping() {
-        assert_eq!(Relocator::helper_name_to_id("bpf_map_lookup_elem"), Some(1));
-        assert_eq!(Relocator::helper_name_to_id("bpf_ktime_get_ns"), Some(5));
         assert_eq!(
-            Relocator::helper_name_to_id("bpf_ringbuf_output"),
-            Some(130)
+            HelperId::from_name("bpf_map_lookup_elem"),
+            Some(HelperId::MapLookupElem)
+        );
+        assert_eq!(
+            HelperId::from_name("bpf_ktime_get_ns").map(|id| id as i32),
+            Some(1)
+        );
+        assert_eq!(
+            HelperId::from_name("bpf_ringbuf_output").map(|id| id as i32),
+            Some(6)
         );
         assert_eq!(
-            Relocator::helper_name_to_id("bpf_motor_emergency_stop"),
-            Some(200)
+            HelperId::from_name("bpf_motor_emergency_stop").map(|id| id as i32),
+            Some(1000)
         );
-        assert_eq!(Relocator::helper_name_to_id("unknown_helper"), None);
+        assert_eq!(HelperId::from_name("unknown_helper"), None);
     }
 }
@@ -179,6 +179,26 @@ impl<P: PhysicalProfile> BpfMap<P> for ArrayMap<P> {
         &self.def
     }
 
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let guard = self.data.read();
+
+        // Every index holds a value; iteration restarts at 0 for indices
+        // past the end, as in Linux
+        let start = key
+            .and_then(Self::parse_key)
+            .map(|index| index as usize + 1)
+            .filter(|&index| index <= guard.max_entries)
+            .unwrap_or(0);
+
+        Ok((start..guard.max_entries)
+            .take(max)
+            .filter_map(|index| {
+                let value = guard.get(index)?;
+                Some(((index as u32).to_ne_bytes().to_vec(), value.to_vec()))
+            })
+            .collect())
+    }
+
     // SAFETY: This method returns a raw pointer to the map value.
     // The caller must ensure that the pointer is not used after the map is modified or dropped.
     // We rely on the caller to maintain the safety invariants required by the BpfMap trait.
@@ -262,6 +282,41 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn array_map_iteration() {
+        let map = ArrayMap::<ActiveProfile>::with_entries(4, 3).expect("create map");
+        map.update(&1u32.to_ne_bytes(), &11u32.to_ne_bytes(), 0)
+            .expect("update");
+
+        let next = |key: Option<u32>| {
+            map.get_next_key(key.map(u32::to_ne_bytes).as_ref().map(|k| &k[..]))
+                .map(|k| u32::from_ne_bytes(k.as_slice().try_into().unwrap()))
+        };
+        assert_eq!(next(None), Ok(0));
+        assert_eq!(next(Some(0)), Ok(1));
+        assert_eq!(next(Some(1)), Ok(2));
+        assert_eq!(next(Some(2)), Err(MapError::KeyNotFound));
+        // Out of range indices restart the iteration
+        assert_eq!(next(Some(100)), Ok(0));
+
+        let entries = map
+            .entries_after(Some(&0u32.to_ne_bytes()), 8)
+            .expect("batch");
+        assert_eq!(
+            entries,
+            [
+                (1u32.to_ne_bytes().to_vec(), 11u32.to_ne_bytes().to_vec()),
+                (2u32.to_ne_bytes().to_vec(), 0u32.to_ne_bytes().to_vec()),
+            ]
+        );
+
+        // Array elements can't be deleted
+        assert_eq!(
+            map.lookup_and_delete(&1u32.to_ne_bytes()),
+            Err(MapError::NotSupported)
+        );
+    }
+
     #[cfg(feature = "cloud-profile")]
     #[test]
     fn array_map_resize() {
@@ -78,7 +78,6 @@ impl Bucket {
         self.state == BucketState::Empty
     }
 
-    #[allow(dead_code)]
     fn is_occupied(&self) -> bool {
         self.state == BucketState::Occupied
     }
@@ -93,6 +92,17 @@ impl Bucket {
     }
 }
 
+/// Hash a map key.
+pub(super) fn hash_key(key: &[u8]) -> usize {
+    // FNV-1a hash - good distribution for typical BPF workloads
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash as usize
+}
+
 /// Internal storage for hash map.
 struct HashStorage {
     /// Bucket array
@@ -124,13 +134,7 @@ impl HashStorage {
 
     /// Compute hash of a key.
     fn hash(&self, key: &[u8]) -> usize {
-        // FNV-1a hash - good distribution for typical BPF workloads
-        let mut hash: u64 = 0xcbf29ce484222325;
-        for byte in key {
-            hash ^= *byte as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
-        }
-        hash as usize
+        hash_key(key)
     }
 
     /// Find bucket for a key.
@@ -239,6 +243,24 @@ impl HashStorage {
         Ok(())
     }
 
+    /// Copy up to `max` entries in bucket order, starting after `key`.
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let start = match key {
+            Some(key) if key.len() == self.key_size => match self.find_bucket(key) {
+                (idx, true) => idx + 1,
+                (_, false) => 0,
+            },
+            _ => 0,
+        };
+
+        self.buckets[start..]
+            .iter()
+            .filter(|bucket| bucket.is_occupied())
+            .take(max)
+            .map(|bucket| (bucket.key.clone(), bucket.value.clone()))
+            .collect()
+    }
+
     /// Resize the hash map (cloud profile only).
     #[cfg(feature = "cloud-profile")]
     fn resize(&mut self, new_capacity: usize) {
@@ -368,6 +390,17 @@ impl<P: PhysicalProfile> BpfMap<P> for HashMap<P> {
         &self.def
     }
 
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.storage.read().entries_after(key, max))
+    }
+
+    fn lookup_and_delete(&self, key: &[u8]) -> MapResult<Vec<u8>> {
+        let mut guard = self.storage.write();
+        let value = guard.lookup(key).ok_or(MapError::KeyNotFound)?.to_vec();
+        guard.delete(key)?;
+        Ok(value)
+    }
+
     /// # Safety
     /// This method returns a raw pointer to the map value. The caller must ensure
     /// that the pointer is not used after the map is modified or dropped.
@@ -594,6 +627,64 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn hash_map_iteration() {
+        let map = HashMap::<ActiveProfile>::with_sizes(4, 4, 16).expect("create map");
+        for i in 0u32..5 {
+            map.update(&i.to_ne_bytes(), &(i * 10).to_ne_bytes(), 0)
+                .expect("insert");
+        }
+        map.delete(&2u32.to_ne_bytes()).expect("delete");
+
+        // Walk the keys one by one, as BPF_MAP_GET_NEXT_KEY does
+        let mut keys = Vec::new();
+        let mut key = map.get_next_key(None).expect("first key");
+        loop {
+            keys.push(u32::from_ne_bytes(key.as_slice().try_into().unwrap()));
+            match map.get_next_key(Some(&key)) {
+                Ok(next) => key = next,
+                Err(err) => {
+                    assert_eq!(err, MapError::KeyNotFound);
+                    break;
+                }
+            }
+        }
+        keys.sort_unstable();
+        assert_eq!(keys, [0, 1, 3, 4]);
+
+        // A key that isn't in the map restarts the iteration
+        assert_eq!(
+            map.get_next_key(Some(&2u32.to_ne_bytes())),
+            map.get_next_key(None)
+        );
+
+        // Batches continue after the last key of the previous one
+        let first = map.entries_after(None, 3).expect("first batch");
+        assert_eq!(first.len(), 3);
+        let rest = map
+            .entries_after(Some(&first[2].0), 3)
+            .expect("second batch");
+        assert_eq!(rest.len(), 1);
+        for (key, value) in first.iter().chain(&rest) {
+            assert_eq!(map.lookup(key).as_ref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn hash_map_lookup_and_delete() {
+        let map = HashMap::<ActiveProfile>::with_sizes(4, 4, 16).expect("create map");
+        let key = 7u32.to_ne_bytes();
+        map.update(&key, &70u32.to_ne_bytes(), 0).expect("insert");
+
+        assert_eq!(
+            map.lookup_and_delete(&key),
+            Ok(70u32.to_ne_bytes().to_vec())
+        );
+        assert!(map.lookup(&key).is_none());
+        assert!(map.is_empty());
+        assert_eq!(map.lookup_and_delete(&key), Err(MapError::KeyNotFound));
+    }
+
     #[cfg(feature = "cloud-profile")]
     #[test]
     fn hash_map_resize() {
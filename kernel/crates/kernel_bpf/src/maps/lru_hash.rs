@@ -0,0 +1,690 @@
+//! LRU Hash Map Implementation
+//!
+//! An LRU hash map behaves like a [`HashMap`](super::HashMap), except that
+//! inserting into a full map evicts the least recently used entry instead
+//! of failing. This suits tracking transient entities (CAN IDs seen,
+//! short-lived tasks) in a bounded amount of memory.
+//!
+//! # Memory Layout
+//!
+//! All map memory is a single slab, sized at creation:
+//!
+//! ```text
+//! ┌──────────────────────┬──────────────────────────────────────────────┐
+//! │ Buckets              │ Nodes                                        │
+//! │ ┌────┬────┬────┬───┐ │ ┌──────────────────────────────────────────┐ │
+//! │ │head│head│head│...│ │ │ prev │ next │ chain │ used │ key │ value │ │
+//! │ └────┴────┴────┴───┘ │ ├──────────────────────────────────────────┤ │
+//! │                      │ │ ...                                      │ │
+//! └──────────────────────┴──────────────────────────────────────────────┘
+//! ```
+//!
+//! Each node is on its bucket's chain and on the recency list (or on the
+//! free list while unused). Lookups and updates move a node to the front
+//! of the recency list; eviction takes the node at the back.
+//!
+//! # Profile Differences
+//!
+//! | Feature       | Cloud          | Embedded         |
+//! |---------------|----------------|------------------|
+//! | Allocation    | Heap           | [`StaticPool`]   |
+//! | Resize        | Supported      | **Erased**       |
+//! | Max entries   | Configurable   | Fixed at init    |
+//!
+//! On the embedded profile the slab comes from the [`StaticPool`], so the
+//! map never allocates after creation. Pool memory is not returned when
+//! the map is dropped.
+//!
+//! [`StaticPool`]: super::StaticPool
+
+extern crate alloc;
+
+#[cfg(feature = "cloud-profile")]
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use spin::Mutex;
+
+use super::hash::hash_key;
+use super::{BpfMap, MapDef, MapError, MapResult, MapType};
+use crate::profile::{ActiveProfile, PhysicalProfile};
+
+/// Marks the end of a list.
+const NIL: u32 = u32::MAX;
+
+/// Node header fields, as `u32` offsets into the node.
+const PREV: usize = 0;
+const NEXT: usize = 4;
+const CHAIN: usize = 8;
+const USED: usize = 12;
+const NODE_HEADER_SIZE: usize = 16;
+
+/// Backing memory of the map.
+#[cfg(feature = "cloud-profile")]
+type Slab = Vec<u8>;
+
+/// Backing memory of the map.
+#[cfg(all(feature = "embedded-profile", not(feature = "cloud-profile")))]
+type Slab = &'static mut [u8];
+
+/// Allocate a zeroed slab of `size` bytes.
+#[cfg(feature = "cloud-profile")]
+fn allocate_slab(size: usize) -> MapResult<Slab> {
+    Ok(vec![0u8; size])
+}
+
+/// Allocate a zeroed slab of `size` bytes.
+#[cfg(all(feature = "embedded-profile", not(feature = "cloud-profile")))]
+fn allocate_slab(size: usize) -> MapResult<Slab> {
+    super::StaticPool::allocate(size).ok_or(MapError::OutOfMemory)
+}
+
+/// Sizes of the slab regions for a map definition.
+struct Layout {
+    bucket_count: usize,
+    node_size: usize,
+    value_offset: usize,
+    nodes_offset: usize,
+    total_size: usize,
+}
+
+impl Layout {
+    fn new(key_size: usize, value_size: usize, capacity: usize) -> Self {
+        let bucket_count = capacity.next_power_of_two();
+        // Keep values 8-byte aligned within the slab
+        let value_offset = NODE_HEADER_SIZE + key_size.next_multiple_of(8);
+        let node_size = (value_offset + value_size).next_multiple_of(8);
+        let nodes_offset = (bucket_count * 4).next_multiple_of(8);
+        Self {
+            bucket_count,
+            node_size,
+            value_offset,
+            nodes_offset,
+            total_size: nodes_offset + node_size * capacity,
+        }
+    }
+}
+
+/// Internal storage for the LRU hash map.
+struct LruStorage {
+    slab: Slab,
+    layout: Layout,
+    key_size: usize,
+    value_size: usize,
+    capacity: usize,
+    /// Number of used nodes
+    count: usize,
+    /// Most recently used node
+    head: u32,
+    /// Least recently used node
+    tail: u32,
+    /// First unused node
+    free: u32,
+}
+
+impl LruStorage {
+    fn new(key_size: usize, value_size: usize, capacity: usize) -> MapResult<Self> {
+        let layout = Layout::new(key_size, value_size, capacity);
+        let slab = allocate_slab(layout.total_size)?;
+
+        let mut storage = Self {
+            slab,
+            layout,
+            key_size,
+            value_size,
+            capacity,
+            count: 0,
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+        };
+
+        for bucket in 0..storage.layout.bucket_count {
+            storage.set_u32(bucket * 4, NIL);
+        }
+        // Thread every node onto the free list
+        for node in (0..capacity as u32).rev() {
+            storage.set_field(node, USED, 0);
+            storage.set_field(node, NEXT, storage.free);
+            storage.free = node;
+        }
+
+        Ok(storage)
+    }
+
+    fn get_u32(&self, offset: usize) -> u32 {
+        u32::from_ne_bytes(self.slab[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.slab[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    fn node_offset(&self, node: u32) -> usize {
+        self.layout.nodes_offset + node as usize * self.layout.node_size
+    }
+
+    fn field(&self, node: u32, field: usize) -> u32 {
+        self.get_u32(self.node_offset(node) + field)
+    }
+
+    fn set_field(&mut self, node: u32, field: usize, value: u32) {
+        self.set_u32(self.node_offset(node) + field, value);
+    }
+
+    fn key(&self, node: u32) -> &[u8] {
+        let start = self.node_offset(node) + NODE_HEADER_SIZE;
+        &self.slab[start..start + self.key_size]
+    }
+
+    fn value(&self, node: u32) -> &[u8] {
+        let start = self.node_offset(node) + self.layout.value_offset;
+        &self.slab[start..start + self.value_size]
+    }
+
+    fn value_mut(&mut self, node: u32) -> &mut [u8] {
+        let start = self.node_offset(node) + self.layout.value_offset;
+        &mut self.slab[start..start + self.value_size]
+    }
+
+    fn bucket_offset(&self, key: &[u8]) -> usize {
+        (hash_key(key) & (self.layout.bucket_count - 1)) * 4
+    }
+
+    /// Find the node holding `key`.
+    fn find(&self, key: &[u8]) -> Option<u32> {
+        if key.len() != self.key_size {
+            return None;
+        }
+
+        let mut node = self.get_u32(self.bucket_offset(key));
+        while node != NIL {
+            if self.key(node) == key {
+                return Some(node);
+            }
+            node = self.field(node, CHAIN);
+        }
+        None
+    }
+
+    /// Remove `node` from its bucket's chain.
+    fn unchain(&mut self, node: u32) {
+        let bucket = self.bucket_offset(self.key(node));
+        let next = self.field(node, CHAIN);
+
+        let mut current = self.get_u32(bucket);
+        if current == node {
+            self.set_u32(bucket, next);
+            return;
+        }
+        while current != NIL {
+            let following = self.field(current, CHAIN);
+            if following == node {
+                self.set_field(current, CHAIN, next);
+                return;
+            }
+            current = following;
+        }
+    }
+
+    /// Remove `node` from the recency list.
+    fn unlink(&mut self, node: u32) {
+        let prev = self.field(node, PREV);
+        let next = self.field(node, NEXT);
+
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.set_field(prev, NEXT, next);
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.set_field(next, PREV, prev);
+        }
+    }
+
+    /// Make `node` the most recently used one.
+    fn push_front(&mut self, node: u32) {
+        self.set_field(node, PREV, NIL);
+        self.set_field(node, NEXT, self.head);
+        if self.head == NIL {
+            self.tail = node;
+        } else {
+            self.set_field(self.head, PREV, node);
+        }
+        self.head = node;
+    }
+
+    /// Mark `node` as used.
+    fn touch(&mut self, node: u32) {
+        if self.head != node {
+            self.unlink(node);
+            self.push_front(node);
+        }
+    }
+
+    /// Take an unused node, evicting the least recently used one if the
+    /// map is full.
+    fn take_node(&mut self) -> u32 {
+        if self.free != NIL {
+            let node = self.free;
+            self.free = self.field(node, NEXT);
+            self.count += 1;
+            return node;
+        }
+
+        let node = self.tail;
+        self.unchain(node);
+        self.unlink(node);
+        node
+    }
+
+    /// Look up `key` and mark it as used.
+    fn lookup(&mut self, key: &[u8]) -> Option<u32> {
+        let node = self.find(key)?;
+        self.touch(node);
+        Some(node)
+    }
+
+    fn update(&mut self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()> {
+        if key.len() != self.key_size {
+            return Err(MapError::InvalidKey);
+        }
+        if value.len() != self.value_size {
+            return Err(MapError::InvalidValue);
+        }
+
+        let existing = self.find(key);
+
+        // BPF_NOEXIST (1): fail if key exists
+        if flags == 1 && existing.is_some() {
+            return Err(MapError::KeyExists);
+        }
+
+        // BPF_EXIST (2): fail if key doesn't exist
+        if flags == 2 && existing.is_none() {
+            return Err(MapError::KeyNotFound);
+        }
+
+        let node = match existing {
+            Some(node) => {
+                self.touch(node);
+                node
+            }
+            None => {
+                let node = self.take_node();
+                let start = self.node_offset(node) + NODE_HEADER_SIZE;
+                self.slab[start..start + self.key_size].copy_from_slice(key);
+
+                let bucket = self.bucket_offset(key);
+                self.set_field(node, CHAIN, self.get_u32(bucket));
+                self.set_u32(bucket, node);
+                self.set_field(node, USED, 1);
+                self.push_front(node);
+                node
+            }
+        };
+        self.value_mut(node).copy_from_slice(value);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> MapResult<()> {
+        if key.len() != self.key_size {
+            return Err(MapError::InvalidKey);
+        }
+        let node = self.find(key).ok_or(MapError::KeyNotFound)?;
+
+        self.unchain(node);
+        self.unlink(node);
+        self.set_field(node, USED, 0);
+        self.set_field(node, NEXT, self.free);
+        self.free = node;
+        self.count -= 1;
+
+        Ok(())
+    }
+
+    /// Copy up to `max` entries in node order, starting after `key`.
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let start = key
+            .and_then(|key| self.find(key))
+            .map_or(0, |node| node + 1);
+
+        (start..self.capacity as u32)
+            .filter(|&node| self.field(node, USED) != 0)
+            .take(max)
+            .map(|node| (self.key(node).to_vec(), self.value(node).to_vec()))
+            .collect()
+    }
+
+    /// Keys in recency order, most recently used first.
+    #[cfg(feature = "cloud-profile")]
+    fn recency(&self) -> Vec<u32> {
+        let mut nodes = Vec::with_capacity(self.count);
+        let mut node = self.head;
+        while node != NIL {
+            nodes.push(node);
+            node = self.field(node, NEXT);
+        }
+        nodes
+    }
+}
+
+/// LRU hash map implementation.
+///
+/// Like [`HashMap`](super::HashMap), but a full map evicts its least
+/// recently used entry to make room for a new key.
+pub struct LruHashMap<P: PhysicalProfile = ActiveProfile> {
+    /// Map definition
+    def: MapDef,
+    /// Storage; lookups update the recency list, so they lock exclusively
+    storage: Mutex<LruStorage>,
+    /// Profile marker
+    _profile: PhantomData<fn() -> P>,
+}
+
+impl<P: PhysicalProfile> LruHashMap<P> {
+    /// Create a new LRU hash map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map definition is invalid or the memory for
+    /// the map can't be allocated.
+    pub fn new(def: MapDef) -> MapResult<Self> {
+        if def.map_type != MapType::LruHash {
+            return Err(MapError::InvalidMapType);
+        }
+
+        if def.key_size == 0 {
+            return Err(MapError::InvalidKey);
+        }
+
+        if def.value_size == 0 || def.max_entries == 0 || def.max_entries == NIL {
+            return Err(MapError::InvalidValue);
+        }
+
+        let key_size = def.key_size as usize;
+        let value_size = def.value_size as usize;
+        let capacity = def.max_entries as usize;
+
+        // Check memory budget for embedded profile
+        #[cfg(feature = "embedded-profile")]
+        {
+            use crate::profile::MemoryStrategy;
+            let budget = <P::MemoryStrategy as MemoryStrategy>::MEMORY_BUDGET;
+            let size = Layout::new(key_size, value_size, capacity).total_size;
+            if budget > 0 && size > budget {
+                return Err(MapError::OutOfMemory);
+            }
+        }
+
+        let storage = LruStorage::new(key_size, value_size, capacity)?;
+
+        Ok(Self {
+            def,
+            storage: Mutex::new(storage),
+            _profile: PhantomData,
+        })
+    }
+
+    /// Create an LRU hash map with specified sizes.
+    pub fn with_sizes(key_size: u32, value_size: u32, max_entries: u32) -> MapResult<Self> {
+        let def = MapDef::new(MapType::LruHash, key_size, value_size, max_entries);
+        Self::new(def)
+    }
+
+    /// Get the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.storage.lock().count
+    }
+
+    /// Check if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the capacity of the map.
+    pub fn capacity(&self) -> usize {
+        self.storage.lock().capacity
+    }
+}
+
+impl<P: PhysicalProfile> BpfMap<P> for LruHashMap<P> {
+    fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let mut guard = self.storage.lock();
+        let node = guard.lookup(key)?;
+        Some(guard.value(node).to_vec())
+    }
+
+    fn update(&self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()> {
+        self.storage.lock().update(key, value, flags)
+    }
+
+    fn delete(&self, key: &[u8]) -> MapResult<()> {
+        self.storage.lock().delete(key)
+    }
+
+    fn def(&self) -> &MapDef {
+        &self.def
+    }
+
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.storage.lock().entries_after(key, max))
+    }
+
+    fn lookup_and_delete(&self, key: &[u8]) -> MapResult<Vec<u8>> {
+        let mut guard = self.storage.lock();
+        let node = guard.find(key).ok_or(MapError::KeyNotFound)?;
+        let value = guard.value(node).to_vec();
+        guard.delete(key)?;
+        Ok(value)
+    }
+
+    /// # Safety
+    /// This method returns a raw pointer to the map value. The caller must ensure
+    /// that the pointer is not used after the map is modified or dropped. Note
+    /// that an update of another key may evict this one and reuse its memory.
+    unsafe fn lookup_ptr(&self, key: &[u8]) -> Option<*mut u8> {
+        let mut guard = self.storage.lock();
+        let node = guard.lookup(key)?;
+        Some(guard.value_mut(node).as_mut_ptr())
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
+        if new_max_entries == 0 || new_max_entries == NIL {
+            return Err(MapError::InvalidValue);
+        }
+
+        let storage = self.storage.get_mut();
+        let mut resized = LruStorage::new(
+            storage.key_size,
+            storage.value_size,
+            new_max_entries as usize,
+        )?;
+
+        // Reinsert from least to most recently used, so that shrinking
+        // evicts the least recently used entries and recency is kept
+        for node in storage.recency().into_iter().rev() {
+            resized.update(storage.key(node), storage.value(node), 0)?;
+        }
+
+        *storage = resized;
+        self.def.max_entries = new_max_entries;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: u32) -> [u8; 4] {
+        i.to_ne_bytes()
+    }
+
+    #[test]
+    fn lru_hash_operations() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 8, 16).expect("create map");
+        assert!(map.is_empty());
+
+        map.update(&key(1), &10u64.to_ne_bytes(), 0)
+            .expect("insert");
+        assert_eq!(map.lookup(&key(1)), Some(10u64.to_ne_bytes().to_vec()));
+
+        map.update(&key(1), &20u64.to_ne_bytes(), 0)
+            .expect("update");
+        assert_eq!(map.lookup(&key(1)), Some(20u64.to_ne_bytes().to_vec()));
+        assert_eq!(map.len(), 1);
+
+        assert_eq!(map.update(&key(1), &[0; 8], 1), Err(MapError::KeyExists));
+        assert_eq!(map.update(&key(2), &[0; 8], 2), Err(MapError::KeyNotFound));
+
+        map.delete(&key(1)).expect("delete");
+        assert!(map.lookup(&key(1)).is_none());
+        assert_eq!(map.delete(&key(1)), Err(MapError::KeyNotFound));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn lru_hash_evicts_least_recently_used() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 4, 3).expect("create map");
+        for i in 0..3 {
+            map.update(&key(i), &key(i), 0).expect("insert");
+        }
+
+        // Key 0 becomes the most recently used one, so key 1 is evicted
+        assert!(map.lookup(&key(0)).is_some());
+        map.update(&key(3), &key(3), 0)
+            .expect("insert into full map");
+
+        assert_eq!(map.len(), 3);
+        assert!(map.lookup(&key(1)).is_none());
+        for i in [0, 2, 3] {
+            assert_eq!(map.lookup(&key(i)), Some(key(i).to_vec()));
+        }
+
+        // Updates count as a use too
+        map.update(&key(0), &key(7), 2).expect("update");
+        map.update(&key(4), &key(4), 0)
+            .expect("insert into full map");
+        assert!(map.lookup(&key(2)).is_none());
+        assert_eq!(map.lookup(&key(0)), Some(key(7).to_vec()));
+    }
+
+    #[test]
+    fn lru_hash_reuses_deleted_nodes() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 4, 2).expect("create map");
+        map.update(&key(0), &key(0), 0).expect("insert");
+        map.update(&key(1), &key(1), 0).expect("insert");
+        map.delete(&key(0)).expect("delete");
+
+        // A free node is used before anything is evicted
+        map.update(&key(2), &key(2), 0).expect("insert");
+        assert!(map.lookup(&key(1)).is_some());
+        assert!(map.lookup(&key(2)).is_some());
+    }
+
+    #[test]
+    fn lru_hash_many_entries() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 4, 64).expect("create map");
+        for i in 0u32..1000 {
+            map.update(&key(i), &(i * 2).to_ne_bytes(), 0)
+                .expect("insert");
+        }
+
+        // Only the 64 most recent keys are left
+        assert_eq!(map.len(), 64);
+        for i in 0u32..936 {
+            assert!(map.lookup(&key(i)).is_none());
+        }
+        for i in 936u32..1000 {
+            assert_eq!(map.lookup(&key(i)), Some((i * 2).to_ne_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn lru_hash_iteration() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 4, 8).expect("create map");
+        for i in 0..5 {
+            map.update(&key(i), &key(i * 3), 0).expect("insert");
+        }
+        map.delete(&key(3)).expect("delete");
+
+        let mut keys = Vec::new();
+        let mut next = map.get_next_key(None);
+        while let Ok(k) = next {
+            keys.push(u32::from_ne_bytes(k.as_slice().try_into().unwrap()));
+            next = map.get_next_key(Some(&k));
+        }
+        keys.sort_unstable();
+        assert_eq!(keys, [0, 1, 2, 4]);
+
+        assert_eq!(map.lookup_and_delete(&key(4)), Ok(key(12).to_vec()));
+        assert!(map.lookup(&key(4)).is_none());
+    }
+
+    #[test]
+    fn lru_hash_lookup_ptr() {
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 8, 4).expect("create map");
+        map.update(&key(1), &5u64.to_ne_bytes(), 0).expect("insert");
+
+        // SAFETY: the map is not modified while the pointer is used
+        let ptr = unsafe { map.lookup_ptr(&key(1)) }.expect("lookup_ptr");
+        // SAFETY: the pointer points at the 8-byte value of key 1
+        unsafe { (ptr as *mut u64).write_unaligned(6) };
+        assert_eq!(map.lookup(&key(1)), Some(6u64.to_ne_bytes().to_vec()));
+    }
+
+    #[test]
+    fn lru_hash_invalid_sizes() {
+        assert!(matches!(
+            LruHashMap::<ActiveProfile>::with_sizes(0, 8, 16),
+            Err(MapError::InvalidKey)
+        ));
+        assert!(matches!(
+            LruHashMap::<ActiveProfile>::with_sizes(4, 0, 16),
+            Err(MapError::InvalidValue)
+        ));
+        assert!(matches!(
+            LruHashMap::<ActiveProfile>::with_sizes(4, 8, 0),
+            Err(MapError::InvalidValue)
+        ));
+
+        let map = LruHashMap::<ActiveProfile>::with_sizes(4, 8, 16).expect("create map");
+        assert_eq!(
+            map.update(&[1, 2, 3], &[0; 8], 0),
+            Err(MapError::InvalidKey)
+        );
+        assert_eq!(map.update(&key(1), &[0; 3], 0), Err(MapError::InvalidValue));
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    #[test]
+    fn lru_hash_resize() {
+        let mut map = LruHashMap::<ActiveProfile>::with_sizes(4, 4, 4).expect("create map");
+        for i in 0..4 {
+            map.update(&key(i), &key(i), 0).expect("insert");
+        }
+        assert!(map.lookup(&key(0)).is_some());
+
+        // Shrinking keeps the most recently used entries
+        map.resize(2).expect("shrink");
+        assert_eq!(map.capacity(), 2);
+        assert_eq!(map.len(), 2);
+        assert!(map.lookup(&key(0)).is_some());
+        assert!(map.lookup(&key(3)).is_some());
+
+        map.resize(8).expect("grow");
+        for i in 10..16 {
+            map.update(&key(i), &key(i), 0)
+                .expect("insert after resize");
+        }
+        assert_eq!(map.len(), 8);
+        assert!(map.lookup(&key(0)).is_some());
+    }
+}
@@ -22,6 +22,9 @@ extern crate alloc;
 
 mod array;
 mod hash;
+mod lru_hash;
+mod percpu;
+mod prog_array;
 mod ringbuf;
 mod timeseries;
 
@@ -29,14 +32,21 @@ mod timeseries;
 mod static_pool;
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 pub use array::ArrayMap;
 pub use hash::HashMap;
-pub use ringbuf::{RingBufMap, RingBufReservation};
+pub use lru_hash::LruHashMap;
+pub use percpu::{PerCpuArrayMap, PerCpuHashMap};
+pub use prog_array::ProgArrayMap;
+pub use ringbuf::{
+    BPF_RB_FORCE_WAKEUP, BPF_RB_NO_WAKEUP, RINGBUF_CONSUMER_OFFSET, RINGBUF_DATA_OFFSET,
+    RINGBUF_PAGE_SIZE, RINGBUF_PRODUCER_OFFSET, RingBufMap, RingBufReservation,
+};
 use spin::RwLock;
 #[cfg(feature = "embedded-profile")]
 pub use static_pool::StaticPool;
-pub use timeseries::{TimeSeriesMap, TimeSeriesStats};
+pub use timeseries::{TimeSeriesAggregate, TimeSeriesMap, TimeSeriesStats};
 
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
@@ -62,8 +72,7 @@ pub enum MapType {
     StackTrace = 7,
     /// Cgroup array
     CgroupArray = 8,
-    /// LRU hash (cloud only)
-    #[cfg(feature = "cloud-profile")]
+    /// LRU hash
     LruHash = 9,
     /// LRU per-CPU hash (cloud only)
     #[cfg(feature = "cloud-profile")]
@@ -167,7 +176,7 @@ pub trait BpfMap<P: PhysicalProfile = ActiveProfile>: Send + Sync {
     /// Look up a value by key.
     ///
     /// Returns `None` if the key is not found.
-    fn lookup(&self, key: &[u8]) -> Option<alloc::vec::Vec<u8>>;
+    fn lookup(&self, key: &[u8]) -> Option<Vec<u8>>;
 
     /// Update a value for a key.
     ///
@@ -178,12 +187,63 @@ pub trait BpfMap<P: PhysicalProfile = ActiveProfile>: Send + Sync {
     /// * `flags` - Update flags (0 = any, 1 = no exist, 2 = exist)
     fn update(&self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()>;
 
+    /// Update a value from a BPF program.
+    ///
+    /// Per-CPU maps only update the value of the CPU the program runs on;
+    /// every other map behaves like [`update`](Self::update).
+    fn update_from_prog(&self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()> {
+        self.update(key, value, flags)
+    }
+
     /// Delete a key from the map.
     fn delete(&self, key: &[u8]) -> MapResult<()>;
 
     /// Get the map definition.
     fn def(&self) -> &MapDef;
 
+    /// Size of a value as userspace looks it up and updates it.
+    ///
+    /// This is the value size, except for per-CPU maps, which hold the
+    /// values of all CPUs, each padded to 8 bytes.
+    fn user_value_size(&self) -> usize {
+        self.def().value_size as usize
+    }
+
+    /// Copy up to `max` entries, as `(key, value)` pairs, in iteration order.
+    ///
+    /// Iteration continues after `key`. It starts at the first entry when
+    /// `key` is `None` or not in the map (e.g. because it was deleted). Fewer
+    /// than `max` entries means the iteration is complete.
+    ///
+    /// Maps that can't be iterated return [`MapError::NotSupported`].
+    fn entries_after(
+        &self,
+        _key: Option<&[u8]>,
+        _max: usize,
+    ) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        Err(MapError::NotSupported)
+    }
+
+    /// Get the key that follows `key` in iteration order.
+    ///
+    /// Returns the first key when `key` is `None` or not in the map, and
+    /// [`MapError::KeyNotFound`] when `key` is the last one.
+    fn get_next_key(&self, key: Option<&[u8]>) -> MapResult<Vec<u8>> {
+        self.entries_after(key, 1)?
+            .pop()
+            .map(|(next_key, _)| next_key)
+            .ok_or(MapError::KeyNotFound)
+    }
+
+    /// Look up a value and delete its key.
+    ///
+    /// Maps whose entries can't be deleted return [`MapError::NotSupported`].
+    fn lookup_and_delete(&self, key: &[u8]) -> MapResult<Vec<u8>> {
+        let value = self.lookup(key).ok_or(MapError::KeyNotFound)?;
+        self.delete(key)?;
+        Ok(value)
+    }
+
     /// Look up a value by key and return a raw pointer.
     ///
     /// # Safety
@@ -193,6 +253,24 @@ pub trait BpfMap<P: PhysicalProfile = ActiveProfile>: Send + Sync {
         None
     }
 
+    /// This map as a ring buffer, for the operations only ring buffers have
+    /// (memory-mapping and waiting for records).
+    fn as_ringbuf(&self) -> Option<&RingBufMap<P>> {
+        None
+    }
+
+    /// This map as a program array, whose slots are set from program file
+    /// descriptors rather than raw values.
+    fn as_prog_array(&self) -> Option<&ProgArrayMap<P>> {
+        None
+    }
+
+    /// This map as a time-series, for the aggregates only time-series maps
+    /// compute.
+    fn as_timeseries(&self) -> Option<&TimeSeriesMap<P>> {
+        None
+    }
+
     /// Resize the map (cloud profile only).
     ///
     /// This method is completely erased from embedded builds.
@@ -229,7 +307,7 @@ impl<P: PhysicalProfile> MapHandle<P> {
     }
 
     /// Look up a value.
-    pub fn lookup(&self, key: &[u8]) -> Option<alloc::vec::Vec<u8>> {
+    pub fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
         self.inner.read().lookup(key)
     }
 
@@ -0,0 +1,747 @@
+//! Per-CPU Map Implementations
+//!
+//! Per-CPU maps keep one copy of every value for each CPU. A BPF program
+//! only sees the copy of the CPU it runs on, so hooks that fire on every
+//! CPU at high frequency (timer tick, syscall tracing) update their values
+//! without taking a lock or bouncing cache lines between CPUs.
+//!
+//! Userspace sees all copies at once: a lookup returns the values of all
+//! CPUs concatenated, in CPU order, and an update takes one value for each
+//! CPU in the same layout. As on Linux, every value in that layout is
+//! padded to a multiple of 8 bytes.
+//!
+//! # Memory Layout
+//!
+//! ```text
+//! ┌──────────────────────────┬──────────────────────────┬─────┐
+//! │ CPU 0                    │ CPU 1                    │ ... │
+//! │ ┌───────┬───────┬─────┐  │ ┌───────┬───────┬─────┐  │     │
+//! │ │slot 0 │slot 1 │ ... │  │ │slot 0 │slot 1 │ ... │  │     │
+//! │ └───────┴───────┴─────┘  │ └───────┴───────┴─────┘  │     │
+//! └──────────────────────────┴──────────────────────────┴─────┘
+//! ```
+//!
+//! Each CPU's slots are cache line aligned, so no two CPUs write to the
+//! same cache line. An array map uses the key as the slot index; a hash map
+//! keeps a [`HashMap`] from keys to slot indices.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+
+use spin::Mutex;
+
+use super::{BpfMap, HashMap, MapDef, MapError, MapResult, MapType};
+use crate::profile::{ActiveProfile, PhysicalProfile};
+
+// SAFETY: This function is defined in the kernel and linked into the final binary.
+// In host-based tests, it is provided by `execution::helpers_stub`.
+unsafe extern "C" {
+    fn bpf_get_smp_processor_id() -> u32;
+}
+
+/// Index of the CPU the caller runs on.
+fn current_cpu() -> usize {
+    // SAFETY: the kernel's implementation only reads the CPU's own context.
+    unsafe { bpf_get_smp_processor_id() as usize }
+}
+
+/// Cache line size the per-CPU regions are aligned to.
+const CACHE_LINE: usize = 64;
+
+/// One cache line of values.
+#[repr(C, align(64))]
+struct CacheLine(UnsafeCell<[u8; CACHE_LINE]>);
+
+/// Per-CPU value storage shared by both map types.
+struct PerCpuValues {
+    /// `num_cpus` regions of `entries` slots each
+    data: Box<[CacheLine]>,
+    value_size: usize,
+    /// Size of a slot, `value_size` rounded up to 8 bytes
+    stride: usize,
+    /// Size of one CPU's region
+    cpu_size: usize,
+    entries: usize,
+    num_cpus: usize,
+}
+
+// SAFETY: Each CPU only writes to its own region from BPF programs. Userspace
+// reads and writes may race with them, which at worst yields a torn value,
+// as on Linux.
+unsafe impl Sync for PerCpuValues {}
+
+impl PerCpuValues {
+    fn new(value_size: usize, entries: usize, num_cpus: usize) -> Self {
+        let stride = value_size.next_multiple_of(8);
+        let cpu_size = (stride * entries).next_multiple_of(CACHE_LINE);
+        let data = (0..cpu_size / CACHE_LINE * num_cpus)
+            .map(|_| CacheLine(UnsafeCell::new([0; CACHE_LINE])))
+            .collect();
+        Self {
+            data,
+            value_size,
+            stride,
+            cpu_size,
+            entries,
+            num_cpus,
+        }
+    }
+
+    /// Bytes needed for `entries` slots on `num_cpus` CPUs.
+    #[cfg(feature = "embedded-profile")]
+    fn size(value_size: usize, entries: usize, num_cpus: usize) -> usize {
+        (value_size.next_multiple_of(8) * entries).next_multiple_of(CACHE_LINE) * num_cpus
+    }
+
+    /// Size of the userspace view of a value, with the values of all CPUs.
+    fn user_value_size(&self) -> usize {
+        self.stride * self.num_cpus
+    }
+
+    /// Pointer to the value of slot `index` on `cpu`.
+    fn slot(&self, cpu: usize, index: usize) -> Option<*mut u8> {
+        if cpu >= self.num_cpus || index >= self.entries {
+            return None;
+        }
+        let offset = cpu * self.cpu_size + index * self.stride;
+        let data = self.data.as_ptr().cast::<u8>().cast_mut();
+        // SAFETY: the offset is within the data, and all of it is in
+        // `UnsafeCell`s, so it may be written through a shared reference.
+        Some(unsafe { data.add(offset) })
+    }
+
+    fn read(&self, cpu: usize, index: usize) -> Option<Vec<u8>> {
+        let slot = self.slot(cpu, index)?;
+        // SAFETY: the slot holds `value_size` bytes.
+        Some(unsafe { core::slice::from_raw_parts(slot, self.value_size) }.to_vec())
+    }
+
+    fn write(&self, cpu: usize, index: usize, value: &[u8]) -> MapResult<()> {
+        if value.len() != self.value_size {
+            return Err(MapError::InvalidValue);
+        }
+        let slot = self.slot(cpu, index).ok_or(MapError::InvalidKey)?;
+        // SAFETY: the slot holds `value_size` bytes.
+        unsafe { core::ptr::copy_nonoverlapping(value.as_ptr(), slot, value.len()) };
+        Ok(())
+    }
+
+    /// The values of slot `index` on all CPUs, each padded to the stride.
+    fn read_all(&self, index: usize) -> Option<Vec<u8>> {
+        let mut values = vec![0u8; self.user_value_size()];
+        for (cpu, value) in values.chunks_exact_mut(self.stride).enumerate() {
+            let slot = self.slot(cpu, index)?;
+            // SAFETY: the slot holds `value_size` bytes.
+            let src = unsafe { core::slice::from_raw_parts(slot, self.value_size) };
+            value[..self.value_size].copy_from_slice(src);
+        }
+        Some(values)
+    }
+
+    /// Set slot `index` on all CPUs from the userspace layout.
+    fn write_all(&self, index: usize, values: &[u8]) -> MapResult<()> {
+        if values.len() != self.user_value_size() {
+            return Err(MapError::InvalidValue);
+        }
+        for (cpu, value) in values.chunks_exact(self.stride).enumerate() {
+            self.write(cpu, index, &value[..self.value_size])?;
+        }
+        Ok(())
+    }
+
+    /// Zero slot `index` on all CPUs.
+    fn clear(&self, index: usize) {
+        for cpu in 0..self.num_cpus {
+            if let Some(slot) = self.slot(cpu, index) {
+                // SAFETY: the slot holds `stride` bytes.
+                unsafe { core::ptr::write_bytes(slot, 0, self.stride) };
+            }
+        }
+    }
+}
+
+/// Check the sizes shared by both per-CPU map types.
+fn validate(def: &MapDef, num_cpus: usize) -> MapResult<()> {
+    if def.value_size == 0 || def.max_entries == 0 || num_cpus == 0 {
+        return Err(MapError::InvalidValue);
+    }
+    Ok(())
+}
+
+/// Check the memory budget for embedded profile.
+#[cfg(feature = "embedded-profile")]
+fn check_budget<P: PhysicalProfile>(def: &MapDef, num_cpus: usize) -> MapResult<()> {
+    use crate::profile::MemoryStrategy;
+    let budget = <P::MemoryStrategy as MemoryStrategy>::MEMORY_BUDGET;
+    let size = PerCpuValues::size(def.value_size as usize, def.max_entries as usize, num_cpus);
+    if budget > 0 && size > budget {
+        return Err(MapError::OutOfMemory);
+    }
+    Ok(())
+}
+
+/// Per-CPU array map implementation.
+///
+/// Like [`ArrayMap`](super::ArrayMap), but with one value per CPU for
+/// every index.
+pub struct PerCpuArrayMap<P: PhysicalProfile = ActiveProfile> {
+    /// Map definition
+    def: MapDef,
+    /// Values of all CPUs
+    values: PerCpuValues,
+    /// Profile marker
+    _profile: PhantomData<fn() -> P>,
+}
+
+impl<P: PhysicalProfile> PerCpuArrayMap<P> {
+    /// Create a new per-CPU array map for `num_cpus` CPUs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map definition is invalid.
+    pub fn new(def: MapDef, num_cpus: usize) -> MapResult<Self> {
+        if def.map_type != MapType::PerCpuArray {
+            return Err(MapError::InvalidMapType);
+        }
+
+        if def.key_size != 4 {
+            // Array maps use u32 keys
+            return Err(MapError::InvalidKey);
+        }
+
+        validate(&def, num_cpus)?;
+        #[cfg(feature = "embedded-profile")]
+        check_budget::<P>(&def, num_cpus)?;
+
+        let values = PerCpuValues::new(def.value_size as usize, def.max_entries as usize, num_cpus);
+
+        Ok(Self {
+            def,
+            values,
+            _profile: PhantomData,
+        })
+    }
+
+    /// Create a per-CPU array map with default parameters.
+    pub fn with_entries(value_size: u32, max_entries: u32, num_cpus: usize) -> MapResult<Self> {
+        let def = MapDef::new(MapType::PerCpuArray, 4, value_size, max_entries);
+        Self::new(def, num_cpus)
+    }
+
+    /// Number of CPUs the map holds values for.
+    pub fn num_cpus(&self) -> usize {
+        self.values.num_cpus
+    }
+
+    /// Look up the value of `cpu`.
+    pub fn lookup_on_cpu(&self, key: &[u8], cpu: usize) -> Option<Vec<u8>> {
+        self.values.read(cpu, Self::parse_key(key)? as usize)
+    }
+
+    /// Update the value of `cpu`.
+    pub fn update_on_cpu(&self, key: &[u8], value: &[u8], cpu: usize) -> MapResult<()> {
+        let index = Self::parse_key(key).ok_or(MapError::InvalidKey)?;
+        self.values.write(cpu, index as usize, value)
+    }
+
+    /// Parse key bytes as u32 index.
+    fn parse_key(key: &[u8]) -> Option<u32> {
+        Some(u32::from_ne_bytes(key.try_into().ok()?))
+    }
+}
+
+impl<P: PhysicalProfile> BpfMap<P> for PerCpuArrayMap<P> {
+    fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.values.read_all(Self::parse_key(key)? as usize)
+    }
+
+    fn update(&self, key: &[u8], value: &[u8], _flags: u64) -> MapResult<()> {
+        let index = Self::parse_key(key).ok_or(MapError::InvalidKey)?;
+        self.values.write_all(index as usize, value)
+    }
+
+    fn update_from_prog(&self, key: &[u8], value: &[u8], _flags: u64) -> MapResult<()> {
+        self.update_on_cpu(key, value, current_cpu())
+    }
+
+    fn delete(&self, _key: &[u8]) -> MapResult<()> {
+        // Array maps don't support delete (values persist until overwritten)
+        Err(MapError::NotSupported)
+    }
+
+    fn def(&self) -> &MapDef {
+        &self.def
+    }
+
+    fn user_value_size(&self) -> usize {
+        self.values.user_value_size()
+    }
+
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let entries = self.values.entries;
+
+        // Iteration restarts at 0 for indices past the end, as in Linux
+        let start = key
+            .and_then(Self::parse_key)
+            .map(|index| index as usize + 1)
+            .filter(|&index| index <= entries)
+            .unwrap_or(0);
+
+        Ok((start..entries)
+            .take(max)
+            .filter_map(|index| {
+                let values = self.values.read_all(index)?;
+                Some(((index as u32).to_ne_bytes().to_vec(), values))
+            })
+            .collect())
+    }
+
+    /// # Safety
+    /// This method returns a raw pointer to the value of the calling CPU. The
+    /// caller must not use it after the map is dropped or from another CPU.
+    unsafe fn lookup_ptr(&self, key: &[u8]) -> Option<*mut u8> {
+        self.values
+            .slot(current_cpu(), Self::parse_key(key)? as usize)
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
+        if new_max_entries == 0 {
+            return Err(MapError::InvalidValue);
+        }
+
+        let resized = PerCpuValues::new(
+            self.values.value_size,
+            new_max_entries as usize,
+            self.values.num_cpus,
+        );
+        for index in 0..self.values.entries.min(resized.entries) {
+            if let Some(values) = self.values.read_all(index) {
+                resized.write_all(index, &values)?;
+            }
+        }
+
+        self.values = resized;
+        self.def.max_entries = new_max_entries;
+        Ok(())
+    }
+}
+
+/// Per-CPU hash map implementation.
+///
+/// Like [`HashMap`], but with one value per CPU for every key. Keys are
+/// shared by all CPUs: inserting or deleting one takes the index lock,
+/// while updating the value of an existing key does not.
+pub struct PerCpuHashMap<P: PhysicalProfile = ActiveProfile> {
+    /// Map definition
+    def: MapDef,
+    /// Slot index of every key
+    index: HashMap<P>,
+    /// Unused slots
+    free: Mutex<Vec<u32>>,
+    /// Values of all CPUs
+    values: PerCpuValues,
+}
+
+impl<P: PhysicalProfile> PerCpuHashMap<P> {
+    /// Create a new per-CPU hash map for `num_cpus` CPUs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map definition is invalid.
+    pub fn new(def: MapDef, num_cpus: usize) -> MapResult<Self> {
+        if def.map_type != MapType::PerCpuHash {
+            return Err(MapError::InvalidMapType);
+        }
+
+        if def.key_size == 0 {
+            return Err(MapError::InvalidKey);
+        }
+
+        validate(&def, num_cpus)?;
+        #[cfg(feature = "embedded-profile")]
+        check_budget::<P>(&def, num_cpus)?;
+
+        let index = HashMap::with_sizes(def.key_size, 4, def.max_entries)?;
+        let free = (0..def.max_entries).rev().collect();
+        let values = PerCpuValues::new(def.value_size as usize, def.max_entries as usize, num_cpus);
+
+        Ok(Self {
+            def,
+            index,
+            free: Mutex::new(free),
+            values,
+        })
+    }
+
+    /// Create a per-CPU hash map with specified sizes.
+    pub fn with_sizes(
+        key_size: u32,
+        value_size: u32,
+        max_entries: u32,
+        num_cpus: usize,
+    ) -> MapResult<Self> {
+        let def = MapDef::new(MapType::PerCpuHash, key_size, value_size, max_entries);
+        Self::new(def, num_cpus)
+    }
+
+    /// Get the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Check if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Number of CPUs the map holds values for.
+    pub fn num_cpus(&self) -> usize {
+        self.values.num_cpus
+    }
+
+    /// Look up the value of `cpu`.
+    pub fn lookup_on_cpu(&self, key: &[u8], cpu: usize) -> Option<Vec<u8>> {
+        self.values.read(cpu, self.slot(key)?)
+    }
+
+    /// Update the value of `cpu`.
+    ///
+    /// A new key starts out with zeroed values on all other CPUs.
+    pub fn update_on_cpu(&self, key: &[u8], value: &[u8], cpu: usize, flags: u64) -> MapResult<()> {
+        if value.len() != self.values.value_size {
+            return Err(MapError::InvalidValue);
+        }
+        self.upsert(key, flags, |slot| self.values.write(cpu, slot, value))
+    }
+
+    /// Slot holding the values of `key`.
+    fn slot(&self, key: &[u8]) -> Option<usize> {
+        if key.len() != self.def.key_size as usize {
+            return None;
+        }
+        // SAFETY: the slot index is copied out before the index can change.
+        let ptr = unsafe { self.index.lookup_ptr(key)? };
+        // SAFETY: index values are 4 bytes long.
+        Some(unsafe { (ptr as *const u32).read_unaligned() } as usize)
+    }
+
+    /// Insert or update `key`, then write its values with `write`.
+    fn upsert(
+        &self,
+        key: &[u8],
+        flags: u64,
+        write: impl Fn(usize) -> MapResult<()>,
+    ) -> MapResult<()> {
+        if key.len() != self.def.key_size as usize {
+            return Err(MapError::InvalidKey);
+        }
+
+        if let Some(slot) = self.slot(key) {
+            // BPF_NOEXIST (1): fail if key exists
+            if flags == 1 {
+                return Err(MapError::KeyExists);
+            }
+            return write(slot);
+        }
+
+        // BPF_EXIST (2): fail if key doesn't exist
+        if flags == 2 {
+            return Err(MapError::KeyNotFound);
+        }
+
+        let slot = self.free.lock().pop().ok_or(MapError::MapFull)?;
+        self.values.clear(slot as usize);
+        write(slot as usize)?;
+
+        match self.index.update(key, &slot.to_ne_bytes(), 1) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.free.lock().push(slot);
+                match e {
+                    // Inserted concurrently; update that entry instead
+                    MapError::KeyExists => self.upsert(key, flags, write),
+                    e => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Parse a slot index stored in the key index.
+    fn parse_slot(slot: &[u8]) -> Option<usize> {
+        Some(u32::from_ne_bytes(slot.try_into().ok()?) as usize)
+    }
+}
+
+impl<P: PhysicalProfile> BpfMap<P> for PerCpuHashMap<P> {
+    fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.values.read_all(self.slot(key)?)
+    }
+
+    fn update(&self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()> {
+        if value.len() != self.values.user_value_size() {
+            return Err(MapError::InvalidValue);
+        }
+        self.upsert(key, flags, |slot| self.values.write_all(slot, value))
+    }
+
+    fn update_from_prog(&self, key: &[u8], value: &[u8], flags: u64) -> MapResult<()> {
+        self.update_on_cpu(key, value, current_cpu(), flags)
+    }
+
+    fn delete(&self, key: &[u8]) -> MapResult<()> {
+        self.lookup_and_delete(key).map(|_| ())
+    }
+
+    fn def(&self) -> &MapDef {
+        &self.def
+    }
+
+    fn user_value_size(&self) -> usize {
+        self.values.user_value_size()
+    }
+
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .index
+            .entries_after(key, max)?
+            .into_iter()
+            .filter_map(|(key, slot)| {
+                let values = self.values.read_all(Self::parse_slot(&slot)?)?;
+                Some((key, values))
+            })
+            .collect())
+    }
+
+    fn lookup_and_delete(&self, key: &[u8]) -> MapResult<Vec<u8>> {
+        let slot = self.index.lookup_and_delete(key)?;
+        let slot = Self::parse_slot(&slot).ok_or(MapError::InvalidValue)?;
+        let values = self.values.read_all(slot).ok_or(MapError::InvalidValue)?;
+        self.free.lock().push(slot as u32);
+        Ok(values)
+    }
+
+    /// # Safety
+    /// This method returns a raw pointer to the value of the calling CPU. The
+    /// caller must not use it after the key is deleted or the map is dropped,
+    /// or from another CPU.
+    unsafe fn lookup_ptr(&self, key: &[u8]) -> Option<*mut u8> {
+        self.values.slot(current_cpu(), self.slot(key)?)
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
+        if (new_max_entries as usize) < self.len() {
+            return Err(MapError::InvalidValue);
+        }
+
+        // Rebuild the map, as slots above the new size may be in use
+        let mut def = self.def.clone();
+        def.max_entries = new_max_entries;
+        let resized = Self::new(def, self.values.num_cpus)?;
+        for (key, values) in self.entries_after(None, usize::MAX)? {
+            resized.update(&key, &values, 1)?;
+        }
+
+        *self = resized;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: u32) -> [u8; 4] {
+        i.to_ne_bytes()
+    }
+
+    #[test]
+    fn percpu_array_operations() {
+        let map = PerCpuArrayMap::<ActiveProfile>::with_entries(4, 8, 3).expect("create map");
+        assert_eq!(map.num_cpus(), 3);
+        // Values are padded to 8 bytes for userspace
+        assert_eq!(map.user_value_size(), 24);
+
+        map.update_on_cpu(&key(2), &7u32.to_ne_bytes(), 1)
+            .expect("update cpu 1");
+        assert_eq!(
+            map.lookup_on_cpu(&key(2), 1),
+            Some(7u32.to_ne_bytes().to_vec())
+        );
+        assert_eq!(map.lookup_on_cpu(&key(2), 0), Some(vec![0; 4]));
+
+        let mut expected = vec![0u8; 24];
+        expected[8..12].copy_from_slice(&7u32.to_ne_bytes());
+        assert_eq!(map.lookup(&key(2)), Some(expected));
+
+        // Userspace updates set every CPU
+        let mut values = vec![0u8; 24];
+        for cpu in 0..3 {
+            values[cpu * 8..cpu * 8 + 4].copy_from_slice(&(cpu as u32 + 10).to_ne_bytes());
+        }
+        map.update(&key(5), &values, 0).expect("update all cpus");
+        for cpu in 0..3 {
+            assert_eq!(
+                map.lookup_on_cpu(&key(5), cpu),
+                Some((cpu as u32 + 10).to_ne_bytes().to_vec())
+            );
+        }
+
+        assert_eq!(
+            map.update(&key(5), &[0; 12], 0),
+            Err(MapError::InvalidValue)
+        );
+        assert_eq!(
+            map.update_on_cpu(&key(8), &[0; 4], 0),
+            Err(MapError::InvalidKey)
+        );
+        assert!(map.lookup_on_cpu(&key(0), 3).is_none());
+        assert!(map.lookup(&key(8)).is_none());
+    }
+
+    #[test]
+    fn percpu_array_prog_view() {
+        let map = PerCpuArrayMap::<ActiveProfile>::with_entries(8, 4, 2).expect("create map");
+
+        // Programs see the value of the CPU they run on (CPU 0 in tests)
+        map.update_from_prog(&key(1), &5u64.to_ne_bytes(), 0)
+            .expect("update from prog");
+        assert_eq!(
+            map.lookup_on_cpu(&key(1), 0),
+            Some(5u64.to_ne_bytes().to_vec())
+        );
+        assert_eq!(map.lookup_on_cpu(&key(1), 1), Some(vec![0; 8]));
+
+        // SAFETY: the map outlives the pointer
+        let ptr = unsafe { map.lookup_ptr(&key(1)) }.expect("lookup_ptr");
+        // SAFETY: the pointer points at the 8-byte value of CPU 0
+        unsafe { (ptr as *mut u64).write_unaligned(6) };
+        assert_eq!(
+            map.lookup_on_cpu(&key(1), 0),
+            Some(6u64.to_ne_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn percpu_array_iteration() {
+        let map = PerCpuArrayMap::<ActiveProfile>::with_entries(4, 3, 2).expect("create map");
+        let keys: Vec<_> = map
+            .entries_after(None, 10)
+            .expect("iterate")
+            .into_iter()
+            .map(|(key, values)| {
+                assert_eq!(values.len(), 16);
+                key
+            })
+            .collect();
+        assert_eq!(keys, [key(0), key(1), key(2)]);
+    }
+
+    #[test]
+    fn percpu_hash_operations() {
+        let map = PerCpuHashMap::<ActiveProfile>::with_sizes(4, 8, 4, 2).expect("create map");
+        assert!(map.is_empty());
+
+        map.update_on_cpu(&key(1), &3u64.to_ne_bytes(), 1, 0)
+            .expect("insert on cpu 1");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.lookup_on_cpu(&key(1), 0), Some(vec![0; 8]));
+        assert_eq!(
+            map.lookup_on_cpu(&key(1), 1),
+            Some(3u64.to_ne_bytes().to_vec())
+        );
+
+        map.update_on_cpu(&key(1), &4u64.to_ne_bytes(), 0, 2)
+            .expect("update on cpu 0");
+        let mut expected = 4u64.to_ne_bytes().to_vec();
+        expected.extend_from_slice(&3u64.to_ne_bytes());
+        assert_eq!(map.lookup(&key(1)), Some(expected.clone()));
+
+        assert_eq!(
+            map.update_on_cpu(&key(1), &[0; 8], 0, 1),
+            Err(MapError::KeyExists)
+        );
+        assert_eq!(
+            map.update_on_cpu(&key(2), &[0; 8], 0, 2),
+            Err(MapError::KeyNotFound)
+        );
+
+        assert_eq!(map.lookup_and_delete(&key(1)), Ok(expected));
+        assert!(map.lookup(&key(1)).is_none());
+        assert_eq!(map.delete(&key(1)), Err(MapError::KeyNotFound));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn percpu_hash_slots_are_reused() {
+        let map = PerCpuHashMap::<ActiveProfile>::with_sizes(4, 4, 2, 2).expect("create map");
+        map.update(&key(1), &[1; 16], 0).expect("insert");
+        map.update(&key(2), &[2; 16], 0).expect("insert");
+        assert_eq!(map.update(&key(3), &[3; 16], 0), Err(MapError::MapFull));
+
+        map.delete(&key(1)).expect("delete");
+        map.update_from_prog(&key(3), &[3; 4], 0).expect("insert");
+
+        // The reused slot starts out zeroed on the other CPUs
+        assert_eq!(map.lookup_on_cpu(&key(3), 0), Some(vec![3; 4]));
+        assert_eq!(map.lookup_on_cpu(&key(3), 1), Some(vec![0; 4]));
+        assert_eq!(map.lookup_on_cpu(&key(2), 1), Some(vec![2; 4]));
+
+        let mut keys: Vec<_> = map
+            .entries_after(None, 10)
+            .expect("iterate")
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [key(2).to_vec(), key(3).to_vec()]);
+    }
+
+    #[test]
+    fn percpu_invalid_definitions() {
+        assert!(matches!(
+            PerCpuArrayMap::<ActiveProfile>::with_entries(4, 8, 0),
+            Err(MapError::InvalidValue)
+        ));
+        assert!(matches!(
+            PerCpuArrayMap::<ActiveProfile>::with_entries(0, 8, 2),
+            Err(MapError::InvalidValue)
+        ));
+        assert!(matches!(
+            PerCpuHashMap::<ActiveProfile>::with_sizes(0, 8, 8, 2),
+            Err(MapError::InvalidKey)
+        ));
+        assert!(matches!(
+            PerCpuArrayMap::<ActiveProfile>::new(MapDef::new(MapType::Array, 4, 8, 8), 2),
+            Err(MapError::InvalidMapType)
+        ));
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    #[test]
+    fn percpu_resize() {
+        let mut array = PerCpuArrayMap::<ActiveProfile>::with_entries(4, 2, 2).expect("create");
+        array.update_on_cpu(&key(1), &[9; 4], 1).expect("update");
+        array.resize(4).expect("grow");
+        assert_eq!(array.lookup_on_cpu(&key(1), 1), Some(vec![9; 4]));
+        assert!(array.lookup_on_cpu(&key(3), 1).is_some());
+
+        let mut hash = PerCpuHashMap::<ActiveProfile>::with_sizes(4, 4, 4, 2).expect("create");
+        for i in 0..3 {
+            hash.update_on_cpu(&key(i), &[i as u8; 4], 1, 0)
+                .expect("insert");
+        }
+        hash.delete(&key(0)).expect("delete");
+        assert_eq!(hash.resize(1), Err(MapError::InvalidValue));
+        hash.resize(2).expect("shrink");
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.lookup_on_cpu(&key(2), 1), Some(vec![2; 4]));
+        assert_eq!(hash.lookup_on_cpu(&key(2), 0), Some(vec![0; 4]));
+    }
+}
@@ -0,0 +1,287 @@
+//! Program Array Map Implementation
+//!
+//! A program array holds the programs `bpf_tail_call` can continue with,
+//! indexed by u32 keys. Userspace fills a slot by updating it with a
+//! program file descriptor, which the kernel resolves before calling
+//! [`ProgArrayMap::set`]; looking a slot up returns the program's ID.
+//! Replacing a slot swaps the stage of every chain going through it.
+//!
+//! Slots don't keep their programs loaded, so programs and the arrays they
+//! reference can't keep each other alive. A slot whose program is gone
+//! behaves like an empty one.
+
+extern crate alloc;
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use spin::RwLock;
+
+use super::{BpfMap, MapDef, MapError, MapResult, MapType};
+use crate::execution::TailCallTarget;
+use crate::profile::{ActiveProfile, PhysicalProfile};
+
+type Slot<P> = Option<Weak<dyn TailCallTarget<P>>>;
+
+/// Program array map implementation.
+pub struct ProgArrayMap<P: PhysicalProfile = ActiveProfile> {
+    /// Map definition
+    def: MapDef,
+    /// One slot per index
+    slots: RwLock<Vec<Slot<P>>>,
+    /// Profile marker (using fn pointer for Send + Sync)
+    _profile: PhantomData<fn() -> P>,
+}
+
+impl<P: PhysicalProfile> ProgArrayMap<P> {
+    /// Create a new program array.
+    ///
+    /// Keys and values are u32 indices and program IDs.
+    pub fn new(def: MapDef) -> MapResult<Self> {
+        if def.map_type != MapType::ProgArray {
+            return Err(MapError::InvalidMapType);
+        }
+        if def.key_size != 4 {
+            return Err(MapError::InvalidKey);
+        }
+        if def.value_size != 4 || def.max_entries == 0 {
+            return Err(MapError::InvalidValue);
+        }
+
+        #[cfg(feature = "embedded-profile")]
+        {
+            use crate::profile::MemoryStrategy;
+            let budget = <P::MemoryStrategy as MemoryStrategy>::MEMORY_BUDGET;
+            let size = def.max_entries as usize * core::mem::size_of::<Slot<P>>();
+            if budget > 0 && size > budget {
+                return Err(MapError::OutOfMemory);
+            }
+        }
+
+        let mut slots = Vec::new();
+        slots.resize_with(def.max_entries as usize, || None);
+
+        Ok(Self {
+            def,
+            slots: RwLock::new(slots),
+            _profile: PhantomData,
+        })
+    }
+
+    /// Create a program array with `max_entries` slots.
+    pub fn with_entries(max_entries: u32) -> MapResult<Self> {
+        Self::new(MapDef::new(MapType::ProgArray, 4, 4, max_entries))
+    }
+
+    /// Put `target` in slot `index`, replacing what was there.
+    pub fn set<T: TailCallTarget<P> + 'static>(
+        &self,
+        index: u32,
+        target: &Arc<T>,
+    ) -> MapResult<()> {
+        let target: Weak<dyn TailCallTarget<P>> = Arc::downgrade(target) as Weak<T>;
+        let mut slots = self.slots.write();
+        let slot = slots.get_mut(index as usize).ok_or(MapError::InvalidKey)?;
+        *slot = Some(target);
+        Ok(())
+    }
+
+    /// The program in slot `index`, if there is one.
+    pub fn get(&self, index: u32) -> Option<Arc<dyn TailCallTarget<P>>> {
+        self.slots.read().get(index as usize)?.as_ref()?.upgrade()
+    }
+
+    /// Parse key bytes as u32 index.
+    fn parse_key(key: &[u8]) -> Option<u32> {
+        Some(u32::from_ne_bytes(key.try_into().ok()?))
+    }
+}
+
+impl<P: PhysicalProfile> BpfMap<P> for ProgArrayMap<P> {
+    fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let target = self.get(Self::parse_key(key)?)?;
+        Some(target.id().to_ne_bytes().to_vec())
+    }
+
+    fn update(&self, _key: &[u8], _value: &[u8], _flags: u64) -> MapResult<()> {
+        // Slots hold programs, which only the kernel can resolve from the
+        // file descriptor userspace passes
+        Err(MapError::NotSupported)
+    }
+
+    fn delete(&self, key: &[u8]) -> MapResult<()> {
+        let index = Self::parse_key(key).ok_or(MapError::InvalidKey)?;
+        let mut slots = self.slots.write();
+        let slot = slots.get_mut(index as usize).ok_or(MapError::InvalidKey)?;
+        slot.take()
+            .filter(|target| target.strong_count() > 0)
+            .map(|_| ())
+            .ok_or(MapError::KeyNotFound)
+    }
+
+    fn def(&self) -> &MapDef {
+        &self.def
+    }
+
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let slots = self.slots.read();
+        let start = key
+            .and_then(Self::parse_key)
+            .map(|index| index as usize + 1)
+            .filter(|&index| index <= slots.len())
+            .unwrap_or(0);
+
+        Ok(slots[start..]
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, slot)| {
+                let target = slot.as_ref()?.upgrade()?;
+                let index = (start + offset) as u32;
+                Some((
+                    index.to_ne_bytes().to_vec(),
+                    target.id().to_ne_bytes().to_vec(),
+                ))
+            })
+            .take(max)
+            .collect())
+    }
+
+    fn as_prog_array(&self) -> Option<&ProgArrayMap<P>> {
+        Some(self)
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
+        if new_max_entries == 0 {
+            return Err(MapError::InvalidValue);
+        }
+        self.slots
+            .get_mut()
+            .resize_with(new_max_entries as usize, || None);
+        self.def.max_entries = new_max_entries;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::insn::BpfInsn;
+    use crate::bytecode::program::{BpfProgType, BpfProgram, ProgramBuilder};
+
+    struct Target {
+        id: u32,
+        program: BpfProgram<ActiveProfile>,
+    }
+
+    impl TailCallTarget<ActiveProfile> for Target {
+        fn id(&self) -> u32 {
+            self.id
+        }
+
+        fn program(&self) -> &BpfProgram<ActiveProfile> {
+            &self.program
+        }
+    }
+
+    fn target(id: u32) -> Arc<Target> {
+        let program = ProgramBuilder::<ActiveProfile>::new(BpfProgType::SocketFilter)
+            .insn(BpfInsn::mov64_imm(0, id as i32))
+            .exit()
+            .build()
+            .expect("valid program");
+        Arc::new(Target { id, program })
+    }
+
+    fn key(index: u32) -> [u8; 4] {
+        index.to_ne_bytes()
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        for def in [
+            MapDef::new(MapType::Array, 4, 4, 4),
+            MapDef::new(MapType::ProgArray, 8, 4, 4),
+            MapDef::new(MapType::ProgArray, 4, 8, 4),
+            MapDef::new(MapType::ProgArray, 4, 4, 0),
+        ] {
+            assert!(ProgArrayMap::<ActiveProfile>::new(def).is_err());
+        }
+    }
+
+    #[test]
+    fn set_get_and_lookup() {
+        let map = ProgArrayMap::<ActiveProfile>::with_entries(4).unwrap();
+        let first = target(7);
+
+        assert!(map.get(1).is_none());
+        map.set(1, &first).unwrap();
+        assert_eq!(map.get(1).map(|t| t.id()), Some(7));
+        assert_eq!(map.lookup(&key(1)), Some(7u32.to_ne_bytes().to_vec()));
+        assert_eq!(map.lookup(&key(0)), None);
+
+        // Out of range
+        assert_eq!(map.set(4, &first), Err(MapError::InvalidKey));
+        assert!(map.get(4).is_none());
+
+        // Userspace can't store raw IDs
+        assert_eq!(
+            map.update(&key(0), &7u32.to_ne_bytes(), 0),
+            Err(MapError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn replacing_a_slot_swaps_the_program() {
+        let map = ProgArrayMap::<ActiveProfile>::with_entries(2).unwrap();
+        let (first, second) = (target(1), target(2));
+
+        map.set(0, &first).unwrap();
+        map.set(0, &second).unwrap();
+        assert_eq!(map.get(0).map(|t| t.id()), Some(2));
+    }
+
+    #[test]
+    fn delete_empties_the_slot() {
+        let map = ProgArrayMap::<ActiveProfile>::with_entries(2).unwrap();
+        let first = target(1);
+
+        map.set(0, &first).unwrap();
+        assert_eq!(map.delete(&key(0)), Ok(()));
+        assert!(map.get(0).is_none());
+        assert_eq!(map.delete(&key(0)), Err(MapError::KeyNotFound));
+        assert_eq!(map.delete(&key(2)), Err(MapError::InvalidKey));
+    }
+
+    #[test]
+    fn slots_do_not_keep_programs_alive() {
+        let map = ProgArrayMap::<ActiveProfile>::with_entries(2).unwrap();
+        let first = target(1);
+
+        map.set(0, &first).unwrap();
+        drop(first);
+        assert!(map.get(0).is_none());
+        assert_eq!(map.lookup(&key(0)), None);
+        assert_eq!(map.delete(&key(0)), Err(MapError::KeyNotFound));
+    }
+
+    #[test]
+    fn iterates_populated_slots() {
+        let map = ProgArrayMap::<ActiveProfile>::with_entries(4).unwrap();
+        let (first, second) = (target(10), target(30));
+        map.set(1, &first).unwrap();
+        map.set(3, &second).unwrap();
+
+        let entries = map.entries_after(None, 8).unwrap();
+        assert_eq!(
+            entries,
+            [
+                (key(1).to_vec(), 10u32.to_ne_bytes().to_vec()),
+                (key(3).to_vec(), 30u32.to_ne_bytes().to_vec()),
+            ]
+        );
+        assert_eq!(map.get_next_key(Some(&key(1))), Ok(key(3).to_vec()));
+        assert_eq!(map.get_next_key(Some(&key(3))), Err(MapError::KeyNotFound));
+    }
+}
@@ -6,23 +6,24 @@
 //!
 //! # Memory Layout
 //!
+//! The buffer uses the Linux `BPF_MAP_TYPE_RINGBUF` layout, so userspace can
+//! map it and consume records without a system call per record:
+//!
 //! ```text
-//! ┌──────────────────────────────────────────────────────────────┐
-//! │                       Ring Buffer                             │
-//! ├──────────┬───────────────────────────────────────────────────┤
-//! │ Control  │                  Data Ring                         │
-//! │ ┌──────┐ │ ┌────┬────┬────┬────┬────┬────┬────┬────┬────┐   │
-//! │ │head  │ │ │    │    │    │    │    │    │    │    │    │   │
-//! │ │tail  │ │ │    │    │ ▓▓ │ ▓▓ │ ▓▓ │    │    │    │    │   │
-//! │ │      │ │ │    │    │    │    │    │    │    │    │    │   │
-//! │ └──────┘ │ └────┴────┴────┴────┴────┴────┴────┴────┴────┘   │
-//! │ 16 bytes │              ▲           ▲                        │
-//! │          │              │           │                        │
-//! │          │            tail        head                       │
-//! │          │         (consumer)   (producer)                   │
-//! └──────────┴───────────────────────────────────────────────────┘
+//! offset 0            4 KiB               8 KiB
+//! ┌───────────────────┬───────────────────┬──────────────────────────────┐
+//! │ consumer page     │ producer page     │ data area (capacity bytes)   │
+//! │ consumer_pos: u64 │ producer_pos: u64 │ ┌────┬────┬────┬────┬────┐   │
+//! │ (written by the   │ (written by the   │ │    │ ▓▓ │ ▓▓ │ ▓▓ │    │   │
+//! │  consumer)        │  kernel)          │ └────┴────┴────┴────┴────┘   │
+//! └───────────────────┴───────────────────┴──────────────────────────────┘
 //! ```
 //!
+//! Positions only ever grow; `pos & (capacity - 1)` is the offset into the
+//! data area. When mapped into a process, the data area is mapped twice back
+//! to back (see [`RingBufMap::mmap_page`]), so a record that wraps around the
+//! end is still contiguous in userspace.
+//!
 //! # Event Format
 //!
 //! Each event in the ring buffer has a header:
@@ -31,16 +32,28 @@
 //! ┌─────────────────────────────────────────────┐
 //! │ Header (8 bytes)                            │
 //! │ ┌───────────────────┬─────────────────────┐ │
-//! │ │ length (4 bytes)  │ flags (4 bytes)     │ │
+//! │ │ len (4 bytes)     │ pg_off (4 bytes)    │ │
 //! │ └───────────────────┴─────────────────────┘ │
 //! ├─────────────────────────────────────────────┤
-//! │ Data (length bytes, 8-byte aligned)         │
+//! │ Data (len bytes, 8-byte aligned)            │
 //! │ ┌─────────────────────────────────────────┐ │
 //! │ │ user data...                            │ │
 //! │ └─────────────────────────────────────────┘ │
 //! └─────────────────────────────────────────────┘
 //! ```
 //!
+//! Bit 31 of `len` marks a record that is reserved but not yet submitted, and
+//! bit 30 a discarded record that consumers skip.
+//!
+//! # Waking Consumers
+//!
+//! A submitted record marks the buffer ready for a waiting consumer once the
+//! unconsumed bytes reach the wakeup watermark (0, the default, means every
+//! record). [`BPF_RB_NO_WAKEUP`] and [`BPF_RB_FORCE_WAKEUP`] override that
+//! per record, like on Linux. A marked buffer also calls the wakeup hook
+//! set with [`RingBufMap::set_wakeup_hook`], which is how the kernel wakes
+//! tasks blocked in `poll()`.
+//!
 //! # Profile Differences
 //!
 //! | Feature       | Cloud          | Embedded       |
@@ -52,25 +65,41 @@
 
 extern crate alloc;
 
+use alloc::alloc::{Layout, alloc_zeroed, dealloc};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
-use spin::Mutex;
+use spin::{Mutex, Once};
 
 use super::{BpfMap, MapDef, MapError, MapResult, MapType};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
+/// Granularity of the ring buffer layout.
+pub const RINGBUF_PAGE_SIZE: usize = 4096;
+
+/// Offset of the page holding the consumer position.
+pub const RINGBUF_CONSUMER_OFFSET: usize = 0;
+
+/// Offset of the page holding the producer position.
+pub const RINGBUF_PRODUCER_OFFSET: usize = RINGBUF_PAGE_SIZE;
+
+/// Offset of the data area.
+pub const RINGBUF_DATA_OFFSET: usize = 2 * RINGBUF_PAGE_SIZE;
+
+/// Submit flag: don't wake up the consumer for this record.
+pub const BPF_RB_NO_WAKEUP: u64 = 1;
+
+/// Submit flag: wake up the consumer even below the watermark.
+pub const BPF_RB_FORCE_WAKEUP: u64 = 2;
+
 /// Event header in the ring buffer.
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-struct EventHeader {
-    /// Length of data (not including header)
-    length: u32,
-    /// Event flags
-    flags: u32,
-}
+///
+/// Only the layout constants are used; the header is read and written in
+/// place because consumers may be looking at it concurrently.
+struct EventHeader;
 
 impl EventHeader {
     const SIZE: usize = 8;
@@ -80,84 +109,49 @@ impl EventHeader {
     /// Flag indicating event is discarded
     const FLAG_DISCARD: u32 = 1 << 30;
 
-    fn new(length: u32) -> Self {
-        Self { length, flags: 0 }
-    }
-
-    fn as_bytes(self) -> [u8; 8] {
-        let mut bytes = [0u8; 8];
-        bytes[0..4].copy_from_slice(&self.length.to_ne_bytes());
-        bytes[4..8].copy_from_slice(&self.flags.to_ne_bytes());
-        bytes
-    }
-
-    fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 8 {
-            return None;
-        }
-        let length = u32::from_ne_bytes(bytes[0..4].try_into().ok()?);
-        let flags = u32::from_ne_bytes(bytes[4..8].try_into().ok()?);
-        Some(Self { length, flags })
-    }
-
-    fn is_busy(&self) -> bool {
-        self.flags & Self::FLAG_BUSY != 0
-    }
-
-    fn is_discarded(&self) -> bool {
-        self.flags & Self::FLAG_DISCARD != 0
-    }
-
-    /// Total size of this event including header, 8-byte aligned
-    fn total_size(&self) -> usize {
-        let data_size = self.length as usize;
-        let total = EventHeader::SIZE + data_size;
-        // Round up to 8-byte alignment
-        (total + 7) & !7
+    /// Total size of an event with `length` bytes of data, including the
+    /// header, 8-byte aligned
+    fn total_size(length: usize) -> usize {
+        (Self::SIZE + length + 7) & !7
     }
 }
 
-/// Ring buffer control structure.
-struct RingControl {
-    /// Producer position (next write position)
-    head: AtomicU64,
-    /// Consumer position (next read position)
-    tail: AtomicU64,
-    /// Buffer capacity
-    capacity: usize,
-    /// Mask for wrapping (capacity - 1, requires power of 2)
-    mask: usize,
+/// Page-aligned memory holding the control pages and the data area.
+struct RingPages {
+    ptr: NonNull<u8>,
+    layout: Layout,
 }
 
-impl RingControl {
-    fn new(capacity: usize) -> Self {
-        // Capacity must be power of 2
-        debug_assert!(capacity.is_power_of_two());
-        Self {
-            head: AtomicU64::new(0),
-            tail: AtomicU64::new(0),
-            capacity,
-            mask: capacity - 1,
-        }
+impl RingPages {
+    fn new(capacity: usize) -> MapResult<Self> {
+        let size = (RINGBUF_DATA_OFFSET + capacity).next_multiple_of(RINGBUF_PAGE_SIZE);
+        let layout =
+            Layout::from_size_align(size, RINGBUF_PAGE_SIZE).map_err(|_| MapError::OutOfMemory)?;
+        // SAFETY: the layout is never zero-sized, it always covers the
+        // control pages.
+        let ptr = unsafe { alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).ok_or(MapError::OutOfMemory)?;
+        Ok(Self { ptr, layout })
     }
 
-    /// Get available space for writing.
-    fn available_space(&self) -> usize {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
-        self.capacity - (head.wrapping_sub(tail) as usize)
+    /// The position stored at the start of the control page at `offset`.
+    fn position(&self, offset: usize) -> &AtomicU64 {
+        debug_assert!(offset == RINGBUF_CONSUMER_OFFSET || offset == RINGBUF_PRODUCER_OFFSET);
+        // SAFETY: both control offsets are page-aligned and inside the
+        // allocation, which lives as long as `self`.
+        unsafe { &*self.ptr.as_ptr().add(offset).cast::<AtomicU64>() }
     }
 
-    /// Get used space (data available for reading).
-    fn used_space(&self) -> usize {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
-        head.wrapping_sub(tail) as usize
+    fn data(&self) -> *mut u8 {
+        // SAFETY: the data area starts inside the allocation.
+        unsafe { self.ptr.as_ptr().add(RINGBUF_DATA_OFFSET) }
     }
+}
 
-    /// Wrap position to buffer index.
-    fn wrap(&self, pos: u64) -> usize {
-        (pos as usize) & self.mask
+impl Drop for RingPages {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` was allocated with `layout` in `new`.
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) };
     }
 }
 
@@ -168,10 +162,22 @@ impl RingControl {
 pub struct RingBufMap<P: PhysicalProfile = ActiveProfile> {
     /// Map definition
     def: MapDef,
-    /// Ring control structure
-    control: RingControl,
-    /// Data buffer
-    data: Mutex<Vec<u8>>,
+    /// Size of the data area (power of 2)
+    capacity: usize,
+    /// Mask for wrapping (capacity - 1)
+    mask: usize,
+    /// Control pages and data area
+    pages: RingPages,
+    /// Serializes producers
+    producer_lock: Mutex<()>,
+    /// Serializes in-kernel consumers
+    consumer_lock: Mutex<()>,
+    /// Unconsumed bytes at which a submit wakes the consumer
+    wakeup_watermark: AtomicU64,
+    /// End of the last record the consumer was woken up for
+    notified_pos: AtomicU64,
+    /// Called whenever a submit marks the buffer ready
+    wakeup_hook: Once<fn()>,
     /// Number of events dropped due to buffer full
     dropped_events: AtomicU64,
     /// Profile marker
@@ -201,7 +207,7 @@ impl<P: PhysicalProfile> RingBufMap<P> {
             return Err(MapError::InvalidValue);
         }
 
-        if size == 0 {
+        if size < EventHeader::SIZE {
             return Err(MapError::InvalidValue);
         }
 
@@ -227,13 +233,16 @@ impl<P: PhysicalProfile> RingBufMap<P> {
             flags: 0,
         };
 
-        let data = vec![0u8; size];
-        let control = RingControl::new(size);
-
         Ok(Self {
             def,
-            control,
-            data: Mutex::new(data),
+            capacity: size,
+            mask: size - 1,
+            pages: RingPages::new(size)?,
+            producer_lock: Mutex::new(()),
+            consumer_lock: Mutex::new(()),
+            wakeup_watermark: AtomicU64::new(0),
+            notified_pos: AtomicU64::new(0),
+            wakeup_hook: Once::new(),
             dropped_events: AtomicU64::new(0),
             _profile: PhantomData,
         })
@@ -250,15 +259,55 @@ impl<P: PhysicalProfile> RingBufMap<P> {
         Self::new(size)
     }
 
+    fn consumer_pos(&self) -> &AtomicU64 {
+        self.pages.position(RINGBUF_CONSUMER_OFFSET)
+    }
+
+    fn producer_pos(&self) -> &AtomicU64 {
+        self.pages.position(RINGBUF_PRODUCER_OFFSET)
+    }
+
+    /// Wrap position to buffer index.
+    fn wrap(&self, pos: u64) -> usize {
+        (pos as usize) & self.mask
+    }
+
+    /// Bytes between the consumer position and `head`.
+    ///
+    /// The consumer position may be written by userspace, so a value that
+    /// is ahead of the producer or too far behind it reads as a full buffer.
+    fn used_until(&self, head: u64) -> usize {
+        let tail = self.consumer_pos().load(Ordering::Acquire);
+        let used = head.wrapping_sub(tail);
+        if used > self.capacity as u64 {
+            self.capacity
+        } else {
+            used as usize
+        }
+    }
+
+    /// The `len` word of the event header at data `offset`.
+    fn header_len(&self, offset: usize) -> &AtomicU32 {
+        debug_assert!(offset.is_multiple_of(EventHeader::SIZE) && offset < self.capacity);
+        // SAFETY: headers are 8-byte aligned and never wrap, because the
+        // capacity is a power of two of at least the header size.
+        unsafe { &*self.pages.data().add(offset).cast::<AtomicU32>() }
+    }
+
     /// Reserve space for writing an event.
     ///
-    /// Returns a reservation that must be submitted or discarded.
+    /// Returns a reservation that must be submitted or discarded. Until
+    /// then, the consumer stops at the reserved event.
     pub fn reserve(&self, size: usize) -> Option<RingBufReservation> {
-        let total_size = EventHeader::SIZE + size;
-        let aligned_size = (total_size + 7) & !7;
+        let total_size = EventHeader::total_size(size);
+
+        let _producer = self.producer_lock.lock();
+        let head = self.producer_pos().load(Ordering::Relaxed);
 
         // Check if there's enough space
-        if self.control.available_space() < aligned_size {
+        if size >= EventHeader::FLAG_DISCARD as usize
+            || self.capacity - self.used_until(head) < total_size
+        {
             self.dropped_events.fetch_add(1, Ordering::Relaxed);
 
             // In embedded profile, drop newest (this reservation)
@@ -271,124 +320,252 @@ impl<P: PhysicalProfile> RingBufMap<P> {
             return None;
         }
 
-        // Allocate space
-        let head = self
-            .control
-            .head
-            .fetch_add(aligned_size as u64, Ordering::AcqRel);
-        let offset = self.control.wrap(head);
+        let offset = self.wrap(head);
+        let pg_off = ((RINGBUF_DATA_OFFSET + offset) / RINGBUF_PAGE_SIZE) as u32;
+        self.header_len(offset)
+            .store(size as u32 | EventHeader::FLAG_BUSY, Ordering::Relaxed);
+        // SAFETY: the header is inside the data area, see `header_len`.
+        unsafe {
+            self.pages
+                .data()
+                .add(offset + 4)
+                .cast::<u32>()
+                .write(pg_off);
+        }
+
+        // Publish the busy header together with the new position
+        self.producer_pos()
+            .store(head + total_size as u64, Ordering::Release);
 
         Some(RingBufReservation {
+            pos: head,
             offset,
             data_size: size,
-            total_size: aligned_size,
+            total_size,
         })
     }
 
     /// Submit data to a reservation.
     ///
-    /// This makes the event visible to consumers.
-    pub fn submit(&self, reservation: &RingBufReservation, data: &[u8]) -> MapResult<()> {
+    /// This makes the event visible to consumers. Bytes of the reservation
+    /// not covered by `data` are zeroed. `flags` may hold
+    /// [`BPF_RB_NO_WAKEUP`] or [`BPF_RB_FORCE_WAKEUP`].
+    pub fn submit(
+        &self,
+        reservation: &RingBufReservation,
+        data: &[u8],
+        flags: u64,
+    ) -> MapResult<()> {
         if data.len() > reservation.data_size {
             return Err(MapError::InvalidValue);
         }
 
-        let mut buffer = self.data.lock();
+        let data_offset = (reservation.offset + EventHeader::SIZE) & self.mask;
+        self.write_wrapped(data_offset, data);
+        self.zero_wrapped(
+            (data_offset + data.len()) & self.mask,
+            reservation.data_size - data.len(),
+        );
 
-        // Write header
-        let header = EventHeader::new(data.len() as u32);
-        let header_bytes = header.as_bytes();
-        self.write_wrapped(&mut buffer, reservation.offset, &header_bytes);
-
-        // Write data
-        let data_offset = (reservation.offset + EventHeader::SIZE) & self.control.mask;
-        self.write_wrapped(&mut buffer, data_offset, data);
+        self.header_len(reservation.offset)
+            .store(reservation.data_size as u32, Ordering::Release);
+        self.notify(reservation.pos + reservation.total_size as u64, flags);
 
         Ok(())
     }
 
+    /// Discard a reservation.
+    ///
+    /// Consumers skip the event without seeing its data.
+    pub fn discard(&self, reservation: &RingBufReservation, flags: u64) {
+        self.header_len(reservation.offset).store(
+            reservation.data_size as u32 | EventHeader::FLAG_DISCARD,
+            Ordering::Release,
+        );
+        self.notify(reservation.pos + reservation.total_size as u64, flags);
+    }
+
     /// Output data directly to the ring buffer.
     ///
     /// This is a convenience method combining reserve + submit.
     pub fn output(&self, data: &[u8], flags: u64) -> MapResult<()> {
-        let _ = flags; // Reserved for future use
-
         let reservation = self.reserve(data.len()).ok_or(MapError::MapFull)?;
-        self.submit(&reservation, data)
+        self.submit(&reservation, data, flags)
+    }
+
+    /// Mark the buffer ready for the consumer up to `end`, if the record
+    /// ending there warrants a wakeup.
+    fn notify(&self, end: u64, flags: u64) {
+        if flags & BPF_RB_NO_WAKEUP != 0 {
+            return;
+        }
+
+        let pending = self.used_until(end) as u64;
+        if flags & BPF_RB_FORCE_WAKEUP != 0
+            || pending >= self.wakeup_watermark.load(Ordering::Relaxed)
+        {
+            self.notified_pos.fetch_max(end, Ordering::Release);
+            if let Some(hook) = self.wakeup_hook.get() {
+                hook();
+            }
+        }
+    }
+
+    /// Set the function called whenever a submit marks the buffer ready.
+    ///
+    /// The hook runs in the context of the submitting program, which may
+    /// be an interrupt handler. Only the first hook set is kept.
+    pub fn set_wakeup_hook(&self, hook: fn()) {
+        self.wakeup_hook.call_once(|| hook);
+    }
+
+    /// Set how many unconsumed bytes a submit needs to wake the consumer.
+    pub fn set_wakeup_watermark(&self, bytes: u64) {
+        self.wakeup_watermark.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Get the wakeup watermark in bytes.
+    pub fn wakeup_watermark(&self) -> u64 {
+        self.wakeup_watermark.load(Ordering::Relaxed)
+    }
+
+    /// Whether a waiting consumer should be woken up.
+    ///
+    /// True once a submit signalled records that the consumer hasn't
+    /// consumed yet.
+    pub fn is_ready(&self) -> bool {
+        let notified = self.notified_pos.load(Ordering::Acquire);
+        let tail = self.consumer_pos().load(Ordering::Acquire);
+        notified > tail
     }
 
     /// Poll for available events.
     ///
-    /// Returns the next event's data if available.
+    /// Returns the next event's data if available. The consumer position
+    /// may be written by userspace; one that isn't on a record boundary
+    /// within the buffer, or a record running past the producer position,
+    /// reads as an empty buffer.
     pub fn poll(&self) -> Option<Vec<u8>> {
-        if self.control.used_space() < EventHeader::SIZE {
-            return None;
-        }
-
-        let buffer = self.data.lock();
-        let tail = self.control.tail.load(Ordering::Acquire);
-        let offset = self.control.wrap(tail);
+        let _consumer = self.consumer_lock.lock();
+
+        loop {
+            let tail = self.consumer_pos().load(Ordering::Acquire);
+            let head = self.producer_pos().load(Ordering::Acquire);
+            let used = head.wrapping_sub(tail);
+            if used == 0
+                || used > self.capacity as u64
+                || !tail.is_multiple_of(EventHeader::SIZE as u64)
+            {
+                return None;
+            }
 
-        // Read header
-        let mut header_bytes = [0u8; 8];
-        self.read_wrapped(&buffer, offset, &mut header_bytes);
-        let header = EventHeader::from_bytes(&header_bytes)?;
+            let offset = self.wrap(tail);
+            let len = self.header_len(offset).load(Ordering::Acquire);
+            if len & EventHeader::FLAG_BUSY != 0 {
+                return None; // Event still being written
+            }
 
-        if header.is_busy() {
-            return None; // Event still being written
-        }
+            let length = (len & !EventHeader::FLAG_DISCARD) as usize;
+            let total_size = EventHeader::total_size(length) as u64;
+            if total_size > used {
+                return None;
+            }
+            let next = tail + total_size;
 
-        if header.is_discarded() {
-            // Skip discarded event
-            self.control
-                .tail
-                .fetch_add(header.total_size() as u64, Ordering::Release);
-            drop(buffer);
-            return self.poll(); // Try next event
-        }
+            if len & EventHeader::FLAG_DISCARD != 0 {
+                // Skip discarded event
+                self.consumer_pos().store(next, Ordering::Release);
+                continue;
+            }
 
-        // Read data
-        let data_offset = (offset + EventHeader::SIZE) & self.control.mask;
-        let mut data = vec![0u8; header.length as usize];
-        self.read_wrapped(&buffer, data_offset, &mut data);
+            // Read data
+            let data_offset = (offset + EventHeader::SIZE) & self.mask;
+            let mut data = vec![0u8; length];
+            self.read_wrapped(data_offset, &mut data);
 
-        // Advance tail
-        self.control
-            .tail
-            .fetch_add(header.total_size() as u64, Ordering::Release);
+            // Advance tail
+            self.consumer_pos().store(next, Ordering::Release);
 
-        Some(data)
+            return Some(data);
+        }
     }
 
     /// Write data with wrapping at buffer boundary.
-    fn write_wrapped(&self, buffer: &mut [u8], offset: usize, data: &[u8]) {
-        let capacity = self.control.capacity;
-        let first_part = capacity - offset;
+    fn write_wrapped(&self, offset: usize, data: &[u8]) {
+        let first_part = data.len().min(self.capacity - offset);
+        let base = self.pages.data();
+
+        // SAFETY: `offset < capacity` and both parts stay inside the data
+        // area, since a reservation is never larger than the capacity.
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), base.add(offset), first_part);
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr().add(first_part),
+                base,
+                data.len() - first_part,
+            );
+        }
+    }
 
-        if first_part >= data.len() {
-            // No wrap needed
-            buffer[offset..offset + data.len()].copy_from_slice(data);
-        } else {
-            // Wrap around
-            buffer[offset..].copy_from_slice(&data[..first_part]);
-            buffer[..data.len() - first_part].copy_from_slice(&data[first_part..]);
+    /// Zero `len` bytes with wrapping at buffer boundary.
+    fn zero_wrapped(&self, offset: usize, len: usize) {
+        let first_part = len.min(self.capacity - offset);
+        let base = self.pages.data();
+
+        // SAFETY: as in `write_wrapped`.
+        unsafe {
+            core::ptr::write_bytes(base.add(offset), 0, first_part);
+            core::ptr::write_bytes(base, 0, len - first_part);
         }
     }
 
     /// Read data with wrapping at buffer boundary.
-    fn read_wrapped(&self, buffer: &[u8], offset: usize, data: &mut [u8]) {
-        let capacity = self.control.capacity;
-        let first_part = capacity - offset;
+    fn read_wrapped(&self, offset: usize, data: &mut [u8]) {
+        let first_part = data.len().min(self.capacity - offset);
+        let base = self.pages.data();
+
+        // SAFETY: as in `write_wrapped`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(base.add(offset), data.as_mut_ptr(), first_part);
+            core::ptr::copy_nonoverlapping(
+                base,
+                data.as_mut_ptr().add(first_part),
+                data.len() - first_part,
+            );
+        }
+    }
 
-        if first_part >= data.len() {
-            // No wrap needed
-            data.copy_from_slice(&buffer[offset..offset + data.len()]);
-        } else {
-            // Wrap around
-            let second_part = data.len() - first_part;
-            data[..first_part].copy_from_slice(&buffer[offset..]);
-            data[first_part..].copy_from_slice(&buffer[..second_part]);
+    /// Whether the buffer can be memory-mapped.
+    ///
+    /// Mapping the data area twice back to back needs it to be made of
+    /// whole pages.
+    pub fn is_mappable(&self) -> bool {
+        self.capacity.is_multiple_of(RINGBUF_PAGE_SIZE)
+    }
+
+    /// Length of a full mapping: both control pages and the data area twice.
+    pub fn mmap_len(&self) -> usize {
+        RINGBUF_DATA_OFFSET + 2 * self.capacity
+    }
+
+    /// Kernel address of the page at `index` of a full mapping.
+    ///
+    /// Page 0 holds the consumer position and page 1 the producer position.
+    /// They are followed by the data pages and then by the same data pages
+    /// again, so that records wrapping around the end of the data area are
+    /// contiguous. Returns `None` past the end of the mapping or if the
+    /// buffer isn't [mappable](Self::is_mappable).
+    pub fn mmap_page(&self, index: usize) -> Option<*const u8> {
+        if !self.is_mappable() || index * RINGBUF_PAGE_SIZE >= self.mmap_len() {
+            return None;
         }
+
+        let offset = match index * RINGBUF_PAGE_SIZE {
+            control if control < RINGBUF_DATA_OFFSET => control,
+            data => RINGBUF_DATA_OFFSET + (data - RINGBUF_DATA_OFFSET) % self.capacity,
+        };
+        // SAFETY: `offset` is inside the allocation.
+        Some(unsafe { self.pages.ptr.as_ptr().add(offset).cast_const() })
     }
 
     /// Get number of dropped events.
@@ -398,29 +575,30 @@ impl<P: PhysicalProfile> RingBufMap<P> {
 
     /// Get current buffer usage in bytes.
     pub fn used_bytes(&self) -> usize {
-        self.control.used_space()
+        self.used_until(self.producer_pos().load(Ordering::Acquire))
     }
 
     /// Get buffer capacity in bytes.
     pub fn capacity(&self) -> usize {
-        self.control.capacity
+        self.capacity
     }
 
     /// Check if the buffer is empty.
     pub fn is_empty(&self) -> bool {
-        self.control.used_space() == 0
+        self.used_bytes() == 0
     }
 }
 
 /// Reservation for writing to ring buffer.
 #[derive(Debug)]
 pub struct RingBufReservation {
+    /// Position of the event header
+    pos: u64,
     /// Offset in the buffer
     offset: usize,
     /// Size of data (not including header)
     data_size: usize,
     /// Total size including header and alignment
-    #[allow(dead_code)]
     total_size: usize,
 }
 
@@ -452,11 +630,15 @@ impl<P: PhysicalProfile> BpfMap<P> for RingBufMap<P> {
         &self.def
     }
 
+    fn as_ringbuf(&self) -> Option<&RingBufMap<P>> {
+        Some(self)
+    }
+
     #[cfg(feature = "cloud-profile")]
     fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
         let new_size = new_max_entries as usize;
 
-        if !new_size.is_power_of_two() {
+        if !new_size.is_power_of_two() || new_size < EventHeader::SIZE {
             return Err(MapError::InvalidValue);
         }
 
@@ -464,21 +646,23 @@ impl<P: PhysicalProfile> BpfMap<P> for RingBufMap<P> {
             return Err(MapError::OutOfMemory);
         }
 
-        // Resize requires draining existing data
-        let mut buffer = self.data.lock();
-        buffer.resize(new_size, 0);
-
-        // Reset control
-        self.control = RingControl::new(new_size);
+        // Resize drains existing data; nothing can be mapped while we hold
+        // the map exclusively
+        self.pages = RingPages::new(new_size)?;
+        self.capacity = new_size;
+        self.mask = new_size - 1;
+        self.notified_pos.store(0, Ordering::Relaxed);
         self.def.max_entries = new_max_entries;
 
         Ok(())
     }
 }
 
-// SAFETY: RingBufMap uses atomic operations and mutex for thread safety.
+// SAFETY: the pages are only accessed through atomics or while holding the
+// producer or consumer lock.
 unsafe impl<P: PhysicalProfile> Send for RingBufMap<P> {}
-// SAFETY: RingBufMap uses atomic operations and mutex for thread safety.
+// SAFETY: the pages are only accessed through atomics or while holding the
+// producer or consumer lock.
 unsafe impl<P: PhysicalProfile> Sync for RingBufMap<P> {}
 
 #[cfg(test)]
@@ -557,7 +741,7 @@ mod tests {
 
         // Submit data
         let data = [0xABu8; 32];
-        ringbuf.submit(&reservation, &data).expect("submit");
+        ringbuf.submit(&reservation, &data, 0).expect("submit");
 
         // Read it back
         let result = ringbuf.poll().expect("poll");
@@ -592,4 +776,130 @@ mod tests {
         let result = RingBufMap::<ActiveProfile>::new(0);
         assert!(matches!(result, Err(MapError::InvalidValue)));
     }
+
+    #[test]
+    fn ringbuf_linux_layout() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+        ringbuf.output(b"abc", 0).expect("output");
+
+        let consumer = ringbuf.mmap_page(0).expect("consumer page");
+        let producer = ringbuf.mmap_page(1).expect("producer page");
+        let data = ringbuf.mmap_page(2).expect("data page");
+        // SAFETY: the pages belong to `ringbuf`, which outlives the reads.
+        unsafe {
+            assert_eq!(consumer.cast::<u64>().read(), 0);
+            assert_eq!(producer.cast::<u64>().read(), 16);
+            assert_eq!(data.cast::<u32>().read(), 3); // len, not busy
+            assert_eq!(data.add(8).cast::<[u8; 3]>().read(), *b"abc");
+        }
+
+        ringbuf.poll().expect("poll");
+        // SAFETY: as above.
+        assert_eq!(unsafe { consumer.cast::<u64>().read() }, 16);
+    }
+
+    #[test]
+    fn ringbuf_mmap_pages_repeat_data() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(2 * RINGBUF_PAGE_SIZE).expect("create");
+        assert_eq!(ringbuf.mmap_len(), 6 * RINGBUF_PAGE_SIZE);
+
+        assert_eq!(ringbuf.mmap_page(2), ringbuf.mmap_page(4));
+        assert_eq!(ringbuf.mmap_page(3), ringbuf.mmap_page(5));
+        assert_ne!(ringbuf.mmap_page(2), ringbuf.mmap_page(3));
+        assert!(ringbuf.mmap_page(6).is_none());
+
+        let small = RingBufMap::<ActiveProfile>::new(256).expect("create");
+        assert!(!small.is_mappable());
+        assert!(small.mmap_page(0).is_none());
+    }
+
+    #[test]
+    fn ringbuf_discarded_events_are_skipped() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+
+        let reservation = ringbuf.reserve(16).expect("reserve");
+        ringbuf.discard(&reservation, 0);
+        ringbuf.output(b"kept", 0).expect("output");
+
+        assert_eq!(ringbuf.poll().expect("poll"), b"kept");
+        assert!(ringbuf.is_empty());
+    }
+
+    #[test]
+    fn ringbuf_corrupt_consumer_state_reads_empty() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+        ringbuf.output(b"event", 0).expect("output");
+
+        // A misaligned position, as userspace could write it
+        ringbuf.consumer_pos().store(3, Ordering::Release);
+        assert!(ringbuf.poll().is_none());
+
+        // A record length running past the producer
+        ringbuf.consumer_pos().store(0, Ordering::Release);
+        ringbuf.header_len(0).store(1 << 29, Ordering::Release);
+        assert!(ringbuf.poll().is_none());
+
+        ringbuf.header_len(0).store(5, Ordering::Release);
+        assert_eq!(ringbuf.poll().expect("poll"), b"event");
+    }
+
+    #[test]
+    fn ringbuf_busy_reservation_blocks_consumer() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+
+        let reservation = ringbuf.reserve(4).expect("reserve");
+        ringbuf.output(b"later", 0).expect("output");
+        assert!(ringbuf.poll().is_none());
+
+        ringbuf.submit(&reservation, b"ab", 0).expect("submit");
+        // Short submits keep the reserved length, zero-filled
+        assert_eq!(ringbuf.poll().expect("poll"), [b'a', b'b', 0, 0]);
+        assert_eq!(ringbuf.poll().expect("poll"), b"later");
+    }
+
+    #[test]
+    fn ringbuf_wakeup_watermark() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+        ringbuf.set_wakeup_watermark(32);
+
+        ringbuf.output(&[0u8; 8], 0).expect("output"); // 16 bytes pending
+        assert!(!ringbuf.is_ready());
+        ringbuf.output(&[0u8; 8], 0).expect("output"); // 32 bytes pending
+        assert!(ringbuf.is_ready());
+
+        while ringbuf.poll().is_some() {}
+        assert!(!ringbuf.is_ready());
+    }
+
+    #[test]
+    fn ringbuf_wakeup_flags() {
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+
+        ringbuf.output(b"quiet", BPF_RB_NO_WAKEUP).expect("output");
+        assert!(!ringbuf.is_ready());
+
+        ringbuf.set_wakeup_watermark(1024);
+        ringbuf
+            .output(b"loud", BPF_RB_FORCE_WAKEUP)
+            .expect("output");
+        assert!(ringbuf.is_ready());
+    }
+
+    #[test]
+    fn ringbuf_wakeup_hook() {
+        use core::sync::atomic::AtomicUsize;
+
+        static WAKEUPS: AtomicUsize = AtomicUsize::new(0);
+        fn hook() {
+            WAKEUPS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let ringbuf = RingBufMap::<ActiveProfile>::new(4096).expect("create ringbuf");
+        ringbuf.set_wakeup_hook(hook);
+
+        ringbuf.output(b"quiet", BPF_RB_NO_WAKEUP).expect("output");
+        assert_eq!(WAKEUPS.load(Ordering::Relaxed), 0);
+        ringbuf.output(b"loud", 0).expect("output");
+        assert_eq!(WAKEUPS.load(Ordering::Relaxed), 1);
+    }
 }
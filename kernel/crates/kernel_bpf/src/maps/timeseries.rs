@@ -34,10 +34,19 @@
 //! // Push a new timestamped value
 //! bpf_timeseries_push(&ts_map, key, &value);
 //!
-//! // Query last N entries
-//! bpf_timeseries_query(&ts_map, key, &output, count);
+//! // Aggregate the last N entries into a stack buffer
+//! struct rkbpf_ts_aggregate agg;
+//! bpf_timeseries_query(&ts_map, 16, &agg, sizeof(agg));
+//!
+//! // Or the entries stamped within a time window
+//! u64 now = bpf_ktime_get_ns();
+//! bpf_timeseries_query_window(&ts_map, now - 100000000, now, &agg, sizeof(agg));
 //! ```
 //!
+//! Aggregates read each value as a signed integer: values of 1, 2 or 4
+//! bytes are sign-extended, larger values contribute their first 8 bytes.
+//! See [`TimeSeriesAggregate`] for the fields.
+//!
 //! # Profile Differences
 //!
 //! | Feature       | Cloud          | Embedded       |
@@ -51,6 +60,7 @@ extern crate alloc;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
+use core::ops::Range;
 
 use spin::RwLock;
 
@@ -119,7 +129,7 @@ impl TimeSeriesStorage {
 
         // Calculate write index (next position after newest)
         let write_idx = if self.count < self.capacity {
-            self.count
+            (self.head_idx + self.count) % self.capacity
         } else {
             // Buffer is full, overwrite oldest
             let idx = self.head_idx;
@@ -193,6 +203,25 @@ impl TimeSeriesStorage {
         result
     }
 
+    /// Entries at logical indices in `range`, oldest first.
+    fn iter(&self, range: Range<usize>) -> impl Iterator<Item = (u64, &[u8])> + Clone {
+        range.filter_map(|i| self.get(i))
+    }
+
+    /// Aggregate the last N entries.
+    fn aggregate_last_n(&self, n: usize) -> Option<TimeSeriesAggregate> {
+        let start = self.count - n.min(self.count);
+        TimeSeriesAggregate::compute(self.value_size, self.iter(start..self.count))
+    }
+
+    /// Aggregate the entries within a time window [start_ns, end_ns].
+    fn aggregate_in_window(&self, start_ns: u64, end_ns: u64) -> Option<TimeSeriesAggregate> {
+        let entries = self
+            .iter(0..self.count)
+            .filter(|&(ts, _)| ts >= start_ns && ts <= end_ns);
+        TimeSeriesAggregate::compute(self.value_size, entries)
+    }
+
     /// Get the newest entry.
     fn newest(&self) -> Option<(u64, &[u8])> {
         if self.count == 0 {
@@ -209,6 +238,34 @@ impl TimeSeriesStorage {
         self.get(0)
     }
 
+    /// Copy up to `max` entries, oldest first, keyed by their timestamp.
+    ///
+    /// Starts after the newest entry stamped `key`, or at the oldest entry
+    /// when there is none.
+    fn entries_after(&self, key: Option<u64>, max: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let start = key
+            .and_then(|key| {
+                (0..self.count)
+                    .rev()
+                    .find(|&i| self.get(i).is_some_and(|(ts, _)| ts == key))
+            })
+            .map_or(0, |i| i + 1);
+
+        (start..self.count)
+            .take(max)
+            .filter_map(|i| self.get(i))
+            .map(|(ts, value)| (ts.to_ne_bytes().to_vec(), value.to_vec()))
+            .collect()
+    }
+
+    /// Remove the oldest entry.
+    fn pop_oldest(&mut self) -> Option<(u64, Vec<u8>)> {
+        let (ts, value) = self.oldest().map(|(ts, value)| (ts, value.to_vec()))?;
+        self.head_idx = (self.head_idx + 1) % self.capacity;
+        self.count -= 1;
+        Some((ts, value))
+    }
+
     /// Clear all entries.
     fn clear(&mut self) {
         self.count = 0;
@@ -425,6 +482,24 @@ impl<P: PhysicalProfile> TimeSeriesMap<P> {
             sum,
         })
     }
+
+    /// Aggregate the last N entries.
+    ///
+    /// Returns `None` if the values can't be read as integers (see
+    /// [`TimeSeriesAggregate`]); an empty map gives a zero `count`.
+    pub fn aggregate_last_n(&self, n: usize) -> Option<TimeSeriesAggregate> {
+        self.storage.read().aggregate_last_n(n)
+    }
+
+    /// Aggregate the entries within a time window.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_ns` - Start timestamp (inclusive)
+    /// * `end_ns` - End timestamp (inclusive)
+    pub fn aggregate_in_window(&self, start_ns: u64, end_ns: u64) -> Option<TimeSeriesAggregate> {
+        self.storage.read().aggregate_in_window(start_ns, end_ns)
+    }
 }
 
 /// Statistics computed over time-series entries.
@@ -460,6 +535,121 @@ impl TimeSeriesStats {
     }
 }
 
+/// Aggregates over a run of time-series entries.
+///
+/// This is the layout `bpf_timeseries_query` writes into a program's stack
+/// buffer and `BPF_TIMESERIES_QUERY` copies out to userspace. Programs can't
+/// use floating point, so every field is an integer: the mean and standard
+/// deviation are truncated, and the slope is in value units per second.
+///
+/// Each value is read as a signed integer: values of 1, 2 or 4 bytes are
+/// sign-extended, and larger values contribute their first 8 bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeSeriesAggregate {
+    /// Number of entries aggregated
+    pub count: u64,
+    /// Timestamp of the oldest entry
+    pub oldest_ns: u64,
+    /// Timestamp of the newest entry
+    pub newest_ns: u64,
+    /// Minimum value
+    pub min: i64,
+    /// Maximum value
+    pub max: i64,
+    /// Mean value
+    pub mean: i64,
+    /// Population standard deviation
+    pub stddev: i64,
+    /// Change from the oldest to the newest value, per second
+    pub slope: i64,
+}
+
+impl TimeSeriesAggregate {
+    /// Size of the aggregate in bytes.
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+
+    /// Read a value as a signed integer.
+    fn sample(value: &[u8]) -> Option<i64> {
+        Some(match value.len() {
+            1 => value[0] as i8 as i64,
+            2 => i16::from_ne_bytes(value.try_into().ok()?) as i64,
+            4 => i32::from_ne_bytes(value.try_into().ok()?) as i64,
+            8.. => i64::from_ne_bytes(value[0..8].try_into().ok()?),
+            _ => return None,
+        })
+    }
+
+    /// Aggregate entries given oldest first.
+    ///
+    /// The entries are walked twice: once for the mean, then again for the
+    /// deviation from it.
+    fn compute<'a>(
+        value_size: usize,
+        entries: impl Iterator<Item = (u64, &'a [u8])> + Clone,
+    ) -> Option<Self> {
+        if !matches!(value_size, 1 | 2 | 4 | 8..) {
+            return None;
+        }
+
+        let mut agg = Self {
+            min: i64::MAX,
+            max: i64::MIN,
+            ..Self::default()
+        };
+        let mut sum: i128 = 0;
+        let mut first = None;
+        let mut last = None;
+        for (ts, value) in entries.clone() {
+            let val = Self::sample(value)?;
+            agg.count += 1;
+            agg.min = agg.min.min(val);
+            agg.max = agg.max.max(val);
+            sum += val as i128;
+            first.get_or_insert((ts, val));
+            last = Some((ts, val));
+        }
+        let (Some((oldest_ns, oldest)), Some((newest_ns, newest))) = (first, last) else {
+            return Some(Self::default());
+        };
+
+        let mean = sum / agg.count as i128;
+        agg.mean = mean as i64;
+        let squares = entries
+            .filter_map(|(_, value)| Self::sample(value))
+            .map(|val| (val as i128 - mean).unsigned_abs().pow(2))
+            .fold(0u128, u128::saturating_add);
+        let stddev = (squares / agg.count as u128).isqrt();
+        agg.stddev = stddev.min(i64::MAX as u128) as i64;
+
+        agg.oldest_ns = oldest_ns;
+        agg.newest_ns = newest_ns;
+        let span_ns = newest_ns.saturating_sub(oldest_ns) as i128;
+        if span_ns > 0 {
+            let slope = (newest as i128 - oldest as i128) * 1_000_000_000 / span_ns;
+            agg.slope = slope.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        }
+        Some(agg)
+    }
+
+    /// Serialize the aggregate in its `repr(C)` layout.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let fields = [
+            self.count.to_ne_bytes(),
+            self.oldest_ns.to_ne_bytes(),
+            self.newest_ns.to_ne_bytes(),
+            self.min.to_ne_bytes(),
+            self.max.to_ne_bytes(),
+            self.mean.to_ne_bytes(),
+            self.stddev.to_ne_bytes(),
+            self.slope.to_ne_bytes(),
+        ];
+        let mut bytes = [0u8; Self::SIZE];
+        bytes.copy_from_slice(fields.as_flattened());
+        bytes
+    }
+}
+
 impl<P: PhysicalProfile> BpfMap<P> for TimeSeriesMap<P> {
     fn lookup(&self, key: &[u8]) -> Option<Vec<u8>> {
         // Key is interpreted as the number of last entries to return
@@ -498,6 +688,32 @@ impl<P: PhysicalProfile> BpfMap<P> for TimeSeriesMap<P> {
         &self.def
     }
 
+    fn as_timeseries(&self) -> Option<&TimeSeriesMap<P>> {
+        Some(self)
+    }
+
+    fn entries_after(&self, key: Option<&[u8]>, max: usize) -> MapResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        // Keys are timestamps, visited oldest first
+        let key = key.and_then(|key| Some(u64::from_ne_bytes(key.try_into().ok()?)));
+        Ok(self.storage.read().entries_after(key, max))
+    }
+
+    fn lookup_and_delete(&self, key: &[u8]) -> MapResult<Vec<u8>> {
+        // Entries can only be drained oldest first, so `key` must be the
+        // timestamp of the oldest entry
+        let timestamp_ns = u64::from_ne_bytes(key.try_into().map_err(|_| MapError::InvalidKey)?);
+        let mut storage = self.storage.write();
+        match storage.oldest() {
+            Some((ts, _)) if ts == timestamp_ns => {}
+            Some(_) => return Err(MapError::NotSupported),
+            None => return Err(MapError::KeyNotFound),
+        }
+        storage
+            .pop_oldest()
+            .map(|(_, value)| value)
+            .ok_or(MapError::KeyNotFound)
+    }
+
     #[cfg(feature = "cloud-profile")]
     fn resize(&mut self, new_max_entries: u32) -> MapResult<()> {
         if new_max_entries as usize > Self::MAX_ENTRIES {
@@ -631,6 +847,67 @@ mod tests {
         assert_eq!(stats.time_span_ns(), 4000);
     }
 
+    #[test]
+    fn timeseries_aggregate_last_n() {
+        let map = TimeSeriesMap::<ActiveProfile>::new(4, 100).expect("create map");
+        for (i, val) in [7i32, 2, 4, 4, 4, 5, 5, 7, 9].into_iter().enumerate() {
+            map.push(i as u64 * 250_000_000, &val.to_ne_bytes())
+                .expect("push");
+        }
+
+        // The last 8 values: 2, 4, 4, 4, 5, 5, 7, 9 over 1.75 seconds
+        let agg = map.aggregate_last_n(8).expect("aggregate");
+        assert_eq!(agg.count, 8);
+        assert_eq!((agg.oldest_ns, agg.newest_ns), (250_000_000, 2_000_000_000));
+        assert_eq!((agg.min, agg.max), (2, 9));
+        assert_eq!(agg.mean, 5);
+        assert_eq!(agg.stddev, 2);
+        assert_eq!(agg.slope, 4);
+
+        // Asking for more than the map holds takes everything
+        assert_eq!(map.aggregate_last_n(1000).expect("aggregate").count, 9);
+    }
+
+    #[test]
+    fn timeseries_aggregate_in_window() {
+        let map = TimeSeriesMap::<ActiveProfile>::new(2, 4).expect("create map");
+        for i in 0i16..6 {
+            map.push(i as u64 * 1000, &(-10 * i).to_ne_bytes())
+                .expect("push");
+        }
+
+        // 0 and 1000 were overwritten; negative values are sign-extended
+        let agg = map.aggregate_in_window(0, 3000).expect("aggregate");
+        assert_eq!(agg.count, 2);
+        assert_eq!((agg.min, agg.max, agg.mean), (-30, -20, -25));
+        assert_eq!(agg.stddev, 5);
+        assert_eq!(agg.slope, -10_000_000);
+
+        // A window without entries aggregates nothing
+        let agg = map.aggregate_in_window(9000, 10_000).expect("aggregate");
+        assert_eq!(agg, TimeSeriesAggregate::default());
+
+        // Values that aren't integers can't be aggregated
+        let map = TimeSeriesMap::<ActiveProfile>::new(3, 4).expect("create map");
+        map.push(0, &[1, 2, 3]).expect("push");
+        assert_eq!(map.aggregate_last_n(1), None);
+    }
+
+    #[test]
+    fn timeseries_aggregate_layout() {
+        let agg = TimeSeriesAggregate {
+            count: 1,
+            min: -1,
+            slope: 7,
+            ..TimeSeriesAggregate::default()
+        };
+        let bytes = agg.to_bytes();
+        assert_eq!(TimeSeriesAggregate::SIZE, 64);
+        assert_eq!(bytes[0..8], 1u64.to_ne_bytes());
+        assert_eq!(bytes[24..32], (-1i64).to_ne_bytes());
+        assert_eq!(bytes[56..64], 7i64.to_ne_bytes());
+    }
+
     #[test]
     fn timeseries_clear() {
         let map = TimeSeriesMap::<ActiveProfile>::new(4, 100).expect("create map");
@@ -665,6 +942,49 @@ mod tests {
         assert_eq!(val, 42);
     }
 
+    #[test]
+    fn timeseries_iteration_and_drain() {
+        let map = TimeSeriesMap::<ActiveProfile>::new(8, 4).expect("create map");
+        for i in 0u64..6 {
+            map.push(i * 100, &(i as i64).to_ne_bytes()).expect("push");
+        }
+
+        // The buffer holds the 4 newest entries, visited oldest first
+        let keys: Vec<u64> = map
+            .entries_after(None, 10)
+            .expect("entries")
+            .iter()
+            .map(|(key, _)| u64::from_ne_bytes(key.as_slice().try_into().unwrap()))
+            .collect();
+        assert_eq!(keys, [200, 300, 400, 500]);
+        assert_eq!(
+            map.get_next_key(Some(&300u64.to_ne_bytes())),
+            Ok(400u64.to_ne_bytes().to_vec())
+        );
+        assert_eq!(
+            map.get_next_key(Some(&500u64.to_ne_bytes())),
+            Err(MapError::KeyNotFound)
+        );
+
+        // Only the oldest entry can be removed
+        assert_eq!(
+            map.lookup_and_delete(&300u64.to_ne_bytes()),
+            Err(MapError::NotSupported)
+        );
+        assert_eq!(
+            map.lookup_and_delete(&200u64.to_ne_bytes()),
+            Ok(2i64.to_ne_bytes().to_vec())
+        );
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.oldest().map(|(ts, _)| ts), Some(300));
+
+        // Pushing after draining keeps the order
+        map.push(600, &6i64.to_ne_bytes()).expect("push");
+        map.push(700, &7i64.to_ne_bytes()).expect("push");
+        assert_eq!(map.oldest().map(|(ts, _)| ts), Some(400));
+        assert_eq!(map.newest().map(|(ts, _)| ts), Some(700));
+    }
+
     #[cfg(feature = "cloud-profile")]
     #[test]
     fn timeseries_resize() {
@@ -70,6 +70,8 @@ mod sealed {
 /// Constants define hard limits enforced at compile time:
 /// - `MAX_STACK_SIZE`: Maximum BPF stack in bytes
 /// - `MAX_INSN_COUNT`: Maximum instructions (for WCET in embedded)
+/// - `MAX_TAIL_CALLS`: Maximum tail calls in one run
+/// - `MAX_LOOP_ITERATIONS`: Maximum iterations of one `bpf_loop`
 /// - `JIT_ALLOWED`: Whether JIT compilation is permitted
 /// - `RESTART_ACCEPTABLE`: Whether restart is a valid failure recovery
 pub trait PhysicalProfile: sealed::Sealed + 'static {
@@ -95,6 +97,23 @@ pub trait PhysicalProfile: sealed::Sealed + 'static {
     /// - Embedded: 100,000 (hard limit for WCET)
     const MAX_INSN_COUNT: usize;
 
+    /// Maximum number of tail calls in one program run.
+    ///
+    /// Tail calls share the instruction budget of the run, so this only
+    /// bounds the length of the chain.
+    /// - Cloud: 33 (as in Linux)
+    /// - Embedded: 8
+    const MAX_TAIL_CALLS: usize;
+
+    /// Maximum number of iterations of one `bpf_loop` call.
+    ///
+    /// Larger counts make the helper fail. The embedded WCET bound charges
+    /// each loop its callback this many times unless the count is a
+    /// constant.
+    /// - Cloud: 8,388,608 (as in Linux)
+    /// - Embedded: 4,096 (a full time-series window)
+    const MAX_LOOP_ITERATIONS: usize;
+
     /// Whether JIT compilation is allowed.
     ///
     /// - Cloud: true (JIT is default execution mode)
@@ -146,6 +165,12 @@ impl PhysicalProfile for CloudProfile {
     /// 1 million instructions (soft limit)
     const MAX_INSN_COUNT: usize = 1_000_000;
 
+    /// Same chain length as Linux
+    const MAX_TAIL_CALLS: usize = 33;
+
+    /// Same loop bound as Linux
+    const MAX_LOOP_ITERATIONS: usize = 1 << 23;
+
     /// JIT enabled by default
     const JIT_ALLOWED: bool = true;
 
@@ -191,6 +216,12 @@ impl PhysicalProfile for EmbeddedProfile {
     /// 100K instructions (hard limit for WCET)
     const MAX_INSN_COUNT: usize = 100_000;
 
+    /// Short chains keep dispatch overhead predictable
+    const MAX_TAIL_CALLS: usize = 8;
+
+    /// Enough to walk the largest time-series window
+    const MAX_LOOP_ITERATIONS: usize = 4 * 1024;
+
     /// No JIT - interpreter or AOT only
     const JIT_ALLOWED: bool = false;
 
@@ -132,9 +132,14 @@ impl BpfScheduler {
     }
 
     /// Submit a program for execution.
-    pub fn submit(&mut self, request: BpfExecRequest<ActiveProfile>) -> SchedResult<()> {
+    ///
+    /// Returns the submission's sequence number, which the program carries
+    /// as [`QueuedProgram::submitted_at`] once it is selected.
+    pub fn submit(&mut self, request: BpfExecRequest<ActiveProfile>) -> SchedResult<u64> {
         let queued = QueuedProgram::from_request(request);
-        self.queue.enqueue(queued)
+        let seq = queued.submitted_at;
+        self.queue.enqueue(queued)?;
+        Ok(seq)
     }
 
     /// Get the next program to execute.
@@ -224,6 +229,27 @@ mod tests {
         assert!(!sched.has_pending());
     }
 
+    #[test]
+    fn submit_returns_sequence() {
+        let mut sched = BpfScheduler::new();
+        let program = create_test_program();
+
+        let first = sched
+            .submit(BpfExecRequest::new(
+                ProgId(1),
+                Arc::clone(&program),
+                BpfContext::empty(),
+            ))
+            .expect("submit");
+        let second = sched
+            .submit(BpfExecRequest::new(ProgId(2), program, BpfContext::empty()))
+            .expect("submit");
+        assert!(second > first);
+
+        assert_eq!(sched.next().expect("next").submitted_at, first);
+        assert_eq!(sched.next().expect("next").submitted_at, second);
+    }
+
     #[test]
     fn cancel_pending() {
         let mut sched = BpfScheduler::new();
@@ -40,6 +40,9 @@ pub enum SigningError {
 
     /// Program requires capabilities that are not available.
     MissingCapabilities,
+
+    /// Program is not signed and unsigned programs are not allowed.
+    UnsignedProgram,
 }
 
 impl fmt::Display for SigningError {
@@ -60,6 +63,7 @@ impl fmt::Display for SigningError {
             Self::MissingCapabilities => {
                 write!(f, "program requires capabilities that are not available")
             }
+            Self::UnsignedProgram => write!(f, "unsigned programs are not allowed"),
         }
     }
 }
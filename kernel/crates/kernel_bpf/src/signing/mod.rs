@@ -78,7 +78,7 @@ mod verifier;
 
 pub use error::{SigningError, SigningResult};
 pub use hash::{ProgramHash, SHA3_256_LEN};
-pub use signature::{SIGNATURE_LEN, Signature, SignedProgram, SignedProgramHeader};
+pub use signature::{SIGNATURE_LEN, SIGNER_ID_LEN, Signature, SignedProgram, SignedProgramHeader};
 pub use verifier::{PUBLIC_KEY_LEN, SignatureVerifier, TrustedKey};
 
 /// Magic bytes identifying a signed BPF program.
@@ -75,7 +75,8 @@ impl core::fmt::Debug for TrustedKey {
 pub struct SignatureVerifier {
     /// List of trusted public keys.
     trusted_keys: Vec<TrustedKey>,
-    /// Whether to allow unsigned programs (debug mode only).
+    /// Whether to allow unsigned programs (cloud profile development builds
+    /// only).
     #[cfg(feature = "cloud-profile")]
     allow_unsigned: bool,
 }
@@ -130,6 +131,32 @@ impl SignatureVerifier {
         self.allow_unsigned = allow;
     }
 
+    /// Check whether unsigned programs are accepted. Never on the embedded
+    /// profile.
+    pub fn allows_unsigned(&self) -> bool {
+        #[cfg(feature = "cloud-profile")]
+        {
+            self.allow_unsigned
+        }
+        #[cfg(not(feature = "cloud-profile"))]
+        {
+            false
+        }
+    }
+
+    /// Apply the signing policy to a program that carries no signature.
+    ///
+    /// Unsigned programs are refused unless [`set_allow_unsigned`] was enabled.
+    ///
+    /// [`set_allow_unsigned`]: Self::set_allow_unsigned
+    pub fn check_unsigned(&self) -> SigningResult<()> {
+        if self.allows_unsigned() {
+            Ok(())
+        } else {
+            Err(SigningError::UnsignedProgram)
+        }
+    }
+
     /// Verify a signed program.
     ///
     /// This checks:
@@ -884,6 +911,25 @@ mod tests {
         assert_eq!(key.id(), &[0u8; SIGNER_ID_LEN]);
     }
 
+    #[test]
+    fn unsigned_policy() {
+        let verifier = SignatureVerifier::new();
+        assert!(!verifier.allows_unsigned());
+        assert_eq!(
+            verifier.check_unsigned(),
+            Err(SigningError::UnsignedProgram)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cloud-profile")]
+    fn unsigned_policy_opt_in() {
+        let mut verifier = SignatureVerifier::new();
+        verifier.set_allow_unsigned(true);
+        assert!(verifier.allows_unsigned());
+        assert!(verifier.check_unsigned().is_ok());
+    }
+
     #[test]
     fn verifier_add_remove_keys() {
         let mut verifier = SignatureVerifier::new();
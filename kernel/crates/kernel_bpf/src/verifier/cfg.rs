@@ -8,6 +8,7 @@ use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 
 use crate::bytecode::insn::BpfInsn;
+use crate::bytecode::subprog;
 
 /// Control flow graph for a BPF program.
 #[derive(Debug, Clone)]
@@ -21,6 +22,9 @@ pub struct ControlFlowGraph {
     /// Edges in the CFG: (from_idx, to_idx)
     edges: Vec<(usize, usize)>,
 
+    /// Edges from BPF function calls and function loads to their targets
+    call_edges: Vec<(usize, usize)>,
+
     /// Back edges (for loop detection)
     back_edges: Vec<(usize, usize)>,
 
@@ -35,6 +39,7 @@ impl ControlFlowGraph {
             insn_count: insns.len(),
             leaders: BTreeSet::new(),
             edges: Vec::new(),
+            call_edges: Vec::new(),
             back_edges: Vec::new(),
             exit_points: Vec::new(),
         };
@@ -58,6 +63,13 @@ impl ControlFlowGraph {
                 if idx + 1 < insns.len() {
                     cfg.edges.push((idx, idx + 1));
                 }
+                // BPF functions start blocks of their own. Calling one
+                // earlier in the stream isn't a loop, so these edges are
+                // kept apart from the jumps.
+                if let Some(target) = subprog::call_target(idx, insn).filter(|&t| t < insns.len()) {
+                    cfg.call_edges.push((idx, target));
+                    cfg.leaders.insert(target);
+                }
                 continue;
             }
 
@@ -86,6 +98,12 @@ impl ControlFlowGraph {
                 if idx + 2 < insns.len() {
                     cfg.edges.push((idx, idx + 2));
                 }
+                // A loaded function is called back by a helper, which
+                // counts as a call from here
+                if let Some(target) = subprog::func_target(idx, insn).filter(|&t| t < insns.len()) {
+                    cfg.call_edges.push((idx, target));
+                    cfg.leaders.insert(target);
+                }
             } else {
                 // Normal instruction: falls through
                 if idx + 1 < insns.len() {
@@ -135,22 +153,30 @@ impl ControlFlowGraph {
         self.leaders.iter().copied()
     }
 
-    /// Get all edges from an instruction.
+    /// Get all edges from an instruction, calls included.
     pub fn successors(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
         self.edges
             .iter()
+            .chain(&self.call_edges)
             .filter(move |(from, _)| *from == idx)
             .map(|(_, to)| *to)
     }
 
-    /// Get all edges to an instruction.
+    /// Get all edges to an instruction, calls included.
     pub fn predecessors(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
         self.edges
             .iter()
+            .chain(&self.call_edges)
             .filter(move |(_, to)| *to == idx)
             .map(|(from, _)| *from)
     }
 
+    /// Get all BPF function calls and function loads as (insn, target)
+    /// pairs.
+    pub fn call_edges(&self) -> &[(usize, usize)] {
+        &self.call_edges
+    }
+
     /// Check if there's a back edge (potential loop).
     pub fn has_loops(&self) -> bool {
         !self.back_edges.is_empty()
@@ -217,6 +243,7 @@ impl ControlFlowGraph {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bytecode::insn::WideInsn;
 
     #[test]
     fn empty_program() {
@@ -278,6 +305,40 @@ mod tests {
         assert!(cfg.has_loops());
         assert!(!cfg.back_edges().is_empty());
     }
+
+    #[test]
+    fn calls_reach_functions_without_loops() {
+        let insns = [
+            BpfInsn::call_local(1),  // 0: call 2
+            BpfInsn::exit(),         // 1
+            BpfInsn::call_local(-3), // 2: call 0
+            BpfInsn::exit(),         // 3
+        ];
+
+        let cfg = ControlFlowGraph::build(&insns);
+        assert!(cfg.is_leader(2));
+        assert!(cfg.is_reachable(3));
+        assert_eq!(cfg.call_edges(), &[(0, 2), (2, 0)]);
+        assert!(!cfg.has_loops());
+    }
+
+    #[test]
+    fn loaded_functions_are_reachable() {
+        let func = WideInsn::ld_func(2, 3);
+        let insns = [
+            func.insn,                // 0: r2 = &func 4
+            func.next,                // 1
+            BpfInsn::mov64_imm(0, 0), // 2
+            BpfInsn::exit(),          // 3
+            BpfInsn::mov64_imm(0, 0), // 4
+            BpfInsn::exit(),          // 5
+        ];
+
+        let cfg = ControlFlowGraph::build(&insns);
+        assert!(cfg.is_leader(4));
+        assert!(cfg.is_reachable(5));
+        assert_eq!(cfg.call_edges(), &[(0, 4)]);
+    }
 }
 
 impl BpfInsn {
@@ -11,12 +11,14 @@ use core::marker::PhantomData;
 
 use super::cfg::ControlFlowGraph;
 use super::error::{VerifyError, VerifyResult};
-use super::helpers::{HelperValidation, validate_helper_call};
+use super::helpers::{HelperId, check_stack_args, validate_helper_call};
 use super::state::{RegState, RegType, ScalarValue, StackSlot, VerifierState};
+use super::subprog::check_subprogs;
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::opcode::{AluOp, OpcodeClass};
 use crate::bytecode::program::{BpfProgType, BpfProgram};
 use crate::bytecode::registers::Register;
+use crate::bytecode::subprog::{self, MAX_FRAME_SIZE, Subprog};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
 /// BPF program verifier.
@@ -31,6 +33,12 @@ pub struct Verifier<P: PhysicalProfile = ActiveProfile> {
     /// Verifier states at each instruction (for path-sensitive analysis)
     states: Vec<Option<VerifierState>>,
 
+    /// Functions of the program
+    subprogs: Vec<Subprog>,
+
+    /// Deepest stack each function's frames use
+    frame_depths: Vec<usize>,
+
     /// Profile marker
     _profile: PhantomData<P>,
 }
@@ -41,6 +49,8 @@ impl<P: PhysicalProfile> Verifier<P> {
         Self {
             cfg: None,
             states: Vec::new(),
+            subprogs: Vec::new(),
+            frame_depths: Vec::new(),
             _profile: PhantomData,
         }
     }
@@ -63,29 +73,34 @@ impl<P: PhysicalProfile> Verifier<P> {
         // Phase 1: Basic checks
         verifier.check_basic(insns)?;
 
-        // Phase 2: Build CFG
+        // Phase 2: Build CFG and check the BPF functions
         let cfg = ControlFlowGraph::build(insns);
         verifier.cfg = Some(cfg);
+        let call_stack = check_subprogs::<P>(insns)?;
+        verifier.subprogs = subprog::split(insns);
+        verifier.frame_depths = alloc::vec![0; verifier.subprogs.len()];
 
         // Phase 3: Core safety verification
-        let stack_size = verifier.verify_safety(insns)?;
+        let stack_size = verifier.verify_safety(insns)?.max(call_stack);
 
         // Phase 4: Profile-specific constraints
         verifier.verify_profile_constraints(insns)?;
 
         // Build the verified program
-        BpfProgram::new(prog_type, insns.to_vec(), stack_size).map_err(|e| match e {
-            crate::bytecode::program::ProgramError::StackSizeExceeded { required, limit } => {
-                VerifyError::StackExceeded {
-                    used: required,
-                    limit,
+        BpfProgram::new(prog_type, insns.to_vec(), stack_size)
+            .map(|program| program.with_stack_depths(&verifier.frame_depths))
+            .map_err(|e| match e {
+                crate::bytecode::program::ProgramError::StackSizeExceeded { required, limit } => {
+                    VerifyError::StackExceeded {
+                        used: required,
+                        limit,
+                    }
                 }
-            }
-            crate::bytecode::program::ProgramError::InsnCountExceeded { count, limit } => {
-                VerifyError::InsnCountExceeded { count, limit }
-            }
-            _ => VerifyError::EmptyProgram,
-        })
+                crate::bytecode::program::ProgramError::InsnCountExceeded { count, limit } => {
+                    VerifyError::InsnCountExceeded { count, limit }
+                }
+                _ => VerifyError::EmptyProgram,
+            })
     }
 
     /// Perform basic structural checks.
@@ -157,7 +172,7 @@ impl<P: PhysicalProfile> Verifier<P> {
         self.states = alloc::vec![None; insns.len()];
 
         // Start verification from entry
-        let initial_state = VerifierState::new_entry(P::MAX_STACK_SIZE);
+        let initial_state = VerifierState::new_entry(self.frame_size());
         self.verify_path(insns, 0, initial_state)?;
 
         // Return computed stack size
@@ -197,6 +212,8 @@ impl<P: PhysicalProfile> Verifier<P> {
                 return Err(VerifyError::InfiniteLoop { insn_idx: idx });
             }
 
+            self.note_depth(idx, &state);
+
             // Merge or store state
             if let Some(existing) = &self.states[idx] {
                 // Already verified this path with compatible state
@@ -239,22 +256,58 @@ impl<P: PhysicalProfile> Verifier<P> {
                     state.insn_idx = fallthrough;
                     state.insn_processed += 1;
                 }
-                InsnResult::Exit => {
-                    return Ok(());
+                InsnResult::Call(target) => {
+                    state.insn_idx = target;
+                    state.insn_processed += 1;
                 }
+                InsnResult::Exit => match state.pop_frame() {
+                    Some(return_idx) => {
+                        state.insn_idx = return_idx;
+                        state.insn_processed += 1;
+                    }
+                    None => return Ok(()),
+                },
             }
         }
     }
 
+    /// Bytes of stack each frame may use.
+    ///
+    /// Frames of a program with BPF functions are capped, so that its
+    /// deepest call chain stays small on the kernel stack.
+    fn frame_size(&self) -> usize {
+        if self.subprogs.len() > 1 {
+            MAX_FRAME_SIZE.min(P::MAX_STACK_SIZE)
+        } else {
+            P::MAX_STACK_SIZE
+        }
+    }
+
+    /// Record the stack used so far by the frame running instruction `idx`.
+    fn note_depth(&mut self, idx: usize, state: &VerifierState) {
+        let func = self.subprogs.partition_point(|s| s.start <= idx) - 1;
+        self.frame_depths[func] = self.frame_depths[func].max(state.stack.max_depth());
+    }
+
     /// Check if two states are compatible (for path merging).
     fn states_compatible(&self, s1: &VerifierState, s2: &VerifierState) -> bool {
-        // Simple compatibility check: same register types
+        // The state already verified must cover every register
         for i in 0..Register::COUNT {
-            if s1.regs[i].reg_type != s2.regs[i].reg_type {
+            if !s2.regs[i].covers(&s1.regs[i]) {
                 return false;
             }
         }
-        true
+        // Callers must match too, or the code after their calls would
+        // only be checked for the first of them
+        s1.frames.len() == s2.frames.len()
+            && s1.frames.iter().zip(&s2.frames).all(|(f1, f2)| {
+                f1.return_idx == f2.return_idx
+                    && f1
+                        .callee_saved
+                        .iter()
+                        .zip(&f2.callee_saved)
+                        .all(|(r1, r2)| r2.covers(r1))
+            })
     }
 
     /// Verify a single instruction.
@@ -273,9 +326,33 @@ impl<P: PhysicalProfile> Verifier<P> {
                     reg: Register::R0,
                 });
             }
+            // bpf_loop reads a callback's result as "stop or go on"
+            if state.in_callback() && state.reg(Register::R0).reg_type != RegType::Scalar {
+                return Err(VerifyError::InvalidCall {
+                    insn_idx: idx,
+                    reason: "callback must return a scalar",
+                });
+            }
             return Ok(InsnResult::Exit);
         }
 
+        // Call of a BPF function: enter its frame, capped like the
+        // caller's
+        if let Some(target) = subprog::call_target(idx, insn) {
+            state.push_frame(idx + 1, self.frame_size());
+            return Ok(InsnResult::Call(target));
+        }
+
+        // bpf_loop: check the call like any other, then walk the callback
+        // as a function called from here
+        if insn.is_call() && insn.imm == HelperId::Loop as i32 {
+            let callback = state.reg(Register::R2).ptr_offset as usize;
+            let ctx = state.reg(Register::R3).clone();
+            self.verify_call(insn, state, idx)?;
+            state.push_callback_frame(idx + 1, self.frame_size(), ctx);
+            return Ok(InsnResult::Call(callback));
+        }
+
         // Call instruction
         if insn.is_call() {
             self.verify_call(insn, state, idx)?;
@@ -293,15 +370,16 @@ impl<P: PhysicalProfile> Verifier<P> {
             return self.verify_jump(insn, state, idx);
         }
 
-        // Memory instructions
-        if insn.is_memory() {
-            self.verify_memory(insn, state, idx)?;
+        // Wide instruction (64-bit immediate load), before the other
+        // instructions of its class
+        if insn.is_wide() {
+            self.verify_wide_load(insn, state, idx)?;
             return Ok(InsnResult::Continue);
         }
 
-        // Wide instruction (64-bit immediate load)
-        if insn.is_wide() {
-            self.verify_wide_load(insn, state, idx)?;
+        // Memory instructions
+        if insn.is_memory() {
+            self.verify_memory(insn, state, idx)?;
             return Ok(InsnResult::Continue);
         }
 
@@ -375,8 +453,7 @@ impl<P: PhysicalProfile> Verifier<P> {
             });
         }
 
-        // Update destination register to scalar
-        state.set_scalar(dst, Some(ScalarValue::unknown()));
+        state.set_alu_result(insn, dst, alu_op);
 
         Ok(())
     }
@@ -460,54 +537,25 @@ impl<P: PhysicalProfile> Verifier<P> {
         ];
 
         // Validate helper call using the registry
-        match validate_helper_call(helper_id, &arg_types) {
-            HelperValidation::Valid(sig) => {
-                // Caller-saved registers are clobbered
-                for reg in [
-                    Register::R0,
-                    Register::R1,
-                    Register::R2,
-                    Register::R3,
-                    Register::R4,
-                    Register::R5,
-                ] {
-                    *state.reg_mut(reg) = RegState::uninit();
-                }
+        let sig = validate_helper_call(helper_id, &arg_types).into_result(idx)?;
+        check_stack_args(&sig, state, idx)?;
+
+        // Caller-saved registers are clobbered
+        for reg in [
+            Register::R0,
+            Register::R1,
+            Register::R2,
+            Register::R3,
+            Register::R4,
+            Register::R5,
+        ] {
+            *state.reg_mut(reg) = RegState::uninit();
+        }
 
-                // R0 contains return value based on helper signature
-                *state.reg_mut(Register::R0) = sig.ret.to_reg_state();
+        // R0 contains return value based on helper signature
+        *state.reg_mut(Register::R0) = sig.ret.to_reg_state();
 
-                Ok(())
-            }
-            HelperValidation::UnknownHelper(id) => Err(VerifyError::InvalidHelper {
-                insn_idx: idx,
-                helper_id: id,
-            }),
-            HelperValidation::NotAvailable(helper) => Err(VerifyError::HelperNotAvailable {
-                insn_idx: idx,
-                helper_name: helper.name(),
-            }),
-            HelperValidation::WrongArgCount {
-                helper,
-                expected,
-                got,
-            } => Err(VerifyError::HelperArgCount {
-                insn_idx: idx,
-                helper_name: helper.name(),
-                expected,
-                got,
-            }),
-            HelperValidation::ArgTypeMismatch {
-                helper,
-                arg_idx,
-                expected: _,
-                got: _,
-            } => Err(VerifyError::HelperArgType {
-                insn_idx: idx,
-                helper_name: helper.name(),
-                arg_idx,
-            }),
-        }
+        Ok(())
     }
 
     /// Verify a memory instruction.
@@ -572,6 +620,7 @@ impl<P: PhysicalProfile> Verifier<P> {
                             size: size.size_bytes(),
                         });
                     }
+                    state.stack.mark_read(offset, size.size_bytes());
                 }
 
                 // Result is scalar
@@ -652,6 +701,25 @@ impl<P: PhysicalProfile> Verifier<P> {
                         reason: "cannot write to this pointer type",
                     });
                 }
+
+                // Update stack state if writing to stack
+                if dst_state.reg_type == RegType::PtrToStack
+                    || dst_state.reg_type == RegType::PtrToFp
+                {
+                    let offset = dst_state.ptr_offset + insn.offset as i64;
+                    if !state.stack.is_valid_access(offset, size.size_bytes()) {
+                        return Err(VerifyError::OutOfBoundsAccess {
+                            insn_idx: idx,
+                            offset,
+                            size: size.size_bytes(),
+                        });
+                    }
+
+                    // Mark stack slots as written
+                    for i in 0..size.size_bytes() {
+                        let _ = state.stack.set(offset - i as i64, StackSlot::Scalar);
+                    }
+                }
             }
 
             _ => {}
@@ -676,6 +744,24 @@ impl<P: PhysicalProfile> Verifier<P> {
             return Err(VerifyError::WriteToReadOnly { insn_idx: idx });
         }
 
+        // A map, for the helpers taking one
+        if insn.is_map_load() {
+            *state.reg_mut(dst) = RegState::map_ptr(insn.imm as u32);
+            return Ok(());
+        }
+
+        // Global data, resolved to an address in the map's value at load
+        if insn.is_map_value_load() {
+            *state.reg_mut(dst) = RegState::map_value_ptr();
+            return Ok(());
+        }
+
+        // A function's address, checked against the program already
+        if let Some(start) = subprog::func_target(idx, insn) {
+            *state.reg_mut(dst) = RegState::func_ptr(start);
+            return Ok(());
+        }
+
         // Result is scalar with known lower 32 bits
         state.set_scalar(dst, Some(ScalarValue::unknown()));
 
@@ -704,22 +790,29 @@ impl<P: PhysicalProfile> Verifier<P> {
 
         // Check for unbounded loops
         if cfg.has_loops() {
-            // In embedded profile, all loops must be bounded
-            // For now, we simply reject programs with back edges
-            // A more sophisticated analysis would compute loop bounds
+            // In embedded profile, all loops must be bounded. Back edges
+            // are rejected outright; bounded loops go through bpf_loop,
+            // whose callbacks are calls rather than back edges.
             if let Some(&(from, _to)) = cfg.back_edges().first() {
                 return Err(VerifyError::UnboundedLoop { insn_idx: from });
             }
         }
 
+        // Every path, with each bpf_loop callback charged per iteration,
+        // must fit the instruction budget
+        let cycles = super::subprog::wcet::<P>(insns);
+        if cycles > P::MAX_INSN_COUNT as u64 {
+            return Err(VerifyError::WcetExceeded {
+                cycles,
+                budget: P::MAX_INSN_COUNT as u64,
+            });
+        }
+
         // Check for dynamic allocation helpers
         for (idx, insn) in insns.iter().enumerate() {
-            if insn.is_call() {
+            if insn.is_call() && !insn.is_pseudo_call() {
                 // List of helpers that perform dynamic allocation
-                const ALLOC_HELPERS: &[i32] = &[
-                    // bpf_ringbuf_reserve, bpf_ringbuf_submit, etc.
-                    // These would be defined based on your helper IDs
-                ];
+                const ALLOC_HELPERS: &[i32] = &[super::helpers::HelperId::RingbufReserve as i32];
 
                 if ALLOC_HELPERS.contains(&insn.imm) {
                     return Err(VerifyError::DynamicAllocationAttempted { insn_idx: idx });
@@ -753,6 +846,8 @@ enum InsnResult {
     Jump(usize),
     /// Branch: verify both paths
     Branch { fallthrough: usize, target: usize },
+    /// Call of the BPF function at the target instruction
+    Call(usize),
     /// Program exit
     Exit,
 }
@@ -831,4 +926,131 @@ mod tests {
             Err(VerifyError::UninitializedRegister { .. })
         ));
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Slow under Miri due to large stack allocation (512KB for cloud profile)
+    fn verify_function_call() {
+        let insns = [
+            BpfInsn::mov64_imm(6, 1),          // 0: r6 = 1
+            BpfInsn::new(0x7a, 10, 0, -8, 0),  // 1: *(u64 *)(r10 - 8) = 0
+            BpfInsn::mov64_imm(1, 2),          // 2: r1 = 2
+            BpfInsn::call_local(2),            // 3: call 6
+            BpfInsn::add64_reg(0, 6),          // 4: r0 += r6
+            BpfInsn::exit(),                   // 5
+            BpfInsn::new(0x7a, 10, 0, -16, 0), // 6: *(u64 *)(r10 - 16) = 0
+            BpfInsn::mov64_reg(0, 1),          // 7: r0 = r1
+            BpfInsn::exit(),                   // 8
+        ];
+
+        let program = Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns)
+            .expect("valid program");
+        assert_eq!(program.stack_size(), 24);
+        assert_eq!(program.subprogs().len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Slow under Miri due to large stack allocation (512KB for cloud profile)
+    fn verify_function_frame_depths() {
+        let mut insns = [
+            BpfInsn::call_local(1),         // 0: call 2
+            BpfInsn::exit(),                // 1
+            BpfInsn::mov64_reg(1, 10),      // 2: r1 = r10
+            BpfInsn::add64_imm(1, -64),     // 3: r1 -= 64
+            BpfInsn::new(0x7a, 1, 0, 0, 0), // 4: *(u64 *)(r1 + 0) = 0
+            BpfInsn::mov64_imm(0, 0),       // 5
+            BpfInsn::exit(),                // 6
+        ];
+
+        // The callee's frame covers the bytes it reaches through R1...
+        let program = Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns)
+            .expect("valid program");
+        assert!(program.subprogs()[1].stack_depth >= 64);
+
+        // ...which must stay within a frame of its own
+        insns[3] = BpfInsn::add64_imm(1, -(MAX_FRAME_SIZE as i32) - 8);
+        assert!(Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Slow under Miri due to large stack allocation (512KB for cloud profile)
+    fn verify_function_frames() {
+        // The callee can't read the caller's R6...
+        let reads_r6 = [
+            BpfInsn::mov64_imm(6, 1),
+            BpfInsn::call_local(1),
+            BpfInsn::exit(),
+            BpfInsn::mov64_reg(0, 6),
+            BpfInsn::exit(),
+        ];
+        assert!(matches!(
+            Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &reads_r6),
+            Err(VerifyError::UninitializedRegister {
+                insn_idx: 3,
+                reg: Register::R6
+            })
+        ));
+
+        // ...and the caller's argument registers are gone after the call
+        let reads_r1 = [
+            BpfInsn::mov64_imm(1, 1),
+            BpfInsn::call_local(2),
+            BpfInsn::mov64_reg(0, 1),
+            BpfInsn::exit(),
+            BpfInsn::mov64_imm(0, 0),
+            BpfInsn::exit(),
+        ];
+        assert!(matches!(
+            Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &reads_r1),
+            Err(VerifyError::UninitializedRegister {
+                insn_idx: 2,
+                reg: Register::R1
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Slow under Miri due to large stack allocation (512KB for cloud profile)
+    fn verify_every_call_site() {
+        // The second call returns to code that reads an uninitialized R2
+        let insns = [
+            BpfInsn::call_local(4),   // 0: call 5
+            BpfInsn::mov64_reg(6, 0), // 1
+            BpfInsn::call_local(2),   // 2: call 5
+            BpfInsn::add64_reg(0, 2), // 3
+            BpfInsn::exit(),          // 4
+            BpfInsn::mov64_imm(0, 0), // 5
+            BpfInsn::exit(),          // 6
+        ];
+
+        let result = Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(matches!(
+            result,
+            Err(VerifyError::UninitializedRegister { insn_idx: 3, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Slow under Miri due to large stack allocation (512KB for cloud profile)
+    fn verify_global_data_access() {
+        use crate::bytecode::insn::WideInsn;
+
+        // Globals are read through the address of a map value...
+        let global = WideInsn::ld_map_value(1, 0, 8);
+        let insns = [
+            global.insn,
+            global.next,
+            BpfInsn::new(0x61, 0, 1, 0, 0), // r0 = *(u32 *)(r1 + 0)
+            BpfInsn::exit(),
+        ];
+        assert!(Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns).is_ok());
+
+        // ...but a plain 64-bit constant is no pointer
+        let constant = WideInsn::ld_dw_imm(1, 0x1000);
+        let insns = [constant.insn, constant.next, insns[2], insns[3]];
+        let result = Verifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(matches!(
+            result,
+            Err(VerifyError::InvalidMemoryAccess { insn_idx: 2, .. })
+        ));
+    }
 }
@@ -124,6 +124,14 @@ pub enum VerifyError {
         insn_idx: usize,
     },
 
+    /// Invalid call of a BPF function
+    InvalidCall {
+        /// Instruction index
+        insn_idx: usize,
+        /// Description of the issue
+        reason: &'static str,
+    },
+
     // ========================================
     // Profile-specific violations
     // ========================================
@@ -195,6 +203,35 @@ pub enum VerifyError {
     },
 }
 
+impl VerifyError {
+    /// Index of the offending instruction, for errors about one.
+    pub fn insn_idx(&self) -> Option<usize> {
+        match *self {
+            Self::InvalidOpcode { insn_idx, .. }
+            | Self::InvalidRegister { insn_idx, .. }
+            | Self::UninitializedRegister { insn_idx, .. }
+            | Self::OutOfBoundsAccess { insn_idx, .. }
+            | Self::InvalidMemoryAccess { insn_idx, .. }
+            | Self::UnreachableInstruction { insn_idx }
+            | Self::InfiniteLoop { insn_idx }
+            | Self::InvalidJump { insn_idx, .. }
+            | Self::InvalidHelper { insn_idx, .. }
+            | Self::HelperNotAvailable { insn_idx, .. }
+            | Self::HelperArgCount { insn_idx, .. }
+            | Self::HelperArgType { insn_idx, .. }
+            | Self::DivisionByZero { insn_idx }
+            | Self::InvalidCall { insn_idx, .. }
+            | Self::WriteToReadOnly { insn_idx }
+            | Self::MisalignedAccess { insn_idx, .. } => Some(insn_idx),
+            #[cfg(feature = "embedded-profile")]
+            Self::InterruptUnsafe { insn_idx, .. }
+            | Self::DynamicAllocationAttempted { insn_idx }
+            | Self::UnboundedLoop { insn_idx } => Some(insn_idx),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for VerifyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -296,6 +333,9 @@ impl fmt::Display for VerifyError {
             Self::DivisionByZero { insn_idx } => {
                 write!(f, "possible division by zero at instruction {}", insn_idx)
             }
+            Self::InvalidCall { insn_idx, reason } => {
+                write!(f, "invalid call at instruction {}: {}", insn_idx, reason)
+            }
             Self::StackExceeded { used, limit } => {
                 write!(f, "stack size {} exceeds limit {}", used, limit)
             }
@@ -1,243 +1,22 @@
-//! Helper Function Registry
+//! Helper Call Validation
 //!
-//! This module defines BPF helper function signatures and provides validation
-//! for helper calls during verification. Each helper has a defined signature
-//! specifying argument types and return type.
-//!
-//! # Helper Categories
-//!
-//! - **Core**: Basic operations (time, random, CPU ID)
-//! - **Map**: Map operations (lookup, update, delete)
-//! - **Probe**: Memory probing (probe_read)
-//! - **Process**: Process information (PID, UID, comm)
-//! - **Robotics**: rkBPF-specific helpers for robotics use cases
+//! This module checks helper calls during verification against the
+//! signatures in the [helper registry](crate::helpers). Each helper has a
+//! defined signature specifying argument types and return type.
 //!
 //! # Profile Availability
 //!
 //! Some helpers are only available in certain profiles:
 //! - Cloud: All helpers available
 //! - Embedded: Restricted set (no dynamic allocation helpers)
+//!
+//! Helpers that are declared but not implemented by this kernel are
+//! rejected as unavailable.
 
-use super::state::{RegState, RegType};
-
-/// Helper function identifier.
-///
-/// These IDs match the standard BPF helper IDs where applicable,
-/// with rkBPF extensions starting at 1000.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(i32)]
-pub enum HelperId {
-    // ===== Core Helpers (1-10) =====
-    /// Get current time in nanoseconds
-    KtimeGetNs = 1,
-    /// Print debug message (debug builds only)
-    TracePrintk = 2,
-    /// Look up element in map
-    MapLookupElem = 3,
-    /// Update element in map
-    MapUpdateElem = 4,
-    /// Delete element from map
-    MapDeleteElem = 5,
-    /// Output to ring buffer (reserve + submit)
-    RingbufOutput = 6,
-
-    /// Get pseudo-random u32
-    GetPrandomU32 = 7,
-    /// Get current CPU ID
-    GetSmpProcessorId = 8,
-
-    // ===== Memory Helpers (20-30) =====
-    /// Read from arbitrary memory (with safety checks)
-    ProbeRead = 20,
-
-    // ===== Process Helpers (30-40) =====
-    /// Get current PID and TGID
-    GetCurrentPidTgid = 30,
-    /// Get current UID and GID
-    GetCurrentUidGid = 31,
-    /// Get current process command name
-    GetCurrentComm = 32,
-
-    // ===== Ring Buffer Helpers (Advanced) (40-50) =====
-    /// Reserve space in ring buffer
-    RingbufReserve = 40,
-    /// Submit reserved ring buffer entry
-    RingbufSubmit = 41,
-    /// Discard reserved ring buffer entry
-    RingbufDiscard = 42,
-
-    // ===== rkBPF Robotics Helpers (1000+) =====
-    /// Emergency stop all motors
-    MotorEmergencyStop = 1000,
-    /// Push value to time-series map
-    TimeseriesPush = 1001,
-    /// Get last timestamp from sensor
-    SensorLastTimestamp = 1002,
-    /// Set GPIO pin state
-    GpioSet = 1003,
-    /// Read GPIO pin state
-    GpioGet = 1004,
-    /// Write to PWM channel
-    PwmWrite = 1005,
-    /// Read IIO sensor value
-    IioRead = 1006,
-    /// Send CAN message
-    CanSend = 1007,
-}
-
-impl HelperId {
-    /// Try to convert from raw helper ID.
-    pub fn from_raw(id: i32) -> Option<Self> {
-        match id {
-            1 => Some(Self::KtimeGetNs),
-            2 => Some(Self::TracePrintk),
-            3 => Some(Self::MapLookupElem),
-            4 => Some(Self::MapUpdateElem),
-            5 => Some(Self::MapDeleteElem),
-            6 => Some(Self::RingbufOutput),
-            7 => Some(Self::GetPrandomU32),
-            8 => Some(Self::GetSmpProcessorId),
-            20 => Some(Self::ProbeRead),
-            30 => Some(Self::GetCurrentPidTgid),
-            31 => Some(Self::GetCurrentUidGid),
-            32 => Some(Self::GetCurrentComm),
-            40 => Some(Self::RingbufReserve),
-            41 => Some(Self::RingbufSubmit),
-            42 => Some(Self::RingbufDiscard),
-            1000 => Some(Self::MotorEmergencyStop),
-            1001 => Some(Self::TimeseriesPush),
-            1002 => Some(Self::SensorLastTimestamp),
-            1003 => Some(Self::GpioSet),
-            1004 => Some(Self::GpioGet),
-            1005 => Some(Self::PwmWrite),
-            1006 => Some(Self::IioRead),
-            1007 => Some(Self::CanSend),
-            _ => None,
-        }
-    }
-
-    /// Get the helper name for error messages.
-    pub const fn name(&self) -> &'static str {
-        match self {
-            Self::KtimeGetNs => "bpf_ktime_get_ns",
-            Self::TracePrintk => "bpf_trace_printk",
-            Self::GetPrandomU32 => "bpf_get_prandom_u32",
-            Self::GetSmpProcessorId => "bpf_get_smp_processor_id",
-            Self::MapLookupElem => "bpf_map_lookup_elem",
-            Self::MapUpdateElem => "bpf_map_update_elem",
-            Self::MapDeleteElem => "bpf_map_delete_elem",
-            Self::ProbeRead => "bpf_probe_read",
-            Self::GetCurrentPidTgid => "bpf_get_current_pid_tgid",
-            Self::GetCurrentUidGid => "bpf_get_current_uid_gid",
-            Self::GetCurrentComm => "bpf_get_current_comm",
-            Self::RingbufReserve => "bpf_ringbuf_reserve",
-            Self::RingbufSubmit => "bpf_ringbuf_submit",
-            Self::RingbufDiscard => "bpf_ringbuf_discard",
-            Self::RingbufOutput => "bpf_ringbuf_output",
-            Self::MotorEmergencyStop => "bpf_motor_emergency_stop",
-            Self::TimeseriesPush => "bpf_timeseries_push",
-            Self::SensorLastTimestamp => "bpf_sensor_last_timestamp",
-            Self::GpioSet => "bpf_gpio_set",
-            Self::GpioGet => "bpf_gpio_get",
-            Self::PwmWrite => "bpf_pwm_write",
-            Self::IioRead => "bpf_iio_read",
-            Self::CanSend => "bpf_can_send",
-        }
-    }
-
-    /// Check if this helper is available in embedded profile.
-    #[cfg(feature = "embedded-profile")]
-    pub const fn available_in_embedded(&self) -> bool {
-        match self {
-            // Core helpers - all available
-            Self::KtimeGetNs => true,
-            Self::GetPrandomU32 => true,
-            Self::GetSmpProcessorId => true,
-
-            // Map helpers - all available
-            Self::MapLookupElem => true,
-            Self::MapUpdateElem => true,
-            Self::MapDeleteElem => true,
-
-            // Debug helpers - disabled in embedded
-            Self::TracePrintk => false,
-
-            // Probe helpers - available but restricted
-            Self::ProbeRead => true,
-
-            // Process helpers - available
-            Self::GetCurrentPidTgid => true,
-            Self::GetCurrentUidGid => true,
-            Self::GetCurrentComm => true,
-
-            // Ring buffer - reserve disabled (dynamic alloc)
-            Self::RingbufReserve => false,
-            Self::RingbufSubmit => true,
-            Self::RingbufDiscard => true,
-            Self::RingbufOutput => true,
-
-            // Robotics helpers - all available
-            Self::MotorEmergencyStop => true,
-            Self::TimeseriesPush => true,
-            Self::SensorLastTimestamp => true,
-            Self::GpioSet => true,
-            Self::GpioGet => true,
-            Self::PwmWrite => true,
-            Self::IioRead => true,
-            Self::CanSend => true,
-        }
-    }
-
-    /// Check if this helper is available in cloud profile.
-    #[cfg(feature = "cloud-profile")]
-    pub const fn available_in_cloud(&self) -> bool {
-        // All helpers available in cloud profile
-        true
-    }
-
-    /// Check if helper is available in the current profile.
-    pub const fn is_available(&self) -> bool {
-        #[cfg(all(feature = "embedded-profile", not(feature = "cloud-profile")))]
-        {
-            self.available_in_embedded()
-        }
-        #[cfg(feature = "cloud-profile")]
-        {
-            self.available_in_cloud()
-        }
-    }
-}
-
-/// Argument type for helper functions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ArgType {
-    /// Any scalar value (integer)
-    Scalar,
-    /// Pointer to map
-    PtrToMap,
-    /// Pointer to map key (read-only)
-    PtrToMapKey,
-    /// Pointer to map value
-    PtrToMapValue,
-    /// Pointer to stack memory
-    PtrToStack,
-    /// Pointer to memory (generic, with size)
-    PtrToMem,
-    /// Pointer to memory or null
-    PtrToMemOrNull,
-    /// Size of memory buffer (paired with PtrToMem)
-    MemSize,
-    /// Pointer to context
-    PtrToCtx,
-    /// Any pointer type
-    AnyPtr,
-    /// Constant value (flags, etc.)
-    Const,
-    /// Ring buffer pointer
-    PtrToRingbuf,
-    /// Reserved ring buffer entry
-    PtrToRingbufSample,
-}
+use super::error::{VerifyError, VerifyResult};
+use super::state::{RegState, RegType, StackSlot, VerifierState};
+use crate::bytecode::registers::Register;
+pub use crate::helpers::{ArgType, HelperId, ReturnType};
 
 impl ArgType {
     /// Check if a register type is compatible with this argument type.
@@ -284,23 +63,11 @@ impl ArgType {
                 // Reserved sample pointer (returned by ringbuf_reserve)
                 matches!(reg_type, RegType::PtrToMapValue)
             }
+            Self::PtrToFunc => matches!(reg_type, RegType::PtrToFunc),
         }
     }
 }
 
-/// Return type for helper functions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ReturnType {
-    /// Returns an integer/scalar
-    Integer,
-    /// Returns pointer to map value (may be NULL)
-    PtrToMapValueOrNull,
-    /// Returns pointer to allocated memory (may be NULL)
-    PtrToAllocMemOrNull,
-    /// Returns void (always 0)
-    Void,
-}
-
 impl ReturnType {
     /// Convert return type to register state.
     pub fn to_reg_state(&self) -> RegState {
@@ -345,135 +112,8 @@ impl HelperSignature {
 
 /// Get the signature for a helper function.
 pub fn get_helper_signature(id: HelperId) -> HelperSignature {
-    match id {
-        // Core helpers
-        HelperId::KtimeGetNs => HelperSignature::new(id, &[], ReturnType::Integer),
-
-        HelperId::TracePrintk => HelperSignature::new(
-            id,
-            &[ArgType::PtrToMem, ArgType::MemSize],
-            ReturnType::Integer,
-        ),
-
-        HelperId::GetPrandomU32 => HelperSignature::new(id, &[], ReturnType::Integer),
-
-        HelperId::GetSmpProcessorId => HelperSignature::new(id, &[], ReturnType::Integer),
-
-        // Map helpers
-        HelperId::MapLookupElem => HelperSignature::new(
-            id,
-            &[ArgType::Scalar, ArgType::PtrToMapKey],
-            ReturnType::PtrToMapValueOrNull,
-        ),
-
-        HelperId::MapUpdateElem => HelperSignature::new(
-            id,
-            &[
-                ArgType::Scalar,
-                ArgType::PtrToMapKey,
-                ArgType::PtrToMapValue,
-                ArgType::Const,
-            ],
-            ReturnType::Integer,
-        ),
-
-        HelperId::MapDeleteElem => HelperSignature::new(
-            id,
-            &[ArgType::Scalar, ArgType::PtrToMapKey],
-            ReturnType::Integer,
-        ),
-
-        // Memory helpers
-        HelperId::ProbeRead => HelperSignature::new(
-            id,
-            &[ArgType::PtrToStack, ArgType::MemSize, ArgType::AnyPtr],
-            ReturnType::Integer,
-        ),
-
-        // Process helpers
-        HelperId::GetCurrentPidTgid => HelperSignature::new(id, &[], ReturnType::Integer),
-
-        HelperId::GetCurrentUidGid => HelperSignature::new(id, &[], ReturnType::Integer),
-
-        HelperId::GetCurrentComm => HelperSignature::new(
-            id,
-            &[ArgType::PtrToStack, ArgType::MemSize],
-            ReturnType::Integer,
-        ),
-
-        // Ring buffer helpers
-        HelperId::RingbufReserve => HelperSignature::new(
-            id,
-            &[ArgType::PtrToRingbuf, ArgType::Scalar, ArgType::Const],
-            ReturnType::PtrToAllocMemOrNull,
-        ),
-
-        HelperId::RingbufSubmit => HelperSignature::new(
-            id,
-            &[ArgType::PtrToRingbufSample, ArgType::Const],
-            ReturnType::Void,
-        ),
-
-        HelperId::RingbufDiscard => HelperSignature::new(
-            id,
-            &[ArgType::PtrToRingbufSample, ArgType::Const],
-            ReturnType::Void,
-        ),
-
-        HelperId::RingbufOutput => HelperSignature::new(
-            id,
-            &[
-                ArgType::Scalar,
-                ArgType::PtrToMem,
-                ArgType::MemSize,
-                ArgType::Const,
-            ],
-            ReturnType::Integer,
-        ),
-
-        // Robotics helpers
-        HelperId::MotorEmergencyStop => {
-            HelperSignature::new(id, &[ArgType::Scalar], ReturnType::Integer)
-        }
-
-        HelperId::TimeseriesPush => HelperSignature::new(
-            id,
-            &[
-                ArgType::Scalar,
-                ArgType::PtrToMapKey,
-                ArgType::PtrToMapValue,
-            ],
-            ReturnType::Integer,
-        ),
-
-        HelperId::SensorLastTimestamp => {
-            HelperSignature::new(id, &[ArgType::Scalar], ReturnType::Integer)
-        }
-
-        HelperId::GpioSet => {
-            HelperSignature::new(id, &[ArgType::Scalar, ArgType::Scalar], ReturnType::Integer)
-        }
-
-        HelperId::GpioGet => HelperSignature::new(id, &[ArgType::Scalar], ReturnType::Integer),
-
-        HelperId::PwmWrite => HelperSignature::new(
-            id,
-            &[ArgType::Scalar, ArgType::Scalar, ArgType::Scalar],
-            ReturnType::Integer,
-        ),
-
-        HelperId::IioRead => HelperSignature::new(
-            id,
-            &[ArgType::Scalar, ArgType::PtrToStack, ArgType::MemSize],
-            ReturnType::Integer,
-        ),
-
-        HelperId::CanSend => HelperSignature::new(
-            id,
-            &[ArgType::Scalar, ArgType::PtrToMem, ArgType::MemSize],
-            ReturnType::Integer,
-        ),
-    }
+    let def = id.def();
+    HelperSignature::new(id, def.args, def.ret)
 }
 
 /// Result of helper validation.
@@ -500,6 +140,40 @@ pub enum HelperValidation {
     },
 }
 
+impl HelperValidation {
+    /// Convert into a verifier result for the call at `insn_idx`.
+    pub fn into_result(self, insn_idx: usize) -> VerifyResult<HelperSignature> {
+        match self {
+            Self::Valid(sig) => Ok(sig),
+            Self::UnknownHelper(helper_id) => Err(VerifyError::InvalidHelper {
+                insn_idx,
+                helper_id,
+            }),
+            Self::NotAvailable(helper) => Err(VerifyError::HelperNotAvailable {
+                insn_idx,
+                helper_name: helper.name(),
+            }),
+            Self::WrongArgCount {
+                helper,
+                expected,
+                got,
+            } => Err(VerifyError::HelperArgCount {
+                insn_idx,
+                helper_name: helper.name(),
+                expected,
+                got,
+            }),
+            Self::ArgTypeMismatch {
+                helper, arg_idx, ..
+            } => Err(VerifyError::HelperArgType {
+                insn_idx,
+                helper_name: helper.name(),
+                arg_idx,
+            }),
+        }
+    }
+}
+
 /// Validate a helper call.
 ///
 /// # Arguments
@@ -516,8 +190,9 @@ pub fn validate_helper_call(helper_id: i32, arg_types: &[RegType; 5]) -> HelperV
         return HelperValidation::UnknownHelper(helper_id);
     };
 
-    // Check profile availability
-    if !id.is_available() {
+    // Check profile availability, and that this kernel implements it
+    let def = id.def();
+    if !def.is_available() || def.func.is_none() {
         return HelperValidation::NotAvailable(id);
     }
 
@@ -540,6 +215,50 @@ pub fn validate_helper_call(helper_id: i32, arg_types: &[RegType; 5]) -> HelperV
     HelperValidation::Valid(sig)
 }
 
+/// Check that each stack buffer passed to the helper `sig` lies in the frame.
+///
+/// A pointer argument followed by a [`ArgType::MemSize`] is a buffer of
+/// that many bytes. For a buffer on the stack the size must be bounded; the
+/// helper may touch every byte up to the bound, so they all count towards
+/// the stack depth.
+pub fn check_stack_args(
+    sig: &HelperSignature,
+    state: &mut VerifierState,
+    insn_idx: usize,
+) -> VerifyResult<()> {
+    const ARGS: [Register; 5] = [
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+    ];
+
+    for (idx, pair) in sig.args.windows(2).enumerate() {
+        let ptr = state.reg(ARGS[idx]);
+        if pair[1] != ArgType::MemSize || ptr.reg_type != RegType::PtrToStack {
+            continue;
+        }
+        let offset = ptr.ptr_offset;
+        let size = state
+            .reg(ARGS[idx + 1])
+            .scalar_value
+            .unwrap_or_default()
+            .max;
+        if !state.stack.is_valid_range(offset, size) {
+            return Err(VerifyError::OutOfBoundsAccess {
+                insn_idx,
+                offset,
+                size: size as usize,
+            });
+        }
+        for byte in offset..offset + size as i64 {
+            state.stack.set(byte, StackSlot::Scalar);
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -563,7 +282,7 @@ mod tests {
     fn helper_signature_map_lookup() {
         let sig = get_helper_signature(HelperId::MapLookupElem);
         assert_eq!(sig.args.len(), 2);
-        assert_eq!(sig.args[0], ArgType::Scalar);
+        assert_eq!(sig.args[0], ArgType::PtrToMap);
         assert_eq!(sig.args[1], ArgType::PtrToMapKey);
         assert_eq!(sig.ret, ReturnType::PtrToMapValueOrNull);
     }
@@ -578,7 +297,7 @@ mod tests {
     #[test]
     fn validate_map_lookup_valid() {
         let mut args = [RegType::NotInit; 5];
-        args[0] = RegType::Scalar; // R1 = map ID
+        args[0] = RegType::ConstPtrToMap; // R1 = map
         args[1] = RegType::PtrToStack; // R2 = key on stack
 
         let result = validate_helper_call(3, &args);
@@ -588,7 +307,7 @@ mod tests {
     #[test]
     fn validate_map_lookup_invalid_arg() {
         let mut args = [RegType::NotInit; 5];
-        args[0] = RegType::PtrToStack; // Wrong! Should be map ID (Scalar)
+        args[0] = RegType::PtrToStack; // Wrong! Should be a loaded map
         args[1] = RegType::PtrToStack;
 
         let result = validate_helper_call(3, &args);
@@ -596,6 +315,33 @@ mod tests {
             result,
             HelperValidation::ArgTypeMismatch { arg_idx: 0, .. }
         ));
+
+        // A map ID in a register could name a map the program doesn't hold
+        args[0] = RegType::Scalar;
+        let result = validate_helper_call(3, &args);
+        assert!(matches!(
+            result,
+            HelperValidation::ArgTypeMismatch { arg_idx: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn validate_tail_call() {
+        let mut args = [RegType::NotInit; 5];
+        args[0] = RegType::PtrToCtx; // R1 = context
+        args[1] = RegType::ConstPtrToMap; // R2 = program array
+        args[2] = RegType::Scalar; // R3 = index
+
+        let result = validate_helper_call(HelperId::TailCall as i32, &args);
+        assert!(matches!(result, HelperValidation::Valid(_)));
+
+        // The target runs with the caller's context
+        args[0] = RegType::PtrToStack;
+        let result = validate_helper_call(HelperId::TailCall as i32, &args);
+        assert!(matches!(
+            result,
+            HelperValidation::ArgTypeMismatch { arg_idx: 0, .. }
+        ));
     }
 
     #[test]
@@ -619,6 +365,10 @@ mod tests {
         // PtrToMemOrNull accepts null
         assert!(ArgType::PtrToMemOrNull.is_compatible(RegType::NullPtr));
         assert!(ArgType::PtrToMemOrNull.is_compatible(RegType::PtrToStack));
+
+        // Callbacks must be loaded function addresses
+        assert!(ArgType::PtrToFunc.is_compatible(RegType::PtrToFunc));
+        assert!(!ArgType::PtrToFunc.is_compatible(RegType::Scalar));
     }
 
     #[test]
@@ -34,6 +34,10 @@
 //!
 //! - **Cloud**: Relaxed constraints, JIT hints, soft WCET
 //! - **Embedded**: Strict constraints, hard WCET, interrupt safety
+//!
+//! [`verify_program`] selects the verifier implementation for the active
+//! profile: the path-sensitive [`Verifier`] on cloud, and the memory-bounded
+//! [`StreamingVerifier`] on embedded.
 
 mod cfg;
 mod core;
@@ -41,11 +45,35 @@ mod error;
 pub mod helpers;
 mod state;
 mod streaming;
+pub(crate) mod subprog;
+
+use crate::bytecode::insn::BpfInsn;
+use crate::bytecode::program::{BpfProgType, BpfProgram};
+use crate::profile::ActiveProfile;
 
 pub use core::Verifier;
 
 pub use cfg::ControlFlowGraph;
-pub use error::VerifyError;
+pub use error::{VerifyError, VerifyResult};
 pub use helpers::{ArgType, HelperId, HelperSignature, get_helper_signature, validate_helper_call};
 pub use state::{RegState, RegType, StackSlot, VerifierState};
 pub use streaming::StreamingVerifier;
+
+/// Verify a program with the verifier appropriate for the active profile.
+///
+/// On success the returned program carries the stack depth computed by
+/// the verifier.
+pub fn verify_program(
+    prog_type: BpfProgType,
+    insns: &[BpfInsn],
+) -> VerifyResult<BpfProgram<ActiveProfile>> {
+    #[cfg(feature = "cloud-profile")]
+    {
+        Verifier::<ActiveProfile>::verify(prog_type, insns)
+    }
+
+    #[cfg(all(feature = "embedded-profile", not(feature = "cloud-profile")))]
+    {
+        StreamingVerifier::<ActiveProfile>::verify(prog_type, insns)
+    }
+}
@@ -9,6 +9,8 @@ extern crate alloc;
 use alloc::vec::Vec;
 use core::fmt;
 
+use crate::bytecode::insn::BpfInsn;
+use crate::bytecode::opcode::{AluOp, SourceType};
 use crate::bytecode::registers::Register;
 
 /// Type of value held in a register.
@@ -51,6 +53,10 @@ pub enum RegType {
     /// Frame pointer (R10, read-only)
     PtrToFp,
 
+    /// Address of a BPF function, for helpers that call back into the
+    /// program
+    PtrToFunc,
+
     /// Null pointer
     NullPtr,
 }
@@ -94,7 +100,8 @@ pub struct RegState {
     /// For scalar values: known value if constant, None otherwise
     pub scalar_value: Option<ScalarValue>,
 
-    /// For pointer types: offset from base
+    /// For pointer types: offset from base. For function addresses, the
+    /// function's first instruction.
     pub ptr_offset: i64,
 
     /// For map pointers: map ID
@@ -142,6 +149,39 @@ impl RegState {
         }
     }
 
+    /// Create the state of a loaded address of the function starting at
+    /// instruction `start`.
+    pub fn func_ptr(start: usize) -> Self {
+        Self {
+            reg_type: RegType::PtrToFunc,
+            scalar_value: None,
+            ptr_offset: start as i64,
+            map_id: None,
+        }
+    }
+
+    /// Create the state of a map loaded by a `BPF_PSEUDO_MAP_FD` wide
+    /// load.
+    pub const fn map_ptr(map_id: u32) -> Self {
+        Self {
+            reg_type: RegType::ConstPtrToMap,
+            scalar_value: None,
+            ptr_offset: 0,
+            map_id: Some(map_id),
+        }
+    }
+
+    /// Create the state of an address in a map value, as loaded for
+    /// global data.
+    pub const fn map_value_ptr() -> Self {
+        Self {
+            reg_type: RegType::PtrToMapValue,
+            scalar_value: None,
+            ptr_offset: 0,
+            map_id: None,
+        }
+    }
+
     /// Create a context pointer state (R1 at entry).
     pub const fn ctx_ptr() -> Self {
         Self {
@@ -157,6 +197,20 @@ impl RegState {
     pub fn is_init(&self) -> bool {
         !matches!(self.reg_type, RegType::NotInit)
     }
+
+    /// Check if this state, already verified, covers `other`: every check
+    /// passed with this state passes with `other` too.
+    pub fn covers(&self, other: &RegState) -> bool {
+        if self.reg_type != other.reg_type {
+            return false;
+        }
+        if self.reg_type == RegType::Scalar {
+            let range = self.scalar_value.unwrap_or_default();
+            let other = other.scalar_value.unwrap_or_default();
+            return range.min <= other.min && other.max <= range.max;
+        }
+        self.ptr_offset == other.ptr_offset && self.map_id == other.map_id
+    }
 }
 
 impl Default for RegState {
@@ -339,6 +393,13 @@ impl StackState {
         true
     }
 
+    /// Count a read of `size` bytes at `offset` towards the maximum depth,
+    /// as a write there would.
+    pub fn mark_read(&mut self, offset: i64, size: usize) {
+        let depth = (size as i64 - 1 - offset) as usize;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
     /// Get the maximum stack depth used.
     pub fn max_depth(&self) -> usize {
         self.max_depth
@@ -359,6 +420,14 @@ impl StackState {
 
         true
     }
+
+    /// Check if the `size` bytes starting at `offset` lie in the frame.
+    pub fn is_valid_range(&self, offset: i64, size: u64) -> bool {
+        let Ok(size) = i64::try_from(size) else {
+            return false;
+        };
+        offset >= -(self.slots.len() as i64) && offset.saturating_add(size) <= 0
+    }
 }
 
 impl Default for StackState {
@@ -376,15 +445,35 @@ impl fmt::Debug for StackState {
     }
 }
 
+/// Caller state saved while a BPF function runs.
+#[derive(Clone)]
+pub struct CallFrame {
+    /// Instruction the callee returns to
+    pub return_idx: usize,
+
+    /// Caller's R6-R9
+    pub callee_saved: [RegState; 4],
+
+    /// Caller's stack
+    pub stack: StackState,
+
+    /// Whether the callee is a `bpf_loop` callback, which returns to the
+    /// helper rather than to its caller
+    pub callback: bool,
+}
+
 /// Complete verifier state at a program point.
 #[derive(Clone)]
 pub struct VerifierState {
     /// Register states
     pub regs: [RegState; Register::COUNT],
 
-    /// Stack state
+    /// Stack state of the current frame
     pub stack: StackState,
 
+    /// Callers of the current frame, outermost first
+    pub frames: Vec<CallFrame>,
+
     /// Current instruction pointer
     pub insn_idx: usize,
 
@@ -406,6 +495,7 @@ impl VerifierState {
         Self {
             regs,
             stack: StackState::new(stack_size),
+            frames: Vec::new(),
             insn_idx: 0,
             insn_processed: 0,
         }
@@ -431,6 +521,140 @@ impl VerifierState {
         self.regs[reg as usize] = RegState::scalar(value);
     }
 
+    /// Set `dst` to the result of the ALU instruction `insn`.
+    ///
+    /// A 64-bit move copies its source, and adding or subtracting a
+    /// constant moves a stack pointer within the frame. Any other result is
+    /// a scalar, known only when a constant is moved.
+    pub fn set_alu_result(&mut self, insn: &BpfInsn, dst: Register, alu_op: AluOp) {
+        let src = match (insn.source_type(), insn.src()) {
+            (SourceType::Reg, Some(src)) => self.reg(src).clone(),
+            _ => RegState::scalar(Some(ScalarValue::constant(insn.imm as i64 as u64))),
+        };
+        let constant = match src.reg_type {
+            RegType::Scalar => src.scalar_value.and_then(|value| value.value),
+            _ => None,
+        };
+        let dst_state = self.reg(dst);
+
+        let result = match alu_op {
+            // A copy of R10 is a stack pointer like any other
+            AluOp::Mov if insn.is_alu64() && src.reg_type == RegType::PtrToFp => {
+                RegState::stack_ptr(src.ptr_offset)
+            }
+            AluOp::Mov if insn.is_alu64() => src,
+            AluOp::Mov => {
+                RegState::scalar(Some(constant.map_or(ScalarValue::unknown(), |value| {
+                    ScalarValue::constant(value as u32 as u64)
+                })))
+            }
+            AluOp::Add | AluOp::Sub
+                if insn.is_alu64() && dst_state.reg_type == RegType::PtrToStack =>
+            {
+                let offset = constant.map(|value| match alu_op {
+                    AluOp::Add => dst_state.ptr_offset.wrapping_add(value as i64),
+                    _ => dst_state.ptr_offset.wrapping_sub(value as i64),
+                });
+                match offset {
+                    Some(offset) if self.stack.is_valid_range(offset, 0) => {
+                        RegState::stack_ptr(offset)
+                    }
+                    _ => RegState::scalar(Some(ScalarValue::unknown())),
+                }
+            }
+            _ => RegState::scalar(Some(ScalarValue::unknown())),
+        };
+        *self.reg_mut(dst) = result;
+    }
+
+    /// Enter a BPF function returning to `return_idx`, with a frame of
+    /// `stack_size` bytes.
+    ///
+    /// The callee starts with the caller's R1-R5 as arguments; R0 and
+    /// R6-R9 are uninitialized until it sets them. Stack pointers among the
+    /// arguments become scalars, as the callee's accesses are only checked
+    /// against its own frame.
+    pub fn push_frame(&mut self, return_idx: usize, stack_size: usize) {
+        let saved = [Register::R6, Register::R7, Register::R8, Register::R9]
+            .map(|reg| core::mem::take(self.reg_mut(reg)));
+        *self.reg_mut(Register::R0) = RegState::uninit();
+        for reg in [
+            Register::R1,
+            Register::R2,
+            Register::R3,
+            Register::R4,
+            Register::R5,
+        ] {
+            if self.reg(reg).reg_type == RegType::PtrToStack {
+                self.set_scalar(reg, Some(ScalarValue::unknown()));
+            }
+        }
+
+        let stack = core::mem::replace(&mut self.stack, StackState::new(stack_size));
+        self.frames.push(CallFrame {
+            return_idx,
+            callee_saved: saved,
+            stack,
+            callback: false,
+        });
+    }
+
+    /// Enter a `bpf_loop` callback for the helper call before `return_idx`,
+    /// with a frame of `stack_size` bytes.
+    ///
+    /// The callback gets the iteration number in R1 and the helper's
+    /// context argument `ctx` in R2, a scalar if it points to the stack;
+    /// R3-R5 are uninitialized.
+    pub fn push_callback_frame(&mut self, return_idx: usize, stack_size: usize, ctx: RegState) {
+        self.push_frame(return_idx, stack_size);
+        if let Some(frame) = self.frames.last_mut() {
+            frame.callback = true;
+        }
+        self.set_scalar(Register::R1, Some(ScalarValue::unknown()));
+        *self.reg_mut(Register::R2) = match ctx.reg_type {
+            RegType::PtrToStack => RegState::scalar(Some(ScalarValue::unknown())),
+            _ => ctx,
+        };
+        for reg in [Register::R3, Register::R4, Register::R5] {
+            *self.reg_mut(reg) = RegState::uninit();
+        }
+    }
+
+    /// Check if the current frame is a `bpf_loop` callback.
+    pub fn in_callback(&self) -> bool {
+        self.frames.last().is_some_and(|frame| frame.callback)
+    }
+
+    /// Return from a BPF function, restoring its caller's frame.
+    ///
+    /// R0 keeps the return value and R1-R5 are clobbered. After a
+    /// callback R0 holds the helper's result instead, the number of
+    /// iterations run. Returns the instruction to continue at, or `None` in
+    /// the main function.
+    pub fn pop_frame(&mut self) -> Option<usize> {
+        let frame = self.frames.pop()?;
+        if frame.callback {
+            self.set_scalar(Register::R0, Some(ScalarValue::unknown()));
+        }
+        for (reg, saved) in [Register::R6, Register::R7, Register::R8, Register::R9]
+            .into_iter()
+            .zip(frame.callee_saved)
+        {
+            *self.reg_mut(reg) = saved;
+        }
+        for reg in [
+            Register::R1,
+            Register::R2,
+            Register::R3,
+            Register::R4,
+            Register::R5,
+        ] {
+            *self.reg_mut(reg) = RegState::uninit();
+        }
+        self.stack = frame.stack;
+        Some(frame.return_idx)
+    }
+
     /// Advance to next instruction.
     pub fn advance(&mut self) {
         self.insn_idx += 1;
@@ -450,6 +674,7 @@ impl fmt::Debug for VerifierState {
             .field("insn_idx", &self.insn_idx)
             .field("insn_processed", &self.insn_processed)
             .field("stack_depth", &self.stack.max_depth())
+            .field("frames", &self.frames.len())
             .finish()
     }
 }
@@ -515,4 +740,43 @@ mod tests {
         assert!(!state.is_reg_init(Register::R0));
         assert!(!state.is_reg_init(Register::R2));
     }
+
+    #[test]
+    fn call_frames_save_callee_saved_registers() {
+        let mut state = VerifierState::new_entry(512);
+        state.set_scalar(Register::R6, None);
+        state.stack.set(-8, StackSlot::Scalar);
+
+        state.push_frame(7, 64);
+        assert!(state.is_reg_init(Register::R1));
+        assert!(!state.is_reg_init(Register::R6));
+        assert_eq!(state.stack.max_depth(), 0);
+        assert!(!state.stack.is_valid_access(-72, 8));
+
+        state.set_scalar(Register::R0, None);
+        assert_eq!(state.pop_frame(), Some(7));
+        assert!(state.is_reg_init(Register::R0));
+        assert!(!state.is_reg_init(Register::R1));
+        assert_eq!(state.reg(Register::R6).reg_type, RegType::Scalar);
+        assert_eq!(state.stack.max_depth(), 8);
+        assert_eq!(state.pop_frame(), None);
+    }
+
+    #[test]
+    fn callback_frames_return_the_helper_result() {
+        let mut state = VerifierState::new_entry(512);
+        state.set_scalar(Register::R6, None);
+
+        state.push_callback_frame(5, 64, RegState::scalar(None));
+        assert!(state.in_callback());
+        assert_eq!(state.reg(Register::R1).reg_type, RegType::Scalar);
+        assert_eq!(state.reg(Register::R2).reg_type, RegType::Scalar);
+        assert!(!state.is_reg_init(Register::R3));
+        assert!(!state.is_reg_init(Register::R6));
+
+        assert_eq!(state.pop_frame(), Some(5));
+        assert!(!state.in_callback());
+        assert_eq!(state.reg(Register::R0).reg_type, RegType::Scalar);
+        assert_eq!(state.reg(Register::R6).reg_type, RegType::Scalar);
+    }
 }
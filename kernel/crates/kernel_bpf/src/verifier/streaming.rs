@@ -50,11 +50,14 @@ use alloc::vec::Vec;
 use core::marker::PhantomData;
 
 use super::error::{VerifyError, VerifyResult};
+use super::helpers::{HelperId, check_stack_args, validate_helper_call};
 use super::state::{RegState, RegType, ScalarValue, StackSlot, VerifierState};
+use super::subprog::check_subprogs;
 use crate::bytecode::insn::BpfInsn;
 use crate::bytecode::opcode::{AluOp, OpcodeClass};
 use crate::bytecode::program::{BpfProgType, BpfProgram};
 use crate::bytecode::registers::Register;
+use crate::bytecode::subprog::{self, MAX_FRAME_SIZE, Subprog};
 use crate::profile::{ActiveProfile, PhysicalProfile};
 
 /// Maximum worklist depth for pending blocks.
@@ -108,6 +111,12 @@ pub struct StreamingVerifier<P: PhysicalProfile = ActiveProfile> {
     /// Maximum stack depth observed
     max_stack_depth: usize,
 
+    /// Functions of the program
+    subprogs: Vec<Subprog>,
+
+    /// Deepest stack each function's frames use
+    frame_depths: Vec<usize>,
+
     /// Profile marker
     _profile: PhantomData<P>,
 }
@@ -143,6 +152,8 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
             block_leaders: Vec::new(),
             loop_counts: Vec::new(),
             max_stack_depth: 0,
+            subprogs: Vec::new(),
+            frame_depths: Vec::new(),
             _profile: PhantomData,
         }
     }
@@ -157,28 +168,33 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
         // Phase 1: Basic structural checks
         verifier.check_basic()?;
 
-        // Phase 2: Compute basic block boundaries
+        // Phase 2: Compute basic block boundaries and check the BPF functions
         verifier.compute_block_leaders();
+        let call_stack = check_subprogs::<P>(insns)?;
+        verifier.subprogs = subprog::split(insns);
+        verifier.frame_depths = alloc::vec![0; verifier.subprogs.len()];
 
         // Phase 3: Streaming verification
-        let stack_size = verifier.verify_streaming()?;
+        let stack_size = verifier.verify_streaming()?.max(call_stack);
 
         // Phase 4: Profile-specific constraints
         verifier.verify_profile_constraints()?;
 
         // Build the verified program
-        BpfProgram::new(prog_type, insns.to_vec(), stack_size).map_err(|e| match e {
-            crate::bytecode::program::ProgramError::StackSizeExceeded { required, limit } => {
-                VerifyError::StackExceeded {
-                    used: required,
-                    limit,
+        BpfProgram::new(prog_type, insns.to_vec(), stack_size)
+            .map(|program| program.with_stack_depths(&verifier.frame_depths))
+            .map_err(|e| match e {
+                crate::bytecode::program::ProgramError::StackSizeExceeded { required, limit } => {
+                    VerifyError::StackExceeded {
+                        used: required,
+                        limit,
+                    }
                 }
-            }
-            crate::bytecode::program::ProgramError::InsnCountExceeded { count, limit } => {
-                VerifyError::InsnCountExceeded { count, limit }
-            }
-            _ => VerifyError::EmptyProgram,
-        })
+                crate::bytecode::program::ProgramError::InsnCountExceeded { count, limit } => {
+                    VerifyError::InsnCountExceeded { count, limit }
+                }
+                _ => VerifyError::EmptyProgram,
+            })
     }
 
     /// Perform basic structural checks.
@@ -271,7 +287,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
     /// Main streaming verification loop.
     fn verify_streaming(&mut self) -> VerifyResult<usize> {
         // Start with initial state at instruction 0
-        let initial_state = VerifierState::new_entry(P::MAX_STACK_SIZE);
+        let initial_state = VerifierState::new_entry(self.frame_size());
         self.worklist.push(WorklistEntry {
             start_idx: 0,
             state: initial_state,
@@ -290,6 +306,9 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
     fn verify_block(&mut self, start_idx: usize) -> VerifyResult<()> {
         let mut state = self.current_state.take().ok_or(VerifyError::EmptyProgram)?;
         state.insn_idx = start_idx;
+        if start_idx < self.insns.len() {
+            self.note_depth(start_idx, &state);
+        }
 
         // Check if we've already processed this block with compatible state
         if let Some(merged) = self.find_merge_point(start_idx) {
@@ -317,6 +336,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
             if state.stack.max_depth() > self.max_stack_depth {
                 self.max_stack_depth = state.stack.max_depth();
             }
+            self.note_depth(idx, &state);
 
             let insn = &self.insns[idx].clone();
 
@@ -365,13 +385,40 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
                     self.add_to_worklist(fallthrough, state)?;
                     return Ok(());
                 }
-                InsnResult::Exit => {
-                    return Ok(());
+                InsnResult::Call(target) => {
+                    // Functions are walked inline, once per call
+                    state.insn_idx = target;
+                    state.insn_processed += 1;
                 }
+                InsnResult::Exit => match state.pop_frame() {
+                    Some(return_idx) => {
+                        state.insn_idx = return_idx;
+                        state.insn_processed += 1;
+                    }
+                    None => return Ok(()),
+                },
             }
         }
     }
 
+    /// Bytes of stack each frame may use.
+    ///
+    /// Frames of a program with BPF functions are capped, so that its
+    /// deepest call chain stays small on the kernel stack.
+    fn frame_size(&self) -> usize {
+        if self.subprogs.len() > 1 {
+            MAX_FRAME_SIZE.min(P::MAX_STACK_SIZE)
+        } else {
+            P::MAX_STACK_SIZE
+        }
+    }
+
+    /// Record the stack used so far by the frame running instruction `idx`.
+    fn note_depth(&mut self, idx: usize, state: &VerifierState) {
+        let func = self.subprogs.partition_point(|s| s.start <= idx) - 1;
+        self.frame_depths[func] = self.frame_depths[func].max(state.stack.max_depth());
+    }
+
     /// Check if an instruction index is a basic block leader.
     fn is_block_leader(&self, idx: usize) -> bool {
         self.block_leaders.binary_search(&idx).is_ok()
@@ -474,13 +521,21 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
 
     /// Check if two states are compatible (can be merged without re-verification).
     fn states_compatible(&self, s1: &VerifierState, s2: &VerifierState) -> bool {
-        // States are compatible if all register types match
+        // The state already verified must cover every register
         for i in 0..Register::COUNT {
-            if s1.regs[i].reg_type != s2.regs[i].reg_type {
+            if !s2.regs[i].covers(&s1.regs[i]) {
                 return false;
             }
         }
-        true
+        s1.frames.len() == s2.frames.len()
+            && s1.frames.iter().zip(&s2.frames).all(|(f1, f2)| {
+                f1.return_idx == f2.return_idx
+                    && f1
+                        .callee_saved
+                        .iter()
+                        .zip(&f2.callee_saved)
+                        .all(|(r1, r2)| r2.covers(r1))
+            })
     }
 
     /// Conservatively merge two states.
@@ -501,6 +556,11 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
                     *target_reg = incoming_reg.clone();
                 }
                 // If incoming not init but target is, keep target
+            } else if target_reg.ptr_offset != incoming_reg.ptr_offset
+                || target_reg.map_id != incoming_reg.map_id
+            {
+                // Same pointer type, different targets -> scalar (unknown)
+                *target_reg = RegState::scalar(Some(ScalarValue::unknown()));
             } else if target_reg.reg_type == RegType::Scalar {
                 // Both scalar - widen value range
                 if let (Some(tv), Some(iv)) =
@@ -529,9 +589,32 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
                     reg: Register::R0,
                 });
             }
+            // bpf_loop reads a callback's result as "stop or go on"
+            if state.in_callback() && state.reg(Register::R0).reg_type != RegType::Scalar {
+                return Err(VerifyError::InvalidCall {
+                    insn_idx: idx,
+                    reason: "callback must return a scalar",
+                });
+            }
             return Ok(InsnResult::Exit);
         }
 
+        // Call of a BPF function: enter its frame
+        if let Some(target) = subprog::call_target(idx, insn) {
+            state.push_frame(idx + 1, self.frame_size());
+            return Ok(InsnResult::Call(target));
+        }
+
+        // bpf_loop: check the call like any other, then walk the callback
+        // as a function called from here
+        if insn.is_call() && insn.imm == HelperId::Loop as i32 {
+            let callback = state.reg(Register::R2).ptr_offset as usize;
+            let ctx = state.reg(Register::R3).clone();
+            self.verify_call(insn, state, idx)?;
+            state.push_callback_frame(idx + 1, self.frame_size(), ctx);
+            return Ok(InsnResult::Call(callback));
+        }
+
         // Call instruction
         if insn.is_call() {
             self.verify_call(insn, state, idx)?;
@@ -549,15 +632,16 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
             return self.verify_jump(insn, state, idx);
         }
 
-        // Memory instructions
-        if insn.is_memory() {
-            self.verify_memory(insn, state, idx)?;
+        // Wide instruction (64-bit immediate load), before the other
+        // instructions of its class
+        if insn.is_wide() {
+            self.verify_wide_load(insn, state, idx)?;
             return Ok(InsnResult::Continue);
         }
 
-        // Wide instruction (64-bit immediate load)
-        if insn.is_wide() {
-            self.verify_wide_load(insn, state, idx)?;
+        // Memory instructions
+        if insn.is_memory() {
+            self.verify_memory(insn, state, idx)?;
             return Ok(InsnResult::Continue);
         }
 
@@ -628,8 +712,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
             });
         }
 
-        // Update destination register to scalar
-        state.set_scalar(dst, Some(ScalarValue::unknown()));
+        state.set_alu_result(insn, dst, alu_op);
 
         Ok(())
     }
@@ -647,7 +730,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
         })?;
 
         let target = (idx as i64) + 1 + (insn.offset as i64);
-        if target < 0 {
+        if target < 0 || target as usize >= self.insns.len() {
             return Err(VerifyError::InvalidJump {
                 insn_idx: idx,
                 target: target as i32,
@@ -701,12 +784,18 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
     ) -> VerifyResult<()> {
         let helper_id = insn.imm;
 
-        if helper_id < 0 {
-            return Err(VerifyError::InvalidHelper {
-                insn_idx: idx,
-                helper_id,
-            });
-        }
+        // Collect argument register types
+        let arg_types = [
+            state.reg(Register::R1).reg_type,
+            state.reg(Register::R2).reg_type,
+            state.reg(Register::R3).reg_type,
+            state.reg(Register::R4).reg_type,
+            state.reg(Register::R5).reg_type,
+        ];
+
+        // Validate helper call using the registry
+        let sig = validate_helper_call(helper_id, &arg_types).into_result(idx)?;
+        check_stack_args(&sig, state, idx)?;
 
         // Clobber caller-saved registers
         for reg in [
@@ -721,7 +810,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
         }
 
         // R0 contains return value
-        state.set_scalar(Register::R0, Some(ScalarValue::unknown()));
+        *state.reg_mut(Register::R0) = sig.ret.to_reg_state();
 
         Ok(())
     }
@@ -784,6 +873,7 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
                             size: size.size_bytes(),
                         });
                     }
+                    state.stack.mark_read(offset, size.size_bytes());
                 }
 
                 state.set_scalar(dst, Some(ScalarValue::unknown()));
@@ -858,6 +948,23 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
                         reason: "cannot write to this pointer type",
                     });
                 }
+
+                if dst_state.reg_type == RegType::PtrToStack
+                    || dst_state.reg_type == RegType::PtrToFp
+                {
+                    let offset = dst_state.ptr_offset + insn.offset as i64;
+                    if !state.stack.is_valid_access(offset, size.size_bytes()) {
+                        return Err(VerifyError::OutOfBoundsAccess {
+                            insn_idx: idx,
+                            offset,
+                            size: size.size_bytes(),
+                        });
+                    }
+
+                    for i in 0..size.size_bytes() {
+                        let _ = state.stack.set(offset - i as i64, StackSlot::Scalar);
+                    }
+                }
             }
 
             _ => {}
@@ -882,6 +989,21 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
             return Err(VerifyError::WriteToReadOnly { insn_idx: idx });
         }
 
+        if insn.is_map_load() {
+            *state.reg_mut(dst) = RegState::map_ptr(insn.imm as u32);
+            return Ok(());
+        }
+
+        if insn.is_map_value_load() {
+            *state.reg_mut(dst) = RegState::map_value_ptr();
+            return Ok(());
+        }
+
+        if let Some(start) = subprog::func_target(idx, insn) {
+            *state.reg_mut(dst) = RegState::func_ptr(start);
+            return Ok(());
+        }
+
         state.set_scalar(dst, Some(ScalarValue::unknown()));
 
         Ok(())
@@ -904,11 +1026,20 @@ impl<P: PhysicalProfile> StreamingVerifier<P> {
 
     #[cfg(feature = "embedded-profile")]
     fn verify_embedded_constraints(&self) -> VerifyResult<()> {
-        // In streaming mode, loops are already bounded by MAX_LOOP_ITERATIONS
+        // In streaming mode, loops are already bounded by MAX_LOOP_ITERATIONS.
+        // bpf_loop callbacks are charged per iteration of the helper.
+        let cycles = super::subprog::wcet::<P>(&self.insns);
+        if cycles > P::MAX_INSN_COUNT as u64 {
+            return Err(VerifyError::WcetExceeded {
+                cycles,
+                budget: P::MAX_INSN_COUNT as u64,
+            });
+        }
+
         // Additional checks for dynamic allocation helpers
         for (idx, insn) in self.insns.iter().enumerate() {
-            if insn.is_call() {
-                const ALLOC_HELPERS: &[i32] = &[];
+            if insn.is_call() && !insn.is_pseudo_call() {
+                const ALLOC_HELPERS: &[i32] = &[super::helpers::HelperId::RingbufReserve as i32];
 
                 if ALLOC_HELPERS.contains(&insn.imm) {
                     return Err(VerifyError::DynamicAllocationAttempted { insn_idx: idx });
@@ -936,6 +1067,7 @@ enum InsnResult {
     Continue,
     Jump(usize),
     Branch { fallthrough: usize, target: usize },
+    Call(usize),
     Exit,
 }
 
@@ -1050,4 +1182,76 @@ mod tests {
         let result = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn verify_helper_calls_against_registry() {
+        // r0 = bpf_ktime_get_ns()
+        let insns = [BpfInsn::call(1), BpfInsn::exit()];
+        let result = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(result.is_ok());
+
+        // Unknown helper
+        let insns = [BpfInsn::call(9999), BpfInsn::exit()];
+        let result = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(matches!(
+            result,
+            Err(VerifyError::InvalidHelper {
+                helper_id: 9999,
+                ..
+            })
+        ));
+
+        // bpf_map_lookup_elem with the context (R1) where the map ID belongs
+        let insns = [
+            BpfInsn::mov64_reg(2, 10),
+            BpfInsn::add64_imm(2, -8),
+            BpfInsn::call(3),
+            BpfInsn::exit(),
+        ];
+        let result = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(matches!(
+            result,
+            Err(VerifyError::HelperArgType { arg_idx: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn verify_function_call() {
+        let insns = [
+            BpfInsn::mov64_imm(6, 1),         // 0: r6 = 1
+            BpfInsn::mov64_imm(1, 2),         // 1: r1 = 2
+            BpfInsn::call_local(2),           // 2: call 5
+            BpfInsn::add64_reg(0, 6),         // 3: r0 += r6
+            BpfInsn::exit(),                  // 4
+            BpfInsn::new(0x7a, 10, 0, -8, 0), // 5: *(u64 *)(r10 - 8) = 0
+            BpfInsn::mov64_reg(0, 1),         // 6: r0 = r1
+            BpfInsn::exit(),                  // 7
+        ];
+
+        let program = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns)
+            .expect("valid program");
+        assert!(program.stack_size() >= 8);
+
+        // The callee can't read the caller's R6
+        let mut reads_r6 = insns;
+        reads_r6[6] = BpfInsn::mov64_reg(0, 6);
+        assert!(matches!(
+            StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &reads_r6),
+            Err(VerifyError::UninitializedRegister {
+                insn_idx: 6,
+                reg: Register::R6
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_recursive_call() {
+        let insns = [
+            BpfInsn::call_local(-1), // 0: call 0
+            BpfInsn::exit(),
+        ];
+
+        let result = StreamingVerifier::<ActiveProfile>::verify(BpfProgType::SocketFilter, &insns);
+        assert!(matches!(result, Err(VerifyError::InvalidCall { .. })));
+    }
 }
@@ -0,0 +1,456 @@
+//! BPF Function Checks
+//!
+//! Checks the layout and call graph of a program's functions before its
+//! paths are walked. Each function must keep its jumps to itself and end
+//! in an exit or a jump; calls must not recurse or chain more than
+//! [`MAX_CALL_FRAMES`] frames. No frame may need more than
+//! [`MAX_FRAME_SIZE`] bytes of stack, nor a chain more than the profile
+//! allows.
+//!
+//! On the embedded profile the call graph also gives the worst-case
+//! execution time, with `bpf_loop` callbacks charged once per iteration.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::error::{VerifyError, VerifyResult};
+use super::helpers::HelperId;
+use crate::bytecode::insn::BpfInsn;
+#[cfg(feature = "embedded-profile")]
+use crate::bytecode::opcode::OpcodeClass;
+use crate::bytecode::subprog::{self, MAX_CALL_FRAMES, MAX_FRAME_SIZE, Subprog};
+use crate::profile::PhysicalProfile;
+
+/// Visit state of a function during the call graph walk.
+#[derive(Clone, Copy)]
+enum Visit {
+    New,
+    OnPath,
+    /// Frames and stack of the deepest chain starting at the function
+    Done(usize, usize),
+}
+
+/// Check the functions of `insns` and their calls.
+///
+/// Returns the stack needed by the deepest call chain.
+pub fn check_subprogs<P: PhysicalProfile>(insns: &[BpfInsn]) -> VerifyResult<usize> {
+    // Calls and loaded functions must land on an instruction of their own
+    for (idx, insn) in insns.iter().enumerate() {
+        if !insn.is_pseudo_call() && !insn.is_func_load() {
+            continue;
+        }
+        let target = target(idx, insn).filter(|&t| t < insns.len());
+        let splits_wide = target.is_some_and(|t| t > 0 && insns[t - 1].is_wide());
+        if target.is_none() || splits_wide {
+            return Err(VerifyError::InvalidJump {
+                insn_idx: idx,
+                target: (idx as i64 + 1 + insn.imm as i64) as i32,
+            });
+        }
+    }
+
+    let subprogs = subprog::split(insns);
+    let mut calls = vec![Vec::new(); subprogs.len()];
+
+    // A lone main function keeps the whole profile stack
+    if subprogs.len() > 1
+        && let Some(sub) = subprogs.iter().find(|s| s.stack_depth > MAX_FRAME_SIZE)
+    {
+        return Err(VerifyError::StackExceeded {
+            used: sub.stack_depth,
+            limit: MAX_FRAME_SIZE,
+        });
+    }
+
+    for (func, sub) in subprogs.iter().enumerate() {
+        for (idx, insn) in (sub.start..sub.end).zip(&insns[sub.start..sub.end]) {
+            if let Some(target) = target(idx, insn) {
+                let callee = subprogs.partition_point(|s| s.start <= target) - 1;
+                calls[func].push((idx, callee));
+            } else if insn.is_call() {
+                if func != 0 && insn.imm == HelperId::TailCall as i32 {
+                    return Err(VerifyError::InvalidCall {
+                        insn_idx: idx,
+                        reason: "tail call from a BPF function",
+                    });
+                }
+            } else if insn.is_jump() && !insn.is_exit() {
+                let target = idx as i64 + 1 + insn.offset as i64;
+                if target < sub.start as i64 || target >= sub.end as i64 {
+                    return Err(VerifyError::InvalidJump {
+                        insn_idx: idx,
+                        target: target as i32,
+                    });
+                }
+            }
+        }
+
+        // Control can't run off the end into the next function. Only
+        // `ja` (0x05) leaves without falling through.
+        let last = &insns[sub.end - 1];
+        let wide_tail = sub.end >= sub.start + 2 && insns[sub.end - 2].is_wide();
+        let falls_through = wide_tail || !(last.is_exit() || last.opcode == 0x05);
+        if sub.end < insns.len() && falls_through {
+            return Err(VerifyError::InvalidCall {
+                insn_idx: sub.end - 1,
+                reason: "function falls through into the next one",
+            });
+        }
+    }
+
+    let mut visits = vec![Visit::New; subprogs.len()];
+    let (_, stack_size) = visit(0, 1, &subprogs, &calls, &mut visits)?;
+
+    if stack_size > P::MAX_STACK_SIZE {
+        return Err(VerifyError::StackExceeded {
+            used: stack_size,
+            limit: P::MAX_STACK_SIZE,
+        });
+    }
+
+    Ok(stack_size)
+}
+
+/// Function called or loaded by `insn` at `idx`.
+///
+/// A loaded function is called back by `bpf_loop`, so it counts as a call
+/// from the function that loads it.
+fn target(idx: usize, insn: &BpfInsn) -> Option<usize> {
+    subprog::call_target(idx, insn).or_else(|| subprog::func_target(idx, insn))
+}
+
+/// Walk the calls of function `func`, entered as frame `frame` of a chain.
+///
+/// Returns the frames and stack of the deepest chain starting at `func`.
+fn visit(
+    func: usize,
+    frame: usize,
+    subprogs: &[Subprog],
+    calls: &[Vec<(usize, usize)>],
+    visits: &mut [Visit],
+) -> VerifyResult<(usize, usize)> {
+    if let Visit::Done(frames, stack) = visits[func] {
+        return Ok((frames, stack));
+    }
+    visits[func] = Visit::OnPath;
+
+    let (mut frames, mut stack) = (0, 0);
+    for &(call_idx, callee) in &calls[func] {
+        let (callee_frames, callee_stack) = match visits[callee] {
+            Visit::OnPath => {
+                return Err(VerifyError::InvalidCall {
+                    insn_idx: call_idx,
+                    reason: "recursive call",
+                });
+            }
+            _ if frame >= MAX_CALL_FRAMES => {
+                return Err(VerifyError::InvalidCall {
+                    insn_idx: call_idx,
+                    reason: "too many nested calls",
+                });
+            }
+            _ => visit(callee, frame + 1, subprogs, calls, visits)?,
+        };
+        if frame + callee_frames > MAX_CALL_FRAMES {
+            return Err(VerifyError::InvalidCall {
+                insn_idx: call_idx,
+                reason: "too many nested calls",
+            });
+        }
+        frames = frames.max(callee_frames);
+        stack = stack.max(callee_stack);
+    }
+
+    let result = (frames + 1, stack + subprogs[func].stack_depth);
+    visits[func] = Visit::Done(result.0, result.1);
+    Ok(result)
+}
+
+/// Worst-case number of instructions one run of `insns` executes.
+///
+/// A function costs each of its instructions once, plus its callees. A
+/// `bpf_loop` call costs its callback once per iteration: as many as the
+/// constant count moved into R1 right before the call, or else
+/// `P::MAX_LOOP_ITERATIONS`. Jumps back are left to the loop checks. The
+/// functions must have passed [`check_subprogs`].
+#[cfg(feature = "embedded-profile")]
+pub fn wcet<P: PhysicalProfile>(insns: &[BpfInsn]) -> u64 {
+    let subprogs = subprog::split(insns);
+    let loaded: Vec<usize> = insns
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, insn)| subprog::func_target(idx, insn))
+        .map(|start| subprogs.partition_point(|s| s.start <= start) - 1)
+        .collect();
+    let mut costs = vec![None; subprogs.len()];
+    cost::<P>(0, insns, &subprogs, &loaded, &mut costs)
+}
+
+/// Worst-case instructions of one call of function `func`.
+///
+/// A function still being costed counts as unbounded, which only happens
+/// when a callback can't be told apart from its callers.
+#[cfg(feature = "embedded-profile")]
+fn cost<P: PhysicalProfile>(
+    func: usize,
+    insns: &[BpfInsn],
+    subprogs: &[Subprog],
+    loaded: &[usize],
+    costs: &mut [Option<u64>],
+) -> u64 {
+    if let Some(cost) = costs[func] {
+        return cost;
+    }
+    costs[func] = Some(u64::MAX);
+
+    let sub = subprogs[func];
+    let func_of = |start: usize| subprogs.partition_point(|s| s.start <= start) - 1;
+    let mut total = 0u64;
+    for (idx, insn) in (sub.start..sub.end).zip(&insns[sub.start..sub.end]) {
+        if idx > sub.start && insns[idx - 1].is_wide() {
+            continue;
+        }
+        total = total.saturating_add(1);
+
+        if let Some(target) = subprog::call_target(idx, insn) {
+            let callee = cost::<P>(func_of(target), insns, subprogs, loaded, costs);
+            total = total.saturating_add(callee);
+        } else if insn.is_call() && insn.imm == HelperId::Loop as i32 {
+            let cap = P::MAX_LOOP_ITERATIONS as u64;
+            let bound = match last_write(insns, sub, idx, 1).map(|w| &insns[w]) {
+                // mov r1, imm (64 or 32 bit)
+                Some(w) if matches!(w.opcode, 0xb7 | 0xb4) && w.imm >= 0 => cap.min(w.imm as u64),
+                _ => cap,
+            };
+            let callback = match last_write(insns, sub, idx, 2) {
+                Some(w) if insns[w].is_func_load() => {
+                    let start = subprog::func_target(w, &insns[w]).unwrap_or(0);
+                    cost::<P>(func_of(start), insns, subprogs, loaded, costs)
+                }
+                // Loaded somewhere else: any loaded function may run
+                _ => loaded
+                    .iter()
+                    .map(|&f| cost::<P>(f, insns, subprogs, loaded, costs))
+                    .max()
+                    .unwrap_or(0),
+            };
+            total = total.saturating_add(bound.saturating_mul(callback));
+        }
+    }
+
+    costs[func] = Some(total);
+    total
+}
+
+/// Last instruction of `sub` that sets register `reg` before the one at
+/// `idx`, if no jump can land in between.
+#[cfg(feature = "embedded-profile")]
+fn last_write(insns: &[BpfInsn], sub: Subprog, idx: usize, reg: u8) -> Option<usize> {
+    let lands_at = |at: usize| {
+        (sub.start..sub.end)
+            .zip(&insns[sub.start..sub.end])
+            .any(|(j, insn)| {
+                insn.is_jump()
+                    && !insn.is_call()
+                    && !insn.is_exit()
+                    && j as i64 + 1 + insn.offset as i64 == at as i64
+            })
+    };
+
+    let mut at = idx;
+    while at > sub.start && !lands_at(at) {
+        at -= 1;
+        if at > sub.start && insns[at - 1].is_wide() {
+            // Second slot of a wide load
+            continue;
+        }
+        let insn = &insns[at];
+        let writes = if insn.is_call() {
+            reg <= 5
+        } else {
+            (insn.is_alu() || insn.is_wide() || insn.class() == Some(OpcodeClass::Ldx))
+                && insn.dst_reg() == reg
+        };
+        if writes {
+            return Some(at);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::insn::WideInsn;
+    use crate::profile::ActiveProfile;
+
+    fn check(insns: &[BpfInsn]) -> VerifyResult<usize> {
+        check_subprogs::<ActiveProfile>(insns)
+    }
+
+    #[test]
+    fn sums_stack_of_deepest_chain() {
+        let insns = [
+            BpfInsn::new(0x7a, 10, 0, -8, 0),  // 0: main uses 8 bytes
+            BpfInsn::call_local(1),            // 1: call 3
+            BpfInsn::exit(),                   // 2
+            BpfInsn::new(0x7a, 10, 0, -16, 0), // 3: uses 16 bytes
+            BpfInsn::call_local(1),            // 4: call 6
+            BpfInsn::exit(),                   // 5
+            BpfInsn::new(0x7a, 10, 0, -4, 0),  // 6: uses 8 bytes
+            BpfInsn::exit(),                   // 7
+        ];
+        assert_eq!(check(&insns), Ok(32));
+    }
+
+    #[test]
+    fn rejects_recursion() {
+        let insns = [
+            BpfInsn::call_local(1),  // 0: call 2
+            BpfInsn::exit(),         // 1
+            BpfInsn::call_local(-1), // 2: call 2
+            BpfInsn::exit(),         // 3
+        ];
+        assert_eq!(
+            check(&insns).err(),
+            Some(VerifyError::InvalidCall {
+                insn_idx: 2,
+                reason: "recursive call"
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_deep_chains() {
+        // Each function calls the next one
+        let mut insns = Vec::new();
+        for _ in 0..MAX_CALL_FRAMES {
+            insns.push(BpfInsn::call_local(1));
+            insns.push(BpfInsn::exit());
+        }
+        insns.push(BpfInsn::mov64_imm(0, 0));
+        insns.push(BpfInsn::exit());
+
+        assert!(matches!(
+            check(&insns),
+            Err(VerifyError::InvalidCall {
+                reason: "too many nested calls",
+                ..
+            })
+        ));
+        assert!(check(&insns[2..]).is_ok());
+    }
+
+    #[test]
+    fn rejects_fall_through_and_foreign_jumps() {
+        let falls_through = [
+            BpfInsn::call_local(0),   // 0: call 1
+            BpfInsn::mov64_imm(0, 0), // 1
+            BpfInsn::exit(),          // 2
+        ];
+        assert!(matches!(
+            check(&falls_through),
+            Err(VerifyError::InvalidCall { insn_idx: 0, .. })
+        ));
+
+        let jumps_out = [
+            BpfInsn::call_local(1), // 0: call 2
+            BpfInsn::exit(),        // 1
+            BpfInsn::ja(-2),        // 2: jump to 1
+        ];
+        assert!(matches!(
+            check(&jumps_out),
+            Err(VerifyError::InvalidJump { insn_idx: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_tail_calls_from_functions() {
+        let insns = [
+            BpfInsn::call_local(1),                   // 0: call 2
+            BpfInsn::exit(),                          // 1
+            BpfInsn::call(HelperId::TailCall as i32), // 2
+            BpfInsn::exit(),                          // 3
+        ];
+        assert!(matches!(
+            check(&insns),
+            Err(VerifyError::InvalidCall { insn_idx: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn loaded_functions_count_as_calls() {
+        let func = WideInsn::ld_func(2, 3);
+        let back = WideInsn::ld_func(2, -5);
+        let insns = [
+            func.insn,                // 0: r2 = &func 4
+            func.next,                // 1
+            BpfInsn::mov64_imm(0, 0), // 2
+            BpfInsn::exit(),          // 3
+            back.insn,                // 4: r2 = &func 0
+            back.next,                // 5
+            BpfInsn::mov64_imm(0, 0), // 6
+            BpfInsn::exit(),          // 7
+        ];
+        assert_eq!(
+            check(&insns).err(),
+            Some(VerifyError::InvalidCall {
+                insn_idx: 4,
+                reason: "recursive call"
+            })
+        );
+
+        let mut splits_wide = insns;
+        splits_wide[0].imm = 4;
+        assert!(matches!(
+            check(&splits_wide),
+            Err(VerifyError::InvalidJump { insn_idx: 0, .. })
+        ));
+    }
+
+    #[cfg(feature = "embedded-profile")]
+    #[test]
+    fn wcet_charges_callbacks_per_iteration() {
+        let func = WideInsn::ld_func(2, 5);
+        let mut insns = [
+            BpfInsn::mov64_imm(1, 10),            // 0: r1 = 10
+            func.insn,                            // 1: r2 = &func 7
+            func.next,                            // 2
+            BpfInsn::mov64_imm(3, 0),             // 3
+            BpfInsn::mov64_imm(4, 0),             // 4
+            BpfInsn::call(HelperId::Loop as i32), // 5
+            BpfInsn::exit(),                      // 6
+            BpfInsn::mov64_imm(0, 0),             // 7
+            BpfInsn::exit(),                      // 8
+        ];
+        assert_eq!(wcet::<ActiveProfile>(&insns), 6 + 10 * 2);
+
+        // Without a constant count the profile's bound applies
+        insns[0] = BpfInsn::call(HelperId::KtimeGetNs as i32);
+        let cap = ActiveProfile::MAX_LOOP_ITERATIONS as u64;
+        assert_eq!(wcet::<ActiveProfile>(&insns), 6 + cap * 2);
+    }
+
+    #[test]
+    fn rejects_large_frames_with_functions() {
+        let insns = [
+            BpfInsn::new(0x7a, 10, 0, -8, 0),    // 0: main uses 8 bytes
+            BpfInsn::call_local(1),              // 1: call 3
+            BpfInsn::exit(),                     // 2
+            BpfInsn::new(0x7a, 10, 0, -1024, 0), // 3: uses 1KB
+            BpfInsn::exit(),                     // 4
+        ];
+        assert_eq!(
+            check(&insns).err(),
+            Some(VerifyError::StackExceeded {
+                used: 1024,
+                limit: MAX_FRAME_SIZE
+            })
+        );
+
+        // Without calls the frame may take the profile's whole stack
+        assert_eq!(check(&insns[3..]), Ok(1024));
+    }
+}
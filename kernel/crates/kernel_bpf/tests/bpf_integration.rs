@@ -9,12 +9,14 @@
 
 #![cfg(any(feature = "cloud-profile", feature = "embedded-profile"))]
 
-use kernel_bpf::bytecode::insn::BpfInsn;
+use kernel_bpf::bytecode::insn::{BpfInsn, WideInsn};
 use kernel_bpf::bytecode::program::{BpfProgType, ProgramBuilder, ProgramError};
 use kernel_bpf::execution::{BpfContext, BpfExecutor, Interpreter};
 use kernel_bpf::maps::{ArrayMap, BpfMap, HashMap, MapError, RingBufMap};
 use kernel_bpf::profile::ActiveProfile;
 
+mod common;
+
 // Stubs for resolving linker errors during integration testing
 
 // SAFETY: Test stub for BPF helper.
@@ -105,12 +107,6 @@ pub extern "C" fn bpf_pwm_write(_pwm_id: u32, _channel: u32, _duty: u32) -> i64
     0
 }
 
-// SAFETY: Test stub for BPF helper.
-#[unsafe(no_mangle)]
-pub extern "C" fn bpf_timeseries_push(_map_id: u32, _key: *const u8, _value: *const u8) -> i64 {
-    0
-}
-
 // SAFETY: Test stub for BPF helper.
 #[unsafe(no_mangle)]
 pub extern "C" fn bpf_motor_emergency_stop(_reason: u32) -> i64 {
@@ -843,3 +839,77 @@ mod program_types {
         assert_eq!(program.prog_type(), BpfProgType::Xdp);
     }
 }
+
+// ============================================================================
+// Load-time Verification
+// ============================================================================
+
+mod verification {
+    use kernel_bpf::verifier::{VerifyError, verify_program};
+
+    use super::*;
+
+    /// stxdw [r10 + off], r0
+    fn stx_dw_fp(off: i16) -> BpfInsn {
+        BpfInsn::new(0x7b, 10, 0, off, 0)
+    }
+
+    #[test]
+    fn verified_program_reports_stack_depth() {
+        let insns = [
+            BpfInsn::mov64_imm(0, 7),
+            stx_dw_fp(-16),
+            BpfInsn::exit(),
+        ];
+
+        let program = verify_program(BpfProgType::SocketFilter, &insns).expect("verifies");
+        assert!(program.stack_size() >= 16);
+        assert_eq!(program.insn_count(), insns.len());
+    }
+
+    #[test]
+    fn stack_free_program_has_zero_depth() {
+        let insns = [BpfInsn::mov64_imm(0, 0), BpfInsn::exit()];
+
+        let program = verify_program(BpfProgType::SocketFilter, &insns).expect("verifies");
+        assert_eq!(program.stack_size(), 0);
+    }
+
+    #[test]
+    fn uninitialized_return_rejected() {
+        let insns = [BpfInsn::exit()];
+
+        let err = verify_program(BpfProgType::SocketFilter, &insns).unwrap_err();
+        assert!(matches!(err, VerifyError::UninitializedRegister { .. }));
+    }
+
+    #[test]
+    fn helper_buffer_past_frame_rejected() {
+        // bpf_timeseries_query writes 64 bytes at fp - 8
+        let map = WideInsn::ld_map_fd(1, 0);
+        let insns = [
+            map.insn,
+            map.next,
+            BpfInsn::mov64_imm(2, 1),
+            BpfInsn::mov64_reg(3, 10),
+            BpfInsn::add64_imm(3, -8),
+            BpfInsn::mov64_imm(4, 64),
+            BpfInsn::call(1008),
+            BpfInsn::exit(),
+        ];
+
+        let err = verify_program(BpfProgType::SocketFilter, &insns).unwrap_err();
+        assert!(matches!(err, VerifyError::OutOfBoundsAccess { .. }));
+    }
+
+    #[test]
+    fn jump_out_of_program_rejected() {
+        let insns = [
+            BpfInsn::mov64_imm(0, 0),
+            BpfInsn::ja(10),
+            BpfInsn::exit(),
+        ];
+
+        assert!(verify_program(BpfProgType::SocketFilter, &insns).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//! Helper stubs shared by the integration tests.
+//!
+//! Stubs whose behaviour a test depends on stay in that test.
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_get_smp_processor_id() -> u32 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_timeseries_push(_map_id: u32, _key: *const u8, _value: *const u8) -> i64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_timeseries_query(_map_id: u32, _n: u64, _out: *mut u8, _size: u32) -> i64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_timeseries_query_window(
+    _map_id: u32,
+    _start_ns: u64,
+    _end_ns: u64,
+    _out: *mut u8,
+    _size: u32,
+) -> i64 {
+    0
+}
@@ -10,6 +10,8 @@ use kernel_bpf::bytecode::program::{BpfProgType, ProgramBuilder};
 use kernel_bpf::execution::{BpfContext, BpfExecutor, Interpreter};
 use kernel_bpf::profile::ActiveProfile;
 
+mod common;
+
 // Stubs for resolving linker errors during integration testing
 
 // SAFETY: Test stub for BPF helper. Safe to be called from C/BPF context in tests.
@@ -100,12 +102,6 @@ pub extern "C" fn bpf_pwm_write(_pwm_id: u32, _channel: u32, _duty: u32) -> i64
     0
 }
 
-// SAFETY: Test stub for BPF helper.
-#[unsafe(no_mangle)]
-pub extern "C" fn bpf_timeseries_push(_map_id: u32, _key: *const u8, _value: *const u8) -> i64 {
-    0
-}
-
 // SAFETY: Test stub for BPF helper.
 #[unsafe(no_mangle)]
 pub extern "C" fn bpf_motor_emergency_stop(_reason: u32) -> i64 {
@@ -0,0 +1,530 @@
+//! JIT Consistency Tests
+//!
+//! These tests run the same BPF bytecode through the x86_64 JIT and the
+//! interpreter and require identical results, including errors. The
+//! interpreter is the reference implementation.
+//!
+//! Executable memory is provided by the stubs below using `mmap`, so the
+//! JIT-compiled code really runs natively on the host.
+
+#![cfg(all(feature = "cloud-profile", target_arch = "x86_64", target_os = "linux"))]
+
+use core::ffi::c_void;
+
+use kernel_bpf::bytecode::insn::{BpfInsn, WideInsn};
+use kernel_bpf::bytecode::program::{BpfProgType, BpfProgram, ProgramBuilder};
+use kernel_bpf::execution::jit::JitExecutor;
+use kernel_bpf::execution::{BpfContext, BpfExecutor, BpfResult, Interpreter};
+use kernel_bpf::profile::CloudProfile;
+
+mod common;
+
+// Executable memory for the JIT, backed by anonymous mappings.
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+unsafe extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64)
+    -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+/// Test implementation of the kernel's JIT allocator.
+///
+/// # Safety
+///
+/// Always safe to call; the signature matches the kernel's allocator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bpf_jit_alloc_exec(size: usize) -> *mut u8 {
+    let ptr = unsafe {
+        mmap(
+            core::ptr::null_mut(),
+            size,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if ptr as isize == -1 {
+        core::ptr::null_mut()
+    } else {
+        ptr.cast()
+    }
+}
+
+/// Test implementation of the kernel's JIT allocator.
+///
+/// # Safety
+///
+/// `ptr` and `size` must describe a mapping from [`bpf_jit_alloc_exec`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bpf_jit_protect_exec(ptr: *mut u8, size: usize) -> bool {
+    unsafe { mprotect(ptr.cast(), size, PROT_READ | PROT_EXEC) == 0 }
+}
+
+/// Test implementation of the kernel's JIT allocator.
+///
+/// # Safety
+///
+/// `ptr` and `size` must describe a mapping from [`bpf_jit_alloc_exec`],
+/// which is unusable afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bpf_jit_free_exec(ptr: *mut u8, size: usize) {
+    unsafe { munmap(ptr.cast(), size) };
+}
+
+// Stubs for resolving linker errors during integration testing. Some return
+// values derived from their arguments so argument passing is checked too.
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_ktime_get_ns() -> u64 {
+    1_000_000
+}
+
+/// Test stub for BPF helper.
+///
+/// # Safety
+///
+/// `ctx` must point to a valid [`BpfContext`], as the executors pass it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bpf_get_interrupt_latency_ns(ctx: *const BpfContext) -> u64 {
+    // SAFETY: Upheld by the caller.
+    unsafe { (*ctx).interrupt_latency_ns }
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_get_boot_time_ms(_ctx: *const BpfContext) -> u64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_get_kernel_heap_kb(_ctx: *const BpfContext) -> u64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_get_kernel_image_mb(_ctx: *const BpfContext) -> u64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_trace_printk(_fmt: *const u8, _len: u32) -> i32 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_map_lookup_elem(_map_id: u32, _key: *const u8) -> *mut u8 {
+    core::ptr::null_mut()
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_map_update_elem(
+    _map_id: u32,
+    _key: *const u8,
+    _value: *const u8,
+    _flags: u64,
+) -> i32 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_map_delete_elem(_map_id: u32, _key: *const u8) -> i32 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_ringbuf_output(
+    _map_id: u32,
+    _data: *const u8,
+    _size: u64,
+    _flags: u64,
+) -> i64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_gpio_read(pin: u32) -> i64 {
+    pin as i64 * 2
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_gpio_write(_pin: u32, _value: u32) -> i64 {
+    0
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_pwm_write(pwm_id: u32, channel: u32, duty: u32) -> i64 {
+    (pwm_id * 100 + channel * 10 + duty) as i64
+}
+
+// SAFETY: Test stub for BPF helper.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_motor_emergency_stop(_reason: u32) -> i64 {
+    0
+}
+
+// Opcode building blocks
+const ALU64: u8 = 0x07;
+const ALU32: u8 = 0x04;
+const JMP: u8 = 0x05;
+const JMP32: u8 = 0x06;
+const K: u8 = 0x00;
+const X: u8 = 0x08;
+
+const ALU_OPS: [u8; 12] = [
+    0x00, // add
+    0x10, // sub
+    0x20, // mul
+    0x30, // div
+    0x40, // or
+    0x50, // and
+    0x60, // lsh
+    0x70, // rsh
+    0x90, // mod
+    0xa0, // xor
+    0xb0, // mov
+    0xc0, // arsh
+];
+
+const JMP_OPS: [u8; 11] = [
+    0x10, // jeq
+    0x20, // jgt
+    0x30, // jge
+    0x40, // jset
+    0x50, // jne
+    0x60, // jsgt
+    0x70, // jsge
+    0xa0, // jlt
+    0xb0, // jle
+    0xc0, // jslt
+    0xd0, // jsle
+];
+
+/// Values that tend to expose sign and width bugs.
+const INTERESTING: [u64; 10] = [
+    0,
+    1,
+    7,
+    0x7fff_ffff,
+    0x8000_0000,
+    0xffff_ffff,
+    0x1_0000_0000,
+    0x8000_0000_0000_0000,
+    0xffff_ffff_ffff_fff0,
+    0x0123_4567_89ab_cdef,
+];
+
+fn build(insns: &[BpfInsn]) -> BpfProgram<CloudProfile> {
+    ProgramBuilder::<CloudProfile>::new(BpfProgType::SocketFilter)
+        .insns(insns.iter().copied())
+        .build()
+        .expect("valid program")
+}
+
+fn ld_imm64(dst: u8, imm: u64) -> [BpfInsn; 2] {
+    let wide = WideInsn::ld_dw_imm(dst, imm);
+    [wide.insn, wide.next]
+}
+
+/// Run a program through both engines and require the same result.
+fn assert_consistent(insns: &[BpfInsn], ctx: &BpfContext) {
+    let _ = run_consistent(insns, ctx);
+}
+
+/// Like [`assert_consistent`], returning the result for further checks.
+fn run_consistent(insns: &[BpfInsn], ctx: &BpfContext) -> BpfResult {
+    let program = build(insns);
+
+    let expected = Interpreter::<CloudProfile>::new().execute(&program, ctx);
+    let image = JitExecutor::new()
+        .load(&program)
+        .expect("program should JIT-compile");
+    let actual = image.call(ctx);
+
+    assert_eq!(
+        actual, expected,
+        "JIT and interpreter disagree on {insns:#?}"
+    );
+    actual
+}
+
+/// Minimal deterministic PRNG (xorshift64).
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+#[test]
+fn jit_alu_binary_ops() {
+    let ctx = BpfContext::empty();
+
+    for class in [ALU64, ALU32] {
+        for op in ALU_OPS {
+            for a in INTERESTING {
+                for b in INTERESTING {
+                    // Register source (R2 -> R3 avoids the div/shift fixups)
+                    let mut insns = Vec::new();
+                    insns.extend(ld_imm64(3, a));
+                    insns.extend(ld_imm64(2, b));
+                    insns.push(BpfInsn::new(class | X | op, 3, 2, 0, 0));
+                    insns.push(BpfInsn::mov64_reg(0, 3));
+                    insns.push(BpfInsn::exit());
+                    assert_consistent(&insns, &ctx);
+
+                    // Immediate source
+                    let mut insns = Vec::new();
+                    insns.extend(ld_imm64(0, a));
+                    insns.push(BpfInsn::new(class | K | op, 0, 0, 0, b as i32));
+                    insns.push(BpfInsn::exit());
+                    assert_consistent(&insns, &ctx);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jit_alu_fixed_registers() {
+    // Division uses RAX/RDX (R0/R3) and variable shifts use RCX (R4), so
+    // exercise every combination of those as source and destination.
+    let ctx = BpfContext::empty();
+    let regs = [0u8, 1, 3, 4, 5, 6];
+
+    for class in [ALU64, ALU32] {
+        for op in [0x30, 0x90, 0x60, 0x70, 0xc0] {
+            for dst in regs {
+                for src in regs {
+                    let mut insns = Vec::new();
+                    for (i, reg) in regs.iter().enumerate() {
+                        insns.extend(ld_imm64(*reg, 0x8000_0000_0000_0100 + i as u64 * 3 + 1));
+                    }
+                    insns.push(BpfInsn::new(class | X | op, dst, src, 0, 0));
+                    // Fold every register into R0 so clobbers are visible
+                    for reg in regs.iter().filter(|&&r| r != 0) {
+                        insns.push(BpfInsn::new(ALU64 | X | 0x10, 0, *reg, 0, 0));
+                        insns.push(BpfInsn::new(ALU64 | K | 0x60, 0, 0, 0, 5));
+                    }
+                    insns.push(BpfInsn::exit());
+                    assert_consistent(&insns, &ctx);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jit_unary_and_byte_swap() {
+    let ctx = BpfContext::empty();
+
+    for class in [ALU64, ALU32] {
+        for a in INTERESTING {
+            let mut insns = Vec::new();
+            insns.extend(ld_imm64(0, a));
+            insns.push(BpfInsn::new(class | 0x80, 0, 0, 0, 0)); // neg
+            insns.push(BpfInsn::exit());
+            assert_consistent(&insns, &ctx);
+
+            for width in [16, 32, 64] {
+                for src in [K, X] {
+                    let mut insns = Vec::new();
+                    insns.extend(ld_imm64(7, a));
+                    insns.push(BpfInsn::new(class | src | 0xd0, 7, 0, 0, width));
+                    insns.push(BpfInsn::mov64_reg(0, 7));
+                    insns.push(BpfInsn::exit());
+                    assert_consistent(&insns, &ctx);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jit_conditional_jumps() {
+    let ctx = BpfContext::empty();
+
+    for class in [JMP, JMP32] {
+        for op in JMP_OPS {
+            for a in INTERESTING {
+                for b in INTERESTING {
+                    for src in [K, X] {
+                        let mut insns = Vec::new();
+                        insns.extend(ld_imm64(6, a));
+                        insns.extend(ld_imm64(7, b));
+                        insns.push(BpfInsn::mov64_imm(0, 1));
+                        insns.push(BpfInsn::new(class | src | op, 6, 7, 1, b as i32));
+                        insns.push(BpfInsn::mov64_imm(0, 2));
+                        insns.push(BpfInsn::exit());
+                        assert_consistent(&insns, &ctx);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jit_jumps_over_wide_loads() {
+    // Jump offsets count the second slot of a wide load
+    let mut insns = vec![BpfInsn::ja(2)];
+    insns.extend(ld_imm64(0, 1));
+    insns.extend(ld_imm64(0, 0xdead_beef_0000_0001));
+    insns.push(BpfInsn::exit());
+
+    let result = run_consistent(&insns, &BpfContext::empty());
+    assert_eq!(result, Ok(0xdead_beef_0000_0001));
+}
+
+#[test]
+fn jit_stack_and_context_access() {
+    let data: Vec<u8> = (0u8..64).map(|b| b.wrapping_mul(37)).collect();
+    let ctx = BpfContext::from_slice(&data);
+
+    // (size bits, bytes)
+    for (size, bytes) in [(0x18u8, 8i16), (0x00, 4), (0x08, 2), (0x10, 1)] {
+        let insns = [
+            // r2 = ctx->data
+            BpfInsn::new(0x61 | 0x18, 2, 1, 0, 0),
+            // r3 = *(size *)(r2 + 5)
+            BpfInsn::new(0x61 | size, 3, 2, 5, 0),
+            // *(size *)(r10 - 16) = r3; *(size *)(r10 - 16 + bytes) = -1
+            BpfInsn::new(0x63 | size, 10, 3, -16, 0),
+            BpfInsn::new(0x62 | size, 10, 0, -16 + bytes, -1),
+            // r0 = *(u64 *)(r10 - 16) after zeroing the slot
+            BpfInsn::new(0x61 | size, 0, 10, -16, 0),
+            BpfInsn::new(0x61 | size, 4, 10, -16 + bytes, 0),
+            BpfInsn::new(ALU64 | X | 0xa0, 0, 4, 0, 0),
+            BpfInsn::exit(),
+        ];
+        assert_consistent(&insns, &ctx);
+    }
+}
+
+#[test]
+fn jit_helper_calls() {
+    let mut ctx = BpfContext::empty();
+    ctx.interrupt_latency_ns = 4242;
+
+    let insns = [
+        // r6 = ktime (constant in tests)
+        BpfInsn::call(1),
+        BpfInsn::mov64_reg(6, 0),
+        // r7 = gpio_read(21)
+        BpfInsn::mov64_imm(1, 21),
+        BpfInsn::call(1004),
+        BpfInsn::mov64_reg(7, 0),
+        // r0 = pwm_write(1, 2, 3)
+        BpfInsn::mov64_imm(1, 1),
+        BpfInsn::mov64_imm(2, 2),
+        BpfInsn::mov64_imm(3, 3),
+        BpfInsn::call(1005),
+        BpfInsn::add64_reg(0, 6),
+        BpfInsn::add64_reg(0, 7),
+        BpfInsn::exit(),
+    ];
+    let result = run_consistent(&insns, &ctx);
+    assert_eq!(result, Ok(1_000_000 + 42 + 123));
+
+    // Context-aware helper reads through R1
+    let insns = [BpfInsn::call(13), BpfInsn::exit()];
+    assert_eq!(run_consistent(&insns, &ctx), Ok(4242));
+}
+
+#[test]
+fn jit_runtime_errors() {
+    let ctx = BpfContext::empty();
+
+    // Division by zero (register and immediate)
+    for class in [ALU64, ALU32] {
+        for op in [0x30, 0x90] {
+            let insns = [
+                BpfInsn::mov64_imm(0, 5),
+                BpfInsn::mov64_imm(2, 0),
+                BpfInsn::new(class | X | op, 0, 2, 0, 0),
+                BpfInsn::exit(),
+            ];
+            assert!(run_consistent(&insns, &ctx).is_err());
+
+            let insns = [
+                BpfInsn::mov64_imm(0, 5),
+                BpfInsn::new(class | K | op, 0, 0, 0, 0),
+                BpfInsn::exit(),
+            ];
+            assert!(run_consistent(&insns, &ctx).is_err());
+        }
+    }
+
+    // Unknown helper
+    let insns = [BpfInsn::call(9999), BpfInsn::exit()];
+    assert!(run_consistent(&insns, &ctx).is_err());
+
+    // Running off the end of the program
+    let insns = [BpfInsn::mov64_imm(0, 1)];
+    assert!(run_consistent(&insns, &ctx).is_err());
+}
+
+#[test]
+fn jit_random_straight_line_programs() {
+    let ctx = BpfContext::empty();
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+    for _ in 0..500 {
+        let mut insns = Vec::new();
+        for reg in 0..10 {
+            let value = if rng.next().is_multiple_of(2) {
+                rng.pick(&INTERESTING)
+            } else {
+                rng.next()
+            };
+            insns.extend(ld_imm64(reg, value));
+        }
+
+        for _ in 0..24 {
+            let class = rng.pick(&[ALU64, ALU32]);
+            let op = rng.pick(&ALU_OPS);
+            let dst = (rng.next() % 10) as u8;
+            let insn = if rng.next().is_multiple_of(2) {
+                let src = (rng.next() % 10) as u8;
+                BpfInsn::new(class | X | op, dst, src, 0, 0)
+            } else {
+                BpfInsn::new(class | K | op, dst, 0, 0, rng.next() as i32)
+            };
+            insns.push(insn);
+        }
+
+        for reg in 1..10 {
+            insns.push(BpfInsn::new(ALU64 | X | 0xa0, 0, reg, 0, 0));
+            insns.push(BpfInsn::new(ALU64 | K | 0x20, 0, 0, 0, 31));
+        }
+        insns.push(BpfInsn::exit());
+
+        assert_consistent(&insns, &ctx);
+    }
+}
@@ -24,6 +24,25 @@ pub enum CreateMappingError {
     NotFound,
 }
 
+/// Reasons the object behind a file descriptor can't be mapped.
+pub enum MapObjectError {
+    /// The file descriptor is not open.
+    BadFd,
+    /// The object doesn't support being mapped.
+    NotMappable,
+    /// The offset or length doesn't fit the object.
+    InvalidRange,
+    /// The object can't be mapped with the requested protection.
+    PermissionDenied,
+    Create(CreateMappingError),
+}
+
+impl From<CreateMappingError> for MapObjectError {
+    fn from(e: CreateMappingError) -> Self {
+        Self::Create(e)
+    }
+}
+
 pub trait MemoryAccess {
     type Mapping: Mapping;
 
@@ -1,5 +1,7 @@
+use core::ffi::c_int;
+
 use crate::UserspacePtr;
-use crate::access::{AllocationStrategy, CreateMappingError, Location};
+use crate::access::{AllocationStrategy, CreateMappingError, Location, MapObjectError};
 
 /// Represents a tracked memory region within a process.
 /// Memory regions can be accessed by kernel components like interrupt handlers.
@@ -25,6 +27,19 @@ pub trait MemoryRegionAccess {
         allocation_strategy: AllocationStrategy,
     ) -> Result<UserspacePtr<u8>, CreateMappingError>;
 
+    /// Maps `size` bytes of the object behind `fd`, starting at `offset`,
+    /// and tracks the mapping as a memory region in the process.
+    /// The memory is shared with the object, not copied.
+    /// Returns the address of the created mapping.
+    fn create_and_track_object_mapping(
+        &self,
+        location: Location,
+        size: usize,
+        fd: c_int,
+        offset: usize,
+        writable: bool,
+    ) -> Result<UserspacePtr<u8>, MapObjectError>;
+
     /// Adds a memory region to the process's memory region tracking.
     /// This makes the region available to other kernel components.
     fn add_memory_region(&self, region: Self::Region);
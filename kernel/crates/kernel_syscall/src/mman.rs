@@ -1,7 +1,9 @@
-use kernel_abi::{EINVAL, ENOMEM, Errno, MapFlags, ProtFlags};
+use kernel_abi::{EBADF, EINVAL, ENODEV, ENOMEM, EPERM, Errno, MapFlags, ProtFlags};
 
 use crate::UserspacePtr;
-use crate::access::{AllocationStrategy, Location, MemoryRegionAccess};
+use crate::access::{
+    AllocationStrategy, CreateMappingError, Location, MapObjectError, MemoryRegionAccess,
+};
 
 pub fn sys_mmap<Cx: MemoryRegionAccess>(
     cx: &Cx,
@@ -9,8 +11,8 @@ pub fn sys_mmap<Cx: MemoryRegionAccess>(
     len: usize,
     prot: i32,
     flags: i32,
-    _fd: i32,
-    _offset: usize,
+    fd: i32,
+    offset: usize,
 ) -> Result<usize, Errno> {
     // Validate size is non-zero
     if len == 0 {
@@ -19,11 +21,13 @@ pub fn sys_mmap<Cx: MemoryRegionAccess>(
 
     let flags = MapFlags::from_bits(flags).ok_or(EINVAL)?;
 
-    // For now, only support anonymous private mappings
-    if !flags.contains(MapFlags::ANONYMOUS) {
-        return Err(EINVAL);
-    }
-    if !flags.contains(MapFlags::PRIVATE) {
+    if flags.contains(MapFlags::ANONYMOUS) {
+        // For now, only support anonymous private mappings
+        if !flags.contains(MapFlags::PRIVATE) {
+            return Err(EINVAL);
+        }
+    } else if !flags.contains(MapFlags::SHARED) || flags.contains(MapFlags::PRIVATE) {
+        // Mappings of an object share its memory; private copies aren't supported
         return Err(EINVAL);
     }
 
@@ -49,6 +53,26 @@ pub fn sys_mmap<Cx: MemoryRegionAccess>(
         Location::Anywhere
     };
 
+    if flags.contains(MapFlags::SHARED) {
+        let mapped_addr = cx
+            .create_and_track_object_mapping(
+                location,
+                len,
+                fd,
+                offset,
+                prot.contains(ProtFlags::WRITE),
+            )
+            .map_err(|e| match e {
+                MapObjectError::BadFd => EBADF,
+                MapObjectError::NotMappable => ENODEV,
+                MapObjectError::InvalidRange => EINVAL,
+                MapObjectError::PermissionDenied => EPERM,
+                MapObjectError::Create(CreateMappingError::OutOfMemory) => ENOMEM,
+                MapObjectError::Create(_) => EINVAL,
+            })?;
+        return Ok(mapped_addr.addr());
+    }
+
     // We'll use eager allocation for now (as specified in requirements)
     let allocation_strategy = AllocationStrategy::Eager;
 
@@ -57,9 +81,9 @@ pub fn sys_mmap<Cx: MemoryRegionAccess>(
     let mapped_addr = cx
         .create_and_track_mapping(location, len, allocation_strategy)
         .map_err(|e| match e {
-            crate::access::CreateMappingError::LocationAlreadyMapped => EINVAL,
-            crate::access::CreateMappingError::OutOfMemory => ENOMEM,
-            crate::access::CreateMappingError::NotFound => EINVAL,
+            CreateMappingError::LocationAlreadyMapped => EINVAL,
+            CreateMappingError::OutOfMemory => ENOMEM,
+            CreateMappingError::NotFound => EINVAL,
         })?;
 
     Ok(mapped_addr.addr())
@@ -69,13 +93,15 @@ pub fn sys_mmap<Cx: MemoryRegionAccess>(
 mod tests {
     use alloc::sync::Arc;
     use alloc::vec::Vec;
+    use core::ffi::c_int;
 
-    use kernel_abi::{EINVAL, MapFlags, ProtFlags};
+    use kernel_abi::{EBADF, EINVAL, EPERM, MapFlags, ProtFlags};
     use spin::mutex::Mutex;
 
     use crate::UserspacePtr;
     use crate::access::{
-        AllocationStrategy, CreateMappingError, Location, MemoryRegion, MemoryRegionAccess,
+        AllocationStrategy, CreateMappingError, Location, MapObjectError, MemoryRegion,
+        MemoryRegionAccess,
     };
     use crate::mman::sys_mmap;
 
@@ -96,6 +122,7 @@ mod tests {
 
     struct TestMemoryAccess {
         mappings: Mutex<Vec<(usize, usize)>>, // (addr, size)
+        object_mappings: Mutex<Vec<(c_int, usize, bool)>>, // (fd, offset, writable)
         next_addr: Mutex<usize>,
     }
 
@@ -103,6 +130,7 @@ mod tests {
         fn new() -> Self {
             Self {
                 mappings: Mutex::new(Vec::new()),
+                object_mappings: Mutex::new(Vec::new()),
                 next_addr: Mutex::new(0x1000), // Start at page boundary
             }
         }
@@ -147,6 +175,24 @@ mod tests {
             Ok(ptr)
         }
 
+        fn create_and_track_object_mapping(
+            &self,
+            location: Location,
+            size: usize,
+            fd: c_int,
+            offset: usize,
+            writable: bool,
+        ) -> Result<UserspacePtr<u8>, MapObjectError> {
+            // fd 3 is an object whose pages are read-only
+            match fd {
+                3 if writable => return Err(MapObjectError::PermissionDenied),
+                3 => {}
+                _ => return Err(MapObjectError::BadFd),
+            }
+            self.object_mappings.lock().push((fd, offset, writable));
+            Ok(self.create_and_track_mapping(location, size, AllocationStrategy::Eager)?)
+        }
+
         fn add_memory_region(&self, _region: Self::Region) {
             // Just a placeholder for testing
         }
@@ -275,4 +321,50 @@ mod tests {
 
         assert_eq!(result, Err(EINVAL));
     }
+
+    #[test]
+    fn test_mmap_shared_object() {
+        let cx = Arc::new(TestMemoryAccess::new());
+        // SAFETY: creating a dummy pointer for testing purposes
+        let addr = unsafe { UserspacePtr::try_from_usize(0).unwrap() };
+
+        let result = sys_mmap(
+            &cx,
+            addr,
+            8192,
+            ProtFlags::READ.bits(),
+            MapFlags::SHARED.bits(),
+            3,
+            4096,
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(cx.object_mappings.lock().as_slice(), &[(3, 4096, false)]);
+    }
+
+    #[test]
+    fn test_mmap_shared_object_errors() {
+        let cx = Arc::new(TestMemoryAccess::new());
+        // SAFETY: creating a dummy pointer for testing purposes
+        let addr = unsafe { UserspacePtr::try_from_usize(0).unwrap() };
+        let rw = (ProtFlags::READ | ProtFlags::WRITE).bits();
+
+        let bad_fd = sys_mmap(&cx, addr, 4096, rw, MapFlags::SHARED.bits(), 7, 0);
+        assert_eq!(bad_fd, Err(EBADF));
+
+        let read_only = sys_mmap(&cx, addr, 4096, rw, MapFlags::SHARED.bits(), 3, 0);
+        assert_eq!(read_only, Err(EPERM));
+
+        let private_copy = sys_mmap(
+            &cx,
+            addr,
+            4096,
+            ProtFlags::READ.bits(),
+            (MapFlags::SHARED | MapFlags::PRIVATE).bits(),
+            3,
+            0,
+        );
+        assert_eq!(private_copy, Err(EINVAL));
+        assert!(cx.object_mappings.lock().is_empty());
+    }
 }
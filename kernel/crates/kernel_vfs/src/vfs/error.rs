@@ -49,6 +49,8 @@ pub enum ReadError {
     ReadFailed,
     #[error("file is not readable")]
     NotReadable,
+    #[error("no data available yet")]
+    WouldBlock,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Error)]
@@ -61,6 +61,18 @@ impl VfsNode {
         }
     }
 
+    /// The handle this node was opened with on its file system.
+    #[must_use]
+    pub fn fs_handle(&self) -> FsHandle {
+        self.fs_handle
+    }
+
+    /// Whether this node was opened on the given file system.
+    #[must_use]
+    pub fn is_on(&self, fs: &Arc<RwLock<dyn FileSystem>>) -> bool {
+        Weak::as_ptr(&self.fs).cast::<()>() == Arc::as_ptr(fs).cast::<()>()
+    }
+
     /// Reads up to `buf.len()` bytes from the file at the given
     /// `offset` into `buf` and returns the number of bytes read.
     ///
@@ -100,8 +112,12 @@ impl VfsNode {
 
 #[cfg(test)]
 mod tests {
+    use alloc::sync::Arc;
     use alloc::vec;
 
+    use spin::RwLock;
+
+    use crate::fs::FileSystem;
     use crate::path::{AbsolutePath, ROOT};
     use crate::testing::TestFs;
     use crate::{CloseError, Stat, Vfs};
@@ -159,4 +175,26 @@ mod tests {
 
         drop(node);
     }
+
+    #[test]
+    fn test_is_on() {
+        let mut fs = TestFs::default();
+        fs.insert_file(
+            AbsolutePath::try_new("/foo/bar.txt").unwrap(),
+            vec![0_u8; 1],
+            Stat::default(),
+        );
+
+        let mut vfs = Vfs::new();
+        vfs.mount(ROOT, fs).unwrap();
+
+        let node = vfs
+            .open(AbsolutePath::try_new("/foo/bar.txt").unwrap())
+            .unwrap();
+        let fs = node.fs.upgrade().expect("file system should still exist");
+        let other: Arc<RwLock<dyn FileSystem>> = Arc::new(RwLock::new(TestFs::default()));
+
+        assert!(node.is_on(&fs));
+        assert!(!node.is_on(&other));
+    }
 }
@@ -69,10 +69,11 @@ pub fn cpu_id() -> usize {
     mpidr & 0xFF
 }
 
-/// Reschedule on timer interrupt
+/// Wake expired sleepers and reschedule on timer interrupt
 ///
 /// Called from the timer interrupt handler.
 pub fn timer_tick() {
+    crate::mcore::mtask::scheduler::sleep::wake_expired();
     if let Some(ctx) = try_current() {
         log::trace!(
             "timer_tick: setting need_reschedule for CPU {}",
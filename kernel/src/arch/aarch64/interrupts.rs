@@ -163,13 +163,7 @@ fn handle_timer_interrupt(ctx: &ExceptionContext) {
     set_next_timer();
 
     // Run BPF hooks (AttachType::Timer = 1)
-    //
-    // We clone programs and release the lock BEFORE execution so that BPF
-    // helpers (e.g. bpf_ringbuf_output) can re-acquire the lock for map
-    // operations without deadlocking.
-    if let Some(manager) = crate::BPF_MANAGER.get() {
-        let programs = manager.lock().get_hook_programs(1);
-
+    if crate::BPF_MANAGER.get().is_some() {
         // Calculate interrupt latency from vector entry to now
         let mut bpf_ctx = kernel_bpf::execution::BpfContext::empty();
 
@@ -192,12 +186,7 @@ fn handle_timer_interrupt(ctx: &ExceptionContext) {
                 (latency_ticks as u128 * 1_000_000_000 / freq as u128) as u64;
         }
 
-        for (prog_id, program) in &programs {
-            match crate::bpf::BpfManager::execute_program(program, &bpf_ctx) {
-                Ok(_res) => {}
-                Err(e) => log::error!("BPF Timer Hook [id={}] failed: {:?}", prog_id, e),
-            }
-        }
+        crate::bpf::hooks::run_hooks(crate::bpf::ATTACH_TYPE_TIMER, &bpf_ctx);
     }
 }
 
@@ -261,29 +261,20 @@ extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFr
     }
 
     // 2. Run BPF hooks (AttachType::Timer = 1)
-    //
-    // We clone programs and release the lock BEFORE execution so that BPF
-    // helpers (e.g. bpf_ringbuf_output) can re-acquire the lock for map
-    // operations without deadlocking.
-    if let Some(manager) = crate::BPF_MANAGER.get() {
-        let programs = manager.lock().get_hook_programs(1);
-        let ctx = kernel_bpf::execution::BpfContext::empty();
-        for (prog_id, program) in &programs {
-            match crate::bpf::BpfManager::execute_program(program, &ctx) {
-                Ok(res) => {
-                    let _ = res;
-                }
-                Err(e) => log::error!("BPF Timer Hook [id={}] failed: {:?}", prog_id, e),
-            }
-        }
-    }
+    crate::bpf::hooks::run_hooks(
+        crate::bpf::ATTACH_TYPE_TIMER,
+        &kernel_bpf::execution::BpfContext::empty(),
+    );
+
+    // 3. Wake the sleeping tasks whose deadline passed
+    crate::mcore::mtask::scheduler::sleep::wake_expired();
 
-    // 3. Schedule next task
+    // 4. Preempt the current task if a ready task outranks it
     let ctx = ExecutionContext::load();
     // SAFETY: Rescheduling is safe here as we are in an interrupt handler
     // and the scheduler handles context switching.
     unsafe {
-        ctx.scheduler_mut().reschedule();
+        ctx.scheduler_mut().preempt();
     }
 }
 
@@ -378,7 +369,7 @@ extern "x86-interrupt" fn page_fault_handler(
                         // TODO: allocate new physical page, map it and add it to the lazy memory
                         // region
                     }
-                    MemoryRegion::Mapped(_mapped_memory_region) => {
+                    MemoryRegion::Mapped(_) | MemoryRegion::Shared(_) => {
                         error!(
                             "invalid memory access in process '{}' task '{}', terminating...",
                             process.name(),
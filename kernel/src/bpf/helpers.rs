@@ -1,3 +1,6 @@
+use kernel_bpf::maps::{TimeSeriesAggregate, TimeSeriesMap};
+
+use crate::mcore::context::ExecutionContext;
 use crate::time::get_kernel_time_ns;
 
 /// BPF helper: Get current time in nanoseconds
@@ -11,6 +14,19 @@ pub extern "C" fn bpf_ktime_get_ns() -> u64 {
     get_kernel_time_ns()
 }
 
+/// BPF helper: Get the ID of the CPU the program runs on.
+///
+/// Per-CPU maps use it to select the values of the current CPU.
+///
+/// # Safety
+///
+/// This function is an entry point for BPF programs. It is safe to call from
+/// any context as it only reads the CPU's execution context.
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_get_smp_processor_id() -> u32 {
+    ExecutionContext::try_load().map_or(0, |ctx| ctx.cpu_id() as u32)
+}
+
 /// BPF helper: Get interrupt latency in nanoseconds.
 ///
 /// This returns the time elapsed from the hardware interrupt entry to the
@@ -339,7 +355,10 @@ pub extern "C" fn bpf_map_update_elem(
             // SAFETY: Verifier ensures valid memory access for value_ptr
             let value = unsafe { core::slice::from_raw_parts(value_ptr, value_size) };
 
-            if manager.map_update(map_id, key, value, flags).is_ok() {
+            if manager
+                .map_update_from_prog(map_id, key, value, flags)
+                .is_ok()
+            {
                 return 0;
             }
         }
@@ -454,3 +473,78 @@ pub extern "C" fn bpf_timeseries_push(
     }
     -1
 }
+
+/// Copy an aggregate of the time-series map `map_id` to `out`.
+///
+/// At most `size` bytes of the aggregate are written; a larger buffer is
+/// left untouched past its end. Returns the number of entries aggregated.
+fn write_timeseries_aggregate(
+    map_id: u32,
+    out: *mut u8,
+    size: u32,
+    aggregate: impl FnOnce(&TimeSeriesMap) -> Option<TimeSeriesAggregate>,
+) -> i64 {
+    if out.is_null() {
+        return -1;
+    }
+    let Some(map) = crate::BPF_MANAGER.get().and_then(|m| m.lock().map(map_id)) else {
+        return -1;
+    };
+    let Some(agg) = map.map().as_timeseries().and_then(aggregate) else {
+        return -1;
+    };
+
+    let bytes = agg.to_bytes();
+    let len = bytes.len().min(size as usize);
+    // SAFETY: The verifier checks that the `size` bytes at `out` lie in the
+    // program's stack frame.
+    unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), out, len) };
+    agg.count as i64
+}
+
+/// BPF helper: Aggregate the last N entries of a time-series map.
+///
+/// # Arguments
+/// * `map_id` - The time-series map ID
+/// * `n` - Number of newest entries to aggregate
+/// * `out` - Buffer for the `TimeSeriesAggregate`
+/// * `size` - Size of the buffer
+///
+/// # Returns
+/// The number of entries aggregated, negative error code on failure.
+///
+/// # Safety
+/// Called from verified BPF programs. The verifier ensures pointers are valid.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_timeseries_query(map_id: u32, n: u64, out: *mut u8, size: u32) -> i64 {
+    write_timeseries_aggregate(map_id, out, size, |ts| ts.aggregate_last_n(n as usize))
+}
+
+/// BPF helper: Aggregate the entries of a time-series map within a window.
+///
+/// # Arguments
+/// * `map_id` - The time-series map ID
+/// * `start_ns` - Start timestamp (inclusive)
+/// * `end_ns` - End timestamp (inclusive)
+/// * `out` - Buffer for the `TimeSeriesAggregate`
+/// * `size` - Size of the buffer
+///
+/// # Returns
+/// The number of entries aggregated, negative error code on failure.
+///
+/// # Safety
+/// Called from verified BPF programs. The verifier ensures pointers are valid.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn bpf_timeseries_query_window(
+    map_id: u32,
+    start_ns: u64,
+    end_ns: u64,
+    out: *mut u8,
+    size: u32,
+) -> i64 {
+    write_timeseries_aggregate(map_id, out, size, |ts| {
+        ts.aggregate_in_window(start_ns, end_ns)
+    })
+}
@@ -0,0 +1,275 @@
+//! Hook execution through the profile's BPF scheduler.
+//!
+//! When a hook fires, every attached program becomes a [`BpfExecRequest`]
+//! and the active policy decides the order: earliest deadline first on the
+//! embedded profile, by priority on the cloud profile. Programs with a
+//! deadline or at least [`INLINE_PRIORITY`] run inline, in the context that
+//! fired the hook. The others are deferred to the bottom half, a kernel task
+//! that runs them once nothing more urgent is ready, with a copy of the
+//! context data.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::iter;
+
+use conquer_once::spin::OnceCell;
+use kernel_bpf::execution::BpfContext;
+#[cfg(feature = "embedded-profile")]
+use kernel_bpf::scheduler::Deadline;
+use kernel_bpf::scheduler::{BpfExecRequest, BpfScheduler, ExecPriority, ProgId, QueuedProgram};
+use spin::Mutex;
+
+use super::{
+    LoadedBpfProgram, ATTACH_TYPE_GPIO, ATTACH_TYPE_IIO, ATTACH_TYPE_PWM,
+    ATTACH_TYPE_SCHED_DEADLINE, ATTACH_TYPE_SYSCALL, ATTACH_TYPE_TIMER,
+};
+use crate::mcore::mtask::process::Process;
+use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::wait::{without_interrupts, WaitQueue};
+use crate::mcore::mtask::task::Task;
+
+/// The lowest priority that runs inline even without a deadline.
+const INLINE_PRIORITY: ExecPriority = ExecPriority::High;
+
+/// Orders the inline runs of a hook. It is drained before the lock is
+/// released, so it never holds the context of a hook that returned.
+static INLINE: OnceCell<Mutex<Inline>> = OnceCell::uninit();
+
+/// The runs waiting for the bottom half.
+static DEFERRED: OnceCell<Mutex<Deferred>> = OnceCell::uninit();
+
+/// Woken whenever a run is deferred.
+static BOTTOM_HALF: WaitQueue = WaitQueue::new();
+
+struct Inline(BpfScheduler);
+
+// SAFETY: The queued contexts point into the data of the hook that submitted
+// them, which waits for the queue to be drained under the lock.
+unsafe impl Send for Inline {}
+
+struct Deferred {
+    scheduler: BpfScheduler,
+    /// The runs in the scheduler, by their submission sequence number.
+    pending: BTreeMap<u64, Pending>,
+}
+
+// SAFETY: The queued contexts only point into the data owned by `pending`.
+unsafe impl Send for Deferred {}
+
+/// What the bottom half needs to know about a deferred run.
+struct Pending {
+    attach_type: u32,
+    fired_ns: u64,
+    /// The copy of the context data, which the queued context points into.
+    _data: Option<Box<[u8]>>,
+}
+
+/// Set up the hook schedulers and start the bottom half.
+pub fn init() {
+    INLINE.init_once(|| Mutex::new(Inline(BpfScheduler::new())));
+    DEFERRED.init_once(|| {
+        Mutex::new(Deferred {
+            scheduler: BpfScheduler::new(),
+            pending: BTreeMap::new(),
+        })
+    });
+
+    let task = Task::create_new(Process::root(), bottom_half, core::ptr::null_mut())
+        .expect("failed to create BPF bottom half task");
+    GlobalTaskQueue::enqueue(Box::pin(task));
+}
+
+/// Run the programs attached to `attach_type` against `ctx`.
+///
+/// Must be called without holding the [`BpfManager`](super::BpfManager)
+/// lock, as helpers may take it.
+pub fn run_hooks(attach_type: u32, ctx: &BpfContext) {
+    let (Some(manager), Some(inline), Some(deferred)) =
+        (crate::BPF_MANAGER.get(), INLINE.get(), DEFERRED.get())
+    else {
+        return;
+    };
+
+    // Clone programs and release the lock BEFORE execution so that BPF
+    // helpers can re-acquire it without deadlocking.
+    let programs = manager.lock().get_hook_programs(attach_type);
+    if programs.is_empty() {
+        return;
+    }
+    let fired_ns = crate::time::get_kernel_time_ns();
+
+    let (urgent, background): (Vec<_>, Vec<_>) = programs
+        .iter()
+        .map(|(_, program)| program)
+        .partition(|program| runs_inline(program));
+
+    if !background.is_empty() {
+        defer(deferred, attach_type, &background, ctx, fired_ns);
+    }
+
+    let ordered: Vec<QueuedProgram> = without_interrupts(|| {
+        let mut inline = inline.lock();
+        #[cfg(feature = "embedded-profile")]
+        inline.0.update_time(fired_ns);
+        for program in &urgent {
+            if let Err(e) = inline.0.submit(request(program, *ctx, fired_ns)) {
+                log::warn!("BPF Hook [id={}] dropped: {}", program.id(), e);
+            }
+        }
+        iter::from_fn(|| inline.0.next()).collect()
+    });
+
+    for queued in ordered {
+        if let Some(program) = urgent.iter().find(|p| p.id() == queued.id.0) {
+            run(attach_type, program, &queued.context, fired_ns);
+        }
+    }
+}
+
+fn runs_inline(program: &LoadedBpfProgram) -> bool {
+    program.deadline_ns().is_some() || program.exec_priority() >= INLINE_PRIORITY
+}
+
+fn request(program: &LoadedBpfProgram, context: BpfContext, fired_ns: u64) -> BpfExecRequest {
+    let request = BpfExecRequest::new(ProgId(program.id()), Arc::clone(&program.program), context)
+        .with_priority(program.exec_priority());
+
+    #[cfg(feature = "embedded-profile")]
+    let request = match program.deadline_ns() {
+        Some(deadline_ns) => request.with_deadline(Deadline::from_now(fired_ns, deadline_ns)),
+        None => request,
+    };
+    #[cfg(not(feature = "embedded-profile"))]
+    let _ = fired_ns;
+
+    request
+}
+
+/// Queue a run of each of `programs` for the bottom half.
+fn defer(
+    deferred: &Mutex<Deferred>,
+    attach_type: u32,
+    programs: &[&Arc<LoadedBpfProgram>],
+    ctx: &BpfContext,
+    fired_ns: u64,
+) {
+    without_interrupts(|| {
+        let mut deferred = deferred.lock();
+        for program in programs {
+            let (context, data) = copy_context(ctx);
+            match deferred
+                .scheduler
+                .submit(request(program, context, fired_ns))
+            {
+                Ok(seq) => {
+                    let pending = Pending {
+                        attach_type,
+                        fired_ns,
+                        _data: data,
+                    };
+                    deferred.pending.insert(seq, pending);
+                }
+                Err(e) => log::warn!("BPF Hook [id={}] dropped: {}", program.id(), e),
+            }
+        }
+    });
+    BOTTOM_HALF.wake_one();
+}
+
+/// A copy of `ctx` that points into its own copy of the data.
+fn copy_context(ctx: &BpfContext) -> (BpfContext, Option<Box<[u8]>>) {
+    let mut context = *ctx;
+    context.data_meta = core::ptr::null();
+    if ctx.data.is_null() {
+        return (context, None);
+    }
+
+    // SAFETY: Hooks build their contexts from a slice that outlives the hook.
+    let data: Box<[u8]> = unsafe {
+        let len = ctx.data_end.offset_from(ctx.data).max(0).cast_unsigned();
+        core::slice::from_raw_parts(ctx.data, len).into()
+    };
+    let range = data.as_ptr_range();
+    context.data = range.start;
+    context.data_end = range.end;
+    (context, Some(data))
+}
+
+extern "C" fn bottom_half(_arg: *mut c_void) {
+    let Some(deferred) = DEFERRED.get() else {
+        return;
+    };
+
+    loop {
+        let (queued, pending) = BOTTOM_HALF.wait_until(|| {
+            without_interrupts(|| {
+                let mut deferred = deferred.lock();
+                #[cfg(feature = "embedded-profile")]
+                deferred
+                    .scheduler
+                    .update_time(crate::time::get_kernel_time_ns());
+                let queued = deferred.scheduler.next()?;
+                let pending = deferred.pending.remove(&queued.submitted_at)?;
+                Some((queued, pending))
+            })
+        });
+
+        // The program may have been unloaded since the hook fired
+        let program = crate::BPF_MANAGER
+            .get()
+            .and_then(|manager| manager.lock().program(queued.id.0));
+        if let Some(program) = program {
+            run(
+                pending.attach_type,
+                &program,
+                &queued.context,
+                pending.fired_ns,
+            );
+        }
+    }
+}
+
+/// Run `program`, counting a deadline miss if it finished too late.
+fn run(attach_type: u32, program: &LoadedBpfProgram, ctx: &BpfContext, fired_ns: u64) {
+    let prog_id = program.id();
+    match program.execute(ctx) {
+        Ok(res) => match attach_type {
+            ATTACH_TYPE_GPIO => log::info!("GPIO BPF Hook [id={}] returned: {}", prog_id, res),
+            ATTACH_TYPE_PWM => log::info!("PWM BPF Hook [id={}] returned: {}", prog_id, res),
+            ATTACH_TYPE_IIO => log::info!("IIO BPF Hook [id={}] returned: {}", prog_id, res),
+            // Log only the calls a tracing program flagged
+            ATTACH_TYPE_SYSCALL if res != 0 => {
+                log::info!("Syscall Trace [id={}] syscall_nr: {}", prog_id, res);
+            }
+            _ => {}
+        },
+        Err(e) => log::error!(
+            "{} BPF Hook [id={}] failed: {:?}",
+            hook_name(attach_type),
+            prog_id,
+            e
+        ),
+    }
+
+    if let Some(deadline_ns) = program.deadline_ns() {
+        let elapsed = crate::time::get_kernel_time_ns().saturating_sub(fired_ns);
+        if elapsed > deadline_ns {
+            program.stats().record_deadline_miss();
+        }
+    }
+}
+
+fn hook_name(attach_type: u32) -> &'static str {
+    match attach_type {
+        ATTACH_TYPE_TIMER => "Timer",
+        ATTACH_TYPE_GPIO => "GPIO",
+        ATTACH_TYPE_PWM => "PWM",
+        ATTACH_TYPE_IIO => "IIO",
+        ATTACH_TYPE_SYSCALL => "Syscall",
+        ATTACH_TYPE_SCHED_DEADLINE => "Deadline",
+        _ => "Unknown",
+    }
+}
@@ -3,6 +3,9 @@
 //! Provides functions to allocate executable memory for BPF JIT compilation.
 //! These functions are exposed via `extern "C"` so they can be linked against
 //! by the `kernel_bpf` crate which does not depend on the kernel directly.
+//!
+//! On x86_64 the memory is W^X: it is mapped writable while the JIT copies
+//! code in, then remapped read-only and executable by `bpf_jit_protect_exec`.
 
 #[cfg(target_arch = "aarch64")]
 pub mod aarch64 {
@@ -11,6 +14,7 @@ pub mod aarch64 {
     use crate::arch::aarch64::mem::{pte_flags, PAGE_SIZE};
     use crate::arch::aarch64::paging::PageTableWalker;
     use crate::arch::aarch64::{mm, phys};
+    use crate::arch::types::{PhysAddr, PhysFrame, Size4KiB};
 
     // Dedicated region for BPF JIT programs: 0xFFFF_FFFF_9000_0000 (256MB)
     // This is below the kernel image and MMIO.
@@ -62,7 +66,7 @@ pub mod aarch64 {
             let page_addr = virt_addr + i * PAGE_SIZE;
 
             // Allocate physical frame
-            let frame = match phys::allocate_frame::<crate::arch::types::Size4KiB>() {
+            let frame = match phys::allocate_frame::<Size4KiB>() {
                 Some(f) => f,
                 None => {
                     log::error!("BPF JIT OOM: physical allocation failed");
@@ -90,35 +94,131 @@ pub mod aarch64 {
 
     /// Free executable memory
     ///
+    /// The pages are unmapped and their frames returned to the physical
+    /// allocator. The virtual range itself is not reused (bump allocator).
+    ///
     /// # Safety
-    /// The pointer must have been allocated by `bpf_jit_alloc_exec`.
+    /// The pointer must have been allocated by `bpf_jit_alloc_exec`, and no
+    /// code in it may be running or run again.
     #[no_mangle]
-    pub unsafe extern "C" fn bpf_jit_free_exec(_ptr: *mut u8, _size: usize) {
-        // Implementation TODO:
-        // In a bump allocator, we can't easily free individual allocations.
-        // For a proof of concept, we just leak the memory.
-        // A proper implementation would use a free list or bitmap.
-        log::warn!("bpf_jit_free_exec: leaking memory (bump allocator implementation)");
+    pub unsafe extern "C" fn bpf_jit_free_exec(ptr: *mut u8, size: usize) {
+        if ptr.is_null() || size == 0 {
+            return;
+        }
+
+        let virt_addr = ptr as usize;
+        if !(BPF_JIT_START..BPF_JIT_END).contains(&virt_addr) {
+            log::error!("bpf_jit_free_exec: {:#x} is not JIT memory", virt_addr);
+            return;
+        }
+
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let l0_phys = mm::kernel_page_table_phys();
+        let mut walker = PageTableWalker::new(l0_phys as *mut _);
+
+        for i in 0..pages {
+            // unmap_page flushes the TLB entry for us
+            match walker.unmap_page(virt_addr + i * PAGE_SIZE) {
+                Ok(phys_addr) => phys::deallocate_frame(PhysFrame::<Size4KiB>::containing_address(
+                    PhysAddr::new(phys_addr as u64),
+                )),
+                Err(e) => log::error!("bpf_jit_free_exec: {}", e),
+            }
+        }
     }
 }
 
-#[cfg(not(target_arch = "aarch64"))]
-pub mod generic {
-    // Stub for non-aarch64 architectures or use existing memory manager
+#[cfg(target_arch = "x86_64")]
+pub mod x86_64 {
+    use kernel_virtual_memory::Segment;
+
+    use crate::arch::types::{PageSize, PageTableFlags, Size4KiB, VirtAddr};
+    use crate::mem::address_space::AddressSpace;
+    use crate::mem::phys::PhysicalMemory;
+    use crate::mem::virt::{VirtualMemoryAllocator, VirtualMemoryHigherHalf};
+    use crate::{U64Ext, UsizeExt};
+
+    fn segment(ptr: *mut u8, size: usize) -> Segment {
+        let page_size = Size4KiB::SIZE.into_usize();
+        Segment::new(
+            VirtAddr::from_ptr(ptr),
+            size.next_multiple_of(page_size).into_u64(),
+        )
+    }
+
+    /// Allocate memory for JIT code.
+    ///
+    /// The pages are mapped writable and non-executable; the JIT copies the
+    /// code in and then calls `bpf_jit_protect_exec`, so a page is never
+    /// writable and executable at the same time.
+    ///
+    /// # Safety
+    /// This function performs raw memory mapping and returns a raw pointer.
+    #[no_mangle]
+    pub unsafe extern "C" fn bpf_jit_alloc_exec(size: usize) -> *mut u8 {
+        if size == 0 {
+            return core::ptr::null_mut();
+        }
+
+        let pages = size.div_ceil(Size4KiB::SIZE.into_usize());
+        let Some(segment) = VirtualMemoryHigherHalf.reserve(pages) else {
+            log::error!("BPF JIT OOM: exhausted virtual space");
+            return core::ptr::null_mut();
+        };
+
+        if let Err(e) = AddressSpace::kernel().map_range::<Size4KiB>(
+            &*segment,
+            PhysicalMemory::allocate_frames_non_contiguous(),
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        ) {
+            log::error!("BPF JIT map failed: {:?}", e);
+            AddressSpace::kernel()
+                .unmap_range::<Size4KiB>(&*segment, PhysicalMemory::deallocate_frame);
+            return core::ptr::null_mut();
+        }
+
+        segment.leak().start.as_mut_ptr::<u8>()
+    }
 
-    /// Allocates executable memory for JIT-compiled BPF code.
+    /// Make JIT memory read-only and executable.
     ///
     /// # Safety
-    /// This function is a stub for non-aarch64 architectures and always returns null.
+    /// The pointer must have been allocated by `bpf_jit_alloc_exec` with the
+    /// same size.
     #[no_mangle]
-    pub unsafe extern "C" fn bpf_jit_alloc_exec(_size: usize) -> *mut u8 {
-        core::ptr::null_mut()
+    pub unsafe extern "C" fn bpf_jit_protect_exec(ptr: *mut u8, size: usize) -> bool {
+        if ptr.is_null() || size == 0 {
+            return false;
+        }
+
+        AddressSpace::kernel()
+            .remap_range::<Size4KiB, _>(&segment(ptr, size), |mut flags: PageTableFlags| {
+                flags.remove(PageTableFlags::WRITABLE);
+                flags.remove(PageTableFlags::NO_EXECUTE);
+                flags
+            })
+            .is_ok()
     }
 
-    /// Frees executable memory previously allocated by `bpf_jit_alloc_exec`.
+    /// Free JIT memory
+    ///
+    /// The pages are unmapped, their frames returned to the physical
+    /// allocator and the virtual range released.
     ///
     /// # Safety
-    /// This function is a stub for non-aarch64 architectures and performs no operation.
+    /// The pointer must have been allocated by `bpf_jit_alloc_exec` with the
+    /// same size, and no code in it may be running or run again.
     #[no_mangle]
-    pub unsafe extern "C" fn bpf_jit_free_exec(_ptr: *mut u8, _size: usize) {}
+    pub unsafe extern "C" fn bpf_jit_free_exec(ptr: *mut u8, size: usize) {
+        if ptr.is_null() || size == 0 {
+            return;
+        }
+
+        let segment = segment(ptr, size);
+        AddressSpace::kernel().unmap_range::<Size4KiB>(&segment, PhysicalMemory::deallocate_frame);
+        // SAFETY: the segment was reserved by `bpf_jit_alloc_exec` and leaked.
+        if !unsafe { VirtualMemoryHigherHalf.release(segment) } {
+            log::error!("bpf_jit_free_exec: {:p} is not JIT memory", ptr);
+        }
+    }
 }
@@ -0,0 +1,132 @@
+//! Kernel-wide trusted keyring for signed BPF programs.
+//!
+//! The keyring lives in [`BpfManager`](super::BpfManager) and is populated
+//! at boot, once the root filesystem is mounted, from [`TRUSTED_KEYS_PATH`].
+//! That file is a concatenation of raw 32-byte Ed25519 public keys (the
+//! `.pub` files written by `rk key generate`).
+//!
+//! Unsigned programs are refused. A cloud profile kernel accepts them if it
+//! was built with the `bpf-allow-unsigned` feature, or if
+//! [`ALLOW_UNSIGNED_PATH`] exists on the root filesystem at boot. The
+//! embedded profile always requires signatures.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use kernel_bpf::signing::{SignatureVerifier, TrustedKey, PUBLIC_KEY_LEN};
+use kernel_vfs::path::AbsolutePath;
+use kernel_vfs::Stat;
+
+use crate::file::vfs;
+use crate::BPF_MANAGER;
+
+/// Location of the trusted public keys on the root filesystem.
+pub const TRUSTED_KEYS_PATH: &str = "/etc/bpf/trusted_keys";
+
+/// If this file exists at boot, a cloud profile kernel accepts unsigned
+/// programs.
+pub const ALLOW_UNSIGNED_PATH: &str = "/etc/bpf/allow_unsigned";
+
+/// Whether unsigned programs are accepted by a freshly built keyring.
+pub const ALLOW_UNSIGNED_BY_DEFAULT: bool = cfg!(all(
+    feature = "cloud-profile",
+    feature = "bpf-allow-unsigned"
+));
+
+/// Create the boot-time keyring before any keys are loaded.
+pub fn new_keyring() -> SignatureVerifier {
+    #[cfg_attr(not(feature = "cloud-profile"), allow(unused_mut))]
+    let mut keyring = SignatureVerifier::new();
+    #[cfg(feature = "cloud-profile")]
+    keyring.set_allow_unsigned(ALLOW_UNSIGNED_BY_DEFAULT);
+    keyring
+}
+
+/// Populate the keyring from the root filesystem.
+///
+/// Must be called after the root filesystem has been mounted.
+pub fn init() {
+    let Some(manager) = BPF_MANAGER.get() else {
+        log::error!("bpf keyring: BPF_MANAGER not initialized");
+        return;
+    };
+
+    // Read everything before taking the manager lock.
+    let keys = read_file(TRUSTED_KEYS_PATH);
+    let allow_unsigned = AbsolutePath::try_new(ALLOW_UNSIGNED_PATH)
+        .is_ok_and(|path| vfs().read().open(path).is_ok());
+
+    let mut mgr = manager.lock();
+    let keyring = mgr.keyring_mut();
+
+    match keys {
+        Some(data) => add_keys(keyring, &data),
+        None => log::warn!("bpf keyring: no trusted keys at {}", TRUSTED_KEYS_PATH),
+    }
+
+    #[cfg(feature = "cloud-profile")]
+    if allow_unsigned {
+        keyring.set_allow_unsigned(true);
+    }
+    #[cfg(not(feature = "cloud-profile"))]
+    if allow_unsigned {
+        log::warn!(
+            "bpf keyring: ignoring {}, the embedded profile requires signatures",
+            ALLOW_UNSIGNED_PATH
+        );
+    }
+
+    if keyring.allows_unsigned() {
+        log::warn!("bpf keyring: unsigned programs are ALLOWED");
+    }
+    log::info!("bpf keyring: {} trusted key(s)", keyring.key_count());
+}
+
+/// Add every complete key in `data` to the keyring.
+fn add_keys(keyring: &mut SignatureVerifier, data: &[u8]) {
+    if !data.len().is_multiple_of(PUBLIC_KEY_LEN) {
+        log::warn!(
+            "bpf keyring: {} has {} trailing bytes, ignoring them",
+            TRUSTED_KEYS_PATH,
+            data.len() % PUBLIC_KEY_LEN
+        );
+    }
+
+    for chunk in data.chunks_exact(PUBLIC_KEY_LEN) {
+        let key = match TrustedKey::from_bytes(chunk) {
+            Ok(key) => key,
+            Err(e) => {
+                log::warn!("bpf keyring: skipping key: {}", e);
+                continue;
+            }
+        };
+
+        match keyring.add_trusted_key(key) {
+            Ok(()) => log::info!("bpf keyring: trusted {:?}", key),
+            Err(e) => {
+                log::warn!("bpf keyring: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    let path = AbsolutePath::try_new(path).ok()?;
+    let node = vfs().read().open(path).ok()?;
+
+    let mut stat = Stat::default();
+    node.stat(&mut stat).ok()?;
+
+    let mut data = vec![0u8; stat.size];
+    let mut offset = 0;
+    while offset < data.len() {
+        match node.read(&mut data[offset..], offset) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => offset += read,
+        }
+    }
+    data.truncate(offset);
+
+    Some(data)
+}
@@ -1,27 +1,653 @@
 pub mod helpers;
+pub mod hooks;
 pub mod jit_memory;
+pub mod keyring;
+pub mod ringbuf;
+pub mod stats;
 
 use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 
+use kernel_abi::{BpfBtfInfo, BpfMapInfo, BpfProgInfo};
 use kernel_bpf::bytecode::insn::BpfInsn;
-use kernel_bpf::bytecode::program::BpfProgram;
-use kernel_bpf::execution::{BpfContext, BpfError, BpfExecutor, Interpreter};
-use kernel_bpf::loader::BpfLoader;
-use kernel_bpf::maps::{ArrayMap, BpfMap, HashMap as BpfHashMap, RingBufMap, TimeSeriesMap};
+use kernel_bpf::bytecode::program::{BpfProgType, BpfProgram};
+use kernel_bpf::execution::{BpfContext, BpfError, Interpreter, TailCallTarget, TailCalls};
+use kernel_bpf::loader::{BpfLoader, Btf, LineInfo, LoadError};
+use kernel_bpf::maps::{
+    ArrayMap, BpfMap, HashMap as BpfHashMap, LruHashMap, MapDef, MapType, PerCpuArrayMap,
+    PerCpuHashMap, ProgArrayMap, RingBufMap, TimeSeriesMap,
+};
 use kernel_bpf::profile::{ActiveProfile, PhysicalProfile};
+use kernel_bpf::scheduler::ExecPriority;
+use kernel_bpf::signing::{SignatureVerifier, SignedProgram, SigningError, SIGNER_ID_LEN};
+use kernel_bpf::verifier::{verify_program, VerifyError};
+
+use self::stats::ProgramStats;
 
 pub const ATTACH_TYPE_TIMER: u32 = 1;
 pub const ATTACH_TYPE_GPIO: u32 = 2;
 pub const ATTACH_TYPE_PWM: u32 = 3;
 pub const ATTACH_TYPE_IIO: u32 = 4;
 pub const ATTACH_TYPE_SYSCALL: u32 = 5;
+pub const ATTACH_TYPE_SCHED_DEADLINE: u32 = 6;
+
+/// Reasons a program can be refused by [`BpfManager`] at load time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BpfLoadError {
+    /// The ELF object could not be parsed.
+    Elf(LoadError),
+    /// The ELF object did not contain any program sections.
+    NoProgram,
+    /// The verifier rejected the program, with the source line of the
+    /// offending instruction when the object carried line info.
+    Verify(VerifyError, Option<LineInfo>),
+    /// The program was refused by the signing policy.
+    Signature(SigningError),
+    /// The program references a map that doesn't exist.
+    UnknownMap(u32),
+    /// A map declared by the object could not be created.
+    MapCreate(BpfError),
+    /// A global data reference points outside an array map's value.
+    InvalidMapValue(u32),
+    /// The `.rodata` contents supplied before load don't fit the object.
+    InvalidRodata,
+    /// Type information passed to `BPF_BTF_LOAD` could not be parsed.
+    Btf(LoadError),
+}
+
+impl fmt::Display for BpfLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Elf(e) => write!(f, "ELF load failed: {}", e),
+            Self::NoProgram => write!(f, "no program found in object"),
+            Self::Verify(e, None) => write!(f, "verification failed: {}", e),
+            Self::Verify(e, Some(line)) => write!(f, "verification failed: {} at {}", e, line),
+            Self::Signature(e) => write!(f, "signature check failed: {}", e),
+            Self::UnknownMap(id) => write!(f, "unknown map {}", id),
+            Self::MapCreate(e) => write!(f, "map creation failed: {}", e),
+            Self::InvalidMapValue(id) => write!(f, "invalid value reference into map {}", id),
+            Self::InvalidRodata => write!(f, ".rodata contents don't match the object"),
+            Self::Btf(e) => write!(f, "BTF load failed: {}", e),
+        }
+    }
+}
+
+/// Native image type for the JIT of the target architecture.
+#[cfg(target_arch = "aarch64")]
+type JitImage = kernel_bpf::execution::Arm64JitImage;
+#[cfg(all(target_arch = "x86_64", feature = "cloud-profile"))]
+type JitImage = kernel_bpf::execution::jit::JitImage;
+
+/// A verified program and, where the JIT is enabled, its native image.
+///
+/// The image is compiled once at load time and freed together with the
+/// program, so hooks never pay for compilation on the hot path.
+///
+/// Programs are shared through `Arc` by file descriptors, attachments and
+/// in-flight executions, and hold the maps they reference, so a map can't
+/// disappear underneath a running program.
+pub struct LoadedBpfProgram {
+    id: u32,
+    /// Shared with the hook scheduler's execution requests.
+    program: Arc<BpfProgram<ActiveProfile>>,
+    maps: Vec<Arc<LoadedBpfMap>>,
+    signer: Option<[u8; SIGNER_ID_LEN]>,
+    btf: Option<Arc<LoadedBpfBtf>>,
+    line_info: Vec<LineInfo>,
+    load_time_ns: u64,
+    unloaded: AtomicBool,
+    stats: ProgramStats,
+    /// The [`ExecPriority`] hooks run the program with, as set on attach.
+    exec_priority: AtomicU8,
+    /// Time after a hook fired that a run has to finish by, 0 if none.
+    deadline_ns: AtomicU64,
+    #[cfg(any(
+        target_arch = "aarch64",
+        all(target_arch = "x86_64", feature = "cloud-profile")
+    ))]
+    jit: Option<JitImage>,
+}
+
+impl LoadedBpfProgram {
+    fn new(
+        id: u32,
+        program: BpfProgram<ActiveProfile>,
+        maps: Vec<Arc<LoadedBpfMap>>,
+        signer: Option<[u8; SIGNER_ID_LEN]>,
+        btf: Option<Arc<LoadedBpfBtf>>,
+        line_info: Vec<LineInfo>,
+    ) -> Self {
+        let load_time_ns = crate::time::get_kernel_time_ns();
+        let program = Arc::new(program);
+
+        #[cfg(any(
+            target_arch = "aarch64",
+            all(target_arch = "x86_64", feature = "cloud-profile")
+        ))]
+        {
+            let jit = if <ActiveProfile as PhysicalProfile>::JIT_ALLOWED {
+                match Self::compile(&program) {
+                    Ok(image) => Some(image),
+                    Err(e) => {
+                        log::warn!("BpfManager: JIT failed ({:?}), using interpreter", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            Self {
+                id,
+                program,
+                maps,
+                signer,
+                btf,
+                line_info,
+                load_time_ns,
+                unloaded: AtomicBool::new(false),
+                stats: ProgramStats::default(),
+                exec_priority: AtomicU8::new(ExecPriority::Normal as u8),
+                deadline_ns: AtomicU64::new(0),
+                jit,
+            }
+        }
+
+        #[cfg(not(any(
+            target_arch = "aarch64",
+            all(target_arch = "x86_64", feature = "cloud-profile")
+        )))]
+        {
+            Self {
+                id,
+                program,
+                maps,
+                signer,
+                btf,
+                line_info,
+                load_time_ns,
+                unloaded: AtomicBool::new(false),
+                stats: ProgramStats::default(),
+                exec_priority: AtomicU8::new(ExecPriority::Normal as u8),
+                deadline_ns: AtomicU64::new(0),
+            }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn compile(
+        program: &BpfProgram<ActiveProfile>,
+    ) -> Result<JitImage, kernel_bpf::execution::jit_aarch64::Arm64JitError> {
+        use kernel_bpf::execution::Arm64JitExecutor;
+        Arm64JitExecutor::<ActiveProfile>::new().load(program)
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "cloud-profile"))]
+    fn compile(
+        program: &BpfProgram<ActiveProfile>,
+    ) -> Result<JitImage, kernel_bpf::execution::jit::JitError> {
+        kernel_bpf::execution::jit::JitExecutor::new().load(program)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn program(&self) -> &BpfProgram<ActiveProfile> {
+        &self.program
+    }
+
+    /// The maps this program references.
+    pub fn maps(&self) -> &[Arc<LoadedBpfMap>] {
+        &self.maps
+    }
+
+    /// Whether the program was unloaded while something still held it.
+    pub fn is_unloaded(&self) -> bool {
+        self.unloaded.load(Ordering::Acquire)
+    }
+
+    /// Whether the program runs from a cached JIT image.
+    pub fn is_jited(&self) -> bool {
+        #[cfg(any(
+            target_arch = "aarch64",
+            all(target_arch = "x86_64", feature = "cloud-profile")
+        ))]
+        {
+            self.jit.is_some()
+        }
+
+        #[cfg(not(any(
+            target_arch = "aarch64",
+            all(target_arch = "x86_64", feature = "cloud-profile")
+        )))]
+        {
+            false
+        }
+    }
+
+    /// The program's runtime counters.
+    pub fn stats(&self) -> &ProgramStats {
+        &self.stats
+    }
+
+    /// The priority hooks run the program with.
+    pub fn exec_priority(&self) -> ExecPriority {
+        match self.exec_priority.load(Ordering::Relaxed) {
+            0 => ExecPriority::Low,
+            2 => ExecPriority::High,
+            3 => ExecPriority::Critical,
+            _ => ExecPriority::Normal,
+        }
+    }
+
+    /// The relative deadline of the program's hook runs, if it has one.
+    pub fn deadline_ns(&self) -> Option<u64> {
+        Some(self.deadline_ns.load(Ordering::Relaxed)).filter(|&ns| ns != 0)
+    }
+
+    /// Set how hooks schedule the program. A `deadline_ns` of 0 removes the
+    /// deadline.
+    pub fn set_exec_params(&self, priority: ExecPriority, deadline_ns: u64) {
+        self.exec_priority.store(priority as u8, Ordering::Relaxed);
+        self.deadline_ns.store(deadline_ns, Ordering::Relaxed);
+    }
+
+    /// The key ID of the signer, if the program was loaded signed.
+    pub fn signer(&self) -> Option<&[u8; SIGNER_ID_LEN]> {
+        self.signer.as_ref()
+    }
+
+    /// The type information of the object the program was loaded from.
+    pub fn btf(&self) -> Option<&Arc<LoadedBpfBtf>> {
+        self.btf.as_ref()
+    }
+
+    /// Source line info, sorted by instruction index.
+    pub fn line_info(&self) -> &[LineInfo] {
+        &self.line_info
+    }
+
+    /// Information reported by `BPF_OBJ_GET_INFO_BY_FD`.
+    ///
+    /// Attach points are tracked by the manager, see
+    /// [`BpfManager::prog_info`].
+    pub fn info(&self) -> BpfProgInfo {
+        let mut info = BpfProgInfo {
+            id: self.id,
+            jited: u32::from(self.is_jited()),
+            prog_type: self.program.prog_type() as u32,
+            insn_cnt: self.program.insn_count() as u32,
+            nr_map_ids: self.maps.len() as u32,
+            signed: u32::from(self.signer.is_some()),
+            load_time_ns: self.load_time_ns,
+            signer_id: self.signer.unwrap_or_default(),
+            btf_id: self.btf.as_ref().map_or(0, |btf| btf.id()),
+            nr_line_info: self.line_info.len() as u32,
+            ..Default::default()
+        };
+        self.stats.fill_info(&mut info);
+        info
+    }
+
+    /// Run the program, recording the run in its stats if they are enabled.
+    pub fn execute(&self, ctx: &BpfContext) -> Result<u64, BpfError> {
+        if !stats::enabled() {
+            return self.run(ctx);
+        }
+
+        let start = crate::time::get_kernel_time_ns();
+        let result = self.run(ctx);
+        let elapsed = crate::time::get_kernel_time_ns().saturating_sub(start);
+        self.stats.record(elapsed, &result);
+        result
+    }
 
+    /// Run the program, using the cached JIT image if there is one.
+    ///
+    /// The x86_64 JIT doesn't compile tail calls, so programs that make them
+    /// are interpreted there.
+    fn run(&self, ctx: &BpfContext) -> Result<u64, BpfError> {
+        let mut tail_calls = TailCalls::new(&resolve_tail_call);
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if let Some(image) = &self.jit {
+                return image.call_with_tail_calls(ctx, &mut tail_calls);
+            }
+        }
+
+        #[cfg(all(target_arch = "x86_64", feature = "cloud-profile"))]
+        {
+            if let Some(image) = &self.jit {
+                return image.call(ctx);
+            }
+        }
+
+        let interpreter = Interpreter::<ActiveProfile>::new();
+        interpreter.execute_with_tail_calls(&self.program, ctx, &mut tail_calls)
+    }
+
+    /// Run the program `repeat` times against `data`, as `BPF_PROG_TEST_RUN`
+    /// does.
+    ///
+    /// Every run sees the data left behind by the previous one. A `repeat`
+    /// of 0 is treated as 1. Test runs don't count towards the program's
+    /// stats.
+    pub fn test_run(&self, data: &mut [u8], repeat: u32) -> Result<BpfTestRun, BpfError> {
+        let repeat = repeat.max(1);
+        let ctx = BpfContext::from_slice(data);
+
+        let start = crate::time::get_kernel_time_ns();
+        let mut retval = 0;
+        for _ in 0..repeat {
+            retval = self.run(&ctx)?;
+        }
+        let elapsed = crate::time::get_kernel_time_ns().saturating_sub(start);
+
+        Ok(BpfTestRun {
+            retval,
+            duration_ns: elapsed / u64::from(repeat),
+        })
+    }
+}
+
+impl TailCallTarget<ActiveProfile> for LoadedBpfProgram {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn program(&self) -> &BpfProgram<ActiveProfile> {
+        &self.program
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn jit_entry(&self) -> Option<*const u8> {
+        self.jit.as_ref().map(JitImage::entry)
+    }
+}
+
+/// The program in slot `index` of the program array `map_id`.
+fn resolve_tail_call(map_id: u32, index: u32) -> Option<Arc<dyn TailCallTarget<ActiveProfile>>> {
+    let map = crate::BPF_MANAGER.get()?.lock().map(map_id)?;
+    map.map().as_prog_array()?.get(index)
+}
+
+/// Outcome of [`LoadedBpfProgram::test_run`].
+#[derive(Debug, Clone, Copy)]
+pub struct BpfTestRun {
+    /// R0 of the last run.
+    pub retval: u64,
+    /// Average run time in nanoseconds.
+    pub duration_ns: u64,
+}
+
+/// A map created through [`BpfManager`].
+///
+/// Like programs, maps are shared through `Arc` and freed once the last
+/// file descriptor or program using them lets go.
+pub struct LoadedBpfMap {
+    id: u32,
+    map: Box<dyn BpfMap<ActiveProfile>>,
+    btf: Option<MapBtf>,
+    deleted: AtomicBool,
+    frozen: AtomicBool,
+}
+
+/// The BTF types describing a map's key and value.
+struct MapBtf {
+    btf: Arc<LoadedBpfBtf>,
+    key_type_id: u32,
+    value_type_id: u32,
+}
+
+impl LoadedBpfMap {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn map(&self) -> &dyn BpfMap<ActiveProfile> {
+        self.map.as_ref()
+    }
+
+    /// Whether the map was deleted while something still held it.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.load(Ordering::Acquire)
+    }
+
+    /// The type information describing the map's key and value.
+    pub fn btf(&self) -> Option<&Arc<LoadedBpfBtf>> {
+        self.btf.as_ref().map(|btf| &btf.btf)
+    }
+
+    /// Make the map read-only for userspace.
+    ///
+    /// Programs can still write to a frozen map. Returns `false` if the map
+    /// was already frozen.
+    pub fn freeze(&self) -> bool {
+        !self.frozen.swap(true, Ordering::AcqRel)
+    }
+
+    /// Whether the map was frozen (`BPF_MAP_FREEZE`, or `.rodata`).
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Acquire)
+    }
+
+    /// Information reported by `BPF_OBJ_GET_INFO_BY_FD`.
+    pub fn info(&self) -> BpfMapInfo {
+        let def = self.map.def();
+        let mut info = BpfMapInfo {
+            id: self.id,
+            map_type: def.map_type as u32,
+            key_size: def.key_size,
+            value_size: def.value_size,
+            max_entries: def.max_entries,
+            ..Default::default()
+        };
+        if let Some(btf) = &self.btf {
+            info.btf_id = btf.btf.id();
+            info.btf_key_type_id = btf.key_type_id;
+            info.btf_value_type_id = btf.value_type_id;
+        }
+        info
+    }
+}
+
+/// Type information loaded with `BPF_BTF_LOAD`, or from the `.BTF` section
+/// of an ELF object.
+///
+/// Programs and maps loaded from the object hold it, so userspace can find
+/// the types of map values and ring buffer records by ID.
+pub struct LoadedBpfBtf {
+    id: u32,
+    btf: Btf,
+}
+
+impl LoadedBpfBtf {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn btf(&self) -> &Btf {
+        &self.btf
+    }
+
+    /// Information reported by `BPF_OBJ_GET_INFO_BY_FD`.
+    pub fn info(&self) -> BpfBtfInfo {
+        BpfBtfInfo {
+            btf: 0,
+            btf_size: self.btf.raw().len() as u32,
+            id: self.id,
+        }
+    }
+}
+
+/// Reasons [`BpfManager::unload_program`] and [`BpfManager::delete_map`]
+/// can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpfUnloadError {
+    /// No live object has this ID.
+    NotFound,
+    /// The map is still referenced by a loaded program.
+    InUse,
+}
+
+impl fmt::Display for BpfUnloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such object"),
+            Self::InUse => write!(f, "map is used by a loaded program"),
+        }
+    }
+}
+
+/// Pick the next free ID from `next`, skipping IDs of live objects.
+///
+/// The table only holds weak references, so an ID stays taken for as long
+/// as anything (a file descriptor, an attachment, a program) still holds
+/// the object, even after it was unloaded.
+fn alloc_id<T>(table: &mut BTreeMap<u32, Weak<T>>, next: &mut u32) -> u32 {
+    table.retain(|_, object| object.strong_count() > 0);
+    loop {
+        let id = *next;
+        *next = next.wrapping_add(1).max(1);
+        if !table.contains_key(&id) {
+            return id;
+        }
+    }
+}
+
+/// Replace the `BPF_PSEUDO_MAP_VALUE` load at `idx` with the address of
+/// the referenced byte in `map`.
+///
+/// Only single-entry array maps (global data) can be referenced this way:
+/// their value never moves, and the program holds the map, so the address
+/// stays valid for as long as the program is loaded. `src_reg` is kept so
+/// the verifier still types the register as a map value pointer.
+fn resolve_map_value(
+    insns: &mut [BpfInsn],
+    idx: usize,
+    map: &LoadedBpfMap,
+) -> Result<(), BpfLoadError> {
+    let def = map.map().def();
+    let offset = insns.get(idx + 1).map_or(u32::MAX, |next| next.imm as u32);
+    if def.map_type != MapType::Array || def.max_entries != 1 || offset >= def.value_size {
+        return Err(BpfLoadError::InvalidMapValue(map.id()));
+    }
+    // SAFETY: array values are allocated when the map is created and never
+    // move; the program keeps the map alive.
+    let value = unsafe { map.map().lookup_ptr(&0u32.to_ne_bytes()) }
+        .ok_or(BpfLoadError::InvalidMapValue(map.id()))?;
+    let addr = value as u64 + offset as u64;
+    insns[idx].imm = addr as u32 as i32;
+    insns[idx + 1].imm = (addr >> 32) as u32 as i32;
+    Ok(())
+}
+
+/// Build a map of the given `BPF_MAP_TYPE_*`.
+fn new_map(
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+) -> Result<Box<dyn BpfMap<ActiveProfile>>, BpfError> {
+    let map: Box<dyn BpfMap<ActiveProfile>> = match map_type {
+        1 => {
+            // Hash map
+            Box::new(
+                BpfHashMap::<ActiveProfile>::with_sizes(key_size, value_size, max_entries)
+                    .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        2 => {
+            // Array map
+            Box::new(
+                ArrayMap::<ActiveProfile>::with_entries(value_size, max_entries)
+                    .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        5 => {
+            // Per-CPU hash map
+            Box::new(
+                PerCpuHashMap::<ActiveProfile>::with_sizes(
+                    key_size,
+                    value_size,
+                    max_entries,
+                    crate::mcore::cpu_count(),
+                )
+                .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        6 => {
+            // Per-CPU array map
+            Box::new(
+                PerCpuArrayMap::<ActiveProfile>::with_entries(
+                    value_size,
+                    max_entries,
+                    crate::mcore::cpu_count(),
+                )
+                .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        3 => {
+            // Program array
+            Box::new(
+                ProgArrayMap::<ActiveProfile>::new(MapDef::new(
+                    MapType::ProgArray,
+                    key_size,
+                    value_size,
+                    max_entries,
+                ))
+                .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        9 => {
+            // LRU hash map
+            Box::new(
+                LruHashMap::<ActiveProfile>::with_sizes(key_size, value_size, max_entries)
+                    .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        27 => {
+            // Ring buffer map - max_entries is the buffer size (must be power of 2)
+            let buffer = RingBufMap::<ActiveProfile>::new(max_entries as usize)
+                .map_err(|_| BpfError::OutOfMemory)?;
+            buffer.set_wakeup_hook(|| ringbuf::RINGBUF_READERS.wake_all());
+            Box::new(buffer)
+        }
+        100 => {
+            // Time-series map
+            Box::new(
+                TimeSeriesMap::<ActiveProfile>::new(value_size, max_entries)
+                    .map_err(|_| BpfError::OutOfMemory)?,
+            )
+        }
+        _ => {
+            log::warn!("Unsupported map type: {}", map_type);
+            return Err(BpfError::InvalidInstruction);
+        }
+    };
+    Ok(map)
+}
+
+/// Registry of loaded programs and maps.
+///
+/// Programs and maps are named by kernel-wide IDs, which is what programs
+/// pass to map helpers and what hooks report. The manager does not own the
+/// objects: file descriptors, attachments and programs (for their maps)
+/// hold `Arc`s, and an object is freed when the last of them is dropped.
 pub struct BpfManager {
-    programs: Vec<BpfProgram<ActiveProfile>>,
-    attachments: BTreeMap<u32, Vec<u32>>,
-    maps: Vec<Box<dyn BpfMap<ActiveProfile>>>,
+    programs: BTreeMap<u32, Weak<LoadedBpfProgram>>,
+    attachments: BTreeMap<u32, Vec<Arc<LoadedBpfProgram>>>,
+    maps: BTreeMap<u32, Weak<LoadedBpfMap>>,
+    btfs: BTreeMap<u32, Weak<LoadedBpfBtf>>,
+    next_prog_id: u32,
+    next_map_id: u32,
+    next_btf_id: u32,
+    keyring: SignatureVerifier,
 }
 
 impl Default for BpfManager {
@@ -33,73 +659,340 @@ impl Default for BpfManager {
 impl BpfManager {
     pub fn new() -> Self {
         Self {
-            programs: Vec::new(),
+            programs: BTreeMap::new(),
             attachments: BTreeMap::new(),
-            maps: Vec::new(),
+            maps: BTreeMap::new(),
+            btfs: BTreeMap::new(),
+            next_prog_id: 1,
+            next_map_id: 1,
+            next_btf_id: 1,
+            keyring: keyring::new_keyring(),
         }
     }
 
-    pub fn load_program(&mut self, elf_bytes: &[u8]) -> Result<u32, BpfError> {
-        let mut loader = BpfLoader::<ActiveProfile>::new();
-        let obj = loader.load(elf_bytes).map_err(|_| BpfError::NotLoaded)?;
+    /// The kernel-wide trusted keyring.
+    pub fn keyring(&self) -> &SignatureVerifier {
+        &self.keyring
+    }
 
-        if let Some(loaded_prog) = obj.programs().first() {
-            let bpf_prog = BpfProgram::new(
-                loaded_prog.prog_type(),
-                loaded_prog.insns().to_vec(),
-                0, // TODO: Calculate stack usage via Verifier
-            )
-            .map_err(|_| BpfError::InvalidInstruction)?;
+    pub fn keyring_mut(&mut self) -> &mut SignatureVerifier {
+        &mut self.keyring
+    }
+
+    /// Look up a loaded program by ID.
+    pub fn program(&self, prog_id: u32) -> Option<Arc<LoadedBpfProgram>> {
+        self.programs
+            .get(&prog_id)?
+            .upgrade()
+            .filter(|program| !program.is_unloaded())
+    }
+
+    /// Look up a map by ID.
+    pub fn map(&self, map_id: u32) -> Option<Arc<LoadedBpfMap>> {
+        self.maps
+            .get(&map_id)?
+            .upgrade()
+            .filter(|map| !map.is_deleted())
+    }
+
+    /// Look up type information by ID.
+    pub fn btf(&self, btf_id: u32) -> Option<Arc<LoadedBpfBtf>> {
+        self.btfs.get(&btf_id)?.upgrade()
+    }
+
+    /// The lowest ID above `start_id` of a program that is still loaded.
+    pub fn next_prog_id(&self, start_id: u32) -> Option<u32> {
+        self.programs
+            .range(start_id.checked_add(1)?..)
+            .map(|(&id, _)| id)
+            .find(|&id| self.program(id).is_some())
+    }
 
-            let id = self.programs.len() as u32;
-            self.programs.push(bpf_prog);
-            Ok(id)
-        } else {
-            Err(BpfError::NotLoaded)
+    /// The lowest ID above `start_id` of a map that still exists.
+    pub fn next_map_id(&self, start_id: u32) -> Option<u32> {
+        self.maps
+            .range(start_id.checked_add(1)?..)
+            .map(|(&id, _)| id)
+            .find(|&id| self.map(id).is_some())
+    }
+
+    /// The lowest ID above `start_id` of type information still in use.
+    pub fn next_btf_id(&self, start_id: u32) -> Option<u32> {
+        self.btfs
+            .range(start_id.checked_add(1)?..)
+            .map(|(&id, _)| id)
+            .find(|&id| self.btf(id).is_some())
+    }
+
+    /// Information about `program`, including the hooks it is attached to.
+    pub fn prog_info(&self, program: &LoadedBpfProgram) -> BpfProgInfo {
+        let mut info = program.info();
+        for (&attach_type, list) in &self.attachments {
+            if attach_type < u64::BITS && list.iter().any(|p| p.id() == program.id()) {
+                info.attach_types |= 1 << attach_type;
+            }
         }
+        info
     }
 
-    pub fn load_raw_program(&mut self, insns: Vec<BpfInsn>) -> Result<u32, BpfError> {
-        let bpf_prog =
-            BpfProgram::new(kernel_bpf::bytecode::program::BpfProgType::Unspec, insns, 0)
-                .map_err(|_| BpfError::InvalidInstruction)?;
+    /// Load a raw `.BTF` blob.
+    ///
+    /// Like maps, type information lives for as long as a file descriptor,
+    /// program or map holds it.
+    pub fn load_btf(&mut self, data: &[u8]) -> Result<Arc<LoadedBpfBtf>, BpfLoadError> {
+        let btf = Btf::parse(data).map_err(BpfLoadError::Btf)?;
+        Ok(self.register_btf(btf))
+    }
 
-        let id = self.programs.len() as u32;
-        self.programs.push(bpf_prog);
+    fn register_btf(&mut self, btf: Btf) -> Arc<LoadedBpfBtf> {
+        let id = alloc_id(&mut self.btfs, &mut self.next_btf_id);
+        let entry = Arc::new(LoadedBpfBtf { id, btf });
+        self.btfs.insert(id, Arc::downgrade(&entry));
         log::info!(
-            "BpfManager: Loaded raw program. Assigned id={}. Total programs={}",
+            "BpfManager: Loaded BTF id={} ({} types)",
             id,
+            entry.btf().type_count()
+        );
+        entry
+    }
+
+    /// Load an unsigned ELF object, subject to the signing policy.
+    ///
+    /// `rodata`, if given, replaces the initial contents of the object's
+    /// `.rodata` section, so configuration constants can be set before the
+    /// program is verified. It must have the same size as the section.
+    pub fn load_program(
+        &mut self,
+        elf_bytes: &[u8],
+        rodata: Option<&[u8]>,
+    ) -> Result<Arc<LoadedBpfProgram>, BpfLoadError> {
+        self.keyring
+            .check_unsigned()
+            .map_err(BpfLoadError::Signature)?;
+        self.load_elf(elf_bytes, rodata, None)
+    }
+
+    /// Load a signed (`.rbpf`) ELF object.
+    ///
+    /// The signature must verify against a key in the trusted keyring.
+    pub fn load_signed_program(
+        &mut self,
+        signed_bytes: &[u8],
+    ) -> Result<Arc<LoadedBpfProgram>, BpfLoadError> {
+        let signed = SignedProgram::from_bytes(signed_bytes).map_err(BpfLoadError::Signature)?;
+        let elf_bytes = self
+            .keyring
+            .verify_and_extract(&signed)
+            .map_err(BpfLoadError::Signature)?;
+
+        log::info!(
+            "BpfManager: signature verified (signer={:02x?})",
+            signed.signer_id()
+        );
+        self.load_elf(elf_bytes, None, Some(*signed.signer_id()))
+    }
+
+    /// Load the first program of an ELF object.
+    ///
+    /// The object's maps are created here and owned by the program; map
+    /// references in the code are rewritten from map indices to map IDs.
+    /// Global data maps are filled with their section contents, `.rodata`
+    /// is frozen, and references into them become addresses. The object's
+    /// BTF is registered and shared by the program and its maps.
+    fn load_elf(
+        &mut self,
+        elf_bytes: &[u8],
+        rodata: Option<&[u8]>,
+        signer: Option<[u8; SIGNER_ID_LEN]>,
+    ) -> Result<Arc<LoadedBpfProgram>, BpfLoadError> {
+        let mut loader = BpfLoader::<ActiveProfile>::new();
+        let mut obj = loader.load(elf_bytes).map_err(BpfLoadError::Elf)?;
+
+        if let Some(rodata) = rodata {
+            let data = obj
+                .map_mut(".rodata")
+                .map(|map| map.data_mut())
+                .filter(|data| data.len() == rodata.len())
+                .ok_or(BpfLoadError::InvalidRodata)?;
+            data.copy_from_slice(rodata);
+        }
+
+        let loaded_prog = obj.programs().first().ok_or(BpfLoadError::NoProgram)?;
+        let btf = obj.btf().map(|btf| self.register_btf(btf.clone()));
+
+        let mut maps = Vec::with_capacity(obj.maps().len());
+        for loaded_map in obj.maps() {
+            let def = loaded_map.def();
+            let (key_type_id, value_type_id) = loaded_map.btf_type_ids();
+            let map_btf = btf
+                .as_ref()
+                .filter(|_| key_type_id != 0 || value_type_id != 0)
+                .map(|btf| MapBtf {
+                    btf: btf.clone(),
+                    key_type_id,
+                    value_type_id,
+                });
+            let map = self
+                .insert_map(
+                    def.map_type as u32,
+                    def.key_size,
+                    def.value_size,
+                    def.max_entries,
+                    map_btf,
+                )
+                .map_err(BpfLoadError::MapCreate)?;
+            if loaded_map.is_global_data() {
+                map.map()
+                    .update(&0u32.to_ne_bytes(), loaded_map.data(), 0)
+                    .map_err(|_| BpfLoadError::MapCreate(BpfError::OutOfMemory))?;
+            }
+            if loaded_map.is_frozen() {
+                map.freeze();
+            }
+            maps.push(map);
+        }
+
+        let mut insns = loaded_prog.insns().to_vec();
+        for idx in 0..insns.len() {
+            let insn = insns[idx];
+            if !insn.is_map_load() && !insn.is_map_value_load() {
+                continue;
+            }
+            let map = maps
+                .get(insn.imm as usize)
+                .ok_or(BpfLoadError::UnknownMap(insn.imm as u32))?;
+            if insn.is_map_value_load() {
+                resolve_map_value(&mut insns, idx, map)?;
+            } else {
+                insns[idx].imm = map.id() as i32;
+            }
+        }
+
+        let bpf_prog = verify_program(loaded_prog.prog_type(), &insns).map_err(|e| {
+            let line = e.insn_idx().and_then(|idx| loaded_prog.source_line(idx));
+            BpfLoadError::Verify(e, line.cloned())
+        })?;
+
+        let line_info = loaded_prog.line_info().to_vec();
+        let entry = self.register_program(bpf_prog, maps, signer, btf, line_info);
+        log::info!(
+            "BpfManager: Loaded ELF program '{}'. Assigned id={} stack_size={} jited={}",
+            loaded_prog.name(),
+            entry.id(),
+            entry.program().stack_size(),
+            entry.is_jited()
+        );
+        Ok(entry)
+    }
+
+    /// Load raw unsigned instructions, subject to the signing policy.
+    ///
+    /// Map references (`BPF_PSEUDO_MAP_FD` and `BPF_PSEUDO_MAP_VALUE` wide
+    /// loads) must already carry map IDs; the program keeps every map it
+    /// references alive.
+    pub fn load_raw_program(
+        &mut self,
+        mut insns: Vec<BpfInsn>,
+    ) -> Result<Arc<LoadedBpfProgram>, BpfLoadError> {
+        self.keyring
+            .check_unsigned()
+            .map_err(BpfLoadError::Signature)?;
+
+        let mut maps: Vec<Arc<LoadedBpfMap>> = Vec::new();
+        for idx in 0..insns.len() {
+            let insn = insns[idx];
+            if !insn.is_map_load() && !insn.is_map_value_load() {
+                continue;
+            }
+            let map_id = insn.imm as u32;
+            let map = match maps.iter().find(|map| map.id() == map_id) {
+                Some(map) => map.clone(),
+                None => {
+                    let map = self.map(map_id).ok_or(BpfLoadError::UnknownMap(map_id))?;
+                    maps.push(map.clone());
+                    map
+                }
+            };
+            if insn.is_map_value_load() {
+                resolve_map_value(&mut insns, idx, &map)?;
+            }
+        }
+
+        let bpf_prog = verify_program(BpfProgType::Unspec, &insns)
+            .map_err(|e| BpfLoadError::Verify(e, None))?;
+
+        let entry = self.register_program(bpf_prog, maps, None, None, Vec::new());
+        log::info!(
+            "BpfManager: Loaded raw program. Assigned id={} stack_size={} jited={}. Total programs={}",
+            entry.id(),
+            entry.program().stack_size(),
+            entry.is_jited(),
             self.programs.len()
         );
-        Ok(id)
+        Ok(entry)
+    }
+
+    fn register_program(
+        &mut self,
+        program: BpfProgram<ActiveProfile>,
+        maps: Vec<Arc<LoadedBpfMap>>,
+        signer: Option<[u8; SIGNER_ID_LEN]>,
+        btf: Option<Arc<LoadedBpfBtf>>,
+        line_info: Vec<LineInfo>,
+    ) -> Arc<LoadedBpfProgram> {
+        let id = alloc_id(&mut self.programs, &mut self.next_prog_id);
+        let entry = Arc::new(LoadedBpfProgram::new(
+            id, program, maps, signer, btf, line_info,
+        ));
+        self.programs.insert(id, Arc::downgrade(&entry));
+        entry
+    }
+
+    /// Unload a program.
+    ///
+    /// The program is detached from every hook and can no longer be found
+    /// by ID. Its memory (and its references to maps) is released once the
+    /// remaining file descriptors are closed.
+    pub fn unload_program(&mut self, prog_id: u32) -> Result<(), BpfUnloadError> {
+        let program = self.program(prog_id).ok_or(BpfUnloadError::NotFound)?;
+        program.unloaded.store(true, Ordering::Release);
+
+        for list in self.attachments.values_mut() {
+            list.retain(|attached| attached.id() != prog_id);
+        }
+
+        log::info!("BpfManager: Unloaded program id={}", prog_id);
+        Ok(())
     }
 
     pub fn attach(&mut self, attach_type: u32, prog_id: u32) -> Result<(), BpfError> {
         log::info!(
-            "BpfManager: Attaching prog_id={} to type={}. Total programs={}",
+            "BpfManager: Attaching prog_id={} to type={}",
             prog_id,
-            attach_type,
-            self.programs.len()
+            attach_type
         );
-        if prog_id as usize >= self.programs.len() {
-            log::error!(
-                "BpfManager: Attach failed. prog_id={} >= programs.len()={}",
-                prog_id,
-                self.programs.len()
-            );
+        let Some(program) = self.program(prog_id) else {
+            log::error!("BpfManager: Attach failed. No program with id={}", prog_id);
             return Err(BpfError::NotLoaded);
-        }
+        };
 
         let list = self.attachments.entry(attach_type).or_default();
-        if !list.contains(&prog_id) {
-            list.push(prog_id);
+        if !list.iter().any(|attached| attached.id() == prog_id) {
+            list.push(program);
         }
         Ok(())
     }
 
+    /// Whether the program is attached to any hook.
+    pub fn is_attached(&self, prog_id: u32) -> bool {
+        self.attachments
+            .values()
+            .any(|list| list.iter().any(|attached| attached.id() == prog_id))
+    }
+
     pub fn detach(&mut self, attach_type: u32, prog_id: u32) -> Result<(), BpfError> {
         if let Some(list) = self.attachments.get_mut(&attach_type) {
-            if let Some(pos) = list.iter().position(|&id| id == prog_id) {
+            if let Some(pos) = list.iter().position(|attached| attached.id() == prog_id) {
                 list.remove(pos);
                 return Ok(());
             }
@@ -108,81 +1001,27 @@ impl BpfManager {
     }
 
     pub fn execute(&self, program_id: u32, ctx: &BpfContext) -> Result<u64, BpfError> {
-        let program = self
-            .programs
-            .get(program_id as usize)
-            .ok_or(BpfError::NotLoaded)?;
-
-        Self::execute_program(program, ctx)
-    }
+        let program = self.program(program_id).ok_or(BpfError::NotLoaded)?;
 
-    /// Execute a BPF program directly (without needing &self).
-    ///
-    /// This is useful when the caller has already cloned the program
-    /// and released the BpfManager lock, allowing BPF helpers to
-    /// re-acquire the lock for map operations.
-    pub fn execute_program(
-        program: &BpfProgram<ActiveProfile>,
-        ctx: &BpfContext,
-    ) -> Result<u64, BpfError> {
-        #[cfg(target_arch = "aarch64")]
-        {
-            if !<ActiveProfile as PhysicalProfile>::JIT_ALLOWED {
-                let interpreter = Interpreter::<ActiveProfile>::new();
-                return interpreter.execute(program, ctx);
-            }
-
-            use kernel_bpf::execution::Arm64JitExecutor;
-            let executor = Arm64JitExecutor::<ActiveProfile>::new();
-            executor.execute(program, ctx)
-        }
-
-        #[cfg(not(target_arch = "aarch64"))]
-        {
-            let interpreter = Interpreter::<ActiveProfile>::new();
-            interpreter.execute(program, ctx)
-        }
+        program.execute(ctx)
     }
 
-    /// Collect cloned programs for a given attach type.
+    /// Collect the programs attached to a given attach type.
     ///
-    /// Returns a Vec of (prog_id, cloned_program) pairs. This allows callers
-    /// to release the BpfManager lock before executing programs, preventing
-    /// deadlocks when BPF helpers (like bpf_ringbuf_output) need to re-acquire
-    /// the lock to access maps.
-    pub fn get_hook_programs(&self, attach_type: u32) -> Vec<(u32, BpfProgram<ActiveProfile>)> {
-        let mut result = Vec::new();
-        if let Some(progs) = self.attachments.get(&attach_type) {
-            for &prog_id in progs {
-                if let Some(program) = self.programs.get(prog_id as usize) {
-                    result.push((prog_id, program.clone()));
-                }
-            }
-        }
-        result
-    }
-
-    pub fn execute_hooks(&self, attach_type: u32, ctx: &BpfContext) {
-        if let Some(progs) = self.attachments.get(&attach_type) {
-            for prog_id in progs {
-                match self.execute(*prog_id, ctx) {
-                    Ok(res) => {
-                        if attach_type == ATTACH_TYPE_IIO {
-                            log::info!("IIO BPF Hook [id={}] returned: {}", prog_id, res);
-                        } else if attach_type == ATTACH_TYPE_PWM {
-                            log::info!("PWM BPF Hook [id={}] returned: {}", prog_id, res);
-                        } else if attach_type == ATTACH_TYPE_SYSCALL {
-                            // Log only interesting syscalls or just debug info
-                            // For demo purposes, we log everything if it returns non-zero
-                            if res != 0 {
-                                log::info!("Syscall Trace [id={}] syscall_nr: {}", prog_id, res);
-                            }
-                        }
-                    }
-                    Err(e) => log::error!("BPF Hook [id={}] failed: {:?}", prog_id, e),
-                }
-            }
-        }
+    /// Returns a Vec of (prog_id, program) pairs. The programs are shared, so
+    /// this is cheap, and it allows callers to release the BpfManager lock
+    /// before executing programs, preventing deadlocks when BPF helpers (like
+    /// bpf_ringbuf_output) need to re-acquire the lock to access maps.
+    pub fn get_hook_programs(&self, attach_type: u32) -> Vec<(u32, Arc<LoadedBpfProgram>)> {
+        self.attachments
+            .get(&attach_type)
+            .map(|progs| {
+                progs
+                    .iter()
+                    .map(|program| (program.id(), program.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     // --- Map operations ---
@@ -193,44 +1032,29 @@ impl BpfManager {
         key_size: u32,
         value_size: u32,
         max_entries: u32,
-    ) -> Result<u32, BpfError> {
-        let map: Box<dyn BpfMap<ActiveProfile>> = match map_type {
-            1 => {
-                // Hash map
-                Box::new(
-                    BpfHashMap::<ActiveProfile>::with_sizes(key_size, value_size, max_entries)
-                        .map_err(|_| BpfError::OutOfMemory)?,
-                )
-            }
-            2 => {
-                // Array map
-                Box::new(
-                    ArrayMap::<ActiveProfile>::with_entries(value_size, max_entries)
-                        .map_err(|_| BpfError::OutOfMemory)?,
-                )
-            }
-            27 => {
-                // Ring buffer map - max_entries is the buffer size (must be power of 2)
-                Box::new(
-                    RingBufMap::<ActiveProfile>::new(max_entries as usize)
-                        .map_err(|_| BpfError::OutOfMemory)?,
-                )
-            }
-            100 => {
-                // Time-series map
-                Box::new(
-                    TimeSeriesMap::<ActiveProfile>::new(value_size, max_entries)
-                        .map_err(|_| BpfError::OutOfMemory)?,
-                )
-            }
-            _ => {
-                log::warn!("Unsupported map type: {}", map_type);
-                return Err(BpfError::InvalidInstruction);
-            }
-        };
+    ) -> Result<Arc<LoadedBpfMap>, BpfError> {
+        self.insert_map(map_type, key_size, value_size, max_entries, None)
+    }
 
-        let id = self.maps.len() as u32;
-        self.maps.push(map);
+    fn insert_map(
+        &mut self,
+        map_type: u32,
+        key_size: u32,
+        value_size: u32,
+        max_entries: u32,
+        btf: Option<MapBtf>,
+    ) -> Result<Arc<LoadedBpfMap>, BpfError> {
+        let map = new_map(map_type, key_size, value_size, max_entries)?;
+
+        let id = alloc_id(&mut self.maps, &mut self.next_map_id);
+        let entry = Arc::new(LoadedBpfMap {
+            id,
+            map,
+            btf,
+            deleted: AtomicBool::new(false),
+            frozen: AtomicBool::new(false),
+        });
+        self.maps.insert(id, Arc::downgrade(&entry));
         log::info!(
             "Created map id={} type={} key_size={} value_size={} max_entries={}",
             id,
@@ -239,11 +1063,35 @@ impl BpfManager {
             value_size,
             max_entries
         );
-        Ok(id)
+        Ok(entry)
+    }
+
+    /// Delete a map.
+    ///
+    /// Maps used by a loaded program can't be deleted; unload the program
+    /// first. Otherwise the map can no longer be found by ID, and its memory
+    /// is released once the remaining file descriptors are closed.
+    pub fn delete_map(&mut self, map_id: u32) -> Result<(), BpfUnloadError> {
+        let map = self.map(map_id).ok_or(BpfUnloadError::NotFound)?;
+
+        let in_use = self
+            .programs
+            .values()
+            .filter_map(Weak::upgrade)
+            .any(|program| {
+                !program.is_unloaded() && program.maps().iter().any(|used| used.id() == map_id)
+            });
+        if in_use {
+            return Err(BpfUnloadError::InUse);
+        }
+
+        map.deleted.store(true, Ordering::Release);
+        log::info!("BpfManager: Deleted map id={}", map_id);
+        Ok(())
     }
 
     pub fn map_lookup(&self, map_id: u32, key: &[u8]) -> Option<Vec<u8>> {
-        self.maps.get(map_id as usize)?.lookup(key)
+        self.map(map_id)?.map().lookup(key)
     }
 
     /// Look up a value by key and return a raw pointer.
@@ -251,10 +1099,11 @@ impl BpfManager {
     /// # Safety
     /// The caller must ensure the map will not be resized or deleted while the
     /// pointer is in use. The returned pointer is only valid while the map lock
-    /// is held by the caller.
+    /// is held by the caller. Programs keep the maps they load through
+    /// `BPF_PSEUDO_MAP_FD` references alive for as long as they run.
     pub unsafe fn map_lookup_ptr(&self, map_id: u32, key: &[u8]) -> Option<*mut u8> {
         // SAFETY: caller ensures map will not be resized or deleted while pointer is in use
-        unsafe { self.maps.get(map_id as usize)?.lookup_ptr(key) }
+        unsafe { self.map(map_id)?.map().lookup_ptr(key) }
     }
 
     pub fn map_update(
@@ -264,18 +1113,36 @@ impl BpfManager {
         value: &[u8],
         flags: u64,
     ) -> Result<(), BpfError> {
-        let map = self.maps.get(map_id as usize).ok_or(BpfError::NotLoaded)?;
-        map.update(key, value, flags)
+        let map = self.map(map_id).ok_or(BpfError::NotLoaded)?;
+        map.map()
+            .update(key, value, flags)
+            .map_err(|_| BpfError::OutOfMemory)
+    }
+
+    /// Update a map element on behalf of a BPF program.
+    ///
+    /// Unlike [`map_update`](Self::map_update), this only updates the
+    /// value of the current CPU in per-CPU maps.
+    pub fn map_update_from_prog(
+        &self,
+        map_id: u32,
+        key: &[u8],
+        value: &[u8],
+        flags: u64,
+    ) -> Result<(), BpfError> {
+        let map = self.map(map_id).ok_or(BpfError::NotLoaded)?;
+        map.map()
+            .update_from_prog(key, value, flags)
             .map_err(|_| BpfError::OutOfMemory)
     }
 
     pub fn map_delete(&self, map_id: u32, key: &[u8]) -> Result<(), BpfError> {
-        let map = self.maps.get(map_id as usize).ok_or(BpfError::NotLoaded)?;
-        map.delete(key).map_err(|_| BpfError::NotLoaded)
+        let map = self.map(map_id).ok_or(BpfError::NotLoaded)?;
+        map.map().delete(key).map_err(|_| BpfError::NotLoaded)
     }
 
-    pub fn get_map_def(&self, map_id: u32) -> Option<&kernel_bpf::maps::MapDef> {
-        self.maps.get(map_id as usize).map(|m| m.def())
+    pub fn get_map_def(&self, map_id: u32) -> Option<MapDef> {
+        self.map(map_id).map(|m| m.map().def().clone())
     }
 
     /// Poll for the next event from a ring buffer map.
@@ -283,9 +1150,9 @@ impl BpfManager {
     /// Returns the event data if available, or None if the ringbuf is empty.
     /// This is used by the BPF_RINGBUF_POLL syscall command.
     pub fn ringbuf_poll(&self, map_id: u32) -> Option<Vec<u8>> {
-        let map = self.maps.get(map_id as usize)?;
+        let map = self.map(map_id)?;
         // RingBufMap::lookup() delegates to poll(), which reads and advances the tail
-        map.lookup(&[])
+        map.map().lookup(&[])
     }
 
     /// Output data to a ring buffer map.
@@ -293,10 +1160,11 @@ impl BpfManager {
     /// This is used by the bpf_ringbuf_output helper. For ringbuf maps,
     /// the key is ignored and value is the event data.
     pub fn ringbuf_output(&self, map_id: u32, data: &[u8], flags: u64) -> Result<(), BpfError> {
-        let map = self.maps.get(map_id as usize).ok_or(BpfError::NotLoaded)?;
+        let map = self.map(map_id).ok_or(BpfError::NotLoaded)?;
 
         // Ring buffer maps use update() with empty key to output data
-        map.update(&[], data, flags)
+        map.map()
+            .update(&[], data, flags)
             .map_err(|_| BpfError::OutOfMemory)
     }
 }
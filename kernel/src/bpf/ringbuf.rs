@@ -0,0 +1,73 @@
+//! Mapping ring buffer maps into user space.
+//!
+//! A ring buffer is mapped like on Linux: the consumer page is the only
+//! writable one, followed by the read-only producer page and the data
+//! pages, which are mapped twice in a row so that records wrapping around
+//! the end of the buffer can be read in one piece.
+//!
+//! Tasks polling ring buffers block on [`RINGBUF_READERS`], which every ring
+//! buffer wakes when a submit marks it ready.
+
+use alloc::vec::Vec;
+
+use kernel_bpf::maps::RINGBUF_PAGE_SIZE;
+use kernel_syscall::access::MapObjectError;
+
+use crate::arch::types::{PageSize, PageTableFlags, PhysFrame, Size4KiB, VirtAddr};
+use crate::bpf::LoadedBpfMap;
+use crate::mcore::mtask::scheduler::wait::WaitQueue;
+use crate::mem::address_space::AddressSpace;
+
+const _: () = assert!(RINGBUF_PAGE_SIZE as u64 == Size4KiB::SIZE);
+
+/// Woken whenever a ring buffer is marked ready for its consumer.
+///
+/// A single queue for all ring buffers, as one `poll()` may wait on several.
+pub static RINGBUF_READERS: WaitQueue = WaitQueue::new();
+
+/// The frames backing `size` bytes of `map`, starting at `offset`, and the
+/// flags to map each of them with.
+pub fn mmap_pages(
+    map: &LoadedBpfMap,
+    offset: usize,
+    size: usize,
+    writable: bool,
+) -> Result<Vec<(PhysFrame, PageTableFlags)>, MapObjectError> {
+    let ringbuf = map
+        .map()
+        .as_ringbuf()
+        .filter(|ringbuf| ringbuf.is_mappable())
+        .ok_or(MapObjectError::NotMappable)?;
+
+    let end = offset
+        .checked_add(size)
+        .ok_or(MapObjectError::InvalidRange)?;
+    if size == 0 || !offset.is_multiple_of(RINGBUF_PAGE_SIZE) || end > ringbuf.mmap_len() {
+        return Err(MapObjectError::InvalidRange);
+    }
+
+    let first = offset / RINGBUF_PAGE_SIZE;
+    let count = size.div_ceil(RINGBUF_PAGE_SIZE);
+    // Only the consumer position may be written by user space.
+    if writable && (first != 0 || count != 1) {
+        return Err(MapObjectError::PermissionDenied);
+    }
+
+    let mut flags =
+        PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_EXECUTE;
+    if writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+
+    (first..first + count)
+        .map(|index| {
+            let page = ringbuf
+                .mmap_page(index)
+                .ok_or(MapObjectError::InvalidRange)?;
+            let phys = AddressSpace::kernel()
+                .translate(VirtAddr::from_ptr(page))
+                .ok_or(MapObjectError::InvalidRange)?;
+            Ok((PhysFrame::containing_address(phys), flags))
+        })
+        .collect()
+}
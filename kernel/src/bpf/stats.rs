@@ -0,0 +1,88 @@
+//! Per-program runtime statistics.
+//!
+//! Timing every run costs two clock reads, so like Linux the counters are
+//! only updated while stats are enabled through `BPF_ENABLE_STATS`. Counters
+//! are atomics on the program itself, because hooks run their programs
+//! without holding the [`BpfManager`](super::BpfManager) lock.
+//!
+//! Deadline misses are counted regardless, as the hooks read the clock for
+//! programs with a deadline anyway.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use kernel_abi::{
+    BpfProgInfo, BPF_ERR_DIVISION_BY_ZERO, BPF_ERR_INVALID_HELPER, BPF_ERR_INVALID_INSTRUCTION,
+    BPF_ERR_KINDS, BPF_ERR_NOT_LOADED, BPF_ERR_OUT_OF_BOUNDS, BPF_ERR_OUT_OF_MEMORY,
+    BPF_ERR_STACK_OVERFLOW, BPF_ERR_TIMEOUT,
+};
+use kernel_bpf::execution::BpfError;
+
+static STATS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start or stop collecting runtime statistics for all programs.
+pub fn set_enabled(enabled: bool) {
+    STATS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether runtime statistics are being collected.
+pub fn enabled() -> bool {
+    STATS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// The `BPF_ERR_*` slot counting `err`.
+fn error_kind(err: &BpfError) -> usize {
+    match err {
+        BpfError::DivisionByZero => BPF_ERR_DIVISION_BY_ZERO,
+        BpfError::OutOfBounds => BPF_ERR_OUT_OF_BOUNDS,
+        BpfError::StackOverflow => BPF_ERR_STACK_OVERFLOW,
+        BpfError::InvalidHelper(_) => BPF_ERR_INVALID_HELPER,
+        BpfError::Timeout => BPF_ERR_TIMEOUT,
+        BpfError::InvalidInstruction => BPF_ERR_INVALID_INSTRUCTION,
+        BpfError::NotLoaded => BPF_ERR_NOT_LOADED,
+        BpfError::OutOfMemory => BPF_ERR_OUT_OF_MEMORY,
+    }
+}
+
+/// Runtime counters of one program.
+#[derive(Default)]
+pub struct ProgramStats {
+    run_cnt: AtomicU64,
+    run_time_ns: AtomicU64,
+    max_run_time_ns: AtomicU64,
+    last_retval: AtomicU64,
+    err_cnt: [AtomicU64; BPF_ERR_KINDS],
+    deadline_misses: AtomicU64,
+}
+
+impl ProgramStats {
+    /// Account for one run that took `elapsed_ns` and produced `result`.
+    pub fn record(&self, elapsed_ns: u64, result: &Result<u64, BpfError>) {
+        self.run_cnt.fetch_add(1, Ordering::Relaxed);
+        self.run_time_ns.fetch_add(elapsed_ns, Ordering::Relaxed);
+        self.max_run_time_ns
+            .fetch_max(elapsed_ns, Ordering::Relaxed);
+        match result {
+            Ok(retval) => self.last_retval.store(*retval, Ordering::Relaxed),
+            Err(err) => {
+                self.err_cnt[error_kind(err)].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Account for a hook run that finished after its deadline.
+    pub fn record_deadline_miss(&self) {
+        self.deadline_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Copy the counters into `info`.
+    pub fn fill_info(&self, info: &mut BpfProgInfo) {
+        info.run_cnt = self.run_cnt.load(Ordering::Relaxed);
+        info.run_time_ns = self.run_time_ns.load(Ordering::Relaxed);
+        info.max_run_time_ns = self.max_run_time_ns.load(Ordering::Relaxed);
+        info.last_retval = self.last_retval.load(Ordering::Relaxed);
+        info.deadline_misses = self.deadline_misses.load(Ordering::Relaxed);
+        for (out, count) in info.err_cnt.iter_mut().zip(&self.err_cnt) {
+            *out = count.load(Ordering::Relaxed);
+        }
+    }
+}
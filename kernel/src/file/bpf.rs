@@ -0,0 +1,212 @@
+//! File descriptors for BPF programs, maps and type information.
+//!
+//! Loading a program, creating a map or loading BTF hands the caller a file descriptor
+//! backed by [`BpfObjectFs`]. The descriptor holds a reference to the object,
+//! so closing it (or exiting the process) releases the object unless an
+//! attachment or a program using the map still needs it.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use conquer_once::spin::OnceCell;
+use kernel_vfs::fs::{FileSystem, FsHandle};
+use kernel_vfs::node::VfsNode;
+use kernel_vfs::path::{AbsoluteOwnedPath, AbsolutePath};
+use kernel_vfs::{
+    CloseError, FsError, MkdirError, OpenError, ReadError, RmdirError, Stat, StatError, WriteError,
+};
+use spin::RwLock;
+
+use crate::bpf::{LoadedBpfBtf, LoadedBpfMap, LoadedBpfProgram};
+use crate::file::OpenFileDescription;
+use crate::mcore::mtask::process::fd::{FdNum, FileDescriptor, FileDescriptorFlags};
+use crate::mcore::mtask::process::Process;
+
+/// A BPF object held by a file descriptor.
+#[derive(Clone)]
+pub enum BpfObject {
+    Program(Arc<LoadedBpfProgram>),
+    Map(Arc<LoadedBpfMap>),
+    Btf(Arc<LoadedBpfBtf>),
+}
+
+/// Anonymous file system behind BPF object file descriptors.
+///
+/// Each open handle owns one reference to its object; the VFS closes the
+/// handle when the last descriptor sharing it goes away.
+pub struct BpfObjectFs {
+    objects: BTreeMap<u64, BpfObject>,
+    next_handle: u64,
+}
+
+impl Default for BpfObjectFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BpfObjectFs {
+    pub fn new() -> Self {
+        Self {
+            objects: BTreeMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    fn insert(&mut self, object: BpfObject) -> FsHandle {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.objects.insert(handle, object);
+        FsHandle::from(handle)
+    }
+}
+
+impl FileSystem for BpfObjectFs {
+    fn open(&mut self, _path: &AbsolutePath) -> Result<FsHandle, OpenError> {
+        Err(OpenError::NotFound) // BPF objects are anonymous
+    }
+
+    fn close(&mut self, handle: FsHandle) -> Result<(), CloseError> {
+        let handle: u64 = handle.into();
+        match self.objects.remove(&handle) {
+            Some(_) => Ok(()),
+            None => Err(CloseError::NotOpen),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _handle: FsHandle,
+        _buf: &mut [u8],
+        _offset: usize,
+    ) -> Result<usize, ReadError> {
+        Err(ReadError::NotReadable)
+    }
+
+    fn write(
+        &mut self,
+        _handle: FsHandle,
+        _buf: &[u8],
+        _offset: usize,
+    ) -> Result<usize, WriteError> {
+        Err(WriteError::NotWritable)
+    }
+
+    fn stat(&mut self, handle: FsHandle, stat: &mut Stat) -> Result<(), StatError> {
+        let handle: u64 = handle.into();
+        if !self.objects.contains_key(&handle) {
+            return Err(StatError::FsError(FsError::InvalidHandle));
+        }
+        stat.size = 0;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, _path: &AbsolutePath) -> Result<(), MkdirError> {
+        Err(MkdirError::FsError(FsError::InvalidHandle))
+    }
+
+    fn rmdir(&mut self, _path: &AbsolutePath) -> Result<(), RmdirError> {
+        Err(RmdirError::FsError(FsError::InvalidHandle))
+    }
+}
+
+pub static BPF_OBJECT_FS: OnceCell<Arc<RwLock<BpfObjectFs>>> = OnceCell::uninit();
+
+pub fn init() {
+    BPF_OBJECT_FS.init_once(|| Arc::new(RwLock::new(BpfObjectFs::new())));
+}
+
+fn object_fs() -> Option<Arc<RwLock<dyn FileSystem>>> {
+    let fs: Arc<RwLock<dyn FileSystem>> = BPF_OBJECT_FS.get()?.clone();
+    Some(fs)
+}
+
+/// Install a file descriptor for `object` in `process`, returning its number.
+///
+/// The descriptor takes over the caller's reference to the object.
+pub fn install(process: &Process, object: BpfObject) -> Option<FdNum> {
+    let fs_lock = BPF_OBJECT_FS.get()?;
+    let fs = object_fs()?;
+
+    let path = match object {
+        BpfObject::Program(_) => "/[bpf-prog]",
+        BpfObject::Map(_) => "/[bpf-map]",
+        BpfObject::Btf(_) => "/[bpf-btf]",
+    };
+    let handle = fs_lock.write().insert(object);
+    let node = VfsNode::new(
+        AbsoluteOwnedPath::try_from(path).unwrap(),
+        handle,
+        Arc::downgrade(&fs),
+    );
+    let ofd = Arc::new(OpenFileDescription::from(node));
+
+    let mut fds = process.file_descriptors().write();
+    let mut num = 0;
+    while fds.contains_key(&FdNum::from(num)) {
+        num += 1;
+    }
+    let fd = FdNum::from(num);
+    fds.insert(
+        fd,
+        FileDescriptor::new(fd, FileDescriptorFlags::empty(), ofd),
+    );
+    Some(fd)
+}
+
+/// Run `f` on the BPF object behind `fd`, if `fd` is one.
+fn with_object<R>(process: &Process, fd: u32, f: impl FnOnce(&BpfObject) -> R) -> Option<R> {
+    let fs = object_fs()?;
+    let fds = process.file_descriptors().read();
+    let ofd = fds
+        .get(&FdNum::from(i32::try_from(fd).ok()?))?
+        .file_description();
+    if !ofd.is_on(&fs) {
+        return None;
+    }
+
+    let handle: u64 = ofd.fs_handle().into();
+    let objects = BPF_OBJECT_FS.get()?.read();
+    objects.objects.get(&handle).map(f)
+}
+
+/// The object behind `fd`.
+pub fn object(process: &Process, fd: u32) -> Option<BpfObject> {
+    with_object(process, fd, BpfObject::clone)
+}
+
+/// The program behind `fd`.
+pub fn program(process: &Process, fd: u32) -> Option<Arc<LoadedBpfProgram>> {
+    with_object(process, fd, |object| match object {
+        BpfObject::Program(program) => Some(program.clone()),
+        _ => None,
+    })
+    .flatten()
+}
+
+/// The map behind `fd`.
+pub fn map(process: &Process, fd: u32) -> Option<Arc<LoadedBpfMap>> {
+    with_object(process, fd, |object| match object {
+        BpfObject::Map(map) => Some(map.clone()),
+        _ => None,
+    })
+    .flatten()
+}
+
+/// The ID of the program behind `fd`.
+pub fn prog_id(process: &Process, fd: u32) -> Option<u32> {
+    with_object(process, fd, |object| match object {
+        BpfObject::Program(program) => Some(program.id()),
+        _ => None,
+    })
+    .flatten()
+}
+
+/// The ID of the map behind `fd`.
+pub fn map_id(process: &Process, fd: u32) -> Option<u32> {
+    with_object(process, fd, |object| match object {
+        BpfObject::Map(map) => Some(map.id()),
+        _ => None,
+    })
+    .flatten()
+}
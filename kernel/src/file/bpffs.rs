@@ -0,0 +1,321 @@
+//! The BPF filesystem, mounted at [`BPFFS_MOUNT_POINT`].
+//!
+//! `BPF_OBJ_PIN` creates a node here that holds a reference to a program or
+//! map, so the object outlives the process that loaded it. `BPF_OBJ_GET`
+//! looks the node up by path and hands out a new file descriptor, which lets
+//! one process load a program and another consume its maps. Directories can
+//! be created with `mkdir` to organize pins; `/sys/fs/bpf/maps` and
+//! `/sys/fs/bpf/progs` exist from boot.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Deref;
+
+use conquer_once::spin::OnceCell;
+use kernel_vfs::fs::{FileSystem, FsHandle};
+use kernel_vfs::path::{AbsolutePath, Path, ROOT};
+use kernel_vfs::{
+    CloseError, FsError, MkdirError, OpenError, ReadError, RmdirError, Stat, StatError, WriteError,
+};
+use spin::RwLock;
+
+use crate::file::bpf::BpfObject;
+
+/// Where the BPF filesystem is mounted.
+pub const BPFFS_MOUNT_POINT: &str = "/sys/fs/bpf";
+
+/// Reasons an object can't be pinned, looked up or unpinned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinError {
+    /// The path is not below the mount point, or contains `.` or `..`.
+    InvalidPath,
+    /// Nothing is pinned at the path, or its parent directory is missing.
+    NotFound,
+    /// Something already exists at the path.
+    AlreadyExists,
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPath => write!(f, "path is not inside {}", BPFFS_MOUNT_POINT),
+            Self::NotFound => write!(f, "not found"),
+            Self::AlreadyExists => write!(f, "already exists"),
+        }
+    }
+}
+
+enum BpfFsNode {
+    Directory,
+    Object(BpfObject),
+}
+
+/// Pinned BPF objects and the directories holding them.
+///
+/// Nodes are keyed by their path below the mount point, with components
+/// joined by `/` and no leading separator; the root directory is implicit.
+pub struct BpfFs {
+    nodes: BTreeMap<String, BpfFsNode>,
+    open_files: BTreeMap<FsHandle, BpfObject>,
+    next_handle: u64,
+}
+
+impl Default for BpfFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BpfFs {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            open_files: BTreeMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Pin `object` at `path`, relative to the mount point.
+    ///
+    /// The parent directory must exist.
+    pub fn pin(&mut self, path: &AbsolutePath, object: BpfObject) -> Result<(), PinError> {
+        let key = Self::key(path).ok_or(PinError::InvalidPath)?;
+        if key.is_empty() {
+            return Err(PinError::AlreadyExists);
+        }
+        if !self.is_directory(Self::parent_key(&key)) {
+            return Err(PinError::NotFound);
+        }
+        if self.nodes.contains_key(&key) {
+            return Err(PinError::AlreadyExists);
+        }
+
+        self.nodes.insert(key, BpfFsNode::Object(object));
+        Ok(())
+    }
+
+    /// The object pinned at `path`, relative to the mount point.
+    pub fn get(&self, path: &AbsolutePath) -> Result<BpfObject, PinError> {
+        let key = Self::key(path).ok_or(PinError::InvalidPath)?;
+        match self.nodes.get(&key) {
+            Some(BpfFsNode::Object(object)) => Ok(object.clone()),
+            _ => Err(PinError::NotFound),
+        }
+    }
+
+    /// Remove the pin at `path`, relative to the mount point.
+    ///
+    /// The pin's reference is returned so the caller can drop it after
+    /// releasing the filesystem lock.
+    pub fn unpin(&mut self, path: &AbsolutePath) -> Result<BpfObject, PinError> {
+        let key = Self::key(path).ok_or(PinError::InvalidPath)?;
+        match self.nodes.remove(&key) {
+            Some(BpfFsNode::Object(object)) => Ok(object),
+            Some(directory) => {
+                self.nodes.insert(key, directory);
+                Err(PinError::NotFound)
+            }
+            None => Err(PinError::NotFound),
+        }
+    }
+
+    fn key(path: &Path) -> Option<String> {
+        let mut components = Vec::new();
+        for name in path.filenames() {
+            if name == "." || name == ".." {
+                return None;
+            }
+            components.push(name);
+        }
+        Some(components.join("/"))
+    }
+
+    fn parent_key(key: &str) -> &str {
+        key.rfind('/').map_or("", |pos| &key[..pos])
+    }
+
+    fn is_directory(&self, key: &str) -> bool {
+        key.is_empty() || matches!(self.nodes.get(key), Some(BpfFsNode::Directory))
+    }
+
+    fn has_children(&self, key: &str) -> bool {
+        self.nodes
+            .keys()
+            .any(|other| Self::parent_key(other) == key)
+    }
+}
+
+impl FileSystem for BpfFs {
+    fn open(&mut self, path: &AbsolutePath) -> Result<FsHandle, OpenError> {
+        let object = self.get(path).map_err(|_| OpenError::NotFound)?;
+        let handle = FsHandle::from(self.next_handle);
+        self.next_handle += 1;
+        self.open_files.insert(handle, object);
+        Ok(handle)
+    }
+
+    fn close(&mut self, handle: FsHandle) -> Result<(), CloseError> {
+        self.open_files.remove(&handle).ok_or(CloseError::NotOpen)?;
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        handle: FsHandle,
+        _buf: &mut [u8],
+        _offset: usize,
+    ) -> Result<usize, ReadError> {
+        if !self.open_files.contains_key(&handle) {
+            return Err(ReadError::FsError(FsError::InvalidHandle));
+        }
+        Err(ReadError::NotReadable) // use BPF_OBJ_GET to access the object
+    }
+
+    fn write(
+        &mut self,
+        handle: FsHandle,
+        _buf: &[u8],
+        _offset: usize,
+    ) -> Result<usize, WriteError> {
+        if !self.open_files.contains_key(&handle) {
+            return Err(WriteError::FsError(FsError::InvalidHandle));
+        }
+        Err(WriteError::NotWritable)
+    }
+
+    fn stat(&mut self, handle: FsHandle, stat: &mut Stat) -> Result<(), StatError> {
+        if !self.open_files.contains_key(&handle) {
+            return Err(StatError::FsError(FsError::InvalidHandle));
+        }
+        stat.size = 0;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &AbsolutePath) -> Result<(), MkdirError> {
+        let key = Self::key(path).ok_or(MkdirError::NotFound)?;
+        if key.is_empty() || self.nodes.contains_key(&key) {
+            return Err(MkdirError::AlreadyExists);
+        }
+        if !self.is_directory(Self::parent_key(&key)) {
+            return Err(MkdirError::NotFound);
+        }
+
+        self.nodes.insert(key, BpfFsNode::Directory);
+        Ok(())
+    }
+
+    fn rmdir(&mut self, path: &AbsolutePath) -> Result<(), RmdirError> {
+        let key = Self::key(path).ok_or(RmdirError::NotFound)?;
+        match self.nodes.get(&key) {
+            Some(BpfFsNode::Directory) => {}
+            Some(BpfFsNode::Object(_)) => return Err(RmdirError::NotADirectory),
+            None => return Err(RmdirError::NotFound), // includes the root
+        }
+        if self.has_children(&key) {
+            return Err(RmdirError::NotEmpty);
+        }
+
+        self.nodes.remove(&key);
+        Ok(())
+    }
+}
+
+/// A shared handle to the [`BpfFs`], so the kernel can pin objects in the
+/// instance that is mounted.
+#[derive(Clone)]
+pub struct ArcLockedBpfFs {
+    inner: Arc<RwLock<BpfFs>>,
+}
+
+impl ArcLockedBpfFs {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(BpfFs::new())),
+        }
+    }
+}
+
+impl Default for ArcLockedBpfFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for ArcLockedBpfFs {
+    type Target = RwLock<BpfFs>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl FileSystem for ArcLockedBpfFs {
+    fn open(&mut self, path: &AbsolutePath) -> Result<FsHandle, OpenError> {
+        self.inner.write().open(path)
+    }
+
+    fn close(&mut self, handle: FsHandle) -> Result<(), CloseError> {
+        self.inner.write().close(handle)
+    }
+
+    fn read(
+        &mut self,
+        handle: FsHandle,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, ReadError> {
+        self.inner.write().read(handle, buf, offset)
+    }
+
+    fn write(&mut self, handle: FsHandle, buf: &[u8], offset: usize) -> Result<usize, WriteError> {
+        self.inner.write().write(handle, buf, offset)
+    }
+
+    fn stat(&mut self, handle: FsHandle, stat: &mut Stat) -> Result<(), StatError> {
+        self.inner.write().stat(handle, stat)
+    }
+
+    fn mkdir(&mut self, path: &AbsolutePath) -> Result<(), MkdirError> {
+        self.inner.write().mkdir(path)
+    }
+
+    fn rmdir(&mut self, path: &AbsolutePath) -> Result<(), RmdirError> {
+        self.inner.write().rmdir(path)
+    }
+}
+
+static BPFFS: OnceCell<ArcLockedBpfFs> = OnceCell::uninit();
+
+#[must_use]
+pub fn bpffs() -> &'static ArcLockedBpfFs {
+    BPFFS.get().expect("bpffs should be initialized")
+}
+
+/// Create the bpffs with the conventional `/maps` and `/progs` directories.
+pub fn init() {
+    let bpffs = ArcLockedBpfFs::new();
+    {
+        let mut guard = bpffs.inner.write();
+        for dir in ["/maps", "/progs"] {
+            guard
+                .mkdir(AbsolutePath::try_new(dir).unwrap())
+                .expect("should be able to create bpffs directory");
+        }
+    }
+    BPFFS.init_once(|| bpffs);
+}
+
+/// The part of `path` below [`BPFFS_MOUNT_POINT`], if it is inside the mount.
+///
+/// The result is still absolute, like the paths the VFS hands to mounted
+/// filesystems.
+pub fn mount_relative(path: &AbsolutePath) -> Option<&AbsolutePath> {
+    let rest = path.strip_prefix(BPFFS_MOUNT_POINT)?;
+    if rest.is_empty() {
+        return Some(ROOT);
+    }
+    AbsolutePath::try_new(rest).ok()
+}
@@ -6,8 +6,11 @@ use kernel_vfs::path::AbsolutePath;
 use kernel_vfs::Vfs;
 use spin::RwLock;
 
+use crate::file::bpffs::{bpffs, BPFFS_MOUNT_POINT};
 use crate::file::devfs::devfs;
 
+pub mod bpf;
+pub mod bpffs;
 pub mod devfs;
 pub mod ext2;
 pub mod pipe;
@@ -22,10 +25,18 @@ pub fn vfs() -> &'static RwLock<Vfs> {
 pub fn init() {
     devfs::init();
     pipe::init();
+    bpf::init();
+    bpffs::init();
 
     VFS.write()
         .mount(AbsolutePath::try_new("/dev").unwrap(), devfs().clone())
         .expect("should be able to mount devfs");
+    VFS.write()
+        .mount(
+            AbsolutePath::try_new(BPFFS_MOUNT_POINT).unwrap(),
+            bpffs().clone(),
+        )
+        .expect("should be able to mount bpffs");
 }
 
 #[derive(Debug)]
@@ -1,5 +1,7 @@
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::{Acquire, Release};
 
 use conquer_once::spin::OnceCell;
 use kernel_vfs::fs::{FileSystem, FsHandle};
@@ -8,9 +10,19 @@ use kernel_vfs::{
 };
 use spin::{Mutex, RwLock};
 
+use crate::mcore::mtask::scheduler::wait::WaitQueue;
+
+/// Woken whenever data is written to a pipe or a write end is closed.
+///
+/// Readers block here after [`PipeFs::read`] returned
+/// [`ReadError::WouldBlock`], as the pipe itself is only reachable under the
+/// [`PIPE_FS`] lock.
+pub static PIPE_READERS: WaitQueue = WaitQueue::new();
+
 pub struct Pipe {
     buffer: Mutex<VecDeque<u8>>,
-    // TODO: Use CondVar or Waker for blocking
+    reader_open: AtomicBool,
+    writer_open: AtomicBool,
 }
 
 impl Default for Pipe {
@@ -23,6 +35,8 @@ impl Pipe {
     pub fn new() -> Self {
         Self {
             buffer: Mutex::new(VecDeque::new()),
+            reader_open: AtomicBool::new(true),
+            writer_open: AtomicBool::new(true),
         }
     }
 
@@ -43,8 +57,45 @@ impl Pipe {
     pub fn write(&self, buf: &[u8]) -> usize {
         let mut buffer = self.buffer.lock();
         buffer.extend(buf);
+        drop(buffer);
+        PIPE_READERS.wake_all();
         buf.len()
     }
+
+    pub fn is_writer_open(&self) -> bool {
+        self.writer_open.load(Acquire)
+    }
+
+    /// Close one end of the pipe, and return whether both ends are closed.
+    fn close(&self, end: End) -> bool {
+        match end {
+            End::Read => self.reader_open.store(false, Release),
+            End::Write => {
+                self.writer_open.store(false, Release);
+                // Readers waiting for data see the end of file now.
+                PIPE_READERS.wake_all();
+            }
+        }
+        !self.reader_open.load(Acquire) && !self.writer_open.load(Acquire)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum End {
+    Read,
+    Write,
+}
+
+/// The handle of one end of the pipe with the given inode.
+fn handle(inode: u64, end: End) -> FsHandle {
+    FsHandle::from(inode << 1 | u64::from(end == End::Write))
+}
+
+/// The inode and end a handle refers to.
+fn split_handle(handle: FsHandle) -> (u64, End) {
+    let raw: u64 = handle.into();
+    let end = if raw & 1 == 0 { End::Read } else { End::Write };
+    (raw >> 1, end)
 }
 
 pub struct PipeFs {
@@ -73,11 +124,7 @@ impl PipeFs {
         let pipe = Arc::new(Pipe::new());
         self.pipes.insert(inode, pipe);
 
-        // We use the same inode for both ends for now, but in reality
-        // we might want separate handles if we track read/write ends differently.
-        // For simple VFS interaction, we can just return the same handle ID.
-        // The file descriptor flags/mode will determine read vs write.
-        (FsHandle::from(inode), FsHandle::from(inode))
+        (handle(inode, End::Read), handle(inode, End::Write))
     }
 }
 
@@ -87,27 +134,11 @@ impl FileSystem for PipeFs {
     }
 
     fn close(&mut self, handle: FsHandle) -> Result<(), CloseError> {
-        // TODO: refcounting? VfsNode holds Weak reference to FS, but FsHandle is just u64.
-        // We probably shouldn't remove the pipe until all handles are closed.
-        // But the current VFS doesn't expose refcounts on handles easily.
-        // For now, we leak or implement a simple refcount in PipeFs?
-        // Let's assume for this MVP that we don't delete pipes to avoid use-after-free
-        // issues until we have a better handle lifecycle.
-        // Or better: check if the Arc strong count is 1 (meaning only this map holds it).
-
-        let _inode: u64 = handle.into();
-        // if let Some(pipe) = self.pipes.get(&inode) {
-        //     if Arc::strong_count(pipe) <= 1 {
-        //         self.pipes.remove(&inode);
-        //     }
-        // }
-        // Actually, VfsNode doesn't hold the Arc<Pipe>, the PipeFs does.
-        // The VfsNode holds a FsHandle.
-        // When VfsNode is dropped, it calls close().
-
-        // Let's assume 2 handles per pipe initially (read/write).
-        // This is tricky without more state.
-        // For now, no-op.
+        let (inode, end) = split_handle(handle);
+        let pipe = self.pipes.get(&inode).ok_or(CloseError::NotOpen)?;
+        if pipe.close(end) {
+            self.pipes.remove(&inode);
+        }
         Ok(())
     }
 
@@ -117,32 +148,33 @@ impl FileSystem for PipeFs {
         buf: &mut [u8],
         _offset: usize,
     ) -> Result<usize, ReadError> {
-        let inode: u64 = handle.into();
-        if let Some(pipe) = self.pipes.get(&inode) {
-            let n = pipe.read(buf);
-            if n == 0 && !buf.is_empty() {
-                // Should block? For now return 0 (EOF) or EAGAIN?
-                // Returning 0 usually means EOF.
-                // If the write end is open but buffer empty, we should block.
-                // If write end closed, return 0.
-                // We don't track write end status yet.
-                // Let's return 0 for "buffer empty" for now, which is technically EOF or non-blocking.
-                Ok(0)
-            } else {
-                Ok(n)
-            }
+        let (inode, end) = split_handle(handle);
+        let pipe = self
+            .pipes
+            .get(&inode)
+            .ok_or(ReadError::FsError(FsError::InvalidHandle))?;
+        if end != End::Read {
+            return Err(ReadError::NotReadable);
+        }
+        let n = pipe.read(buf);
+        if n == 0 && !buf.is_empty() && pipe.is_writer_open() {
+            // Empty, but more data may still come. A zero read means EOF.
+            Err(ReadError::WouldBlock)
         } else {
-            Err(ReadError::FsError(FsError::InvalidHandle))
+            Ok(n)
         }
     }
 
     fn write(&mut self, handle: FsHandle, buf: &[u8], _offset: usize) -> Result<usize, WriteError> {
-        let inode: u64 = handle.into();
-        if let Some(pipe) = self.pipes.get(&inode) {
-            Ok(pipe.write(buf))
-        } else {
-            Err(WriteError::FsError(FsError::InvalidHandle))
+        let (inode, end) = split_handle(handle);
+        let pipe = self
+            .pipes
+            .get(&inode)
+            .ok_or(WriteError::FsError(FsError::InvalidHandle))?;
+        if end != End::Write {
+            return Err(WriteError::NotWritable);
         }
+        Ok(pipe.write(buf))
     }
 
     fn stat(&mut self, _handle: FsHandle, stat: &mut Stat) -> Result<(), StatError> {
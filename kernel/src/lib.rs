@@ -143,6 +143,8 @@ pub fn init() {
         mcore::init();
         dbg_mark(0x6c); // 'l'
         info!("Multicore/scheduler initialized");
+
+        bpf::hooks::init();
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -71,6 +71,8 @@ unsafe extern "C" fn main() -> ! {
                 ),
             )
             .expect("should be able to mount ext2fs at /");
+
+        kernel::bpf::keyring::init();
     }
 
     {
@@ -151,6 +153,8 @@ unsafe extern "C" fn main() -> ! {
         }
         dbg_mark(0x44); // 'D'
 
+        kernel::bpf::keyring::init();
+
         info!("starting init process...");
         let init_path = match AbsolutePath::try_new("/bin/init") {
             Ok(p) => p,
@@ -61,7 +61,7 @@ impl ExecutionContext {
             sel,
             _idt: idt,
             tss: UnsafeCell::new(tss),
-            scheduler: UnsafeCell::new(Scheduler::new_cpu_local()),
+            scheduler: UnsafeCell::new(Scheduler::new_cpu_local(cpu.id as usize)),
         }
     }
 
@@ -69,7 +69,7 @@ impl ExecutionContext {
     pub fn new(cpu_id: usize) -> Self {
         ExecutionContext {
             cpu_id,
-            scheduler: UnsafeCell::new(Scheduler::new_cpu_local()),
+            scheduler: UnsafeCell::new(Scheduler::new_cpu_local(cpu_id)),
             need_reschedule: core::sync::atomic::AtomicBool::new(false),
         }
     }
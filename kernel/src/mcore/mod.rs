@@ -2,6 +2,7 @@
 use alloc::boxed::Box;
 #[cfg(target_arch = "x86_64")]
 use core::sync::atomic::Ordering::{Acquire, Release};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(target_arch = "x86_64")]
 use log::info;
@@ -32,6 +33,7 @@ use crate::arch::types::{PhysAddr, PhysFrame, VirtAddr};
 use crate::limine::MP_REQUEST;
 use crate::mcore::mtask::scheduler::cleanup::TaskCleanup;
 use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::wait::without_interrupts;
 #[cfg(target_arch = "x86_64")]
 use crate::sse;
 
@@ -42,6 +44,17 @@ use crate::mcore::context::ExecutionContext;
 mod lapic;
 pub mod mtask;
 
+/// See [`cpu_count`].
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+/// The number of CPUs the kernel may run on.
+///
+/// Every [`ExecutionContext::cpu_id`](context::ExecutionContext::cpu_id) is
+/// below this, so it can size per-CPU data.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Relaxed)
+}
+
 #[allow(clippy::missing_panics_doc)]
 pub fn init() {
     #[cfg(target_arch = "x86_64")]
@@ -60,6 +73,9 @@ pub fn init() {
             frame.start_address().as_u64() | flags.bits()
         };
 
+        let cpu_count = resp.cpus().iter().map(|cpu| cpu.id as usize + 1).max();
+        CPU_COUNT.store(cpu_count.unwrap_or(1), Ordering::Relaxed);
+
         // set the extra field in the CPU structs to the CR3 value (or other arch-specific data)
         resp.cpus().iter().for_each(|cpu| {
             cpu.extra.store(extra_val, Release);
@@ -79,7 +95,10 @@ pub fn init() {
     }
 
     #[cfg(target_arch = "aarch64")]
-    GlobalTaskQueue::init();
+    {
+        CPU_COUNT.store(crate::arch::aarch64::cpu::MAX_CPUS, Ordering::Relaxed);
+        GlobalTaskQueue::init();
+    }
 
     TaskCleanup::init();
 }
@@ -178,9 +197,13 @@ unsafe extern "C" fn cpu_init_and_idle(cpu: &limine::mp::Cpu) -> ! {
 ///
 /// This adapts the current task priority and affinity.
 pub fn turn_idle() -> ! {
-    // This is an idle-task now.
-    // TODO: pin this task to this CPU
-    // TODO: make this task lowest (idle) priority, so that it doesn't get scheduled if there are any other tasks
+    // The task is already pinned to this CPU, and from now on only runs when
+    // no other task is ready here.
+    without_interrupts(|| {
+        // SAFETY: Interrupts are disabled, so nothing else uses this CPU's
+        // scheduler.
+        unsafe { context::ExecutionContext::load().scheduler_mut() }.make_current_idle();
+    });
     loop {
         #[cfg(target_arch = "x86_64")]
         hlt();
@@ -1,5 +1,6 @@
 use alloc::vec::Vec;
-use core::slice;
+use core::any::Any;
+use core::{fmt, slice};
 
 use kernel_vfs::node::VfsNode;
 use spin::mutex::Mutex;
@@ -19,10 +20,11 @@ impl Default for MemoryRegions {
     }
 }
 
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 
-use crate::arch::types::{PageSize, PageTableFlags, Size4KiB};
+use crate::arch::types::{Page, PageSize, PageTableFlags, Size4KiB};
 use crate::mcore::mtask::process::Process;
+use crate::mem::address_space::AddressSpace;
 use crate::mem::virt::VirtualMemoryAllocator;
 
 impl MemoryRegions {
@@ -93,6 +95,19 @@ impl MemoryRegions {
     pub fn clear(&self) {
         self.regions.lock().clear();
     }
+
+    /// Unmaps every shared region from `address_space`.
+    ///
+    /// Shared regions unmap themselves through their process when they are
+    /// dropped. While the process itself is being dropped, it can't be reached
+    /// anymore, so it has to unmap them before its address space goes away.
+    pub fn unmap_shared(&self, address_space: &AddressSpace) {
+        for region in self.regions.lock().iter() {
+            if let MemoryRegion::Shared(shared) = region {
+                shared.unmap(address_space);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -114,6 +129,12 @@ pub enum MemoryRegion {
     ///
     /// - [`FileBackedMemoryRegion`]
     FileBacked(FileBackedMemoryRegion),
+    /// A memory region whose pages belong to a kernel object, such as
+    /// a BPF ring buffer. The pages are shared with the object and with
+    /// every other process mapping it, and are not freed with the region.
+    ///
+    /// - [`SharedMemoryRegion`]
+    Shared(SharedMemoryRegion),
 }
 
 impl MemoryRegion {
@@ -124,6 +145,7 @@ impl MemoryRegion {
             MemoryRegion::FileBacked(file_backed_memory_region) => {
                 file_backed_memory_region.region.segment.start
             }
+            MemoryRegion::Shared(shared_memory_region) => shared_memory_region.segment.start,
         }
     }
 
@@ -134,6 +156,7 @@ impl MemoryRegion {
             MemoryRegion::FileBacked(r) => {
                 Ok(MemoryRegion::FileBacked(r.clone_to_process(new_process)?))
             }
+            MemoryRegion::Shared(r) => Ok(MemoryRegion::Shared(r.clone_to_process(new_process)?)),
         }
     }
 
@@ -144,6 +167,7 @@ impl MemoryRegion {
             MemoryRegion::FileBacked(file_backed_memory_region) => {
                 file_backed_memory_region.region.size
             }
+            MemoryRegion::Shared(shared_memory_region) => shared_memory_region.size,
         }
     }
 
@@ -220,6 +244,38 @@ impl MappedMemoryRegion {
     }
 }
 
+impl SharedMemoryRegion {
+    pub fn clone_to_process(&self, new_process: &Arc<Process>) -> Result<Self, &'static str> {
+        let new_segment = new_process
+            .vmm()
+            .mark_as_reserved(kernel_virtual_memory::Segment::new(
+                self.segment.start,
+                self.segment.len,
+            ))
+            .map_err(|_| "Failed to reserve segment in new process")?;
+
+        // Map the same frames, so both processes keep sharing the memory
+        new_process.with_address_space(|as_| {
+            for (i, &(frame, flags)) in self.pages.iter().enumerate() {
+                let page = Page::<Size4KiB>::containing_address(
+                    new_segment.start + (i as u64 * Size4KiB::SIZE),
+                );
+                as_.map(page, frame, flags)
+                    .map_err(|_| "Failed to map memory in new process")?;
+            }
+            Ok::<_, &'static str>(())
+        })?;
+
+        Ok(SharedMemoryRegion {
+            segment: new_segment,
+            size: self.size,
+            pages: self.pages.clone(),
+            process: Arc::downgrade(new_process),
+            owner: self.owner.clone(),
+        })
+    }
+}
+
 impl LazyMemoryRegion {
     pub fn clone_to_process(&self, _new_process: &Arc<Process>) -> Result<Self, &'static str> {
         // TODO: Implement proper deep copy for Lazy regions.
@@ -288,6 +344,62 @@ impl Drop for MappedMemoryRegion {
     }
 }
 
+pub struct SharedMemoryRegion {
+    segment: OwnedSegment<'static>,
+    size: usize,
+    /// The frames mapped into the segment, in order, with the flags
+    /// they are mapped with.
+    pages: Vec<(PhysFrame, PageTableFlags)>,
+    /// The process whose address space `pages` are mapped into.
+    process: Weak<Process>,
+    /// Keeps the memory behind `pages` alive.
+    owner: Arc<dyn Any + Send + Sync>,
+}
+
+impl SharedMemoryRegion {
+    pub fn new(
+        segment: OwnedSegment<'static>,
+        size: usize,
+        pages: Vec<(PhysFrame, PageTableFlags)>,
+        process: &Arc<Process>,
+        owner: Arc<dyn Any + Send + Sync>,
+    ) -> Self {
+        Self {
+            segment,
+            size,
+            pages,
+            process: Arc::downgrade(process),
+            owner,
+        }
+    }
+
+    fn unmap(&self, address_space: &AddressSpace) {
+        address_space.with_active(|as_| {
+            // unmapping flushes the TLB entry of every page
+            as_.unmap_range::<Size4KiB>(&*self.segment, |_| {});
+        });
+    }
+}
+
+impl Drop for SharedMemoryRegion {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.upgrade() {
+            process.with_address_space(|as_| self.unmap(as_));
+        }
+        // `owner` is only released after this, once no page maps its memory anymore
+    }
+}
+
+impl fmt::Debug for SharedMemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedMemoryRegion")
+            .field("segment", &self.segment)
+            .field("size", &self.size)
+            .field("pages", &self.pages.len())
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct FileBackedMemoryRegion {
     region: LazyMemoryRegion,
@@ -47,6 +47,7 @@ pub mod telemetry;
 
 use crate::arch::UserContext;
 use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::wait::WaitQueue;
 use crate::mem::virt::VirtualMemoryAllocator;
 
 pub mod tree;
@@ -147,6 +148,8 @@ pub struct Process {
     ppid: RwLock<ProcessId>,
 
     exit_code: RwLock<Option<i32>>,
+    /// Woken whenever a child process exits.
+    child_exit: WaitQueue,
 
     executable_path: Option<AbsoluteOwnedPath>,
     executable_file_data: RwLock<Option<LowerHalfAllocation<Executable>>>,
@@ -173,6 +176,7 @@ impl Process {
                 name: "root".to_string(),
                 ppid: RwLock::new(pid),
                 exit_code: RwLock::new(None),
+                child_exit: WaitQueue::new(),
                 executable_path: None,
                 executable_file_data: RwLock::new(None),
                 current_working_directory: RwLock::new(ROOT.to_owned()),
@@ -208,6 +212,7 @@ impl Process {
             name,
             ppid: RwLock::new(parent_pid),
             exit_code: RwLock::new(None),
+            child_exit: WaitQueue::new(),
             executable_path: executable_path.map(|x| x.as_ref().to_owned()),
             executable_file_data: RwLock::new(None),
             current_working_directory: RwLock::new(parent.current_working_directory.read().clone()),
@@ -274,6 +279,24 @@ impl Process {
         &self.exit_code
     }
 
+    /// Record that the process exited with `status`, and wake its parent
+    /// should it wait for its children.
+    pub fn set_exit_code(&self, status: i32) {
+        *self.exit_code.write() = Some(status);
+        let parent = process_tree()
+            .read()
+            .processes
+            .get(&*self.ppid.read())
+            .cloned();
+        if let Some(parent) = parent {
+            parent.child_exit.wake_all();
+        }
+    }
+
+    pub fn child_exit(&self) -> &WaitQueue {
+        &self.child_exit
+    }
+
     pub fn pid(&self) -> ProcessId {
         self.pid
     }
@@ -290,6 +313,16 @@ impl Process {
         &self.file_descriptors
     }
 
+    /// Close all open file descriptors.
+    ///
+    /// Called on exit, so that files and BPF objects the process owns are
+    /// released right away instead of when the parent reaps it.
+    pub fn close_file_descriptors(&self) {
+        // take the table first so descriptors are dropped without the lock held
+        let fds = core::mem::take(&mut *self.file_descriptors.write());
+        drop(fds);
+    }
+
     pub fn with_address_space<F, R>(&self, f: F) -> R
     where
         F: FnOnce(&AddressSpace) -> R,
@@ -568,6 +601,10 @@ impl Drop for Process {
             }
         }
 
+        if let Some(address_space) = self.address_space.get_mut().as_ref() {
+            self.memory_regions.unmap_shared(address_space);
+        }
+
         // TODO: deallocate all physical frames that are not part of a shared mapping
     }
 }
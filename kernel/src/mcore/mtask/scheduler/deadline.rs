@@ -0,0 +1,292 @@
+//! The deadline class: earliest deadline first, with a constant bandwidth
+//! server (CBS) per task.
+//!
+//! A deadline task gets `runtime_ns` of CPU time in every period of
+//! `period_ns`, which it has to use by `deadline_ns` after the period
+//! started. Ready deadline tasks outrank every other task, and among them the
+//! one with the earliest absolute deadline runs.
+//!
+//! Running uses up the runtime of the period. A task that used it up is
+//! throttled until its next period starts, so that it can't take the time
+//! of the others. A task that wakes up keeps its runtime and deadline,
+//! unless using that runtime by that deadline would exceed its bandwidth,
+//! in which case it starts a new period right away.
+//!
+//! Admission control keeps the bandwidth (runtime per period) of all
+//! deadline tasks together within [`BANDWIDTH_LIMIT`], so that every task
+//! can meet its deadlines.
+
+use core::mem::size_of;
+
+use kernel_bpf::execution::{
+    BpfContext, SchedDeadlineTraceContext, DEADLINE_BUDGET_EXHAUSTED, DEADLINE_MISSED,
+};
+use spin::Mutex;
+
+use crate::mcore::mtask::task::Task;
+
+/// The fixed point unit of bandwidths: a whole CPU.
+const BANDWIDTH_UNIT: u64 = 1 << 20;
+
+/// The share of a CPU that deadline tasks can reserve together. The rest is
+/// left to the other tasks and interrupt handling.
+pub const BANDWIDTH_LIMIT: u64 = BANDWIDTH_UNIT * 95 / 100;
+
+/// The bandwidth reserved by all deadline tasks.
+static RESERVED: Mutex<u64> = Mutex::new(0);
+
+/// The runtime, deadline and period of a deadline task.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DeadlineParams {
+    runtime_ns: u64,
+    deadline_ns: u64,
+    period_ns: u64,
+}
+
+impl DeadlineParams {
+    /// The shortest runtime per period. Shorter ones can't be accounted for.
+    pub const MIN_RUNTIME_NS: u64 = 1 << 10;
+
+    /// Parameters with `runtime_ns <= deadline_ns <= period_ns`, if they
+    /// are valid. A zero period equals the deadline.
+    #[must_use]
+    pub fn new(runtime_ns: u64, deadline_ns: u64, period_ns: u64) -> Option<Self> {
+        let period_ns = if period_ns == 0 {
+            deadline_ns
+        } else {
+            period_ns
+        };
+        // Deadlines are absolute kernel times, which must not overflow
+        let valid = Self::MIN_RUNTIME_NS <= runtime_ns
+            && runtime_ns <= deadline_ns
+            && deadline_ns <= period_ns
+            && period_ns < 1 << 63;
+        valid.then_some(Self {
+            runtime_ns,
+            deadline_ns,
+            period_ns,
+        })
+    }
+
+    #[must_use]
+    pub fn runtime_ns(self) -> u64 {
+        self.runtime_ns
+    }
+
+    #[must_use]
+    pub fn deadline_ns(self) -> u64 {
+        self.deadline_ns
+    }
+
+    #[must_use]
+    pub fn period_ns(self) -> u64 {
+        self.period_ns
+    }
+
+    /// The share of a CPU the task needs, in units of [`BANDWIDTH_UNIT`].
+    fn bandwidth(self) -> u64 {
+        let bandwidth =
+            u128::from(self.runtime_ns) * u128::from(BANDWIDTH_UNIT) / u128::from(self.period_ns);
+        // At most a whole CPU, as the runtime can't exceed the period
+        bandwidth as u64
+    }
+}
+
+/// The bandwidth admitted for a task, released when dropped.
+#[derive(Debug)]
+struct Reservation(u64);
+
+impl Reservation {
+    /// Reserve `bandwidth`, if it fits once `replacing` is released.
+    fn admit(bandwidth: u64, replacing: u64) -> Option<Self> {
+        let mut reserved = RESERVED.lock();
+        let total = reserved.saturating_sub(replacing) + bandwidth;
+        if total > BANDWIDTH_LIMIT {
+            return None;
+        }
+        // `replacing` is released when its reservation is dropped
+        *reserved += bandwidth;
+        Some(Self(bandwidth))
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut reserved = RESERVED.lock();
+        *reserved = reserved.saturating_sub(self.0);
+    }
+}
+
+/// How a deadline task ran late.
+#[derive(Copy, Clone, Debug)]
+pub struct Overrun {
+    /// [`DEADLINE_BUDGET_EXHAUSTED`] or [`DEADLINE_MISSED`].
+    kind: u32,
+    /// The absolute deadline of the period that overran.
+    deadline: u64,
+    /// The overruns of the task so far, this one included.
+    count: u64,
+    params: DeadlineParams,
+}
+
+/// The server state of a deadline task.
+#[derive(Debug)]
+pub struct DeadlineTask {
+    params: DeadlineParams,
+    _reservation: Reservation,
+    /// The absolute deadline of the current period.
+    deadline: u64,
+    /// The runtime left in the current period.
+    remaining_ns: u64,
+    /// When the next period starts, if the task used up its runtime.
+    throttled_until: Option<u64>,
+    overruns: u64,
+}
+
+impl DeadlineTask {
+    /// The server for a task with `params`, starting a period at `now`.
+    ///
+    /// Returns `None` if the bandwidth isn't available. The bandwidth of
+    /// `previous`, the task's current server, counts as available.
+    #[must_use]
+    pub fn admit(params: DeadlineParams, previous: Option<&Self>, now: u64) -> Option<Self> {
+        let replacing = previous.map_or(0, |previous| previous.params.bandwidth());
+        let reservation = Reservation::admit(params.bandwidth(), replacing)?;
+        Some(Self {
+            params,
+            _reservation: reservation,
+            deadline: now + params.deadline_ns,
+            remaining_ns: params.runtime_ns,
+            throttled_until: None,
+            overruns: 0,
+        })
+    }
+
+    #[must_use]
+    pub fn params(&self) -> DeadlineParams {
+        self.params
+    }
+
+    /// The absolute deadline of the current period.
+    #[must_use]
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// When the next period starts, if the task used up its runtime.
+    #[must_use]
+    pub fn throttled_until(&self) -> Option<u64> {
+        self.throttled_until
+    }
+
+    /// Account for the task having run for `elapsed_ns` until `now`.
+    pub(super) fn charge(&mut self, elapsed_ns: u64, now: u64) -> Option<Overrun> {
+        if let Some(until) = self.throttled_until {
+            // Nothing else was ready, so the task kept running throttled
+            if now >= until {
+                self.replenish(now);
+            }
+            return None;
+        }
+
+        self.remaining_ns = self.remaining_ns.saturating_sub(elapsed_ns);
+        let kind = if now >= self.deadline {
+            DEADLINE_MISSED
+        } else if self.remaining_ns == 0 {
+            DEADLINE_BUDGET_EXHAUSTED
+        } else {
+            return None;
+        };
+
+        self.overruns += 1;
+        let overrun = Overrun {
+            kind,
+            deadline: self.deadline,
+            count: self.overruns,
+            params: self.params,
+        };
+        if kind == DEADLINE_MISSED {
+            self.replenish(now);
+        } else {
+            self.throttled_until = Some(self.next_period());
+        }
+        Some(overrun)
+    }
+
+    /// Update the server of a task that becomes ready at `now` after it
+    /// blocked.
+    pub(super) fn wake(&mut self, now: u64) {
+        if let Some(until) = self.throttled_until {
+            if now >= until {
+                self.replenish(now);
+            }
+            return;
+        }
+
+        // The CBS wake-up rule: start a new period if the runtime left
+        // can't be used by the deadline within the task's bandwidth.
+        let overflows = self.deadline <= now
+            || u128::from(self.remaining_ns) * u128::from(self.params.period_ns)
+                > u128::from(self.deadline - now) * u128::from(self.params.runtime_ns);
+        if overflows {
+            self.deadline = now + self.params.deadline_ns;
+            self.remaining_ns = self.params.runtime_ns;
+        }
+    }
+
+    /// The start of the period after the current one.
+    fn next_period(&self) -> u64 {
+        self.deadline - self.params.deadline_ns + self.params.period_ns
+    }
+
+    /// Start the next period with a full runtime, or a new one at `now` if
+    /// the task fell behind by more than a period.
+    fn replenish(&mut self, now: u64) {
+        self.deadline += self.params.period_ns;
+        if self.deadline <= now {
+            self.deadline = now + self.params.deadline_ns;
+        }
+        self.remaining_ns = self.params.runtime_ns;
+        self.throttled_until = None;
+    }
+}
+
+/// Run the programs attached to the deadline overrun tracepoint.
+pub(super) fn report_overrun(task: &Task, overrun: Overrun) {
+    log::debug!(
+        "task {} overran its deadline {} ({})",
+        task.id(),
+        overrun.deadline,
+        if overrun.kind == DEADLINE_MISSED {
+            "missed"
+        } else {
+            "budget exhausted"
+        }
+    );
+
+    let trace_ctx = SchedDeadlineTraceContext {
+        timestamp: crate::time::get_kernel_time_ns(),
+        pid: task.process().pid().as_u64(),
+        tid: task.id().as_u64(),
+        kind: overrun.kind,
+        _pad: 0,
+        overruns: overrun.count,
+        deadline: overrun.deadline,
+        runtime_ns: overrun.params.runtime_ns,
+        deadline_ns: overrun.params.deadline_ns,
+        period_ns: overrun.params.period_ns,
+    };
+
+    // SAFETY: We are creating a slice from a stack-allocated struct.
+    // The slice is only used within this scope to create the BpfContext.
+    let slice = unsafe {
+        core::slice::from_raw_parts(
+            &trace_ctx as *const _ as *const u8,
+            size_of::<SchedDeadlineTraceContext>(),
+        )
+    };
+    crate::bpf::hooks::run_hooks(
+        crate::bpf::ATTACH_TYPE_SCHED_DEADLINE,
+        &BpfContext::from_slice(slice),
+    );
+}
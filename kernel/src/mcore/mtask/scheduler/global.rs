@@ -1,29 +1,102 @@
+//! The run queues of all CPUs, and how tasks are spread across them.
+//!
+//! Every CPU's scheduler runs the tasks of its own [`RunQueue`], so CPUs
+//! don't contend for a single queue. A task that becomes ready is placed on
+//! the CPU it last ran on, so that its caches are still warm, or else on the
+//! least loaded CPU it may run on. Each CPU checks on its timer tick for
+//! ready tasks on other CPUs that outrank its current task, or for any ready
+//! task if it is idle, and steals them.
+
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
 use core::pin::Pin;
 
 use conquer_once::spin::OnceCell;
 
-use crate::mcore::mtask::task::{Task, TaskQueue};
+use crate::mcore::cpu_count;
+use crate::mcore::mtask::scheduler::run_queue::RunQueue;
+use crate::mcore::mtask::task::{AtomicCpuSet, CpuSet, Task};
+
+static RUN_QUEUES: OnceCell<Box<[RunQueue]>> = OnceCell::uninit();
 
-static GLOBAL_QUEUE: OnceCell<TaskQueue> = OnceCell::uninit();
+/// The CPUs whose scheduler runs.
+static ONLINE: AtomicCpuSet = AtomicCpuSet::new(CpuSet::EMPTY);
 
-fn global_queue() -> &'static TaskQueue {
-    GLOBAL_QUEUE.get().unwrap()
+fn run_queues() -> &'static [RunQueue] {
+    RUN_QUEUES.get().unwrap()
 }
 
 pub struct GlobalTaskQueue;
 
 impl GlobalTaskQueue {
+    /// Create a run queue for each of the [`cpu_count`] CPUs.
     pub fn init() {
-        GLOBAL_QUEUE.init_once(TaskQueue::new);
+        RUN_QUEUES.init_once(|| (0..cpu_count()).map(|_| RunQueue::new()).collect());
     }
 
+    /// Make a task ready on one of the CPUs it may run on.
     pub fn enqueue(task: Pin<Box<Task>>) {
-        global_queue().enqueue(task);
+        let cpu = Self::place(&task);
+        run_queues()[cpu].enqueue(task);
     }
 
+    /// The CPUs whose scheduler runs.
     #[must_use]
-    pub fn dequeue() -> Option<Pin<Box<Task>>> {
-        global_queue().dequeue()
+    pub fn online() -> CpuSet {
+        ONLINE.load()
+    }
+
+    /// The run queue of `cpu`, which is online from now on.
+    pub(super) fn bring_online(cpu: usize) -> &'static RunQueue {
+        ONLINE.insert(cpu);
+        &run_queues()[cpu]
+    }
+
+    /// Steal the highest ranking ready task of at least `min_level` that may
+    /// run on `cpu` from the other CPUs.
+    pub(super) fn steal(cpu: usize, min_level: usize) -> Option<Pin<Box<Task>>> {
+        let mut victims: Vec<_> = Self::others(cpu)
+            .filter_map(|queue| Some((queue.highest_ready_level()?, queue)))
+            .filter(|&(level, _)| level >= min_level)
+            .collect();
+        victims.sort_unstable_by_key(|&(level, _)| Reverse(level));
+        victims
+            .into_iter()
+            .find_map(|(_, queue)| queue.steal(cpu, min_level))
+    }
+
+    /// The run queues of the online CPUs other than `cpu`.
+    fn others(cpu: usize) -> impl Iterator<Item = &'static RunQueue> {
+        Self::online()
+            .iter()
+            .filter(move |&other| other != cpu)
+            .filter_map(|other| run_queues().get(other))
+    }
+
+    /// Whether `task` may run on `cpu`. A task whose CPUs are all offline
+    /// runs wherever it can.
+    pub(super) fn may_run(task: &Task, cpu: usize) -> bool {
+        let allowed = task.affinity().intersection(Self::online());
+        allowed.is_empty() || allowed.contains(cpu)
+    }
+
+    /// The CPU to make `task` ready on.
+    fn place(task: &Task) -> usize {
+        let online = Self::online().intersection(CpuSet::first(run_queues().len()));
+        let allowed = task.affinity().intersection(online);
+        // Before any scheduler runs, the boot CPU picks everything up
+        let allowed = match (allowed.is_empty(), online.is_empty()) {
+            (false, _) => allowed,
+            (true, false) => online,
+            (true, true) => return 0,
+        };
+        match task.last_cpu() {
+            Some(cpu) if allowed.contains(cpu) => cpu,
+            _ => allowed
+                .iter()
+                .min_by_key(|&cpu| run_queues()[cpu].len())
+                .unwrap_or(0),
+        }
     }
 }
@@ -23,12 +23,19 @@ use crate::mcore::context::ExecutionContext;
 #[cfg(all(target_arch = "aarch64", feature = "rpi5"))]
 use crate::mcore::mtask::process::Process;
 use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::run_queue::RunQueue;
 use crate::mcore::mtask::scheduler::switch::switch_impl;
-use crate::mcore::mtask::task::Task;
+use crate::mcore::mtask::scheduler::wait::BlockOn;
+use crate::mcore::mtask::task::{CpuSet, SchedParams, SchedPolicy, State, Task, TaskId};
+use crate::time::get_kernel_time_ns;
 
 pub mod cleanup;
+pub mod deadline;
 pub mod global;
+mod run_queue;
+pub mod sleep;
 mod switch;
+pub mod wait;
 
 #[cfg(all(target_arch = "aarch64", feature = "rpi5"))]
 static SCHED_SWITCH_MARKER_SENT: AtomicBool = AtomicBool::new(false);
@@ -57,12 +64,27 @@ fn dbg_hex_nibble(n: u8) -> u32 {
 
 #[derive(Debug)]
 pub struct Scheduler {
+    /// The CPU this scheduler runs on.
+    cpu: usize,
+    /// The ready tasks of this CPU.
+    run_queue: &'static RunQueue,
     /// The task that is currently executing in this scheduler.
     current_task: Pin<Box<Task>>,
     /// The task this scheduler last switched away from. We need this to
     /// eliminate the race condition between re-queueing a task and
     /// actually switching away from it.
     zombie_task: Option<Pin<Box<Task>>>,
+    /// Where the current task is parked once this scheduler switches away
+    /// from it, if it is blocking.
+    blocking: Option<BlockOn>,
+    /// Where the zombie task is parked, if it blocked.
+    zombie_block: Option<BlockOn>,
+    /// When the runtime of the current task was last accounted for.
+    charged_at: u64,
+    /// The idle task of this CPU, while another task runs.
+    idle: Option<Pin<Box<Task>>>,
+    /// The ID of the idle task, once there is one.
+    idle_id: Option<TaskId>,
     /// A dummy location that is a placeholder for the switch code to write the old stack
     /// pointer to if the old task is terminated.
     dummy_old_stack_ptr: UnsafeCell<usize>,
@@ -70,17 +92,32 @@ pub struct Scheduler {
 
 impl Scheduler {
     #[must_use]
-    pub fn new_cpu_local() -> Self {
+    pub fn new_cpu_local(cpu: usize) -> Self {
         // SAFETY: We are creating a task representing the current CPU execution state.
         // This is done once per CPU during initialization.
         let current_task = Box::pin(unsafe { Task::create_current() });
+        // It becomes this CPU's idle task eventually, so it must stay here
+        current_task.set_affinity(CpuSet::single(cpu));
         Self {
+            cpu,
+            run_queue: GlobalTaskQueue::bring_online(cpu),
             current_task,
             zombie_task: None,
+            blocking: None,
+            zombie_block: None,
+            charged_at: 0,
+            idle: None,
+            idle_id: None,
             dummy_old_stack_ptr: UnsafeCell::new(0),
         }
     }
 
+    /// Make the current task the idle task of this CPU, which only runs
+    /// when no other task is ready here.
+    pub fn make_current_idle(&mut self) {
+        self.idle_id = Some(self.current_task.id());
+    }
+
     /// # Safety
     /// Trivially unsafe. If you don't know why, please don't call this function.
     // SAFETY: This function performs a context switch, which is inherently unsafe.
@@ -92,15 +129,11 @@ impl Scheduler {
         #[cfg(all(target_arch = "aarch64", feature = "aarch64_arch"))]
         assert!(!Arch::are_interrupts_enabled());
 
+        // Whatever runs next starts its runtime now
+        self.charge();
+
         // in theory, we could move this to the end of this function, but I'd rather not do this right now
-        if let Some(zombie_task) = self.zombie_task.take() {
-            // log::info!("reschedule: cleaning up zombie task {}", zombie_task.id());
-            if zombie_task.should_terminate() {
-                TaskCleanup::enqueue(zombie_task);
-            } else {
-                GlobalTaskQueue::enqueue(zombie_task);
-            }
-        }
+        self.requeue_zombie();
 
         let (next_task, cr3_value) = {
             let next_task_opt = self.next_task();
@@ -156,6 +189,8 @@ impl Scheduler {
             (next_task, cr3_value)
         };
 
+        next_task.set_state(State::Running);
+        next_task.set_last_cpu(self.cpu);
         let mut old_task = self.swap_current_task(next_task);
         // log::trace!("reschedule: swapped current task, old task was {}", old_task.id());
         let old_stack_ptr = if old_task.should_terminate() {
@@ -191,6 +226,7 @@ impl Scheduler {
 
         assert!(self.zombie_task.is_none());
         self.zombie_task = Some(old_task);
+        self.zombie_block = self.blocking.take();
 
         // log::trace!("reschedule: calling switch_impl (old_sp_ptr={:p}, new_sp={:#x}, ttbr0={:#x})",
         //     old_stack_ptr, *self.current_task.last_stack_ptr(), cr3_value);
@@ -208,6 +244,160 @@ impl Scheduler {
         // log::trace!("reschedule: switch_impl returned");
     }
 
+    /// Reschedule on a timer tick, unless the current task outranks every
+    /// ready task.
+    ///
+    /// Real-time tasks always preempt time-shared ones, and higher priorities
+    /// preempt lower ones. On equal priority, a [`SchedPolicy::Fifo`] task
+    /// keeps running while the others are time-sliced. Deadline tasks
+    /// preempt every other task, and each other by earlier deadlines.
+    ///
+    /// Ready tasks of other CPUs that outrank the current task are stolen
+    /// first, and an idle CPU steals any task it may run.
+    ///
+    /// # Safety
+    /// Same as [`reschedule`](Self::reschedule).
+    pub unsafe fn preempt(&mut self) {
+        self.charge();
+        if self.is_outranked(false) {
+            // SAFETY: Upheld by the caller.
+            unsafe { self.reschedule() };
+        }
+    }
+
+    /// Hand the CPU to the next ready task of at least the current task's
+    /// priority, if there is one.
+    ///
+    /// # Safety
+    /// Same as [`reschedule`](Self::reschedule).
+    pub unsafe fn yield_now(&mut self) {
+        if self.is_outranked(true) {
+            // SAFETY: Upheld by the caller.
+            unsafe { self.reschedule() };
+        }
+    }
+
+    /// Whether a ready task should replace the current one. A yielding task
+    /// gives way to equal priorities, regardless of its policy.
+    fn is_outranked(&mut self, yielding: bool) -> bool {
+        // The zombie is ready too, and has to compete with the current task.
+        self.requeue_zombie();
+        self.pull();
+
+        let Some(ready_level) = self.run_queue.highest_ready_level() else {
+            // Only the idle task can take over
+            return self.idle.is_some() && !self.can_continue();
+        };
+        if self.is_idle(&self.current_task) || !self.can_continue() {
+            return true;
+        }
+        let params = self.current_task.sched_params();
+        if ready_level == SchedParams::DEADLINE_LEVEL && params.policy() == SchedPolicy::Deadline {
+            let ready = self.run_queue.earliest_deadline().unwrap_or(u64::MAX);
+            let current = self.current_task.absolute_deadline().unwrap_or(u64::MAX);
+            return ready < current || (yielding && ready == current);
+        }
+        ready_level > params.level()
+            || (ready_level == params.level() && (yielding || params.policy() != SchedPolicy::Fifo))
+    }
+
+    /// Whether the current task can keep running on this CPU.
+    fn can_continue(&self) -> bool {
+        self.current_task.state() == State::Running
+            && !self.current_task.should_terminate()
+            && self.current_task.throttled_until().is_none()
+            && GlobalTaskQueue::may_run(&self.current_task, self.cpu)
+    }
+
+    fn is_idle(&self, task: &Task) -> bool {
+        self.idle_id == Some(task.id())
+    }
+
+    /// Steal a ready task from another CPU, if one would run here before the
+    /// current task and the local ready tasks.
+    fn pull(&self) {
+        let local_level = self.run_queue.highest_ready_level();
+        let min_level = if self.is_idle(&self.current_task) || !self.can_continue() {
+            if local_level.is_some() {
+                return;
+            }
+            0
+        } else {
+            // Equal priorities are time-sliced on the CPU they are on
+            let current_level = self.current_task.sched_params().level();
+            local_level.map_or(current_level, |level| level.max(current_level)) + 1
+        };
+        if let Some(task) = GlobalTaskQueue::steal(self.cpu, min_level) {
+            self.run_queue.enqueue(task);
+        }
+    }
+
+    /// Account the time since the last charge to the current task, if it is
+    /// a deadline task.
+    fn charge(&mut self) {
+        let now = get_kernel_time_ns();
+        let elapsed_ns = now.saturating_sub(self.charged_at);
+        self.charged_at = now;
+        let overrun = self
+            .current_task
+            .with_deadline_task(|server| server.charge(elapsed_ns, now))
+            .flatten();
+        if let Some(overrun) = overrun {
+            deadline::report_overrun(&self.current_task, overrun);
+        }
+    }
+
+    /// Park, clean up or requeue the task this scheduler last switched away
+    /// from.
+    fn requeue_zombie(&mut self) {
+        let Some(zombie_task) = self.zombie_task.take() else {
+            return;
+        };
+        // log::info!("reschedule: cleaning up zombie task {}", zombie_task.id());
+        let block = self.zombie_block.take();
+        if self.is_idle(&zombie_task) {
+            // The idle task is never queued, it runs when nothing else can
+            zombie_task.set_state(State::Ready);
+            self.idle = Some(zombie_task);
+        } else if zombie_task.should_terminate() {
+            TaskCleanup::enqueue(zombie_task);
+        } else if let Some(block) = block {
+            block.park(zombie_task);
+        } else if let Some(until) = zombie_task.throttled_until() {
+            // A throttled deadline task waits for its next period
+            zombie_task.set_state(State::Blocked);
+            BlockOn::Sleep { deadline_ns: until }.park(zombie_task);
+        } else {
+            zombie_task.set_state(State::Ready);
+            GlobalTaskQueue::enqueue(zombie_task);
+        }
+    }
+
+    /// Switch away from the current task and park it as `on` describes.
+    ///
+    /// If no other task is ready, this returns right away and the block
+    /// stays pending until [`unblock`](Self::unblock) or the next switch.
+    ///
+    /// # Safety
+    /// Same as [`reschedule`](Self::reschedule).
+    unsafe fn block(&mut self, on: BlockOn) {
+        self.current_task.set_state(State::Blocked);
+        self.blocking = Some(on);
+        // SAFETY: Upheld by the caller.
+        unsafe { self.reschedule() };
+    }
+
+    /// Whether the current task blocked, but hasn't been switched away from.
+    fn is_blocking(&self) -> bool {
+        self.blocking.is_some()
+    }
+
+    /// Keep running the current task, dropping a pending block.
+    fn unblock(&mut self) {
+        self.blocking = None;
+        self.current_task.set_state(State::Running);
+    }
+
     // SAFETY: Low-level context switch implementation.
     unsafe fn switch(old_stack_ptr: &mut usize, new_stack_ptr: usize, new_cr3_value: usize) {
         // SAFETY: Calling the assembly implementation of context switch.
@@ -231,8 +421,17 @@ impl Scheduler {
         next_task
     }
 
-    #[allow(clippy::unused_self)]
-    fn next_task(&self) -> Option<Pin<Box<Task>>> {
-        GlobalTaskQueue::dequeue()
+    /// The next task to run: the ready task with the highest priority, or
+    /// the idle task if none is ready and the current task can't continue.
+    fn next_task(&mut self) -> Option<Pin<Box<Task>>> {
+        self.pull();
+        if let Some(task) = self.run_queue.dequeue() {
+            return Some(task);
+        }
+        if self.can_continue() {
+            None
+        } else {
+            self.idle.take()
+        }
     }
 }
@@ -0,0 +1,141 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::pin::Pin;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering::Relaxed;
+
+use spin::Mutex;
+
+use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::wait::without_interrupts;
+use crate::mcore::mtask::task::{SchedParams, SchedPolicy, Task, TaskId};
+
+/// The ready tasks of one CPU.
+///
+/// The CPU's scheduler takes its tasks from here, other CPUs add the tasks
+/// placed on it and steal from it.
+#[derive(Debug)]
+pub struct RunQueue {
+    queues: Mutex<Queues>,
+    /// The number of ready tasks, readable without the lock.
+    len: AtomicUsize,
+    /// One above the highest level with a ready task, 0 if there is none.
+    /// Readable without the lock.
+    top: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Queues {
+    /// Ready deadline tasks, by absolute deadline.
+    deadline: BTreeMap<(u64, TaskId), Pin<Box<Task>>>,
+    /// One FIFO queue of ready tasks per priority level.
+    levels: [VecDeque<Pin<Box<Task>>>; SchedParams::LEVELS],
+}
+
+impl Queues {
+    fn highest_ready_level(&self) -> Option<usize> {
+        if !self.deadline.is_empty() {
+            return Some(SchedParams::DEADLINE_LEVEL);
+        }
+        (0..SchedParams::LEVELS)
+            .rev()
+            .find(|&level| !self.levels[level].is_empty())
+    }
+}
+
+impl RunQueue {
+    pub(super) fn new() -> Self {
+        Self {
+            queues: Mutex::new(Queues {
+                deadline: BTreeMap::new(),
+                levels: core::array::from_fn(|_| VecDeque::new()),
+            }),
+            len: AtomicUsize::new(0),
+            top: AtomicUsize::new(0),
+        }
+    }
+
+    pub(super) fn enqueue(&self, task: Pin<Box<Task>>) {
+        self.update(|queues| {
+            if task.sched_params().policy() == SchedPolicy::Deadline {
+                let deadline = task.absolute_deadline().unwrap_or(u64::MAX);
+                queues.deadline.insert((deadline, task.id()), task);
+            } else {
+                queues.levels[task.sched_params().level()].push_back(task);
+            }
+        });
+    }
+
+    /// Dequeue the ready deadline task with the earliest deadline, or else
+    /// the task that waited longest on the highest priority level.
+    pub(super) fn dequeue(&self) -> Option<Pin<Box<Task>>> {
+        self.update(|queues| {
+            if let Some((_, task)) = queues.deadline.pop_first() {
+                return Some(task);
+            }
+            let level = queues.highest_ready_level()?;
+            queues.levels[level].pop_front()
+        })
+    }
+
+    /// Like [`dequeue`](Self::dequeue), but only the tasks of at least
+    /// `min_level` that may run on `cpu`.
+    pub(super) fn steal(&self, cpu: usize, min_level: usize) -> Option<Pin<Box<Task>>> {
+        self.update(|queues| {
+            let key = queues
+                .deadline
+                .iter()
+                .find(|(_, task)| GlobalTaskQueue::may_run(task, cpu))
+                .map(|(&key, _)| key);
+            if let Some(key) = key {
+                return queues.deadline.remove(&key);
+            }
+            let levels = queues.levels.get_mut(min_level..)?;
+            levels.iter_mut().rev().find_map(|level| {
+                let index = level
+                    .iter()
+                    .position(|task| GlobalTaskQueue::may_run(task, cpu))?;
+                level.remove(index)
+            })
+        })
+    }
+
+    /// The highest priority level that has a ready task.
+    pub(super) fn highest_ready_level(&self) -> Option<usize> {
+        self.top.load(Relaxed).checked_sub(1)
+    }
+
+    /// The earliest absolute deadline of the ready deadline tasks.
+    pub(super) fn earliest_deadline(&self) -> Option<u64> {
+        // The timer interrupt takes the lock too, to make sleepers ready
+        without_interrupts(|| {
+            let queues = self.queues.lock();
+            queues
+                .deadline
+                .first_key_value()
+                .map(|(&(deadline, _), _)| deadline)
+        })
+    }
+
+    /// The number of ready tasks.
+    pub(super) fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    /// Change the queues with `f` and refresh the hints.
+    ///
+    /// Interrupts are disabled meanwhile, as the timer interrupt enqueues
+    /// the sleepers it wakes.
+    fn update<R>(&self, f: impl FnOnce(&mut Queues) -> R) -> R {
+        without_interrupts(|| {
+            let mut queues = self.queues.lock();
+            let result = f(&mut queues);
+            let len =
+                queues.deadline.len() + queues.levels.iter().map(VecDeque::len).sum::<usize>();
+            self.len.store(len, Relaxed);
+            let top = queues.highest_ready_level().map_or(0, |level| level + 1);
+            self.top.store(top, Relaxed);
+            result
+        })
+    }
+}
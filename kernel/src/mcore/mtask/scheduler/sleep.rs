@@ -0,0 +1,55 @@
+//! The sleep queue: tasks blocked until a deadline, woken from the timer
+//! interrupt.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::pin::Pin;
+
+use spin::Mutex;
+
+use crate::mcore::mtask::scheduler::wait::{block_current, make_ready, BlockOn};
+use crate::mcore::mtask::task::{Task, TaskId};
+use crate::time::get_kernel_time_ns;
+
+/// Sleeping tasks, ordered by deadline.
+static SLEEPERS: Mutex<BTreeMap<(u64, TaskId), Pin<Box<Task>>>> = Mutex::new(BTreeMap::new());
+
+/// Block the current task until the kernel time reaches `deadline_ns`.
+pub fn sleep_until(deadline_ns: u64) {
+    while get_kernel_time_ns() < deadline_ns {
+        block_current(BlockOn::Sleep { deadline_ns });
+    }
+}
+
+/// Block the current task for at least `duration_ns` nanoseconds.
+pub fn sleep_ns(duration_ns: u64) {
+    sleep_until(get_kernel_time_ns().saturating_add(duration_ns));
+}
+
+/// Wake the tasks whose deadline has passed.
+///
+/// Called from the timer interrupt.
+pub fn wake_expired() {
+    let now = get_kernel_time_ns();
+    let mut sleepers = SLEEPERS.lock();
+    while let Some(entry) = sleepers.first_entry() {
+        if entry.key().0 > now {
+            break;
+        }
+        make_ready(entry.remove());
+    }
+}
+
+/// Take the task sleeping until `deadline_ns` off the sleep queue, unless
+/// it was woken already.
+pub(super) fn take(deadline_ns: u64, id: TaskId) -> Option<Pin<Box<Task>>> {
+    SLEEPERS.lock().remove(&(deadline_ns, id))
+}
+
+pub(super) fn park(task: Pin<Box<Task>>, deadline_ns: u64) {
+    if deadline_ns <= get_kernel_time_ns() {
+        make_ready(task);
+    } else {
+        SLEEPERS.lock().insert((deadline_ns, task.id()), task);
+    }
+}
@@ -0,0 +1,275 @@
+//! Wait queues that tasks block on until another task or an interrupt
+//! handler wakes them.
+//!
+//! A blocking task isn't parked right away: it keeps running until the
+//! scheduler has switched to another task, and only then is moved onto its
+//! queue. A wake in between must not be lost, so every queue counts its
+//! wakes, and a task that was woken since it decided to block is put back
+//! on the run queue instead of being parked.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering::SeqCst;
+
+use spin::Mutex;
+#[cfg(target_arch = "x86_64")]
+use x86_64::instructions::interrupts;
+
+#[cfg(all(target_arch = "aarch64", feature = "aarch64_arch"))]
+use crate::arch::aarch64::Aarch64 as Arch;
+#[cfg(all(target_arch = "aarch64", feature = "aarch64_arch"))]
+use crate::arch::traits::Architecture;
+use crate::mcore::context::ExecutionContext;
+use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::scheduler::sleep;
+use crate::mcore::mtask::task::{State, Task, TaskId};
+use crate::time::get_kernel_time_ns;
+
+/// Where a blocking task is parked once the scheduler switched away from it.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum BlockOn {
+    /// A wait queue, unless it was woken after `generation`.
+    ///
+    /// With a deadline, the task waits on the sleep queue instead, and the
+    /// wait queue only remembers where to find it. It is then woken by
+    /// whichever comes first.
+    ///
+    /// The queue outlives the wait, as the blocked task borrows it.
+    Queue {
+        queue: NonNull<WaitQueue>,
+        generation: u64,
+        deadline_ns: Option<u64>,
+    },
+    /// The sleep queue, until the kernel time reaches `deadline_ns`.
+    Sleep { deadline_ns: u64 },
+}
+
+impl BlockOn {
+    /// Park `task`, or make it ready if it was woken already.
+    pub(super) fn park(self, task: Pin<Box<Task>>) {
+        match self {
+            // SAFETY: The blocked task borrows the queue until it is woken.
+            Self::Queue {
+                queue,
+                generation,
+                deadline_ns,
+            } => unsafe { queue.as_ref() }.park(task, generation, deadline_ns),
+            Self::Sleep { deadline_ns } => sleep::park(task, deadline_ns),
+        }
+    }
+}
+
+/// A task waiting on a [`WaitQueue`].
+#[derive(Debug)]
+enum Waiter {
+    /// Parked on the wait queue itself.
+    Parked(Pin<Box<Task>>),
+    /// Parked on the sleep queue until `deadline_ns`, unless woken before.
+    Sleeping { deadline_ns: u64, id: TaskId },
+}
+
+/// A queue of tasks blocked until some condition holds.
+///
+/// Wakers make the condition hold and then call [`wake_one`](Self::wake_one)
+/// or [`wake_all`](Self::wake_all); both can be called from interrupt
+/// handlers.
+#[derive(Debug)]
+pub struct WaitQueue {
+    /// Incremented by every wake.
+    generation: AtomicU64,
+    waiters: Mutex<VecDeque<Waiter>>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block the current task until `f` returns `Some`, and return its value.
+    ///
+    /// `f` is called before every block and after every wake-up, which may
+    /// be spurious.
+    pub fn wait_until<T>(&self, mut f: impl FnMut() -> Option<T>) -> T {
+        loop {
+            let generation = self.generation.load(SeqCst);
+            if let Some(value) = f() {
+                return value;
+            }
+            block_current(BlockOn::Queue {
+                queue: NonNull::from(self),
+                generation,
+                deadline_ns: None,
+            });
+        }
+    }
+
+    /// Like [`wait_until`](Self::wait_until), but give up and return `None`
+    /// once the kernel time reaches `deadline_ns`.
+    pub fn wait_until_deadline<T>(
+        &self,
+        deadline_ns: u64,
+        mut f: impl FnMut() -> Option<T>,
+    ) -> Option<T> {
+        let id = ExecutionContext::load().current_task().id();
+        loop {
+            let generation = self.generation.load(SeqCst);
+            if let Some(value) = f() {
+                return Some(value);
+            }
+            if get_kernel_time_ns() >= deadline_ns {
+                return None;
+            }
+            block_current(BlockOn::Queue {
+                queue: NonNull::from(self),
+                generation,
+                deadline_ns: Some(deadline_ns),
+            });
+            // After a timeout, the task is still remembered here
+            without_interrupts(|| {
+                self.waiters.lock().retain(
+                    |waiter| !matches!(waiter, Waiter::Sleeping { id: other, .. } if *other == id),
+                );
+            });
+        }
+    }
+
+    /// Wake the task that has waited longest.
+    pub fn wake_one(&self) {
+        self.wake(1);
+    }
+
+    /// Wake every waiting task.
+    pub fn wake_all(&self) {
+        self.wake(usize::MAX);
+    }
+
+    fn wake(&self, count: usize) {
+        without_interrupts(|| {
+            self.generation.fetch_add(1, SeqCst);
+            let mut waiters = self.waiters.lock();
+            let mut woken = 0;
+            while woken < count {
+                let Some(waiter) = waiters.pop_front() else {
+                    break;
+                };
+                let task = match waiter {
+                    Waiter::Parked(task) => Some(task),
+                    // Gone already if the deadline passed
+                    Waiter::Sleeping { deadline_ns, id } => sleep::take(deadline_ns, id),
+                };
+                if let Some(task) = task {
+                    make_ready(task);
+                    woken += 1;
+                }
+            }
+        });
+    }
+
+    fn park(&self, task: Pin<Box<Task>>, generation: u64, deadline_ns: Option<u64>) {
+        let mut waiters = self.waiters.lock();
+        if self.generation.load(SeqCst) != generation {
+            drop(waiters);
+            make_ready(task);
+            return;
+        }
+        match deadline_ns {
+            None => waiters.push_back(Waiter::Parked(task)),
+            Some(deadline_ns) => {
+                waiters.push_back(Waiter::Sleeping {
+                    deadline_ns,
+                    id: task.id(),
+                });
+                // Still holding the queue, so no wake can miss the sleeper
+                sleep::park(task, deadline_ns);
+            }
+        }
+    }
+}
+
+/// Put a woken task back on the run queue.
+pub(super) fn make_ready(task: Pin<Box<Task>>) {
+    task.with_deadline_task(|server| server.wake(get_kernel_time_ns()));
+    task.set_state(State::Ready);
+    GlobalTaskQueue::enqueue(task);
+}
+
+/// Switch away from the current task and park it as `on` describes.
+///
+/// Returns once the task was woken, or after idling until the next interrupt
+/// if no other task was ready to run. Either way, the caller has to check
+/// again what it waits for.
+pub(super) fn block_current(on: BlockOn) {
+    without_interrupts(|| {
+        // SAFETY: Interrupts are disabled, so nothing else uses this CPU's
+        // scheduler.
+        unsafe { ExecutionContext::load().scheduler_mut().block(on) };
+
+        // The task may have been woken on another CPU.
+        if ExecutionContext::load().scheduler().is_blocking() {
+            // Nothing else was ready. Idle with the block in place, so a
+            // reschedule from the interrupt still parks the task.
+            wait_for_interrupt();
+        }
+        // SAFETY: As above.
+        unsafe { ExecutionContext::load().scheduler_mut().unblock() };
+    });
+}
+
+/// Run `f` with interrupts disabled, so it can take locks that interrupt
+/// handlers take too.
+pub(crate) fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(target_arch = "x86_64")]
+    {
+        interrupts::without_interrupts(f)
+    }
+    #[cfg(all(target_arch = "aarch64", feature = "aarch64_arch"))]
+    {
+        let enabled = Arch::are_interrupts_enabled();
+        Arch::disable_interrupts();
+        let result = f();
+        if enabled {
+            Arch::enable_interrupts();
+        }
+        result
+    }
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", feature = "aarch64_arch")
+    )))]
+    {
+        f()
+    }
+}
+
+/// Halt until the next interrupt has been handled.
+fn wait_for_interrupt() {
+    #[cfg(target_arch = "x86_64")]
+    {
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+    }
+    #[cfg(all(target_arch = "aarch64", feature = "aarch64_arch"))]
+    {
+        Arch::enable_interrupts();
+        Arch::wait_for_interrupt();
+        Arch::disable_interrupts();
+    }
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", feature = "aarch64_arch")
+    )))]
+    core::hint::spin_loop();
+}
@@ -5,17 +5,18 @@ use alloc::sync::Arc;
 use core::ffi::c_void;
 use core::pin::Pin;
 use core::ptr::NonNull;
-use core::sync::atomic::AtomicBool;
 use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
 
 use cordyceps::mpsc_queue::Links;
 use cordyceps::Linked;
 use log::trace;
-use spin::RwLock;
+use spin::{Mutex, RwLock};
 
 use crate::arch::UserContext;
 use crate::mcore::context::ExecutionContext;
 use crate::mcore::mtask::process::Process;
+use crate::mcore::mtask::scheduler::deadline::DeadlineTask;
 use crate::mem::memapi::{LowerHalfAllocation, Writable};
 use crate::U64Ext;
 
@@ -23,6 +24,8 @@ mod id;
 pub use id::*;
 mod queue;
 pub use queue::*;
+mod sched;
+pub use sched::*;
 mod stack;
 pub use stack::*;
 mod state;
@@ -44,7 +47,15 @@ pub struct Task {
     /// If this task is currently running, then this value is not the current stack pointer.
     /// This must be set during the context switch.
     last_stack_ptr: Pin<Box<usize>>,
-    state: State,
+    state: AtomicState,
+    /// The scheduling policy and priority of the task.
+    sched_params: AtomicSchedParams,
+    /// The server of a [`SchedPolicy::Deadline`] task.
+    deadline: Mutex<Option<DeadlineTask>>,
+    /// The CPUs the task may run on.
+    affinity: AtomicCpuSet,
+    /// The CPU the task last ran on, or `usize::MAX` if it never ran.
+    last_cpu: AtomicUsize,
     /// The kernel stack of the task. Every task starts with a stack in the higher half.
     /// Userspace tasks will then allocate a stack in the lower half, which will be stored in
     /// `ustack`.
@@ -111,7 +122,7 @@ impl Task {
         let name = format!("task-{tid}");
         let process = process.clone();
         let should_terminate = AtomicBool::new(false);
-        let state = State::Ready;
+        let state = AtomicState::new(State::Ready);
         let last_stack_ptr = Box::pin(stack.initial_rsp().as_u64().into_usize());
         let links = Links::default();
         Self {
@@ -121,6 +132,10 @@ impl Task {
             should_terminate,
             last_stack_ptr,
             state,
+            sched_params: AtomicSchedParams::new(SchedParams::NORMAL),
+            deadline: Mutex::new(None),
+            affinity: AtomicCpuSet::new(CpuSet::ALL),
+            last_cpu: AtomicUsize::new(usize::MAX),
             kstack: Some(stack),
             ustack: RwLock::new(None),
             tls: RwLock::new(None),
@@ -135,7 +150,7 @@ impl Task {
         let process = Process::root().clone();
         let should_terminate = AtomicBool::new(false);
         let last_stack_ptr = Box::pin(0);
-        let state = State::Finished;
+        let state = AtomicState::new(State::Finished);
         let links = Links::new_stub();
         Self {
             tid,
@@ -144,6 +159,10 @@ impl Task {
             should_terminate,
             last_stack_ptr,
             state,
+            sched_params: AtomicSchedParams::new(SchedParams::NORMAL),
+            deadline: Mutex::new(None),
+            affinity: AtomicCpuSet::new(CpuSet::ALL),
+            last_cpu: AtomicUsize::new(usize::MAX),
             kstack: None,
             ustack: RwLock::new(None),
             tls: RwLock::new(None),
@@ -216,7 +235,7 @@ impl Task {
         let current_sp = 0;
 
         let last_stack_ptr = Box::pin(current_sp);
-        let state = State::Running;
+        let state = AtomicState::new(State::Running);
         Self {
             tid,
             name,
@@ -224,6 +243,10 @@ impl Task {
             should_terminate,
             last_stack_ptr,
             state,
+            sched_params: AtomicSchedParams::new(SchedParams::NORMAL),
+            deadline: Mutex::new(None),
+            affinity: AtomicCpuSet::new(CpuSet::ALL),
+            last_cpu: AtomicUsize::new(usize::MAX),
             kstack: None,
             ustack: RwLock::new(None),
             tls: RwLock::new(None),
@@ -253,7 +276,68 @@ impl Task {
     }
 
     pub fn state(&self) -> State {
-        self.state
+        self.state.load()
+    }
+
+    pub(in crate::mcore::mtask) fn set_state(&self, state: State) {
+        self.state.store(state);
+    }
+
+    pub fn sched_params(&self) -> SchedParams {
+        self.sched_params.load()
+    }
+
+    /// Change the policy and priority. A ready task is only moved to its new
+    /// priority once it ran again.
+    ///
+    /// Deadline tasks are set up with
+    /// [`set_deadline_task`](Self::set_deadline_task) instead. Leaving the
+    /// deadline policy releases the task's reserved bandwidth.
+    pub fn set_sched_params(&self, params: SchedParams) {
+        debug_assert_ne!(params.policy(), SchedPolicy::Deadline);
+        self.sched_params.store(params);
+        *self.deadline.lock() = None;
+    }
+
+    /// Make the task a [`SchedPolicy::Deadline`] task served by `server`.
+    pub fn set_deadline_task(&self, server: DeadlineTask) {
+        *self.deadline.lock() = Some(server);
+        self.sched_params.store(SchedParams::DEADLINE);
+    }
+
+    /// Run `f` on the server of a deadline task.
+    pub fn with_deadline_task<R>(&self, f: impl FnOnce(&mut DeadlineTask) -> R) -> Option<R> {
+        self.deadline.lock().as_mut().map(f)
+    }
+
+    /// The absolute deadline of a deadline task's current period.
+    pub fn absolute_deadline(&self) -> Option<u64> {
+        self.with_deadline_task(|server| server.deadline())
+    }
+
+    /// When the next period of a throttled deadline task starts.
+    pub fn throttled_until(&self) -> Option<u64> {
+        self.with_deadline_task(|server| server.throttled_until())
+            .flatten()
+    }
+
+    pub fn affinity(&self) -> CpuSet {
+        self.affinity.load()
+    }
+
+    /// Restrict the task to `cpus`. A ready task is only moved to one of them
+    /// once it ran again.
+    pub fn set_affinity(&self, cpus: CpuSet) {
+        self.affinity.store(cpus);
+    }
+
+    /// The CPU the task last ran on, if it ran yet.
+    pub fn last_cpu(&self) -> Option<usize> {
+        Some(self.last_cpu.load(Relaxed)).filter(|&cpu| cpu != usize::MAX)
+    }
+
+    pub(in crate::mcore::mtask) fn set_last_cpu(&self, cpu: usize) {
+        self.last_cpu.store(cpu, Relaxed);
     }
 
     pub fn kstack(&self) -> &Option<HigherHalfStack> {
@@ -276,7 +360,7 @@ impl Task {
         let tid = TaskId::new();
         let name = format!("task-{tid}");
         let should_terminate = AtomicBool::new(false);
-        let state = State::Ready;
+        let state = AtomicState::new(State::Ready);
         let last_stack_ptr = Box::pin(stack.initial_rsp().as_u64().into_usize());
         let links = Links::default();
 
@@ -337,6 +421,16 @@ impl Task {
             should_terminate,
             last_stack_ptr,
             state,
+            // Like POSIX, a forked child keeps the parent's policy. Deadline
+            // tasks can't share their reserved bandwidth, so their children
+            // are time-shared.
+            sched_params: AtomicSchedParams::new(match parent_task.sched_params().policy() {
+                SchedPolicy::Deadline => SchedParams::NORMAL,
+                _ => parent_task.sched_params(),
+            }),
+            deadline: Mutex::new(None),
+            affinity: AtomicCpuSet::new(parent_task.affinity()),
+            last_cpu: AtomicUsize::new(usize::MAX),
             kstack: Some(stack),
             ustack: RwLock::new(ustack),
             tls: RwLock::new(tls),
@@ -0,0 +1,224 @@
+use core::sync::atomic::Ordering::{Acquire, Release};
+use core::sync::atomic::{AtomicU16, AtomicU64};
+
+use kernel_abi::{
+    SCHED_DEADLINE, SCHED_FIFO, SCHED_OTHER, SCHED_PRIORITY_MAX, SCHED_PRIORITY_MIN, SCHED_RR,
+};
+
+/// How the scheduler picks a task among the ready ones of its priority, and
+/// when it preempts it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum SchedPolicy {
+    /// Time-shared. Every tick hands the CPU to the next ready task.
+    Normal,
+    /// Real-time. Only a higher priority task preempts it.
+    Fifo,
+    /// Real-time. Ticks hand the CPU to ready tasks of the same priority.
+    RoundRobin,
+    /// Earliest deadline first, within a runtime budget per period. Outranks
+    /// every other policy, see [`deadline`](crate::mcore::mtask::scheduler::deadline).
+    Deadline,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SchedParams {
+    policy: SchedPolicy,
+    /// 0 for [`SchedPolicy::Normal`] and [`SchedPolicy::Deadline`], otherwise
+    /// in `SCHED_PRIORITY_MIN..=SCHED_PRIORITY_MAX`.
+    priority: u8,
+}
+
+impl SchedParams {
+    /// The number of distinct priority levels, the time-shared one included.
+    pub const LEVELS: usize = SCHED_PRIORITY_MAX as usize + 1;
+
+    /// The level of deadline tasks, above every priority level. They are
+    /// ordered by deadline instead.
+    pub const DEADLINE_LEVEL: usize = Self::LEVELS;
+
+    pub const NORMAL: Self = Self {
+        policy: SchedPolicy::Normal,
+        priority: 0,
+    };
+
+    pub const DEADLINE: Self = Self {
+        policy: SchedPolicy::Deadline,
+        priority: 0,
+    };
+
+    /// Parameters from a `SCHED_*` policy and priority, if they are valid
+    /// together. Deadline tasks need more than that, so `SCHED_DEADLINE` is
+    /// never valid here.
+    #[must_use]
+    pub fn from_abi(policy: i32, priority: i32) -> Option<Self> {
+        let policy = match policy {
+            SCHED_OTHER if priority == 0 => return Some(Self::NORMAL),
+            SCHED_FIFO => SchedPolicy::Fifo,
+            SCHED_RR => SchedPolicy::RoundRobin,
+            _ => return None,
+        };
+        if !(SCHED_PRIORITY_MIN..=SCHED_PRIORITY_MAX).contains(&priority) {
+            return None;
+        }
+        Some(Self {
+            policy,
+            priority: u8::try_from(priority).ok()?,
+        })
+    }
+
+    #[must_use]
+    pub fn policy(self) -> SchedPolicy {
+        self.policy
+    }
+
+    #[must_use]
+    pub fn priority(self) -> u8 {
+        self.priority
+    }
+
+    /// The `SCHED_*` constant of the policy.
+    #[must_use]
+    pub fn abi_policy(self) -> i32 {
+        match self.policy {
+            SchedPolicy::Normal => SCHED_OTHER,
+            SchedPolicy::Fifo => SCHED_FIFO,
+            SchedPolicy::RoundRobin => SCHED_RR,
+            SchedPolicy::Deadline => SCHED_DEADLINE,
+        }
+    }
+
+    /// The run queue level, higher levels run first. Time-shared tasks are
+    /// on level 0, below every real-time task, and deadline tasks on
+    /// [`DEADLINE_LEVEL`](Self::DEADLINE_LEVEL), above them.
+    #[must_use]
+    pub fn level(self) -> usize {
+        match self.policy {
+            SchedPolicy::Deadline => Self::DEADLINE_LEVEL,
+            _ => usize::from(self.priority),
+        }
+    }
+}
+
+/// [`SchedParams`] that a task can change while the scheduler owns it.
+#[derive(Debug)]
+pub struct AtomicSchedParams(AtomicU16);
+
+impl AtomicSchedParams {
+    pub const fn new(params: SchedParams) -> Self {
+        Self(AtomicU16::new(Self::pack(params)))
+    }
+
+    pub fn load(&self) -> SchedParams {
+        let raw = self.0.load(Acquire);
+        let policy = match raw >> 8 {
+            1 => SchedPolicy::Fifo,
+            2 => SchedPolicy::RoundRobin,
+            3 => SchedPolicy::Deadline,
+            _ => SchedPolicy::Normal,
+        };
+        SchedParams {
+            policy,
+            priority: raw as u8,
+        }
+    }
+
+    pub fn store(&self, params: SchedParams) {
+        self.0.store(Self::pack(params), Release);
+    }
+
+    const fn pack(params: SchedParams) -> u16 {
+        (params.policy as u16) << 8 | params.priority as u16
+    }
+}
+
+/// A set of CPUs, by [`cpu_id`](crate::mcore::context::ExecutionContext::cpu_id).
+/// Only the first [`CpuSet::MAX_CPUS`] CPUs can be members.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CpuSet(u64);
+
+impl CpuSet {
+    pub const MAX_CPUS: usize = u64::BITS as usize;
+
+    pub const EMPTY: Self = Self(0);
+
+    pub const ALL: Self = Self(u64::MAX);
+
+    #[must_use]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// The set of just `cpu`.
+    #[must_use]
+    pub fn single(cpu: usize) -> Self {
+        Self::EMPTY.with(cpu)
+    }
+
+    /// The CPUs below `count`.
+    #[must_use]
+    pub fn first(count: usize) -> Self {
+        match count {
+            0 => Self::EMPTY,
+            count if count >= Self::MAX_CPUS => Self::ALL,
+            count => Self(u64::MAX >> (Self::MAX_CPUS - count)),
+        }
+    }
+
+    #[must_use]
+    pub fn with(self, cpu: usize) -> Self {
+        if cpu < Self::MAX_CPUS {
+            Self(self.0 | (1 << cpu))
+        } else {
+            self
+        }
+    }
+
+    #[must_use]
+    pub fn contains(self, cpu: usize) -> bool {
+        cpu < Self::MAX_CPUS && self.0 & (1 << cpu) != 0
+    }
+
+    #[must_use]
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The member CPUs, in ascending order.
+    pub fn iter(self) -> impl Iterator<Item = usize> {
+        (0..Self::MAX_CPUS).filter(move |&cpu| self.contains(cpu))
+    }
+}
+
+/// A [`CpuSet`] that a task can change while the scheduler owns it.
+#[derive(Debug)]
+pub struct AtomicCpuSet(AtomicU64);
+
+impl AtomicCpuSet {
+    pub const fn new(set: CpuSet) -> Self {
+        Self(AtomicU64::new(set.0))
+    }
+
+    pub fn load(&self) -> CpuSet {
+        CpuSet(self.0.load(Acquire))
+    }
+
+    pub fn store(&self, set: CpuSet) {
+        self.0.store(set.0, Release);
+    }
+
+    /// Add `cpu`, returning the set before.
+    pub fn insert(&self, cpu: usize) -> CpuSet {
+        CpuSet(self.0.fetch_or(CpuSet::single(cpu).0, Release))
+    }
+}
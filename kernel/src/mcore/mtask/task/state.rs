@@ -1,6 +1,36 @@
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering::{Acquire, Release};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
 pub enum State {
     Ready,
     Running,
+    /// Parked on a wait queue or the sleep queue until it is woken.
+    Blocked,
     Finished,
 }
+
+/// A [`State`] that wakers on other CPUs and in interrupt handlers can
+/// update while the scheduler owns the task.
+#[derive(Debug)]
+pub struct AtomicState(AtomicU8);
+
+impl AtomicState {
+    pub const fn new(state: State) -> Self {
+        Self(AtomicU8::new(state as u8))
+    }
+
+    pub fn load(&self) -> State {
+        match self.0.load(Acquire) {
+            0 => State::Ready,
+            1 => State::Running,
+            2 => State::Blocked,
+            _ => State::Finished,
+        }
+    }
+
+    pub fn store(&self, state: State) {
+        self.0.store(state as u8, Release);
+    }
+}
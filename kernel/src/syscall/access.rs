@@ -1,14 +1,18 @@
 use alloc::borrow::ToOwned;
 use alloc::sync::Arc;
+use core::ffi::c_int;
 use core::sync::atomic::Ordering::Relaxed;
 
 use kernel_abi::{Errno, ENOENT};
-use kernel_syscall::access::{CwdAccess, FileAccess};
+use kernel_syscall::access::{CwdAccess, FileAccess, MapObjectError};
 use kernel_syscall::stat::{mode, StatAccess, UserStat};
 use kernel_vfs::node::VfsNode;
 use kernel_vfs::path::AbsolutePath;
+use kernel_vfs::ReadError;
 use spin::rwlock::RwLock;
 
+use crate::file::bpf::{self as bpf_fd, BpfObject};
+use crate::file::pipe::PIPE_READERS;
 use crate::file::{vfs, OpenFileDescription};
 use crate::mcore::context::ExecutionContext;
 use crate::mcore::mtask::process::fd::{FdNum, FileDescriptor, FileDescriptorFlags};
@@ -99,15 +103,23 @@ impl FileAccess for KernelAccess<'_> {
     }
 
     fn read(&self, fd: Self::Fd, buf: &mut [u8]) -> Result<usize, ()> {
-        let fds = self.process.file_descriptors();
-        let guard = fds.read();
-
-        let desc = guard.get(&fd).ok_or(())?;
-        let ofd = desc.file_description();
+        // Don't hold the descriptor table while blocked on a pipe.
+        let ofd = self
+            .process
+            .file_descriptors()
+            .read()
+            .get(&fd)
+            .ok_or(())?
+            .file_description()
+            .clone();
         let len = buf.len() as u64;
         let offset = ofd.position().fetch_add(len, Relaxed); // TODO: respect file max len
 
-        match ofd.read(buf, offset.into_usize()) {
+        let result = PIPE_READERS.wait_until(|| match ofd.read(&mut *buf, offset.into_usize()) {
+            Err(ReadError::WouldBlock) => None,
+            result => Some(result),
+        });
+        match result {
             Ok(bytes_read) => {
                 let bytes_read_u64 = bytes_read as u64;
                 if bytes_read_u64 < len {
@@ -381,6 +393,40 @@ impl kernel_syscall::access::MemoryRegionAccess for KernelAccess<'_> {
         Ok(addr)
     }
 
+    fn create_and_track_object_mapping(
+        &self,
+        location: kernel_syscall::access::Location,
+        size: usize,
+        fd: c_int,
+        offset: usize,
+        writable: bool,
+    ) -> Result<kernel_syscall::UserspacePtr<u8>, MapObjectError> {
+        if !self
+            .process
+            .file_descriptors()
+            .read()
+            .contains_key(&FdNum::from(fd))
+        {
+            return Err(MapObjectError::BadFd);
+        }
+
+        // Ring buffer maps are the only objects that can be mapped for now
+        let map = match u32::try_from(fd)
+            .ok()
+            .and_then(|fd| bpf_fd::object(&self.process, fd))
+        {
+            Some(BpfObject::Map(map)) => map,
+            _ => return Err(MapObjectError::NotMappable),
+        };
+        let pages = crate::bpf::ringbuf::mmap_pages(&map, offset, size, writable)?;
+
+        let region_handle = self.create_shared_mapping(location, size, pages, map)?;
+        let addr = region_handle.addr;
+        self.add_memory_region(region_handle);
+
+        Ok(addr)
+    }
+
     fn add_memory_region(&self, region: Self::Region) {
         self.process.memory_regions().add_region(region.inner);
     }
@@ -1,11 +1,17 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
 use kernel_syscall::access::{
     AllocationStrategy, CreateMappingError, Location, Mapping, MemoryAccess,
 };
 use kernel_syscall::UserspacePtr;
 use kernel_virtual_memory::Segment;
 
-use crate::arch::types::{PageSize, PageTableFlags, PhysFrameRangeInclusive, Size4KiB, VirtAddr};
-use crate::mcore::mtask::process::mem::{MappedMemoryRegion, MemoryRegion};
+use crate::arch::types::{
+    Page, PageSize, PageTableFlags, PhysFrame, PhysFrameRangeInclusive, Size4KiB, VirtAddr,
+};
+use crate::mcore::mtask::process::mem::{MappedMemoryRegion, MemoryRegion, SharedMemoryRegion};
 use crate::mem::phys::PhysicalMemory;
 use crate::mem::phys_to_virt;
 use crate::mem::virt::{OwnedSegment, VirtualMemoryAllocator};
@@ -84,6 +90,65 @@ impl MemoryAccess for KernelAccess<'_> {
     }
 }
 
+impl KernelAccess<'_> {
+    /// Map `pages`, which belong to `owner`, into the process and return
+    /// the region tracking them.
+    ///
+    /// The frames are not allocated or zeroed here; they stay shared with
+    /// `owner`, which the region keeps alive.
+    pub fn create_shared_mapping(
+        &self,
+        location: Location,
+        size: usize,
+        pages: Vec<(PhysFrame, PageTableFlags)>,
+        owner: Arc<dyn Any + Send + Sync>,
+    ) -> Result<KernelMemoryRegionHandle, CreateMappingError> {
+        let page_aligned_size = pages.len() * Size4KiB::SIZE as usize;
+        debug_assert!(size <= page_aligned_size);
+
+        let segment = if let Location::Fixed(addr) = location {
+            self.process
+                .vmm()
+                .mark_as_reserved(Segment::new(
+                    VirtAddr::new(addr.as_ptr() as u64),
+                    page_aligned_size.into_u64(),
+                ))
+                .map_err(|_| CreateMappingError::LocationAlreadyMapped)?
+        } else {
+            self.process
+                .vmm()
+                .reserve(pages.len())
+                .ok_or(CreateMappingError::OutOfMemory)?
+        };
+
+        self.process.with_address_space(|as_| {
+            for (i, &(frame, flags)) in pages.iter().enumerate() {
+                let page = Page::<Size4KiB>::containing_address(
+                    segment.start + (i as u64 * Size4KiB::SIZE),
+                );
+                as_.map(page, frame, flags)
+                    .map_err(|_| CreateMappingError::OutOfMemory)?;
+            }
+            Ok::<_, CreateMappingError>(())
+        })?;
+
+        let addr = segment
+            .start
+            .as_ptr::<u8>()
+            .try_into()
+            .expect("shared mapping should be located in user space");
+        let inner = MemoryRegion::Shared(SharedMemoryRegion::new(
+            segment,
+            size,
+            pages,
+            &self.process,
+            owner,
+        ));
+
+        Ok(KernelMemoryRegionHandle { addr, size, inner })
+    }
+}
+
 pub struct KernelMapping {
     addr: VirtAddr,
     size: usize,
@@ -1,15 +1,331 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::mem::size_of;
+use core::ffi::c_int;
+use core::mem::{offset_of, size_of};
 
 use kernel_abi::{
-    BpfAttr, BPF_MAP_CREATE, BPF_MAP_DELETE_ELEM, BPF_MAP_LOOKUP_ELEM, BPF_MAP_UPDATE_ELEM,
-    BPF_PROG_ATTACH, BPF_PROG_DETACH, BPF_PROG_LOAD, BPF_PROG_LOAD_ELF, BPF_RINGBUF_POLL,
+    BpfAttr, BpfBtfInfo, BpfProgInfo, BpfTsAggregate, Errno, BPF_BTF_GET_FD_BY_ID,
+    BPF_BTF_GET_NEXT_ID, BPF_BTF_LOAD, BPF_ENABLE_STATS, BPF_EXEC_PRIO_CRITICAL,
+    BPF_EXEC_PRIO_DEFAULT, BPF_EXEC_PRIO_HIGH, BPF_EXEC_PRIO_LOW, BPF_EXEC_PRIO_NORMAL,
+    BPF_MAP_CREATE, BPF_MAP_DELETE, BPF_MAP_DELETE_BATCH, BPF_MAP_DELETE_ELEM, BPF_MAP_FREEZE,
+    BPF_MAP_GET_FD_BY_ID, BPF_MAP_GET_NEXT_ID, BPF_MAP_GET_NEXT_KEY,
+    BPF_MAP_LOOKUP_AND_DELETE_BATCH, BPF_MAP_LOOKUP_AND_DELETE_ELEM, BPF_MAP_LOOKUP_BATCH,
+    BPF_MAP_LOOKUP_ELEM, BPF_MAP_UPDATE_BATCH, BPF_MAP_UPDATE_ELEM, BPF_OBJ_GET,
+    BPF_OBJ_GET_INFO_BY_FD, BPF_OBJ_PIN, BPF_OBJ_UNPIN, BPF_PROG_ATTACH, BPF_PROG_DETACH,
+    BPF_PROG_GET_FD_BY_ID, BPF_PROG_GET_NEXT_ID, BPF_PROG_LOAD, BPF_PROG_LOAD_ELF,
+    BPF_PROG_LOAD_SIGNED, BPF_PROG_QUERY, BPF_PROG_TEST_RUN, BPF_PROG_UNLOAD, BPF_RINGBUF_POLL,
+    BPF_TIMESERIES_QUERY, PATH_MAX,
 };
 use kernel_bpf::bytecode::insn::BpfInsn;
+use kernel_bpf::maps::{MapError, ProgArrayMap};
+use kernel_bpf::profile::ActiveProfile;
+use kernel_bpf::scheduler::ExecPriority;
+use kernel_vfs::path::{AbsoluteOwnedPath, AbsolutePath};
 
-use super::validation::{copy_from_userspace, copy_to_userspace, read_userspace_slice};
+use super::validation::{
+    copy_from_userspace, copy_to_userspace, read_userspace_slice, read_userspace_string,
+};
+use crate::bpf::{self, BpfLoadError, BpfManager, BpfUnloadError, LoadedBpfMap, LoadedBpfProgram};
+use crate::file::bpf::{self as bpf_fd, BpfObject};
+use crate::file::bpffs::{self, bpffs, PinError};
+use crate::mcore::context::ExecutionContext;
+use crate::mcore::mtask::process::Process;
 use crate::BPF_MANAGER;
 
+const EBADF: isize = -9;
+const EBUSY: isize = -16;
+const EEXIST: isize = -17;
+
+/// Largest input buffer accepted by PROG_TEST_RUN.
+const TEST_RUN_MAX_DATA: usize = 64 * 1024;
+
+/// Most runs accepted by PROG_TEST_RUN. They all happen inside the syscall,
+/// with interrupts disabled.
+const TEST_RUN_MAX_REPEAT: u32 = 1000;
+
+/// Largest type information blob accepted by BTF_LOAD.
+const BTF_MAX_SIZE: usize = 1024 * 1024;
+
+fn current_process() -> Arc<Process> {
+    ExecutionContext::load().current_task().process().clone()
+}
+
+/// Hand a newly loaded object to the caller as a file descriptor.
+///
+/// The manager lock must not be held: if installing fails, dropping the
+/// object may free it.
+fn install_fd(object: BpfObject) -> isize {
+    match bpf_fd::install(&current_process(), object) {
+        Some(fd) => c_int::from(fd) as isize,
+        None => {
+            log::error!("sys_bpf: failed to install object fd");
+            -1
+        }
+    }
+}
+
+/// Read `attr.pathname` and resolve it to a path inside the bpffs.
+fn read_pin_path(attr: &BpfAttr) -> Result<AbsoluteOwnedPath, isize> {
+    let path = read_userspace_string(attr.pathname as usize, PATH_MAX).map_err(|_| -1)?;
+    let inside = AbsolutePath::try_new(&path)
+        .ok()
+        .and_then(bpffs::mount_relative)
+        .ok_or(-1)?; // EINVAL
+    Ok(inside.to_owned())
+}
+
+/// Write a `u32` output field of the caller's attribute struct.
+fn write_attr_u32(attr_ptr: usize, offset: usize, value: u32) -> Result<(), isize> {
+    copy_to_userspace(attr_ptr + offset, &value.to_ne_bytes()).map_err(|_| -1) // EFAULT
+}
+
+/// Copy up to `capacity` object IDs into the `u32` array at `ptr`.
+fn copy_ids(ptr: u64, capacity: u32, ids: &[u32]) -> Result<(), Errno> {
+    let count = ids.len().min(capacity as usize);
+    if count == 0 {
+        return Ok(());
+    }
+    let bytes: Vec<u8> = ids[..count]
+        .iter()
+        .flat_map(|id| id.to_ne_bytes())
+        .collect();
+    copy_to_userspace(ptr as usize, &bytes)
+}
+
+/// Copy an info struct into `attr.info`, truncated to `attr.info_len`, and
+/// report the number of bytes written back in `info_len`.
+///
+/// Like Linux, callers built against an older, shorter struct get the
+/// fields that fit.
+///
+/// # Safety
+/// `T` must be a `repr(C)` struct without padding bytes.
+unsafe fn copy_info<T: Copy>(attr_ptr: usize, attr: &BpfAttr, info: &T) -> isize {
+    // SAFETY: the caller guarantees every byte of `T` is initialized.
+    let bytes =
+        unsafe { core::slice::from_raw_parts((info as *const T).cast::<u8>(), size_of::<T>()) };
+    let len = bytes.len().min(attr.info_len as usize);
+    if len > 0 && copy_to_userspace(attr.info as usize, &bytes[..len]).is_err() {
+        return -1; // EFAULT
+    }
+    match write_attr_u32(attr_ptr, offset_of!(BpfAttr, info_len), len as u32) {
+        Ok(()) => 0,
+        Err(e) => e,
+    }
+}
+
+/// The map behind `attr.map_fd`, unless it was deleted.
+fn attr_map(attr: &BpfAttr) -> Result<Arc<LoadedBpfMap>, isize> {
+    match bpf_fd::map(&current_process(), attr.map_fd) {
+        Some(map) if map.is_deleted() => Err(-2), // ENOENT
+        Some(map) => Ok(map),
+        None => Err(EBADF),
+    }
+}
+
+/// The hook scheduling a PROG_ATTACH requests for `program`, or `None` if
+/// both fields are zero, which keeps what an earlier attach set.
+///
+/// Hooks may already be running an attached program, so it can't be given
+/// a different scheduling.
+fn exec_params(
+    manager: &BpfManager,
+    program: &LoadedBpfProgram,
+    attr: &BpfAttr,
+) -> Result<Option<(ExecPriority, u64)>, isize> {
+    if attr.exec_priority == BPF_EXEC_PRIO_DEFAULT && attr.deadline_ns == 0 {
+        return Ok(None);
+    }
+    let priority = match attr.exec_priority {
+        BPF_EXEC_PRIO_DEFAULT => program.exec_priority(),
+        BPF_EXEC_PRIO_LOW => ExecPriority::Low,
+        BPF_EXEC_PRIO_NORMAL => ExecPriority::Normal,
+        BPF_EXEC_PRIO_HIGH => ExecPriority::High,
+        BPF_EXEC_PRIO_CRITICAL => ExecPriority::Critical,
+        _ => return Err(-1), // EINVAL
+    };
+    let params = (priority, attr.deadline_ns);
+    let current = (program.exec_priority(), program.deadline_ns().unwrap_or(0));
+    if manager.is_attached(program.id()) && params != current {
+        return Err(EBUSY);
+    }
+    Ok(Some(params))
+}
+
+fn map_error(err: MapError) -> isize {
+    match err {
+        MapError::KeyNotFound => -2, // ENOENT
+        MapError::KeyExists => EEXIST,
+        MapError::MapFull => -7, // E2BIG
+        _ => -1,                 // EINVAL
+    }
+}
+
+/// Put the program behind the file descriptor in `value` into slot `key`
+/// of a program array.
+fn update_prog_array(prog_array: &ProgArrayMap<ActiveProfile>, key: &[u8], value: &[u8]) -> isize {
+    let (Ok(index), Ok(fd)) = (<[u8; 4]>::try_from(key), <[u8; 4]>::try_from(value)) else {
+        return -1; // EINVAL
+    };
+    let Some(program) = bpf_fd::program(&current_process(), u32::from_ne_bytes(fd)) else {
+        return EBADF;
+    };
+    if program.is_unloaded() {
+        return -2; // ENOENT
+    }
+    match prog_array.set(u32::from_ne_bytes(index), &program) {
+        Ok(()) => 0,
+        Err(e) => map_error(e),
+    }
+}
+
+/// Run one of the `MAP_*_BATCH` commands.
+///
+/// At most `max_entries` elements are processed per call, and `count` is
+/// set to the number processed. Lookups return ENOENT with the last
+/// elements once the iteration is complete; the cursor written to
+/// `out_batch` is the last key returned.
+fn map_batch(cmd: u32, attr_ptr: usize, attr: &BpfAttr) -> isize {
+    let map = match attr_map(attr) {
+        Ok(map) => map,
+        Err(e) => return e,
+    };
+    let def = map.map().def();
+    let key_size = def.key_size as usize;
+    let value_size = map.map().user_value_size();
+    let count = attr.count.min(def.max_entries) as usize;
+
+    if count == 0 || key_size == 0 || attr.keys == 0 {
+        return -1; // EINVAL
+    }
+    if cmd != BPF_MAP_LOOKUP_BATCH && map.is_frozen() {
+        return -1; // EPERM
+    }
+
+    let (done, ret) = match cmd {
+        BPF_MAP_LOOKUP_BATCH | BPF_MAP_LOOKUP_AND_DELETE_BATCH => {
+            if attr.values == 0 || attr.out_batch == 0 {
+                return -1; // EINVAL
+            }
+            let cursor = if attr.in_batch == 0 {
+                None
+            } else {
+                match read_userspace_slice(attr.in_batch as usize, key_size) {
+                    Ok(cursor) => Some(cursor),
+                    Err(_) => return -1, // EFAULT
+                }
+            };
+
+            let mut entries = match map.map().entries_after(cursor.as_deref(), count) {
+                Ok(entries) => entries,
+                Err(e) => return map_error(e),
+            };
+            let complete = entries.len() < count;
+            if cmd == BPF_MAP_LOOKUP_AND_DELETE_BATCH {
+                // Report the value that was deleted; entries deleted
+                // concurrently are skipped.
+                entries.retain_mut(|(key, value)| match map.map().lookup_and_delete(key) {
+                    Ok(deleted) => {
+                        *value = deleted;
+                        true
+                    }
+                    Err(_) => false,
+                });
+            }
+
+            let keys: Vec<u8> = entries.iter().flat_map(|(key, _)| key.clone()).collect();
+            let values: Vec<u8> = entries
+                .iter()
+                .flat_map(|(_, value)| value.clone())
+                .collect();
+            if let Some((last, _)) = entries.last() {
+                if copy_to_userspace(attr.keys as usize, &keys).is_err()
+                    || copy_to_userspace(attr.values as usize, &values).is_err()
+                    || copy_to_userspace(attr.out_batch as usize, last).is_err()
+                {
+                    return -1; // EFAULT
+                }
+            }
+
+            (entries.len(), if complete { -2 } else { 0 }) // ENOENT: no more entries
+        }
+        BPF_MAP_UPDATE_BATCH | BPF_MAP_DELETE_BATCH => {
+            let Ok(keys) = read_userspace_slice(attr.keys as usize, count * key_size) else {
+                return -1; // EFAULT
+            };
+            let values = if cmd == BPF_MAP_UPDATE_BATCH {
+                match read_userspace_slice(attr.values as usize, count * value_size) {
+                    Ok(values) => values,
+                    Err(_) => return -1, // EFAULT
+                }
+            } else {
+                Vec::new()
+            };
+
+            // Stop at the first element that fails
+            let mut done = 0;
+            let mut ret = 0;
+            for (i, key) in keys.chunks_exact(key_size).enumerate() {
+                let result = if cmd == BPF_MAP_UPDATE_BATCH {
+                    let value = &values[i * value_size..(i + 1) * value_size];
+                    map.map().update(key, value, attr.elem_flags)
+                } else {
+                    map.map().delete(key)
+                };
+                if let Err(e) = result {
+                    ret = map_error(e);
+                    break;
+                }
+                done += 1;
+            }
+            (done, ret)
+        }
+        _ => unreachable!("not a batch command"),
+    };
+
+    match write_attr_u32(attr_ptr, offset_of!(BpfAttr, count), done as u32) {
+        Ok(()) => ret,
+        Err(e) => e,
+    }
+}
+
+fn pin_error(err: PinError) -> isize {
+    match err {
+        PinError::InvalidPath => -1, // EINVAL
+        PinError::NotFound => -2,    // ENOENT
+        PinError::AlreadyExists => EEXIST,
+    }
+}
+
+/// Report a failed program load to userspace.
+///
+/// Like Linux, a rejected program gets its verifier message copied into
+/// `log_buf` (truncated to `log_size` and always NUL-terminated) when the
+/// caller supplied one. Verifier rejections return EACCES, signing policy
+/// rejections return EPERM, everything else returns EINVAL.
+fn report_load_error(attr: &BpfAttr, err: &BpfLoadError) -> isize {
+    if attr.log_buf != 0 && attr.log_size > 0 {
+        let msg = format!("{}\n", err);
+        let len = msg.len().min(attr.log_size as usize - 1);
+
+        let mut log = Vec::with_capacity(len + 1);
+        log.extend_from_slice(&msg.as_bytes()[..len]);
+        log.push(0);
+
+        if copy_to_userspace(attr.log_buf as usize, &log).is_err() {
+            log::warn!("sys_bpf: failed to copy verifier log to userspace");
+        }
+    }
+
+    match err {
+        BpfLoadError::Verify(..) => -13,  // EACCES
+        BpfLoadError::Signature(_) => -1, // EPERM
+        _ => -22,                         // EINVAL
+    }
+}
+
 pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
     // Security Hardening: Validate the attribute size matches expected struct size
     // This prevents reading past the end of the userspace buffer.
@@ -43,11 +359,16 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             let max_entries = ((attr.insns >> 32) & 0xFFFFFFFF) as u32;
 
             if let Some(manager) = BPF_MANAGER.get() {
-                match manager
+                let result = manager
                     .lock()
-                    .create_map(map_type, key_size, value_size, max_entries)
-                {
-                    Ok(map_id) => map_id as isize,
+                    .create_map(map_type, key_size, value_size, max_entries);
+                match result {
+                    Ok(map) => {
+                        if let Some(ringbuf) = map.map().as_ringbuf() {
+                            ringbuf.set_wakeup_watermark(attr.map_extra);
+                        }
+                        install_fd(BpfObject::Map(map))
+                    }
                     Err(e) => {
                         log::error!("sys_bpf: MAP_CREATE failed: {}", e);
                         -1
@@ -64,7 +385,9 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 Err(_) => return -1,
             };
 
-            let map_id = attr.map_fd;
+            let Some(map_id) = bpf_fd::map_id(&current_process(), attr.map_fd) else {
+                return EBADF;
+            };
             let key_ptr = attr.key as *const u8;
             let value_ptr = attr.value as *mut u8;
 
@@ -79,7 +402,7 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 let key_size = if let Some(def) = mgr.get_map_def(map_id) {
                     def.key_size as usize
                 } else {
-                    return -1; // Invalid map_fd
+                    return -2; // ENOENT: map was deleted
                 };
 
                 let key = match read_userspace_slice(key_ptr as usize, key_size) {
@@ -107,7 +430,9 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 Err(_) => return -1,
             };
 
-            let map_id = attr.map_fd;
+            let Some(map_id) = bpf_fd::map_id(&current_process(), attr.map_fd) else {
+                return EBADF;
+            };
             let key_ptr = attr.key as *const u8;
             let value_ptr = attr.value as *const u8;
             let flags = attr.flags;
@@ -119,12 +444,15 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             if let Some(manager) = BPF_MANAGER.get() {
                 let mgr = manager.lock();
 
-                // Get map definition to determine sizes
-                let (key_size, value_size) = if let Some(def) = mgr.get_map_def(map_id) {
-                    (def.key_size as usize, def.value_size as usize)
-                } else {
-                    return -1; // Invalid map_fd
+                let Some(map) = mgr.map(map_id) else {
+                    return -2; // ENOENT: map was deleted
                 };
+                if map.is_frozen() {
+                    return -1; // EPERM
+                }
+                // Values of per-CPU maps hold one value for each CPU
+                let key_size = map.map().def().key_size as usize;
+                let value_size = map.map().user_value_size();
 
                 let key = match read_userspace_slice(key_ptr as usize, key_size) {
                     Ok(k) => k,
@@ -136,6 +464,10 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                     Err(_) => return -1,
                 };
 
+                if let Some(prog_array) = map.map().as_prog_array() {
+                    return update_prog_array(prog_array, &key, &value);
+                }
+
                 match mgr.map_update(map_id, &key, &value, flags) {
                     Ok(_) => 0,
                     Err(e) => {
@@ -154,7 +486,9 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 Err(_) => return -1,
             };
 
-            let map_id = attr.map_fd;
+            let Some(map_id) = bpf_fd::map_id(&current_process(), attr.map_fd) else {
+                return EBADF;
+            };
             let key_ptr = attr.key as *const u8;
 
             if key_ptr.is_null() {
@@ -164,12 +498,13 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             if let Some(manager) = BPF_MANAGER.get() {
                 let mgr = manager.lock();
 
-                // Get map definition to determine key size
-                let key_size = if let Some(def) = mgr.get_map_def(map_id) {
-                    def.key_size as usize
-                } else {
-                    return -1; // Invalid map_fd
+                let Some(map) = mgr.map(map_id) else {
+                    return -2; // ENOENT: map was deleted
                 };
+                if map.is_frozen() {
+                    return -1; // EPERM
+                }
+                let key_size = map.map().def().key_size as usize;
 
                 let key = match read_userspace_slice(key_ptr as usize, key_size) {
                     Ok(k) => k,
@@ -184,6 +519,104 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 -1
             }
         }
+        BPF_MAP_GET_NEXT_KEY => {
+            log::debug!("sys_bpf: MAP_GET_NEXT_KEY");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let map = match attr_map(&attr) {
+                Ok(map) => map,
+                Err(e) => return e,
+            };
+            let key_size = map.map().def().key_size as usize;
+
+            if attr.value == 0 {
+                return -1; // EINVAL
+            }
+
+            // Without a key, iteration starts at the first key
+            let key = if attr.key == 0 {
+                None
+            } else {
+                match read_userspace_slice(attr.key as usize, key_size) {
+                    Ok(k) => Some(k),
+                    Err(_) => return -1,
+                }
+            };
+
+            match map.map().get_next_key(key.as_deref()) {
+                Ok(next_key) => {
+                    if copy_to_userspace(attr.value as usize, &next_key).is_err() {
+                        return -1; // EFAULT
+                    }
+                    0
+                }
+                Err(e) => map_error(e),
+            }
+        }
+        BPF_MAP_LOOKUP_AND_DELETE_ELEM => {
+            log::debug!("sys_bpf: MAP_LOOKUP_AND_DELETE_ELEM");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let map = match attr_map(&attr) {
+                Ok(map) => map,
+                Err(e) => return e,
+            };
+            let key_size = map.map().def().key_size as usize;
+
+            if attr.key == 0 || attr.value == 0 {
+                return -1;
+            }
+            if map.is_frozen() {
+                return -1; // EPERM
+            }
+
+            let key = match read_userspace_slice(attr.key as usize, key_size) {
+                Ok(k) => k,
+                Err(_) => return -1,
+            };
+
+            match map.map().lookup_and_delete(&key) {
+                Ok(value) => {
+                    if copy_to_userspace(attr.value as usize, &value).is_err() {
+                        return -1; // EFAULT
+                    }
+                    0
+                }
+                Err(e) => map_error(e),
+            }
+        }
+        BPF_MAP_FREEZE => {
+            log::debug!("sys_bpf: MAP_FREEZE");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            // Userspace can no longer write the map; programs still can
+            match attr_map(&attr) {
+                Ok(map) if map.freeze() => 0,
+                Ok(_) => EBUSY, // already frozen
+                Err(e) => e,
+            }
+        }
+        BPF_MAP_LOOKUP_BATCH
+        | BPF_MAP_LOOKUP_AND_DELETE_BATCH
+        | BPF_MAP_UPDATE_BATCH
+        | BPF_MAP_DELETE_BATCH => {
+            log::debug!("sys_bpf: MAP_*_BATCH ({})", cmd_u32);
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            map_batch(cmd_u32, attr_ptr, &attr)
+        }
         BPF_PROG_ATTACH => {
             log::info!("sys_bpf: PROG_ATTACH");
             let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
@@ -199,10 +632,28 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             );
 
             let attach_type = attr.attach_btf_id;
-            let prog_id = attr.attach_prog_fd;
+            let Some(prog_id) = bpf_fd::prog_id(&current_process(), attr.attach_prog_fd) else {
+                return EBADF;
+            };
 
             if let Some(manager) = BPF_MANAGER.get() {
-                match manager.lock().attach(attach_type, prog_id) {
+                let mut manager = manager.lock();
+                let params = match manager.program(prog_id) {
+                    Some(program) => match exec_params(&manager, &program, &attr) {
+                        Ok(params) => params.map(|params| (program, params)),
+                        Err(e) => return e,
+                    },
+                    None => None,
+                };
+                let result = manager.attach(attach_type, prog_id);
+                // Hooks only see the program once the lock is released, so
+                // the first run already has the scheduling
+                if let (Ok(()), Some((program, (priority, deadline_ns)))) = (&result, params) {
+                    program.set_exec_params(priority, deadline_ns);
+                }
+                drop(manager);
+
+                match result {
                     Ok(_) => {
                         log::info!("sys_bpf: attached prog {} to type {}", prog_id, attach_type);
 
@@ -330,7 +781,9 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             };
 
             let attach_type = attr.attach_btf_id;
-            let prog_id = attr.attach_prog_fd;
+            let Some(prog_id) = bpf_fd::prog_id(&current_process(), attr.attach_prog_fd) else {
+                return EBADF;
+            };
 
             if let Some(manager) = BPF_MANAGER.get() {
                 match manager.lock().detach(attach_type, prog_id) {
@@ -395,15 +848,29 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 }
             }
 
+            // Map references carry the caller's map fds; the kernel works with map IDs.
+            let process = current_process();
+            for insn in insns
+                .iter_mut()
+                .filter(|insn| insn.is_map_load() || insn.is_map_value_load())
+            {
+                let Some(map_id) = bpf_fd::map_id(&process, insn.imm as u32) else {
+                    log::error!("sys_bpf: program references invalid map fd {}", insn.imm);
+                    return EBADF;
+                };
+                insn.imm = map_id as i32;
+            }
+
             if let Some(manager) = BPF_MANAGER.get() {
-                match manager.lock().load_raw_program(insns) {
-                    Ok(id) => {
-                        log::info!("sys_bpf: program loaded with id {}", id);
-                        id as isize
+                let result = manager.lock().load_raw_program(insns);
+                match result {
+                    Ok(program) => {
+                        log::info!("sys_bpf: program loaded with id {}", program.id());
+                        install_fd(BpfObject::Program(program))
                     }
                     Err(e) => {
                         log::error!("sys_bpf: failed to load program: {}", e);
-                        -1
+                        report_load_error(&attr, &e)
                     }
                 }
             } else {
@@ -442,15 +909,80 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 }
             };
 
+            // Configuration constants set by the caller before load; .rodata
+            // comes from the file, so it can't be larger than the file
+            let rodata = if attr.rodata == 0 {
+                None
+            } else if attr.rodata_size as usize > file_size {
+                return -1; // EINVAL
+            } else {
+                match read_userspace_slice(attr.rodata as usize, attr.rodata_size as usize) {
+                    Ok(bytes) => Some(bytes),
+                    Err(_) => {
+                        log::error!("sys_bpf: failed to read .rodata from userspace");
+                        return -1;
+                    }
+                }
+            };
+
             if let Some(manager) = BPF_MANAGER.get() {
-                match manager.lock().load_program(&elf_bytes) {
-                    Ok(id) => {
-                        log::info!("sys_bpf: ELF program loaded with id {}", id);
-                        id as isize
+                let result = manager.lock().load_program(&elf_bytes, rodata.as_deref());
+                match result {
+                    Ok(program) => {
+                        log::info!("sys_bpf: ELF program loaded with id {}", program.id());
+                        install_fd(BpfObject::Program(program))
                     }
                     Err(e) => {
                         log::error!("sys_bpf: failed to load ELF program: {}", e);
-                        -1
+                        report_load_error(&attr, &e)
+                    }
+                }
+            } else {
+                log::error!("sys_bpf: BPF_MANAGER not initialized");
+                -1
+            }
+        }
+        BPF_PROG_LOAD_SIGNED => {
+            log::info!("sys_bpf: PROG_LOAD_SIGNED");
+
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            // same layout as PROG_LOAD_ELF: insn_cnt is the file size, insns the pointer
+            let file_size = attr.insn_cnt as usize;
+            let file_ptr = attr.insns as usize;
+
+            if file_ptr == 0 || file_size == 0 || file_size > 1024 * 1024 {
+                log::error!(
+                    "sys_bpf: invalid signed file (ptr={:#x}, size={})",
+                    file_ptr,
+                    file_size
+                );
+                return -1;
+            }
+
+            log::info!("sys_bpf: loading signed file of {} bytes", file_size);
+
+            let signed_bytes = match read_userspace_slice(file_ptr, file_size) {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    log::error!("sys_bpf: failed to read signed bytes from userspace");
+                    return -1;
+                }
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let result = manager.lock().load_signed_program(&signed_bytes);
+                match result {
+                    Ok(program) => {
+                        log::info!("sys_bpf: signed program loaded with id {}", program.id());
+                        install_fd(BpfObject::Program(program))
+                    }
+                    Err(e) => {
+                        log::error!("sys_bpf: failed to load signed program: {}", e);
+                        report_load_error(&attr, &e)
                     }
                 }
             } else {
@@ -458,6 +990,34 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 -1
             }
         }
+        BPF_BTF_LOAD => {
+            log::info!("sys_bpf: BTF_LOAD");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let size = attr.btf_size as usize;
+            if attr.btf == 0 || size == 0 || size > BTF_MAX_SIZE {
+                return -1; // EINVAL
+            }
+            let Ok(data) = read_userspace_slice(attr.btf as usize, size) else {
+                return -1; // EFAULT
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let result = manager.lock().load_btf(&data);
+                match result {
+                    Ok(btf) => install_fd(BpfObject::Btf(btf)),
+                    Err(e) => {
+                        log::warn!("sys_bpf: failed to load BTF: {}", e);
+                        report_load_error(&attr, &e)
+                    }
+                }
+            } else {
+                -1
+            }
+        }
         BPF_RINGBUF_POLL => {
             log::debug!("sys_bpf: RINGBUF_POLL");
             let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
@@ -466,10 +1026,12 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
             };
 
             // For RINGBUF_POLL:
-            //   map_fd  -> map_id (which ringbuf to poll)
+            //   map_fd  -> which ringbuf to poll
             //   key     -> buf_ptr (userspace buffer to write event data into)
             //   value   -> buf_size (capacity of the userspace buffer)
-            let map_id = attr.map_fd;
+            let Some(map_id) = bpf_fd::map_id(&current_process(), attr.map_fd) else {
+                return EBADF;
+            };
             let buf_ptr = attr.key as usize;
             let buf_size = attr.value as usize;
 
@@ -500,6 +1062,421 @@ pub fn sys_bpf(cmd: usize, attr_ptr: usize, size: usize) -> isize {
                 -1
             }
         }
+        BPF_PROG_TEST_RUN => {
+            log::debug!("sys_bpf: PROG_TEST_RUN");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let Some(program) = bpf_fd::program(&current_process(), attr.attach_prog_fd) else {
+                return EBADF;
+            };
+            if program.is_unloaded() {
+                return -2; // ENOENT
+            }
+
+            let data_size = attr.data_size_in as usize;
+            if data_size > TEST_RUN_MAX_DATA || attr.repeat > TEST_RUN_MAX_REPEAT {
+                return -1; // EINVAL
+            }
+            let mut data = if data_size == 0 {
+                Vec::new()
+            } else {
+                match read_userspace_slice(attr.data_in as usize, data_size) {
+                    Ok(d) => d,
+                    Err(_) => return -1,
+                }
+            };
+
+            // The manager lock is not held here, so helpers can take it.
+            let run = match program.test_run(&mut data, attr.repeat) {
+                Ok(run) => run,
+                Err(e) => {
+                    log::warn!("sys_bpf: test run of prog {} failed: {}", program.id(), e);
+                    return -1;
+                }
+            };
+
+            let duration = u32::try_from(run.duration_ns).unwrap_or(u32::MAX);
+            let data_size_out = data.len() as u32;
+            // Like Linux, only the output fields of the attribute are written back.
+            let outputs: [(usize, &[u8]); 3] = [
+                (offset_of!(BpfAttr, retval), &run.retval.to_ne_bytes()),
+                (offset_of!(BpfAttr, duration), &duration.to_ne_bytes()),
+                (
+                    offset_of!(BpfAttr, data_size_out),
+                    &data_size_out.to_ne_bytes(),
+                ),
+            ];
+            for (offset, bytes) in outputs {
+                if copy_to_userspace(attr_ptr + offset, bytes).is_err() {
+                    return -1; // EFAULT
+                }
+            }
+
+            if attr.data_out != 0 && !data.is_empty() {
+                let len = data.len().min(attr.data_size_out as usize);
+                if copy_to_userspace(attr.data_out as usize, &data[..len]).is_err() {
+                    return -1; // EFAULT
+                }
+                if len < data.len() {
+                    return -28; // ENOSPC: output truncated
+                }
+            }
+
+            0
+        }
+        BPF_ENABLE_STATS => {
+            log::info!("sys_bpf: ENABLE_STATS");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            bpf::stats::set_enabled(attr.flags != 0);
+            0
+        }
+        BPF_OBJ_GET_INFO_BY_FD => {
+            log::debug!("sys_bpf: OBJ_GET_INFO_BY_FD");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let Some(object) = bpf_fd::object(&current_process(), attr.bpf_fd) else {
+                return EBADF;
+            };
+
+            match object {
+                BpfObject::Program(program) => {
+                    let Some(manager) = BPF_MANAGER.get() else {
+                        return -1;
+                    };
+                    let mut info = manager.lock().prog_info(&program);
+
+                    // Callers that want the map IDs pass a buffer for them in
+                    // the info struct itself.
+                    if attr.info_len as usize >= size_of::<BpfProgInfo>() {
+                        let Ok(request) = copy_from_userspace::<BpfProgInfo>(attr.info as usize)
+                        else {
+                            return -1; // EFAULT
+                        };
+                        if request.map_ids != 0 {
+                            let ids: Vec<u32> = program.maps().iter().map(|m| m.id()).collect();
+                            if copy_ids(request.map_ids, request.nr_map_ids, &ids).is_err() {
+                                return -1; // EFAULT
+                            }
+                        }
+                        info.map_ids = request.map_ids;
+                    }
+
+                    // SAFETY: BpfProgInfo is a repr(C) struct of integers
+                    // without padding.
+                    unsafe { copy_info(attr_ptr, &attr, &info) }
+                }
+                BpfObject::Map(map) => {
+                    // SAFETY: BpfMapInfo is a repr(C) struct of u32s.
+                    unsafe { copy_info(attr_ptr, &attr, &map.info()) }
+                }
+                BpfObject::Btf(btf) => {
+                    let mut info = btf.info();
+
+                    // Like map IDs for programs, the raw data is copied into
+                    // a buffer named by the info struct itself.
+                    if attr.info_len as usize >= size_of::<BpfBtfInfo>() {
+                        let Ok(request) = copy_from_userspace::<BpfBtfInfo>(attr.info as usize)
+                        else {
+                            return -1; // EFAULT
+                        };
+                        let raw = btf.btf().raw();
+                        let len = raw.len().min(request.btf_size as usize);
+                        if request.btf != 0
+                            && len > 0
+                            && copy_to_userspace(request.btf as usize, &raw[..len]).is_err()
+                        {
+                            return -1; // EFAULT
+                        }
+                        info.btf = request.btf;
+                    }
+
+                    // SAFETY: BpfBtfInfo is a repr(C) struct of integers
+                    // without padding.
+                    unsafe { copy_info(attr_ptr, &attr, &info) }
+                }
+            }
+        }
+        BPF_PROG_GET_NEXT_ID | BPF_MAP_GET_NEXT_ID | BPF_BTF_GET_NEXT_ID => {
+            log::debug!("sys_bpf: GET_NEXT_ID");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let next_id = match cmd_u32 {
+                    BPF_PROG_GET_NEXT_ID => manager.lock().next_prog_id(attr.start_id),
+                    BPF_MAP_GET_NEXT_ID => manager.lock().next_map_id(attr.start_id),
+                    _ => manager.lock().next_btf_id(attr.start_id),
+                };
+                let Some(next_id) = next_id else {
+                    return -2; // ENOENT: no more objects
+                };
+                match write_attr_u32(attr_ptr, offset_of!(BpfAttr, next_id), next_id) {
+                    Ok(()) => 0,
+                    Err(e) => e,
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_PROG_GET_FD_BY_ID => {
+            log::info!("sys_bpf: PROG_GET_FD_BY_ID");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let program = manager.lock().program(attr.start_id);
+                match program {
+                    Some(program) => install_fd(BpfObject::Program(program)),
+                    None => -2, // ENOENT
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_MAP_GET_FD_BY_ID => {
+            log::info!("sys_bpf: MAP_GET_FD_BY_ID");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let map = manager.lock().map(attr.start_id);
+                match map {
+                    Some(map) => install_fd(BpfObject::Map(map)),
+                    None => -2, // ENOENT
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_BTF_GET_FD_BY_ID => {
+            log::info!("sys_bpf: BTF_GET_FD_BY_ID");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let btf = manager.lock().btf(attr.start_id);
+                match btf {
+                    Some(btf) => install_fd(BpfObject::Btf(btf)),
+                    None => -2, // ENOENT
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_PROG_QUERY => {
+            log::debug!("sys_bpf: PROG_QUERY");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            // Like PROG_ATTACH, the attach type is passed in attach_btf_id.
+            let attach_type = attr.attach_btf_id;
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let ids: Vec<u32> = manager
+                    .lock()
+                    .get_hook_programs(attach_type)
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect();
+
+                if let Err(e) =
+                    write_attr_u32(attr_ptr, offset_of!(BpfAttr, prog_cnt), ids.len() as u32)
+                {
+                    return e;
+                }
+                if attr.prog_ids != 0 {
+                    if copy_ids(attr.prog_ids, attr.prog_cnt, &ids).is_err() {
+                        return -1; // EFAULT
+                    }
+                    if ids.len() > attr.prog_cnt as usize {
+                        return -28; // ENOSPC
+                    }
+                }
+                0
+            } else {
+                -1
+            }
+        }
+        BPF_OBJ_PIN => {
+            log::info!("sys_bpf: OBJ_PIN");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let Some(object) = bpf_fd::object(&current_process(), attr.bpf_fd) else {
+                return EBADF;
+            };
+            let path = match read_pin_path(&attr) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+
+            let result = bpffs().write().pin(&path, object);
+            match result {
+                Ok(()) => 0,
+                Err(e) => {
+                    log::warn!("sys_bpf: pinning at {} failed: {}", &*path, e);
+                    pin_error(e)
+                }
+            }
+        }
+        BPF_OBJ_GET => {
+            log::info!("sys_bpf: OBJ_GET");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let path = match read_pin_path(&attr) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+
+            let result = bpffs().read().get(&path);
+            match result {
+                Ok(BpfObject::Program(program)) if program.is_unloaded() => -2, // ENOENT
+                Ok(BpfObject::Map(map)) if map.is_deleted() => -2,              // ENOENT
+                Ok(object) => install_fd(object),
+                Err(e) => pin_error(e),
+            }
+        }
+        BPF_OBJ_UNPIN => {
+            log::info!("sys_bpf: OBJ_UNPIN");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let path = match read_pin_path(&attr) {
+                Ok(path) => path,
+                Err(e) => return e,
+            };
+
+            // The pin's reference is dropped here, after the bpffs lock is released.
+            let result = bpffs().write().unpin(&path);
+            match result {
+                Ok(_) => 0,
+                Err(e) => pin_error(e),
+            }
+        }
+        BPF_PROG_UNLOAD => {
+            log::info!("sys_bpf: PROG_UNLOAD");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            // Only a process holding the program may unload it; a pinned
+            // program can be opened with OBJ_GET first.
+            let Some(prog_id) = bpf_fd::prog_id(&current_process(), attr.attach_prog_fd) else {
+                return EBADF;
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let result = manager.lock().unload_program(prog_id);
+                match result {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        log::error!("sys_bpf: unload of prog {} failed: {}", prog_id, e);
+                        -2 // ENOENT
+                    }
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_MAP_DELETE => {
+            log::info!("sys_bpf: MAP_DELETE");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            // Like PROG_UNLOAD, only for a map the caller holds.
+            let Some(map_id) = bpf_fd::map_id(&current_process(), attr.map_fd) else {
+                return EBADF;
+            };
+
+            if let Some(manager) = BPF_MANAGER.get() {
+                let result = manager.lock().delete_map(map_id);
+                match result {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        log::error!("sys_bpf: delete of map {} failed: {}", map_id, e);
+                        match e {
+                            BpfUnloadError::NotFound => -2, // ENOENT
+                            BpfUnloadError::InUse => EBUSY,
+                        }
+                    }
+                }
+            } else {
+                -1
+            }
+        }
+        BPF_TIMESERIES_QUERY => {
+            log::debug!("sys_bpf: TIMESERIES_QUERY");
+            let attr = match copy_from_userspace::<BpfAttr>(attr_ptr) {
+                Ok(a) => a,
+                Err(_) => return -1,
+            };
+
+            let map = match attr_map(&attr) {
+                Ok(map) => map,
+                Err(e) => return e,
+            };
+            let Some(timeseries) = map.map().as_timeseries() else {
+                return -1; // EINVAL
+            };
+            let aggregate = if attr.count > 0 {
+                timeseries.aggregate_last_n(attr.count as usize)
+            } else {
+                timeseries.aggregate_in_window(attr.window_start_ns, attr.window_end_ns)
+            };
+            let Some(agg) = aggregate else {
+                return -1; // EINVAL: values aren't integers
+            };
+
+            let info = BpfTsAggregate {
+                count: agg.count,
+                oldest_ns: agg.oldest_ns,
+                newest_ns: agg.newest_ns,
+                min: agg.min,
+                max: agg.max,
+                mean: agg.mean,
+                stddev: agg.stddev,
+                slope: agg.slope,
+            };
+            // SAFETY: BpfTsAggregate is a repr(C) struct of 64-bit integers.
+            let ret = unsafe { copy_info(attr_ptr, &attr, &info) };
+            if ret != 0 {
+                return ret;
+            }
+            match write_attr_u32(attr_ptr, offset_of!(BpfAttr, count), agg.count as u32) {
+                Ok(()) => 0,
+                Err(e) => e,
+            }
+        }
         _ => {
             log::warn!("sys_bpf: Unknown command {}", cmd);
             -1
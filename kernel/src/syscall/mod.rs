@@ -51,9 +51,12 @@ fn hlt() {
 mod access;
 pub mod bpf;
 #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod poll;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
 mod process;
 #[cfg(all(target_arch = "aarch64", feature = "rpi5"))]
 pub mod pwm;
+mod sched;
 mod validation;
 
 use crate::arch::UserContext;
@@ -92,7 +95,7 @@ pub fn dispatch_syscall(
 
     // Run BPF hooks (AttachType::Syscall = 2) at syscall entry
     #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
-    if let Some(manager) = crate::BPF_MANAGER.get() {
+    if crate::BPF_MANAGER.get().is_some() {
         use kernel_bpf::execution::SyscallTraceContext;
 
         let trace_ctx = SyscallTraceContext {
@@ -116,21 +119,7 @@ pub fn dispatch_syscall(
 
         let ctx = kernel_bpf::execution::BpfContext::from_slice(slice);
 
-        // Lock-free pattern: clone programs and release lock BEFORE execution
-        // so that BPF helpers can re-acquire the lock without deadlocking.
-        let programs = manager
-            .lock()
-            .get_hook_programs(crate::bpf::ATTACH_TYPE_SYSCALL);
-        for (prog_id, program) in &programs {
-            match crate::bpf::BpfManager::execute_program(program, &ctx) {
-                Ok(res) => {
-                    if res != 0 {
-                        log::info!("Syscall Trace [id={}] syscall_nr: {}", prog_id, res);
-                    }
-                }
-                Err(e) => log::error!("Syscall BPF Hook [id={}] failed: {:?}", prog_id, e),
-            }
-        }
+        crate::bpf::hooks::run_hooks(crate::bpf::ATTACH_TYPE_SYSCALL, &ctx);
     }
 
     let result: Result<usize, Errno> = match n {
@@ -140,7 +129,8 @@ pub fn dispatch_syscall(
             let ctx = crate::mcore::context::ExecutionContext::load();
             let task = ctx.current_task();
             let process = task.process();
-            *process.exit_code().write() = Some(status);
+            process.set_exit_code(status);
+            process.close_file_descriptors();
             task.set_should_terminate(true);
             // SAFETY: Interrupts are disabled during syscall handling (PSTATE.DAIF masked on
             // exception entry). reschedule() context-switches away; since should_terminate is
@@ -178,7 +168,8 @@ pub fn dispatch_syscall(
             let ctx = crate::mcore::context::ExecutionContext::load();
             let task = ctx.current_task();
             let process = task.process();
-            *process.exit_code().write() = Some(status);
+            process.set_exit_code(status);
+            process.close_file_descriptors();
             task.set_should_terminate(true);
             unsafe {
                 ctx.scheduler_mut().reschedule();
@@ -197,10 +188,20 @@ pub fn dispatch_syscall(
         kernel_abi::SYS_PWM_ENABLE => dispatch_sys_pwm_enable(arg1, arg2, arg3),
         kernel_abi::SYS_CLOCK_GETTIME => dispatch_sys_clock_gettime(arg1, arg2),
         kernel_abi::SYS_NANOSLEEP => dispatch_sys_nanosleep(arg1, arg2),
+        kernel_abi::SYS_POLL => dispatch_sys_poll(arg1, arg2, arg3),
         kernel_abi::SYS_SPAWN => dispatch_sys_spawn(arg1, arg2),
         kernel_abi::SYS_FORK => dispatch_sys_fork(ctx),
         kernel_abi::SYS_EXECVE => dispatch_sys_execve(ctx, arg1, arg2, arg3),
         kernel_abi::SYS_WAITPID => dispatch_sys_waitpid(arg1, arg2, arg3),
+        kernel_abi::SYS_SCHED_SETSCHEDULER => dispatch_sys_sched_setscheduler(arg1, arg2, arg3),
+        kernel_abi::SYS_SCHED_GETSCHEDULER => dispatch_sys_sched_getscheduler(arg1),
+        kernel_abi::SYS_SCHED_SETPARAM => dispatch_sys_sched_setparam(arg1, arg2),
+        kernel_abi::SYS_SCHED_GETPARAM => dispatch_sys_sched_getparam(arg1, arg2),
+        kernel_abi::SYS_SCHED_YIELD => dispatch_sys_sched_yield(),
+        kernel_abi::SYS_SCHED_SETATTR => dispatch_sys_sched_setattr(arg1, arg2, arg3),
+        kernel_abi::SYS_SCHED_GETATTR => dispatch_sys_sched_getattr(arg1, arg2, arg3, arg4),
+        kernel_abi::SYS_SCHED_SETAFFINITY => dispatch_sys_sched_setaffinity(arg1, arg2, arg3),
+        kernel_abi::SYS_SCHED_GETAFFINITY => dispatch_sys_sched_getaffinity(arg1, arg2, arg3),
         _ => {
             error!("unimplemented syscall: {} ({n})", syscall_name(n));
             loop {
@@ -617,25 +618,20 @@ fn dispatch_sys_nanosleep(req: usize, _rem: usize) -> Result<usize, Errno> {
         .and_then(|s| s.checked_add(ts.tv_nsec as u64))
         .ok_or(EINVAL)?;
 
-    let start = crate::time::get_kernel_time_ns();
-
-    // Busy wait loop
-    // TODO: Use proper scheduler sleep/wait queue
-    loop {
-        let now = crate::time::get_kernel_time_ns();
-        if now.wrapping_sub(start) >= duration_ns {
-            break;
-        }
+    crate::mcore::mtask::scheduler::sleep::sleep_ns(duration_ns);
 
-        // On x86_64, enable interrupts and halt to save power
-        #[cfg(target_arch = "x86_64")]
-        x86_64::instructions::interrupts::enable_and_hlt();
+    Ok(0)
+}
 
-        #[cfg(not(target_arch = "x86_64"))]
-        core::hint::spin_loop();
-    }
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn dispatch_sys_poll(fds: usize, nfds: usize, timeout: usize) -> Result<usize, Errno> {
+    // A negative timeout waits forever
+    poll::sys_poll(fds, nfds, timeout as i32)
+}
 
-    Ok(0)
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn dispatch_sys_poll(_fds: usize, _nfds: usize, _timeout: usize) -> Result<usize, Errno> {
+    Err(EINVAL)
 }
 
 #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
@@ -721,6 +717,51 @@ fn dispatch_sys_waitpid(pid: usize, status: usize, options: usize) -> Result<usi
     process::sys_waitpid(pid as isize, status, options)
 }
 
+fn dispatch_sys_sched_setscheduler(
+    pid: usize,
+    policy: usize,
+    param: usize,
+) -> Result<usize, Errno> {
+    sched::sys_sched_setscheduler(pid, policy as i32, param)
+}
+
+fn dispatch_sys_sched_getscheduler(pid: usize) -> Result<usize, Errno> {
+    sched::sys_sched_getscheduler(pid)
+}
+
+fn dispatch_sys_sched_setparam(pid: usize, param: usize) -> Result<usize, Errno> {
+    sched::sys_sched_setparam(pid, param)
+}
+
+fn dispatch_sys_sched_getparam(pid: usize, param: usize) -> Result<usize, Errno> {
+    sched::sys_sched_getparam(pid, param)
+}
+
+fn dispatch_sys_sched_yield() -> Result<usize, Errno> {
+    sched::sys_sched_yield()
+}
+
+fn dispatch_sys_sched_setattr(pid: usize, attr: usize, flags: usize) -> Result<usize, Errno> {
+    sched::sys_sched_setattr(pid, attr, flags)
+}
+
+fn dispatch_sys_sched_getattr(
+    pid: usize,
+    attr: usize,
+    size: usize,
+    flags: usize,
+) -> Result<usize, Errno> {
+    sched::sys_sched_getattr(pid, attr, size, flags)
+}
+
+fn dispatch_sys_sched_setaffinity(pid: usize, size: usize, mask: usize) -> Result<usize, Errno> {
+    sched::sys_sched_setaffinity(pid, size, mask)
+}
+
+fn dispatch_sys_sched_getaffinity(pid: usize, size: usize, mask: usize) -> Result<usize, Errno> {
+    sched::sys_sched_getaffinity(pid, size, mask)
+}
+
 #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
 fn dispatch_sys_fork(_ctx: &UserContext) -> Result<usize, Errno> {
     Err(EINVAL)
@@ -0,0 +1,82 @@
+use alloc::vec::Vec;
+use core::mem::{offset_of, size_of};
+
+use kernel_abi::{pollfd, Errno, EINVAL, POLLIN, POLLNVAL, POLLOUT};
+
+use crate::bpf::ringbuf::RINGBUF_READERS;
+use crate::file::bpf::{self as bpf_fd, BpfObject};
+use crate::mcore::context::ExecutionContext;
+use crate::mcore::mtask::process::fd::FdNum;
+use crate::mcore::mtask::process::Process;
+use crate::syscall::validation::{copy_from_userspace, copy_to_userspace};
+
+/// Most descriptors a single poll() call may wait on.
+const POLL_MAX_FDS: usize = 1024;
+
+pub fn sys_poll(fds_ptr: usize, nfds: usize, timeout_ms: i32) -> Result<usize, Errno> {
+    if nfds > POLL_MAX_FDS {
+        return Err(EINVAL);
+    }
+
+    let mut fds = (0..nfds)
+        .map(|i| copy_from_userspace::<pollfd>(fds_ptr + i * size_of::<pollfd>()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let process = ExecutionContext::load().current_process().clone();
+    let poll = || {
+        let mut ready = 0;
+        for entry in &mut fds {
+            entry.revents = revents(&process, entry);
+            if entry.revents != 0 {
+                ready += 1;
+            }
+        }
+        (ready > 0).then_some(ready)
+    };
+
+    // Only ring buffers can be waited on, every other descriptor is ready
+    let ready = match u64::try_from(timeout_ms) {
+        Ok(ms) => {
+            let deadline_ns = crate::time::get_kernel_time_ns().saturating_add(ms * 1_000_000);
+            RINGBUF_READERS
+                .wait_until_deadline(deadline_ns, poll)
+                .unwrap_or(0)
+        }
+        Err(_) => RINGBUF_READERS.wait_until(poll),
+    };
+
+    for (i, entry) in fds.iter().enumerate() {
+        let revents_ptr = fds_ptr + i * size_of::<pollfd>() + offset_of!(pollfd, revents);
+        copy_to_userspace(revents_ptr, &entry.revents.to_ne_bytes())?;
+    }
+
+    Ok(ready)
+}
+
+/// The events that are pending on `entry.fd`.
+///
+/// Ring buffer maps are readable once a submit signalled new records. Every
+/// other descriptor never blocks, so it is always reported as ready.
+fn revents(process: &Process, entry: &pollfd) -> i16 {
+    // Negative descriptors are ignored, as in POSIX
+    if entry.fd < 0 {
+        return 0;
+    }
+    if !process
+        .file_descriptors()
+        .read()
+        .contains_key(&FdNum::from(entry.fd))
+    {
+        return POLLNVAL;
+    }
+
+    let ready = match bpf_fd::object(process, entry.fd as u32) {
+        Some(BpfObject::Map(map)) => match map.map().as_ringbuf() {
+            Some(ringbuf) if ringbuf.is_ready() => POLLIN,
+            Some(_) => 0,
+            None => POLLIN | POLLOUT,
+        },
+        _ => POLLIN | POLLOUT,
+    };
+    entry.events & ready
+}
@@ -3,6 +3,7 @@ use kernel_vfs::path::AbsolutePath;
 
 use crate::arch::UserContext;
 use crate::mcore::context::ExecutionContext;
+use crate::mcore::mtask::process::{Process, ProcessId};
 use crate::syscall::validation::{
     copy_to_userspace, read_userspace_string, read_userspace_string_array,
 };
@@ -99,78 +100,58 @@ pub fn sys_execve(
 }
 
 pub fn sys_waitpid(pid: isize, status_ptr: usize, options: usize) -> Result<usize, Errno> {
-    let ctx = ExecutionContext::load();
-    let current_process = ctx.current_process();
-    let pid_arg = pid;
-
-    loop {
-        let mut reaped_pid = None;
-        let mut reaped_status = 0;
-
-        {
-            let mut tree = crate::mcore::mtask::process::tree::process_tree().write();
-            // Check if we have any children at all
-            if let Some(children) = tree.children.get_mut(&current_process.pid()) {
-                let mut index_to_remove = None;
-
-                for (i, child) in children.iter().enumerate() {
-                    // Filter by PID
-                    // pid > 0: wait for specific pid
-                    // pid == -1: wait for any child
-                    // pid == 0: wait for any child in same process group (TODO)
-                    // pid < -1: wait for any child in specific process group (TODO)
-                    if pid_arg > 0 && child.pid().as_u64() != pid_arg as u64 {
-                        continue;
-                    }
-
-                    // Check if exited
-                    if let Some(code) = *child.exit_code().read() {
-                        reaped_pid = Some(child.pid());
-                        // Construct status: (exit_code << 8) | sig (0)
-                        reaped_status = (code & 0xff) << 8;
-                        index_to_remove = Some(i);
-                        break;
-                    }
-                }
-
-                if let Some(i) = index_to_remove {
-                    let child_proc = children.remove(i);
-                    // Remove from global processes map to drop the final Arc (unless other references exist)
-                    tree.processes.remove(&child_proc.pid());
-                }
-            } else {
-                // No children at all
-                return Err(ECHILD);
-            }
-        }
+    // The task may resume on another CPU, so keep the process rather than the context
+    let current_process = ExecutionContext::load().current_process().clone();
 
-        if let Some(pid) = reaped_pid {
-            if status_ptr != 0 {
-                // Copy status to userspace
-                let slice = unsafe {
-                    core::slice::from_raw_parts(&reaped_status as *const _ as *const u8, 4)
-                };
-                copy_to_userspace(status_ptr, slice)?;
-            }
-            use crate::U64Ext;
-            return Ok(pid.as_u64().into_usize());
-        }
-
-        if options & WNOHANG != 0 {
-            return Ok(0);
+    let (pid, status) = if options & WNOHANG != 0 {
+        match try_reap(&current_process, pid)? {
+            Some(reaped) => reaped,
+            None => return Ok(0),
         }
+    } else {
+        // Block until a child exits
+        current_process
+            .child_exit()
+            .wait_until(|| try_reap(&current_process, pid).transpose())?
+    };
+
+    if status_ptr != 0 {
+        // Copy status to userspace
+        copy_to_userspace(status_ptr, &status.to_ne_bytes())?;
+    }
+    use crate::U64Ext;
+    Ok(pid.as_u64().into_usize())
+}
 
-        // Yield to scheduler so other tasks (including our child) can run.
-        // TODO: Use a proper wait queue when available
-        #[cfg(target_arch = "x86_64")]
-        x86_64::instructions::interrupts::enable_and_hlt();
-        #[cfg(target_arch = "aarch64")]
-        // SAFETY: Interrupts are disabled during syscall handling. Reschedule
-        // switches to another task; when we're rescheduled, we re-check the child.
-        unsafe {
-            crate::mcore::context::ExecutionContext::load()
-                .scheduler_mut()
-                .reschedule();
+/// Reap an exited child of `process` that `pid_arg` selects.
+///
+/// Returns the child's PID and wait status, or `None` if none has exited yet.
+fn try_reap(process: &Process, pid_arg: isize) -> Result<Option<(ProcessId, i32)>, Errno> {
+    let mut tree = crate::mcore::mtask::process::tree::process_tree().write();
+    // Check if we have any children at all
+    let Some(children) = tree.children.get_mut(&process.pid()) else {
+        return Err(ECHILD);
+    };
+
+    // Filter by PID
+    // pid > 0: wait for specific pid
+    // pid == -1: wait for any child
+    // pid == 0: wait for any child in same process group (TODO)
+    // pid < -1: wait for any child in specific process group (TODO)
+    let exited = children.iter().enumerate().find_map(|(i, child)| {
+        if pid_arg > 0 && child.pid().as_u64() != pid_arg as u64 {
+            return None;
         }
-    }
+        // Construct status: (exit_code << 8) | sig (0)
+        let code = (*child.exit_code().read())?;
+        Some((i, (code & 0xff) << 8))
+    });
+    let Some((i, status)) = exited else {
+        return Ok(None);
+    };
+
+    let child_proc = children.remove(i);
+    // Remove from global processes map to drop the final Arc (unless other references exist)
+    tree.processes.remove(&child_proc.pid());
+    Ok(Some((child_proc.pid(), status)))
 }
@@ -0,0 +1,196 @@
+use core::mem::size_of;
+
+use kernel_abi::{
+    sched_attr, sched_param, Errno, E2BIG, EBUSY, EINVAL, EPERM, ESRCH, SCHED_DEADLINE,
+};
+
+use crate::mcore::context::ExecutionContext;
+use crate::mcore::cpu_count;
+use crate::mcore::mtask::process::tree::process_tree;
+use crate::mcore::mtask::scheduler::deadline::{DeadlineParams, DeadlineTask};
+use crate::mcore::mtask::scheduler::global::GlobalTaskQueue;
+use crate::mcore::mtask::task::{CpuSet, SchedParams};
+use crate::syscall::validation::{copy_from_userspace, copy_to_userspace, read_userspace_slice};
+use crate::time::get_kernel_time_ns;
+
+pub fn sys_sched_setscheduler(pid: usize, policy: i32, param_ptr: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    let param = copy_from_userspace::<sched_param>(param_ptr)?;
+    let params = SchedParams::from_abi(policy, param.sched_priority).ok_or(EINVAL)?;
+    set_current(params);
+    Ok(0)
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    let params = ExecutionContext::load().current_task().sched_params();
+    Ok(params.abi_policy() as usize)
+}
+
+pub fn sys_sched_setparam(pid: usize, param_ptr: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    let param = copy_from_userspace::<sched_param>(param_ptr)?;
+    let policy = ExecutionContext::load()
+        .current_task()
+        .sched_params()
+        .abi_policy();
+    let params = SchedParams::from_abi(policy, param.sched_priority).ok_or(EINVAL)?;
+    set_current(params);
+    Ok(0)
+}
+
+pub fn sys_sched_getparam(pid: usize, param_ptr: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    let params = ExecutionContext::load().current_task().sched_params();
+    // `sched_param` is a single `i32`
+    let priority = i32::from(params.priority());
+    copy_to_userspace(param_ptr, &priority.to_ne_bytes())?;
+    Ok(0)
+}
+
+pub fn sys_sched_setattr(pid: usize, attr_ptr: usize, flags: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    let attr = copy_from_userspace::<sched_attr>(attr_ptr)?;
+    if attr.size != 0 && (attr.size as usize) < size_of::<sched_attr>() {
+        return Err(E2BIG);
+    }
+    if flags != 0 || attr.sched_flags != 0 {
+        return Err(EINVAL);
+    }
+
+    let policy = i32::try_from(attr.sched_policy).map_err(|_| EINVAL)?;
+    if policy != SCHED_DEADLINE {
+        let priority = i32::try_from(attr.sched_priority).map_err(|_| EINVAL)?;
+        let params = SchedParams::from_abi(policy, priority).ok_or(EINVAL)?;
+        set_current(params);
+        return Ok(0);
+    }
+
+    if attr.sched_priority != 0 {
+        return Err(EINVAL);
+    }
+    let params = DeadlineParams::new(attr.sched_runtime, attr.sched_deadline, attr.sched_period)
+        .ok_or(EINVAL)?;
+
+    let ctx = ExecutionContext::load();
+    let task = ctx.current_task();
+    let now = get_kernel_time_ns();
+    // Admit against the bandwidth of every other deadline task
+    let server = task
+        .with_deadline_task(|previous| DeadlineTask::admit(params, Some(previous), now))
+        .unwrap_or_else(|| DeadlineTask::admit(params, None, now))
+        .ok_or(EBUSY)?;
+    task.set_deadline_task(server);
+    // SAFETY: Interrupts are disabled during syscall handling, so nothing
+    // else uses this CPU's scheduler.
+    unsafe { ctx.scheduler_mut().preempt() };
+    Ok(0)
+}
+
+pub fn sys_sched_getattr(
+    pid: usize,
+    attr_ptr: usize,
+    size: usize,
+    flags: usize,
+) -> Result<usize, Errno> {
+    check_target(pid)?;
+    if flags != 0 || size < size_of::<sched_attr>() {
+        return Err(EINVAL);
+    }
+
+    let task = ExecutionContext::load().current_task();
+    let params = task.sched_params();
+    let deadline = task.with_deadline_task(|server| server.params());
+    let attr = sched_attr {
+        size: size_of::<sched_attr>() as u32,
+        sched_policy: params.abi_policy() as u32,
+        sched_priority: u32::from(params.priority()),
+        sched_runtime: deadline.map_or(0, DeadlineParams::runtime_ns),
+        sched_deadline: deadline.map_or(0, DeadlineParams::deadline_ns),
+        sched_period: deadline.map_or(0, DeadlineParams::period_ns),
+        ..Default::default()
+    };
+
+    // SAFETY: `sched_attr` is a `repr(C)` struct without padding bytes.
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            (&attr as *const sched_attr).cast::<u8>(),
+            size_of::<sched_attr>(),
+        )
+    };
+    copy_to_userspace(attr_ptr, bytes)?;
+    Ok(0)
+}
+
+/// Restrict the current task to the CPUs in the `size` byte mask at
+/// `mask_ptr`, in which bit `n` stands for CPU `n`. Bits beyond the
+/// [`CpuSet::MAX_CPUS`] CPUs the kernel supports are ignored.
+pub fn sys_sched_setaffinity(pid: usize, size: usize, mask_ptr: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    if size == 0 {
+        return Err(EINVAL);
+    }
+    let mut bytes = [0; size_of::<u64>()];
+    let len = size.min(bytes.len());
+    bytes[..len].copy_from_slice(&read_userspace_slice(mask_ptr, len)?);
+    let affinity =
+        CpuSet::from_bits(u64::from_ne_bytes(bytes)).intersection(CpuSet::first(cpu_count()));
+    if affinity.intersection(GlobalTaskQueue::online()).is_empty() {
+        return Err(EINVAL);
+    }
+
+    let ctx = ExecutionContext::load();
+    ctx.current_task().set_affinity(affinity);
+    if !affinity.contains(ctx.cpu_id()) {
+        // SAFETY: Interrupts are disabled during syscall handling, so nothing
+        // else uses this CPU's scheduler.
+        unsafe { ctx.scheduler_mut().reschedule() };
+    }
+    Ok(0)
+}
+
+/// Write the CPU mask of the current task to the `size` bytes at
+/// `mask_ptr`, and return the size of the mask.
+pub fn sys_sched_getaffinity(pid: usize, size: usize, mask_ptr: usize) -> Result<usize, Errno> {
+    check_target(pid)?;
+    if size < size_of::<u64>() {
+        return Err(EINVAL);
+    }
+    let affinity = ExecutionContext::load().current_task().affinity();
+    copy_to_userspace(mask_ptr, &affinity.bits().to_ne_bytes())?;
+    Ok(size_of::<u64>())
+}
+
+pub fn sys_sched_yield() -> Result<usize, Errno> {
+    // SAFETY: Interrupts are disabled during syscall handling, so nothing
+    // else uses this CPU's scheduler.
+    unsafe { ExecutionContext::load().scheduler_mut().yield_now() };
+    Ok(0)
+}
+
+/// Check that `pid` names the calling process, either as 0 or by its PID.
+///
+/// Tasks aren't tracked per process, so the scheduling of other processes
+/// can't be changed.
+fn check_target(pid: usize) -> Result<(), Errno> {
+    let current = ExecutionContext::load().current_process().pid();
+    if pid == 0 || current == pid as u64 {
+        return Ok(());
+    }
+    let exists = process_tree()
+        .read()
+        .processes
+        .keys()
+        .any(|other| *other == pid as u64);
+    Err(if exists { EPERM } else { ESRCH })
+}
+
+/// Apply `params` to the current task, and give way if a ready task outranks
+/// it now.
+fn set_current(params: SchedParams) {
+    let ctx = ExecutionContext::load();
+    ctx.current_task().set_sched_params(params);
+    // SAFETY: Interrupts are disabled during syscall handling, so nothing
+    // else uses this CPU's scheduler.
+    unsafe { ctx.scheduler_mut().preempt() };
+}
@@ -218,13 +218,20 @@ pub extern "C" fn _start() -> ! {
             off: -8,
             imm: 0,
         }, // *(u64*)(r10-8) = r0
-        // 6. Call bpf_ringbuf_output(map_id, &data, 40, 0)
+        // 6. Call bpf_ringbuf_output(map, &data, 40, 0)
+        // r1 = map (wide load of the map fd, resolved to the map by the kernel)
         BpfInsn {
-            code: 0xb7,
-            dst_src: regs(1, 0),
+            code: 0x18,
+            dst_src: regs(1, 1),
             off: 0,
             imm: ringbuf_map_id,
         },
+        BpfInsn {
+            code: 0x00,
+            dst_src: 0x00,
+            off: 0,
+            imm: 0,
+        },
         BpfInsn {
             code: 0xbf,
             dst_src: regs(2, 10),
@@ -253,7 +260,7 @@ pub extern "C" fn _start() -> ! {
             code: 0x85,
             dst_src: 0x00,
             off: 0,
-            imm: 8,
+            imm: 6,
         },
         BpfInsn {
             code: 0xb7,
@@ -101,7 +101,7 @@ pub extern "C" fn _start() -> ! {
     write(1, b")\n");
 
     // ==================================================================
-    // Step 3: Construct BPF program (27 instructions, 3 helpers)
+    // Step 3: Construct BPF program (29 instructions, 3 helpers)
     //
     // On each timer tick, this program:
     //   a) Looks up counter from array map (key=0) via bpf_map_lookup_elem
@@ -111,10 +111,10 @@ pub extern "C" fn _start() -> ! {
     //   e) Calls bpf_trace_printk for serial visibility
     //   f) Returns 0
     //
-    // Helper IDs (from interpreter dispatch):
+    // Helper IDs (from the kernel_bpf::helpers registry):
     //   2 = bpf_trace_printk(fmt_ptr, size)
-    //   3 = bpf_map_lookup_elem(map_id, key_ptr) -> returns *mut u8
-    //   6 = bpf_ringbuf_output(map_id, data_ptr, data_size, flags)
+    //   3 = bpf_map_lookup_elem(map, key_ptr) -> returns *mut u8
+    //   6 = bpf_ringbuf_output(map, data_ptr, data_size, flags)
     //
     // Stack layout (r10-relative):
     //   r10 - 4  : key (u32 = 0)       [4 bytes]
@@ -147,28 +147,34 @@ pub extern "C" fn _start() -> ! {
             imm: 0,
         },
         // --- Call bpf_map_lookup_elem(array_map_id, &key) ---
-        // Insn 2: r1 = array_map_id
+        // Insn 2-3: r1 = array map (LD_DW_IMM of the map fd, resolved by the kernel)
         BpfInsn {
-            code: 0xb7,
-            dst_src: regs(1, 0),
+            code: 0x18,
+            dst_src: regs(1, 1),
             off: 0,
             imm: array_map_id,
         },
-        // Insn 3: r2 = r10
+        BpfInsn {
+            code: 0x00,
+            dst_src: 0x00,
+            off: 0,
+            imm: 0,
+        },
+        // Insn 4: r2 = r10
         BpfInsn {
             code: 0xbf,
             dst_src: regs(2, 10),
             off: 0,
             imm: 0,
         },
-        // Insn 4: r2 += -4  (point to key on stack)
+        // Insn 5: r2 += -4  (point to key on stack)
         BpfInsn {
             code: 0x07,
             dst_src: regs(2, 0),
             off: 0,
             imm: -4,
         },
-        // Insn 5: call bpf_map_lookup_elem (helper 3)
+        // Insn 6: call bpf_map_lookup_elem (helper 3)
         BpfInsn {
             code: 0x85,
             dst_src: 0x00,
@@ -176,37 +182,37 @@ pub extern "C" fn _start() -> ! {
             imm: 3,
         },
         // --- Check if lookup returned NULL; skip map+ringbuf if so ---
-        // Insn 6: if r0 == 0 goto +11 -> target = insn 18 (trace_printk)
-        //         Jump formula: target = pc + 1 + off = 6 + 1 + 11 = 18
+        // Insn 7: if r0 == 0 goto +12 -> target = insn 20 (trace_printk)
+        //         Jump formula: target = pc + 1 + off = 7 + 1 + 12 = 20
         BpfInsn {
             code: 0x15,
             dst_src: regs(0, 0),
-            off: 11,
+            off: 12,
             imm: 0,
         },
         // --- Increment counter in-place via direct pointer ---
-        // Insn 7: r6 = r0  (save map value pointer in callee-saved r6)
+        // Insn 8: r6 = r0  (save map value pointer in callee-saved r6)
         BpfInsn {
             code: 0xbf,
             dst_src: regs(6, 0),
             off: 0,
             imm: 0,
         },
-        // Insn 8: r1 = *(u64*)(r6 + 0)  (load current counter value)
+        // Insn 9: r1 = *(u64*)(r6 + 0)  (load current counter value)
         BpfInsn {
             code: 0x79,
             dst_src: regs(1, 6),
             off: 0,
             imm: 0,
         },
-        // Insn 9: r1 += 1  (increment)
+        // Insn 10: r1 += 1  (increment)
         BpfInsn {
             code: 0x07,
             dst_src: regs(1, 0),
             off: 0,
             imm: 1,
         },
-        // Insn 10: *(u64*)(r6 + 0) = r1  (store incremented value back)
+        // Insn 11: *(u64*)(r6 + 0) = r1  (store incremented value back)
         BpfInsn {
             code: 0x7b,
             dst_src: regs(6, 1),
@@ -214,7 +220,7 @@ pub extern "C" fn _start() -> ! {
             imm: 0,
         },
         // --- Store counter on stack for ringbuf event data ---
-        // Insn 11: *(u64*)(r10 - 16) = r1
+        // Insn 12: *(u64*)(r10 - 16) = r1
         BpfInsn {
             code: 0x7b,
             dst_src: regs(10, 1),
@@ -222,42 +228,48 @@ pub extern "C" fn _start() -> ! {
             imm: 0,
         },
         // --- Call bpf_ringbuf_output(ringbuf_map_id, &counter, 8, 0) ---
-        // Insn 12: r1 = ringbuf_map_id
+        // Insn 13-14: r1 = ringbuf map (LD_DW_IMM of the map fd, resolved by the kernel)
         BpfInsn {
-            code: 0xb7,
-            dst_src: regs(1, 0),
+            code: 0x18,
+            dst_src: regs(1, 1),
             off: 0,
             imm: ringbuf_map_id,
         },
-        // Insn 13: r2 = r10
+        BpfInsn {
+            code: 0x00,
+            dst_src: 0x00,
+            off: 0,
+            imm: 0,
+        },
+        // Insn 15: r2 = r10
         BpfInsn {
             code: 0xbf,
             dst_src: regs(2, 10),
             off: 0,
             imm: 0,
         },
-        // Insn 14: r2 += -16  (point to counter on stack)
+        // Insn 16: r2 += -16  (point to counter on stack)
         BpfInsn {
             code: 0x07,
             dst_src: regs(2, 0),
             off: 0,
             imm: -16,
         },
-        // Insn 15: r3 = 8  (data size = sizeof(u64))
+        // Insn 17: r3 = 8  (data size = sizeof(u64))
         BpfInsn {
             code: 0xb7,
             dst_src: regs(3, 0),
             off: 0,
             imm: 8,
         },
-        // Insn 16: r4 = 0  (flags)
+        // Insn 18: r4 = 0  (flags)
         BpfInsn {
             code: 0xb7,
             dst_src: regs(4, 0),
             off: 0,
             imm: 0,
         },
-        // Insn 17: call bpf_ringbuf_output (helper 6)
+        // Insn 19: call bpf_ringbuf_output (helper 6)
         BpfInsn {
             code: 0x85,
             dst_src: 0x00,
@@ -265,49 +277,49 @@ pub extern "C" fn _start() -> ! {
             imm: 6,
         },
         // --- Call bpf_trace_printk("Tick!", 6) for serial visibility ---
-        // Insn 18: LD_DW_IMM r1, "Tick!\0" (occupies 2 instruction slots)
+        // Insn 20: LD_DW_IMM r1, "Tick!\0" (occupies 2 instruction slots)
         BpfInsn {
             code: 0x18,
             dst_src: regs(1, 0),
             off: 0,
             imm: tick_lo,
         },
-        // Insn 19: (continuation of LD_DW_IMM)
+        // Insn 21: (continuation of LD_DW_IMM)
         BpfInsn {
             code: 0x00,
             dst_src: 0x00,
             off: 0,
             imm: tick_hi,
         },
-        // Insn 20: *(u64*)(r10 - 24) = r1  (store string on stack)
+        // Insn 22: *(u64*)(r10 - 24) = r1  (store string on stack)
         BpfInsn {
             code: 0x7b,
             dst_src: regs(10, 1),
             off: -24,
             imm: 0,
         },
-        // Insn 21: r1 = r10
+        // Insn 23: r1 = r10
         BpfInsn {
             code: 0xbf,
             dst_src: regs(1, 10),
             off: 0,
             imm: 0,
         },
-        // Insn 22: r1 += -24  (pointer to string on stack)
+        // Insn 24: r1 += -24  (pointer to string on stack)
         BpfInsn {
             code: 0x07,
             dst_src: regs(1, 0),
             off: 0,
             imm: -24,
         },
-        // Insn 23: r2 = 6  (string length including NUL)
+        // Insn 25: r2 = 6  (string length including NUL)
         BpfInsn {
             code: 0xb7,
             dst_src: regs(2, 0),
             off: 0,
             imm: 6,
         },
-        // Insn 24: call bpf_trace_printk (helper 2)
+        // Insn 26: call bpf_trace_printk (helper 2)
         BpfInsn {
             code: 0x85,
             dst_src: 0x00,
@@ -315,14 +327,14 @@ pub extern "C" fn _start() -> ! {
             imm: 2,
         },
         // --- Return 0 ---
-        // Insn 25: r0 = 0
+        // Insn 27: r0 = 0
         BpfInsn {
             code: 0xb7,
             dst_src: regs(0, 0),
             off: 0,
             imm: 0,
         },
-        // Insn 26: exit
+        // Insn 28: exit
         BpfInsn {
             code: 0x95,
             dst_src: 0x00,
@@ -21,6 +21,7 @@ pub const STRUCTURE: Dir<'static> = Dir::new(
             ],
         ),
         Dir::new("dev", &[Dir::new("fd", &[], &[])], &[]),
+        Dir::new("etc", &[Dir::new("bpf", &[], &[])], &[]),
         Dir::new("var", &[Dir::new("tmp", &[], &[])], &[]),
     ],
     &[],
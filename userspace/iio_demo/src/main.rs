@@ -89,28 +89,34 @@ pub extern "C" fn _start() -> ! {
             off: IIOVENT_VALUE_OFFSET,
             imm: 0,
         },
-        // if R0 < 100, goto reject (offset +8)
+        // if R0 < 100, goto reject (offset +9)
         BpfInsn {
             code: 0x35,    // JSLT (signed <)
             dst_src: 0x00, // dst=R0, src=imm
-            off: 8,        // skip to reject
+            off: 9,        // skip to reject
             imm: MIN_VALUE,
         },
-        // if R0 > 900, goto reject (offset +7)
+        // if R0 > 900, goto reject (offset +8)
         BpfInsn {
             code: 0x25,    // JGT (signed >)
             dst_src: 0x00, // dst=R0, src=imm
-            off: 7,        // skip to reject
+            off: 8,        // skip to reject
             imm: MAX_VALUE,
         },
         // Value is in range - output to ringbuf
-        // R1 = ringbuf_id
+        // R1 = ringbuf map (wide load of its fd, resolved by the kernel)
         BpfInsn {
-            code: 0xb7,    // MOV64_IMM
-            dst_src: 0x01, // dst=R1
+            code: 0x18,    // LD_DW_IMM
+            dst_src: 0x11, // dst=R1, src=BPF_PSEUDO_MAP_FD
             off: 0,
             imm: ringbuf_id,
         },
+        BpfInsn {
+            code: 0x00, // second slot of LD_DW_IMM
+            dst_src: 0x00,
+            off: 0,
+            imm: 0,
+        },
         // R2 = R6 (event pointer)
         BpfInsn {
             code: 0xbf,    // MOV64
@@ -74,7 +74,7 @@ pub extern "C" fn _start() -> ! {
         write(1, b"Loading counter BPF program...\n");
 
         // BPF program bytecode:
-        // r6 = map_id (will be patched)
+        // r6 = map (LD_DW_IMM of the map fd, resolved by the kernel)
         // *(u32 *)(r10 - 4) = 0  // key = 0 on stack
         // r1 = r6                 // map_id
         // r2 = r10 - 4            // key pointer
@@ -86,13 +86,19 @@ pub extern "C" fn _start() -> ! {
         // exit
 
         let insns = [
-            // r6 = map_id (0 in this case)
+            // r6 = map (LD_DW_IMM with src_reg = BPF_PSEUDO_MAP_FD)
             BpfInsn {
-                code: 0xb7,
-                dst_src: 0x06,
+                code: 0x18,
+                dst_src: 0x16,
                 off: 0,
                 imm: map_id,
             },
+            BpfInsn {
+                code: 0x00,
+                dst_src: 0x00,
+                off: 0,
+                imm: 0,
+            },
             // r1 = 0 (key value)
             BpfInsn {
                 code: 0xb7,
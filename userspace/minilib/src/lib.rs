@@ -107,6 +107,27 @@ pub fn syscall4(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) ->
     }
 }
 
+pub fn syscall6(
+    n: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    arg5: usize,
+    arg6: usize,
+) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        let mut rax = n;
+        asm!("int 0x80", inout("rax") rax, in("rdi") arg1, in("rsi") arg2, in("rdx") arg3, in("rcx") arg4, in("r8") arg5, in("r9") arg6, clobber_abi("C"));
+        rax
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        do_syscall(n, arg1, arg2, arg3, arg4, arg5, arg6)
+    }
+}
+
 // --- libc-like functions ---
 
 pub fn exit(code: i32) -> ! {
@@ -283,6 +304,31 @@ pub fn writev(fd: c_int, iov: &[iovec]) -> c_int {
     syscall3(38, fd as usize, iov.as_ptr() as usize, iov.len()) as i32
 }
 
+pub const POLLIN: i16 = 0x001;
+pub const POLLPRI: i16 = 0x002;
+pub const POLLOUT: i16 = 0x004;
+pub const POLLERR: i16 = 0x008;
+pub const POLLHUP: i16 = 0x010;
+pub const POLLNVAL: i16 = 0x020;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct pollfd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+/// Wait until one of `fds` is ready. A negative timeout waits forever.
+pub fn poll(fds: &mut [pollfd], timeout_ms: c_int) -> c_int {
+    syscall3(
+        24,
+        fds.as_mut_ptr() as usize,
+        fds.len(),
+        timeout_ms as usize,
+    ) as i32
+}
+
 // --- Memory ---
 
 pub fn malloc(size: usize) -> *mut u8 {
@@ -293,6 +339,37 @@ pub fn free(ptr: *mut u8) {
     syscall1(28, ptr as usize);
 }
 
+pub const PROT_READ: c_int = 0x1;
+pub const PROT_WRITE: c_int = 0x2;
+pub const PROT_EXEC: c_int = 0x4;
+
+pub const MAP_SHARED: c_int = 0x01;
+pub const MAP_PRIVATE: c_int = 0x02;
+pub const MAP_FIXED: c_int = 0x10;
+pub const MAP_ANONYMOUS: c_int = 0x20;
+
+pub const MAP_FAILED: *mut u8 = usize::MAX as *mut u8;
+
+pub fn mmap(
+    addr: *mut u8,
+    len: usize,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: usize,
+) -> *mut u8 {
+    let ret = syscall6(
+        41,
+        addr as usize,
+        len,
+        prot as usize,
+        flags as usize,
+        fd as usize,
+        offset,
+    ) as isize;
+    if ret < 0 { MAP_FAILED } else { ret as *mut u8 }
+}
+
 // --- Process Management ---
 
 pub const WNOHANG: c_int = 1;
@@ -308,3 +385,105 @@ pub fn execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -
 pub fn waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int {
     syscall3(59, pid as usize, status as usize, options as usize) as c_int
 }
+
+// --- Scheduling ---
+
+pub const SCHED_OTHER: c_int = 0;
+pub const SCHED_FIFO: c_int = 1;
+pub const SCHED_RR: c_int = 2;
+pub const SCHED_DEADLINE: c_int = 6;
+
+pub const SCHED_PRIORITY_MIN: c_int = 1;
+pub const SCHED_PRIORITY_MAX: c_int = 99;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct sched_param {
+    pub sched_priority: c_int,
+}
+
+/// Set the policy and priority of the calling process (`pid` 0 or its own).
+/// Real-time policies take a priority between `SCHED_PRIORITY_MIN` and
+/// `SCHED_PRIORITY_MAX`, `SCHED_OTHER` takes 0.
+pub fn sched_setscheduler(pid: c_int, policy: c_int, param: &sched_param) -> c_int {
+    syscall3(
+        60,
+        pid as usize,
+        policy as usize,
+        param as *const sched_param as usize,
+    ) as c_int
+}
+
+pub fn sched_getscheduler(pid: c_int) -> c_int {
+    syscall1(61, pid as usize) as c_int
+}
+
+/// Change the priority, keeping the policy.
+pub fn sched_setparam(pid: c_int, param: &sched_param) -> c_int {
+    syscall2(62, pid as usize, param as *const sched_param as usize) as c_int
+}
+
+pub fn sched_getparam(pid: c_int, param: &mut sched_param) -> c_int {
+    syscall2(63, pid as usize, param as *mut sched_param as usize) as c_int
+}
+
+/// Let another ready task of at least the same priority run.
+pub fn sched_yield() -> c_int {
+    syscall0(64) as c_int
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct sched_attr {
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    pub sched_nice: i32,
+    pub sched_priority: u32,
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
+
+/// Set the policy and its parameters, the only way to `SCHED_DEADLINE`.
+/// Fails with `EBUSY` if the deadline tasks would need more CPU time than
+/// there is.
+pub fn sched_setattr(pid: c_int, attr: &sched_attr, flags: u32) -> c_int {
+    syscall3(
+        65,
+        pid as usize,
+        attr as *const sched_attr as usize,
+        flags as usize,
+    ) as c_int
+}
+
+pub fn sched_getattr(pid: c_int, attr: &mut sched_attr, size: u32, flags: u32) -> c_int {
+    syscall4(
+        66,
+        pid as usize,
+        attr as *mut sched_attr as usize,
+        size as usize,
+        flags as usize,
+    ) as c_int
+}
+
+/// Restrict the calling task to the CPUs whose bits are set in `mask`, and
+/// move it off the current CPU if that isn't one of them.
+pub fn sched_setaffinity(pid: c_int, mask: &u64) -> c_int {
+    syscall3(
+        67,
+        pid as usize,
+        size_of::<u64>(),
+        mask as *const u64 as usize,
+    ) as c_int
+}
+
+/// Returns the size of the mask written to `mask`.
+pub fn sched_getaffinity(pid: c_int, mask: &mut u64) -> c_int {
+    syscall3(
+        68,
+        pid as usize,
+        size_of::<u64>(),
+        mask as *mut u64 as usize,
+    ) as c_int
+}
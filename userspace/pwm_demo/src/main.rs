@@ -4,7 +4,7 @@
 use kernel_abi::BpfAttr;
 use minilib::{bpf, exit, write};
 
-// Helper IDs (runtime dispatch in interpreter/JIT)
+// Helper IDs (from the kernel_bpf::helpers registry)
 const HELPER_KTIME_GET_NS: i32 = 1;
 const HELPER_RINGBUF_OUTPUT: i32 = 6;
 const HELPER_PWM_WRITE: i32 = 1005;
@@ -50,6 +50,26 @@ impl BpfInsn {
         }
     }
 
+    /// First slot of a wide load of a map fd: dst = map (fd resolved by the kernel)
+    const fn ld_map_fd(dst: u8, fd: i32) -> Self {
+        Self {
+            code: 0x18,
+            dst_src: (1 << 4) | dst,
+            off: 0,
+            imm: fd,
+        }
+    }
+
+    /// Second slot of a wide load, holding the upper 32 bits of the immediate
+    const fn ld_imm64_hi(imm: i32) -> Self {
+        Self {
+            code: 0,
+            dst_src: 0,
+            off: 0,
+            imm,
+        }
+    }
+
     const fn add64_imm(dst: u8, imm: i32) -> Self {
         Self {
             code: 0x07,
@@ -198,9 +218,10 @@ pub extern "C" fn _start() -> ! {
         BpfInsn::ldx_w(7, 6, 16),
         // Store period_ns to stack: *(u32*)(R10 - 8) = R7
         BpfInsn::stx_w(10, 7, -8),
-        // Call bpf_ringbuf_output(map_id, data_ptr, data_size, flags)
+        // Call bpf_ringbuf_output(map, data_ptr, data_size, flags)
         // R1 = map_id
-        BpfInsn::mov64_imm(1, map_id),
+        BpfInsn::ld_map_fd(1, map_id),
+        BpfInsn::ld_imm64_hi(0),
         // R2 = R10 - 24 (pointer to event data on stack)
         BpfInsn::mov64_reg(2, 10),
         BpfInsn::add64_imm(2, -24),
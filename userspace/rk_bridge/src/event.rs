@@ -1,9 +1,11 @@
 //! Event types for rkBPF kernel events.
 //!
 //! These types represent the events that can be produced by rkBPF programs
-//! and consumed by the ROS2 bridge.
+//! and consumed by the ROS2 bridge. Records of other layouts can be decoded
+//! with the program's BTF (see [`crate::btf`]).
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// Common header for all rkBPF events.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -231,6 +233,13 @@ pub enum RkEvent {
     TimeSeries(TimeSeriesEvent),
     /// Debug trace event
     Trace(TraceEvent),
+    /// Record decoded by its BTF type
+    Typed {
+        /// C name of the record type
+        type_name: String,
+        /// Decoded record, with struct members as fields
+        value: Value,
+    },
     /// Unknown/raw event
     Unknown {
         /// Event type
@@ -311,6 +320,7 @@ impl RkEvent {
             RkEvent::Gpio(e) => e.header.timestamp_ns,
             RkEvent::TimeSeries(e) => e.header.timestamp_ns,
             RkEvent::Trace(e) => e.header.timestamp_ns,
+            RkEvent::Typed { value, .. } => Self::typed_field(value, "timestamp_ns"),
             RkEvent::Unknown { .. } => 0,
         }
     }
@@ -324,9 +334,20 @@ impl RkEvent {
             RkEvent::Gpio(_) => GpioEvent::EVENT_TYPE,
             RkEvent::TimeSeries(_) => TimeSeriesEvent::EVENT_TYPE,
             RkEvent::Trace(_) => TraceEvent::EVENT_TYPE,
+            RkEvent::Typed { value, .. } => Self::typed_field(value, "event_type") as u32,
             RkEvent::Unknown { event_type, .. } => *event_type,
         }
     }
+
+    /// A header field of a typed record, either a member of the record or
+    /// of its `header` member, like the built-in events.
+    fn typed_field(value: &Value, name: &str) -> u64 {
+        value
+            .get(name)
+            .or_else(|| value.get("header")?.get(name))
+            .and_then(Value::as_u64)
+            .unwrap_or(0)
+    }
 }
 
 #[cfg(test)]
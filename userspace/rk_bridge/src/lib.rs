@@ -34,12 +34,17 @@
 //!
 //! # Bridge motor events with custom rate limiting
 //! rk-to-ros --map /sys/fs/bpf/maps/motor_events --topic /rk/motor --rate-limit 1000
+//!
+//! # Decode records by their BTF type instead of the built-in layouts
+//! rk-to-ros --map /sys/fs/bpf/maps/events --btf imu.o --type imu_sample
 //! ```
 
+pub mod btf;
 pub mod event;
 pub mod publisher;
 pub mod ringbuf;
 
+pub use btf::{Btf, BtfError, RecordDecoder};
 pub use event::{EventHeader, ImuEvent, MotorEvent, RkEvent, SafetyEvent};
 pub use publisher::{EventPublisher, PublisherConfig, RosPublisher, StdoutPublisher};
 pub use ringbuf::{RingBufConsumer, RingBufError};
@@ -54,6 +59,10 @@ pub enum Error {
     #[error("ring buffer error: {0}")]
     RingBuf(#[from] RingBufError),
 
+    /// BTF error
+    #[error("BTF error: {0}")]
+    Btf(#[from] BtfError),
+
     /// I/O error
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
@@ -14,11 +14,15 @@
 //!
 //! # With rate limiting
 //! rk-to-ros --map /sys/fs/bpf/maps/motor_events --topic /rk/motor --rate-limit 1000
+//!
+//! # Decode records with the BTF of the program's object file
+//! rk-to-ros --map /sys/fs/bpf/maps/events --btf imu.o --type imu_sample
 //! ```
 
 use anyhow::{Context, Result};
 use clap::{Parser, ValueEnum};
 use rk_bridge::{
+    btf::{Btf, RecordDecoder},
     event::RkEvent,
     publisher::{EventPublisher, OutputFormat, PublisherConfig, RosPublisher, StdoutPublisher},
     ringbuf::RingBufConsumer,
@@ -47,6 +51,9 @@ Examples:
 
   # With rate limiting (max 1000 events/sec)
   rk-to-ros --map /sys/fs/bpf/maps/motor --topic /rk/motor --rate-limit 1000
+
+  # Decode records as `struct imu_sample` using the object's BTF
+  rk-to-ros --map /sys/fs/bpf/maps/events --btf imu.o --type imu_sample
 "#)]
 struct Args {
     /// Path to the pinned rkBPF ring buffer map
@@ -81,6 +88,14 @@ struct Args {
     #[arg(long)]
     demo: bool,
 
+    /// BTF describing the records: a BPF object file or a raw .BTF blob
+    #[arg(long, requires = "record_type")]
+    btf: Option<PathBuf>,
+
+    /// Name of the record type in the BTF, e.g. imu_sample
+    #[arg(long = "type", requires = "btf")]
+    record_type: Option<String>,
+
     /// Verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -108,6 +123,7 @@ struct Bridge {
     publisher: Box<dyn EventPublisher>,
     poll_interval: Duration,
     running: Arc<AtomicBool>,
+    decoder: Option<RecordDecoder>,
 }
 
 impl Bridge {
@@ -116,6 +132,20 @@ impl Bridge {
             publisher,
             poll_interval,
             running: Arc::new(AtomicBool::new(true)),
+            decoder: None,
+        }
+    }
+
+    /// Decode records by their BTF type instead of the built-in layouts.
+    fn with_decoder(mut self, decoder: RecordDecoder) -> Self {
+        self.decoder = Some(decoder);
+        self
+    }
+
+    fn parse(&self, data: &[u8]) -> std::result::Result<RkEvent, String> {
+        match &self.decoder {
+            Some(decoder) => decoder.decode(data).map_err(|e| e.to_string()),
+            None => RkEvent::from_bytes(data).map_err(str::to_string),
         }
     }
 
@@ -130,7 +160,7 @@ impl Bridge {
 
             // Poll for events
             for data in consumer.poll() {
-                match RkEvent::from_bytes(&data) {
+                match self.parse(&data) {
                     Ok(event) => {
                         if let Err(e) = self.publisher.publish(&event) {
                             log::warn!("Failed to publish event: {}", e);
@@ -274,7 +304,13 @@ async fn main() -> Result<()> {
     };
 
     // Create bridge
-    let bridge = Bridge::new(publisher, Duration::from_millis(args.poll_interval));
+    let mut bridge = Bridge::new(publisher, Duration::from_millis(args.poll_interval));
+    if let (Some(path), Some(name)) = (&args.btf, &args.record_type) {
+        let btf = Btf::load(path).with_context(|| format!("Failed to load BTF from {:?}", path))?;
+        let decoder = RecordDecoder::new(btf, name)?;
+        log::info!("Decoding records as {}", name);
+        bridge = bridge.with_decoder(decoder);
+    }
 
     // Set up signal handler for graceful shutdown
     let running = bridge.running();
@@ -197,6 +197,9 @@ impl StdoutPublisher {
             RkEvent::Trace(e) => {
                 format!("{}TRACE: {}", ts, e.message)
             }
+            RkEvent::Typed { type_name, value } => {
+                format!("{}{}: {}", ts, type_name, value)
+            }
             RkEvent::Unknown { event_type, data } => {
                 format!("{}UNKNOWN[type={}]: {} bytes", ts, event_type, data.len())
             }
@@ -2,24 +2,18 @@
 //!
 //! This module provides a userspace consumer for BPF ring buffers,
 //! allowing efficient reading of kernel events via memory mapping.
+//!
+//! The mapping follows the Linux layout: a writable consumer page,
+//! followed by a read-only producer page and the data pages, which the
+//! kernel maps twice in a row so records never wrap.
 
 use std::fs::File;
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-/// Ring buffer header structure (matches kernel definition).
-#[repr(C)]
-struct RingBufHeader {
-    /// Consumer position (userspace updates this)
-    consumer_pos: AtomicU64,
-    /// Padding to cache line
-    _pad1: [u8; 56],
-    /// Producer position (kernel updates this)
-    producer_pos: AtomicU64,
-    /// Padding to cache line
-    _pad2: [u8; 56],
-}
+/// Size of the consumer and producer pages.
+const PAGE_SIZE: usize = 4096;
 
 /// Ring buffer record header.
 #[repr(C)]
@@ -75,9 +69,11 @@ pub enum RingBufError {
 
 /// Consumer for reading events from a BPF ring buffer.
 pub struct RingBufConsumer {
-    /// Memory-mapped header region
-    header: *mut RingBufHeader,
-    /// Memory-mapped data region
+    /// Memory-mapped consumer page (userspace updates the position)
+    consumer: *mut AtomicU64,
+    /// Memory-mapped producer page (kernel updates the position)
+    producer: *const AtomicU64,
+    /// Memory-mapped data region, mapped twice in a row
     data: *const u8,
     /// Size of the data region (power of 2)
     data_size: usize,
@@ -123,17 +119,13 @@ impl RingBufConsumer {
             return Err(RingBufError::InvalidSize(data_size));
         }
 
-        let header_size = std::mem::size_of::<RingBufHeader>();
-        let total_size = header_size + data_size;
-
-        // Memory map the ring buffer
+        // Memory map the consumer page, the only one we may write to
         // SAFETY: We are mapping a file descriptor that represents a BPF ring buffer.
-        // We trust the kernel to provide a valid mapping. The size is calculated based
-        // on the header size and data size.
-        let ptr = unsafe {
+        // We trust the kernel to provide a valid mapping of the consumer page.
+        let consumer = unsafe {
             libc::mmap(
                 std::ptr::null_mut(),
-                total_size,
+                PAGE_SIZE,
                 libc::PROT_READ | libc::PROT_WRITE,
                 libc::MAP_SHARED,
                 fd,
@@ -141,17 +133,39 @@ impl RingBufConsumer {
             )
         };
 
-        if ptr == libc::MAP_FAILED {
+        if consumer == libc::MAP_FAILED {
             return Err(RingBufError::Mmap(std::io::Error::last_os_error()));
         }
 
-        let header = ptr as *mut RingBufHeader;
-        // SAFETY: The data region follows the header in the mapped memory.
-        // We verified the mapping size covers both header and data.
-        let data = unsafe { (ptr as *const u8).add(header_size) };
+        // Memory map the producer page and the data pages, which follow it
+        // SAFETY: As above. The kernel maps the data pages twice, so the size
+        // covers the producer page and two copies of the data region.
+        let producer = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                PAGE_SIZE + 2 * data_size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                fd,
+                PAGE_SIZE as libc::off_t,
+            )
+        };
+
+        if producer == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            // SAFETY: We are unmapping the consumer page mapped above.
+            unsafe {
+                libc::munmap(consumer, PAGE_SIZE);
+            }
+            return Err(RingBufError::Mmap(err));
+        }
+
+        // SAFETY: The data region follows the producer page in the mapping.
+        let data = unsafe { (producer as *const u8).add(PAGE_SIZE) };
 
         Ok(Self {
-            header,
+            consumer: consumer as *mut AtomicU64,
+            producer: producer as *const AtomicU64,
             data,
             data_size,
             mask: data_size - 1,
@@ -182,11 +196,11 @@ impl RingBufConsumer {
     ///
     /// Returns `None` if no events are available.
     pub fn read_event(&self) -> Option<Vec<u8>> {
-        // SAFETY: self.header points to valid mapped memory for the lifetime of self.
-        let header = unsafe { &*self.header };
+        // SAFETY: Both pointers point to valid mapped memory for the lifetime of self.
+        let (consumer_pos, producer_pos) = unsafe { (&*self.consumer, &*self.producer) };
 
-        let cons_pos = header.consumer_pos.load(Ordering::Acquire);
-        let prod_pos = header.producer_pos.load(Ordering::Acquire);
+        let cons_pos = consumer_pos.load(Ordering::Acquire);
+        let prod_pos = producer_pos.load(Ordering::Acquire);
 
         if cons_pos >= prod_pos {
             return None;
@@ -216,23 +230,17 @@ impl RingBufConsumer {
         let data = if record_header.is_discarded() {
             Vec::new()
         } else {
-            let data_offset = (record_offset + header_size) & self.mask;
             let mut data = vec![0u8; data_len];
 
-            // Handle wrap-around
-            let first_chunk = std::cmp::min(data_len, self.data_size - data_offset);
             // SAFETY: We are copying data from the memory mapped ring buffer to a vector.
-            // We ensure that we don't read past the bounds of the ring buffer using first_chunk calculation.
+            // The data region is mapped twice in a row, so a record starting inside the
+            // first copy can be read in one piece even if it wraps around.
             unsafe {
-                std::ptr::copy_nonoverlapping(self.data.add(data_offset), data.as_mut_ptr(), first_chunk);
-
-                if first_chunk < data_len {
-                    std::ptr::copy_nonoverlapping(
-                        self.data,
-                        data.as_mut_ptr().add(first_chunk),
-                        data_len - first_chunk,
-                    );
-                }
+                std::ptr::copy_nonoverlapping(
+                    self.data.add(record_offset + header_size),
+                    data.as_mut_ptr(),
+                    data_len,
+                );
             }
 
             data
@@ -240,7 +248,7 @@ impl RingBufConsumer {
 
         // Advance consumer position
         let new_cons_pos = cons_pos + record_size as u64;
-        header.consumer_pos.store(new_cons_pos, Ordering::Release);
+        consumer_pos.store(new_cons_pos, Ordering::Release);
 
         if data.is_empty() {
             // Discarded record, try next
@@ -252,10 +260,10 @@ impl RingBufConsumer {
 
     /// Get the number of bytes available to read.
     pub fn available(&self) -> usize {
-        // SAFETY: self.header points to valid mapped memory for the lifetime of self.
-        let header = unsafe { &*self.header };
-        let cons_pos = header.consumer_pos.load(Ordering::Relaxed);
-        let prod_pos = header.producer_pos.load(Ordering::Relaxed);
+        // SAFETY: Both pointers point to valid mapped memory for the lifetime of self.
+        let (consumer_pos, producer_pos) = unsafe { (&*self.consumer, &*self.producer) };
+        let cons_pos = consumer_pos.load(Ordering::Relaxed);
+        let prod_pos = producer_pos.load(Ordering::Relaxed);
 
         (prod_pos.saturating_sub(cons_pos)) as usize
     }
@@ -268,13 +276,11 @@ impl RingBufConsumer {
 
 impl Drop for RingBufConsumer {
     fn drop(&mut self) {
-        let header_size = std::mem::size_of::<RingBufHeader>();
-        let total_size = header_size + self.data_size;
-
         // SAFETY: We are unmapping the memory we previously mapped in open().
-        // self.header points to the start of the mapping.
+        // Both pointers point to the start of their mapping.
         unsafe {
-            libc::munmap(self.header as *mut libc::c_void, total_size);
+            libc::munmap(self.consumer as *mut libc::c_void, PAGE_SIZE);
+            libc::munmap(self.producer as *mut libc::c_void, PAGE_SIZE + 2 * self.data_size);
         }
     }
 }
@@ -0,0 +1,536 @@
+//! BTF type information.
+//!
+//! These match the kernel_bpf loader's BTF format. Objects built with BTF
+//! describe their maps and global variables in the `.BTF` section, which
+//! lets `rk info` show map layouts and decode globals by type.
+
+use serde_json::{Map, Number, Value};
+
+/// Magic number at the start of raw BTF data.
+const BTF_MAGIC: u16 = 0xeb9f;
+
+/// Size of the BTF header.
+const HEADER_LEN: usize = 24;
+
+/// Longest chain of types followed while decoding a value.
+const MAX_DEPTH: usize = 32;
+
+/// Errors from parsing BTF.
+#[derive(Debug, thiserror::Error)]
+pub enum BtfError {
+    /// The data doesn't start with a BTF header.
+    #[error("not BTF data")]
+    BadMagic,
+
+    /// The data ends inside a header, type or string.
+    #[error("truncated BTF data")]
+    Truncated,
+
+    /// A type uses a kind this parser doesn't know.
+    #[error("unknown BTF kind {0}")]
+    UnknownKind(u32),
+
+    /// The ELF object has no `.BTF` section.
+    #[error("object has no .BTF section")]
+    NoBtfSection,
+}
+
+/// A struct or union member.
+#[derive(Debug, Clone, Copy)]
+struct Member {
+    name: u32,
+    ty: u32,
+    bit_offset: u32,
+    bitfield_size: u32,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Int {
+        signed: bool,
+        char: bool,
+        bool: bool,
+        bit_offset: u32,
+        bits: u32,
+    },
+    Ptr,
+    Array {
+        elem: u32,
+        len: u32,
+    },
+    Struct {
+        union: bool,
+        members: Vec<Member>,
+    },
+    Enum {
+        signed: bool,
+        values: Vec<(u32, i64)>,
+    },
+    /// Typedefs, type modifiers and variables: `size_or_type` is the target.
+    Ref,
+    Float,
+    DataSec {
+        vars: Vec<(u32, u32)>, // variable type, offset
+    },
+    /// Forward declarations, functions and tags, which describe no data.
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Type {
+    name: u32,
+    size_or_type: u32,
+    kind: Kind,
+}
+
+/// A field of a BTF-defined map.
+#[derive(Debug, Clone, Copy)]
+pub enum MapField {
+    /// `__uint(name, value)`
+    Uint(u32),
+    /// `__type(name, type)`, with the type ID
+    Type(u32),
+}
+
+/// Parsed BTF type information.
+#[derive(Debug, Clone)]
+pub struct Btf {
+    types: Vec<Type>, // index 0 is type ID 1
+    strings: Vec<u8>,
+}
+
+fn u32_at(data: &[u8], off: usize) -> Result<u32, BtfError> {
+    data.get(off..off + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(BtfError::Truncated)
+}
+
+impl Btf {
+    /// Parse a raw `.BTF` blob.
+    pub fn parse(data: &[u8]) -> Result<Self, BtfError> {
+        if data.len() < HEADER_LEN {
+            return Err(BtfError::Truncated);
+        }
+        if u16::from_le_bytes([data[0], data[1]]) != BTF_MAGIC {
+            return Err(BtfError::BadMagic);
+        }
+        let hdr_len = u32_at(data, 4)? as usize;
+        let type_off = u32_at(data, 8)? as usize;
+        let type_len = u32_at(data, 12)? as usize;
+        let str_off = u32_at(data, 16)? as usize;
+        let str_len = u32_at(data, 20)? as usize;
+
+        let section = |off: usize, len: usize| {
+            let start = hdr_len.checked_add(off).ok_or(BtfError::Truncated)?;
+            let end = start.checked_add(len).ok_or(BtfError::Truncated)?;
+            data.get(start..end).ok_or(BtfError::Truncated)
+        };
+        let type_data = section(type_off, type_len)?;
+        let strings = section(str_off, str_len)?.to_vec();
+
+        let mut types = Vec::new();
+        let mut off = 0;
+        while off < type_data.len() {
+            let name = u32_at(type_data, off)?;
+            let info = u32_at(type_data, off + 4)?;
+            let size_or_type = u32_at(type_data, off + 8)?;
+            off += 12;
+
+            let vlen = (info & 0xffff) as usize;
+            let kind_flag = info >> 31 != 0;
+            let kind = match (info >> 24) & 0x1f {
+                1 => {
+                    let enc = u32_at(type_data, off)?;
+                    off += 4;
+                    Kind::Int {
+                        signed: (enc >> 24) & 1 != 0,
+                        char: (enc >> 24) & 2 != 0,
+                        bool: (enc >> 24) & 4 != 0,
+                        bit_offset: (enc >> 16) & 0xff,
+                        bits: enc & 0xff,
+                    }
+                }
+                2 => Kind::Ptr,
+                3 => {
+                    let elem = u32_at(type_data, off)?;
+                    let len = u32_at(type_data, off + 8)?;
+                    off += 12;
+                    Kind::Array { elem, len }
+                }
+                kind @ (4 | 5) => {
+                    let mut members = Vec::with_capacity(vlen);
+                    for _ in 0..vlen {
+                        let offset = u32_at(type_data, off + 8)?;
+                        let (bit_offset, bitfield_size) = if kind_flag {
+                            (offset & 0xff_ffff, offset >> 24)
+                        } else {
+                            (offset, 0)
+                        };
+                        members.push(Member {
+                            name: u32_at(type_data, off)?,
+                            ty: u32_at(type_data, off + 4)?,
+                            bit_offset,
+                            bitfield_size,
+                        });
+                        off += 12;
+                    }
+                    Kind::Struct {
+                        union: kind == 5,
+                        members,
+                    }
+                }
+                6 => {
+                    let mut values = Vec::with_capacity(vlen);
+                    for _ in 0..vlen {
+                        let val = u32_at(type_data, off + 4)?;
+                        let val = if kind_flag {
+                            i64::from(val as i32)
+                        } else {
+                            i64::from(val)
+                        };
+                        values.push((u32_at(type_data, off)?, val));
+                        off += 8;
+                    }
+                    Kind::Enum {
+                        signed: kind_flag,
+                        values,
+                    }
+                }
+                19 => {
+                    let mut values = Vec::with_capacity(vlen);
+                    for _ in 0..vlen {
+                        let lo = u64::from(u32_at(type_data, off + 4)?);
+                        let hi = u64::from(u32_at(type_data, off + 8)?);
+                        values.push((u32_at(type_data, off)?, (hi << 32 | lo) as i64));
+                        off += 12;
+                    }
+                    Kind::Enum {
+                        signed: kind_flag,
+                        values,
+                    }
+                }
+                7 | 12 => Kind::Other,
+                13 => {
+                    off += vlen * 8;
+                    Kind::Other
+                }
+                8 | 9 | 10 | 11 | 18 => Kind::Ref,
+                14 => {
+                    off += 4;
+                    Kind::Ref
+                }
+                15 => {
+                    let mut vars = Vec::with_capacity(vlen);
+                    for _ in 0..vlen {
+                        vars.push((u32_at(type_data, off)?, u32_at(type_data, off + 4)?));
+                        off += 12;
+                    }
+                    Kind::DataSec { vars }
+                }
+                16 => Kind::Float,
+                17 => {
+                    off += 4;
+                    Kind::Other
+                }
+                kind => return Err(BtfError::UnknownKind(kind)),
+            };
+            types.push(Type {
+                name,
+                size_or_type,
+                kind,
+            });
+        }
+        if off > type_data.len() {
+            return Err(BtfError::Truncated);
+        }
+
+        Ok(Self { types, strings })
+    }
+
+    /// Parse the `.BTF` section of a little-endian ELF64 object.
+    pub fn from_elf(data: &[u8]) -> Result<Self, BtfError> {
+        elf_section(data, ".BTF")
+            .ok_or(BtfError::NoBtfSection)
+            .and_then(Self::parse)
+    }
+
+    fn get(&self, id: u32) -> Option<&Type> {
+        self.types.get((id as usize).checked_sub(1)?)
+    }
+
+    fn string(&self, off: u32) -> &str {
+        let rest = self.strings.get(off as usize..).unwrap_or_default();
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        std::str::from_utf8(&rest[..end]).unwrap_or_default()
+    }
+
+    /// Follow typedefs, modifiers and variables to the underlying type.
+    fn resolve(&self, mut id: u32) -> Option<(u32, &Type)> {
+        for _ in 0..MAX_DEPTH {
+            let ty = self.get(id)?;
+            match ty.kind {
+                Kind::Ref => id = ty.size_or_type,
+                _ => return Some((id, ty)),
+            }
+        }
+        None
+    }
+
+    /// Number of types described.
+    pub fn type_count(&self) -> usize {
+        self.types.len()
+    }
+
+    /// The variables of the data section `name`, as (name, type, offset).
+    pub fn datasec(&self, name: &str) -> Option<Vec<(&str, u32, usize)>> {
+        let sec = self
+            .types
+            .iter()
+            .find(|ty| matches!(ty.kind, Kind::DataSec { .. }) && self.string(ty.name) == name)?;
+        let Kind::DataSec { vars } = &sec.kind else {
+            return None;
+        };
+        Some(
+            vars.iter()
+                .map(|&(var, offset)| {
+                    let (name, ty) = self
+                        .get(var)
+                        .map_or(("", 0), |v| (self.string(v.name), v.size_or_type));
+                    (name, ty, offset as usize)
+                })
+                .collect(),
+        )
+    }
+
+    /// The fields of a BTF-defined map type (`__uint`/`__type` members).
+    ///
+    /// `__uint(name, value)` fields are pointers to arrays of `value`
+    /// elements; `__type(name, type)` fields are pointers to the type.
+    pub fn map_fields(&self, id: u32) -> Vec<(&str, MapField)> {
+        let Some((_, ty)) = self.resolve(id) else {
+            return Vec::new();
+        };
+        let Kind::Struct { members, .. } = &ty.kind else {
+            return Vec::new();
+        };
+        members
+            .iter()
+            .filter_map(|member| {
+                let (_, ptr) = self.resolve(member.ty)?;
+                let Kind::Ptr = ptr.kind else {
+                    return None;
+                };
+                let target = ptr.size_or_type;
+                let field = match self.resolve(target) {
+                    Some((
+                        _,
+                        Type {
+                            kind: Kind::Array { len, .. },
+                            ..
+                        },
+                    )) => MapField::Uint(*len),
+                    _ => MapField::Type(target),
+                };
+                Some((self.string(member.name), field))
+            })
+            .collect()
+    }
+
+    /// Size in bytes of a value of type `id`.
+    pub fn size_of(&self, id: u32) -> Option<usize> {
+        let (_, ty) = self.resolve(id)?;
+        match &ty.kind {
+            Kind::Ptr => Some(8),
+            Kind::Array { elem, len } => self.size_of(*elem)?.checked_mul(*len as usize),
+            Kind::Int { .. }
+            | Kind::Struct { .. }
+            | Kind::Enum { .. }
+            | Kind::Float
+            | Kind::DataSec { .. } => Some(ty.size_or_type as usize),
+            Kind::Ref | Kind::Other => None,
+        }
+    }
+
+    /// The C spelling of type `id`, like `struct imu_event` or `__u32[4]`.
+    pub fn type_name(&self, id: u32) -> String {
+        let Some(ty) = self.get(id) else {
+            return "void".to_string();
+        };
+        let name = self.string(ty.name);
+        match &ty.kind {
+            Kind::Ptr => format!("{} *", self.type_name(ty.size_or_type)),
+            Kind::Array { elem, len } => format!("{}[{}]", self.type_name(*elem), len),
+            Kind::Struct { union, .. } => {
+                let keyword = if *union { "union" } else { "struct" };
+                if name.is_empty() {
+                    format!("{} {{...}}", keyword)
+                } else {
+                    format!("{} {}", keyword, name)
+                }
+            }
+            Kind::Enum { .. } => format!("enum {}", name),
+            Kind::Ref if name.is_empty() => self.type_name(ty.size_or_type),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Decode a value of type `id` from `data`.
+    ///
+    /// Structs become objects, arrays of `char` become strings and enums
+    /// become the name of their value. Parts of the value that don't fit in
+    /// `data` decode as `null`.
+    pub fn decode(&self, id: u32, data: &[u8]) -> Value {
+        self.decode_at(id, data, 0)
+    }
+
+    fn decode_at(&self, id: u32, data: &[u8], depth: usize) -> Value {
+        let Some((_, ty)) = self.resolve(id).filter(|_| depth < MAX_DEPTH) else {
+            return Value::Null;
+        };
+        let size = ty.size_or_type as usize;
+        match &ty.kind {
+            &Kind::Int {
+                signed,
+                bool,
+                bit_offset,
+                bits,
+                ..
+            } => match read_bits(data, bit_offset, bits.min(size as u32 * 8), signed) {
+                Some(v) if bool => Value::Bool(v != 0),
+                Some(v) => int_value(v, signed),
+                None => Value::Null,
+            },
+            Kind::Ptr => read_bits(data, 0, 64, false).map_or(Value::Null, |v| int_value(v, false)),
+            Kind::Enum { signed, values } => match read_bits(data, 0, size as u32 * 8, *signed) {
+                Some(v) => values
+                    .iter()
+                    .find(|&&(_, val)| val as i128 == v)
+                    .map_or(int_value(v, *signed), |&(name, _)| {
+                        Value::String(self.string(name).to_string())
+                    }),
+                None => Value::Null,
+            },
+            Kind::Float => match (size, data.get(..size)) {
+                (4, Some(b)) => float_value(f32::from_le_bytes(b.try_into().unwrap()).into()),
+                (8, Some(b)) => float_value(f64::from_le_bytes(b.try_into().unwrap())),
+                _ => Value::Null,
+            },
+            Kind::Array { elem, len } => {
+                let Some(elem_size) = self.size_of(*elem).filter(|&s| s > 0) else {
+                    return Value::Null;
+                };
+                if self.is_char(*elem) {
+                    let bytes = &data[..data.len().min(*len as usize)];
+                    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                    return Value::String(String::from_utf8_lossy(&bytes[..end]).into_owned());
+                }
+                (0..*len as usize)
+                    .map(|i| {
+                        let rest = data.get(i * elem_size..).unwrap_or_default();
+                        self.decode_at(*elem, rest, depth + 1)
+                    })
+                    .collect()
+            }
+            Kind::Struct { members, .. } => {
+                let mut fields = Map::new();
+                for member in members {
+                    let value = if member.bitfield_size > 0 {
+                        let signed = matches!(
+                            self.resolve(member.ty).map(|(_, ty)| &ty.kind),
+                            Some(Kind::Int { signed: true, .. })
+                        );
+                        read_bits(data, member.bit_offset, member.bitfield_size, signed)
+                            .map_or(Value::Null, |v| int_value(v, signed))
+                    } else {
+                        let rest = data
+                            .get(member.bit_offset as usize / 8..)
+                            .unwrap_or_default();
+                        self.decode_at(member.ty, rest, depth + 1)
+                    };
+                    match (self.string(member.name), value) {
+                        // Members of anonymous structs and unions belong to
+                        // the enclosing struct
+                        ("", Value::Object(inner)) => fields.extend(inner),
+                        (name, value) => {
+                            fields.insert(name.to_string(), value);
+                        }
+                    }
+                }
+                Value::Object(fields)
+            }
+            Kind::DataSec { vars } => vars
+                .iter()
+                .map(|&(var, offset)| {
+                    let name = self.get(var).map_or("", |v| self.string(v.name));
+                    let rest = data.get(offset as usize..).unwrap_or_default();
+                    (name.to_string(), self.decode_at(var, rest, depth + 1))
+                })
+                .collect::<Map<_, _>>()
+                .into(),
+            Kind::Ref | Kind::Other => Value::Null,
+        }
+    }
+
+    /// Whether `id` is a one-byte character type.
+    fn is_char(&self, id: u32) -> bool {
+        matches!(
+            self.resolve(id),
+            Some((_, ty)) if ty.size_or_type == 1 && matches!(ty.kind, Kind::Int { char: true, .. })
+        )
+    }
+}
+
+/// Read `bits` bits starting `bit_offset` bits into `data`, little-endian.
+fn read_bits(data: &[u8], bit_offset: u32, bits: u32, signed: bool) -> Option<i128> {
+    if bits == 0 || bits > 64 {
+        return None;
+    }
+    let start = bit_offset as usize / 8;
+    let shift = bit_offset % 8;
+    let len = (shift + bits).div_ceil(8) as usize;
+    let mut raw = [0u8; 16];
+    raw[..len].copy_from_slice(data.get(start..start + len)?);
+    let value = (u128::from_le_bytes(raw) >> shift) & ((1u128 << bits) - 1);
+    if signed && value >> (bits - 1) & 1 != 0 {
+        Some(value as i128 - (1i128 << bits))
+    } else {
+        Some(value as i128)
+    }
+}
+
+fn int_value(value: i128, signed: bool) -> Value {
+    if signed {
+        Value::Number(Number::from(value as i64))
+    } else {
+        Value::Number(Number::from(value as u64))
+    }
+}
+
+fn float_value(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+/// Find a section of a little-endian ELF64 object by name.
+pub fn elf_section<'a>(data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let u16_at = |off: usize| Some(u16::from_le_bytes(data.get(off..off + 2)?.try_into().ok()?));
+    let u64_at = |off: usize| Some(u64::from_le_bytes(data.get(off..off + 8)?.try_into().ok()?));
+    let section = |index: usize| -> Option<(u32, &'a [u8])> {
+        let shoff = u64_at(0x28)? as usize;
+        let header = shoff.checked_add(index.checked_mul(u16_at(0x3a)? as usize)?)?;
+        let name = u32::from_le_bytes(data.get(header..header + 4)?.try_into().ok()?);
+        let offset = u64_at(header + 0x18)? as usize;
+        let size = u64_at(header + 0x20)? as usize;
+        Some((name, data.get(offset..offset.checked_add(size)?)?))
+    };
+
+    if data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+        return None; // not ELF64 little-endian
+    }
+    let (_, names) = section(u16_at(0x3e)? as usize)?;
+    (0..u16_at(0x3c)? as usize).find_map(|index| {
+        let (name_off, contents) = section(index)?;
+        let rest = names.get(name_off as usize..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        (&rest[..end] == name.as_bytes()).then_some(contents)
+    })
+}
@@ -6,6 +6,7 @@ use anyhow::{Context, Result};
 use colored::Colorize;
 use sha3::{Digest, Sha3_256};
 
+use crate::btf::{elf_section, Btf, BtfError, MapField};
 use crate::signing::{SignedProgramHeader, HEADER_SIZE, MAGIC};
 
 /// Show information about a BPF program.
@@ -149,9 +150,76 @@ fn show_elf_summary(data: &[u8]) -> Result<()> {
         }
     }
 
+    show_btf_info(data);
+
     Ok(())
 }
 
+/// Show the maps and global variables described by the object's BTF.
+fn show_btf_info(data: &[u8]) {
+    let btf = match Btf::from_elf(data) {
+        Ok(btf) => btf,
+        Err(BtfError::NoBtfSection) => return,
+        Err(e) => {
+            println!("  {} {} ({})", "BTF:".cyan(), "invalid".red(), e);
+            return;
+        }
+    };
+    println!("  {} {} types", "BTF:".cyan(), btf.type_count());
+
+    if let Some(maps) = btf.datasec(".maps") {
+        println!();
+        println!("  {}", "Maps:".cyan());
+        for (name, ty, _) in maps {
+            let fields: Vec<String> = btf
+                .map_fields(ty)
+                .into_iter()
+                .map(|(field, value)| match (field, value) {
+                    ("type", MapField::Uint(map_type)) => map_type_name(map_type).to_string(),
+                    (field, MapField::Uint(value)) => format!("{}={}", field, value),
+                    (field, MapField::Type(ty)) => format!("{}={}", field, btf.type_name(ty)),
+                })
+                .collect();
+            println!("    {} {}", name.green(), fields.join(" "));
+        }
+    }
+
+    for section in [".rodata", ".data", ".bss"] {
+        let Some(vars) = btf.datasec(section) else {
+            continue;
+        };
+        // .bss takes no space in the file and starts out zeroed
+        let contents = match section {
+            ".bss" => None,
+            _ => elf_section(data, section),
+        };
+
+        println!();
+        println!("  {} {}", "Globals:".cyan(), section);
+        for (name, ty, offset) in vars {
+            let value = match contents {
+                Some(contents) => btf.decode(ty, contents.get(offset..).unwrap_or_default()),
+                None => btf.decode(ty, &vec![0; btf.size_of(ty).unwrap_or(0)]),
+            };
+            println!("    {} {} = {}", btf.type_name(ty), name.green(), value);
+        }
+    }
+}
+
+fn map_type_name(map_type: u32) -> &'static str {
+    match map_type {
+        1 => "hash",
+        2 => "array",
+        3 => "prog_array",
+        5 => "percpu_hash",
+        6 => "percpu_array",
+        9 => "lru_hash",
+        27 => "ringbuf",
+        100 => "timeseries",
+        _ => "unknown",
+    }
+}
+
 fn hex_string(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
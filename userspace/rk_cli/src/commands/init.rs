@@ -108,16 +108,37 @@ typedef signed long long __s64;
 #include "types.h"
 
 // BPF helper function definitions
-// These are provided by the kernel at runtime
+// These are provided by the kernel at runtime; the IDs match the kernel's
+// helper registry (kernel_bpf::helpers)
 
-static long (*bpf_trace_printk)(const char *fmt, __u32 fmt_size, ...) = (void *) 6;
-static long (*bpf_get_current_pid_tgid)(void) = (void *) 14;
-static long (*bpf_ktime_get_ns)(void) = (void *) 5;
+static __u64 (*bpf_ktime_get_ns)(void) = (void *) 1;
+static long (*bpf_trace_printk)(const char *fmt, __u32 fmt_size) = (void *) 2;
+static void *(*bpf_map_lookup_elem)(void *map, const void *key) = (void *) 3;
+static long (*bpf_map_update_elem)(void *map, const void *key, const void *value, __u64 flags) = (void *) 4;
+static long (*bpf_map_delete_elem)(void *map, const void *key) = (void *) 5;
+static long (*bpf_ringbuf_output)(void *map, void *data, __u64 size, __u64 flags) = (void *) 6;
 
 // rkBPF-specific helpers
-static long (*rkbpf_motor_stop)(__u32 motor_id) = (void *) 1000;
-static long (*rkbpf_gpio_read)(__u32 pin) = (void *) 1001;
-static long (*rkbpf_timeseries_push)(__u32 map_id, __u64 timestamp, __u64 value) = (void *) 1002;
+static long (*rkbpf_motor_stop)(__u32 reason) = (void *) 1000;
+static long (*rkbpf_timeseries_push)(void *map, const void *key, const void *value) = (void *) 1001;
+static long (*rkbpf_gpio_write)(__u32 pin, __u32 value) = (void *) 1003;
+static long (*rkbpf_gpio_read)(__u32 pin) = (void *) 1004;
+static long (*rkbpf_pwm_write)(__u32 pwm_id, __u32 channel, __u32 duty) = (void *) 1005;
+
+// Aggregates written by the time-series query helpers
+struct rkbpf_ts_aggregate {
+    __u64 count;
+    __u64 oldest_ns;
+    __u64 newest_ns;
+    __s64 min;
+    __s64 max;
+    __s64 mean;
+    __s64 stddev;
+    __s64 slope;      // change per second between oldest and newest
+};
+
+static long (*rkbpf_timeseries_query)(void *map, __u64 n, struct rkbpf_ts_aggregate *out, __u32 size) = (void *) 1008;
+static long (*rkbpf_timeseries_query_window)(void *map, __u64 start_ns, __u64 end_ns, struct rkbpf_ts_aggregate *out, __u32 size) = (void *) 1009;
 
 #endif /* RKBPF_HELPERS_H */
 "#;
@@ -3,6 +3,7 @@
 //! A command-line tool for managing, signing, and deploying BPF programs
 //! for the rkBPF robotics kernel subsystem.
 
+mod btf;
 mod commands;
 mod config;
 mod signing;
@@ -12,7 +12,7 @@ const PWM_CHIP: u32 = 0;
 const PWM_CHANNEL: u32 = 1;
 const MOTOR_DUTY: i32 = 50; // 50% duty cycle simulates running motor
 
-// === BPF Helper IDs (from interpreter/JIT dispatch tables) ===
+// === BPF Helper IDs (from the kernel_bpf::helpers registry) ===
 const HELPER_TRACE_PRINTK: i32 = 2;
 const HELPER_MOTOR_STOP: i32 = 1000;
 const HELPER_PWM_WRITE: i32 = 1005;
@@ -42,6 +42,26 @@ impl BpfInsn {
         }
     }
 
+    /// First slot of a wide load of a map fd: dst = map (fd resolved by the kernel)
+    const fn ld_map_fd(dst: u8, fd: i32) -> Self {
+        Self {
+            code: 0x18,
+            dst_src: (1 << 4) | dst,
+            off: 0,
+            imm: fd,
+        }
+    }
+
+    /// Second slot of a wide load, holding the upper 32 bits of the immediate
+    const fn ld_imm64_hi(imm: i32) -> Self {
+        Self {
+            code: 0,
+            dst_src: 0,
+            off: 0,
+            imm,
+        }
+    }
+
     const fn ldx_w(dst: u8, src: u8, off: i16) -> Self {
         Self {
             code: 0x61,
@@ -143,9 +163,10 @@ pub extern "C" fn _start() -> ! {
         // Create a value: val = ts
         // Store val at [R10 - 16]
         BpfInsn::stx_dw(10, 1, -16),
-        // Prepare arguments for bpf_timeseries_push(map_id, key_ptr, value_ptr)
+        // Prepare arguments for bpf_timeseries_push(map, key_ptr, value_ptr)
         // R1 = map_fd
-        BpfInsn::mov64_imm(1, map_fd),
+        BpfInsn::ld_map_fd(1, map_fd),
+        BpfInsn::ld_imm64_hi(0),
         // R2 = R10 - 8 (pointer to key/ts)
         BpfInsn::mov64_reg(2, 10),
         BpfInsn {